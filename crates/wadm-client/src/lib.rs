@@ -1,18 +1,30 @@
 //! A client for interacting with Wadm.
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
 use async_nats::{HeaderMap, Message};
 use error::{ClientError, SerializationError};
-use futures::Stream;
+use futures::{Stream, StreamExt};
 use topics::TopicGenerator;
 use wadm_types::{
     api::{
-        DeleteModelRequest, DeleteModelResponse, DeleteResult, DeployModelRequest,
-        DeployModelResponse, DeployResult, GetModelRequest, GetModelResponse, GetResult,
-        ModelSummary, PutModelResponse, PutResult, Status, StatusResponse, StatusResult,
-        VersionInfo, VersionResponse,
+        AdminConsumersResponse, AdminInfoResponse, AdminLatticesResponse, AdminPermitsResponse,
+        AdminScalersResponse, AppScalers, AuditQueryRequest, AuditQueryResponse, AuditRecord,
+        BuildInfo, CommandAck, ConsumerStatus, CordonHostRequest, CordonHostResponse,
+        DeadLetterListResponse, DeadLetterReplayRequest, DeadLetterReplayResponse,
+        DeadLetterSummary, DeleteModelRequest, DeleteModelResponse, DeleteResult,
+        DeployModelRequest, DeployModelResponse, DeployResult, ExportModelsResponse,
+        GetModelRequest, GetModelResponse, GetResult, HostPlacement, ImportConflictPolicy,
+        ImportModelsRequest, ImportModelsResponse, LintModelResponse, LintResult, ManagedLattice,
+        ModelBundle, ModelSummary, PermitUsage, PlacementRequest, PlacementResponse,
+        PutModelResponse, PutResult, ReconcileReport, ReloadConfigResponse, ShardStatusResponse,
+        Status, StatusRequest, StatusResponse, StatusResult, Topology, TopologyFormat,
+        TopologyRequest, TopologyResponse, UndeployModelRequest, VersionInfo, VersionResponse,
+        TEMPLATE_OVERLAY_HEADER, TEMPLATE_VARIABLES_HEADER,
     },
+    validation::ValidationFailure,
     Manifest,
 };
 
@@ -97,6 +109,25 @@ impl Client {
         }
     }
 
+    /// Returns a client for the same lattice that only uses the read-only API, for tooling that is
+    /// only granted access to it. Any operation that changes something returns an error
+    pub fn read_only(&self) -> Client {
+        Client {
+            topics: Arc::new(self.topics.read_only()),
+            client: self.client.clone(),
+        }
+    }
+
+    /// Returns a client for the same lattice that sends requests to the versioned API subjects, so
+    /// it keeps talking to the same version of the API after wadm adds a new one. This requires a
+    /// version of wadm that serves versioned subjects
+    pub fn versioned(&self) -> Client {
+        Client {
+            topics: Arc::new(self.topics.versioned()),
+            client: self.client.clone(),
+        }
+    }
+
     /// Puts the given manifest into the lattice. The lattice can be anything that implements the
     /// [`ManifestLoader`] trait (a path to a file, raw bytes, or an already parsed manifest).
     ///
@@ -121,6 +152,69 @@ impl Client {
         Ok((body.name, body.current_version))
     }
 
+    /// Validates a manifest and checks it for common mistakes without putting it into the lattice
+    ///
+    /// Returns all of the errors and warnings that were found, which is empty if there were none
+    pub async fn lint_manifest(
+        &self,
+        manifest: impl ManifestLoader,
+    ) -> Result<Vec<ValidationFailure>> {
+        let manifest = manifest.load_manifest().await?;
+        let manifest_bytes = serde_json::to_vec(&manifest).map_err(SerializationError::from)?;
+        let topic = self.topics.model_lint_topic();
+        let resp = self
+            .client
+            .request_with_headers(
+                topic,
+                get_headers_content_type_json().clone(),
+                manifest_bytes.into(),
+            )
+            .await?;
+        let body: LintModelResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        if matches!(body.result, LintResult::Error) {
+            return Err(ClientError::ApiError(body.message));
+        }
+        Ok(body.failures)
+    }
+
+    /// Puts a manifest template into the lattice. Wadm renders the template with the given values
+    /// for its variables, then applies the overlay (a JSON merge patch) if one is given. The
+    /// template is sent as-is, as YAML or JSON, since it may not be a valid manifest until it is
+    /// rendered
+    ///
+    /// Returns the name and version of the manifest that was put into the lattice
+    pub async fn put_manifest_template(
+        &self,
+        template: impl Into<Vec<u8>>,
+        variables: &BTreeMap<String, String>,
+        overlay: Option<&serde_json::Value>,
+    ) -> Result<(String, String)> {
+        let mut headers = HeaderMap::new();
+        if !variables.is_empty() {
+            let variables = serde_json::to_string(variables).map_err(SerializationError::from)?;
+            headers.insert(TEMPLATE_VARIABLES_HEADER, variables.as_str());
+        }
+        if let Some(overlay) = overlay {
+            let overlay = serde_json::to_string(overlay).map_err(SerializationError::from)?;
+            headers.insert(TEMPLATE_OVERLAY_HEADER, overlay.as_str());
+        }
+        let resp = self
+            .client
+            .request_with_headers(
+                self.topics.model_put_topic(),
+                headers,
+                template.into().into(),
+            )
+            .await?;
+        let body: PutModelResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        if matches!(body.result, PutResult::Error) {
+            return Err(ClientError::ApiError(body.message));
+        }
+        Ok((body.name, body.current_version))
+    }
+
     /// Gets a list of all manifests in the lattice. This does not return the full manifest, just a
     /// summary of its metadata and status
     pub async fn list_manifests(&self) -> Result<Vec<ModelSummary>> {
@@ -134,6 +228,45 @@ impl Client {
         Ok(body)
     }
 
+    /// Exports every model in the lattice, with all of their versions, as a single bundle that can
+    /// be imported into another lattice with [`Client::import_models`]
+    pub async fn export_models(&self) -> Result<ModelBundle> {
+        let topic = self.topics.model_export_topic();
+        let resp = self
+            .client
+            .request(topic, Vec::with_capacity(0).into())
+            .await?;
+        let body: ExportModelsResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match (body.result, body.bundle) {
+            (GetResult::Success, Some(bundle)) => Ok(bundle),
+            _ => Err(ClientError::ApiError(body.message)),
+        }
+    }
+
+    /// Imports a bundle of models into the lattice, using the given policy for models that already
+    /// exist. Imported models aren't deployed. Returns which models were created, updated, skipped,
+    /// and which failed to import along with why
+    pub async fn import_models(
+        &self,
+        bundle: &ModelBundle,
+        conflict_policy: ImportConflictPolicy,
+    ) -> Result<ImportModelsResponse> {
+        let topic = self.topics.model_import_topic();
+        let body = serde_json::to_vec(&ImportModelsRequest {
+            bundle: bundle.clone(),
+            conflict_policy,
+        })
+        .map_err(SerializationError::from)?;
+        let resp = self.client.request(topic, body.into()).await?;
+        let body: ImportModelsResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            GetResult::Success => Ok(body),
+            GetResult::Error | GetResult::NotFound => Err(ClientError::ApiError(body.message)),
+        }
+    }
+
     /// Gets a manifest from the lattice by name and optionally its version. If no version is set,
     /// the latest version will be returned
     pub async fn get_manifest(&self, name: &str, version: Option<&str>) -> Result<Manifest> {
@@ -216,6 +349,8 @@ impl Client {
         let body = if let Some(version) = version {
             serde_json::to_vec(&DeployModelRequest {
                 version: Some(version.to_string()),
+                dry_run: false,
+                overrides: None,
             })
             .map_err(SerializationError::from)?
         } else {
@@ -231,6 +366,61 @@ impl Client {
         }
     }
 
+    /// Deploys the given version of a manifest with a JSON merge patch applied on top of it. The
+    /// overrides only apply to this deployment and don't create a new version. The optional version
+    /// parameter works the same as in [`deploy_manifest`](Self::deploy_manifest)
+    ///
+    /// Returns a tuple of the name and version of the manifest that was deployed
+    pub async fn deploy_manifest_with_overrides(
+        &self,
+        name: &str,
+        version: Option<&str>,
+        overrides: serde_json::Value,
+    ) -> Result<(String, Option<String>)> {
+        let topic = self.topics.model_deploy_topic(name);
+        let body = serde_json::to_vec(&DeployModelRequest {
+            version: version.map(ToString::to_string),
+            dry_run: false,
+            overrides: Some(overrides),
+        })
+        .map_err(SerializationError::from)?;
+        let resp = self.client.request(topic, body.into()).await?;
+        let body: DeployModelResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            DeployResult::Error => Err(ClientError::ApiError(body.message)),
+            DeployResult::NotFound => Err(ClientError::NotFound(name.to_string())),
+            DeployResult::Acknowledged => Ok((body.name, body.version)),
+        }
+    }
+
+    /// Computes the commands that deploying the given manifest would issue against the current
+    /// state of the lattice, without actually deploying it. The optional version parameter works
+    /// the same as in [`deploy_manifest`](Self::deploy_manifest)
+    ///
+    /// Returns the list of commands as raw JSON values
+    pub async fn dry_run_deploy_manifest(
+        &self,
+        name: &str,
+        version: Option<&str>,
+    ) -> Result<Vec<serde_json::Value>> {
+        let topic = self.topics.model_deploy_topic(name);
+        let body = serde_json::to_vec(&DeployModelRequest {
+            version: version.map(ToString::to_string),
+            dry_run: true,
+            overrides: None,
+        })
+        .map_err(SerializationError::from)?;
+        let resp = self.client.request(topic, body.into()).await?;
+        let body: DeployModelResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            DeployResult::Error => Err(ClientError::ApiError(body.message)),
+            DeployResult::NotFound => Err(ClientError::NotFound(name.to_string())),
+            DeployResult::Acknowledged => Ok(body.commands.unwrap_or_default()),
+        }
+    }
+
     /// A shorthand method that is the equivalent of calling [`put_manifest`](Self::put_manifest)
     /// and then [`deploy_manifest`](Self::deploy_manifest)
     ///
@@ -251,11 +441,55 @@ impl Client {
     ///
     /// Returns Ok(manifest_name) if the manifest undeploy request was acknowledged
     pub async fn undeploy_manifest(&self, name: &str) -> Result<String> {
-        let topic = self.topics.model_undeploy_topic(name);
-        let resp = self
-            .client
-            .request(topic, Vec::with_capacity(0).into())
-            .await?;
+        self.send_undeploy(name, Vec::with_capacity(0)).await
+    }
+
+    /// Undeploys the given shared manifest along with the deployed manifests that depend on it.
+    /// Dependent manifests are undeployed first, waiting the given delay between each stage. If
+    /// no delay is given, the server's default is used
+    pub async fn undeploy_manifest_with_dependents(
+        &self,
+        name: &str,
+        stage_delay: Option<Duration>,
+    ) -> Result<String> {
+        let req = serde_json::to_vec(&UndeployModelRequest {
+            cascade: true,
+            stage_delay_seconds: stage_delay.map(|delay| delay.as_secs()),
+        })
+        .map_err(SerializationError::from)?;
+        self.send_undeploy(name, req).await
+    }
+
+    /// Pauses reconciliation of the given deployed manifest. Its scalers are kept, but wadm won't
+    /// start, stop or link anything for it until it is resumed, so manual changes to the lattice
+    /// aren't undone. Deploying or undeploying the manifest also resumes it
+    ///
+    /// Returns Ok(manifest_name) if the pause request was acknowledged
+    pub async fn pause_manifest(&self, name: &str) -> Result<String> {
+        self.send_deploy_request(self.topics.model_pause_topic(name), name, Vec::new())
+            .await
+    }
+
+    /// Resumes reconciliation of the given paused manifest, reconciling it right away
+    ///
+    /// Returns Ok(manifest_name) if the resume request was acknowledged
+    pub async fn resume_manifest(&self, name: &str) -> Result<String> {
+        self.send_deploy_request(self.topics.model_resume_topic(name), name, Vec::new())
+            .await
+    }
+
+    async fn send_undeploy(&self, name: &str, payload: Vec<u8>) -> Result<String> {
+        self.send_deploy_request(self.topics.model_undeploy_topic(name), name, payload)
+            .await
+    }
+
+    async fn send_deploy_request(
+        &self,
+        topic: String,
+        name: &str,
+        payload: Vec<u8>,
+    ) -> Result<String> {
+        let resp = self.client.request(topic, payload.into()).await?;
         let body: DeployModelResponse =
             serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
         match body.result {
@@ -283,6 +517,348 @@ impl Client {
         }
     }
 
+    /// Gets the report of the last time wadm reconciled the given manifest, which has the commands
+    /// it issued, the scalers that found drift and any errors. Returns `None` if wadm hasn't
+    /// reconciled the manifest yet or doesn't keep reports
+    pub async fn get_last_reconcile(&self, name: &str) -> Result<Option<ReconcileReport>> {
+        let topic = self.topics.model_status_topic(name);
+        let resp = self
+            .client
+            .request(topic, Vec::with_capacity(0).into())
+            .await?;
+        let body: StatusResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            StatusResult::Error => Err(ClientError::ApiError(body.message)),
+            StatusResult::NotFound => Err(ClientError::NotFound(name.to_string())),
+            StatusResult::Ok => Ok(body.last_reconcile),
+        }
+    }
+
+    /// Gets the last response to each command for the given manifest that failed on its last
+    /// attempt and hasn't succeeded since
+    pub async fn get_failed_commands(&self, name: &str) -> Result<Vec<CommandAck>> {
+        let topic = self.topics.model_status_topic(name);
+        let resp = self
+            .client
+            .request(topic, Vec::with_capacity(0).into())
+            .await?;
+        let body: StatusResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            StatusResult::Error => Err(ClientError::ApiError(body.message)),
+            StatusResult::NotFound => Err(ClientError::NotFound(name.to_string())),
+            StatusResult::Ok => Ok(body.failed_commands),
+        }
+    }
+
+    /// Gets the status the given manifest had at a point in time. The timestamp must be in RFC 3339
+    /// format (e.g. `2024-03-06T02:13:00Z`).
+    ///
+    /// Returns the status along with the RFC 3339 timestamp of when it was recorded. This will
+    /// return a not found error if no status was recorded at or before the given time
+    pub async fn get_manifest_status_at(&self, name: &str, at: &str) -> Result<(Status, String)> {
+        let topic = self.topics.model_status_topic(name);
+        let body = serde_json::to_vec(&StatusRequest {
+            at: Some(at.to_string()),
+        })
+        .map_err(SerializationError::from)?;
+        let resp = self.client.request(topic, body.into()).await?;
+        let body: StatusResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            StatusResult::Error => Err(ClientError::ApiError(body.message)),
+            StatusResult::NotFound => Err(ClientError::NotFound(name.to_string())),
+            StatusResult::Ok => body
+                .status
+                .map(|status| (status, body.recorded_at.unwrap_or_default()))
+                .ok_or_else(|| {
+                    ClientError::ApiError(
+                        "API returned success but didn't set a status".to_string(),
+                    )
+                }),
+        }
+    }
+
+    /// Explains where wadm places the given component of a stored application, returning every
+    /// host in the lattice along with why it is or isn't a candidate and how many instances the
+    /// scaler wants there. Uses the deployed version unless a version is given
+    pub async fn explain_placement(
+        &self,
+        name: &str,
+        component: &str,
+        version: Option<&str>,
+    ) -> Result<Vec<HostPlacement>> {
+        self.placement(
+            self.topics.model_placement_topic(name),
+            PlacementRequest {
+                component: component.to_string(),
+                version: version.map(ToString::to_string),
+                manifest: None,
+            },
+        )
+        .await
+    }
+
+    /// Explains where wadm would place the given component of a manifest that doesn't have to be
+    /// stored. See [`explain_placement`](Self::explain_placement) for more information
+    pub async fn explain_manifest_placement(
+        &self,
+        manifest: Manifest,
+        component: &str,
+    ) -> Result<Vec<HostPlacement>> {
+        self.placement(
+            self.topics.manifest_placement_topic(),
+            PlacementRequest {
+                component: component.to_string(),
+                version: None,
+                manifest: Some(manifest),
+            },
+        )
+        .await
+    }
+
+    async fn placement(
+        &self,
+        topic: String,
+        request: PlacementRequest,
+    ) -> Result<Vec<HostPlacement>> {
+        let body = serde_json::to_vec(&request).map_err(SerializationError::from)?;
+        let resp = self.client.request(topic, body.into()).await?;
+        let body: PlacementResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            GetResult::Success => Ok(body.hosts),
+            GetResult::NotFound => Err(ClientError::NotFound(body.message)),
+            GetResult::Error => Err(ClientError::ApiError(body.message)),
+        }
+    }
+
+    /// Gets the topology of the lattice: the hosts, deployed applications, components, providers
+    /// and links that wadm knows about
+    pub async fn get_lattice_topology(&self) -> Result<Topology> {
+        self.lattice_topology(TopologyFormat::Json)
+            .await?
+            .topology
+            .ok_or_else(|| {
+                ClientError::ApiError("API returned success but didn't set a topology".to_string())
+            })
+    }
+
+    /// Gets the topology of the lattice rendered as a GraphViz DOT document. See
+    /// [`get_lattice_topology`](Self::get_lattice_topology) for more information
+    pub async fn get_lattice_topology_dot(&self) -> Result<String> {
+        self.lattice_topology(TopologyFormat::Dot)
+            .await?
+            .dot
+            .ok_or_else(|| {
+                ClientError::ApiError(
+                    "API returned success but didn't set a DOT document".to_string(),
+                )
+            })
+    }
+
+    async fn lattice_topology(&self, format: TopologyFormat) -> Result<TopologyResponse> {
+        let topic = self.topics.lattice_topology_topic();
+        let body =
+            serde_json::to_vec(&TopologyRequest { format }).map_err(SerializationError::from)?;
+        let resp = self.client.request(topic, body.into()).await?;
+        let body: TopologyResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            GetResult::Success => Ok(body),
+            GetResult::Error | GetResult::NotFound => Err(ClientError::ApiError(body.message)),
+        }
+    }
+
+    /// Returns which wadm replica the lattice is sharded to, along with all replicas sharing
+    /// lattices. Returns an error if sharding isn't enabled
+    pub async fn get_shard_status(&self) -> Result<ShardStatusResponse> {
+        let topic = self.topics.lattice_shard_topic();
+        let resp = self.client.request(topic, Vec::new().into()).await?;
+        let body: ShardStatusResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            GetResult::Success => Ok(body),
+            GetResult::Error | GetResult::NotFound => Err(ClientError::ApiError(body.message)),
+        }
+    }
+
+    /// Asks wadm to reload its runtime config, returning the names of the settings that changed.
+    /// When several wadm processes share the API prefix, this returns the answer of the first one
+    pub async fn reload_config(&self) -> Result<Vec<String>> {
+        let topic = self.topics.admin_reload_topic();
+        let resp = self.client.request(topic, Vec::new().into()).await?;
+        let body: ReloadConfigResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            GetResult::Success => Ok(body.changed),
+            GetResult::Error | GetResult::NotFound => Err(ClientError::ApiError(body.message)),
+        }
+    }
+
+    /// Returns the version and build info of wadm. Like the other admin requests, this is answered
+    /// by a single wadm process when several share the API prefix
+    pub async fn get_build_info(&self) -> Result<BuildInfo> {
+        let topic = self.topics.admin_info_topic();
+        let resp = self.client.request(topic, Vec::new().into()).await?;
+        let body: AdminInfoResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match (body.result, body.info) {
+            (GetResult::Success, Some(info)) => Ok(info),
+            (GetResult::Success, None) => Err(ClientError::ApiError(
+                "Build info not found in response".to_string(),
+            )),
+            (GetResult::Error | GetResult::NotFound, _) => Err(ClientError::ApiError(body.message)),
+        }
+    }
+
+    /// Lists the lattices a wadm process is managing
+    pub async fn list_managed_lattices(&self) -> Result<Vec<ManagedLattice>> {
+        let topic = self.topics.admin_lattices_topic();
+        let resp = self.client.request(topic, Vec::new().into()).await?;
+        let body: AdminLatticesResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            GetResult::Success => Ok(body.lattices),
+            GetResult::Error | GetResult::NotFound => Err(ClientError::ApiError(body.message)),
+        }
+    }
+
+    /// Lists the consumers a wadm process is running, including the last message each was
+    /// delivered
+    pub async fn list_consumers(&self) -> Result<Vec<ConsumerStatus>> {
+        let topic = self.topics.admin_consumers_topic();
+        let resp = self.client.request(topic, Vec::new().into()).await?;
+        let body: AdminConsumersResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            GetResult::Success => Ok(body.consumers),
+            GetResult::Error | GetResult::NotFound => Err(ClientError::ApiError(body.message)),
+        }
+    }
+
+    /// Lists the scalers a wadm process is running for each app
+    pub async fn list_scalers(&self) -> Result<Vec<AppScalers>> {
+        let topic = self.topics.admin_scalers_topic();
+        let resp = self.client.request(topic, Vec::new().into()).await?;
+        let body: AdminScalersResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            GetResult::Success => Ok(body.apps),
+            GetResult::Error | GetResult::NotFound => Err(ClientError::ApiError(body.message)),
+        }
+    }
+
+    /// Returns how much of the work permit pool of a wadm process is in use
+    pub async fn get_permit_usage(&self) -> Result<PermitUsage> {
+        let topic = self.topics.admin_permits_topic();
+        let resp = self.client.request(topic, Vec::new().into()).await?;
+        let body: AdminPermitsResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match (body.result, body.permits) {
+            (GetResult::Success, Some(permits)) => Ok(permits),
+            (GetResult::Success, None) => Err(ClientError::ApiError(
+                "Permit usage not found in response".to_string(),
+            )),
+            (GetResult::Error | GetResult::NotFound, _) => Err(ClientError::ApiError(body.message)),
+        }
+    }
+
+    /// Lists the commands in the lattice that wadm gave up on after they failed on every attempt
+    pub async fn list_dead_letters(&self) -> Result<Vec<DeadLetterSummary>> {
+        let topic = self.topics.dead_letter_list_topic();
+        let resp = self.client.request(topic, Vec::new().into()).await?;
+        let body: DeadLetterListResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            GetResult::Success => Ok(body.dead_letters),
+            GetResult::Error | GetResult::NotFound => Err(ClientError::ApiError(body.message)),
+        }
+    }
+
+    /// Replays the dead letters with the given sequences, sending their commands back to be
+    /// executed. Returns the sequences that were replayed. Sequences that weren't found are skipped
+    pub async fn replay_dead_letters(&self, sequences: &[u64]) -> Result<Vec<u64>> {
+        self.replay(DeadLetterReplayRequest {
+            sequences: sequences.to_vec(),
+            all: false,
+        })
+        .await
+    }
+
+    /// Replays all dead letters in the lattice. Returns the sequences that were replayed
+    pub async fn replay_all_dead_letters(&self) -> Result<Vec<u64>> {
+        self.replay(DeadLetterReplayRequest {
+            sequences: Vec::new(),
+            all: true,
+        })
+        .await
+    }
+
+    async fn replay(&self, request: DeadLetterReplayRequest) -> Result<Vec<u64>> {
+        let topic = self.topics.dead_letter_replay_topic();
+        let body = serde_json::to_vec(&request).map_err(SerializationError::from)?;
+        let resp = self.client.request(topic, body.into()).await?;
+        let body: DeadLetterReplayResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            GetResult::Success => Ok(body.replayed),
+            GetResult::NotFound => Err(ClientError::NotFound(format!("{:?}", body.not_found))),
+            GetResult::Error => Err(ClientError::ApiError(body.message)),
+        }
+    }
+
+    /// Returns up to `limit` records of the mutating calls made in the lattice, oldest first,
+    /// starting at the given sequence. Also returns the sequence to start the next page at, if
+    /// there are more records
+    pub async fn query_audit_log(
+        &self,
+        start_sequence: Option<u64>,
+        limit: Option<usize>,
+    ) -> Result<(Vec<AuditRecord>, Option<u64>)> {
+        let topic = self.topics.audit_query_topic();
+        let body = serde_json::to_vec(&AuditQueryRequest {
+            start_sequence,
+            limit,
+        })
+        .map_err(SerializationError::from)?;
+        let resp = self.client.request(topic, body.into()).await?;
+        let body: AuditQueryResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            GetResult::Success => Ok((body.records, body.next_sequence)),
+            GetResult::Error | GetResult::NotFound => Err(ClientError::ApiError(body.message)),
+        }
+    }
+
+    /// Cordons the given host, so wadm stops placing new components and providers on it. If
+    /// `drain` is set, the components and providers wadm manages on the host are also moved to
+    /// other eligible hosts
+    pub async fn cordon_host(&self, host_id: &str, drain: bool) -> Result<()> {
+        let body =
+            serde_json::to_vec(&CordonHostRequest { drain }).map_err(SerializationError::from)?;
+        self.send_cordon_request(self.topics.host_cordon_topic(host_id), body)
+            .await
+    }
+
+    /// Removes the cordon from the given host, so wadm can place workloads on it again
+    pub async fn uncordon_host(&self, host_id: &str) -> Result<()> {
+        self.send_cordon_request(self.topics.host_uncordon_topic(host_id), Vec::new())
+            .await
+    }
+
+    async fn send_cordon_request(&self, topic: String, payload: Vec<u8>) -> Result<()> {
+        let resp = self.client.request(topic, payload.into()).await?;
+        let body: CordonHostResponse =
+            serde_json::from_slice(&resp.payload).map_err(SerializationError::from)?;
+        match body.result {
+            GetResult::Success => Ok(()),
+            GetResult::NotFound => Err(ClientError::NotFound(body.message)),
+            GetResult::Error => Err(ClientError::ApiError(body.message)),
+        }
+    }
+
     /// Subscribes to the status of a given manifest
     pub async fn subscribe_to_status(&self, name: &str) -> Result<impl Stream<Item = Message>> {
         let subject = self.topics.wadm_status_topic(name);
@@ -294,4 +870,14 @@ impl Client {
 
         Ok(subscriber)
     }
+
+    /// Subscribes to the status of a given manifest, decoding each status update as it is
+    /// published. Updates that can't be decoded are returned as errors without ending the stream
+    pub async fn watch_status(&self, name: &str) -> Result<impl Stream<Item = Result<Status>>> {
+        let subscriber = self.subscribe_to_status(name).await?;
+        Ok(subscriber.map(|msg| {
+            serde_json::from_slice(&msg.payload)
+                .map_err(|e| ClientError::from(SerializationError::from(e)))
+        }))
+    }
 }
@@ -1,29 +1,91 @@
-use wadm_types::api::{DEFAULT_WADM_TOPIC_PREFIX, WADM_STATUS_API_PREFIX};
+use wadm_types::api::{
+    ADMIN_CONSUMERS_SUBJECT, ADMIN_INFO_SUBJECT, ADMIN_LATTICES_SUBJECT, ADMIN_PERMITS_SUBJECT,
+    ADMIN_RELOAD_SUBJECT, ADMIN_SCALERS_SUBJECT, API_VERSION, DEFAULT_WADM_TOPIC_PREFIX,
+    READ_ONLY_API_SEGMENT, WADM_STATUS_API_PREFIX,
+};
 
 /// A generator that uses various config options to generate the proper topic names for the wadm API
 pub struct TopicGenerator {
-    topic_prefix: String,
+    api_prefix: String,
+    lattice: String,
+    version: Option<String>,
+    read_only: bool,
+    request_prefix: String,
     model_prefix: String,
 }
 
 impl TopicGenerator {
     /// Creates a new topic generator with a lattice ID and an optional API prefix
     pub fn new(lattice: &str, prefix: Option<&str>) -> TopicGenerator {
-        let topic_prefix = format!(
-            "{}.{}",
-            prefix.unwrap_or(DEFAULT_WADM_TOPIC_PREFIX),
-            lattice
-        );
-        let model_prefix = format!("{}.model", topic_prefix);
+        TopicGenerator::build(
+            prefix.unwrap_or(DEFAULT_WADM_TOPIC_PREFIX).to_owned(),
+            lattice.to_owned(),
+            None,
+            false,
+        )
+    }
+
+    fn build(
+        api_prefix: String,
+        lattice: String,
+        version: Option<String>,
+        read_only: bool,
+    ) -> TopicGenerator {
+        // Without a version, a lattice named like the version would be read as the version, so
+        // those always use versioned subjects
+        let version = version.or_else(|| (lattice == API_VERSION).then(|| API_VERSION.to_owned()));
+        let mut request_prefix = match version.as_deref() {
+            Some(version) => format!("{api_prefix}.{version}.{lattice}"),
+            None => format!("{api_prefix}.{lattice}"),
+        };
+        if read_only {
+            request_prefix = format!("{request_prefix}.{READ_ONLY_API_SEGMENT}");
+        }
         TopicGenerator {
-            topic_prefix,
-            model_prefix,
+            model_prefix: format!("{request_prefix}.model"),
+            api_prefix,
+            lattice,
+            version,
+            read_only,
+            request_prefix,
+        }
+    }
+
+    /// Returns a generator for the same lattice that sends requests to the read-only API. Requests
+    /// for operations that change anything are rejected there
+    pub fn read_only(&self) -> TopicGenerator {
+        TopicGenerator::build(
+            self.api_prefix.clone(),
+            self.lattice.clone(),
+            self.version.clone(),
+            true,
+        )
+    }
+
+    /// Returns a generator for the same lattice that sends requests to the versioned API subjects
+    /// (e.g. `wadm.api.v1.{lattice}.model.get`). Only versions of wadm that serve versioned subjects
+    /// answer these requests
+    pub fn versioned(&self) -> TopicGenerator {
+        TopicGenerator::build(
+            self.api_prefix.clone(),
+            self.lattice.clone(),
+            Some(API_VERSION.to_owned()),
+            self.read_only,
+        )
+    }
+
+    /// Returns the prefix for the admin topics, which aren't scoped to a lattice
+    fn admin_prefix(&self) -> String {
+        match self.version.as_deref() {
+            Some(version) => format!("{}.{version}", self.api_prefix),
+            None => self.api_prefix.clone(),
         }
     }
 
-    /// Returns the full prefix for the topic, including the API prefix and the lattice ID
+    /// Returns the full prefix for the topic, including the API prefix and the lattice ID (and the
+    /// read-only segment for read-only generators)
     pub fn prefix(&self) -> &str {
-        &self.topic_prefix
+        &self.request_prefix
     }
 
     /// Returns the full prefix for model operations (currently the only operations supported in the
@@ -37,6 +99,11 @@ impl TopicGenerator {
         format!("{}.put", self.model_prefix())
     }
 
+    /// Returns the full topic for a model lint operation
+    pub fn model_lint_topic(&self) -> String {
+        format!("{}.lint", self.model_prefix())
+    }
+
     /// Returns the full topic for a model get operation
     pub fn model_get_topic(&self, model_name: &str) -> String {
         format!("{}.get.{model_name}", self.model_prefix())
@@ -52,6 +119,16 @@ impl TopicGenerator {
         format!("{}.list", self.model_prefix())
     }
 
+    /// Returns the full topic for exporting every model as a bundle
+    pub fn model_export_topic(&self) -> String {
+        format!("{}.export", self.model_prefix())
+    }
+
+    /// Returns the full topic for importing a bundle of models
+    pub fn model_import_topic(&self) -> String {
+        format!("{}.import", self.model_prefix())
+    }
+
     /// Returns the full topic for listing the versions of a model
     pub fn model_versions_topic(&self, model_name: &str) -> String {
         format!("{}.versions.{model_name}", self.model_prefix())
@@ -67,16 +144,101 @@ impl TopicGenerator {
         format!("{}.undeploy.{model_name}", self.model_prefix())
     }
 
+    /// Returns the full topic for pausing reconciliation of a model
+    pub fn model_pause_topic(&self, model_name: &str) -> String {
+        format!("{}.pause.{model_name}", self.model_prefix())
+    }
+
+    /// Returns the full topic for resuming reconciliation of a model
+    pub fn model_resume_topic(&self, model_name: &str) -> String {
+        format!("{}.resume.{model_name}", self.model_prefix())
+    }
+
     /// Returns the full topic for getting a model status
     pub fn model_status_topic(&self, model_name: &str) -> String {
         format!("{}.status.{model_name}", self.model_prefix())
     }
 
-    /// Returns the full topic for WADM status subscriptions
+    /// Returns the full topic for explaining where a component of a stored model is placed
+    pub fn model_placement_topic(&self, model_name: &str) -> String {
+        format!("{}.placement.{model_name}", self.model_prefix())
+    }
+
+    /// Returns the full topic for explaining where a component of a given manifest is placed
+    pub fn manifest_placement_topic(&self) -> String {
+        format!("{}.placement", self.model_prefix())
+    }
+
+    /// Returns the full topic for getting the lattice topology
+    pub fn lattice_topology_topic(&self) -> String {
+        format!("{}.lattice.topology", self.prefix())
+    }
+
+    /// Returns the full topic for getting which wadm replica the lattice is sharded to
+    pub fn lattice_shard_topic(&self) -> String {
+        format!("{}.lattice.shard", self.prefix())
+    }
+
+    /// Returns the full topic for cordoning a host
+    pub fn host_cordon_topic(&self, host_id: &str) -> String {
+        format!("{}.host.cordon.{host_id}", self.prefix())
+    }
+
+    /// Returns the full topic for uncordoning a host
+    pub fn host_uncordon_topic(&self, host_id: &str) -> String {
+        format!("{}.host.uncordon.{host_id}", self.prefix())
+    }
+
+    /// Returns the full topic for listing dead lettered commands
+    pub fn dead_letter_list_topic(&self) -> String {
+        format!("{}.dlq.list", self.prefix())
+    }
+
+    /// Returns the full topic for replaying dead lettered commands
+    pub fn dead_letter_replay_topic(&self) -> String {
+        format!("{}.dlq.replay", self.prefix())
+    }
+
+    /// Returns the full topic for querying the audit log
+    pub fn audit_query_topic(&self) -> String {
+        format!("{}.audit.query", self.prefix())
+    }
+
+    /// Returns the full topic for reloading the runtime config of every wadm listening on the API
+    /// prefix. This topic isn't scoped to a lattice
+    pub fn admin_reload_topic(&self) -> String {
+        format!("{}.{ADMIN_RELOAD_SUBJECT}", self.admin_prefix())
+    }
+
+    /// Returns the full topic for getting the version and build info of a wadm process. This topic
+    /// isn't scoped to a lattice
+    pub fn admin_info_topic(&self) -> String {
+        format!("{}.{ADMIN_INFO_SUBJECT}", self.admin_prefix())
+    }
+
+    /// Returns the full topic for listing the lattices a wadm process is managing
+    pub fn admin_lattices_topic(&self) -> String {
+        format!("{}.{ADMIN_LATTICES_SUBJECT}", self.admin_prefix())
+    }
+
+    /// Returns the full topic for listing the consumers a wadm process is running
+    pub fn admin_consumers_topic(&self) -> String {
+        format!("{}.{ADMIN_CONSUMERS_SUBJECT}", self.admin_prefix())
+    }
+
+    /// Returns the full topic for listing the scalers a wadm process is running
+    pub fn admin_scalers_topic(&self) -> String {
+        format!("{}.{ADMIN_SCALERS_SUBJECT}", self.admin_prefix())
+    }
+
+    /// Returns the full topic for getting the work permit usage of a wadm process
+    pub fn admin_permits_topic(&self) -> String {
+        format!("{}.{ADMIN_PERMITS_SUBJECT}", self.admin_prefix())
+    }
+
+    /// Returns the full topic for WADM status subscriptions. Statuses are published to
+    /// `wadm.status.{lattice}.{app_name}` whatever the API prefix is
     pub fn wadm_status_topic(&self, app_name: &str) -> String {
-        format!(
-            "{}.{}.{}",
-            WADM_STATUS_API_PREFIX, self.topic_prefix, app_name
-        )
+        format!("{WADM_STATUS_API_PREFIX}.{}.{app_name}", self.lattice)
     }
 }
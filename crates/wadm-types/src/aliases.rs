@@ -0,0 +1,236 @@
+//! Friendly aliases for capability contracts. An alias like `httpserver` stands in for the
+//! namespace, package and interfaces of a link and for the image of the provider that implements
+//! them, so long contract strings don't have to be copied between manifests. Aliases are expanded
+//! before a manifest is validated and stored
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{Manifest, Properties, TraitProperty};
+
+/// The contract and default provider an alias expands to
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CapabilityAlias {
+    /// The WIT namespace of the contract, e.g. `wasi`
+    pub namespace: String,
+    /// The WIT package of the contract, e.g. `http`
+    pub package: String,
+    /// The interfaces used when a link to this alias doesn't list any
+    #[serde(default)]
+    pub interfaces: Vec<String>,
+    /// The image reference of the provider used when a capability component uses this alias as
+    /// its image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+}
+
+/// A registry of capability aliases, keyed by their friendly name
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct AliasRegistry {
+    aliases: BTreeMap<String, CapabilityAlias>,
+}
+
+impl AliasRegistry {
+    /// Parses a registry from YAML (or JSON) mapping alias names to their contract
+    pub fn from_slice(data: &[u8]) -> Result<AliasRegistry> {
+        let parsed: BTreeMap<String, CapabilityAlias> =
+            serde_yaml::from_slice(data).context("Unable to parse capability aliases")?;
+        let mut registry = AliasRegistry::default();
+        for (name, alias) in parsed {
+            registry.insert(name, alias)?;
+        }
+        Ok(registry)
+    }
+
+    /// Loads a registry from the given file
+    pub async fn from_file(path: impl AsRef<Path>) -> Result<AliasRegistry> {
+        let data = tokio::fs::read(path.as_ref()).await.with_context(|| {
+            format!(
+                "Unable to read capability aliases from {}",
+                path.as_ref().display()
+            )
+        })?;
+        AliasRegistry::from_slice(&data)
+    }
+
+    /// Adds an alias to the registry, replacing any alias with the same name. Names can't contain
+    /// `/`, `:` or `.` so they are never mistaken for an image reference
+    pub fn insert(&mut self, name: impl Into<String>, alias: CapabilityAlias) -> Result<()> {
+        let name = name.into();
+        if name.is_empty() || name.contains(['/', ':', '.']) || name.contains(char::is_whitespace) {
+            anyhow::bail!(
+                "Capability alias name [{name}] is not allowed, it can't be empty or contain '/', ':', '.' or whitespace"
+            );
+        }
+        if alias.namespace.is_empty() || alias.package.is_empty() {
+            anyhow::bail!("Capability alias [{name}] must have a namespace and package");
+        }
+        self.aliases.insert(name, alias);
+        Ok(())
+    }
+
+    /// Returns the alias with the given name
+    pub fn get(&self, name: &str) -> Option<&CapabilityAlias> {
+        self.aliases.get(name)
+    }
+
+    /// Returns whether the registry has no aliases
+    pub fn is_empty(&self) -> bool {
+        self.aliases.is_empty()
+    }
+
+    /// Expands every alias used in the manifest in place. Links with a `capability` get any
+    /// namespace, package or interfaces they don't set from the alias, and capability components
+    /// whose image is an alias name get the image of the alias. Returns a message for each alias
+    /// that couldn't be expanded
+    pub fn expand(&self, manifest: &mut Manifest) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        for component in manifest.spec.components.iter_mut() {
+            if let Properties::Capability { properties } = &mut component.properties {
+                if let Some(image) = properties.image.as_mut() {
+                    if let Some(alias) = self.get(image) {
+                        match alias.image.as_ref() {
+                            Some(alias_image) => *image = alias_image.clone(),
+                            None => errors.push(format!(
+                                "Component [{}] uses capability alias [{image}] as its image, but the alias has no default image",
+                                component.name
+                            )),
+                        }
+                    }
+                }
+            }
+
+            for trait_item in component.traits.iter_mut().flatten() {
+                let TraitProperty::Link(link) = &mut trait_item.properties else {
+                    continue;
+                };
+                let Some(name) = link.capability.take() else {
+                    continue;
+                };
+                let Some(alias) = self.get(&name) else {
+                    errors.push(format!(
+                        "Component [{}] links to unknown capability alias [{name}]",
+                        component.name
+                    ));
+                    continue;
+                };
+                if link.namespace.is_empty() {
+                    link.namespace.clone_from(&alias.namespace);
+                }
+                if link.package.is_empty() {
+                    link.package.clone_from(&alias.package);
+                }
+                if link.interfaces.is_empty() {
+                    link.interfaces.clone_from(&alias.interfaces);
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::LinkProperty;
+
+    #[test]
+    fn expands_aliases_in_links_and_images() {
+        let registry = AliasRegistry::from_slice(
+            br#"
+httpserver:
+  namespace: wasi
+  package: http
+  interfaces: [incoming-handler]
+  image: ghcr.io/wasmcloud/http-server:0.23.0
+keyvalue:
+  namespace: wasi
+  package: keyvalue
+"#,
+        )
+        .unwrap();
+        assert!(
+            AliasRegistry::from_slice(b"ghcr.io/http:\n  namespace: wasi\n  package: http\n")
+                .is_err(),
+            "Alias names that look like images should be rejected"
+        );
+
+        let mut manifest: Manifest = serde_yaml::from_str(
+            r#"
+apiVersion: core.oam.dev/v1beta1
+kind: Application
+metadata:
+  name: aliased
+  annotations:
+    version: v0.0.1
+spec:
+  components:
+    - name: hello
+      type: component
+      properties:
+        image: ghcr.io/wasmcloud/components/http-hello-world-rust:0.1.0
+      traits:
+        - type: link
+          properties:
+            capability: keyvalue
+            interfaces: [atomics]
+            target:
+              name: kv
+    - name: httpserver
+      type: capability
+      properties:
+        image: httpserver
+      traits:
+        - type: link
+          properties:
+            capability: httpserver
+            target:
+              name: hello
+"#,
+        )
+        .unwrap();
+        registry.expand(&mut manifest).unwrap();
+
+        let links = manifest
+            .links()
+            .filter_map(|t| match &t.properties {
+                TraitProperty::Link(link) => Some(link.clone()),
+                _ => None,
+            })
+            .collect::<Vec<LinkProperty>>();
+        assert_eq!(
+            (
+                links[0].namespace.as_str(),
+                links[0].package.as_str(),
+                links[0].interfaces.clone()
+            ),
+            ("wasi", "keyvalue", vec!["atomics".to_string()]),
+            "Interfaces set on the link should be kept"
+        );
+        assert_eq!(links[1].interfaces, vec!["incoming-handler".to_string()]);
+        assert!(links.iter().all(|link| link.capability.is_none()));
+        assert!(matches!(
+            &manifest.spec.components[1].properties,
+            Properties::Capability { properties }
+                if properties.image.as_deref() == Some("ghcr.io/wasmcloud/http-server:0.23.0")
+        ));
+
+        let mut unknown = manifest.clone();
+        if let TraitProperty::Link(link) =
+            &mut unknown.spec.components[0].traits.as_mut().unwrap()[0].properties
+        {
+            link.capability = Some("blobstore".to_string());
+        }
+        assert_eq!(registry.expand(&mut unknown).unwrap_err().len(), 1);
+    }
+}
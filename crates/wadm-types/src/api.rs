@@ -1,20 +1,51 @@
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::Manifest;
+use crate::{validation::ValidationFailure, Manifest};
 
 /// The default topic prefix for the wadm API;
 pub const DEFAULT_WADM_TOPIC_PREFIX: &str = "wadm.api";
+/// The current version of the API. Versioned subjects put it after the API prefix, e.g.
+/// `wadm.api.v1.{lattice}.model.get`, so breaking changes can be made under a new version while
+/// clients of the old one keep working. Subjects without a version are answered as this version
+pub const API_VERSION: &str = "v1";
 pub const WADM_STATUS_API_PREFIX: &str = "wadm.status";
+/// The segment after the lattice ID that marks a request as going to the read-only API, e.g.
+/// `wadm.api.{lattice}.ro.model.get`. Only operations that don't change anything are answered
+/// there, so it can be granted to dashboards and other observability tooling
+pub const READ_ONLY_API_SEGMENT: &str = "ro";
+/// The subject, relative to the API prefix, used to ask wadm to reload its runtime config
+pub const ADMIN_RELOAD_SUBJECT: &str = "admin.reload";
+/// The subject, relative to the API prefix, used to get the version and build info of wadm
+pub const ADMIN_INFO_SUBJECT: &str = "admin.info";
+/// The subject, relative to the API prefix, used to list the lattices wadm is managing
+pub const ADMIN_LATTICES_SUBJECT: &str = "admin.lattices";
+/// The subject, relative to the API prefix, used to list the consumers wadm is running
+pub const ADMIN_CONSUMERS_SUBJECT: &str = "admin.consumers";
+/// The subject, relative to the API prefix, used to list the scalers wadm is running per app
+pub const ADMIN_SCALERS_SUBJECT: &str = "admin.scalers";
+/// The subject, relative to the API prefix, used to get the usage of the work permit pool
+pub const ADMIN_PERMITS_SUBJECT: &str = "admin.permits";
+/// The header on a put request with the values for the variables of a manifest template, as a
+/// JSON object of variable names to string values
+pub const TEMPLATE_VARIABLES_HEADER: &str = "Wadm-Variables";
+/// The header on a put request with an overlay to apply to the manifest, as a JSON merge patch
+pub const TEMPLATE_OVERLAY_HEADER: &str = "Wadm-Overlay";
+/// The header callers can set to identify themselves in the audit log. This is self reported, so
+/// the identity the NATS server attaches to requests is used instead when there is one
+pub const CALLER_HEADER: &str = "Wadm-Caller";
 
 /// The request body for getting a manifest
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct GetModelRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub version: Option<String>,
 }
 
 /// The response from a get request
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct GetModelResponse {
     pub result: GetResult,
     #[serde(default)]
@@ -23,7 +54,7 @@ pub struct GetModelResponse {
     pub manifest: Option<Manifest>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct ListModelsResponse {
     pub result: GetResult,
     #[serde(default)]
@@ -32,7 +63,7 @@ pub struct ListModelsResponse {
 }
 
 /// Possible outcomes of a get request
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum GetResult {
     Error,
@@ -41,7 +72,7 @@ pub enum GetResult {
 }
 
 /// The type returned when putting a model
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct PutModelResponse {
     pub result: PutResult,
     #[serde(default)]
@@ -55,7 +86,7 @@ pub struct PutModelResponse {
 }
 
 /// Possible outcomes of a put request
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum PutResult {
     Error,
@@ -63,8 +94,114 @@ pub enum PutResult {
     NewVersion,
 }
 
+/// The type returned when linting a model. Linting runs the same validation as a put along with
+/// best practice checks, without storing anything
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct LintModelResponse {
+    pub result: LintResult,
+    /// The errors and warnings found in the manifest
+    #[serde(default)]
+    pub failures: Vec<ValidationFailure>,
+    #[serde(default)]
+    pub message: String,
+}
+
+/// Possible outcomes of a lint request
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LintResult {
+    /// The manifest couldn't be linted, like when it couldn't be parsed
+    Error,
+    /// The manifest has no errors, though it may have warnings
+    Valid,
+    /// The manifest has errors and would be rejected if it was put
+    Invalid,
+}
+
+/// The version of the bundle format produced when exporting models
+pub const MODEL_BUNDLE_VERSION: u32 = 1;
+
+/// Every model stored in a lattice as a single document, used to promote models between
+/// environments or to back them up
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct ModelBundle {
+    /// The version of the bundle format
+    pub version: u32,
+    /// The lattice the models were exported from
+    pub lattice_id: String,
+    /// The RFC 3339 timestamp of when the models were exported
+    pub exported_at: String,
+    #[serde(default)]
+    pub models: Vec<BundledModel>,
+}
+
+/// A model in a bundle, with every version that was stored
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct BundledModel {
+    pub name: String,
+    /// Every stored version of the model, oldest first
+    pub versions: Vec<Manifest>,
+    /// The version that was deployed when the model was exported. Importing a model doesn't deploy
+    /// it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployed_version: Option<String>,
+}
+
+/// The response to a request to export every model in a lattice
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ExportModelsResponse {
+    pub result: GetResult,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle: Option<ModelBundle>,
+}
+
+/// What to do when a model being imported already exists
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportConflictPolicy {
+    /// Leave the existing model as it is
+    #[default]
+    Skip,
+    /// Replace the versions of the existing model with the imported ones. A deployed version is
+    /// kept as it is, since it is running
+    Overwrite,
+    /// Add the imported versions to the existing model. Versions that already exist with different
+    /// contents are added under a new version, like `v1.0.0-import.1`
+    VersionBump,
+}
+
+/// A request to import a bundle of models
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ImportModelsRequest {
+    pub bundle: ModelBundle,
+    #[serde(default)]
+    pub conflict_policy: ImportConflictPolicy,
+}
+
+/// The response to a request to import a bundle of models
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ImportModelsResponse {
+    pub result: GetResult,
+    #[serde(default)]
+    pub message: String,
+    /// Models that didn't exist before the import
+    #[serde(default)]
+    pub created: Vec<String>,
+    /// Existing models that were changed by the import
+    #[serde(default)]
+    pub updated: Vec<String>,
+    /// Existing models that were left alone
+    #[serde(default)]
+    pub skipped: Vec<String>,
+    /// Models that couldn't be imported, mapped to the reason why
+    #[serde(default)]
+    pub failed: BTreeMap<String, String>,
+}
+
 /// Summary of a given model returned when listing
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct ModelSummary {
     pub name: String,
     pub version: String,
@@ -79,7 +216,7 @@ pub struct ModelSummary {
 }
 
 /// The response to a versions request
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct VersionResponse {
     pub result: GetResult,
     #[serde(default)]
@@ -88,21 +225,30 @@ pub struct VersionResponse {
 }
 
 /// Information about a given version of a model, returned as part of a list of all versions
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct VersionInfo {
     pub version: String,
     pub deployed: bool,
+    /// The JSON merge patch applied on top of this version for the current deployment. Only set
+    /// for the deployed version, if it was deployed with overrides
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overrides: Option<serde_json::Value>,
+    /// The images of this version that were pinned to digests for the current deployment, mapped
+    /// to their reference with the digest. Only set for the deployed version, if digest pinning is
+    /// enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_images: Option<std::collections::BTreeMap<String, String>>,
 }
 
 /// A request for deleting a model
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct DeleteModelRequest {
     #[serde(default)]
     pub version: Option<String>,
 }
 
 /// A response from a delete request
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct DeleteModelResponse {
     pub result: DeleteResult,
     #[serde(default)]
@@ -112,7 +258,7 @@ pub struct DeleteModelResponse {
 }
 
 /// All possible outcomes of a delete operation
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum DeleteResult {
     Deleted,
@@ -124,13 +270,25 @@ pub enum DeleteResult {
 ///
 /// If the given version is empty (or the body is empty), it will deploy the latest version. If the
 /// version is set to "latest", it will also deploy the latest version
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// If `dry_run` is set, the server will compute the commands that would be issued to deploy the
+/// given version against the current state of the lattice and return them without deploying
+/// anything
+///
+/// If `overrides` is set, it is applied as a JSON merge patch (RFC 7386) over the given version for
+/// this deployment only. The stored version is left untouched, and the overrides are cleared the
+/// next time the model is deployed
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct DeployModelRequest {
     pub version: Option<String>,
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overrides: Option<serde_json::Value>,
 }
 
 /// A response from a deploy or undeploy request
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct DeployModelResponse {
     pub result: DeployResult,
     #[serde(default)]
@@ -139,10 +297,14 @@ pub struct DeployModelResponse {
     pub name: String,
     #[serde(default)]
     pub version: Option<String>,
+    /// The list of commands that would be issued to deploy the application. Only set when the
+    /// request was a dry run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commands: Option<Vec<serde_json::Value>>,
 }
 
 /// All possible outcomes of a deploy operation
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum DeployResult {
     Error,
@@ -150,24 +312,109 @@ pub enum DeployResult {
     NotFound,
 }
 
-/// A request to undeploy a model
-///
-/// Right now this is just an empty struct, but it is reserved for future use
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UndeployModelRequest {}
+/// A request to undeploy a model. An empty request undeploys only the given model
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct UndeployModelRequest {
+    /// If the model is shared, also undeploy the deployed applications that depend on it. They are
+    /// torn down in reverse dependency order, so the shared model is undeployed last. If this isn't
+    /// set, undeploying a shared model that is still used by other applications fails
+    #[serde(default)]
+    pub cascade: bool,
+    /// The number of seconds to wait between each stage of a cascading undeploy, giving dependent
+    /// applications time to drain. If not set, the server's default is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage_delay_seconds: Option<u64>,
+}
+
+/// A request for the status of a model. An empty request returns the current status
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct StatusRequest {
+    /// An RFC 3339 timestamp. If set, the status the model had at that point in time is returned
+    /// instead of the current status. How far back this can go depends on how much status
+    /// history wadm is configured to keep
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at: Option<String>,
+}
 
 /// A response to a status request
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct StatusResponse {
     pub result: StatusResult,
     #[serde(default)]
     pub message: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub status: Option<Status>,
+    /// The RFC 3339 timestamp of when the returned status was recorded. Only set when requesting
+    /// the status at a point in time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recorded_at: Option<String>,
+    /// What wadm did the last time it reconciled the model. Not set when requesting the status at
+    /// a point in time, or if the model hasn't been reconciled yet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_reconcile: Option<ReconcileReport>,
+    /// Commands for the model that failed on their last attempt and haven't succeeded since. Not
+    /// set when requesting the status at a point in time
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed_commands: Vec<CommandAck>,
+}
+
+/// A summary of a single pass of reconciling a model against the state of the lattice
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Default, Clone, Eq, PartialEq)]
+pub struct ReconcileReport {
+    /// The RFC 3339 timestamp of when the pass finished
+    pub time: String,
+    /// What started the pass, either the type of the event that was handled or the name of the
+    /// manifest operation, like `manifest_published`
+    pub trigger: String,
+    /// How long the pass took, in milliseconds
+    pub duration_ms: u64,
+    /// The number of commands that were issued
+    pub commands_issued: usize,
+    /// A description of each command that was issued. Only the first few commands are described
+    /// for passes that issue a lot of them
+    #[serde(default)]
+    pub commands: Vec<String>,
+    /// The names of the scalers that found the lattice didn't match the model and issued commands
+    /// to fix it
+    #[serde(default)]
+    pub drift: Vec<String>,
+    /// Every error hit during the pass
+    #[serde(default)]
+    pub errors: Vec<String>,
+}
+
+/// The last response wadm got from the lattice control interface for a command
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Eq, PartialEq)]
+pub struct CommandAck {
+    /// A description of the command
+    pub command: String,
+    pub outcome: CommandOutcome,
+    /// The message the host responded with, or the error sending the command
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub message: String,
+    /// The number of times the command has been attempted
+    pub attempts: u64,
+    /// Whether this was the last attempt, meaning wadm gave up on the command
+    #[serde(default)]
+    pub last_attempt: bool,
+    /// The RFC 3339 timestamp of when the response was received
+    pub time: String,
+}
+
+/// All possible outcomes of sending a command
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandOutcome {
+    /// The host accepted the command
+    Accepted,
+    /// The host responded, but rejected the command
+    Rejected,
+    /// The command couldn't be sent or wasn't responded to, like when the host is unreachable
+    Failed,
 }
 
 /// All possible outcomes of a status operation
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum StatusResult {
     Error,
@@ -176,7 +423,7 @@ pub enum StatusResult {
 }
 
 /// The current status of a model
-#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Default, Clone, PartialEq, Eq)]
 pub struct Status {
     #[serde(rename = "status")]
     pub info: StatusInfo,
@@ -188,6 +435,9 @@ pub struct Status {
     #[serde(default)]
     #[deprecated(since = "0.14.0")]
     pub components: Vec<ComponentStatus>,
+    /// Where instances of the model's components were running when this status was recorded
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub instances: Vec<HostInstances>,
 }
 
 impl Status {
@@ -198,12 +448,27 @@ impl Status {
             scalers,
             version: String::with_capacity(0),
             components: Vec::with_capacity(0),
+            instances: Vec::with_capacity(0),
         }
     }
+
+    /// Sets the instance distribution for this status
+    pub fn with_instances(mut self, instances: Vec<HostInstances>) -> Self {
+        self.instances = instances;
+        self
+    }
+}
+
+/// The component instances of a model running on a single host
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Default, Clone, PartialEq, Eq)]
+pub struct HostInstances {
+    pub host_id: String,
+    /// The number of instances of each component, keyed by component ID
+    pub components: BTreeMap<String, usize>,
 }
 
 /// The current status of a component
-#[derive(Debug, Serialize, Deserialize, Default, Clone, Eq, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Default, Clone, Eq, PartialEq)]
 pub struct ComponentStatus {
     pub name: String,
     #[serde(rename = "type")]
@@ -215,7 +480,7 @@ pub struct ComponentStatus {
 }
 
 /// The current status of a trait
-#[derive(Debug, Serialize, Deserialize, Default, Clone, Eq, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Default, Clone, Eq, PartialEq)]
 pub struct TraitStatus {
     #[serde(rename = "type")]
     pub trait_type: String,
@@ -224,7 +489,7 @@ pub struct TraitStatus {
 }
 
 /// The current status of a scaler
-#[derive(Debug, Serialize, Deserialize, Default, Clone, Eq, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Default, Clone, Eq, PartialEq)]
 pub struct ScalerStatus {
     /// The id of the scaler
     #[serde(default)]
@@ -240,7 +505,7 @@ pub struct ScalerStatus {
 }
 
 /// Common high-level status information
-#[derive(Debug, Serialize, Deserialize, Default, Clone, Eq, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Default, Clone, Eq, PartialEq)]
 pub struct StatusInfo {
     #[serde(rename = "type")]
     pub status_type: StatusType,
@@ -286,7 +551,7 @@ impl StatusInfo {
 }
 
 /// All possible status types
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Copy, Default)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Eq, PartialEq, Clone, Copy, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum StatusType {
     Waiting,
@@ -338,6 +603,469 @@ impl std::iter::Sum for StatusType {
     }
 }
 
+/// A request for the topology of a lattice. An empty request returns the topology as JSON
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct TopologyRequest {
+    #[serde(default)]
+    pub format: TopologyFormat,
+}
+
+/// The formats a lattice topology can be returned in
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TopologyFormat {
+    /// The topology is returned as a [`Topology`] in the `topology` field of the response
+    #[default]
+    Json,
+    /// The topology is returned as a GraphViz DOT document in the `dot` field of the response
+    Dot,
+}
+
+/// A response to a topology request
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct TopologyResponse {
+    pub result: GetResult,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topology: Option<Topology>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dot: Option<String>,
+}
+
+/// A graph of everything wadm knows about in a lattice: hosts, deployed applications, and the
+/// components and providers running on hosts along with the links between them
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Default, Clone, PartialEq, Eq)]
+pub struct Topology {
+    pub nodes: Vec<TopologyNode>,
+    pub edges: Vec<TopologyEdge>,
+}
+
+/// A single node in a [`Topology`]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct TopologyNode {
+    /// The unique ID of the node in the graph. This is the ID of the underlying object prefixed
+    /// with its kind (e.g. `host:NASDASDIMAREALHOST`)
+    pub id: String,
+    pub kind: TopologyNodeKind,
+    /// A human friendly name for the node
+    pub label: String,
+}
+
+/// The kinds of nodes in a [`Topology`]
+#[derive(
+    Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum TopologyNodeKind {
+    Host,
+    Application,
+    Component,
+    Provider,
+}
+
+impl TopologyNodeKind {
+    /// Returns the prefix used for IDs of nodes of this kind
+    pub fn id_prefix(&self) -> &'static str {
+        match self {
+            TopologyNodeKind::Host => "host",
+            TopologyNodeKind::Application => "app",
+            TopologyNodeKind::Component => "component",
+            TopologyNodeKind::Provider => "provider",
+        }
+    }
+
+    /// Returns the node ID for an object of this kind
+    pub fn node_id(&self, id: &str) -> String {
+        format!("{}:{id}", self.id_prefix())
+    }
+}
+
+/// A directed edge between two nodes in a [`Topology`]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct TopologyEdge {
+    pub from: String,
+    pub to: String,
+    pub kind: TopologyEdgeKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// The kinds of edges in a [`Topology`]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TopologyEdgeKind {
+    /// A component or provider is running on a host
+    RunsOn,
+    /// An application manages a component or provider
+    Manages,
+    /// A component or provider is linked to another
+    Link,
+}
+
+impl Topology {
+    /// Renders the topology as a GraphViz DOT document
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph lattice {\n    rankdir=LR;\n");
+        for node in self.nodes.iter() {
+            let shape = match node.kind {
+                TopologyNodeKind::Host => "box3d",
+                TopologyNodeKind::Application => "folder",
+                TopologyNodeKind::Component => "component",
+                TopologyNodeKind::Provider => "cylinder",
+            };
+            dot.push_str(&format!(
+                "    \"{}\" [label=\"{}\", shape={shape}];\n",
+                escape_dot(&node.id),
+                escape_dot(&node.label)
+            ));
+        }
+        for edge in self.edges.iter() {
+            let style = match edge.kind {
+                TopologyEdgeKind::RunsOn => "solid",
+                TopologyEdgeKind::Manages => "dotted",
+                TopologyEdgeKind::Link => "bold",
+            };
+            let label = edge
+                .label
+                .as_deref()
+                .map(|label| format!(", label=\"{}\"", escape_dot(label)))
+                .unwrap_or_default();
+            dot.push_str(&format!(
+                "    \"{}\" -> \"{}\" [style={style}{label}];\n",
+                escape_dot(&edge.from),
+                escape_dot(&edge.to)
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// A request to explain where wadm places a component of an application. The component is looked
+/// up in the given manifest, or in the stored model named in the subject if no manifest is given
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct PlacementRequest {
+    /// The name of the component in the manifest
+    pub component: String,
+    /// The version of the stored model to use. Defaults to the deployed version, or the latest
+    /// version if the model isn't deployed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// A manifest to explain the placement for instead of a stored model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<Manifest>,
+}
+
+/// A response to a placement request
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PlacementResponse {
+    pub result: GetResult,
+    #[serde(default)]
+    pub message: String,
+    /// Every host in the lattice and what the scaler of the component does with it
+    #[serde(default)]
+    pub hosts: Vec<HostPlacement>,
+}
+
+/// Whether a host is a candidate for a component, and how many instances the scaler of the
+/// component wants to run there
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct HostPlacement {
+    pub host_id: String,
+    pub friendly_name: String,
+    /// Whether new instances of the component can be placed on the host
+    pub eligible: bool,
+    /// The labels of the host that match a spread requirement or host constraint of the component
+    #[serde(default)]
+    pub matching_labels: BTreeMap<String, String>,
+    /// Why the host was included or excluded
+    #[serde(default)]
+    pub reasons: Vec<String>,
+    /// The number of instances running on the host now
+    pub current_instances: usize,
+    /// The number of instances the scaler wants on the host
+    pub planned_instances: usize,
+}
+
+/// A command that wadm gave up on after it failed on every attempt
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct DeadLetterSummary {
+    /// The sequence of the dead letter in the dead letter stream. This is used to select dead
+    /// letters to replay
+    pub sequence: u64,
+    /// The number of times the command was attempted
+    pub attempts: u64,
+    /// The error returned by the last attempt
+    pub error: String,
+    /// The RFC 3339 timestamp of when the command was dead lettered
+    pub failed_at: String,
+    /// The command that failed
+    pub command: serde_json::Value,
+}
+
+/// A response to a request to list the dead letters for a lattice
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DeadLetterListResponse {
+    pub result: GetResult,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub dead_letters: Vec<DeadLetterSummary>,
+}
+
+/// A request to replay dead lettered commands, sending them back to be executed
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DeadLetterReplayRequest {
+    /// The sequences of the dead letters to replay
+    #[serde(default)]
+    pub sequences: Vec<u64>,
+    /// Replay all dead letters for the lattice, ignoring `sequences`
+    #[serde(default)]
+    pub all: bool,
+}
+
+/// A response to a request to replay dead letters
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DeadLetterReplayResponse {
+    pub result: GetResult,
+    #[serde(default)]
+    pub message: String,
+    /// The sequences of the dead letters that were replayed
+    #[serde(default)]
+    pub replayed: Vec<u64>,
+    /// The requested sequences that weren't found for the lattice
+    #[serde(default)]
+    pub not_found: Vec<u64>,
+}
+
+/// A mutating API call recorded in the audit log
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// The sequence of the record in the audit stream. This is set when records are queried and is
+    /// used to page through the log
+    #[serde(default)]
+    pub sequence: u64,
+    /// The RFC 3339 timestamp of the call
+    pub time: String,
+    /// The operation that was called, like `put` or `deploy`
+    pub operation: String,
+    /// The account the call was made in, for multitenant deployments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
+    pub lattice_id: String,
+    /// The name of the model the call changed
+    pub name: String,
+    /// Who made the call
+    pub caller: String,
+    /// Whether the caller was identified by the NATS server. If not, the caller is self reported
+    /// with the [`CALLER_HEADER`] or `unknown`
+    #[serde(default)]
+    pub caller_verified: bool,
+    /// A summary of what changed
+    pub summary: String,
+}
+
+/// A request to page through the audit log of a lattice, oldest records first
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct AuditQueryRequest {
+    /// The sequence to start at, usually the `next_sequence` of the previous page. Defaults to the
+    /// start of the log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_sequence: Option<u64>,
+    /// The most records to return
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// A response to a request to page through the audit log
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct AuditQueryResponse {
+    pub result: GetResult,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub records: Vec<AuditRecord>,
+    /// The sequence to start the next page at, if there are more records
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_sequence: Option<u64>,
+}
+
+/// A response to a request for which wadm replica a lattice is sharded to
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ShardStatusResponse {
+    pub result: GetResult,
+    #[serde(default)]
+    pub message: String,
+    /// The ID of the wadm replica that owns the lattice
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// The IDs of all wadm replicas sharing lattices, as seen by the replica that answered
+    #[serde(default)]
+    pub members: Vec<String>,
+}
+
+/// A response to a request to reload the runtime config
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ReloadConfigResponse {
+    pub result: GetResult,
+    #[serde(default)]
+    pub message: String,
+    /// The names of the settings that changed
+    #[serde(default)]
+    pub changed: Vec<String>,
+}
+
+/// A request to cordon a host, so no new components or providers are placed on it
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct CordonHostRequest {
+    /// Also move the workloads managed by wadm off of the host
+    #[serde(default)]
+    pub drain: bool,
+}
+
+/// A response to a request to cordon or uncordon a host
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CordonHostResponse {
+    pub result: GetResult,
+    #[serde(default)]
+    pub message: String,
+}
+
+/// The version and build info of the wadm instance that answered an admin request
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// The version of wadm
+    pub version: String,
+    /// The API prefix this instance is serving
+    pub api_prefix: String,
+    /// Whether this instance is running in multitenant mode
+    pub multitenant: bool,
+    /// The number of seconds since this instance started
+    pub uptime_seconds: u64,
+}
+
+/// A response to a request for version and build info
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct AdminInfoResponse {
+    pub result: GetResult,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub info: Option<BuildInfo>,
+}
+
+/// A lattice that a wadm instance is managing
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ManagedLattice {
+    pub id: String,
+    /// The topics of the consumers running for this lattice
+    #[serde(default)]
+    pub consumers: Vec<String>,
+}
+
+/// A response to a request for the lattices a wadm instance is managing
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct AdminLatticesResponse {
+    pub result: GetResult,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub lattices: Vec<ManagedLattice>,
+}
+
+/// The state of a consumer, as seen by the wadm instance running it
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ConsumerStatus {
+    /// The topic the consumer is filtered to
+    pub topic: String,
+    pub lattice_id: String,
+    /// Whether the consumer is still running. Consumers that stopped are restarted on the next
+    /// lattice reconcile
+    pub running: bool,
+    /// The number of messages currently being handled
+    pub in_flight: usize,
+    /// The number of messages that failed processing and were nacked since the consumer started
+    pub nacked: u64,
+    /// Whether the last message handled by the consumer failed
+    pub last_failed: bool,
+    /// The stream sequence of the last message delivered to the consumer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_delivered_sequence: Option<u64>,
+    /// The number of messages for the consumer that haven't been acked, as of the last lag check
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lag: Option<u64>,
+}
+
+/// A response to a request for the consumers a wadm instance is running
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct AdminConsumersResponse {
+    pub result: GetResult,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub consumers: Vec<ConsumerStatus>,
+}
+
+/// A scaler that is running for an app
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ScalerSummary {
+    pub id: String,
+    pub name: String,
+    pub kind: String,
+    pub status: StatusInfo,
+}
+
+/// The scalers running for an app in a lattice
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct AppScalers {
+    pub lattice_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub scalers: Vec<ScalerSummary>,
+}
+
+/// A response to a request for the scalers a wadm instance is running
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct AdminScalersResponse {
+    pub result: GetResult,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub apps: Vec<AppScalers>,
+}
+
+/// The usage of the pools of permits that limit how much work is done at once. The top level
+/// counts are the totals of every pool
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct PermitUsage {
+    /// The maximum number of jobs that can run at once. Not set if there is no limit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<usize>,
+    /// The number of permits that are free
+    pub available: usize,
+    /// The number of permits in use. Not set if there is no limit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_use: Option<usize>,
+    /// The usage of each pool, keyed by the kind of work it limits (e.g. `events`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pools: BTreeMap<String, PermitUsage>,
+}
+
+/// A response to a request for the usage of the work permit pool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct AdminPermitsResponse {
+    pub result: GetResult,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permits: Option<PermitUsage>,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -394,4 +1122,36 @@ mod test {
         let empty: Vec<StatusType> = Vec::new();
         assert!(matches!(empty.into_iter().sum(), StatusType::Undeployed));
     }
+
+    #[test]
+    fn test_topology_to_dot() {
+        let topology = Topology {
+            nodes: vec![
+                TopologyNode {
+                    id: TopologyNodeKind::Host.node_id("NHOST"),
+                    kind: TopologyNodeKind::Host,
+                    label: "my \"host\"".to_string(),
+                },
+                TopologyNode {
+                    id: TopologyNodeKind::Component.node_id("echo"),
+                    kind: TopologyNodeKind::Component,
+                    label: "echo".to_string(),
+                },
+            ],
+            edges: vec![TopologyEdge {
+                from: TopologyNodeKind::Component.node_id("echo"),
+                to: TopologyNodeKind::Host.node_id("NHOST"),
+                kind: TopologyEdgeKind::RunsOn,
+                label: Some("2 instances".to_string()),
+            }],
+        };
+
+        let dot = topology.to_dot();
+        assert!(dot.starts_with("digraph lattice {"));
+        assert!(dot.contains(r#""host:NHOST" [label="my \"host\"", shape=box3d];"#));
+        assert!(
+            dot.contains(r#""component:echo" -> "host:NHOST" [style=solid, label="2 instances"];"#)
+        );
+        assert!(dot.trim_end().ends_with('}'));
+    }
 }
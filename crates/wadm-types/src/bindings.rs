@@ -3,10 +3,11 @@ use crate::{
         ComponentStatus, DeleteResult, GetResult, ModelSummary, PutResult, Status, StatusInfo,
         StatusResult, StatusType, TraitStatus, VersionInfo,
     },
-    CapabilityProperties, Component, ComponentProperties, ConfigDefinition, ConfigProperty,
-    LinkProperty, Manifest, Metadata, Policy, Properties, SecretProperty, SecretSourceProperty,
-    SharedApplicationComponentProperties, Specification, Spread, SpreadScalerProperty,
-    TargetConfig, Trait, TraitProperty,
+    AutoscalerProperty, CapabilityProperties, Component, ComponentProperties, ConfigDefinition,
+    ConfigProperty, HostConstraintProperty, LinkProperty, Manifest, Metadata, Policy, Properties,
+    ProviderConfigPayload, RolloutProperty, RolloutStrategy, ScheduleProperty, ScheduleWindow,
+    SecretProperty, SecretRef, SecretSourceProperty, SharedApplicationComponentProperties,
+    Specification, Spread, SpreadScalerProperty, TargetConfig, Trait, TraitProperty,
 };
 use wasmcloud::wadm;
 
@@ -105,6 +106,20 @@ impl From<CapabilityProperties> for wadm::types::CapabilityProperties {
             id: properties.id,
             config: properties.config.into_iter().map(|c| c.into()).collect(),
             secrets: properties.secrets.into_iter().map(|c| c.into()).collect(),
+            config_payload: properties.config_payload.map(Into::into),
+        }
+    }
+}
+
+impl From<ProviderConfigPayload> for wadm::types::ProviderConfigPayload {
+    fn from(payload: ProviderConfigPayload) -> Self {
+        match payload {
+            ProviderConfigPayload::Json(value) => {
+                wadm::types::ProviderConfigPayload::Json(value.to_string())
+            }
+            ProviderConfigPayload::Base64(encoded) => {
+                wadm::types::ProviderConfigPayload::Base64(encoded)
+            }
         }
     }
 }
@@ -114,6 +129,21 @@ impl From<ConfigProperty> for wadm::types::ConfigProperty {
         wadm::types::ConfigProperty {
             name: property.name,
             properties: property.properties.map(|props| props.into_iter().collect()),
+            secret_refs: property
+                .secret_refs
+                .into_iter()
+                .map(|(key, secret)| (key, secret.into()))
+                .collect(),
+        }
+    }
+}
+
+impl From<SecretRef> for wadm::types::SecretRef {
+    fn from(secret: SecretRef) -> Self {
+        wadm::types::SecretRef {
+            backend: secret.backend,
+            key: secret.key,
+            field: secret.field,
         }
     }
 }
@@ -165,6 +195,16 @@ impl From<TraitProperty> for wadm::types::TraitProperty {
             TraitProperty::SpreadScaler(spread) => {
                 wadm::types::TraitProperty::Spreadscaler(spread.into())
             }
+            TraitProperty::Rollout(rollout) => wadm::types::TraitProperty::Rollout(rollout.into()),
+            TraitProperty::HostConstraint(constraint) => {
+                wadm::types::TraitProperty::Hostconstraint(constraint.into())
+            }
+            TraitProperty::Autoscaler(autoscaler) => {
+                wadm::types::TraitProperty::Autoscaler(autoscaler.into())
+            }
+            TraitProperty::Schedule(schedule) => {
+                wadm::types::TraitProperty::Schedule(schedule.into())
+            }
             TraitProperty::Custom(custom) => wadm::types::TraitProperty::Custom(custom.to_string()),
         }
     }
@@ -207,6 +247,69 @@ impl From<SpreadScalerProperty> for wadm::types::SpreadscalerProperty {
         wadm::types::SpreadscalerProperty {
             instances: property.instances as u32,
             spread: property.spread.into_iter().map(|s| s.into()).collect(),
+            hosts: property.hosts,
+            max_concurrent_changes: property.max_concurrent_changes.map(|max| max as u32),
+            max_per_host: property.max_per_host.map(|max| max as u32),
+            anti_affinity: property.anti_affinity,
+        }
+    }
+}
+
+impl From<RolloutProperty> for wadm::types::RolloutProperty {
+    fn from(property: RolloutProperty) -> Self {
+        wadm::types::RolloutProperty {
+            strategy: property.strategy.into(),
+            steps: property.steps,
+            bake_time_seconds: property.bake_time_seconds,
+        }
+    }
+}
+
+impl From<RolloutStrategy> for wadm::types::RolloutStrategy {
+    fn from(strategy: RolloutStrategy) -> Self {
+        match strategy {
+            RolloutStrategy::Canary => wadm::types::RolloutStrategy::Canary,
+            RolloutStrategy::BlueGreen => wadm::types::RolloutStrategy::BlueGreen,
+        }
+    }
+}
+
+impl From<HostConstraintProperty> for wadm::types::HostConstraintProperty {
+    fn from(property: HostConstraintProperty) -> Self {
+        wadm::types::HostConstraintProperty {
+            min_host_version: property.min_host_version,
+            required_labels: property.required_labels.into_iter().collect(),
+        }
+    }
+}
+
+impl From<AutoscalerProperty> for wadm::types::AutoscalerProperty {
+    fn from(property: AutoscalerProperty) -> Self {
+        wadm::types::AutoscalerProperty {
+            subject: property.subject,
+            target_value: property.target_value,
+            min_instances: property.min_instances as u32,
+            max_instances: property.max_instances as u32,
+            step: property.step as u32,
+            cooldown_seconds: property.cooldown_seconds,
+        }
+    }
+}
+
+impl From<ScheduleProperty> for wadm::types::ScheduleProperty {
+    fn from(property: ScheduleProperty) -> Self {
+        wadm::types::ScheduleProperty {
+            timezone: property.timezone,
+            windows: property.windows.into_iter().map(|w| w.into()).collect(),
+        }
+    }
+}
+
+impl From<ScheduleWindow> for wadm::types::ScheduleWindow {
+    fn from(window: ScheduleWindow) -> Self {
+        wadm::types::ScheduleWindow {
+            cron: window.cron,
+            instances: window.instances as u32,
         }
     }
 }
@@ -421,6 +524,20 @@ impl From<wadm::types::CapabilityProperties> for CapabilityProperties {
             id: properties.id,
             config: properties.config.into_iter().map(|c| c.into()).collect(),
             secrets: properties.secrets.into_iter().map(|c| c.into()).collect(),
+            config_payload: properties.config_payload.map(Into::into),
+        }
+    }
+}
+
+impl From<wadm::types::ProviderConfigPayload> for ProviderConfigPayload {
+    fn from(payload: wadm::types::ProviderConfigPayload) -> Self {
+        match payload {
+            wadm::types::ProviderConfigPayload::Json(json) => ProviderConfigPayload::Json(
+                serde_json::from_str(&json).unwrap_or(serde_json::Value::String(json)),
+            ),
+            wadm::types::ProviderConfigPayload::Base64(encoded) => {
+                ProviderConfigPayload::Base64(encoded)
+            }
         }
     }
 }
@@ -430,6 +547,21 @@ impl From<wadm::types::ConfigProperty> for ConfigProperty {
         ConfigProperty {
             name: property.name,
             properties: property.properties.map(|props| props.into_iter().collect()),
+            secret_refs: property
+                .secret_refs
+                .into_iter()
+                .map(|(key, secret)| (key, secret.into()))
+                .collect(),
+        }
+    }
+}
+
+impl From<wadm::types::SecretRef> for SecretRef {
+    fn from(secret: wadm::types::SecretRef) -> Self {
+        SecretRef {
+            backend: secret.backend,
+            key: secret.key,
+            field: secret.field,
         }
     }
 }
@@ -481,6 +613,16 @@ impl From<wadm::types::TraitProperty> for TraitProperty {
             wadm::types::TraitProperty::Spreadscaler(spread) => {
                 TraitProperty::SpreadScaler(spread.into())
             }
+            wadm::types::TraitProperty::Rollout(rollout) => TraitProperty::Rollout(rollout.into()),
+            wadm::types::TraitProperty::Hostconstraint(constraint) => {
+                TraitProperty::HostConstraint(constraint.into())
+            }
+            wadm::types::TraitProperty::Autoscaler(autoscaler) => {
+                TraitProperty::Autoscaler(autoscaler.into())
+            }
+            wadm::types::TraitProperty::Schedule(schedule) => {
+                TraitProperty::Schedule(schedule.into())
+            }
             wadm::types::TraitProperty::Custom(custom) => {
                 TraitProperty::Custom(serde_json::value::Value::String(custom))
             }
@@ -498,6 +640,7 @@ impl From<wadm::types::LinkProperty> for LinkProperty {
             package: property.package,
             interfaces: property.interfaces,
             name: property.name,
+            capability: None,
             source_config: None,
             target_config: None,
         }
@@ -528,6 +671,69 @@ impl From<wadm::types::SpreadscalerProperty> for SpreadScalerProperty {
         SpreadScalerProperty {
             instances: property.instances as usize,
             spread: property.spread.into_iter().map(|s| s.into()).collect(),
+            hosts: property.hosts,
+            max_concurrent_changes: property.max_concurrent_changes.map(|max| max as usize),
+            max_per_host: property.max_per_host.map(|max| max as usize),
+            anti_affinity: property.anti_affinity,
+        }
+    }
+}
+
+impl From<wadm::types::RolloutProperty> for RolloutProperty {
+    fn from(property: wadm::types::RolloutProperty) -> Self {
+        RolloutProperty {
+            strategy: property.strategy.into(),
+            steps: property.steps,
+            bake_time_seconds: property.bake_time_seconds,
+        }
+    }
+}
+
+impl From<wadm::types::RolloutStrategy> for RolloutStrategy {
+    fn from(strategy: wadm::types::RolloutStrategy) -> Self {
+        match strategy {
+            wadm::types::RolloutStrategy::Canary => RolloutStrategy::Canary,
+            wadm::types::RolloutStrategy::BlueGreen => RolloutStrategy::BlueGreen,
+        }
+    }
+}
+
+impl From<wadm::types::HostConstraintProperty> for HostConstraintProperty {
+    fn from(property: wadm::types::HostConstraintProperty) -> Self {
+        HostConstraintProperty {
+            min_host_version: property.min_host_version,
+            required_labels: property.required_labels.into_iter().collect(),
+        }
+    }
+}
+
+impl From<wadm::types::AutoscalerProperty> for AutoscalerProperty {
+    fn from(property: wadm::types::AutoscalerProperty) -> Self {
+        AutoscalerProperty {
+            subject: property.subject,
+            target_value: property.target_value,
+            min_instances: property.min_instances as usize,
+            max_instances: property.max_instances as usize,
+            step: property.step as usize,
+            cooldown_seconds: property.cooldown_seconds,
+        }
+    }
+}
+
+impl From<wadm::types::ScheduleProperty> for ScheduleProperty {
+    fn from(property: wadm::types::ScheduleProperty) -> Self {
+        ScheduleProperty {
+            timezone: property.timezone,
+            windows: property.windows.into_iter().map(|w| w.into()).collect(),
+        }
+    }
+}
+
+impl From<wadm::types::ScheduleWindow> for ScheduleWindow {
+    fn from(window: wadm::types::ScheduleWindow) -> Self {
+        ScheduleWindow {
+            cron: window.cron,
+            instances: window.instances as usize,
         }
     }
 }
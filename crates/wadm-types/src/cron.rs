@@ -0,0 +1,240 @@
+//! Parsing of the cron expressions and timezones used by the schedule trait. Expressions use the
+//! standard five fields (minute, hour, day of month, month and day of week), each of which can be
+//! `*`, a value, a range like `1-5`, a list like `1,15` or a step like `*/15` or `8-18/2`. Months
+//! and days of the week can also be given by their three letter names, like `JAN` or `MON-FRI`.
+//!
+//! As in standard cron, when both the day of month and the day of week are restricted, a day
+//! matches if either of them does
+
+use std::str::FromStr;
+
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// A parsed cron expression. Each field is a bitset of the values it matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CronExpression {
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl CronExpression {
+    /// Returns whether the given minute (0-59) and hour (0-23) match
+    pub fn matches_time(&self, hour: u32, minute: u32) -> bool {
+        self.hours & (1 << hour) != 0 && self.minutes & (1 << minute) != 0
+    }
+
+    /// Returns whether the given day of the month (1-31), month (1-12) and day of the week (0-6,
+    /// starting on Sunday) match
+    pub fn matches_date(&self, day: u32, month: u32, weekday: u32) -> bool {
+        if self.months & (1 << month) == 0 {
+            return false;
+        }
+        let day_matches = self.days & (1 << day) != 0;
+        let weekday_matches = self.weekdays & (1 << weekday) != 0;
+        if self.days_restricted && self.weekdays_restricted {
+            day_matches || weekday_matches
+        } else {
+            day_matches && weekday_matches
+        }
+    }
+
+    /// Returns the matching times of day as (hour, minute), from earliest to latest
+    pub fn times(&self) -> impl DoubleEndedIterator<Item = (u32, u32)> + '_ {
+        (0..24u32)
+            .flat_map(|hour| (0..60u32).map(move |minute| (hour, minute)))
+            .filter(|(hour, minute)| self.matches_time(*hour, *minute))
+    }
+}
+
+impl FromStr for CronExpression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "cron expression '{s}' must have 5 fields (minute, hour, day of month, month and day of week)"
+            ));
+        };
+        // Sunday can also be given as 7
+        let weekday_bits = parse_field(weekdays, 0, 7, &WEEKDAYS, 0)?;
+        Ok(CronExpression {
+            minutes: parse_field(minutes, 0, 59, &[], 0)?,
+            hours: parse_field(hours, 0, 23, &[], 0)? as u32,
+            days: parse_field(days, 1, 31, &[], 0)? as u32,
+            months: parse_field(months, 1, 12, &MONTHS, 1)? as u16,
+            weekdays: ((weekday_bits | (weekday_bits >> 7)) & 0x7f) as u8,
+            days_restricted: days != "*",
+            weekdays_restricted: weekdays != "*",
+        })
+    }
+}
+
+/// Parses a single field into a bitset of the values it matches. `names` are alternative names for
+/// the values starting at `first_name`
+fn parse_field(
+    field: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+    first_name: u32,
+) -> Result<u64, String> {
+    let value = |raw: &str| -> Result<u32, String> {
+        let parsed = names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(raw))
+            .map(|pos| pos as u32 + first_name)
+            .or_else(|| raw.parse::<u32>().ok())
+            .ok_or_else(|| format!("'{raw}' is not a valid value in cron field '{field}'"))?;
+        if !(min..=max).contains(&parsed) {
+            return Err(format!(
+                "{parsed} is out of range {min}-{max} in cron field '{field}'"
+            ));
+        }
+        Ok(parsed)
+    };
+
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => {
+                    return Err(format!(
+                        "'{step}' is not a valid step in cron field '{field}'"
+                    ))
+                }
+            },
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // A step on a single value runs to the end of the range, like `5/15`
+                None if step > 1 => (value(range)?, max),
+                None => {
+                    let single = value(range)?;
+                    (single, single)
+                }
+            },
+        };
+        if start > end {
+            return Err(format!(
+                "range '{range}' in cron field '{field}' must not end before it starts"
+            ));
+        }
+        for v in (start..=end).step_by(step as usize) {
+            bits |= 1 << v;
+        }
+    }
+    Ok(bits)
+}
+
+/// The timezone a schedule is evaluated in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleTimezone {
+    /// A fixed offset from UTC, in seconds east of UTC
+    Offset(i32),
+    /// The local timezone of the host wadm runs on, including its daylight saving time rules
+    Local,
+}
+
+impl FromStr for ScheduleTimezone {
+    type Err = String;
+
+    /// Parses `UTC`, `Z`, `local` or an offset from UTC like `+02:00` or `-0530`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!("timezone '{s}' must be UTC, local or an offset from UTC like +02:00 or -05:30")
+        };
+        let trimmed = s.trim();
+        if trimmed.eq_ignore_ascii_case("utc") || trimmed.eq_ignore_ascii_case("z") {
+            return Ok(ScheduleTimezone::Offset(0));
+        }
+        if trimmed.eq_ignore_ascii_case("local") {
+            return Ok(ScheduleTimezone::Local);
+        }
+        let (sign, rest) = match trimmed.split_at_checked(1) {
+            Some(("+", rest)) => (1, rest),
+            Some(("-", rest)) => (-1, rest),
+            _ => return Err(invalid()),
+        };
+        let digits = rest.replace(':', "");
+        if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        // SAFETY: We just checked these are 4 ASCII digits
+        let hours: i32 = digits[..2].parse().unwrap();
+        let minutes: i32 = digits[2..].parse().unwrap();
+        if hours > 14 || minutes > 59 {
+            return Err(invalid());
+        }
+        Ok(ScheduleTimezone::Offset(
+            sign * (hours * 3600 + minutes * 60),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_parse_cron_expressions() {
+        let business_hours: CronExpression = "*/30 9-17 * * MON-FRI".parse().unwrap();
+        assert!(business_hours.matches_time(9, 0));
+        assert!(business_hours.matches_time(17, 30));
+        assert!(!business_hours.matches_time(18, 0));
+        assert!(!business_hours.matches_time(9, 15));
+        // 2024-06-03 was a Monday
+        assert!(business_hours.matches_date(3, 6, 1));
+        assert!(!business_hours.matches_date(2, 6, 0));
+
+        let sundays: CronExpression = "0 0 * * 7".parse().unwrap();
+        assert!(sundays.matches_date(2, 6, 0), "7 should be Sunday");
+
+        let either: CronExpression = "0 0 1 JAN,jul SAT".parse().unwrap();
+        assert!(
+            either.matches_date(1, 1, 3),
+            "The day of month should match"
+        );
+        assert!(either.matches_date(6, 7, 6), "The day of week should match");
+        assert!(
+            !either.matches_date(6, 2, 6),
+            "The month should always match"
+        );
+
+        assert!("* * * *".parse::<CronExpression>().is_err());
+        assert!("60 * * * *".parse::<CronExpression>().is_err());
+        assert!("* * * * FUNDAY".parse::<CronExpression>().is_err());
+        assert!("*/0 * * * *".parse::<CronExpression>().is_err());
+        assert!("* 5-1 * * *".parse::<CronExpression>().is_err());
+
+        assert_eq!(
+            "+02:00".parse::<ScheduleTimezone>(),
+            Ok(ScheduleTimezone::Offset(7200))
+        );
+        assert_eq!(
+            "-0530".parse::<ScheduleTimezone>(),
+            Ok(ScheduleTimezone::Offset(-19800))
+        );
+        assert_eq!(
+            "UTC".parse::<ScheduleTimezone>(),
+            Ok(ScheduleTimezone::Offset(0))
+        );
+        assert_eq!(
+            "local".parse::<ScheduleTimezone>(),
+            Ok(ScheduleTimezone::Local)
+        );
+        assert!("Europe/Berlin".parse::<ScheduleTimezone>().is_err());
+    }
+}
@@ -1,12 +1,14 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use schemars::JsonSchema;
 use serde::{de, Deserialize, Serialize};
 use utoipa::ToSchema;
 
+pub mod aliases;
 pub mod api;
 #[cfg(feature = "wit")]
 pub mod bindings;
+pub mod cron;
 #[cfg(feature = "wit")]
 pub use bindings::*;
 pub mod validation;
@@ -33,6 +35,16 @@ pub const SPREADSCALER_TRAIT: &str = "spreadscaler";
 pub const DAEMONSCALER_TRAIT: &str = "daemonscaler";
 /// The identifier for the builtin linkdef trait type
 pub const LINK_TRAIT: &str = "link";
+/// The identifier for the builtin rollout strategy trait type
+pub const ROLLOUT_TRAIT: &str = "rollout";
+/// The identifier for the builtin host constraint trait type
+pub const HOST_CONSTRAINT_TRAIT: &str = "hostconstraint";
+/// The identifier for the builtin autoscaler trait type
+pub const AUTOSCALER_TRAIT: &str = "autoscaler";
+/// The identifier for the builtin schedule trait type
+pub const SCHEDULE_TRAIT: &str = "schedule";
+/// The type of policy that declares where lifecycle notifications for an application are sent
+pub const NOTIFICATION_POLICY_TYPE: &str = "policy.notification.wasmcloud.dev/v1alpha1";
 /// The string used for indicating a latest version. It is explicitly forbidden to use as a version
 /// for a manifest
 pub const LATEST_VERSION: &str = "latest";
@@ -129,6 +141,31 @@ impl Manifest {
             .collect()
     }
 
+    /// Returns the names of the shared applications this manifest uses components from
+    pub fn shared_dependencies(&self) -> BTreeSet<&str> {
+        self.components()
+            .filter_map(|c| match &c.properties {
+                Properties::Capability {
+                    properties:
+                        CapabilityProperties {
+                            image: None,
+                            application: Some(shared_app),
+                            ..
+                        },
+                }
+                | Properties::Component {
+                    properties:
+                        ComponentProperties {
+                            image: None,
+                            application: Some(shared_app),
+                            ..
+                        },
+                } => Some(shared_app.name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
     /// Returns only the WebAssembly components in the manifest
     pub fn wasm_components(&self) -> impl Iterator<Item = &Component> {
         self.components()
@@ -169,6 +206,14 @@ impl Manifest {
             .map(|p| (&p.name, p))
             .collect::<HashMap<&String, &Policy>>()
     }
+
+    /// Returns where lifecycle notifications for this application are sent, in addition to the
+    /// wadm event stream. Notification policies that aren't valid are skipped
+    pub fn notification_routes(&self) -> Vec<NotificationRoute> {
+        self.policies()
+            .filter_map(|policy| policy.notification_route().ok().flatten())
+            .collect()
+    }
 }
 
 /// The metadata describing the manifest
@@ -209,6 +254,42 @@ pub struct Policy {
     pub policy_type: String,
 }
 
+impl Policy {
+    /// Returns the route declared by this policy if it is a notification policy. Notification
+    /// policies must have exactly one of a `subject` or `webhook` property, otherwise an error
+    /// describing the problem is returned
+    pub fn notification_route(&self) -> Result<Option<NotificationRoute>, String> {
+        if self.policy_type != NOTIFICATION_POLICY_TYPE {
+            return Ok(None);
+        }
+        match (
+            self.properties.get("subject"),
+            self.properties.get("webhook"),
+        ) {
+            (Some(subject), None) => Ok(Some(NotificationRoute::Subject(subject.to_owned()))),
+            (None, Some(webhook)) => Ok(Some(NotificationRoute::Webhook(webhook.to_owned()))),
+            (Some(_), Some(_)) => Err(format!(
+                "notification policy '{}' must only have one of a 'subject' or 'webhook' property",
+                self.name
+            )),
+            (None, None) => Err(format!(
+                "notification policy '{}' must have a 'subject' or 'webhook' property",
+                self.name
+            )),
+        }
+    }
+}
+
+/// Where lifecycle notifications for an application are sent, as declared by a notification
+/// policy
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum NotificationRoute {
+    /// Notifications are published on the given NATS subject
+    Subject(String),
+    /// Notifications are sent to the webhook with the given name, from the set configured in wadm
+    Webhook(String),
+}
+
 /// A component definition
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema, JsonSchema)]
 // TODO: figure out why this can't be uncommented
@@ -349,6 +430,73 @@ pub struct CapabilityProperties {
     /// these values at runtime using `wasmcloud:secrets/store`.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub secrets: Vec<SecretProperty>,
+    /// A configuration payload for the provider, given inline as JSON or as an opaque base64
+    /// encoded blob. Wadm puts it as a named configuration and passes it to the provider along
+    /// with the rest of its `config` when starting it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_payload: Option<ProviderConfigPayload>,
+}
+
+/// The name of the configuration key a base64 encoded provider config payload is passed in
+pub const CONFIG_PAYLOAD_B64_KEY: &str = "config_b64";
+
+/// An inline configuration payload for a provider
+///
+/// ## Usage
+/// ```yaml
+/// config_payload:
+///   json:
+///     address: "0.0.0.0:8080"
+///     timeout_ms: 5000
+/// ```
+///
+/// Each top level key of a JSON payload becomes a configuration key. String values are passed
+/// as-is and any other value is passed as its JSON encoding. A base64 payload is passed unchanged
+/// under the [`CONFIG_PAYLOAD_B64_KEY`] key for the provider to decode
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderConfigPayload {
+    /// A JSON object of configuration
+    Json(serde_json::Value),
+    /// A base64 encoded configuration blob
+    Base64(String),
+}
+
+impl ProviderConfigPayload {
+    /// Returns the configuration properties the payload is passed to the provider as, or an error
+    /// if a JSON payload isn't an object or a base64 payload isn't valid base64
+    pub fn to_properties(&self) -> Result<HashMap<String, String>, String> {
+        match self {
+            ProviderConfigPayload::Json(serde_json::Value::Object(values)) => Ok(values
+                .iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        serde_json::Value::String(s) => s.to_owned(),
+                        other => other.to_string(),
+                    };
+                    (key.to_owned(), value)
+                })
+                .collect()),
+            ProviderConfigPayload::Json(_) => {
+                Err("a JSON config payload must be an object".to_string())
+            }
+            ProviderConfigPayload::Base64(encoded) => {
+                let trimmed = encoded.trim_end_matches('=');
+                let valid = encoded.len() % 4 == 0
+                    && encoded.len() - trimmed.len() <= 2
+                    && trimmed
+                        .bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/');
+                if !valid {
+                    return Err("a base64 config payload must be valid padded base64".to_string());
+                }
+                Ok(HashMap::from([(
+                    CONFIG_PAYLOAD_B64_KEY.to_string(),
+                    encoded.to_owned(),
+                )]))
+            }
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema, JsonSchema)]
@@ -389,6 +537,58 @@ impl Trait {
         self.trait_type == SPREADSCALER_TRAIT || self.trait_type == DAEMONSCALER_TRAIT
     }
 
+    /// Check if a trait is a rollout strategy
+    pub fn is_rollout(&self) -> bool {
+        self.trait_type == ROLLOUT_TRAIT
+    }
+
+    /// Helper that creates a new rollout type trait with the given properties
+    pub fn new_rollout(props: RolloutProperty) -> Trait {
+        Trait {
+            trait_type: ROLLOUT_TRAIT.to_owned(),
+            properties: TraitProperty::Rollout(props),
+        }
+    }
+
+    /// Check if a trait is a host constraint
+    pub fn is_host_constraint(&self) -> bool {
+        self.trait_type == HOST_CONSTRAINT_TRAIT
+    }
+
+    /// Helper that creates a new host constraint type trait with the given properties
+    pub fn new_host_constraint(props: HostConstraintProperty) -> Trait {
+        Trait {
+            trait_type: HOST_CONSTRAINT_TRAIT.to_owned(),
+            properties: TraitProperty::HostConstraint(props),
+        }
+    }
+
+    /// Check if a trait is an autoscaler
+    pub fn is_autoscaler(&self) -> bool {
+        self.trait_type == AUTOSCALER_TRAIT
+    }
+
+    /// Helper that creates a new autoscaler type trait with the given properties
+    pub fn new_autoscaler(props: AutoscalerProperty) -> Trait {
+        Trait {
+            trait_type: AUTOSCALER_TRAIT.to_owned(),
+            properties: TraitProperty::Autoscaler(props),
+        }
+    }
+
+    /// Check if a trait is a schedule
+    pub fn is_schedule(&self) -> bool {
+        self.trait_type == SCHEDULE_TRAIT
+    }
+
+    /// Helper that creates a new schedule type trait with the given properties
+    pub fn new_schedule(props: ScheduleProperty) -> Trait {
+        Trait {
+            trait_type: SCHEDULE_TRAIT.to_owned(),
+            properties: TraitProperty::Schedule(props),
+        }
+    }
+
     /// Helper that creates a new spreadscaler type trait with the given properties
     pub fn new_spreadscaler(props: SpreadScalerProperty) -> Trait {
         Trait {
@@ -412,6 +612,10 @@ impl Trait {
 pub enum TraitProperty {
     Link(LinkProperty),
     SpreadScaler(SpreadScalerProperty),
+    Rollout(RolloutProperty),
+    HostConstraint(HostConstraintProperty),
+    Autoscaler(AutoscalerProperty),
+    Schedule(ScheduleProperty),
     // TODO(thomastaylor312): This is still broken right now with deserializing. If the incoming
     // type specifies instances, it matches with spreadscaler first. So we need to implement a custom
     // parser here
@@ -430,6 +634,30 @@ impl From<SpreadScalerProperty> for TraitProperty {
     }
 }
 
+impl From<RolloutProperty> for TraitProperty {
+    fn from(value: RolloutProperty) -> Self {
+        Self::Rollout(value)
+    }
+}
+
+impl From<HostConstraintProperty> for TraitProperty {
+    fn from(value: HostConstraintProperty) -> Self {
+        Self::HostConstraint(value)
+    }
+}
+
+impl From<AutoscalerProperty> for TraitProperty {
+    fn from(value: AutoscalerProperty) -> Self {
+        Self::Autoscaler(value)
+    }
+}
+
+impl From<ScheduleProperty> for TraitProperty {
+    fn from(value: ScheduleProperty) -> Self {
+        Self::Schedule(value)
+    }
+}
+
 // impl From<serde_json::Value> for TraitProperty {
 //     fn from(value: serde_json::Value) -> Self {
 //         Self::Custom(value)
@@ -460,6 +688,39 @@ pub struct ConfigProperty {
     /// and will not attempt to create it, only reporting the status as failed if not found.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub properties: Option<HashMap<String, String>>,
+    /// Configuration keys whose values are secrets, resolved by wadm from one of its configured
+    /// secret backends when it puts the configuration. Only the references are stored with the
+    /// manifest, never the resolved values. Configuration with secret references is managed by wadm
+    /// like configuration with `properties`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub secret_refs: HashMap<String, SecretRef>,
+}
+
+/// A reference to a secret held in one of the secret backends configured on wadm
+///
+/// ## Usage
+/// ```yaml
+/// config:
+///   - name: database
+///     properties:
+///       host: db.internal
+///     secret_refs:
+///       password:
+///         backend: vault
+///         key: apps/database
+///         field: password
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash, ToSchema, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SecretRef {
+    /// The name of the secret backend to resolve the secret from, like `kv`, `env`, `file` or
+    /// `vault`
+    pub backend: String,
+    /// The key of the secret in the backend
+    pub key: String,
+    /// The field to read when the secret is a JSON object, like the secrets stored in Vault
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
 }
 
 /// This impl is a helper to help compare a `Vec<String>` to a `Vec<ConfigProperty>`
@@ -474,10 +735,13 @@ impl PartialEq<ConfigProperty> for String {
 #[serde(deny_unknown_fields)]
 pub struct LinkProperty {
     /// WIT namespace for the link
+    #[serde(default)]
     pub namespace: String,
     /// WIT package for the link
+    #[serde(default)]
     pub package: String,
     /// WIT interfaces for the link
+    #[serde(default)]
     pub interfaces: Vec<String>,
     /// Configuration to apply to the source of the link
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -487,6 +751,11 @@ pub struct LinkProperty {
     /// The name of this link
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    /// A capability alias to take the namespace, package and interfaces from when they are left
+    /// out. Aliases are expanded by wadm when the manifest is put, so the stored manifest never
+    /// has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capability: Option<String>,
 
     #[serde(default, skip_serializing)]
     #[deprecated(since = "0.13.0")]
@@ -540,31 +809,49 @@ impl<'de> Deserialize<'de> for LinkProperty {
             source = Some(serde_json::from_value(s.clone()).map_err(de::Error::custom)?);
         }
 
-        // Validate that the required keys are all present
-        if json.get("namespace").is_none() {
-            return Err(de::Error::custom("namespace is required"));
-        }
+        let capability = json
+            .get("capability")
+            .map(|v| v.as_str().unwrap().to_string());
 
-        if json.get("package").is_none() {
-            return Err(de::Error::custom("package is required"));
-        }
+        // Validate that the required keys are all present. Links to a capability alias get them
+        // from the alias when it is expanded
+        if capability.is_none() {
+            if json.get("namespace").is_none() {
+                return Err(de::Error::custom("namespace is required"));
+            }
+
+            if json.get("package").is_none() {
+                return Err(de::Error::custom("package is required"));
+            }
 
-        if json.get("interfaces").is_none() {
-            return Err(de::Error::custom("interfaces is required"));
+            if json.get("interfaces").is_none() {
+                return Err(de::Error::custom("interfaces is required"));
+            }
         }
 
         Ok(LinkProperty {
-            namespace: json["namespace"].as_str().unwrap().to_string(),
-            package: json["package"].as_str().unwrap().to_string(),
-            interfaces: json["interfaces"]
-                .as_array()
-                .unwrap()
-                .iter()
+            namespace: json
+                .get("namespace")
                 .map(|v| v.as_str().unwrap().to_string())
-                .collect(),
+                .unwrap_or_default(),
+            package: json
+                .get("package")
+                .map(|v| v.as_str().unwrap().to_string())
+                .unwrap_or_default(),
+            interfaces: json
+                .get("interfaces")
+                .and_then(|v| v.as_array())
+                .map(|interfaces| {
+                    interfaces
+                        .iter()
+                        .map(|v| v.as_str().unwrap().to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
             source,
             target,
             name: json.get("name").map(|v| v.as_str().unwrap().to_string()),
+            capability,
             ..Default::default()
         })
     }
@@ -596,6 +883,26 @@ pub struct SpreadScalerProperty {
     /// Requirements for spreading those instances
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub spread: Vec<Spread>,
+    /// An explicit list of host IDs to place instances on. When set, spread requirements are
+    /// ignored and instances are only placed on these hosts. This is intended for debugging and
+    /// controlled experiments rather than general use
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+    /// The maximum number of instances that can be starting or stopping at once. Larger changes
+    /// are made in steps over multiple reconciles, each waiting on the previous step to finish.
+    /// Unlimited if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_changes: Option<usize>,
+    /// The maximum number of instances of a component to place on a single host. Instances are
+    /// spread over more hosts instead of piling onto one. Only used by spreadscalers of
+    /// components, as a provider only ever runs once per host. Unlimited if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_per_host: Option<usize>,
+    /// The names of other components in this manifest that this component is never placed on the
+    /// same host as. Instances that are already running aren't moved, but nothing new is started
+    /// on a host running any of these components
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub anti_affinity: Vec<String>,
 }
 
 /// Configuration for various spreading requirements
@@ -622,6 +929,210 @@ impl Default for Spread {
     }
 }
 
+/// The default steps, as percentages of the desired instances, used for canary rollouts
+pub const DEFAULT_CANARY_STEPS: [u8; 3] = [10, 50, 100];
+/// The default amount of time, in seconds, to wait at each step of a rollout
+pub const DEFAULT_BAKE_TIME_SECONDS: u64 = 60;
+
+/// Properties for the rollout trait. This controls how a component is moved from the previously
+/// deployed version of a manifest to the new one when its image changes
+///
+/// ## Usage
+/// ```yaml
+/// traits:
+///   - type: rollout
+///     properties:
+///       strategy: canary
+///       steps: [10, 50, 100]
+///       bake_time_seconds: 120
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RolloutProperty {
+    /// The strategy to use when rolling out a new version
+    pub strategy: RolloutStrategy,
+    /// The percentages of the desired instances to run on the new version at each step of a
+    /// canary rollout. Ignored for blue/green rollouts. Defaults to 10, 50, then 100 percent
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<u8>,
+    /// The amount of time, in seconds, that each step must be healthy before moving to the next
+    #[serde(default = "default_bake_time_seconds")]
+    pub bake_time_seconds: u64,
+}
+
+fn default_bake_time_seconds() -> u64 {
+    DEFAULT_BAKE_TIME_SECONDS
+}
+
+impl RolloutProperty {
+    /// Returns the steps for this rollout as percentages of desired instances. The returned list
+    /// is never empty and always finishes at 100 percent
+    pub fn effective_steps(&self) -> Vec<u8> {
+        let mut steps = match self.strategy {
+            RolloutStrategy::BlueGreen => Vec::new(),
+            RolloutStrategy::Canary if self.steps.is_empty() => DEFAULT_CANARY_STEPS.to_vec(),
+            RolloutStrategy::Canary => self
+                .steps
+                .iter()
+                .copied()
+                .filter(|step| *step > 0 && *step < 100)
+                .collect(),
+        };
+        if steps.last() != Some(&100) {
+            steps.push(100);
+        }
+        steps
+    }
+}
+
+/// The available strategies for rolling out a new version of a component
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum RolloutStrategy {
+    /// Start a percentage of the new version at each step, scaling down the previous version as
+    /// each step becomes healthy
+    Canary,
+    /// Start the full new version alongside the previous one and remove the previous version once
+    /// the new one is healthy
+    BlueGreen,
+}
+
+/// Properties for the host constraint trait. This limits the hosts a component or provider can be
+/// placed on to those that are able to run it correctly. Scalers won't place instances on hosts that
+/// don't meet the constraint and will remove any instances already running on them
+///
+/// ## Usage
+/// ```yaml
+/// traits:
+///   - type: hostconstraint
+///     properties:
+///       min_host_version: 1.2.0
+///       required_labels:
+///         wasmcloud.dev/feature-wasi-p3: "true"
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, ToSchema, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HostConstraintProperty {
+    /// The minimum wasmCloud host version, as a semantic version, that can run this component
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_host_version: Option<String>,
+    /// Labels that a host must have, with these exact values, to run this component. These apply
+    /// on top of any spread requirements
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub required_labels: BTreeMap<String, String>,
+}
+
+/// The default number of instances an autoscaler adds or removes at a time
+pub const DEFAULT_AUTOSCALER_STEP: usize = 1;
+/// The default amount of time, in seconds, an autoscaler waits between adjustments
+pub const DEFAULT_AUTOSCALER_COOLDOWN_SECONDS: u64 = 60;
+
+/// Properties for the autoscaler trait. This adjusts the instances of a component's spreadscaler
+/// from a metric, such as requests per second, that is published as a number on a NATS subject.
+/// The component runs enough instances for each of them to handle `target_value` of the metric,
+/// within the given bounds
+///
+/// ## Usage
+/// ```yaml
+/// traits:
+///   - type: autoscaler
+///     properties:
+///       subject: metrics.hello.requests_per_second
+///       target_value: 100
+///       min_instances: 1
+///       max_instances: 20
+///       step: 2
+///       cooldown_seconds: 30
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AutoscalerProperty {
+    /// The NATS subject the metric is published on, as a plain number for the whole component
+    pub subject: String,
+    /// The value of the metric each instance should handle
+    pub target_value: u64,
+    /// The fewest instances to run
+    pub min_instances: usize,
+    /// The most instances to run
+    pub max_instances: usize,
+    /// The most instances to add or remove in a single adjustment. Defaults to 1
+    #[serde(default = "default_autoscaler_step")]
+    pub step: usize,
+    /// The amount of time, in seconds, to wait after an adjustment before making another one.
+    /// Defaults to 60 seconds
+    #[serde(default = "default_autoscaler_cooldown_seconds")]
+    pub cooldown_seconds: u64,
+}
+
+fn default_autoscaler_step() -> usize {
+    DEFAULT_AUTOSCALER_STEP
+}
+
+fn default_autoscaler_cooldown_seconds() -> u64 {
+    DEFAULT_AUTOSCALER_COOLDOWN_SECONDS
+}
+
+impl AutoscalerProperty {
+    /// Returns the instances needed for the given value of the metric, within the bounds of the
+    /// autoscaler
+    pub fn instances_for(&self, value: f64) -> usize {
+        let needed = (value.max(0.0) / self.target_value.max(1) as f64).ceil() as usize;
+        needed.clamp(
+            self.min_instances,
+            self.max_instances.max(self.min_instances),
+        )
+    }
+}
+
+/// Properties for the schedule trait. This changes the instances of a component's spreadscaler at
+/// the times given by cron expressions, such as running more instances during business hours.
+/// The component runs the instances of the window that started most recently, or the instances in
+/// its spreadscaler if none of the windows have started within the last year
+///
+/// ## Usage
+/// ```yaml
+/// traits:
+///   - type: schedule
+///     properties:
+///       timezone: "+01:00"
+///       windows:
+///         - cron: "0 9 * * MON-FRI"
+///           instances: 20
+///         - cron: "0 18 * * MON-FRI"
+///           instances: 2
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleProperty {
+    /// The timezone the cron expressions are evaluated in. This is either `UTC`, `local` for the
+    /// timezone of the host wadm runs on, or a fixed offset from UTC like `+02:00`. Defaults to UTC
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// The windows of the schedule, each of which starts at the times its cron expression matches
+    pub windows: Vec<ScheduleWindow>,
+}
+
+impl ScheduleProperty {
+    /// Returns the timezone the schedule is evaluated in
+    pub fn timezone(&self) -> Result<cron::ScheduleTimezone, String> {
+        self.timezone
+            .as_deref()
+            .map(str::parse)
+            .unwrap_or(Ok(cron::ScheduleTimezone::Offset(0)))
+    }
+}
+
+/// A window of a schedule
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleWindow {
+    /// The cron expression for when the window starts, with the fields minute, hour, day of month,
+    /// month and day of week
+    pub cron: String,
+    /// The number of instances to run from the start of the window
+    pub instances: usize,
+}
+
 #[cfg(test)]
 mod test {
     use std::io::BufReader;
@@ -803,6 +1314,10 @@ mod test {
         let spreadscalerprop = SpreadScalerProperty {
             instances: 4,
             spread: spread_vec,
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
         let trait_item = Trait::new_spreadscaler(spreadscalerprop);
         trait_vec.push(trait_item);
@@ -819,6 +1334,7 @@ mod test {
                     vec![ConfigProperty {
                         name: "http".to_string(),
                         properties: Some(HashMap::from([("port".to_string(), "8080".to_string())])),
+                        secret_refs: HashMap::new(),
                     }]
                 },
                 ..Default::default()
@@ -852,6 +1368,7 @@ mod test {
                     id: None,
                     config: vec![],
                     secrets: vec![],
+                    config_payload: None,
                 },
             },
             traits: None,
@@ -868,6 +1385,10 @@ mod test {
         let spreadscalerprop = SpreadScalerProperty {
             instances: 1,
             spread: spread_vec,
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
         let mut trait_vec: Vec<Trait> = Vec::new();
         let trait_item = Trait::new_spreadscaler(spreadscalerprop);
@@ -881,6 +1402,7 @@ mod test {
                     id: None,
                     config: vec![],
                     secrets: vec![],
+                    config_payload: None,
                 },
             },
             traits: Some(trait_vec),
@@ -7,11 +7,13 @@ use std::sync::OnceLock;
 
 use anyhow::{Context as _, Result};
 use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    CapabilityProperties, ComponentProperties, LinkProperty, Manifest, Properties, Trait,
-    TraitProperty, LATEST_VERSION,
+    cron::CronExpression, CapabilityProperties, ComponentProperties, LinkProperty, Manifest,
+    NotificationRoute, Properties, RolloutStrategy, Trait, TraitProperty, LATEST_VERSION,
+    SPREADSCALER_TRAIT,
 };
 
 /// A namespace -> package -> interface lookup
@@ -170,7 +172,7 @@ fn is_invalid_known_interface(
 }
 
 /// Level of a failure related to validation
-#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[non_exhaustive]
 pub enum ValidationFailureLevel {
     #[default]
@@ -192,7 +194,7 @@ impl core::fmt::Display for ValidationFailureLevel {
 }
 
 /// Failure detailing a validation failure, normally indicating a failure
-#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[non_exhaustive]
 pub struct ValidationFailure {
     pub level: ValidationFailureLevel,
@@ -200,7 +202,7 @@ pub struct ValidationFailure {
 }
 
 impl ValidationFailure {
-    fn new(level: ValidationFailureLevel, msg: String) -> Self {
+    pub fn new(level: ValidationFailureLevel, msg: String) -> Self {
         ValidationFailure { level, msg }
     }
 }
@@ -309,6 +311,14 @@ pub async fn validate_manifest_bytes(
 /// - unknown packages under known namespaces
 /// - "dangling" links (missing components)
 /// - secrets mapped to unknown policies
+/// - notification policies without a valid route
+/// - links to capability aliases that weren't expanded
+/// - scalers that don't allow any concurrent changes
+/// - scalers that don't allow any instances per host or have anti-affinity with unknown components
+/// - autoscalers with bounds, targets or subjects that can't work
+/// - schedules with invalid cron expressions or timezones
+/// - provider config payloads that can't be passed to the provider
+/// - secret references without a backend or that shadow a config property
 ///
 /// Since `[ValidationFailure]` implements `ValidationOutput`, you can call `valid()` and other
 /// trait methods on it:
@@ -337,14 +347,111 @@ pub async fn validate_manifest(manifest: &Manifest) -> Result<Vec<ValidationFail
             .cloned(),
     );
     failures.extend(core_validation(manifest));
+    failures.extend(check_unexpanded_aliases(manifest));
     failures.extend(check_misnamed_interfaces(manifest));
     failures.extend(check_dangling_links(manifest));
     failures.extend(validate_policies(manifest));
     failures.extend(ensure_no_custom_traits(manifest));
     failures.extend(validate_component_properties(manifest));
+    failures.extend(check_pinned_hosts(manifest));
+    failures.extend(check_concurrent_changes(manifest));
+    failures.extend(check_placement_limits(manifest));
+    failures.extend(validate_rollouts(manifest));
+    failures.extend(validate_host_constraints(manifest));
+    failures.extend(validate_autoscalers(manifest));
+    failures.extend(validate_schedules(manifest));
+    failures.extend(validate_provider_config_payloads(manifest));
+    failures.extend(validate_secret_refs(manifest));
     Ok(failures)
 }
 
+/// Lint a WADM application manifest for best practices, returning warnings for things that are
+/// valid but likely to cause problems once deployed. This doesn't validate the manifest, so it
+/// should be used along with [`validate_manifest`].
+///
+/// At present this warns about:
+/// - images without a tag or with the `latest` tag, which can change under a running app
+/// - spread entries without requirements, which match every host
+/// - capability providers that no link uses
+pub fn lint_manifest(manifest: &Manifest) -> Vec<ValidationFailure> {
+    let mut failures = Vec::new();
+    let link_targets: HashSet<&str> = manifest
+        .links()
+        .filter_map(|t| match &t.properties {
+            TraitProperty::Link(link) => Some(link.target.name.as_str()),
+            _ => None,
+        })
+        .collect();
+    for component in manifest.components() {
+        let (Properties::Component {
+            properties: ComponentProperties { image, .. },
+        }
+        | Properties::Capability {
+            properties: CapabilityProperties { image, .. },
+        }) = &component.properties;
+        if let Some(image) = image.as_deref() {
+            match image_tag(image) {
+                Some(None) => failures.push(ValidationFailure::new(
+                    ValidationFailureLevel::Warning,
+                    format!(
+                        "image '{image}' of component '{}' has no tag, so the latest image will be used",
+                        component.name
+                    ),
+                )),
+                Some(Some("latest")) => failures.push(ValidationFailure::new(
+                    ValidationFailureLevel::Warning,
+                    format!(
+                        "image '{image}' of component '{}' uses the latest tag, which can change under a running app",
+                        component.name
+                    ),
+                )),
+                _ => {}
+            }
+        }
+
+        for trait_item in component.traits.iter().flatten() {
+            let TraitProperty::SpreadScaler(props) = &trait_item.properties else {
+                continue;
+            };
+            for spread in props.spread.iter().filter(|s| s.requirements.is_empty()) {
+                failures.push(ValidationFailure::new(
+                    ValidationFailureLevel::Warning,
+                    format!(
+                        "spread '{}' of component '{}' has no requirements and matches every host",
+                        spread.name, component.name
+                    ),
+                ));
+            }
+        }
+
+        if matches!(component.properties, Properties::Capability { .. })
+            && !link_targets.contains(component.name.as_str())
+            && !component.traits.iter().flatten().any(Trait::is_link)
+        {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Warning,
+                format!(
+                    "capability provider '{}' isn't linked to any component",
+                    component.name
+                ),
+            ));
+        }
+    }
+    failures
+}
+
+/// Returns the tag of an OCI image reference, or `None` for images that aren't OCI references, like
+/// `file://` paths, or that are pinned to a digest
+fn image_tag(image: &str) -> Option<Option<&str>> {
+    let reference = image.strip_prefix("oci://").unwrap_or(image);
+    if reference.contains("://") || reference.contains('@') {
+        return None;
+    }
+    // A tag can only be in the last path segment, since registries can have a port
+    let last_segment = reference.rsplit('/').next().unwrap_or(reference);
+    Some(last_segment.split_once(':').map(|(_, tag)| tag))
+}
+
 fn core_validation(manifest: &Manifest) -> Vec<ValidationFailure> {
     let mut failures = Vec::new();
     let mut name_registry: HashSet<String> = HashSet::new();
@@ -447,6 +554,24 @@ fn core_validation(manifest: &Manifest) -> Vec<ValidationFailure> {
 }
 
 /// Check for misnamed host-supported interfaces in the manifest
+/// Links to a capability alias only get their namespace and package once the alias is expanded
+/// with an [`AliasRegistry`](crate::aliases::AliasRegistry), which must happen before validation
+fn check_unexpanded_aliases(manifest: &Manifest) -> Vec<ValidationFailure> {
+    manifest
+        .links()
+        .filter_map(|link_trait| match &link_trait.properties {
+            TraitProperty::Link(LinkProperty {
+                capability: Some(alias),
+                ..
+            }) => Some(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!("link uses capability alias [{alias}], which was not expanded"),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
 fn check_misnamed_interfaces(manifest: &Manifest) -> Vec<ValidationFailure> {
     let mut failures = Vec::new();
     for link_trait in manifest.links() {
@@ -486,6 +611,22 @@ fn ensure_no_custom_traits(manifest: &Manifest) -> Vec<ValidationFailure> {
                         ValidationFailureLevel::Error,
                         format!("Scaler trait deserialized as custom trait, ensure fields are correct: {}", trt),
                     )),
+                    TraitProperty::Custom(trt) if trait_item.is_rollout() => failures.push(ValidationFailure::new(
+                        ValidationFailureLevel::Error,
+                        format!("Rollout trait deserialized as custom trait, ensure fields are correct: {}", trt),
+                    )),
+                    TraitProperty::Custom(trt) if trait_item.is_host_constraint() => failures.push(ValidationFailure::new(
+                        ValidationFailureLevel::Error,
+                        format!("Host constraint trait deserialized as custom trait, ensure fields are correct: {}", trt),
+                    )),
+                    TraitProperty::Custom(trt) if trait_item.is_autoscaler() => failures.push(ValidationFailure::new(
+                        ValidationFailureLevel::Error,
+                        format!("Autoscaler trait deserialized as custom trait, ensure fields are correct: {}", trt),
+                    )),
+                    TraitProperty::Custom(trt) if trait_item.is_schedule() => failures.push(ValidationFailure::new(
+                        ValidationFailureLevel::Error,
+                        format!("Schedule trait deserialized as custom trait, ensure fields are correct: {}", trt),
+                    )),
                     _ => (),
                 }
             }
@@ -494,6 +635,417 @@ fn ensure_no_custom_traits(manifest: &Manifest) -> Vec<ValidationFailure> {
     failures
 }
 
+/// Check scalers that are pinned to specific hosts. Whether the hosts actually exist can only be
+/// known at runtime, so this only checks that the host IDs look like host public keys and warns
+/// about spread requirements that will be ignored.
+fn check_pinned_hosts(manifest: &Manifest) -> Vec<ValidationFailure> {
+    let mut failures = Vec::new();
+    for component in manifest.components() {
+        for trait_item in component.traits.iter().flatten() {
+            let TraitProperty::SpreadScaler(props) = &trait_item.properties else {
+                continue;
+            };
+            if props.hosts.is_empty() {
+                continue;
+            }
+            if !props.spread.is_empty() {
+                failures.push(ValidationFailure::new(
+                    ValidationFailureLevel::Warning,
+                    format!(
+                        "component '{}' is pinned to specific hosts, its spread requirements will be ignored",
+                        component.name
+                    ),
+                ));
+            }
+            for host_id in props.hosts.iter() {
+                if !is_valid_host_id(host_id) {
+                    failures.push(ValidationFailure::new(
+                        ValidationFailureLevel::Error,
+                        format!(
+                            "component '{}' is pinned to '{host_id}' which is not a valid host ID",
+                            component.name
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    failures
+}
+
+/// A limit of 0 concurrent changes would keep the scaler from ever making progress
+fn check_concurrent_changes(manifest: &Manifest) -> Vec<ValidationFailure> {
+    manifest
+        .components()
+        .flat_map(|component| {
+            component
+                .traits
+                .iter()
+                .flatten()
+                .filter(|trait_item| {
+                    matches!(
+                        &trait_item.properties,
+                        TraitProperty::SpreadScaler(props) if props.max_concurrent_changes == Some(0)
+                    )
+                })
+                .map(|_| {
+                    ValidationFailure::new(
+                        ValidationFailureLevel::Error,
+                        format!(
+                            "component '{}' has max_concurrent_changes set to 0, it must be at least 1",
+                            component.name
+                        ),
+                    )
+                })
+        })
+        .collect()
+}
+
+/// A limit of 0 instances per host can never be satisfied, and anti-affinity can only refer to
+/// other components in the manifest
+fn check_placement_limits(manifest: &Manifest) -> Vec<ValidationFailure> {
+    let mut failures = Vec::new();
+    for component in manifest.components() {
+        for props in component.traits.iter().flatten().filter_map(|trait_item| {
+            match &trait_item.properties {
+                TraitProperty::SpreadScaler(props) => Some(props),
+                _ => None,
+            }
+        }) {
+            if props.max_per_host == Some(0) {
+                failures.push(ValidationFailure::new(
+                    ValidationFailureLevel::Error,
+                    format!(
+                        "component '{}' has max_per_host set to 0, it must be at least 1",
+                        component.name
+                    ),
+                ));
+            }
+            for name in props.anti_affinity.iter() {
+                if name == &component.name {
+                    failures.push(ValidationFailure::new(
+                        ValidationFailureLevel::Error,
+                        format!(
+                            "component '{}' can't have anti-affinity with itself, use max_per_host to limit instances per host",
+                            component.name
+                        ),
+                    ));
+                } else if !manifest.components().any(|other| &other.name == name) {
+                    failures.push(ValidationFailure::new(
+                        ValidationFailureLevel::Error,
+                        format!(
+                            "component '{}' has anti-affinity with unknown component '{name}'",
+                            component.name
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    failures
+}
+
+/// Check whether a host ID is valid. Host IDs are 56 character nkey public keys that start with
+/// `N`
+pub fn is_valid_host_id(host_id: &str) -> bool {
+    host_id.len() == 56
+        && host_id.starts_with('N')
+        && host_id
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+/// Ensure that rollout traits are only used where they can take effect and that canary steps are
+/// well formed.
+fn validate_rollouts(manifest: &Manifest) -> Vec<ValidationFailure> {
+    let mut failures = Vec::new();
+    for component in manifest.components() {
+        let traits = component.traits.as_deref().unwrap_or_default();
+        let rollouts = traits
+            .iter()
+            .filter_map(|t| match &t.properties {
+                TraitProperty::Rollout(props) if t.is_rollout() => Some(props),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let Some(rollout) = rollouts.first() else {
+            continue;
+        };
+
+        if rollouts.len() > 1 {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!(
+                    "component '{}' has more than one rollout trait",
+                    component.name
+                ),
+            ));
+        }
+        if matches!(component.properties, Properties::Capability { .. }) {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!(
+                    "rollout trait on '{}' is only supported for components, not providers",
+                    component.name
+                ),
+            ));
+            continue;
+        }
+        if !traits.iter().any(|t| t.trait_type == SPREADSCALER_TRAIT) {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Warning,
+                format!(
+                    "component '{}' has a rollout trait but no spreadscaler, the rollout will be ignored",
+                    component.name
+                ),
+            ));
+        }
+        match rollout.strategy {
+            RolloutStrategy::BlueGreen if !rollout.steps.is_empty() => {
+                failures.push(ValidationFailure::new(
+                    ValidationFailureLevel::Warning,
+                    format!(
+                        "component '{}' uses a blue-green rollout, its steps will be ignored",
+                        component.name
+                    ),
+                ))
+            }
+            RolloutStrategy::Canary => {
+                let in_range = rollout.steps.iter().all(|step| (1..=100).contains(step));
+                let increasing = rollout.steps.windows(2).all(|pair| pair[0] < pair[1]);
+                if !in_range || !increasing {
+                    failures.push(ValidationFailure::new(
+                        ValidationFailureLevel::Error,
+                        format!(
+                            "canary steps for component '{}' must be increasing percentages between 1 and 100",
+                            component.name
+                        ),
+                    ))
+                }
+            }
+            _ => (),
+        }
+    }
+    failures
+}
+
+/// Ensure that host constraint traits have a valid minimum version and are only used where they
+/// can take effect.
+fn validate_host_constraints(manifest: &Manifest) -> Vec<ValidationFailure> {
+    let mut failures = Vec::new();
+    for component in manifest.components() {
+        let traits = component.traits.as_deref().unwrap_or_default();
+        let constraints = traits
+            .iter()
+            .filter_map(|t| match &t.properties {
+                TraitProperty::HostConstraint(props) if t.is_host_constraint() => Some(props),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let Some(constraint) = constraints.first() else {
+            continue;
+        };
+
+        if constraints.len() > 1 {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!(
+                    "component '{}' has more than one host constraint trait",
+                    component.name
+                ),
+            ));
+        }
+        if let Some(version) = constraint.min_host_version.as_deref() {
+            if let Err(e) = semver::Version::parse(version) {
+                failures.push(ValidationFailure::new(
+                    ValidationFailureLevel::Error,
+                    format!(
+                        "minimum host version '{version}' for component '{}' is not a valid semantic version: {e}",
+                        component.name
+                    ),
+                ));
+            }
+        }
+        let shared = match &component.properties {
+            Properties::Component { properties } => properties.image.is_none(),
+            Properties::Capability { properties } => properties.image.is_none(),
+        };
+        if shared {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Warning,
+                format!(
+                    "component '{}' refers to a shared application component, its host constraint will be ignored",
+                    component.name
+                ),
+            ));
+        }
+    }
+    failures
+}
+
+/// Ensure that autoscaler traits are only used where they can take effect and that their bounds,
+/// target and subject can work.
+fn validate_autoscalers(manifest: &Manifest) -> Vec<ValidationFailure> {
+    let mut failures = Vec::new();
+    for component in manifest.components() {
+        let traits = component.traits.as_deref().unwrap_or_default();
+        let autoscalers = traits
+            .iter()
+            .filter_map(|t| match &t.properties {
+                TraitProperty::Autoscaler(props) if t.is_autoscaler() => Some(props),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let Some(autoscaler) = autoscalers.first() else {
+            continue;
+        };
+
+        if autoscalers.len() > 1 {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!(
+                    "component '{}' has more than one autoscaler trait",
+                    component.name
+                ),
+            ));
+        }
+        if matches!(component.properties, Properties::Capability { .. }) {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!(
+                    "autoscaler trait on '{}' is only supported for components, not providers",
+                    component.name
+                ),
+            ));
+            continue;
+        }
+        if !traits.iter().any(|t| t.trait_type == SPREADSCALER_TRAIT) {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Warning,
+                format!(
+                    "component '{}' has an autoscaler trait but no spreadscaler, the autoscaler will be ignored",
+                    component.name
+                ),
+            ));
+        }
+        if autoscaler.max_instances == 0 || autoscaler.min_instances > autoscaler.max_instances {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!(
+                    "autoscaler for component '{}' must have a max_instances above 0 and not below min_instances",
+                    component.name
+                ),
+            ));
+        }
+        if autoscaler.target_value == 0 || autoscaler.step == 0 {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!(
+                    "autoscaler for component '{}' must have a target_value and step above 0",
+                    component.name
+                ),
+            ));
+        }
+        let subject = autoscaler.subject.as_str();
+        if subject.is_empty()
+            || subject.contains(char::is_whitespace)
+            || subject
+                .split('.')
+                .any(|token| token.is_empty() || token == "*" || token == ">")
+        {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!(
+                    "autoscaler subject '{subject}' for component '{}' must be a NATS subject without wildcards",
+                    component.name
+                ),
+            ));
+        }
+    }
+    failures
+}
+
+/// Ensure that schedule traits are only used where they can take effect and that their cron
+/// expressions and timezones can be parsed
+fn validate_schedules(manifest: &Manifest) -> Vec<ValidationFailure> {
+    let mut failures = Vec::new();
+    for component in manifest.components() {
+        let traits = component.traits.as_deref().unwrap_or_default();
+        let schedules = traits
+            .iter()
+            .filter_map(|t| match &t.properties {
+                TraitProperty::Schedule(props) if t.is_schedule() => Some(props),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let Some(schedule) = schedules.first() else {
+            continue;
+        };
+
+        if schedules.len() > 1 {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!(
+                    "component '{}' has more than one schedule trait",
+                    component.name
+                ),
+            ));
+        }
+        if matches!(component.properties, Properties::Capability { .. }) {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!(
+                    "schedule trait on '{}' is only supported for components, not providers",
+                    component.name
+                ),
+            ));
+            continue;
+        }
+        if traits.iter().any(|t| t.is_autoscaler()) {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!(
+                    "component '{}' can't have both a schedule and an autoscaler trait",
+                    component.name
+                ),
+            ));
+        }
+        if !traits.iter().any(|t| t.trait_type == SPREADSCALER_TRAIT) {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Warning,
+                format!(
+                    "component '{}' has a schedule trait but no spreadscaler, the schedule will be ignored",
+                    component.name
+                ),
+            ));
+        }
+        if schedule.windows.is_empty() {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!(
+                    "schedule for component '{}' must have at least one window",
+                    component.name
+                ),
+            ));
+        }
+        if let Err(e) = schedule.timezone() {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!("schedule for component '{}': {e}", component.name),
+            ));
+        }
+        for window in schedule.windows.iter() {
+            if let Err(e) = window.cron.parse::<CronExpression>() {
+                failures.push(ValidationFailure::new(
+                    ValidationFailureLevel::Error,
+                    format!("schedule for component '{}': {e}", component.name),
+                ));
+            }
+        }
+    }
+    failures
+}
+
 /// Check for "dangling" links, which contain targets that are not specified elsewhere in the
 /// WADM manifest.
 ///
@@ -557,6 +1109,104 @@ fn check_dangling_links(manifest: &Manifest) -> Vec<ValidationFailure> {
 
 /// Ensure that a manifest has secrets that are mapped to known policies
 /// and that those policies have the expected type and properties.
+/// Checks that provider config payloads can be passed to the provider and aren't given for shared
+/// providers, whose configuration is managed by the application that defines them
+fn validate_provider_config_payloads(manifest: &Manifest) -> Vec<ValidationFailure> {
+    let mut failures = Vec::new();
+    for component in manifest.components() {
+        let Properties::Capability {
+            properties:
+                CapabilityProperties {
+                    image,
+                    config_payload: Some(payload),
+                    ..
+                },
+        } = &component.properties
+        else {
+            continue;
+        };
+        if image.is_none() {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!(
+                    "shared provider '{}' cannot specify a config payload",
+                    component.name
+                ),
+            ));
+        }
+        if let Err(e) = payload.to_properties() {
+            failures.push(ValidationFailure::new(
+                ValidationFailureLevel::Error,
+                format!(
+                    "invalid config payload for provider '{}': {e}",
+                    component.name
+                ),
+            ));
+        }
+    }
+    failures
+}
+
+/// Checks that secret references in configuration name a backend and key, and don't also give a
+/// plain value for the same configuration key
+fn validate_secret_refs(manifest: &Manifest) -> Vec<ValidationFailure> {
+    let configs = manifest.components().flat_map(|component| {
+        let (Properties::Component {
+            properties: ComponentProperties { config, .. },
+        }
+        | Properties::Capability {
+            properties: CapabilityProperties { config, .. },
+        }) = &component.properties;
+        let link_configs = component
+            .traits
+            .iter()
+            .flatten()
+            .filter_map(|t| match &t.properties {
+                TraitProperty::Link(link) => Some(link),
+                _ => None,
+            })
+            .flat_map(|link| {
+                link.source
+                    .iter()
+                    .flat_map(|source| source.config.iter())
+                    .chain(link.target.config.iter())
+            });
+        config
+            .iter()
+            .chain(link_configs)
+            .map(move |config| (component.name.as_str(), config))
+    });
+
+    let mut failures = Vec::new();
+    for (component, config) in configs {
+        for (key, secret) in config.secret_refs.iter() {
+            if secret.backend.is_empty() || secret.key.is_empty() {
+                failures.push(ValidationFailure::new(
+                    ValidationFailureLevel::Error,
+                    format!(
+                        "secret reference for '{key}' in config '{}' of component '{component}' must have a backend and a key",
+                        config.name
+                    ),
+                ));
+            }
+            if config
+                .properties
+                .as_ref()
+                .is_some_and(|props| props.contains_key(key))
+            {
+                failures.push(ValidationFailure::new(
+                    ValidationFailureLevel::Error,
+                    format!(
+                        "'{key}' in config '{}' of component '{component}' is both a property and a secret reference",
+                        config.name
+                    ),
+                ));
+            }
+        }
+    }
+    failures
+}
+
 fn validate_policies(manifest: &Manifest) -> Vec<ValidationFailure> {
     let policies = manifest.policy_lookup();
     let mut failures = Vec::new();
@@ -594,9 +1244,43 @@ fn validate_policies(manifest: &Manifest) -> Vec<ValidationFailure> {
             }
         }
     }
+    // Ensure notification policies route somewhere that can be published to
+    for policy in manifest.policies() {
+        match policy.notification_route() {
+            Ok(Some(NotificationRoute::Subject(subject))) if !is_valid_notification_subject(&subject) => {
+                failures.push(ValidationFailure::new(
+                    ValidationFailureLevel::Error,
+                    format!(
+                        "notification policy '{}' has invalid subject '{subject}'. Subjects can't be empty, contain whitespace or wildcards, or start with '$'",
+                        policy.name
+                    ),
+                ))
+            }
+            Ok(Some(NotificationRoute::Webhook(webhook))) if webhook.trim().is_empty() => {
+                failures.push(ValidationFailure::new(
+                    ValidationFailureLevel::Error,
+                    format!("notification policy '{}' has an empty webhook name", policy.name),
+                ))
+            }
+            Ok(_) => {}
+            Err(msg) => failures.push(ValidationFailure::new(ValidationFailureLevel::Error, msg)),
+        }
+    }
     failures
 }
 
+/// Returns whether notifications can be published on the given subject. System subjects (starting
+/// with `$`) aren't allowed so applications can't send requests to NATS or JetStream
+fn is_valid_notification_subject(subject: &str) -> bool {
+    !subject.starts_with('$')
+        && subject.split('.').all(|token| {
+            !token.is_empty()
+                && token != "*"
+                && token != ">"
+                && !token.contains(|c: char| c.is_whitespace())
+        })
+}
+
 /// Ensure that all components in a manifest either specify an image reference or a shared
 /// component in a different manifest. Note that this does not validate that the image reference
 /// is valid or that the shared component is valid, only that one of the two properties is set.
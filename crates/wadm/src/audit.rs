@@ -0,0 +1,284 @@
+//! An audit log of the mutating API calls made to wadm. Every put, delete, deploy and undeploy of a
+//! model is recorded along with who made the call and a summary of what changed, so operators can
+//! tell who changed what in a lattice and when
+
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_nats::{
+    jetstream::{
+        consumer::{pull::Config as PullConfig, AckPolicy, DeliverPolicy},
+        stream::Stream,
+        Context,
+    },
+    HeaderMap,
+};
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use serde::Deserialize;
+use tracing::{instrument, warn};
+use wadm_types::{api::AuditRecord, api::CALLER_HEADER, Manifest};
+
+/// The header the NATS server adds to requests with information about the client that sent them,
+/// when the API is imported by another account with `share` enabled
+pub const REQUEST_INFO_HEADER: &str = "Nats-Request-Info";
+
+/// The caller recorded for requests that don't identify who sent them
+const UNKNOWN_CALLER: &str = "unknown";
+
+/// Anything that can record and page through audit records
+#[async_trait]
+pub trait AuditLog {
+    /// Records the given call
+    async fn record(&self, record: &AuditRecord) -> Result<()>;
+
+    /// Returns up to `limit` records for the lattice, oldest first, starting at the given sequence.
+    /// Also returns the sequence the next page starts at if there are more records
+    async fn query(
+        &self,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        start_sequence: u64,
+        limit: usize,
+    ) -> Result<(Vec<AuditRecord>, Option<u64>)>;
+}
+
+/// An [`AuditLog`] backed by a JetStream stream. Records are published to
+/// `{topic_prefix}.{lattice_id}`
+#[derive(Clone)]
+pub struct StreamAuditLog {
+    context: Context,
+    stream: Stream,
+    topic_prefix: String,
+}
+
+impl StreamAuditLog {
+    pub fn new(context: Context, stream: Stream, topic_prefix: &str) -> StreamAuditLog {
+        StreamAuditLog {
+            context,
+            stream,
+            topic_prefix: topic_prefix.trim_end_matches('.').to_owned(),
+        }
+    }
+
+    fn topic(&self, lattice_id: &str) -> String {
+        format!("{}.{lattice_id}", self.topic_prefix)
+    }
+}
+
+#[async_trait]
+impl AuditLog for StreamAuditLog {
+    #[instrument(level = "debug", skip_all, fields(lattice_id = %record.lattice_id))]
+    async fn record(&self, record: &AuditRecord) -> Result<()> {
+        self.context
+            .publish(
+                self.topic(&record.lattice_id),
+                serde_json::to_vec(record)?.into(),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn query(
+        &self,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        start_sequence: u64,
+        limit: usize,
+    ) -> Result<(Vec<AuditRecord>, Option<u64>)> {
+        let consumer = self
+            .stream
+            .create_consumer(PullConfig {
+                filter_subject: self.topic(lattice_id),
+                ack_policy: AckPolicy::None,
+                deliver_policy: DeliverPolicy::ByStartSequence {
+                    start_sequence: start_sequence.max(1),
+                },
+                inactive_threshold: Duration::from_secs(30),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+        let pending = consumer.cached_info().num_pending as usize;
+        let mut records = Vec::new();
+        let mut next_sequence = None;
+        if pending > 0 && limit > 0 {
+            let messages = consumer
+                .fetch()
+                .max_messages(pending.min(limit))
+                .messages()
+                .await
+                .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+            let mut last_sequence = 0;
+            records = messages
+                .map_err(|e| anyhow::anyhow!("{e:?}"))
+                .try_filter_map(|msg| {
+                    let sequence = msg.info().map(|info| info.stream_sequence);
+                    async move {
+                        let sequence = sequence.map_err(|e| anyhow::anyhow!("{e:?}"))?;
+                        match serde_json::from_slice::<AuditRecord>(&msg.payload) {
+                            Ok(record) => Ok(Some(AuditRecord { sequence, ..record })),
+                            Err(e) => {
+                                warn!(error = %e, %sequence, "Skipping audit record that couldn't be decoded");
+                                Ok(None)
+                            }
+                        }
+                    }
+                })
+                .try_collect::<Vec<_>>()
+                .await?;
+            if let Some(last) = records.last() {
+                last_sequence = last.sequence;
+            }
+            if pending > limit && last_sequence > 0 {
+                next_sequence = Some(last_sequence + 1);
+            }
+            // Other accounts can use the same lattice ID, so their records are left out
+            records.retain(|record| record.account_id.as_deref() == account_id);
+        }
+        let name = consumer.cached_info().name.clone();
+        if let Err(e) = self.stream.delete_consumer(&name).await {
+            warn!(error = %e, "Unable to delete audit log consumer, it will be removed once inactive");
+        }
+        Ok((records, next_sequence))
+    }
+}
+
+/// The parts of the client info the NATS server sends in the [`REQUEST_INFO_HEADER`]
+#[derive(Debug, Default, Deserialize)]
+struct RequestInfo {
+    #[serde(default)]
+    acc: Option<String>,
+    #[serde(default)]
+    user: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// Returns the NATS account the server says sent a request with the given headers, if any
+pub(crate) fn request_account(headers: Option<&HeaderMap>) -> Option<String> {
+    headers?
+        .get(REQUEST_INFO_HEADER)
+        .and_then(|value| serde_json::from_str::<RequestInfo>(value.as_str()).ok())?
+        .acc
+        .filter(|acc| !acc.is_empty())
+}
+
+/// Returns who sent a request with the given headers, and whether the NATS server vouched for it.
+/// The client info from the NATS server is preferred over the self reported [`CALLER_HEADER`]
+pub fn caller_identity(headers: Option<&HeaderMap>) -> (String, bool) {
+    let Some(headers) = headers else {
+        return (UNKNOWN_CALLER.to_owned(), false);
+    };
+    let info = headers
+        .get(REQUEST_INFO_HEADER)
+        .and_then(|value| serde_json::from_str::<RequestInfo>(value.as_str()).ok())
+        .unwrap_or_default();
+    let non_empty = |s: Option<String>| s.filter(|s| !s.is_empty());
+    match (
+        non_empty(info.user).or(non_empty(info.name)),
+        non_empty(info.acc),
+    ) {
+        (Some(user), Some(account)) => (format!("{user}@{account}"), true),
+        (Some(user), None) => (user, true),
+        (None, Some(account)) => (account, true),
+        (None, None) => match headers
+            .get(CALLER_HEADER)
+            .map(|value| value.as_str().trim())
+        {
+            Some(caller) if !caller.is_empty() => (caller.to_owned(), false),
+            _ => (UNKNOWN_CALLER.to_owned(), false),
+        },
+    }
+}
+
+/// Summarizes the changes between the previous version of a model and the version that was put
+pub fn put_summary(previous: Option<&Manifest>, manifest: &Manifest) -> String {
+    let Some(previous) = previous else {
+        return format!(
+            "Put version {} as a new model with {} components",
+            manifest.version(),
+            manifest.spec.components.len()
+        );
+    };
+    let names =
+        |m: &Manifest| -> BTreeSet<String> { m.components().map(|c| c.name.clone()).collect() };
+    let (old, new) = (names(previous), names(manifest));
+    let added: Vec<&String> = new.difference(&old).collect();
+    let removed: Vec<&String> = old.difference(&new).collect();
+    let changed: Vec<&String> = new
+        .intersection(&old)
+        .filter(|name| {
+            previous.components().find(|c| &&c.name == name)
+                != manifest.components().find(|c| &&c.name == name)
+        })
+        .collect();
+    let mut summary = format!(
+        "Put version {} replacing {}",
+        manifest.version(),
+        previous.version()
+    );
+    for (label, components) in [("added", added), ("removed", removed), ("changed", changed)] {
+        if !components.is_empty() {
+            summary.push_str(&format!(
+                ", {label} {}",
+                components
+                    .iter()
+                    .map(|c| c.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn identifies_callers_and_summarizes_changes() {
+        assert_eq!(caller_identity(None), ("unknown".to_string(), false));
+        let mut headers = HeaderMap::new();
+        headers.insert(CALLER_HEADER, "ci-pipeline");
+        assert_eq!(
+            caller_identity(Some(&headers)),
+            ("ci-pipeline".to_string(), false)
+        );
+        headers.insert(
+            REQUEST_INFO_HEADER,
+            r#"{"acc":"ACCOUNT","rtt":"1ms","user":"alice"}"#,
+        );
+        assert_eq!(
+            caller_identity(Some(&headers)),
+            ("alice@ACCOUNT".to_string(), true),
+            "The identity from the NATS server should win over the self reported one"
+        );
+
+        let manifest: Manifest = serde_yaml::from_slice(
+            &std::fs::read("../../tests/fixtures/manifests/upgradedapp.yaml").unwrap(),
+        )
+        .unwrap();
+        let previous: Manifest = serde_yaml::from_slice(
+            &std::fs::read("../../tests/fixtures/manifests/outdatedapp.yaml").unwrap(),
+        )
+        .unwrap();
+        assert!(put_summary(None, &previous).contains("as a new model"));
+        let summary = put_summary(Some(&previous), &manifest);
+        assert!(
+            summary.starts_with(&format!(
+                "Put version {} replacing {}",
+                manifest.version(),
+                previous.version()
+            )),
+            "{summary}"
+        );
+        assert!(summary.contains("changed"), "{summary}");
+    }
+}
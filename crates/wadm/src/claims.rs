@@ -0,0 +1,152 @@
+//! Verification of component claims before a manifest is deployed. Components are signed with the
+//! capability contracts they are allowed to use, so a link from a component to a contract that
+//! isn't in its claims can't be used by the component. Checking this at deploy time surfaces the
+//! problem to whoever deployed the manifest instead of as a failing link later on
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tracing::instrument;
+use wadm_types::{Manifest, Properties, TraitProperty};
+
+use crate::{
+    scaler::{convert::compute_component_id, planner::LatticeSourceCreator},
+    workers::{Claims, ClaimsSource},
+};
+
+/// Anything that can check that the components of a manifest have claims for the contracts they
+/// are linked to
+#[async_trait]
+pub trait ClaimsVerifier {
+    /// Returns a description of every link in the manifest whose source component doesn't have
+    /// claims for the link's contract. Components the lattice has no claims for are not checked
+    async fn verify(
+        &self,
+        lattice_id: &str,
+        multitenant_prefix: Option<&str>,
+        manifest: &Manifest,
+    ) -> Result<Vec<String>>;
+}
+
+/// A [`ClaimsVerifier`] that checks the claims known to the lattice, using the lattice scoped
+/// sources from the given creator
+#[derive(Clone)]
+pub struct LatticeClaimsVerifier<C> {
+    source_creator: C,
+}
+
+impl<C> LatticeClaimsVerifier<C> {
+    /// Creates a new verifier that fetches claims with sources from the given creator
+    pub fn new(source_creator: C) -> LatticeClaimsVerifier<C> {
+        LatticeClaimsVerifier { source_creator }
+    }
+}
+
+#[async_trait]
+impl<C> ClaimsVerifier for LatticeClaimsVerifier<C>
+where
+    C: LatticeSourceCreator + Send + Sync,
+    C::Output: ClaimsSource,
+{
+    #[instrument(level = "debug", skip(self, manifest), fields(name = %manifest.metadata.name))]
+    async fn verify(
+        &self,
+        lattice_id: &str,
+        multitenant_prefix: Option<&str>,
+        manifest: &Manifest,
+    ) -> Result<Vec<String>> {
+        let claims = self
+            .source_creator
+            .create(lattice_id, multitenant_prefix)
+            .get_claims()
+            .await?;
+        Ok(unclaimed_links(manifest, &claims))
+    }
+}
+
+/// Returns a description of every link from a component of the manifest to a contract that isn't
+/// in the component's claims. Claims are looked up by the component's ID, and then by its name
+pub fn unclaimed_links(manifest: &Manifest, claims: &HashMap<String, Claims>) -> Vec<String> {
+    manifest
+        .components()
+        .filter_map(|component| {
+            let Properties::Component { properties } = &component.properties else {
+                return None;
+            };
+            let id = compute_component_id(
+                &manifest.metadata.name,
+                properties.id.as_ref(),
+                &component.name,
+            );
+            let component_claims = claims
+                .get(&id)
+                .or_else(|| claims.values().find(|c| c.name == component.name))?;
+            Some(
+                component
+                    .traits
+                    .iter()
+                    .flatten()
+                    .filter_map(move |t| match &t.properties {
+                        TraitProperty::Link(link) => {
+                            let contract = format!("{}:{}", link.namespace, link.package);
+                            let claimed = component_claims.capabilities.iter().any(|cap| {
+                                cap == &contract
+                                    || cap
+                                        .strip_prefix(contract.as_str())
+                                        .is_some_and(|rest| rest.starts_with('/'))
+                            });
+                            (!claimed).then(|| {
+                                format!(
+                                    "Component {} is linked to {} with contract {contract}, but its claims only allow {:?}",
+                                    component.name, link.target.name, component_claims.capabilities
+                                )
+                            })
+                        }
+                        _ => None,
+                    }),
+            )
+        })
+        .flatten()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TestLatticeSource;
+
+    #[tokio::test]
+    async fn finds_links_without_claims() {
+        let manifest: Manifest = serde_yaml::from_slice(
+            &std::fs::read("../../tests/fixtures/manifests/outdatedapp.yaml").unwrap(),
+        )
+        .unwrap();
+        // The kvcounter component is linked to wasi:keyvalue
+        let claims = |capabilities: &[&str]| {
+            HashMap::from([(
+                "updateapp-kvcounter".to_string(),
+                Claims {
+                    name: "kvcounter".to_string(),
+                    capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+                    issuer: "AISSUER".to_string(),
+                },
+            )])
+        };
+
+        let verifier = LatticeClaimsVerifier::new(TestLatticeSource {
+            claims: claims(&["wasmcloud:httpserver"]),
+            ..Default::default()
+        });
+        let unclaimed = verifier.verify("default", None, &manifest).await.unwrap();
+        assert_eq!(unclaimed.len(), 1, "{unclaimed:?}");
+        assert!(unclaimed[0].contains("wasi:keyvalue"), "{}", unclaimed[0]);
+
+        assert!(unclaimed_links(&manifest, &claims(&["wasi:keyvalue"])).is_empty());
+        assert!(unclaimed_links(&manifest, &claims(&["wasi:keyvalue/atomics"])).is_empty());
+        assert!(
+            unclaimed_links(&manifest, &HashMap::new()).is_empty(),
+            "Components without known claims shouldn't be checked"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! A pluggable source of time. Everything in wadm that expires, backs off or waits for a period of
+//! time reads the time from a [`Clock`], so tests and simulations can swap in a [`ManualClock`] and
+//! move time forward deterministically instead of sleeping.
+//!
+//! wadm only ever compares times that came from its own clock, such as when it last saw a host or
+//! when it published a command, and never the timestamps hosts put on their events. This keeps
+//! expiry and TTLs correct even when the clocks of wadm and the hosts are far apart. Retries that
+//! are delayed with tokio timers can be controlled in tests with `tokio::time::pause`
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+/// A source of the current time
+pub trait Clock: Send + Sync {
+    /// Returns the current wall clock time
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Returns the current monotonic time, used for measuring elapsed time
+    fn instant(&self) -> Instant;
+
+    /// Returns how much time has passed since the given instant
+    fn elapsed(&self, since: Instant) -> Duration {
+        self.instant().saturating_duration_since(since)
+    }
+}
+
+/// A clock that can be shared by everything that needs the time
+pub type SharedClock = Arc<dyn Clock>;
+
+/// Returns a shared handle to the system clock
+pub fn system() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+/// The clock of the system wadm is running on. This is what is used unless another clock is given
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+#[derive(Debug)]
+struct ManualTime {
+    now: DateTime<Utc>,
+    offset: Duration,
+}
+
+/// A clock that only moves when it is told to. Cloning is cheap and all clones share the same
+/// time, so a clone can be handed to the code under test while the original is advanced
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    start: Instant,
+    time: Arc<Mutex<ManualTime>>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new(Utc::now())
+    }
+}
+
+impl ManualClock {
+    /// Creates a new manual clock starting at the given wall clock time
+    pub fn new(now: DateTime<Utc>) -> ManualClock {
+        ManualClock {
+            start: Instant::now(),
+            time: Arc::new(Mutex::new(ManualTime {
+                now,
+                offset: Duration::ZERO,
+            })),
+        }
+    }
+
+    /// Moves the clock forward by the given amount of time
+    pub fn advance(&self, by: Duration) {
+        let mut time = self.time.lock().unwrap_or_else(|e| e.into_inner());
+        time.offset += by;
+        time.now += chrono::Duration::from_std(by)
+            .expect("The given duration is out of bounds for a max duration value");
+    }
+
+    /// Sets the wall clock time without moving the monotonic time. This can be used to simulate
+    /// the system clock being changed
+    pub fn set(&self, now: DateTime<Utc>) {
+        self.time.lock().unwrap_or_else(|e| e.into_inner()).now = now;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.time.lock().unwrap_or_else(|e| e.into_inner()).now
+    }
+
+    fn instant(&self) -> Instant {
+        self.start + self.time.lock().unwrap_or_else(|e| e.into_inner()).offset
+    }
+}
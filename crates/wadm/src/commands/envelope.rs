@@ -0,0 +1,239 @@
+//! A versioned envelope around the commands published to the command stream. Every wadm replica
+//! reads the same command stream, so during a rolling upgrade commands published by one version of
+//! wadm are handled by another. The schema version and expiry of a command are sent in headers and
+//! the body is always a bare [`Command`], so the previous version can still read commands published
+//! by this one, and decoding through the envelope accepts commands from the previous version
+
+use async_nats::HeaderMap;
+use thiserror::Error;
+
+use super::{proto, Command, PublishedCommand};
+use crate::publisher::Encoding;
+
+/// The header that holds the version of the command schema a command was published with
+pub const COMMAND_VERSION_HEADER: &str = "Wadm-Command-Version";
+/// The version of the command schema published by this version of wadm
+pub const COMMAND_SCHEMA_VERSION: u32 = 2;
+/// The version of commands published without a version, which is every command published before
+/// the envelope was introduced
+const UNVERSIONED_SCHEMA_VERSION: u32 = 1;
+
+/// An error returned when a published command can't be decoded
+#[derive(Debug, Error)]
+pub enum CommandDecodeError {
+    /// The payload wasn't a valid command for its version
+    #[error("Invalid command: {0}")]
+    Invalid(#[from] serde_json::Error),
+    /// The version header wasn't a number
+    #[error("Invalid command schema version {0:?}")]
+    InvalidVersion(String),
+    /// The command was published with a version we don't know how to read
+    #[error("Unsupported command schema version {0}")]
+    UnsupportedVersion(u32),
+    /// The payload couldn't be decoded with the encoding it was sent with
+    #[error("Unable to decode command: {0:#}")]
+    Encoding(anyhow::Error),
+}
+
+/// A published command along with the version of the schema it was published with
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CommandEnvelope {
+    /// The version of the command schema
+    pub version: u32,
+    pub command: PublishedCommand,
+}
+
+impl CommandEnvelope {
+    /// Wraps the given command with the current schema version
+    pub fn new(command: PublishedCommand) -> CommandEnvelope {
+        CommandEnvelope {
+            version: COMMAND_SCHEMA_VERSION,
+            command,
+        }
+    }
+
+    /// Encodes the command with the given encoding, adding its content type, schema version and
+    /// expiry to the given headers
+    pub fn encode(&self, encoding: Encoding, headers: &mut HeaderMap) -> anyhow::Result<Vec<u8>> {
+        let data = match encoding {
+            Encoding::Json => serde_json::to_vec(&self.command.command)?,
+            Encoding::Protobuf => proto::encode(&self.command.command),
+        };
+        encoding.insert_header(headers);
+        headers.insert(COMMAND_VERSION_HEADER, self.version.to_string().as_str());
+        self.command.insert_headers(headers);
+        Ok(data)
+    }
+
+    /// Decodes a command published by this or the previous version of wadm. The encoding, schema
+    /// version and expiry of the command are read from the given headers. Commands from a newer
+    /// version are decoded as the current version if they can be, as new versions only add fields
+    /// that older versions can ignore
+    pub fn decode(
+        data: &[u8],
+        headers: Option<&HeaderMap>,
+    ) -> Result<CommandEnvelope, CommandDecodeError> {
+        let version = match headers.and_then(|headers| headers.get(COMMAND_VERSION_HEADER)) {
+            Some(version) => version
+                .as_str()
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| CommandDecodeError::InvalidVersion(version.to_string()))?,
+            None => UNVERSIONED_SCHEMA_VERSION,
+        };
+        if version < UNVERSIONED_SCHEMA_VERSION {
+            return Err(CommandDecodeError::UnsupportedVersion(version));
+        }
+        let encoding = Encoding::from_headers(headers).map_err(CommandDecodeError::Encoding)?;
+        let command = match encoding {
+            Encoding::Json => serde_json::from_slice::<Command>(data).map_err(Into::into),
+            Encoding::Protobuf => proto::decode(data).map_err(CommandDecodeError::Encoding),
+        };
+        let command = match command {
+            Ok(command) => command,
+            Err(_) if version > COMMAND_SCHEMA_VERSION => {
+                return Err(CommandDecodeError::UnsupportedVersion(version))
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(CommandEnvelope {
+            version,
+            command: PublishedCommand {
+                command,
+                expires_at: PublishedCommand::expiry_from_headers(headers),
+            },
+        })
+    }
+}
+
+impl From<PublishedCommand> for CommandEnvelope {
+    fn from(command: PublishedCommand) -> CommandEnvelope {
+        CommandEnvelope::new(command)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::commands::{DeleteConfig, PutConfig, PutLink, ScaleComponent};
+
+    fn published() -> PublishedCommand {
+        PublishedCommand::new(
+            Command::from(ScaleComponent {
+                component_id: "echo".to_string(),
+                host_id: "host".to_string(),
+                count: 2,
+                reference: "ghcr.io/echo:0.1.0".to_string(),
+                model_name: "model".to_string(),
+                ..Default::default()
+            }),
+            Duration::from_secs(60),
+        )
+    }
+
+    #[test]
+    fn decodes_commands_across_versions() {
+        let published = published();
+        let envelope = CommandEnvelope::new(published.clone());
+        let mut headers = HeaderMap::new();
+        let data = envelope.encode(Encoding::Json, &mut headers).unwrap();
+        assert_eq!(
+            CommandEnvelope::decode(&data, Some(&headers)).unwrap(),
+            envelope
+        );
+        assert_eq!(
+            serde_json::from_slice::<Command>(&data).unwrap(),
+            published.command,
+            "The previous version should be able to decode the published bytes as a bare command"
+        );
+
+        // The previous version, which sent a bare command without any headers
+        let data = serde_json::to_vec(&published.command).unwrap();
+        let decoded = CommandEnvelope::decode(&data, None).unwrap();
+        assert_eq!(decoded.version, UNVERSIONED_SCHEMA_VERSION);
+        assert_eq!(decoded.command.command, published.command);
+        assert_eq!(decoded.command.expires_at, None);
+
+        // Newer versions are read as the current one as long as they still have what it needs
+        let mut newer = HeaderMap::new();
+        newer.insert(
+            COMMAND_VERSION_HEADER,
+            (COMMAND_SCHEMA_VERSION + 1).to_string().as_str(),
+        );
+        let decoded = CommandEnvelope::decode(&data, Some(&newer)).unwrap();
+        assert_eq!(decoded.command.command, published.command);
+        assert!(matches!(
+            CommandEnvelope::decode(br#"{"Reboot":{}}"#, Some(&newer)),
+            Err(CommandDecodeError::UnsupportedVersion(version)) if version == COMMAND_SCHEMA_VERSION + 1
+        ));
+        let mut old = HeaderMap::new();
+        old.insert(COMMAND_VERSION_HEADER, "0");
+        assert!(matches!(
+            CommandEnvelope::decode(&data, Some(&old)),
+            Err(CommandDecodeError::UnsupportedVersion(0))
+        ));
+    }
+
+    #[test]
+    fn protobuf_commands_round_trip_exactly() {
+        let commands = [
+            Command::from(ScaleComponent {
+                component_id: "echo".to_string(),
+                host_id: "host".to_string(),
+                count: u32::MAX,
+                reference: "ghcr.io/echo:0.1.0".to_string(),
+                model_name: "model".to_string(),
+                annotations: [("key".to_string(), "value".to_string())].into(),
+                config: vec!["echo-config".to_string()],
+            }),
+            Command::from(PutLink {
+                source_id: "echo".to_string(),
+                target: "httpserver".to_string(),
+                name: "default".to_string(),
+                wit_namespace: "wasi".to_string(),
+                wit_package: "http".to_string(),
+                interfaces: vec!["incoming-handler".to_string()],
+                source_config: vec!["source".to_string()],
+                target_config: vec!["target".to_string()],
+                model_name: "model".to_string(),
+            }),
+            Command::from(PutConfig {
+                config_name: "echo-config".to_string(),
+                // Large numbers are just strings here, but make sure nothing tries to read them
+                config: [("max".to_string(), u64::MAX.to_string())].into(),
+            }),
+            Command::from(DeleteConfig {
+                config_name: "echo-config".to_string(),
+            }),
+        ];
+        for command in commands {
+            let envelope = CommandEnvelope::new(PublishedCommand::new(
+                command.clone(),
+                Duration::from_secs(60),
+            ));
+            let mut headers = HeaderMap::new();
+            let data = envelope.encode(Encoding::Protobuf, &mut headers).unwrap();
+            let json = serde_json::to_vec(&command).unwrap();
+            assert!(
+                data.len() < json.len(),
+                "Protobuf commands should be smaller than JSON ones"
+            );
+            let decoded = CommandEnvelope::decode(&data, Some(&headers)).unwrap();
+            // Some commands only compare some of their fields, so compare everything
+            assert_eq!(
+                serde_json::to_value(&decoded.command.command).unwrap(),
+                serde_json::to_value(&command).unwrap()
+            );
+            assert_eq!(decoded.command.expires_at, envelope.command.expires_at);
+        }
+
+        let mut headers = HeaderMap::new();
+        Encoding::Protobuf.insert_header(&mut headers);
+        assert!(matches!(
+            CommandEnvelope::decode(&[], Some(&headers)),
+            Err(CommandDecodeError::Encoding(_))
+        ));
+    }
+}
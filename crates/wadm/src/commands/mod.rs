@@ -4,8 +4,12 @@ use std::{
     collections::{BTreeMap, HashMap},
     error::Error,
     hash::{Hash, Hasher},
+    time::Duration,
 };
 
+use async_nats::HeaderMap;
+use chrono::{DateTime, SecondsFormat, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use wasmcloud_control_interface::Link;
 
@@ -14,6 +18,11 @@ use crate::{
     workers::insert_managed_annotations,
 };
 
+mod envelope;
+mod proto;
+
+pub use envelope::*;
+
 macro_rules! from_impl {
     ($t:ident) => {
         impl From<$t> for Command {
@@ -24,8 +33,65 @@ macro_rules! from_impl {
     };
 }
 
+/// The default amount of time a published command stays valid for
+pub const DEFAULT_COMMAND_VALIDITY: Duration = Duration::from_secs(300);
+
+/// The header that holds when a published command stops being valid, as an RFC 3339 timestamp
+pub const COMMAND_EXPIRES_HEADER: &str = "Wadm-Command-Expires";
+
+/// A command as it is published to the command stream, along with when it stops being valid.
+/// Commands are computed from the state of the lattice when they were published, so a command
+/// that sat in the stream for too long (e.g. during an outage) is discarded instead of executed.
+/// The next reconcile will produce a fresh command if it is still needed
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PublishedCommand {
+    pub command: Command,
+    /// When the command stops being valid, sent in the [`COMMAND_EXPIRES_HEADER`]. Commands
+    /// without an expiry (e.g. ones published by an older version of wadm or replayed from the
+    /// dead letter queue) are always executed
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl PublishedCommand {
+    /// Wraps the given command so that it expires once the given validity has passed
+    pub fn new(command: Command, validity: Duration) -> PublishedCommand {
+        PublishedCommand::new_at(command, validity, Utc::now())
+    }
+
+    /// Wraps the given command so that it expires once the given validity has passed since `now`
+    pub fn new_at(command: Command, validity: Duration, now: DateTime<Utc>) -> PublishedCommand {
+        PublishedCommand {
+            command,
+            expires_at: chrono::Duration::from_std(validity)
+                .ok()
+                .and_then(|validity| now.checked_add_signed(validity)),
+        }
+    }
+
+    /// Adds the expiry of this command, if it has one, to the given headers
+    pub fn insert_headers(&self, headers: &mut HeaderMap) {
+        if let Some(expires_at) = self.expires_at {
+            headers.insert(
+                COMMAND_EXPIRES_HEADER,
+                expires_at
+                    .to_rfc3339_opts(SecondsFormat::Nanos, true)
+                    .as_str(),
+            );
+        }
+    }
+
+    /// Returns the expiry in the given headers. A missing or unparsable expiry is treated as no
+    /// expiry, so the command is still executed
+    pub fn expiry_from_headers(headers: Option<&HeaderMap>) -> Option<DateTime<Utc>> {
+        headers
+            .and_then(|headers| headers.get(COMMAND_EXPIRES_HEADER))
+            .and_then(|value| DateTime::parse_from_rfc3339(value.as_str()).ok())
+            .map(|expires_at| expires_at.with_timezone(&Utc))
+    }
+}
+
 /// All possible compensatory commands for a lattice
-#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum Command {
     ScaleComponent(ScaleComponent),
     StartProvider(StartProvider),
@@ -112,8 +178,142 @@ impl Command {
     }
 }
 
+/// What a command acts on. Commands say what the state of their target should be (e.g. how many
+/// instances of a component a host should run), so a later command for a target supersedes any
+/// earlier ones
+#[derive(PartialEq, Eq, Hash)]
+enum CommandTarget<'a> {
+    Component {
+        component_id: &'a str,
+        host_id: &'a str,
+    },
+    Provider {
+        provider_id: &'a str,
+        host_id: &'a str,
+    },
+    Link {
+        source_id: &'a str,
+        wit_namespace: &'a str,
+        wit_package: &'a str,
+        name: &'a str,
+    },
+    Config(&'a str),
+}
+
+impl std::fmt::Display for CommandTarget<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandTarget::Component {
+                component_id,
+                host_id,
+            } => write!(f, "component/{component_id}/{host_id}"),
+            CommandTarget::Provider {
+                provider_id,
+                host_id,
+            } => write!(f, "provider/{provider_id}/{host_id}"),
+            CommandTarget::Link {
+                source_id,
+                wit_namespace,
+                wit_package,
+                name,
+            } => write!(f, "link/{source_id}/{wit_namespace}:{wit_package}/{name}"),
+            CommandTarget::Config(name) => write!(f, "config/{name}"),
+        }
+    }
+}
+
+impl Command {
+    /// Returns the name of the model that generated the command. Config commands aren't tied to a
+    /// model
+    pub fn model_name(&self) -> Option<&str> {
+        match self {
+            Command::ScaleComponent(ScaleComponent { model_name, .. })
+            | Command::StartProvider(StartProvider { model_name, .. })
+            | Command::StopProvider(StopProvider { model_name, .. })
+            | Command::PutLink(PutLink { model_name, .. })
+            | Command::DeleteLink(DeleteLink { model_name, .. }) => Some(model_name),
+            Command::PutConfig(_) | Command::DeleteConfig(_) => None,
+        }
+    }
+
+    /// Returns what the command acts on, like a component on a specific host. A later command for
+    /// the same target supersedes an earlier one
+    pub fn target_id(&self) -> String {
+        self.target().to_string()
+    }
+
+    fn target(&self) -> CommandTarget<'_> {
+        match self {
+            Command::ScaleComponent(cmd) => CommandTarget::Component {
+                component_id: &cmd.component_id,
+                host_id: &cmd.host_id,
+            },
+            Command::StartProvider(StartProvider {
+                provider_id,
+                host_id,
+                ..
+            })
+            | Command::StopProvider(StopProvider {
+                provider_id,
+                host_id,
+                ..
+            }) => CommandTarget::Provider {
+                provider_id,
+                host_id,
+            },
+            Command::PutLink(cmd) => CommandTarget::Link {
+                source_id: &cmd.source_id,
+                wit_namespace: &cmd.wit_namespace,
+                wit_package: &cmd.wit_package,
+                name: &cmd.name,
+            },
+            Command::DeleteLink(cmd) => CommandTarget::Link {
+                source_id: &cmd.source_id,
+                wit_namespace: &cmd.wit_namespace,
+                wit_package: &cmd.wit_package,
+                name: &cmd.link_name,
+            },
+            Command::PutConfig(PutConfig { config_name, .. })
+            | Command::DeleteConfig(DeleteConfig { config_name }) => {
+                CommandTarget::Config(config_name)
+            }
+        }
+    }
+}
+
+/// Merges the commands from a single reconcile pass so each component, provider, link and config
+/// is acted on at most once. Only the last command for each of them is kept, since it is the one
+/// that says what its state should end up being. This drops duplicates and pairs that would undo
+/// each other (e.g. a provider that is stopped and then started again on the same host is just
+/// started). Each kept command stays at its last position in the pass, so it is still published
+/// after anything the scalers asked for before it. Config puts are the exception and stay where
+/// their config first showed up, so the config exists before the components and providers that
+/// use it are started
+pub fn coalesce_commands(commands: Vec<Command>) -> Vec<Command> {
+    // The first and last index of the commands for each target
+    let mut positions: HashMap<CommandTarget, (usize, usize)> =
+        HashMap::with_capacity(commands.len());
+    for (index, command) in commands.iter().enumerate() {
+        positions
+            .entry(command.target())
+            .and_modify(|(_, last)| *last = index)
+            .or_insert((index, index));
+    }
+    let mut kept: Vec<(usize, usize)> = positions
+        .into_values()
+        .map(|(first, last)| match commands[last] {
+            Command::PutConfig(_) => (first, last),
+            _ => (last, last),
+        })
+        .collect();
+    kept.sort_unstable();
+    kept.into_iter()
+        .map(|(_, index)| commands[index].clone())
+        .collect()
+}
+
 /// Struct for the ScaleComponent command
-#[derive(Clone, Debug, Serialize, Deserialize, Default, Eq)]
+#[derive(Clone, Debug, Serialize, Deserialize, Default, Eq, JsonSchema)]
 pub struct ScaleComponent {
     /// The ID of the component to scale. This should be computed by wadm as a combination
     /// of the manifest name and the component name.
@@ -146,7 +346,7 @@ impl PartialEq for ScaleComponent {
 }
 
 /// Struct for the StartProvider command
-#[derive(Clone, Debug, Eq, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, Eq, Serialize, Deserialize, Default, JsonSchema)]
 pub struct StartProvider {
     /// The OCI or bindle reference to start
     pub reference: String,
@@ -157,7 +357,8 @@ pub struct StartProvider {
     pub host_id: String,
     /// The name of the model/manifest that generated this command
     pub model_name: String,
-    /// Named configuration to pass to the provider.
+    /// Named configuration to pass to the provider. This includes the configuration created
+    /// from the provider's config payload, if the manifest gives one
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub config: Vec<String>,
     /// Additional annotations to attach on this command
@@ -182,7 +383,7 @@ impl Hash for StartProvider {
 }
 
 /// Struct for the StopProvider command
-#[derive(Clone, Debug, Eq, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, Eq, Serialize, Deserialize, Default, JsonSchema)]
 pub struct StopProvider {
     /// The ID of the provider to stop
     pub provider_id: String,
@@ -212,7 +413,7 @@ impl Hash for StopProvider {
 }
 
 /// Struct for the PutLinkdef command
-#[derive(Clone, Debug, Eq, Serialize, Deserialize, Default, PartialEq, Hash)]
+#[derive(Clone, Debug, Eq, Serialize, Deserialize, Default, PartialEq, Hash, JsonSchema)]
 pub struct PutLink {
     /// Source identifier for the link
     pub source_id: String,
@@ -256,7 +457,7 @@ impl TryFrom<PutLink> for Link {
 from_impl!(PutLink);
 
 /// Struct for the DeleteLinkdef command
-#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Default, JsonSchema)]
 pub struct DeleteLink {
     /// The ID of the component to unlink
     pub source_id: String,
@@ -273,7 +474,7 @@ pub struct DeleteLink {
 from_impl!(DeleteLink);
 
 /// Struct for the PutConfig command
-#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Default, JsonSchema)]
 pub struct PutConfig {
     /// The name of the configuration to put
     pub config_name: String,
@@ -284,10 +485,119 @@ pub struct PutConfig {
 from_impl!(PutConfig);
 
 /// Struct for the DeleteConfig command
-#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Default, JsonSchema)]
 pub struct DeleteConfig {
     /// The name of the configuration to delete
     pub config_name: String,
 }
 
 from_impl!(DeleteConfig);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn published_commands_are_backwards_compatible() {
+        let command = Command::from(StopProvider {
+            provider_id: "provider".to_string(),
+            host_id: "host".to_string(),
+            model_name: "model".to_string(),
+            ..Default::default()
+        });
+
+        let published = PublishedCommand::new(command.clone(), Duration::from_secs(60));
+        let expires_at = published.expires_at.expect("Expiry should be set");
+        assert!(expires_at > Utc::now() + chrono::Duration::seconds(50));
+        let mut headers = HeaderMap::new();
+        published.insert_headers(&mut headers);
+        assert_eq!(
+            PublishedCommand::expiry_from_headers(Some(&headers)),
+            Some(expires_at)
+        );
+
+        // Commands published without an expiry never expire
+        assert_eq!(PublishedCommand::expiry_from_headers(None), None);
+        assert_eq!(
+            PublishedCommand::expiry_from_headers(Some(&HeaderMap::new())),
+            None
+        );
+    }
+
+    #[test]
+    fn coalesces_commands_for_the_same_target() {
+        let scale = |host_id: &str, count| {
+            Command::from(ScaleComponent {
+                component_id: "echo".to_string(),
+                host_id: host_id.to_string(),
+                count,
+                reference: "ghcr.io/echo:0.1.0".to_string(),
+                model_name: "model".to_string(),
+                ..Default::default()
+            })
+        };
+        let start = Command::from(StartProvider {
+            provider_id: "httpserver".to_string(),
+            host_id: "host1".to_string(),
+            reference: "ghcr.io/httpserver:0.1.0".to_string(),
+            model_name: "model".to_string(),
+            ..Default::default()
+        });
+        let stop = Command::from(StopProvider {
+            provider_id: "httpserver".to_string(),
+            host_id: "host1".to_string(),
+            model_name: "model".to_string(),
+            ..Default::default()
+        });
+        let put_config = Command::from(PutConfig {
+            config_name: "echo-config".to_string(),
+            config: HashMap::new(),
+        });
+
+        let commands = coalesce_commands(vec![
+            put_config.clone(),
+            scale("host1", 1),
+            stop.clone(),
+            scale("host2", 1),
+            scale("host1", 3),
+            start.clone(),
+            put_config.clone(),
+        ]);
+        assert_eq!(
+            commands,
+            vec![
+                put_config.clone(),
+                scale("host2", 1),
+                scale("host1", 3),
+                start.clone(),
+            ],
+            "Only the last command for each target should be kept, with config ahead of the commands that use it"
+        );
+
+        let commands = coalesce_commands(vec![
+            scale("host1", 1),
+            put_config.clone(),
+            start.clone(),
+            put_config.clone(),
+        ]);
+        assert_eq!(
+            commands,
+            vec![scale("host1", 1), put_config.clone(), start],
+            "A config put should stay ahead of commands that came after it first appeared"
+        );
+
+        let delete_config = Command::from(DeleteConfig {
+            config_name: "echo-config".to_string(),
+        });
+        let commands = coalesce_commands(vec![
+            put_config.clone(),
+            scale("host1", 1),
+            delete_config.clone(),
+        ]);
+        assert_eq!(
+            commands,
+            vec![scale("host1", 1), delete_config],
+            "A config deleted after a put should not be deleted before the components that use it"
+        );
+    }
+}
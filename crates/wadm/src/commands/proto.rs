@@ -0,0 +1,329 @@
+//! Protobuf messages for commands published with the protobuf encoding. Each command has its own
+//! message with numbered fields, so the encoding is compact and round trips every value exactly.
+//!
+//! These are written by hand instead of generated by `prost-build` to avoid needing `protoc` to
+//! build wadm. They are equivalent to this schema:
+//!
+//! ```protobuf
+//! syntax = "proto3";
+//! package wadm.commands;
+//!
+//! message Command {
+//!   oneof kind {
+//!     ScaleComponent scale_component = 1;
+//!     StartProvider start_provider = 2;
+//!     StopProvider stop_provider = 3;
+//!     PutLink put_link = 4;
+//!     DeleteLink delete_link = 5;
+//!     PutConfig put_config = 6;
+//!     DeleteConfig delete_config = 7;
+//!   }
+//! }
+//!
+//! message ScaleComponent {
+//!   string component_id = 1;
+//!   string host_id = 2;
+//!   uint32 count = 3;
+//!   string reference = 4;
+//!   string model_name = 5;
+//!   map<string, string> annotations = 6;
+//!   repeated string config = 7;
+//! }
+//!
+//! message StartProvider {
+//!   string reference = 1;
+//!   string provider_id = 2;
+//!   string host_id = 3;
+//!   string model_name = 4;
+//!   repeated string config = 5;
+//!   map<string, string> annotations = 6;
+//! }
+//!
+//! message StopProvider {
+//!   string provider_id = 1;
+//!   string host_id = 2;
+//!   string model_name = 3;
+//!   map<string, string> annotations = 4;
+//! }
+//!
+//! message PutLink {
+//!   string source_id = 1;
+//!   string target = 2;
+//!   string name = 3;
+//!   string wit_namespace = 4;
+//!   string wit_package = 5;
+//!   repeated string interfaces = 6;
+//!   repeated string source_config = 7;
+//!   repeated string target_config = 8;
+//!   string model_name = 9;
+//! }
+//!
+//! message DeleteLink {
+//!   string source_id = 1;
+//!   string wit_namespace = 2;
+//!   string wit_package = 3;
+//!   string link_name = 4;
+//!   string model_name = 5;
+//! }
+//!
+//! message PutConfig {
+//!   string config_name = 1;
+//!   map<string, string> config = 2;
+//! }
+//!
+//! message DeleteConfig {
+//!   string config_name = 1;
+//! }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+
+use prost::{Message, Oneof};
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct Command {
+    #[prost(oneof = "CommandKind", tags = "1, 2, 3, 4, 5, 6, 7")]
+    pub kind: Option<CommandKind>,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+pub(crate) enum CommandKind {
+    #[prost(message, tag = "1")]
+    ScaleComponent(ScaleComponent),
+    #[prost(message, tag = "2")]
+    StartProvider(StartProvider),
+    #[prost(message, tag = "3")]
+    StopProvider(StopProvider),
+    #[prost(message, tag = "4")]
+    PutLink(PutLink),
+    #[prost(message, tag = "5")]
+    DeleteLink(DeleteLink),
+    #[prost(message, tag = "6")]
+    PutConfig(PutConfig),
+    #[prost(message, tag = "7")]
+    DeleteConfig(DeleteConfig),
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct ScaleComponent {
+    #[prost(string, tag = "1")]
+    pub component_id: String,
+    #[prost(string, tag = "2")]
+    pub host_id: String,
+    #[prost(uint32, tag = "3")]
+    pub count: u32,
+    #[prost(string, tag = "4")]
+    pub reference: String,
+    #[prost(string, tag = "5")]
+    pub model_name: String,
+    #[prost(btree_map = "string, string", tag = "6")]
+    pub annotations: BTreeMap<String, String>,
+    #[prost(string, repeated, tag = "7")]
+    pub config: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct StartProvider {
+    #[prost(string, tag = "1")]
+    pub reference: String,
+    #[prost(string, tag = "2")]
+    pub provider_id: String,
+    #[prost(string, tag = "3")]
+    pub host_id: String,
+    #[prost(string, tag = "4")]
+    pub model_name: String,
+    #[prost(string, repeated, tag = "5")]
+    pub config: Vec<String>,
+    #[prost(btree_map = "string, string", tag = "6")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct StopProvider {
+    #[prost(string, tag = "1")]
+    pub provider_id: String,
+    #[prost(string, tag = "2")]
+    pub host_id: String,
+    #[prost(string, tag = "3")]
+    pub model_name: String,
+    #[prost(btree_map = "string, string", tag = "4")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct PutLink {
+    #[prost(string, tag = "1")]
+    pub source_id: String,
+    #[prost(string, tag = "2")]
+    pub target: String,
+    #[prost(string, tag = "3")]
+    pub name: String,
+    #[prost(string, tag = "4")]
+    pub wit_namespace: String,
+    #[prost(string, tag = "5")]
+    pub wit_package: String,
+    #[prost(string, repeated, tag = "6")]
+    pub interfaces: Vec<String>,
+    #[prost(string, repeated, tag = "7")]
+    pub source_config: Vec<String>,
+    #[prost(string, repeated, tag = "8")]
+    pub target_config: Vec<String>,
+    #[prost(string, tag = "9")]
+    pub model_name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct DeleteLink {
+    #[prost(string, tag = "1")]
+    pub source_id: String,
+    #[prost(string, tag = "2")]
+    pub wit_namespace: String,
+    #[prost(string, tag = "3")]
+    pub wit_package: String,
+    #[prost(string, tag = "4")]
+    pub link_name: String,
+    #[prost(string, tag = "5")]
+    pub model_name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct PutConfig {
+    #[prost(string, tag = "1")]
+    pub config_name: String,
+    #[prost(map = "string, string", tag = "2")]
+    pub config: HashMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct DeleteConfig {
+    #[prost(string, tag = "1")]
+    pub config_name: String,
+}
+
+impl From<super::Command> for Command {
+    fn from(command: super::Command) -> Command {
+        let kind = match command {
+            super::Command::ScaleComponent(cmd) => CommandKind::ScaleComponent(ScaleComponent {
+                component_id: cmd.component_id,
+                host_id: cmd.host_id,
+                count: cmd.count,
+                reference: cmd.reference,
+                model_name: cmd.model_name,
+                annotations: cmd.annotations,
+                config: cmd.config,
+            }),
+            super::Command::StartProvider(cmd) => CommandKind::StartProvider(StartProvider {
+                reference: cmd.reference,
+                provider_id: cmd.provider_id,
+                host_id: cmd.host_id,
+                model_name: cmd.model_name,
+                config: cmd.config,
+                annotations: cmd.annotations,
+            }),
+            super::Command::StopProvider(cmd) => CommandKind::StopProvider(StopProvider {
+                provider_id: cmd.provider_id,
+                host_id: cmd.host_id,
+                model_name: cmd.model_name,
+                annotations: cmd.annotations,
+            }),
+            super::Command::PutLink(cmd) => CommandKind::PutLink(PutLink {
+                source_id: cmd.source_id,
+                target: cmd.target,
+                name: cmd.name,
+                wit_namespace: cmd.wit_namespace,
+                wit_package: cmd.wit_package,
+                interfaces: cmd.interfaces,
+                source_config: cmd.source_config,
+                target_config: cmd.target_config,
+                model_name: cmd.model_name,
+            }),
+            super::Command::DeleteLink(cmd) => CommandKind::DeleteLink(DeleteLink {
+                source_id: cmd.source_id,
+                wit_namespace: cmd.wit_namespace,
+                wit_package: cmd.wit_package,
+                link_name: cmd.link_name,
+                model_name: cmd.model_name,
+            }),
+            super::Command::PutConfig(cmd) => CommandKind::PutConfig(PutConfig {
+                config_name: cmd.config_name,
+                config: cmd.config,
+            }),
+            super::Command::DeleteConfig(cmd) => CommandKind::DeleteConfig(DeleteConfig {
+                config_name: cmd.config_name,
+            }),
+        };
+        Command { kind: Some(kind) }
+    }
+}
+
+impl TryFrom<Command> for super::Command {
+    type Error = anyhow::Error;
+
+    fn try_from(command: Command) -> Result<super::Command, Self::Error> {
+        let command = match command.kind {
+            Some(CommandKind::ScaleComponent(cmd)) => super::Command::from(super::ScaleComponent {
+                component_id: cmd.component_id,
+                host_id: cmd.host_id,
+                count: cmd.count,
+                reference: cmd.reference,
+                model_name: cmd.model_name,
+                annotations: cmd.annotations,
+                config: cmd.config,
+            }),
+            Some(CommandKind::StartProvider(cmd)) => super::Command::from(super::StartProvider {
+                reference: cmd.reference,
+                provider_id: cmd.provider_id,
+                host_id: cmd.host_id,
+                model_name: cmd.model_name,
+                config: cmd.config,
+                annotations: cmd.annotations,
+            }),
+            Some(CommandKind::StopProvider(cmd)) => super::Command::from(super::StopProvider {
+                provider_id: cmd.provider_id,
+                host_id: cmd.host_id,
+                model_name: cmd.model_name,
+                annotations: cmd.annotations,
+            }),
+            Some(CommandKind::PutLink(cmd)) => super::Command::from(super::PutLink {
+                source_id: cmd.source_id,
+                target: cmd.target,
+                name: cmd.name,
+                wit_namespace: cmd.wit_namespace,
+                wit_package: cmd.wit_package,
+                interfaces: cmd.interfaces,
+                source_config: cmd.source_config,
+                target_config: cmd.target_config,
+                model_name: cmd.model_name,
+            }),
+            Some(CommandKind::DeleteLink(cmd)) => super::Command::from(super::DeleteLink {
+                source_id: cmd.source_id,
+                wit_namespace: cmd.wit_namespace,
+                wit_package: cmd.wit_package,
+                link_name: cmd.link_name,
+                model_name: cmd.model_name,
+            }),
+            Some(CommandKind::PutConfig(cmd)) => super::Command::from(super::PutConfig {
+                config_name: cmd.config_name,
+                config: cmd.config,
+            }),
+            Some(CommandKind::DeleteConfig(cmd)) => super::Command::from(super::DeleteConfig {
+                config_name: cmd.config_name,
+            }),
+            // A command kind added by a newer version of wadm
+            None => anyhow::bail!("Command has no kind this version of wadm knows about"),
+        };
+        Ok(command)
+    }
+}
+
+/// Encodes the given command as protobuf
+pub(crate) fn encode(command: &super::Command) -> Vec<u8> {
+    Command::from(command.clone()).encode_to_vec()
+}
+
+/// Decodes a command that was encoded as protobuf
+pub(crate) fn decode(data: &[u8]) -> anyhow::Result<super::Command> {
+    Command::decode(data)
+        .map_err(|e| anyhow::anyhow!("Invalid protobuf command: {e}"))?
+        .try_into()
+}
@@ -0,0 +1,329 @@
+//! Runtime settings that can be changed without restarting wadm. The current settings live in a
+//! [`SharedConfig`], which anything that depends on them can watch for changes. Settings are
+//! reloaded when wadm receives a SIGHUP or a request on the admin reload subject
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{watch, Semaphore};
+use tracing::{debug, info};
+
+use crate::consumers::{EventFilter, EventFilterRules, EventStartPosition};
+
+/// Settings that can be changed while wadm is running. When loaded from a file, any setting that
+/// isn't given keeps the value wadm was started with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// The maximum number of jobs to run at once. `None` means there is no limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_jobs: Option<usize>,
+    /// The maximum number of events to handle at once, overriding `max_jobs` for events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_event_jobs: Option<usize>,
+    /// The maximum number of commands to handle at once, overriding `max_jobs` for commands
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_command_jobs: Option<usize>,
+    /// How often, in seconds, the lattice state is checked for hosts, components and providers
+    /// that have gone away
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cleanup_interval_seconds: Option<u64>,
+    /// Log filter directives, in the same format as `RUST_LOG`. `None` uses `RUST_LOG`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<String>,
+    /// Event types to drop before they are processed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub event_exclude: Vec<String>,
+    /// Event types to sample, mapped to how often they are processed. Only every Nth event of each
+    /// type is processed
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub event_sample: BTreeMap<String, u32>,
+    /// Event types to drop for specific lattices. Lattices given here don't use the global exclude
+    /// and sample settings
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub lattice_event_exclude: BTreeMap<String, Vec<String>>,
+    /// Where event consumers start reading events when they are created. `None` replays all
+    /// events still in the stream
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_start: Option<EventStartPosition>,
+    /// Where the event consumers of specific lattices start reading events, overriding
+    /// `event_start` for those lattices
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub lattice_event_start: BTreeMap<String, EventStartPosition>,
+}
+
+impl RuntimeConfig {
+    /// Returns a copy of this config with every setting given in `other` replacing the one here
+    pub fn overlay(mut self, other: RuntimeConfig) -> RuntimeConfig {
+        if other.max_jobs.is_some() {
+            self.max_jobs = other.max_jobs;
+        }
+        if other.max_event_jobs.is_some() {
+            self.max_event_jobs = other.max_event_jobs;
+        }
+        if other.max_command_jobs.is_some() {
+            self.max_command_jobs = other.max_command_jobs;
+        }
+        if other.cleanup_interval_seconds.is_some() {
+            self.cleanup_interval_seconds = other.cleanup_interval_seconds;
+        }
+        if other.log_level.is_some() {
+            self.log_level = other.log_level;
+        }
+        if !other.event_exclude.is_empty() {
+            self.event_exclude = other.event_exclude;
+        }
+        if !other.event_sample.is_empty() {
+            self.event_sample = other.event_sample;
+        }
+        if !other.lattice_event_exclude.is_empty() {
+            self.lattice_event_exclude = other.lattice_event_exclude;
+        }
+        if other.event_start.is_some() {
+            self.event_start = other.event_start;
+        }
+        if !other.lattice_event_start.is_empty() {
+            self.lattice_event_start = other.lattice_event_start;
+        }
+        self
+    }
+
+    /// Returns the names of the settings that differ between this config and `other`
+    pub fn changed_settings(&self, other: &RuntimeConfig) -> Vec<&'static str> {
+        [
+            ("max_jobs", self.max_jobs != other.max_jobs),
+            (
+                "max_event_jobs",
+                self.max_event_jobs != other.max_event_jobs,
+            ),
+            (
+                "max_command_jobs",
+                self.max_command_jobs != other.max_command_jobs,
+            ),
+            (
+                "cleanup_interval_seconds",
+                self.cleanup_interval_seconds != other.cleanup_interval_seconds,
+            ),
+            ("log_level", self.log_level != other.log_level),
+            ("event_exclude", self.event_exclude != other.event_exclude),
+            ("event_sample", self.event_sample != other.event_sample),
+            (
+                "lattice_event_exclude",
+                self.lattice_event_exclude != other.lattice_event_exclude,
+            ),
+            ("event_start", self.event_start != other.event_start),
+            (
+                "lattice_event_start",
+                self.lattice_event_start != other.lattice_event_start,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect()
+    }
+
+    /// Returns the maximum number of events to handle at once. `None` means there is no limit
+    pub fn event_jobs(&self) -> Option<usize> {
+        self.max_event_jobs.or(self.max_jobs)
+    }
+
+    /// Returns the maximum number of commands to handle at once. `None` means there is no limit
+    pub fn command_jobs(&self) -> Option<usize> {
+        self.max_command_jobs.or(self.max_jobs)
+    }
+
+    /// Returns the cleanup interval, if one is set
+    pub fn cleanup_interval(&self) -> Option<Duration> {
+        self.cleanup_interval_seconds.map(Duration::from_secs)
+    }
+
+    /// Returns where the event consumer for the given lattice starts reading events
+    pub fn event_start(&self, lattice_id: &str) -> EventStartPosition {
+        self.lattice_event_start
+            .get(lattice_id)
+            .copied()
+            .or(self.event_start)
+            .unwrap_or_default()
+    }
+
+    /// Builds the event filter used by event consumers from these settings
+    pub fn event_filter(&self) -> EventFilter {
+        let mut default_rules = EventFilterRules::default();
+        for event_type in self.event_exclude.iter().filter(|ty| !ty.is_empty()) {
+            default_rules = default_rules.exclude(event_type);
+        }
+        for (event_type, every) in self.event_sample.iter() {
+            default_rules = default_rules.sample(event_type, *every);
+        }
+        self.lattice_event_exclude.iter().fold(
+            EventFilter::new(default_rules),
+            |filter, (lattice_id, event_types)| {
+                let rules = event_types
+                    .iter()
+                    .fold(EventFilterRules::default(), |rules, ty| rules.exclude(ty));
+                filter.with_lattice(lattice_id, rules)
+            },
+        )
+    }
+}
+
+/// The current [`RuntimeConfig`], shared with everything that depends on it. This is cheap to
+/// clone and all clones share the same config
+#[derive(Clone)]
+pub struct SharedConfig {
+    sender: Arc<watch::Sender<RuntimeConfig>>,
+}
+
+impl Default for SharedConfig {
+    fn default() -> Self {
+        SharedConfig::new(RuntimeConfig::default())
+    }
+}
+
+impl SharedConfig {
+    /// Creates a new shared config starting with the given settings
+    pub fn new(config: RuntimeConfig) -> SharedConfig {
+        SharedConfig {
+            sender: Arc::new(watch::channel(config).0),
+        }
+    }
+
+    /// Returns a copy of the current settings
+    pub fn current(&self) -> RuntimeConfig {
+        self.sender.borrow().clone()
+    }
+
+    /// Returns a receiver that is notified whenever the settings change
+    pub fn subscribe(&self) -> watch::Receiver<RuntimeConfig> {
+        self.sender.subscribe()
+    }
+
+    /// Replaces the current settings, notifying subscribers if anything changed. Returns the names
+    /// of the settings that changed
+    pub fn update(&self, config: RuntimeConfig) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        self.sender.send_if_modified(|current| {
+            changed = current.changed_settings(&config);
+            *current = config;
+            !changed.is_empty()
+        });
+        if changed.is_empty() {
+            debug!("Reloaded config without any changes");
+        } else {
+            info!(?changed, "Reloaded config");
+        }
+        changed
+    }
+}
+
+/// Anything that can reload the runtime config, used to answer admin reload requests
+#[async_trait]
+pub trait ConfigReloader {
+    /// Reloads the config, returning the names of the settings that changed
+    async fn reload(&self) -> Result<Vec<&'static str>>;
+}
+
+/// Changes the number of permits in a work pool from the `from` limit to the `to` limit, where
+/// `None` means there is no limit. Permits that are in use when shrinking the pool are removed as
+/// they are returned, so this waits until the pool has shrunk
+pub async fn resize_permits(permits: Arc<Semaphore>, from: Option<usize>, to: Option<usize>) {
+    let from = from.unwrap_or(Semaphore::MAX_PERMITS);
+    let to = to.unwrap_or(Semaphore::MAX_PERMITS);
+    if to >= from {
+        permits.add_permits(to - from);
+        return;
+    }
+    let mut remaining = from - to;
+    while remaining > 0 {
+        let chunk = remaining.min(u32::MAX as usize);
+        // NOTE: This only fails if the pool was closed, in which case nothing is working anymore
+        let Ok(acquired) = permits.acquire_many(chunk as u32).await else {
+            return;
+        };
+        acquired.forget();
+        remaining -= chunk;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn can_reload_config() {
+        let base = RuntimeConfig {
+            max_jobs: Some(10),
+            cleanup_interval_seconds: Some(70),
+            event_exclude: vec!["health_check_status".to_string()],
+            ..Default::default()
+        };
+        let file: RuntimeConfig = serde_json::from_value(serde_json::json!({
+            "max_jobs": 4,
+            "max_command_jobs": 2,
+            "log_level": "wadm=debug",
+            "lattice_event_exclude": {"quiet": ["host_heartbeat"]},
+            "lattice_event_start": {"quiet": "new"},
+        }))
+        .unwrap();
+
+        let shared = SharedConfig::new(base.clone());
+        let mut watcher = shared.subscribe();
+        let merged = base.overlay(file);
+        assert_eq!(merged.cleanup_interval(), Some(Duration::from_secs(70)));
+        assert_eq!(
+            merged.event_exclude,
+            vec!["health_check_status".to_string()],
+            "Settings not given should keep their value"
+        );
+
+        assert_eq!(
+            shared.update(merged.clone()),
+            vec![
+                "max_jobs",
+                "max_command_jobs",
+                "log_level",
+                "lattice_event_exclude",
+                "lattice_event_start"
+            ]
+        );
+        assert!(watcher.has_changed().unwrap());
+        let current = watcher.borrow_and_update().clone();
+        assert_eq!(current.event_jobs(), Some(4));
+        assert_eq!(
+            current.command_jobs(),
+            Some(2),
+            "Commands should have their own limit when one is set"
+        );
+        assert!(shared.update(merged).is_empty());
+        assert!(
+            !watcher.has_changed().unwrap(),
+            "Subscribers shouldn't be notified if nothing changed"
+        );
+
+        let filter = shared.current().event_filter();
+        assert!(filter.rules_for("quiet") != filter.rules_for("other"));
+        assert_eq!(
+            shared.current().event_start("quiet"),
+            EventStartPosition::New
+        );
+        assert_eq!(
+            shared.current().event_start("other"),
+            EventStartPosition::All
+        );
+
+        let permits = Arc::new(Semaphore::new(10));
+        let held = permits.clone().acquire_many_owned(8).await.unwrap();
+        let shrink = tokio::spawn(resize_permits(permits.clone(), Some(10), Some(4)));
+        tokio::task::yield_now().await;
+        assert!(!shrink.is_finished(), "Should wait for permits in use");
+        drop(held);
+        shrink.await.unwrap();
+        assert_eq!(permits.available_permits(), 4);
+        resize_permits(permits.clone(), Some(4), Some(6)).await;
+        assert_eq!(permits.available_permits(), 6);
+    }
+}
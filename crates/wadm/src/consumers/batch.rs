@@ -0,0 +1,249 @@
+//! Batched pulls from a durable consumer. Instead of pulling one message at a time, each pull asks
+//! for as many messages as there are work permits available, so busy lattices are drained quickly
+//! without pulling more messages than can be worked on. Messages that are waiting in a batch are
+//! kept from being redelivered by marking them in progress until they are handed out
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_nats::{
+    jetstream::{consumer::PullConsumer, AckKind, Message},
+    Error as NatsError,
+};
+use futures::{FutureExt, Stream, TryStreamExt};
+use opentelemetry_metrics::{metrics::Histogram, KeyValue};
+use tokio::{sync::Semaphore, task::JoinHandle};
+use tracing::{trace, warn};
+
+/// The most messages pulled at once, no matter how many permits are available
+const MAX_BATCH_SIZE: usize = 64;
+
+/// How long a pull waits for a message to show up when none are waiting
+const PULL_EXPIRY: Duration = Duration::from_secs(30);
+
+/// How long to wait before returning a failed pull, so a consumer that keeps failing (like while
+/// NATS is unavailable) doesn't spin
+const PULL_ERROR_DELAY: Duration = Duration::from_secs(1);
+
+/// A pull that is in progress. This is `Sync` so consumers can be shared
+type Pull = Pin<Box<dyn Future<Output = Result<Vec<Message>, NatsError>> + Send + Sync>>;
+
+/// Messages that have been pulled but not handed out yet
+type Buffer = Arc<Mutex<VecDeque<Message>>>;
+
+/// A stream of the messages of a pull consumer, fetched in batches sized by the number of work
+/// permits available. Without a permit pool, messages are pulled one at a time
+pub(crate) struct BatchedMessages {
+    consumer: PullConsumer,
+    permits: Option<Arc<Semaphore>>,
+    batch_sizes: Histogram<u64>,
+    attributes: Arc<[KeyValue]>,
+    buffered: Buffer,
+    pulling: Option<Pull>,
+    /// The task marking buffered messages in progress, started with the first batch
+    keepalive: Option<JoinHandle<()>>,
+}
+
+impl BatchedMessages {
+    /// Creates a stream of the messages of the given consumer. The lattice ID and kind of consumer
+    /// are attached to the batch size metrics
+    pub(crate) fn new(consumer: PullConsumer, lattice_id: &str, kind: &str) -> BatchedMessages {
+        BatchedMessages {
+            consumer,
+            permits: None,
+            batch_sizes: opentelemetry_metrics::global::meter("wadm")
+                .u64_histogram("wadm.consumer.batch_size")
+                .with_description("Number of messages pulled from a consumer at once")
+                .init(),
+            attributes: Arc::new([
+                KeyValue::new("lattice_id", lattice_id.to_owned()),
+                KeyValue::new("consumer", kind.to_owned()),
+            ]),
+            buffered: Buffer::default(),
+            pulling: None,
+            keepalive: None,
+        }
+    }
+
+    /// Sizes batches by the permits available in the given pool
+    pub(crate) fn with_permits(mut self, permits: Arc<Semaphore>) -> BatchedMessages {
+        self.permits = Some(permits);
+        self
+    }
+
+    fn batch_size(&self) -> usize {
+        self.permits
+            .as_ref()
+            .map(|permits| batch_size(permits.available_permits()))
+            .unwrap_or(1)
+    }
+
+    /// Starts marking buffered messages in progress, if that isn't running yet. This is done
+    /// twice per ack wait so messages sitting in a batch while earlier messages are worked on
+    /// aren't redelivered
+    fn start_keepalive(&mut self) {
+        if self.keepalive.is_some() {
+            return;
+        }
+        let interval = self.consumer.cached_info().config.ack_wait / 2;
+        if interval.is_zero() {
+            return;
+        }
+        self.keepalive = Some(tokio::spawn(keep_buffered_alive(
+            Arc::downgrade(&self.buffered),
+            interval,
+        )));
+    }
+}
+
+/// Marks every message in the buffer in progress on the given interval, until the buffer is dropped
+async fn keep_buffered_alive(buffer: Weak<Mutex<VecDeque<Message>>>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let Some(buffered) = buffer.upgrade() else {
+            return;
+        };
+        let waiting: Vec<Message> = buffered
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .cloned()
+            .collect();
+        drop(buffered);
+        for msg in waiting {
+            if let Err(e) = msg.ack_with(AckKind::Progress).await {
+                warn!(error = %e, "Unable to mark buffered message in progress, it may be redelivered");
+            }
+        }
+    }
+}
+
+impl Drop for BatchedMessages {
+    fn drop(&mut self) {
+        if let Some(keepalive) = self.keepalive.take() {
+            keepalive.abort();
+        }
+        let waiting: Vec<Message> = self
+            .buffered
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .drain(..)
+            .collect();
+        if waiting.is_empty() {
+            return;
+        }
+        // Nack anything that was never handed out so it is redelivered right away rather than
+        // after the ack wait
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(async move {
+                for msg in waiting {
+                    if let Err(e) = msg.ack_with(AckKind::Nak(None)).await {
+                        warn!(error = %e, "Error when sending nack for buffered message during drop")
+                    }
+                }
+            });
+        } else {
+            warn!("Couldn't find async runtime to nack buffered messages during drop")
+        }
+    }
+}
+
+/// Returns how many messages to pull when the given number of permits are available. At least one
+/// message is always pulled, so the consumer still waits for messages when all permits are in use
+fn batch_size(available_permits: usize) -> usize {
+    available_permits.clamp(1, MAX_BATCH_SIZE)
+}
+
+/// Takes up to `size` messages that are already waiting. If none are, this waits for the next
+/// message instead, so messages are handed out as soon as they arrive
+async fn pull(consumer: PullConsumer, size: usize) -> Result<Vec<Message>, NatsError> {
+    let res = pull_batch(&consumer, size).await;
+    if res.is_err() {
+        tokio::time::sleep(PULL_ERROR_DELAY).await;
+    }
+    res
+}
+
+async fn pull_batch(consumer: &PullConsumer, size: usize) -> Result<Vec<Message>, NatsError> {
+    loop {
+        let waiting: Vec<Message> = consumer
+            .fetch()
+            .max_messages(size)
+            .messages()
+            .await?
+            .try_collect()
+            .await?;
+        if !waiting.is_empty() {
+            return Ok(waiting);
+        }
+        let next: Vec<Message> = consumer
+            .batch()
+            .max_messages(1)
+            .expires(PULL_EXPIRY)
+            .messages()
+            .await?
+            .try_collect()
+            .await?;
+        if !next.is_empty() {
+            return Ok(next);
+        }
+    }
+}
+
+impl Stream for BatchedMessages {
+    type Item = Result<Message, NatsError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let next = self
+                .buffered
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .pop_front();
+            if let Some(msg) = next {
+                return Poll::Ready(Some(Ok(msg)));
+            }
+            if self.pulling.is_none() {
+                let size = self.batch_size();
+                trace!(%size, "Pulling batch of messages");
+                self.pulling = Some(Box::pin(pull(self.consumer.clone(), size)));
+            }
+            // SAFETY: The pull was set above if there wasn't one in progress
+            let res = match self.pulling.as_mut().unwrap().poll_unpin(cx) {
+                Poll::Ready(res) => res,
+                Poll::Pending => return Poll::Pending,
+            };
+            self.pulling = None;
+            match res {
+                Ok(messages) => {
+                    self.batch_sizes
+                        .record(messages.len() as u64, &self.attributes);
+                    if messages.len() > 1 {
+                        self.start_keepalive();
+                    }
+                    self.buffered
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .extend(messages);
+                }
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sizes_batches_by_available_permits() {
+        assert_eq!(batch_size(0), 1, "Should always pull at least one message");
+        assert_eq!(batch_size(5), 5);
+        assert_eq!(batch_size(Semaphore::MAX_PERMITS), MAX_BATCH_SIZE);
+    }
+}
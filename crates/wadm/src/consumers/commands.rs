@@ -2,28 +2,56 @@
 
 use std::collections::HashMap;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use async_nats::{
-    jetstream::{
-        consumer::pull::{Config as PullConfig, Stream as MessageStream},
-        stream::Stream as JsStream,
-    },
+    jetstream::{consumer::pull::Config as PullConfig, stream::Stream as JsStream},
     Error as NatsError,
 };
 use futures::{Stream, TryStreamExt};
+use tokio::sync::Semaphore;
 use tracing::{error, warn};
 
-use super::{CreateConsumer, ScopedMessage, LATTICE_METADATA_KEY, MULTITENANT_METADATA_KEY};
+use super::{
+    batch::BatchedMessages, quarantine::Quarantine, CreateConsumer, ScopedMessage,
+    LATTICE_METADATA_KEY, MULTITENANT_METADATA_KEY,
+};
 use crate::commands::*;
+use crate::trace_context::TraceContext;
 
 /// The name of the durable NATS stream and consumer that contains incoming lattice events
 pub const COMMANDS_CONSUMER_PREFIX: &str = "wadm_commands";
 
+/// The default number of times a command is delivered before the consumer gives up on it
+pub const DEFAULT_COMMAND_MAX_DELIVER: i64 = 3;
+
+/// Options for creating a [`CommandConsumer`]
+#[derive(Clone)]
+pub struct CommandConsumerOptions {
+    /// The maximum number of times a command is delivered. This should be at least the number of
+    /// attempts the command worker makes before dead lettering a command.
+    ///
+    /// NOTE: This is only applied when the durable consumer is first created
+    pub max_deliver: i64,
+    /// Where commands that can't be decoded are quarantined. Without one they are skipped
+    pub quarantine: Option<Quarantine>,
+}
+
+impl Default for CommandConsumerOptions {
+    fn default() -> Self {
+        CommandConsumerOptions {
+            max_deliver: DEFAULT_COMMAND_MAX_DELIVER,
+            quarantine: None,
+        }
+    }
+}
+
 /// A stream of all commands in a lattice, consumed from a durable NATS stream and consumer
 pub struct CommandConsumer {
-    stream: MessageStream,
+    stream: BatchedMessages,
     lattice_id: String,
+    quarantine: Option<Quarantine>,
 }
 
 impl CommandConsumer {
@@ -38,6 +66,24 @@ impl CommandConsumer {
         topic: &str,
         lattice_id: &str,
         multitenant_prefix: Option<&str>,
+    ) -> Result<CommandConsumer, NatsError> {
+        CommandConsumer::new_with_options(
+            stream,
+            topic,
+            lattice_id,
+            multitenant_prefix,
+            &CommandConsumerOptions::default(),
+        )
+        .await
+    }
+
+    /// Same as [`CommandConsumer::new`], but with the given options for the durable consumer
+    pub async fn new_with_options(
+        stream: JsStream,
+        topic: &str,
+        lattice_id: &str,
+        multitenant_prefix: Option<&str>,
+        options: &CommandConsumerOptions,
     ) -> Result<CommandConsumer, NatsError> {
         if !topic.contains(lattice_id) {
             return Err(format!("Topic {topic} does not match for lattice ID {lattice_id}").into());
@@ -68,7 +114,7 @@ impl CommandConsumer {
                     )),
                     ack_policy: async_nats::jetstream::consumer::AckPolicy::Explicit,
                     ack_wait: super::DEFAULT_ACK_TIME,
-                    max_deliver: 3,
+                    max_deliver: options.max_deliver,
                     deliver_policy: async_nats::jetstream::consumer::DeliverPolicy::All,
                     filter_subject: topic.to_owned(),
                     metadata,
@@ -76,16 +122,19 @@ impl CommandConsumer {
                 },
             )
             .await?;
-        let messages = consumer
-            .stream()
-            .max_messages_per_batch(1)
-            .messages()
-            .await?;
         Ok(CommandConsumer {
-            stream: messages,
+            stream: BatchedMessages::new(consumer, lattice_id, "commands"),
             lattice_id: lattice_id.to_owned(),
+            quarantine: options.quarantine.clone(),
         })
     }
+
+    /// Pulls commands in batches sized by the permits available in the given work pool, instead
+    /// of one at a time
+    pub fn with_permits(mut self, permits: Arc<Semaphore>) -> CommandConsumer {
+        self.stream = self.stream.with_permits(permits);
+        self
+    }
 }
 
 impl Stream for CommandConsumer {
@@ -94,14 +143,17 @@ impl Stream for CommandConsumer {
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         match self.stream.try_poll_next_unpin(cx) {
             Poll::Ready(None) => Poll::Ready(None),
-            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(Box::new(e)))),
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
             Poll::Ready(Some(Ok(msg))) => {
                 // Convert to our event type, skipping if we can't do it (and looping around to
                 // try the next poll)
-                let cmd = match serde_json::from_slice(&msg.payload) {
-                    Ok(cmd) => cmd,
+                let decoded = CommandEnvelope::decode(&msg.payload, msg.headers.as_ref());
+                let cmd = match decoded {
+                    Ok(envelope) => envelope.command,
                     Err(e) => {
                         warn!(error = ?e, "Unable to decode as command. Skipping message");
+                        let quarantine = self.quarantine.clone();
+                        let lattice_id = self.lattice_id.clone();
                         // This is slightly janky, but rather than having to store and poll the
                         // future (which gets a little gnarly), just pass the message onto a
                         // spawned thread which wakes up the thread when it is done acking.
@@ -111,7 +163,10 @@ impl Stream for CommandConsumer {
                         // can't as it means we can't ack the message and we'll be stuck waiting
                         // for it to deliver again until it fails
                         tokio::spawn(async move {
-                            if let Err(e) = msg.ack().await {
+                            if let Some(quarantine) = quarantine {
+                                let error = format!("Unable to decode as command: {e}");
+                                quarantine.park_undecodable(&lattice_id, msg, &error).await;
+                            } else if let Err(e) = msg.ack().await {
                                 error!(error = %e, "Error when trying to ack skipped message, message will be redelivered")
                             }
                             waker.wake();
@@ -123,10 +178,13 @@ impl Stream for CommandConsumer {
                 // NOTE(thomastaylor312): Ideally we'd consume `msg.payload` above with a
                 // `Cursor` and `from_reader` and then manually reconstruct the acking using the
                 // message context, but I didn't want to waste time optimizing yet
+                let trace_context = msg.headers.as_ref().and_then(TraceContext::from_headers);
                 Poll::Ready(Some(Ok(ScopedMessage {
                     lattice_id: self.lattice_id.clone(),
-                    inner: cmd,
+                    inner: cmd.command,
                     acker: Some(msg),
+                    expires_at: cmd.expires_at,
+                    trace_context,
                 })))
             }
             Poll::Pending => Poll::Pending,
@@ -137,13 +195,18 @@ impl Stream for CommandConsumer {
 #[async_trait::async_trait]
 impl CreateConsumer for CommandConsumer {
     type Output = CommandConsumer;
+    type Options = CommandConsumerOptions;
 
     async fn create(
         stream: async_nats::jetstream::stream::Stream,
         topic: &str,
         lattice_id: &str,
         multitenant_prefix: Option<&str>,
+        options: &Self::Options,
+        permits: Arc<Semaphore>,
     ) -> Result<Self::Output, NatsError> {
-        CommandConsumer::new(stream, topic, lattice_id, multitenant_prefix).await
+        CommandConsumer::new_with_options(stream, topic, lattice_id, multitenant_prefix, options)
+            .await
+            .map(|consumer| consumer.with_permits(permits))
     }
 }
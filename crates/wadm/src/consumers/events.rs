@@ -1,30 +1,239 @@
 //! A module for creating and consuming a stream of events from a wasmcloud lattice
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
+use std::fmt;
 use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use async_nats::{
     jetstream::{
-        consumer::pull::{Config as PullConfig, Stream as MessageStream},
+        consumer::{pull::Config as PullConfig, DeliverPolicy},
         stream::Stream as JsStream,
     },
     Error as NatsError,
 };
+use chrono::{DateTime, SecondsFormat, Utc};
+use cloudevents::AttributesReader;
 use futures::{Stream, TryStreamExt};
-use tracing::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{watch, Semaphore};
+use tracing::{debug, error, info, trace, warn};
 
-use super::{CreateConsumer, ScopedMessage, LATTICE_METADATA_KEY, MULTITENANT_METADATA_KEY};
+use super::quarantine::Quarantine;
+use super::{
+    batch::BatchedMessages, CreateConsumer, ScopedMessage, LATTICE_METADATA_KEY,
+    MULTITENANT_METADATA_KEY,
+};
+use crate::config::{RuntimeConfig, SharedConfig};
 use crate::events::*;
+use crate::trace_context::TraceContext;
 
 /// The name of the durable NATS stream and consumer that contains incoming lattice events
 pub const EVENTS_CONSUMER_PREFIX: &str = "wadm_event_consumer";
 
+/// The prefix of the event types wadm publishes itself. These events drive deploys, so they are
+/// never filtered
+const WADM_EVENT_TYPE_PREFIX: &str = "com.wadm.";
+/// The prefix added to event types given without a namespace
+const LATTICE_EVENT_TYPE_PREFIX: &str = "com.wasmcloud.lattice.";
+
+/// Rules for which events an [`EventConsumer`] drops before they are passed on to a worker. This
+/// is meant for cutting down on high volume events (like health checks) in large lattices. Dropped
+/// events are acked and never processed.
+///
+/// Event types can either be given in full (e.g. `com.wasmcloud.lattice.health_check_status`) or
+/// without the `com.wasmcloud.lattice.` prefix (e.g. `health_check_status`)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventFilterRules {
+    excluded: HashSet<String>,
+    sampled: HashMap<String, u32>,
+}
+
+impl EventFilterRules {
+    /// Drops all events of the given type
+    pub fn exclude(mut self, event_type: &str) -> EventFilterRules {
+        if let Some(ty) = filterable_type(event_type) {
+            self.excluded.insert(ty);
+        }
+        self
+    }
+
+    /// Only processes every `every`th event of the given type, dropping the rest. A value of 0 or 1
+    /// processes every event
+    pub fn sample(mut self, event_type: &str, every: u32) -> EventFilterRules {
+        if let Some(ty) = filterable_type(event_type) {
+            if every > 1 {
+                self.sampled.insert(ty, every);
+            } else {
+                self.sampled.remove(&ty);
+            }
+        }
+        self
+    }
+
+    /// Returns true if these rules don't drop any events
+    pub fn is_empty(&self) -> bool {
+        self.excluded.is_empty() && self.sampled.is_empty()
+    }
+
+    /// Returns whether an event of the given type should be processed. `counts` tracks how many
+    /// events of each sampled type have been seen so far
+    fn allows(&self, event_type: &str, counts: &mut HashMap<String, u32>) -> bool {
+        if self.excluded.contains(event_type) {
+            return false;
+        }
+        match self.sampled.get(event_type) {
+            Some(every) => {
+                let count = counts.entry(event_type.to_owned()).or_default();
+                let allowed = *count == 0;
+                *count = (*count + 1) % every;
+                allowed
+            }
+            None => true,
+        }
+    }
+}
+
+/// Returns the full event type to filter on, or `None` if the type can't be filtered
+fn filterable_type(event_type: &str) -> Option<String> {
+    let ty = if event_type.contains('.') {
+        event_type.to_owned()
+    } else {
+        format!("{LATTICE_EVENT_TYPE_PREFIX}{event_type}")
+    };
+    if ty.starts_with(WADM_EVENT_TYPE_PREFIX) {
+        warn!(event_type = %ty, "Events published by wadm cannot be filtered, ignoring");
+        return None;
+    }
+    Some(ty)
+}
+
+/// Event filtering configuration for all event consumers. Each lattice uses the default rules
+/// unless it has its own rules set, in which case the lattice rules are used instead
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    default: EventFilterRules,
+    lattices: HashMap<String, EventFilterRules>,
+}
+
+impl EventFilter {
+    /// Creates a new filter that applies the given rules to all lattices
+    pub fn new(default: EventFilterRules) -> EventFilter {
+        EventFilter {
+            default,
+            lattices: HashMap::new(),
+        }
+    }
+
+    /// Sets the rules used for a specific lattice, replacing the default rules for that lattice
+    pub fn with_lattice(mut self, lattice_id: &str, rules: EventFilterRules) -> EventFilter {
+        self.lattices.insert(lattice_id.to_owned(), rules);
+        self
+    }
+
+    /// Returns the rules that apply to the given lattice
+    pub fn rules_for(&self, lattice_id: &str) -> &EventFilterRules {
+        self.lattices.get(lattice_id).unwrap_or(&self.default)
+    }
+}
+
+/// Where the event consumer of a lattice starts reading events when it is created. Given as `all`,
+/// `new` or an RFC 3339 time (e.g. `2024-06-01T12:00:00Z`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum EventStartPosition {
+    /// Replays every event still kept in the events stream
+    #[default]
+    All,
+    /// Only reads events published after the consumer is created, skipping any backlog
+    New,
+    /// Reads the events published at or after the given time
+    Time(DateTime<Utc>),
+}
+
+impl EventStartPosition {
+    fn deliver_policy(&self) -> Result<DeliverPolicy, NatsError> {
+        Ok(match self {
+            EventStartPosition::All => DeliverPolicy::All,
+            EventStartPosition::New => DeliverPolicy::New,
+            EventStartPosition::Time(time) => DeliverPolicy::ByStartTime {
+                start_time: time::OffsetDateTime::from_unix_timestamp_nanos(
+                    time.timestamp_nanos_opt()
+                        .ok_or("Event start time is out of range")?
+                        .into(),
+                )?,
+            },
+        })
+    }
+}
+
+impl FromStr for EventStartPosition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(EventStartPosition::All),
+            "new" => Ok(EventStartPosition::New),
+            _ => DateTime::parse_from_rfc3339(s)
+                .map(|time| EventStartPosition::Time(time.with_timezone(&Utc)))
+                .map_err(|e| {
+                    format!("Invalid event start position {s}, expected all, new or an RFC 3339 time: {e}")
+                }),
+        }
+    }
+}
+
+impl TryFrom<String> for EventStartPosition {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for EventStartPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventStartPosition::All => f.write_str("all"),
+            EventStartPosition::New => f.write_str("new"),
+            EventStartPosition::Time(time) => {
+                f.write_str(&time.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+            }
+        }
+    }
+}
+
+impl From<EventStartPosition> for String {
+    fn from(position: EventStartPosition) -> Self {
+        position.to_string()
+    }
+}
+
+/// The default number of times an event is delivered before the consumer gives up on it
+pub const DEFAULT_EVENT_MAX_DELIVER: i64 = 3;
+
+/// Options for creating an [`EventConsumer`]
+#[derive(Clone, Default)]
+pub struct EventConsumerOptions {
+    /// The config the event filter and start position come from
+    pub config: SharedConfig,
+    /// Where events that can't be decoded are quarantined. When set, events are delivered as many
+    /// times as the quarantine allows instead of [`DEFAULT_EVENT_MAX_DELIVER`] times
+    pub quarantine: Option<Quarantine>,
+}
+
 /// A stream of all events of a lattice, consumed from a durable NATS stream and consumer
 pub struct EventConsumer {
-    stream: MessageStream,
+    stream: BatchedMessages,
     lattice_id: String,
+    filter: EventFilterRules,
+    sample_counts: HashMap<String, u32>,
+    /// Watches for config reloads so the filter can be updated while the consumer is running
+    config: Option<watch::Receiver<RuntimeConfig>>,
+    quarantine: Option<Quarantine>,
 }
 
 impl EventConsumer {
@@ -33,12 +242,39 @@ impl EventConsumer {
     ///
     /// The `topic` param should be a valid topic where lattice events are expected to be sent and
     /// should match the given lattice ID. An error will be returned if the given lattice ID is not
-    /// contained in the topic
+    /// contained in the topic.
+    ///
+    /// The durable consumer keeps its place across restarts, so `start` only matters when it is
+    /// created. If the existing consumer was created with a different start position, it is
+    /// recreated from the new position
     pub async fn new(
         stream: JsStream,
         topic: &str,
         lattice_id: &str,
         multitenant_prefix: Option<&str>,
+        start: EventStartPosition,
+    ) -> Result<EventConsumer, NatsError> {
+        EventConsumer::new_with_max_deliver(
+            stream,
+            topic,
+            lattice_id,
+            multitenant_prefix,
+            start,
+            DEFAULT_EVENT_MAX_DELIVER,
+        )
+        .await
+    }
+
+    /// Same as [`EventConsumer::new`], but delivers each event up to the given number of times.
+    ///
+    /// NOTE: This is only applied when the durable consumer is first created
+    pub async fn new_with_max_deliver(
+        stream: JsStream,
+        topic: &str,
+        lattice_id: &str,
+        multitenant_prefix: Option<&str>,
+        start: EventStartPosition,
+        max_deliver: i64,
     ) -> Result<EventConsumer, NatsError> {
         if !topic.contains(lattice_id) {
             return Err(format!("Topic {topic} does not match for lattice ID {lattice_id}").into());
@@ -57,81 +293,163 @@ impl EventConsumer {
                 HashMap::from([(LATTICE_METADATA_KEY.to_string(), lattice_id.to_string())]),
             )
         };
-        let consumer = stream
-            .get_or_create_consumer(
-                &consumer_name,
-                PullConfig {
-                    durable_name: Some(consumer_name.clone()),
-                    name: Some(consumer_name.clone()),
-                    description: Some(format!(
-                        "Durable wadm events consumer for lattice {lattice_id}"
-                    )),
-                    ack_policy: async_nats::jetstream::consumer::AckPolicy::Explicit,
-                    ack_wait: super::DEFAULT_ACK_TIME,
-                    max_deliver: 3,
-                    deliver_policy: async_nats::jetstream::consumer::DeliverPolicy::All,
-                    filter_subject: topic.to_owned(),
-                    metadata,
-                    ..Default::default()
-                },
-            )
-            .await?;
-        let messages = consumer
-            .stream()
-            .max_messages_per_batch(1)
-            .messages()
+        let config = PullConfig {
+            durable_name: Some(consumer_name.clone()),
+            name: Some(consumer_name.clone()),
+            description: Some(format!(
+                "Durable wadm events consumer for lattice {lattice_id}"
+            )),
+            ack_policy: async_nats::jetstream::consumer::AckPolicy::Explicit,
+            ack_wait: super::DEFAULT_ACK_TIME,
+            max_deliver,
+            deliver_policy: start.deliver_policy()?,
+            filter_subject: topic.to_owned(),
+            metadata,
+            ..Default::default()
+        };
+        let mut consumer = stream
+            .get_or_create_consumer(&consumer_name, config.clone())
             .await?;
+        if consumer.cached_info().config.deliver_policy != config.deliver_policy {
+            info!(%lattice_id, %start, "Event start position changed, recreating events consumer");
+            stream.delete_consumer(&consumer_name).await?;
+            consumer = stream.create_consumer(config).await?;
+        }
         Ok(EventConsumer {
-            stream: messages,
+            stream: BatchedMessages::new(consumer, lattice_id, "events"),
             lattice_id: lattice_id.to_owned(),
+            filter: EventFilterRules::default(),
+            sample_counts: HashMap::new(),
+            config: None,
+            quarantine: None,
         })
     }
+
+    /// Quarantines events that can't be decoded instead of skipping them
+    pub fn with_quarantine(mut self, quarantine: Quarantine) -> EventConsumer {
+        self.quarantine = Some(quarantine);
+        self
+    }
+
+    /// Quarantines a message that couldn't be decoded if there is a quarantine, otherwise skips
+    /// it
+    fn reject(&self, msg: async_nats::jetstream::Message, error: String, cx: &mut Context<'_>) {
+        let Some(quarantine) = self.quarantine.clone() else {
+            ack_skipped(msg, cx);
+            return;
+        };
+        let lattice_id = self.lattice_id.clone();
+        let waker = cx.waker().clone();
+        tokio::spawn(async move {
+            quarantine.park_undecodable(&lattice_id, msg, &error).await;
+            waker.wake();
+        });
+    }
+
+    /// Pulls events in batches sized by the permits available in the given work pool, instead of
+    /// one at a time
+    pub fn with_permits(mut self, permits: Arc<Semaphore>) -> EventConsumer {
+        self.stream = self.stream.with_permits(permits);
+        self
+    }
+
+    /// Sets the rules for events that should be dropped instead of being returned by this consumer
+    pub fn with_filter(mut self, filter: EventFilterRules) -> EventConsumer {
+        self.filter = filter;
+        self.sample_counts.clear();
+        self
+    }
+
+    /// Uses the event filter from the given config, updating it whenever the config is reloaded
+    pub fn with_config(mut self, mut config: watch::Receiver<RuntimeConfig>) -> EventConsumer {
+        let filter = config
+            .borrow_and_update()
+            .event_filter()
+            .rules_for(&self.lattice_id)
+            .clone();
+        self.config = Some(config);
+        self.with_filter(filter)
+    }
+
+    /// Updates the filter if the config was reloaded since it was last checked
+    fn refresh_filter(&mut self) {
+        let Some(config) = self.config.as_mut() else {
+            return;
+        };
+        // NOTE: This only errors if the sender was dropped, in which case the filter can't change
+        if !config.has_changed().unwrap_or(false) {
+            return;
+        }
+        let filter = config
+            .borrow_and_update()
+            .event_filter()
+            .rules_for(&self.lattice_id)
+            .clone();
+        if filter != self.filter {
+            debug!(lattice_id = %self.lattice_id, "Updating event filter from reloaded config");
+            self.filter = filter;
+            self.sample_counts.clear();
+        }
+    }
+}
+
+/// Acks a message that is being skipped, waking up the stream once it is done
+fn ack_skipped(msg: async_nats::jetstream::Message, cx: &mut Context<'_>) {
+    // This is slightly janky, but rather than having to store and poll the future (which gets a
+    // little gnarly), just pass the message onto a spawned thread which wakes up the thread when
+    // it is done acking.
+    let waker = cx.waker().clone();
+    // NOTE: If we are already in a stream impl, we should be able to spawn without worrying. A
+    // panic isn't the worst here if for some reason we can't as it means we can't ack the message
+    // and we'll be stuck waiting for it to deliver again until it fails
+    tokio::spawn(async move {
+        if let Err(e) = msg.ack().await {
+            error!(error = %e, "Error when trying to ack skipped message, message will be redelivered")
+        }
+        waker.wake();
+    });
 }
 
 impl Stream for EventConsumer {
     type Item = Result<ScopedMessage<Event>, NatsError>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.refresh_filter();
         match self.stream.try_poll_next_unpin(cx) {
             Poll::Ready(None) => Poll::Ready(None),
-            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(Box::new(e)))),
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
             Poll::Ready(Some(Ok(msg))) => {
                 // Parse as a cloud event, skipping if we can't do it (and looping around to try
                 // the next poll)
-                let raw_evt: cloudevents::Event = match serde_json::from_slice(&msg.payload) {
+                let raw_evt = match parse_cloudevent(&msg.payload) {
                     Ok(evt) => evt,
                     Err(e) => {
                         warn!(error = %e, "Unable to decode message as cloudevent. Skipping message");
-                        // This is slightly janky, but rather than having to store and poll the
-                        // future (which gets a little gnarly), just pass the message onto a
-                        // spawned thread which wakes up the thread when it is done acking.
-                        let waker = cx.waker().clone();
-                        // NOTE: If we are already in a stream impl, we should be able to spawn
-                        // without worrying. A panic isn't the worst here if for some reason we
-                        // can't as it means we can't ack the message and we'll be stuck waiting
-                        // for it to deliver again until it fails
-                        tokio::spawn(async move {
-                            if let Err(e) = msg.ack().await {
-                                error!(error = %e, "Error when trying to ack skipped message, message will be redelivered")
-                            }
-                            waker.wake();
-                        });
+                        self.reject(msg, format!("Unable to decode as cloudevent: {e}"), cx);
                         // Return a poll pending. It will then wake up and try again once it has acked
                         return Poll::Pending;
                     }
                 };
+                // Drop filtered events before doing the work of converting them
+                let this = &mut *self;
+                if !this.filter.allows(raw_evt.ty(), &mut this.sample_counts) {
+                    trace!(event_type = %raw_evt.ty(), "Event is filtered. Skipping message");
+                    ack_skipped(msg, cx);
+                    return Poll::Pending;
+                }
+                // Prefer the context on the event itself, as that is what the host set when creating
+                // it, and fall back to the one it was published with
+                let trace_context = TraceContext::from_cloudevent(&raw_evt)
+                    .or_else(|| msg.headers.as_ref().and_then(TraceContext::from_headers));
                 // Convert to our event type, skipping if we can't do it
-                let evt = match Event::try_from(raw_evt) {
-                    Ok(evt) => evt,
+                let evt = match EventEnvelope::try_from(raw_evt) {
+                    Ok(envelope) => {
+                        trace!(id = %envelope.id, host_id = ?envelope.host_id(), "Parsed event envelope");
+                        envelope.into_event()
+                    }
                     Err(e) => {
                         debug!(error = ?e, "Unable to decode as event. Skipping message");
-                        let waker = cx.waker().clone();
-                        tokio::spawn(async move {
-                            if let Err(e) = msg.ack().await {
-                                error!(error = %e, "Error when trying to ack skipped message, message will be redelivered")
-                            }
-                            waker.wake();
-                        });
+                        self.reject(msg, format!("Unable to decode as event: {e:?}"), cx);
                         // Return a poll pending. It will then wake up and try again once it has acked
                         return Poll::Pending;
                     }
@@ -143,6 +461,8 @@ impl Stream for EventConsumer {
                     lattice_id: self.lattice_id.clone(),
                     inner: evt,
                     acker: Some(msg),
+                    expires_at: None,
+                    trace_context,
                 })))
             }
             Poll::Pending => Poll::Pending,
@@ -153,13 +473,84 @@ impl Stream for EventConsumer {
 #[async_trait::async_trait]
 impl CreateConsumer for EventConsumer {
     type Output = EventConsumer;
+    type Options = EventConsumerOptions;
 
     async fn create(
         stream: async_nats::jetstream::stream::Stream,
         topic: &str,
         lattice_id: &str,
         multitenant_prefix: Option<&str>,
+        options: &Self::Options,
+        permits: Arc<Semaphore>,
     ) -> Result<Self::Output, NatsError> {
-        EventConsumer::new(stream, topic, lattice_id, multitenant_prefix).await
+        let start = options.config.current().event_start(lattice_id);
+        let max_deliver = options
+            .quarantine
+            .as_ref()
+            .map(|quarantine| quarantine.max_deliveries() as i64)
+            .unwrap_or(DEFAULT_EVENT_MAX_DELIVER);
+        let consumer = EventConsumer::new_with_max_deliver(
+            stream,
+            topic,
+            lattice_id,
+            multitenant_prefix,
+            start,
+            max_deliver,
+        )
+        .await?
+        .with_config(options.config.subscribe())
+        .with_permits(permits);
+        Ok(match options.quarantine.clone() {
+            Some(quarantine) => consumer.with_quarantine(quarantine),
+            None => consumer,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_filter_events() {
+        let rules = EventFilterRules::default()
+            .exclude("health_check_status")
+            .exclude("com.wadm.manifest_published")
+            .sample("com.wasmcloud.lattice.host_heartbeat", 3);
+        let mut counts = HashMap::new();
+
+        assert!(!rules.allows("com.wasmcloud.lattice.health_check_status", &mut counts));
+        assert!(rules.allows("com.wasmcloud.lattice.health_check_passed", &mut counts));
+        assert!(
+            rules.allows("com.wadm.manifest_published", &mut counts),
+            "wadm events should never be filtered"
+        );
+
+        let allowed = (0..7)
+            .map(|_| rules.allows("com.wasmcloud.lattice.host_heartbeat", &mut counts))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            allowed,
+            vec![true, false, false, true, false, false, true],
+            "Only every third heartbeat should be processed"
+        );
+
+        let filter =
+            EventFilter::new(rules.clone()).with_lattice("quiet", EventFilterRules::default());
+        assert_eq!(filter.rules_for("default"), &rules);
+        assert!(filter.rules_for("quiet").is_empty());
+    }
+
+    #[test]
+    fn can_parse_start_positions() {
+        assert_eq!("all".parse(), Ok(EventStartPosition::All));
+        assert_eq!("new".parse(), Ok(EventStartPosition::New));
+        let position: EventStartPosition = "2024-06-01T14:00:00+02:00".parse().unwrap();
+        assert_eq!(position.to_string(), "2024-06-01T12:00:00Z");
+        assert!(matches!(
+            position.deliver_policy().unwrap(),
+            DeliverPolicy::ByStartTime { start_time } if start_time.unix_timestamp() == 1717243200
+        ));
+        assert!("yesterday".parse::<EventStartPosition>().is_err());
     }
 }
@@ -1,21 +1,31 @@
 use std::fmt::Debug;
-use std::{collections::HashMap, marker::PhantomData, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap},
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use async_nats::jetstream::stream::Stream as NatsStream;
-use futures::{Stream, StreamExt};
+use futures::{FutureExt, Stream, StreamExt, TryStreamExt};
+use opentelemetry_metrics::KeyValue;
+use serde::{Deserialize, Serialize};
 use tokio::{
-    sync::{RwLock, Semaphore},
-    task::JoinHandle,
+    sync::{watch, RwLock, Semaphore},
+    task::{JoinError, JoinHandle, JoinSet},
 };
 use tracing::{error, instrument, trace, warn, Instrument};
+use wadm_types::api::ConsumerStatus;
 
 use crate::consumers::{LATTICE_METADATA_KEY, MULTITENANT_METADATA_KEY};
+use crate::crash::{self, CrashContext};
 
-use super::{CreateConsumer, ScopedMessage};
+use super::{scheduler::WorkScheduler, CreateConsumer, ScopedMessage};
 
 /// A convenience type for returning work results
 pub type WorkResult<T> = Result<T, WorkError>;
 type WorkHandles = Arc<RwLock<HashMap<String, JoinHandle<WorkResult<()>>>>>;
+type WorkStatsMap = Arc<Mutex<HashMap<String, WorkStats>>>;
 
 /// An error that describes possible work failures when performing actions based on incoming messages
 #[derive(Debug, thiserror::Error)]
@@ -37,6 +47,9 @@ pub enum WorkError {
     /// A catch all error for non-described errors that are not fatal
     #[error(transparent)]
     Other(Box<dyn std::error::Error + Send>),
+    /// The worker panicked. The message it was handling is nacked and a crash report is written
+    #[error("Worker panicked: {0}")]
+    Panicked(String),
 }
 
 impl WorkError {
@@ -68,6 +81,24 @@ pub trait Worker {
     /// that work should stop. Any worker MUST handle acking the message (or passing it to another
     /// worker). By default, when a [`ScopedMessage`] is dropped, it will nack it
     async fn do_work(&self, message: ScopedMessage<Self::Message>) -> WorkResult<()>;
+
+    /// Returns the application or host the message is for, if handling it only touches that
+    /// application or host. Messages with the same key are handled one at a time in the order they
+    /// were received, while messages with different keys are handled in parallel. Messages without
+    /// a key are handled on their own once all work before them is done. By default no messages
+    /// have a key, so they are all handled one at a time
+    fn work_key(&self, _message: &Self::Message) -> Option<WorkKey> {
+        None
+    }
+}
+
+/// What handling a message is scoped to. See [`Worker::work_key`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum WorkKey {
+    /// The message only touches the application with the given name
+    App(String),
+    /// The message only touches the host with the given ID, like a heartbeat
+    Host(String),
 }
 
 /// A trait used for dynamically creating workers.
@@ -85,6 +116,62 @@ pub trait WorkerCreator {
     ) -> anyhow::Result<Self::Output>;
 }
 
+/// Counters for the work done by a single consumer, used to report what was left behind on
+/// shutdown and for introspection
+#[derive(Debug, Default)]
+struct WorkStats {
+    lattice_id: String,
+    multitenant_prefix: Option<String>,
+    in_flight: usize,
+    nacked: u64,
+    last_failed: bool,
+    last_sequence: Option<u64>,
+    /// The number of messages for the consumer that haven't been acked, as of the last check
+    lag: Option<u64>,
+    /// The stream sequence last delivered to the consumer, as of the last check
+    checked_delivered: Option<u64>,
+}
+
+/// Work that was still being processed by a consumer when it was shut down. These messages are
+/// nacked and will be redelivered to the next wadm instance
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AbandonedWork {
+    /// The topic of the consumer the work came from
+    pub topic: String,
+    /// The lattice the work was for
+    pub lattice_id: String,
+    /// The number of messages that were in flight
+    pub messages: usize,
+}
+
+/// A summary of the state that consumers were left in when they were shut down
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Work that was abandoned mid-processing
+    pub abandoned: Vec<AbandonedWork>,
+    /// The total number of messages that failed processing and were nacked for redelivery over
+    /// the lifetime of the consumers
+    pub nacked_messages: u64,
+    /// Lattices that had abandoned work or whose most recent message failed. These may need a
+    /// catch-up reconcile once another instance picks them up
+    pub lattices_needing_reconcile: BTreeSet<String>,
+}
+
+impl ShutdownReport {
+    /// Combines another report into this one
+    pub fn merge(&mut self, other: ShutdownReport) {
+        self.abandoned.extend(other.abandoned);
+        self.nacked_messages += other.nacked_messages;
+        self.lattices_needing_reconcile
+            .extend(other.lattices_needing_reconcile);
+    }
+
+    /// Returns true if nothing was left behind
+    pub fn is_clean(&self) -> bool {
+        self.abandoned.is_empty() && self.lattices_needing_reconcile.is_empty()
+    }
+}
+
 /// A manager of a specific type of Consumer that handles giving out permits to work and managing
 /// per lattice consumers.
 ///
@@ -93,15 +180,33 @@ pub trait WorkerCreator {
 ///
 /// NOTE: We use a work permit semaphore pool here to make sure in large, multi-tenant deployments,
 /// we aren't trying to simultaneously handle every single lattice event and command consumer
-#[derive(Clone)]
-pub struct ConsumerManager<C> {
+pub struct ConsumerManager<C: CreateConsumer> {
     handles: WorkHandles,
+    stats: WorkStatsMap,
     permits: Arc<Semaphore>,
     stream: NatsStream,
+    options: C::Options,
+    /// Set to true once the manager starts draining, telling consumers to stop fetching messages
+    draining: Arc<watch::Sender<bool>>,
     phantom: PhantomData<C>,
 }
 
-impl<C> ConsumerManager<C> {
+// NOTE: Clone is implemented manually so that consumer types don't have to be clonable
+impl<C: CreateConsumer> Clone for ConsumerManager<C> {
+    fn clone(&self) -> Self {
+        ConsumerManager {
+            handles: self.handles.clone(),
+            stats: self.stats.clone(),
+            permits: self.permits.clone(),
+            stream: self.stream.clone(),
+            options: self.options.clone(),
+            draining: self.draining.clone(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<C: CreateConsumer> ConsumerManager<C> {
     /// Returns a new consumer manager set up to use the given permit pool. This meant to use a
     /// shared pool of permits with other consumer managers to manage the amount of simultaneous
     /// work, so the Semaphore must be wrapped in an [`Arc`].
@@ -115,6 +220,34 @@ impl<C> ConsumerManager<C> {
         worker_generator: F,
         multitenant: bool,
     ) -> ConsumerManager<C>
+    where
+        W: Worker + Send + Sync + 'static,
+        C: Stream<Item = Result<ScopedMessage<W::Message>, async_nats::Error>>
+            + CreateConsumer<Output = C>
+            + Send
+            + Unpin
+            + 'static,
+        F: WorkerCreator<Output = W>,
+    {
+        Self::new_with_options(
+            permit_pool,
+            stream,
+            worker_generator,
+            multitenant,
+            C::Options::default(),
+        )
+        .await
+    }
+
+    /// Same as [`ConsumerManager::new`], but passes the given options to every consumer this
+    /// manager creates
+    pub async fn new_with_options<W, F>(
+        permit_pool: Arc<Semaphore>,
+        stream: NatsStream,
+        worker_generator: F,
+        multitenant: bool,
+        options: C::Options,
+    ) -> ConsumerManager<C>
     where
         W: Worker + Send + Sync + 'static,
         C: Stream<Item = Result<ScopedMessage<W::Message>, async_nats::Error>>
@@ -126,8 +259,11 @@ impl<C> ConsumerManager<C> {
     {
         let mut manager = ConsumerManager {
             handles: Arc::new(RwLock::new(HashMap::default())),
+            stats: Arc::default(),
             permits: permit_pool,
             stream,
+            options,
+            draining: Arc::new(watch::channel(false).0),
             phantom: PhantomData,
         };
 
@@ -196,6 +332,25 @@ impl<C> ConsumerManager<C> {
         manager
     }
 
+    /// Returns a new consumer manager that doesn't start consumers for any existing durable
+    /// consumers. This is used when something else decides which lattices this process should
+    /// manage, like leader election
+    pub fn new_empty(
+        permit_pool: Arc<Semaphore>,
+        stream: NatsStream,
+        options: C::Options,
+    ) -> ConsumerManager<C> {
+        ConsumerManager {
+            handles: Arc::default(),
+            stats: Arc::default(),
+            permits: permit_pool,
+            stream,
+            options,
+            draining: Arc::new(watch::channel(false).0),
+            phantom: PhantomData,
+        }
+    }
+
     /// Starts a new consumer for the given topic. This method will only fail if there was an error
     /// setting up the consumer.
     ///
@@ -242,10 +397,39 @@ impl<C> ConsumerManager<C> {
             + Unpin
             + 'static,
     {
-        let consumer =
-            C::create(self.stream.clone(), topic, lattice_id, multitenant_prefix).await?;
+        let consumer = C::create(
+            self.stream.clone(),
+            topic,
+            lattice_id,
+            multitenant_prefix,
+            &self.options,
+            self.permits.clone(),
+        )
+        .await?;
         let permits = self.permits.clone();
-        Ok(tokio::spawn(work_fn(consumer, permits, worker).instrument(
+        let stats = WorkStatsHandle {
+            stats: self.stats.clone(),
+            topic: topic.to_owned(),
+        };
+        stats.update(|s| {
+            s.lattice_id = lattice_id.to_owned();
+            s.multitenant_prefix = multitenant_prefix.map(ToOwned::to_owned);
+        });
+        let draining = self.draining.subscribe();
+        let context = CrashContext {
+            worker_type: std::any::type_name::<W>().to_owned(),
+            topic: topic.to_owned(),
+            lattice_id: lattice_id.to_owned(),
+            ..Default::default()
+        };
+        // NOTE: Panics while handling a message are caught in the work function. This catches
+        // anything else, so that the consumer shows up as stopped and can be started again
+        let work = crash::capture(
+            context.clone(),
+            work_fn(consumer, permits, worker, stats, draining, context),
+        )
+        .map(|res| res.unwrap_or_else(|message| Err(WorkError::Panicked(message))));
+        Ok(tokio::spawn(work.instrument(
             tracing::info_span!("consumer_worker", %topic, worker_type = %std::any::type_name::<W>()),
         )))
     }
@@ -267,44 +451,464 @@ impl<C> ConsumerManager<C> {
             .unwrap_or(false)
     }
 
+    /// Returns the state of every consumer this manager has started, sorted by topic
+    pub async fn statuses(&self) -> Vec<ConsumerStatus> {
+        let running: HashMap<String, bool> = self
+            .handles
+            .read()
+            .await
+            .iter()
+            .map(|(topic, handle)| (topic.to_owned(), !handle.is_finished()))
+            .collect();
+        let stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        let mut statuses: Vec<ConsumerStatus> = stats
+            .iter()
+            .map(|(topic, stat)| ConsumerStatus {
+                topic: topic.to_owned(),
+                lattice_id: stat.lattice_id.clone(),
+                running: running.get(topic).copied().unwrap_or(false),
+                in_flight: stat.in_flight,
+                nacked: stat.nacked,
+                last_failed: stat.last_failed,
+                last_delivered_sequence: stat.last_sequence,
+                lag: stat.lag,
+            })
+            .collect();
+        statuses.sort_by(|a, b| a.topic.cmp(&b.topic));
+        statuses
+    }
+
+    /// Periodically checks on every consumer this manager has started until the manager starts
+    /// draining. Each check records the lag of the consumer (the messages on its subjects past its
+    /// ack floor) as a metric and recreates consumers whose pull loop has stopped, or that stalled
+    /// with messages waiting to be delivered (like after a JetStream leader change), so they don't
+    /// sit idle until wadm restarts. The given worker generator creates the workers for recreated
+    /// consumers
+    ///
+    /// This registers metrics with the global meter provider, so it should be called after any
+    /// provider is installed
+    pub async fn monitor<W, F>(&self, worker_generator: F, interval: Duration)
+    where
+        W: Worker + Send + Sync + 'static,
+        C: Stream<Item = Result<ScopedMessage<W::Message>, async_nats::Error>>
+            + CreateConsumer<Output = C>
+            + Send
+            + Unpin
+            + 'static,
+        F: WorkerCreator<Output = W>,
+    {
+        let meter = opentelemetry_metrics::global::meter("wadm");
+        let observed = self.stats.clone();
+        let _lag = meter
+            .u64_observable_gauge("wadm.consumer.lag")
+            .with_description("Number of messages for a consumer that haven't been acked yet")
+            .with_callback(move |observer| {
+                let stats = observed.lock().unwrap_or_else(|e| e.into_inner());
+                for (topic, stat) in stats.iter() {
+                    if let Some(lag) = stat.lag {
+                        observer.observe(
+                            lag,
+                            &[
+                                KeyValue::new("topic", topic.clone()),
+                                KeyValue::new("lattice_id", stat.lattice_id.clone()),
+                            ],
+                        );
+                    }
+                }
+            })
+            .init();
+        let recreated = meter
+            .u64_counter("wadm.consumer.recreated")
+            .with_description(
+                "Number of consumers that were recreated after they stopped or stalled",
+            )
+            .init();
+
+        let mut draining = self.draining.subscribe();
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // The first tick completes right away, and there is nothing to check before consumers ran
+        ticker.tick().await;
+        loop {
+            tokio::select! {
+                _ = draining.wait_for(|draining| *draining) => return,
+                _ = ticker.tick() => (),
+            }
+            for (topic, reason) in self.check_consumers().await {
+                let Some((lattice_id, multitenant_prefix)) = self
+                    .stats
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .get(&topic)
+                    .map(|stat| (stat.lattice_id.clone(), stat.multitenant_prefix.clone()))
+                else {
+                    continue;
+                };
+                warn!(%topic, %lattice_id, reason, "Recreating consumer");
+                let worker = match worker_generator
+                    .create(&lattice_id, multitenant_prefix.as_deref())
+                    .await
+                {
+                    Ok(worker) => worker,
+                    Err(e) => {
+                        error!(error = %e, %topic, %lattice_id, "Unable to create worker to recreate consumer, will try again");
+                        continue;
+                    }
+                };
+                let handle = match self
+                    .spawn_handler(&topic, &lattice_id, multitenant_prefix.as_deref(), worker)
+                    .await
+                {
+                    Ok(handle) => handle,
+                    Err(e) => {
+                        error!(error = %e, %topic, %lattice_id, "Unable to recreate consumer, will try again");
+                        continue;
+                    }
+                };
+                let mut handles = self.handles.write().await;
+                // The consumer could have been removed while this one was being created
+                match handles.get_mut(&topic) {
+                    Some(old) => std::mem::replace(old, handle).abort(),
+                    None => {
+                        handle.abort();
+                        continue;
+                    }
+                }
+                recreated.add(
+                    1,
+                    &[
+                        KeyValue::new("topic", topic.clone()),
+                        KeyValue::new("lattice_id", lattice_id),
+                        KeyValue::new("reason", reason),
+                    ],
+                );
+            }
+        }
+    }
+
+    /// Updates the lag of every consumer and returns the topics of the ones that need to be
+    /// recreated, along with why
+    async fn check_consumers(&self) -> Vec<(String, &'static str)> {
+        let running: HashMap<String, bool> = self
+            .handles
+            .read()
+            .await
+            .iter()
+            .map(|(topic, handle)| (topic.to_owned(), !handle.is_finished()))
+            .collect();
+        let infos: HashMap<String, LagCheck> =
+            match self.stream.consumers().try_collect::<Vec<_>>().await {
+                Ok(infos) => infos
+                    .into_iter()
+                    .map(|info| {
+                        (
+                            info.config.filter_subject,
+                            LagCheck {
+                                pending: info.num_pending,
+                                lag: info.num_pending + info.num_ack_pending as u64,
+                                delivered: info.delivered.stream_sequence,
+                            },
+                        )
+                    })
+                    .collect(),
+                Err(e) => {
+                    // Without consumer info, only consumers that stopped can be found
+                    warn!(error = %e, "Unable to get consumer info to check lag");
+                    return running
+                        .into_iter()
+                        .filter(|(_, running)| !running)
+                        .map(|(topic, _)| (topic, STOPPED))
+                        .collect();
+                }
+            };
+        // If every permit is in use, consumers can be waiting on a permit rather than stalled
+        let saturated = self.permits.available_permits() == 0;
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        running
+            .into_iter()
+            .filter_map(|(topic, running)| {
+                let stat = stats.entry(topic.clone()).or_default();
+                let check = infos.get(&topic);
+                let reason = recreate_reason(running, check, stat, saturated);
+                stat.lag = check.map(|check| check.lag);
+                stat.checked_delivered = check.map(|check| check.delivered);
+                reason.map(|reason| (topic, reason))
+            })
+            .collect()
+    }
+
+    /// Stops the consumer for the given topic, if one is running. Any work in progress is abandoned
+    /// and its messages are nacked so they will be redelivered. The durable consumer is left in
+    /// place so the consumer can be added again later and pick up where it left off.
+    ///
+    /// Returns whether a consumer was running for the topic
+    pub async fn remove_for_lattice(&self, topic: &str) -> bool {
+        let Some(handle) = self.handles.write().await.remove(topic) else {
+            return false;
+        };
+        handle.abort();
+        // Wait for the task to stop so the worker (and anything it holds onto) is dropped
+        let _ = handle.await;
+        self.stats
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(topic);
+        true
+    }
+
+    /// Stops the consumers for the given lattice ID in every account, like
+    /// [`ConsumerManager::remove_for_lattice`]. This is used when something that is tracked by
+    /// lattice ID alone, like a lease, is lost. Returns the number of consumers that were stopped
+    pub async fn remove_all_for_lattice(&self, lattice_id: &str) -> usize {
+        let topics: Vec<String> = self
+            .stats
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|(_, stats)| stats.lattice_id == lattice_id)
+            .map(|(topic, _)| topic.clone())
+            .collect();
+        let mut removed = 0;
+        for topic in topics {
+            if self.remove_for_lattice(&topic).await {
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Stops all consumers, abandoning any work currently in progress, and returns a report of what
+    /// was left behind. Abandoned messages are nacked so they are redelivered to another instance
+    pub async fn shutdown(&self) -> ShutdownReport {
+        let handles: Vec<_> = self.handles.write().await.drain().collect();
+        for (topic, handle) in handles {
+            handle.abort();
+            // Wait for the task to actually stop so the in-flight counts are final. We expect a
+            // cancellation error here
+            if let Ok(Err(e)) = handle.await {
+                warn!(%topic, error = %e, "Consumer had already stopped with an error");
+            }
+        }
+
+        self.report()
+    }
+
+    /// Gracefully stops all consumers. Consumers stop fetching new messages right away and work
+    /// already in progress is given until the timeout to finish. Any consumer still working after
+    /// that is stopped like in [`ConsumerManager::shutdown`], nacking its messages. Returns a
+    /// report of what was left behind
+    pub async fn drain(&self, timeout: Duration) -> ShutdownReport {
+        self.draining.send_replace(true);
+        let deadline = tokio::time::Instant::now() + timeout;
+        let handles: Vec<_> = self.handles.write().await.drain().collect();
+        for (topic, mut handle) in handles {
+            match tokio::time::timeout_at(deadline, &mut handle).await {
+                Ok(Ok(Err(e))) => {
+                    warn!(%topic, error = %e, "Consumer had already stopped with an error")
+                }
+                Ok(_) => trace!(%topic, "Consumer drained"),
+                Err(_) => {
+                    warn!(%topic, "Consumer didn't finish its work before the shutdown timeout, abandoning it");
+                    handle.abort();
+                    let _ = handle.await;
+                }
+            }
+        }
+        self.report()
+    }
+
+    /// Builds a report of the work that was in progress or failed for every consumer
+    fn report(&self) -> ShutdownReport {
+        let stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        let mut report = ShutdownReport::default();
+        for (topic, stat) in stats.iter() {
+            report.nacked_messages += stat.nacked;
+            if stat.in_flight > 0 {
+                report.abandoned.push(AbandonedWork {
+                    topic: topic.to_owned(),
+                    lattice_id: stat.lattice_id.clone(),
+                    messages: stat.in_flight,
+                });
+            }
+            if stat.in_flight > 0 || stat.last_failed {
+                report
+                    .lattices_needing_reconcile
+                    .insert(stat.lattice_id.clone());
+            }
+        }
+        report.abandoned.sort_by(|a, b| a.topic.cmp(&b.topic));
+        report
+    }
+
     // NOTE(thomastaylor312): We could add a supervisory element to this by starting a notifier
     // thread that can restart work if a fatal error is received (or a join handle finishes), but
     // that is not necessary now
 }
 
-async fn work_fn<C, W>(mut consumer: C, permits: Arc<Semaphore>, worker: W) -> WorkResult<()>
+const STOPPED: &str = "stopped";
+const STALLED: &str = "stalled";
+const MISSING: &str = "missing";
+
+/// The state of a consumer as reported by JetStream
+#[derive(Debug, Clone, Copy)]
+struct LagCheck {
+    /// Messages that haven't been delivered yet
+    pending: u64,
+    /// Messages that haven't been acked yet, including ones that were delivered
+    lag: u64,
+    /// The last stream sequence delivered
+    delivered: u64,
+}
+
+/// Returns why a consumer needs to be recreated, if it does. A consumer is stalled if it has
+/// messages waiting to be delivered, isn't working on anything and hasn't been delivered anything
+/// since the last check
+fn recreate_reason(
+    running: bool,
+    check: Option<&LagCheck>,
+    stat: &WorkStats,
+    saturated: bool,
+) -> Option<&'static str> {
+    if !running {
+        return Some(STOPPED);
+    }
+    let Some(check) = check else {
+        // The durable consumer is gone, so the pull loop won't get anything until it is created
+        // again
+        return Some(MISSING);
+    };
+    let stalled = !saturated
+        && check.pending > 0
+        && stat.in_flight == 0
+        && stat.checked_delivered == Some(check.delivered);
+    stalled.then_some(STALLED)
+}
+
+/// A handle to the stats for a single consumer topic
+#[derive(Clone)]
+struct WorkStatsHandle {
+    stats: WorkStatsMap,
+    topic: String,
+}
+
+impl WorkStatsHandle {
+    fn update(&self, f: impl FnOnce(&mut WorkStats)) {
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        f(stats.entry(self.topic.clone()).or_default())
+    }
+}
+
+async fn work_fn<C, W>(
+    mut consumer: C,
+    permits: Arc<Semaphore>,
+    worker: W,
+    stats: WorkStatsHandle,
+    mut draining: watch::Receiver<bool>,
+    context: CrashContext,
+) -> WorkResult<()>
 where
-    W: Worker + Send,
+    W: Worker + Send + Sync + 'static,
+    W::Message: 'static,
     C: Stream<Item = Result<ScopedMessage<W::Message>, async_nats::Error>> + Unpin,
 {
+    let worker = Arc::new(worker);
+    let scheduler = WorkScheduler::default();
+    let mut in_flight = JoinSet::new();
     loop {
-        // Get next value from stream, returning error if the consumer stopped
-        let res = consumer.next().await.ok_or(WorkError::ConsumerStopped)?;
+        // Get next value from stream, returning error if the consumer stopped. Once the manager is
+        // draining, we stop fetching. Messages the consumer already pulled but didn't hand out yet
+        // are redelivered once their ack wait expires
+        let res = tokio::select! {
+            biased;
+            _ = async {
+                // If the manager is gone without draining, there is nothing left to wait on
+                if draining.wait_for(|draining| *draining).await.is_err() {
+                    futures::future::pending::<()>().await
+                }
+            } => {
+                trace!("Consumer is draining, no longer fetching messages");
+                while let Some(res) = in_flight.join_next().await {
+                    handle_work_result(res)?;
+                }
+                return Ok(());
+            }
+            Some(res) = in_flight.join_next(), if !in_flight.is_empty() => {
+                handle_work_result(res)?;
+                continue;
+            }
+            res = consumer.next() => res.ok_or(WorkError::ConsumerStopped)?,
+        };
 
         // Grab a permit to do some work. This will only return errors if the pool is closed
         trace!("Getting work permit");
-        let _permit = permits.acquire().await?;
+        let permit = permits.clone().acquire_owned().await?;
         trace!("Received work permit, attempting to pull from consumer");
-        let res = match res {
+        match res {
             Ok(msg) => {
                 trace!(message = ?msg, "Got message from consumer");
-                worker.do_work(msg).await
+                // If this task is aborted while working, the in flight count is left incremented so
+                // it shows up as abandoned work on shutdown
+                let sequence = msg.stream_sequence();
+                stats.update(|s| {
+                    s.in_flight += 1;
+                    s.last_sequence = sequence.or(s.last_sequence);
+                });
+                let context = CrashContext {
+                    lattice_id: msg.lattice_id.clone(),
+                    subject: msg.subject(),
+                    sequence,
+                    ..context.clone()
+                };
+                // Continue the trace the message was sent with, so everything done while handling
+                // it is part of the same trace
+                let span = tracing::info_span!("handle_message", lattice_id = %msg.lattice_id);
+                if let Some(trace_context) = msg.trace_context() {
+                    trace_context.attach(&span);
+                }
+                // Waiting on the scheduler here, before the next message is pulled, keeps messages
+                // for the same application in order
+                let guard = scheduler.acquire(worker.work_key(msg.as_ref())).await;
+                let worker = worker.clone();
+                let stats = stats.clone();
+                in_flight.spawn(async move {
+                    let _permit = permit;
+                    let _guard = guard;
+                    // If the worker panics, the message is dropped while unwinding, which nacks it
+                    // so it is redelivered. The consumer keeps going with the next message
+                    let res = crash::capture(context, worker.do_work(msg).instrument(span))
+                        .await
+                        .unwrap_or_else(|message| Err(WorkError::Panicked(message)));
+                    stats.update(|s| {
+                        s.in_flight -= 1;
+                        s.last_failed = res.is_err();
+                        if res.is_err() {
+                            s.nacked += 1;
+                        }
+                    });
+                    res
+                });
             }
             Err(e) => {
                 error!(error = %e, "Got error from stream when reading from consumer. Will try again");
-                continue;
             }
-        };
-        match res {
-            // Return fatal errors if they occur
-            Err(e) if matches!(e, WorkError::Fatal(_)) => return Err(e),
-            // For the rest of the errors, right now we just log. Could do nicer retry behavior as this evolves
-            Err(e) => error!(error = ?e, "Got error from worker"),
-            _ => (),
         }
     }
 }
 
+/// Handles the result of work on a single message, returning fatal errors so the consumer stops
+fn handle_work_result(res: Result<WorkResult<()>, JoinError>) -> WorkResult<()> {
+    match res.unwrap_or_else(|e| Err(WorkError::Panicked(e.to_string()))) {
+        // Return fatal errors if they occur
+        Err(e) if matches!(e, WorkError::Fatal(_)) => Err(e),
+        // For the rest of the errors, right now we just log. Could do nicer retry behavior as this evolves
+        Err(e) => {
+            error!(error = ?e, "Got error from worker");
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
 /// Extracts the lattice ID and multitenant prefix from a consumer name in the form of either:
 /// 1. <consumer_prefix>-<lattice_prefix>_<multitenant_prefix>
 /// 2. <consumer_prefix>-<lattice_prefix>
@@ -325,7 +929,82 @@ fn extract_lattice_and_multitenant(consumer_name: &str) -> (Option<String>, Opti
 
 #[cfg(test)]
 mod test {
-    use super::extract_lattice_and_multitenant;
+    use super::{
+        extract_lattice_and_multitenant, recreate_reason, AbandonedWork, LagCheck, ShutdownReport,
+        WorkStats, MISSING, STALLED, STOPPED,
+    };
+
+    #[test]
+    fn can_find_consumers_to_recreate() {
+        let check = LagCheck {
+            pending: 3,
+            lag: 4,
+            delivered: 10,
+        };
+        let stat = WorkStats {
+            checked_delivered: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(recreate_reason(false, None, &stat, false), Some(STOPPED));
+        assert_eq!(recreate_reason(true, None, &stat, false), Some(MISSING));
+        assert_eq!(
+            recreate_reason(true, Some(&check), &stat, false),
+            Some(STALLED),
+            "A consumer that wasn't delivered anything with messages waiting should be stalled"
+        );
+        assert_eq!(
+            recreate_reason(true, Some(&check), &stat, true),
+            None,
+            "Consumers can't be stalled while waiting on permits"
+        );
+
+        let working = WorkStats {
+            in_flight: 1,
+            ..stat
+        };
+        assert_eq!(recreate_reason(true, Some(&check), &working, false), None);
+        let progressed = WorkStats {
+            checked_delivered: Some(8),
+            ..Default::default()
+        };
+        assert_eq!(
+            recreate_reason(true, Some(&check), &progressed, false),
+            None
+        );
+        let caught_up = LagCheck {
+            pending: 0,
+            ..check
+        };
+        assert_eq!(
+            recreate_reason(true, Some(&caught_up), &WorkStats::default(), false),
+            None
+        );
+    }
+
+    #[test]
+    fn can_merge_shutdown_reports() {
+        let mut report = ShutdownReport {
+            nacked_messages: 2,
+            lattices_needing_reconcile: ["default".to_string()].into(),
+            ..Default::default()
+        };
+        report.merge(ShutdownReport::default());
+        assert!(!report.is_clean());
+
+        report.merge(ShutdownReport {
+            abandoned: vec![AbandonedWork {
+                topic: "wadm.cmd.other".to_string(),
+                lattice_id: "other".to_string(),
+                messages: 1,
+            }],
+            nacked_messages: 1,
+            lattices_needing_reconcile: ["default".to_string(), "other".to_string()].into(),
+        });
+        assert_eq!(report.nacked_messages, 3);
+        assert_eq!(report.abandoned.len(), 1);
+        assert_eq!(report.lattices_needing_reconcile.len(), 2);
+        assert!(ShutdownReport::default().is_clean());
+    }
 
     #[test]
     fn can_extract_lattice_and_multitenant() {
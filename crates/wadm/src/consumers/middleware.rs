@@ -0,0 +1,280 @@
+//! Middleware for [`Worker`]s. Middleware runs before and after a worker handles each message, so
+//! concerns that apply to every message (like metrics, dedup or rate limiting) can be added to any
+//! worker without changing it
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use opentelemetry_metrics::{
+    metrics::{Counter, Histogram},
+    KeyValue,
+};
+
+use super::{
+    manager::{WorkKey, WorkResult, Worker},
+    ScopedMessage,
+};
+
+/// Whether a message should keep going down the middleware chain to the worker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Pass the message on to the next middleware, or to the worker
+    Continue,
+    /// Stop handling the message. The middleware that stops a message should ack or nack it, as
+    /// anything left unacked is nacked when the message is dropped
+    Stop,
+}
+
+/// The metadata of a message, given to middleware once the message has been handled
+#[derive(Debug, Clone)]
+pub struct MessageMetadata {
+    /// The lattice the message is for
+    pub lattice_id: String,
+    /// The subject the message was published on
+    pub subject: Option<String>,
+    /// The sequence of the message in its stream
+    pub stream_sequence: Option<u64>,
+    /// How many times the message has been delivered, including this delivery
+    pub delivery_count: Option<u64>,
+    /// When the message stops being valid, if the publisher set an expiry
+    pub expires_at: Option<DateTime<Utc>>,
+    /// When handling the message started
+    pub started: Instant,
+}
+
+impl<T> ScopedMessage<T> {
+    /// Returns the metadata of this message
+    pub fn metadata(&self) -> MessageMetadata {
+        MessageMetadata {
+            lattice_id: self.lattice_id.clone(),
+            subject: self.subject(),
+            stream_sequence: self.stream_sequence(),
+            delivery_count: self.delivery_count(),
+            expires_at: self.expires_at,
+            started: Instant::now(),
+        }
+    }
+}
+
+/// Hooks that run around a worker handling a message
+#[async_trait::async_trait]
+pub trait Middleware<M: Send>: Send + Sync {
+    /// Runs before the message is handled. Returning [`Flow::Stop`] or an error skips the rest of
+    /// the chain and the worker. By default this passes every message on
+    async fn before(&self, _message: &mut ScopedMessage<M>) -> WorkResult<Flow> {
+        Ok(Flow::Continue)
+    }
+
+    /// Runs after the message was handled, with the error handling it returned, if any. This runs
+    /// for every middleware that passed the message on, in reverse order. A message that was
+    /// stopped has no error. By default this does nothing
+    async fn after(&self, _metadata: &MessageMetadata, _error: Option<&str>) {}
+}
+
+/// A worker wrapped in a chain of middleware. Middleware runs in the order it was added
+pub struct MiddlewareWorker<W: Worker> {
+    worker: W,
+    middleware: Vec<Arc<dyn Middleware<W::Message>>>,
+}
+
+impl<W: Worker> MiddlewareWorker<W> {
+    /// Wraps the given worker without any middleware
+    pub fn new(worker: W) -> MiddlewareWorker<W> {
+        MiddlewareWorker {
+            worker,
+            middleware: Vec::new(),
+        }
+    }
+
+    /// Adds middleware to the end of the chain
+    pub fn with_middleware(
+        mut self,
+        middleware: impl Middleware<W::Message> + 'static,
+    ) -> MiddlewareWorker<W> {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Adds middleware that is shared with other workers to the end of the chain
+    pub fn with_shared_middleware(
+        mut self,
+        middleware: Arc<dyn Middleware<W::Message>>,
+    ) -> MiddlewareWorker<W> {
+        self.middleware.push(middleware);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl<W> Worker for MiddlewareWorker<W>
+where
+    W: Worker + Send + Sync,
+{
+    type Message = W::Message;
+
+    async fn do_work(&self, mut message: ScopedMessage<Self::Message>) -> WorkResult<()> {
+        let metadata = message.metadata();
+        let mut ran = 0;
+        let mut res = Ok(Flow::Continue);
+        for middleware in self.middleware.iter() {
+            res = middleware.before(&mut message).await;
+            if !matches!(res, Ok(Flow::Continue)) {
+                break;
+            }
+            ran += 1;
+        }
+        let res = match res {
+            Ok(Flow::Continue) => self.worker.do_work(message).await,
+            Ok(Flow::Stop) => Ok(()),
+            Err(e) => Err(e),
+        };
+        let error = res.as_ref().err().map(ToString::to_string);
+        for middleware in self.middleware[..ran].iter().rev() {
+            middleware.after(&metadata, error.as_deref()).await;
+        }
+        res
+    }
+
+    fn work_key(&self, message: &Self::Message) -> Option<WorkKey> {
+        self.worker.work_key(message)
+    }
+}
+
+/// Middleware that records how many messages a worker handled and how long each one took
+pub struct WorkMetrics {
+    handled: Counter<u64>,
+    duration: Histogram<f64>,
+    worker: &'static str,
+}
+
+impl WorkMetrics {
+    /// Creates middleware that records metrics for the given kind of worker, like `events`
+    ///
+    /// This registers metrics with the global meter provider, so it should be called after any
+    /// provider is installed
+    pub fn new(worker: &'static str) -> WorkMetrics {
+        let meter = opentelemetry_metrics::global::meter("wadm");
+        WorkMetrics {
+            handled: meter
+                .u64_counter("wadm.worker.messages")
+                .with_description("Number of messages handled by a worker")
+                .init(),
+            duration: meter
+                .f64_histogram("wadm.worker.duration")
+                .with_description("Time taken to handle a message, in seconds")
+                .init(),
+            worker,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Send> Middleware<M> for WorkMetrics {
+    async fn after(&self, metadata: &MessageMetadata, error: Option<&str>) {
+        let attributes = [
+            KeyValue::new("worker", self.worker),
+            KeyValue::new("lattice_id", metadata.lattice_id.clone()),
+            KeyValue::new("success", error.is_none()),
+        ];
+        self.handled.add(1, &attributes);
+        self.duration
+            .record(metadata.started.elapsed().as_secs_f64(), &attributes);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    struct RecordingWorker {
+        handled: Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Worker for RecordingWorker {
+        type Message = String;
+
+        async fn do_work(&self, message: ScopedMessage<String>) -> WorkResult<()> {
+            self.handled.lock().unwrap().push(message.inner.clone());
+            Ok(())
+        }
+    }
+
+    /// Stops duplicate messages, recording every hook that runs
+    struct Dedup {
+        name: &'static str,
+        seen: Mutex<Vec<String>>,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Middleware<String> for Dedup {
+        async fn before(&self, message: &mut ScopedMessage<String>) -> WorkResult<Flow> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{} before {}", self.name, message.inner));
+            let mut seen = self.seen.lock().unwrap();
+            if seen.contains(&message.inner) {
+                return Ok(Flow::Stop);
+            }
+            seen.push(message.inner.clone());
+            Ok(Flow::Continue)
+        }
+
+        async fn after(&self, metadata: &MessageMetadata, _error: Option<&str>) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{} after {}", self.name, metadata.lattice_id));
+        }
+    }
+
+    fn message(inner: &str) -> ScopedMessage<String> {
+        ScopedMessage {
+            lattice_id: "default".to_string(),
+            inner: inner.to_string(),
+            acker: None,
+            expires_at: None,
+            trace_context: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_middleware_around_worker() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let dedup = |name| Dedup {
+            name,
+            seen: Mutex::default(),
+            calls: calls.clone(),
+        };
+        let worker = MiddlewareWorker::new(RecordingWorker {
+            handled: Mutex::default(),
+        })
+        .with_middleware(dedup("outer"))
+        .with_middleware(dedup("inner"))
+        .with_middleware(WorkMetrics::new("test"));
+
+        worker.do_work(message("one")).await.unwrap();
+        assert_eq!(
+            calls.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            vec![
+                "outer before one",
+                "inner before one",
+                "inner after default",
+                "outer after default"
+            ]
+        );
+
+        // The outer middleware stops the message, so nothing after it runs
+        worker.do_work(message("one")).await.unwrap();
+        assert_eq!(
+            calls.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            vec!["outer before one"]
+        );
+        assert_eq!(*worker.worker.handled.lock().unwrap(), vec!["one"]);
+    }
+}
@@ -3,15 +3,24 @@
 
 use std::fmt::Debug;
 use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
 use std::time::Duration;
 
 use async_nats::jetstream::{AckKind, Message};
 use async_nats::Error as NatsError;
+use chrono::{DateTime, Utc};
+use tokio::sync::Semaphore;
 use tracing::{error, warn};
 
+use crate::trace_context::TraceContext;
+
+mod batch;
 mod commands;
 mod events;
 pub mod manager;
+pub mod middleware;
+pub mod quarantine;
+mod scheduler;
 
 /// The default time given for a command to ack. This is longer than events due to the possible need for more processing time
 pub const DEFAULT_ACK_TIME: Duration = Duration::from_secs(2);
@@ -37,6 +46,10 @@ pub struct ScopedMessage<T> {
     pub(crate) inner: T,
     // Wrapped in an option so we only do it once
     pub(crate) acker: Option<Message>,
+    /// When the message stops being valid, if the publisher set an expiry
+    pub(crate) expires_at: Option<DateTime<Utc>>,
+    /// The trace context the message was sent with, if any
+    pub(crate) trace_context: Option<TraceContext>,
 }
 
 impl<T> ScopedMessage<T> {
@@ -87,6 +100,50 @@ impl<T> ScopedMessage<T> {
         }
     }
 
+    /// Returns when this message stops being valid, if the publisher set an expiry
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at
+    }
+
+    /// Returns whether this message has expired and should be discarded instead of acted on
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(Utc::now())
+    }
+
+    /// Returns whether this message has expired as of the given time
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Returns the trace context the message was sent with, if any
+    pub fn trace_context(&self) -> Option<&TraceContext> {
+        self.trace_context.as_ref()
+    }
+
+    /// Returns the subject the message was published on. Returns `None` if the message was already
+    /// acked
+    pub(crate) fn subject(&self) -> Option<String> {
+        self.acker.as_ref().map(|msg| msg.subject.to_string())
+    }
+
+    /// Returns the sequence of the message in its stream. Returns `None` if the message was already
+    /// acked or if it wasn't delivered by JetStream
+    pub(crate) fn stream_sequence(&self) -> Option<u64> {
+        self.acker
+            .as_ref()
+            .and_then(|msg| msg.info().ok())
+            .map(|info| info.stream_sequence)
+    }
+
+    /// Returns how many times this message has been delivered, including this delivery. Returns
+    /// `None` if the message was already acked or if it wasn't delivered by JetStream
+    pub(crate) fn delivery_count(&self) -> Option<u64> {
+        self.acker
+            .as_ref()
+            .and_then(|msg| msg.info().ok())
+            .map(|info| info.delivered.max(0) as u64)
+    }
+
     /// Nacks this Event. This should be called if there was an error when processing. By default,
     /// this is called when a [`ScopedMessage`] is dropped. Calling this again is a noop.
     ///
@@ -98,6 +155,38 @@ impl<T> ScopedMessage<T> {
             self.acker = None;
         }
     }
+
+    /// Nacks this message, asking for it to be redelivered once the given delay has passed. Use
+    /// this for failures that are likely to go away, like a host that is busy. Calling this again
+    /// is a noop.
+    ///
+    /// Like [`nack`](Self::nack), this doesn't return an error, since the message will be
+    /// redelivered once its ack wait expires anyway
+    pub async fn nack_with_delay(&mut self, delay: Duration) {
+        if let Err(e) = self.custom_ack(AckKind::Nak(Some(delay))).await {
+            error!(error = %e, "Error when nacking message with delay");
+            self.acker = None;
+        }
+    }
+
+    /// Tells the server that this message is still being worked on, resetting its ack wait so it
+    /// isn't redelivered while work that takes longer than the ack wait is in progress
+    pub async fn in_progress(&self) -> Result<(), NatsError> {
+        match self.acker.as_ref() {
+            Some(msg) => msg.ack_with(AckKind::Progress).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Terminates this message so it is never redelivered, no matter how many deliveries it has
+    /// left. Use this for failures that retrying can't fix, like a message that can't be acted on.
+    /// Calling this again (or after acking or nacking) is a noop.
+    ///
+    /// Returns the underlying NATS error, if any. If an error occurs, the message is redelivered
+    /// once its ack wait expires
+    pub async fn term(&mut self) -> Result<(), NatsError> {
+        self.custom_ack(AckKind::Term).await
+    }
 }
 
 impl<T> Drop for ScopedMessage<T> {
@@ -156,12 +245,17 @@ impl<T: Debug> Debug for ScopedMessage<T> {
 #[async_trait::async_trait]
 pub trait CreateConsumer {
     type Output: Unpin;
+    /// Additional configuration used when creating consumers, such as event filtering
+    type Options: Clone + Default + Send + Sync + 'static;
 
-    /// Create a type of the specified `Output`
+    /// Create a type of the specified `Output`. Messages are pulled in batches sized by the
+    /// permits available in the given work pool, and are kept in progress until they are handed out
     async fn create(
         stream: async_nats::jetstream::stream::Stream,
         topic: &str,
         lattice_id: &str,
         multitenant_prefix: Option<&str>,
+        options: &Self::Options,
+        permits: Arc<Semaphore>,
     ) -> Result<Self::Output, NatsError>;
 }
@@ -0,0 +1,212 @@
+//! Quarantine for poison messages. Messages that can't be decoded, or that fail on every delivery,
+//! are parked in a quarantine stream along with the error instead of being redelivered until they
+//! run out of deliveries, so a single bad message can't hold up the messages behind it
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_nats::jetstream::{AckKind, Message};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{error, instrument, warn};
+
+use super::{
+    manager::WorkResult,
+    middleware::{Flow, MessageMetadata, Middleware},
+    ScopedMessage,
+};
+use crate::publisher::Publisher;
+
+/// The default number of deliveries a message gets before it is quarantined
+pub const DEFAULT_MAX_DELIVERIES: u64 = 3;
+
+/// A message that couldn't be handled, along with the error it failed with
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QuarantinedMessage {
+    /// The lattice the message was for
+    pub lattice_id: String,
+    /// The subject the message was published on
+    pub subject: String,
+    /// The sequence of the message in the stream it was consumed from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_sequence: Option<u64>,
+    /// The number of times the message was delivered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deliveries: Option<u64>,
+    /// The error from decoding the message or from the last time it was handled
+    pub error: String,
+    /// The payload of the message. Payloads that aren't valid UTF-8 have the invalid bytes replaced
+    pub payload: String,
+    /// When the message was quarantined
+    pub quarantined_at: DateTime<Utc>,
+}
+
+impl QuarantinedMessage {
+    fn new(lattice_id: &str, msg: &Message, error: &str) -> QuarantinedMessage {
+        let info = msg.info().ok();
+        QuarantinedMessage {
+            lattice_id: lattice_id.to_owned(),
+            subject: msg.subject.to_string(),
+            stream_sequence: info.as_ref().map(|info| info.stream_sequence),
+            deliveries: info.as_ref().map(|info| info.delivered.max(0) as u64),
+            error: error.to_owned(),
+            payload: String::from_utf8_lossy(&msg.payload).into_owned(),
+            quarantined_at: Utc::now(),
+        }
+    }
+}
+
+/// Parks poison messages on a quarantine topic. Consumers quarantine messages they can't decode,
+/// and as [`Middleware`] this quarantines messages that fail on their last delivery. Quarantined
+/// messages are terminated so they are never redelivered. Cloning is cheap and clones share the
+/// messages on their last delivery
+#[derive(Clone)]
+pub struct Quarantine {
+    publisher: Arc<dyn Publisher + Send + Sync>,
+    topic_prefix: String,
+    max_deliveries: u64,
+    /// Messages on their last delivery that are being handled, keyed by stream sequence
+    last_deliveries: Arc<Mutex<HashMap<u64, Message>>>,
+}
+
+impl Quarantine {
+    /// Creates a quarantine that publishes with the given publisher. Messages are quarantined on
+    /// `{topic_prefix}.{lattice_id}` once they have failed [`DEFAULT_MAX_DELIVERIES`] times
+    pub fn new(
+        publisher: impl Publisher + Send + Sync + 'static,
+        topic_prefix: &str,
+    ) -> Quarantine {
+        Quarantine {
+            publisher: Arc::new(publisher),
+            topic_prefix: topic_prefix.trim_end_matches('.').to_owned(),
+            max_deliveries: DEFAULT_MAX_DELIVERIES,
+            last_deliveries: Arc::default(),
+        }
+    }
+
+    /// Sets the number of deliveries a message gets before it is quarantined
+    pub fn with_max_deliveries(mut self, max_deliveries: u64) -> Quarantine {
+        self.max_deliveries = max_deliveries.max(1);
+        self
+    }
+
+    /// Returns the number of deliveries a message gets before it is quarantined. Consumers should
+    /// deliver messages at least this many times
+    pub fn max_deliveries(&self) -> u64 {
+        self.max_deliveries
+    }
+
+    /// Returns the topic messages for the given lattice are quarantined on
+    pub fn topic(&self, lattice_id: &str) -> String {
+        format!("{}.{lattice_id}", self.topic_prefix)
+    }
+
+    /// Publishes the message with the error it failed with, then terminates it so it is never
+    /// redelivered. If it couldn't be published, the message is left to be redelivered
+    #[instrument(level = "debug", skip(self, msg))]
+    pub async fn park(&self, lattice_id: &str, msg: &Message, error: &str) -> Result<()> {
+        let quarantined = QuarantinedMessage::new(lattice_id, msg, error);
+        self.publisher
+            .publish(
+                serde_json::to_vec(&quarantined)?,
+                Some(&self.topic(lattice_id)),
+            )
+            .await?;
+        warn!(subject = %quarantined.subject, sequence = ?quarantined.stream_sequence, %error, "Quarantined message");
+        msg.ack_with(AckKind::Term)
+            .await
+            .map_err(|e| anyhow::anyhow!("Unable to terminate quarantined message: {e}"))
+    }
+
+    /// Quarantines a message that couldn't be decoded, logging any failure. Messages that can't be
+    /// quarantined are nacked so they are redelivered
+    pub(crate) async fn park_undecodable(&self, lattice_id: &str, msg: Message, error: &str) {
+        if let Err(e) = self.park(lattice_id, &msg, error).await {
+            error!(error = %e, "Unable to quarantine message that couldn't be decoded");
+            if let Err(e) = msg.ack_with(AckKind::Nak(None)).await {
+                warn!(error = %e, "Unable to nack message that couldn't be quarantined");
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Send> Middleware<M> for Quarantine {
+    async fn before(&self, message: &mut ScopedMessage<M>) -> WorkResult<Flow> {
+        if let (Some(msg), Some(sequence)) = (message.acker.as_ref(), message.stream_sequence()) {
+            if message.delivery_count().unwrap_or(1) >= self.max_deliveries {
+                self.last_deliveries
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(sequence, msg.clone());
+            }
+        }
+        Ok(Flow::Continue)
+    }
+
+    async fn after(&self, metadata: &MessageMetadata, error: Option<&str>) {
+        let Some(msg) = metadata.stream_sequence.and_then(|sequence| {
+            self.last_deliveries
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&sequence)
+        }) else {
+            return;
+        };
+        if let Some(error) = error {
+            if let Err(e) = self.park(&metadata.lattice_id, &msg, error).await {
+                error!(error = %e, "Unable to quarantine message that failed on its last delivery");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::test_util::NoopPublisher;
+
+    #[tokio::test]
+    async fn only_tracks_jetstream_messages_on_their_last_delivery() {
+        let quarantine = Quarantine::new(NoopPublisher, "wadm.quarantine.").with_max_deliveries(0);
+        assert_eq!(
+            quarantine.max_deliveries(),
+            1,
+            "Messages should always be delivered at least once"
+        );
+        assert_eq!(quarantine.topic("default"), "wadm.quarantine.default");
+
+        // Messages that weren't delivered by JetStream can't be redelivered or quarantined, so
+        // they should pass straight through
+        let mut message = ScopedMessage {
+            lattice_id: "default".to_string(),
+            inner: "event".to_string(),
+            acker: None,
+            expires_at: None,
+            trace_context: None,
+        };
+        let flow = Middleware::<String>::before(&quarantine, &mut message)
+            .await
+            .unwrap();
+        assert_eq!(flow, Flow::Continue);
+        assert!(quarantine.last_deliveries.lock().unwrap().is_empty());
+
+        Middleware::<String>::after(
+            &quarantine,
+            &MessageMetadata {
+                lattice_id: "default".to_string(),
+                subject: None,
+                stream_sequence: Some(1),
+                delivery_count: Some(1),
+                expires_at: None,
+                started: Instant::now(),
+            },
+            Some("boom"),
+        )
+        .await;
+        assert!(quarantine.last_deliveries.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,119 @@
+//! Scheduling of the work done by a single consumer. Work for the same application or host is done
+//! one message at a time, in order, while work for different applications and hosts can happen in
+//! parallel so a slow reconcile of one application doesn't hold up the rest of the lattice
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::{
+    Mutex as AsyncMutex, OwnedMutexGuard, OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock,
+};
+
+use super::manager::WorkKey;
+
+/// Hands out the right to work on a message. Every consumer is scoped to a single lattice, so a key
+/// only needs to name the application or host. Work without a key (like a link being set) can
+/// touch anything in the lattice, so it waits for all other work to finish and nothing else runs
+/// until it is done.
+///
+/// Guards must be acquired in the order messages are received, one at a time, which is what keeps
+/// messages for the same key in order
+#[derive(Default)]
+pub(crate) struct WorkScheduler {
+    lattice: Arc<RwLock<()>>,
+    keys: Mutex<HashMap<WorkKey, Arc<AsyncMutex<()>>>>,
+}
+
+/// Held for as long as work on a message is in progress
+pub(crate) struct WorkGuard {
+    _lattice: Option<OwnedRwLockWriteGuard<()>>,
+    _key: Option<(OwnedRwLockReadGuard<()>, OwnedMutexGuard<()>)>,
+}
+
+impl WorkScheduler {
+    /// Waits until work with the given key can start
+    pub(crate) async fn acquire(&self, key: Option<WorkKey>) -> WorkGuard {
+        let Some(key) = key else {
+            return WorkGuard {
+                _lattice: Some(self.lattice.clone().write_owned().await),
+                _key: None,
+            };
+        };
+        let lattice = self.lattice.clone().read_owned().await;
+        let lock = {
+            let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+            // Forget keys nothing is working on anymore so this doesn't grow forever
+            keys.retain(|_, lock| Arc::strong_count(lock) > 1);
+            keys.entry(key).or_default().clone()
+        };
+        WorkGuard {
+            _lattice: None,
+            _key: Some((lattice, lock.lock_owned().await)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    async fn blocks(fut: impl std::future::Future) -> bool {
+        tokio::time::timeout(Duration::from_millis(20), fut)
+            .await
+            .is_err()
+    }
+
+    #[tokio::test]
+    async fn serializes_work_per_app_and_host() {
+        let scheduler = WorkScheduler::default();
+
+        let app = |name: &str| Some(WorkKey::App(name.to_owned()));
+        let host = |id: &str| Some(WorkKey::Host(id.to_owned()));
+
+        let echo = scheduler.acquire(app("echo")).await;
+        let _other = scheduler.acquire(app("other")).await;
+        assert!(
+            blocks(scheduler.acquire(app("echo"))).await,
+            "Work for the same app should wait"
+        );
+        drop(echo);
+        let echo = scheduler.acquire(app("echo")).await;
+
+        let host1 = scheduler.acquire(host("host1")).await;
+        let host2 = scheduler.acquire(host("host2")).await;
+        assert!(
+            blocks(scheduler.acquire(host("host1"))).await,
+            "Work for the same host should wait"
+        );
+        // A host and an app with the same name are still different keys
+        let echo_host = scheduler.acquire(host("echo")).await;
+        drop((host1, host2, echo_host));
+
+        assert!(
+            blocks(scheduler.acquire(None)).await,
+            "Lattice wide work should wait for all app work"
+        );
+        drop(echo);
+        drop(_other);
+        let lattice = scheduler.acquire(None).await;
+        assert!(
+            blocks(scheduler.acquire(app("other"))).await,
+            "App work should wait for lattice wide work"
+        );
+        assert!(
+            blocks(scheduler.acquire(host("host1"))).await,
+            "Host work should wait for lattice wide work"
+        );
+        drop(lattice);
+        scheduler.acquire(app("other")).await;
+        assert_eq!(
+            scheduler.keys.lock().unwrap().len(),
+            1,
+            "Keys that aren't being worked on should be forgotten"
+        );
+    }
+}
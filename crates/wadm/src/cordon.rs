@@ -0,0 +1,119 @@
+//! Cordoning and draining of hosts, which works like `kubectl cordon` and `kubectl drain`. A host
+//! is cordoned by setting the [`CORDON_LABEL`] on it, so every wadm instance picks it up from the
+//! label change event. Cordoned hosts keep what is running on them but scalers don't place
+//! anything new there, while draining hosts are ineligible for every scaler so their managed
+//! workloads are moved to other eligible hosts
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tracing::instrument;
+
+use crate::{scaler::planner::LatticeSourceCreator, CORDON_DRAIN_VALUE, CORDON_LABEL};
+
+/// A trait for anything that can set and remove labels on the hosts of a lattice
+#[async_trait]
+pub trait HostLabeler {
+    async fn put_host_label(&self, host_id: &str, key: &str, value: &str) -> Result<()>;
+    async fn delete_host_label(&self, host_id: &str, key: &str) -> Result<()>;
+}
+
+#[async_trait]
+impl HostLabeler for wasmcloud_control_interface::Client {
+    async fn put_host_label(&self, host_id: &str, key: &str, value: &str) -> Result<()> {
+        match self
+            .put_label(host_id, key, value)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?
+        {
+            ctl_resp if ctl_resp.succeeded() => Ok(()),
+            ctl_resp => Err(anyhow::anyhow!(
+                "Failed to put label on host {host_id}, {}",
+                ctl_resp.message()
+            )),
+        }
+    }
+
+    async fn delete_host_label(&self, host_id: &str, key: &str) -> Result<()> {
+        match self
+            .delete_label(host_id, key)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?
+        {
+            ctl_resp if ctl_resp.succeeded() => Ok(()),
+            ctl_resp => Err(anyhow::anyhow!(
+                "Failed to delete label from host {host_id}, {}",
+                ctl_resp.message()
+            )),
+        }
+    }
+}
+
+/// Anything that can cordon and uncordon the hosts of a lattice
+#[async_trait]
+pub trait HostCordoner {
+    /// Cordons the given host. If `drain` is set, managed workloads are also moved off of the host
+    async fn cordon(
+        &self,
+        lattice_id: &str,
+        multitenant_prefix: Option<&str>,
+        host_id: &str,
+        drain: bool,
+    ) -> Result<()>;
+
+    /// Removes the cordon from the given host, so scalers can place workloads on it again
+    async fn uncordon(
+        &self,
+        lattice_id: &str,
+        multitenant_prefix: Option<&str>,
+        host_id: &str,
+    ) -> Result<()>;
+}
+
+/// A [`HostCordoner`] that sets the [`CORDON_LABEL`] on hosts using the lattice scoped sources from
+/// the given creator
+#[derive(Clone)]
+pub struct LabelCordoner<C> {
+    source_creator: C,
+}
+
+impl<C> LabelCordoner<C> {
+    /// Creates a new cordoner that labels hosts with sources from the given creator
+    pub fn new(source_creator: C) -> LabelCordoner<C> {
+        LabelCordoner { source_creator }
+    }
+}
+
+#[async_trait]
+impl<C> HostCordoner for LabelCordoner<C>
+where
+    C: LatticeSourceCreator + Send + Sync,
+    C::Output: HostLabeler,
+{
+    #[instrument(level = "debug", skip(self))]
+    async fn cordon(
+        &self,
+        lattice_id: &str,
+        multitenant_prefix: Option<&str>,
+        host_id: &str,
+        drain: bool,
+    ) -> Result<()> {
+        let value = if drain { CORDON_DRAIN_VALUE } else { "true" };
+        self.source_creator
+            .create(lattice_id, multitenant_prefix)
+            .put_host_label(host_id, CORDON_LABEL, value)
+            .await
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn uncordon(
+        &self,
+        lattice_id: &str,
+        multitenant_prefix: Option<&str>,
+        host_id: &str,
+    ) -> Result<()> {
+        self.source_creator
+            .create(lattice_id, multitenant_prefix)
+            .delete_host_label(host_id, CORDON_LABEL)
+            .await
+    }
+}
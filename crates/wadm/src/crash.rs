@@ -0,0 +1,241 @@
+//! Capturing of panics in consumer workers. A panic while handling a message is caught instead of
+//! taking down the whole consumer loop: the message is nacked so it is redelivered, a crash report
+//! with the context of the message is written, and the consumer keeps going.
+//!
+//! The panic hook installed by [`install`] records where each panic happened so it can be added to
+//! the report. Panics are still caught without the hook, but the report won't have a location or
+//! backtrace
+
+use std::any::Any;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::RefCell;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{Context, Result};
+use async_nats::jetstream::kv::Store;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+static REPORTER: OnceLock<Arc<dyn CrashReporter + Send + Sync>> = OnceLock::new();
+
+thread_local! {
+    /// Details of the last panic on this thread, recorded by the panic hook. Futures are polled on
+    /// a single thread at a time, so these are taken right after the panic is caught
+    static LAST_PANIC: RefCell<Option<PanicDetails>> = const { RefCell::new(None) };
+}
+
+struct PanicDetails {
+    location: Option<String>,
+    backtrace: Option<String>,
+}
+
+/// A report of a panic that was caught while handling work
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CrashReport {
+    /// A unique ID for the report
+    pub id: String,
+    /// The panic message
+    pub message: String,
+    /// Where in the code the panic happened, if the panic hook is installed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// The backtrace of the panic, if the panic hook is installed and backtraces are enabled
+    /// (with `RUST_BACKTRACE`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backtrace: Option<String>,
+    /// What was being worked on when the panic happened
+    pub context: CrashContext,
+    /// When the panic happened
+    pub crashed_at: DateTime<Utc>,
+}
+
+/// The work being done when a panic happened
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CrashContext {
+    /// The type of worker that panicked
+    pub worker_type: String,
+    /// The topic of the consumer the work came from
+    pub topic: String,
+    /// The lattice the work was for
+    pub lattice_id: String,
+    /// The subject of the message being handled. Not set if the panic happened outside of handling
+    /// a message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// The stream sequence of the message being handled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+}
+
+/// Anything that can store crash reports
+#[async_trait]
+pub trait CrashReporter {
+    /// Stores the given report
+    async fn report(&self, report: &CrashReport) -> Result<()>;
+}
+
+/// Writes each crash report as a JSON file named after the report ID in a directory
+pub struct FileCrashReporter {
+    dir: PathBuf,
+}
+
+impl FileCrashReporter {
+    /// Creates a reporter that writes to the given directory, which is created if it doesn't exist
+    pub fn new(dir: impl Into<PathBuf>) -> FileCrashReporter {
+        FileCrashReporter { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl CrashReporter for FileCrashReporter {
+    async fn report(&self, report: &CrashReport) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("Unable to create directory {}", self.dir.display()))?;
+        let path = self.dir.join(format!("{}.json", report.id));
+        tokio::fs::write(&path, serde_json::to_vec_pretty(report)?)
+            .await
+            .with_context(|| format!("Unable to write crash report to {}", path.display()))
+    }
+}
+
+/// Stores each crash report in a NATS KV bucket under the report ID
+pub struct KvCrashReporter {
+    store: Store,
+}
+
+impl KvCrashReporter {
+    /// Creates a reporter that stores reports in the given bucket
+    pub fn new(store: Store) -> KvCrashReporter {
+        KvCrashReporter { store }
+    }
+}
+
+#[async_trait]
+impl CrashReporter for KvCrashReporter {
+    async fn report(&self, report: &CrashReport) -> Result<()> {
+        self.store
+            .put(&report.id, serde_json::to_vec(report)?.into())
+            .await
+            .map(|_| ())
+            .map_err(|e| anyhow::anyhow!("Unable to store crash report: {e:?}"))
+    }
+}
+
+/// Installs a panic hook that records the location and backtrace of panics for crash reports and
+/// logs them. If a reporter is given, every caught panic is reported to it. This should only be
+/// called once, and reporters given on later calls are ignored
+pub fn install(reporter: Option<Arc<dyn CrashReporter + Send + Sync>>) {
+    if let Some(reporter) = reporter {
+        if REPORTER.set(reporter).is_err() {
+            warn!("Crash reporter was already set, ignoring the new one");
+        }
+    }
+    std::panic::set_hook(Box::new(|info| {
+        let location = info.location().map(ToString::to_string);
+        let backtrace = Backtrace::capture();
+        let backtrace =
+            (backtrace.status() == BacktraceStatus::Captured).then(|| backtrace.to_string());
+        error!(
+            message = %panic_message(info.payload()),
+            location = location.as_deref().unwrap_or("unknown"),
+            backtrace = backtrace.as_deref(),
+            "Panic occurred"
+        );
+        LAST_PANIC.with(|last| {
+            *last.borrow_mut() = Some(PanicDetails {
+                location,
+                backtrace,
+            })
+        });
+    }));
+}
+
+/// Runs the given future, catching any panic. If it panics, a crash report with the given context
+/// is logged and sent to the installed reporter, and the panic message is returned as an error
+pub async fn capture<F: Future>(context: CrashContext, fut: F) -> Result<F::Output, String> {
+    let payload = match AssertUnwindSafe(fut).catch_unwind().await {
+        Ok(output) => return Ok(output),
+        Err(payload) => payload,
+    };
+    let details = LAST_PANIC.with(|last| last.borrow_mut().take());
+    let message = panic_message(payload.as_ref());
+    let report = CrashReport {
+        id: ulid::Ulid::new().to_string(),
+        message: message.clone(),
+        location: details.as_ref().and_then(|d| d.location.clone()),
+        backtrace: details.and_then(|d| d.backtrace),
+        context,
+        crashed_at: Utc::now(),
+    };
+    error!(
+        report_id = %report.id,
+        %message,
+        worker_type = %report.context.worker_type,
+        lattice_id = %report.context.lattice_id,
+        subject = ?report.context.subject,
+        sequence = ?report.context.sequence,
+        "Caught panic while handling work"
+    );
+    if let Some(reporter) = REPORTER.get() {
+        if let Err(e) = reporter.report(&report).await {
+            warn!(error = ?e, report_id = %report.id, "Unable to write crash report");
+        }
+    }
+    Err(message)
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Box<dyn Any>".to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn can_capture_panics() {
+        let context = CrashContext {
+            worker_type: "TestWorker".to_string(),
+            topic: "wadm.cmd.default".to_string(),
+            lattice_id: "default".to_string(),
+            subject: Some("wadm.cmd.default".to_string()),
+            sequence: Some(42),
+        };
+        assert_eq!(capture(context.clone(), async { 1 }).await, Ok(1));
+
+        let res = capture(context, async {
+            tokio::task::yield_now().await;
+            panic!("worker blew up on {}", "purpose");
+        })
+        .await;
+        assert_eq!(res, Err::<(), _>("worker blew up on purpose".to_string()));
+
+        let dir = std::env::temp_dir().join(format!("wadm-crash-{}", ulid::Ulid::new()));
+        let report = CrashReport {
+            id: ulid::Ulid::new().to_string(),
+            message: "boom".to_string(),
+            location: None,
+            backtrace: None,
+            context: CrashContext::default(),
+            crashed_at: Utc::now(),
+        };
+        FileCrashReporter::new(&dir).report(&report).await.unwrap();
+        let written: CrashReport = serde_json::from_slice(
+            &std::fs::read(dir.join(format!("{}.json", report.id))).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(written, report);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,240 @@
+//! A validated cloudevent envelope around lattice events. Lattice events are always sent as
+//! cloudevents, with the actual event in the `data` attribute. This module handles parsing and
+//! validating the envelope before the data is converted into an [`Event`]
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use chrono::{DateTime, Utc};
+use cloudevents::{AttributesReader, Event as CloudEvent};
+use serde_json::Value;
+use thiserror::Error;
+use wadm_types::validation::is_valid_host_id;
+
+use super::types::{ConversionError, Event};
+
+/// The extension attribute that holds the ID of the lattice an event was sent on
+pub const LATTICE_ID_EXTENSION: &str = "latticeid";
+/// The extension attribute that holds the ID of the host that sent an event
+pub const HOST_ID_EXTENSION: &str = "hostid";
+
+/// The cloudevents spec versions we know how to parse
+const SUPPORTED_SPEC_VERSIONS: &[&str] = &["1.0", "0.3"];
+/// The spec version assumed for events that don't set one. Older hosts didn't always set it
+const DEFAULT_SPEC_VERSION: &str = "1.0";
+
+/// An error returned when a cloudevent envelope is invalid or its data can't be converted
+#[derive(Debug, Error)]
+pub enum EnvelopeError {
+    /// The payload wasn't a valid cloudevent
+    #[error("Invalid cloudevent: {0}")]
+    Invalid(#[from] serde_json::Error),
+    /// The cloudevent used a spec version we don't support
+    #[error("Unsupported cloudevents spec version {0}")]
+    UnsupportedSpecVersion(String),
+    /// A required attribute was missing or empty
+    #[error("Cloudevent is missing the required {0} attribute")]
+    MissingAttribute(&'static str),
+    /// The envelope was valid, but the data couldn't be converted to an event
+    // NOTE: Boxed because a conversion error can hold the whole original cloudevent
+    #[error(transparent)]
+    Conversion(Box<ConversionError>),
+}
+
+impl From<ConversionError> for EnvelopeError {
+    fn from(value: ConversionError) -> Self {
+        EnvelopeError::Conversion(Box::new(value))
+    }
+}
+
+/// A lattice event along with the attributes of the cloudevent it was sent in
+#[derive(Debug, Clone)]
+pub struct EventEnvelope {
+    /// The unique ID of the event
+    pub id: String,
+    /// The source of the event, generally the ID of the host that sent it
+    pub source: String,
+    /// The raw cloudevent type
+    pub ty: String,
+    /// When the event was sent, if set
+    pub time: Option<DateTime<Utc>>,
+    /// Any extension attributes set on the cloudevent
+    pub extensions: BTreeMap<String, String>,
+    /// The typed event data
+    pub event: Event,
+}
+
+impl EventEnvelope {
+    /// Parses and validates a raw cloudevent payload, converting the data to an [`Event`]
+    pub fn from_slice(payload: &[u8]) -> Result<EventEnvelope, EnvelopeError> {
+        EventEnvelope::try_from(parse_cloudevent(payload)?)
+    }
+
+    /// Returns the ID of the lattice this event was sent on, if the sender set it
+    pub fn lattice_id(&self) -> Option<&str> {
+        self.extensions
+            .get(LATTICE_ID_EXTENSION)
+            .map(String::as_str)
+    }
+
+    /// Returns the ID of the host that sent this event. This is taken from the host ID extension
+    /// if set, otherwise from the source if it looks like a host ID
+    pub fn host_id(&self) -> Option<&str> {
+        self.extensions
+            .get(HOST_ID_EXTENSION)
+            .map(String::as_str)
+            .or_else(|| is_valid_host_id(&self.source).then_some(self.source.as_str()))
+    }
+
+    /// Returns the typed event data
+    pub fn data(&self) -> &Event {
+        &self.event
+    }
+
+    /// Consumes the envelope, returning the typed event data
+    pub fn into_event(self) -> Event {
+        self.event
+    }
+}
+
+impl TryFrom<CloudEvent> for EventEnvelope {
+    type Error = EnvelopeError;
+
+    fn try_from(value: CloudEvent) -> Result<Self, Self::Error> {
+        validate(&value)?;
+        let id = value.id().to_owned();
+        let source = value.source().to_string();
+        let ty = value.ty().to_owned();
+        let time = value.time().cloned();
+        let extensions = value
+            .iter_extensions()
+            .map(|(key, val)| (key.to_owned(), val.to_string()))
+            .collect();
+        let event = Event::try_from(value)?;
+        Ok(EventEnvelope {
+            id,
+            source,
+            ty,
+            time,
+            extensions,
+            event,
+        })
+    }
+}
+
+/// Parses a raw payload into a validated cloudevent without converting its data. This is useful
+/// for looking at the envelope (like the event type) before doing the work of converting the data.
+///
+/// Parsing is tolerant of events sent by older hosts, which may be missing the spec version or ID
+pub fn parse_cloudevent(payload: &[u8]) -> Result<CloudEvent, EnvelopeError> {
+    let mut raw: Value = serde_json::from_slice(payload)?;
+    if let Some(obj) = raw.as_object_mut() {
+        match obj.get("specversion") {
+            Some(Value::String(version)) => {
+                if !SUPPORTED_SPEC_VERSIONS.contains(&version.as_str()) {
+                    return Err(EnvelopeError::UnsupportedSpecVersion(version.to_owned()));
+                }
+            }
+            Some(other) => return Err(EnvelopeError::UnsupportedSpecVersion(other.to_string())),
+            None => {
+                obj.insert("specversion".into(), DEFAULT_SPEC_VERSION.into());
+            }
+        }
+        if !obj.get("id").is_some_and(|id| id.is_string()) {
+            obj.insert("id".into(), uuid::Uuid::new_v4().to_string().into());
+        }
+    }
+    let evt: CloudEvent = serde_json::from_value(raw)?;
+    validate(&evt)?;
+    Ok(evt)
+}
+
+fn validate(evt: &CloudEvent) -> Result<(), EnvelopeError> {
+    if evt.ty().is_empty() {
+        return Err(EnvelopeError::MissingAttribute("type"));
+    }
+    if evt.source().as_str().is_empty() {
+        return Err(EnvelopeError::MissingAttribute("source"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HOST_ID: &str = "NB6PMW4RGLBP3NAVUVO2IH34VFJFSX7LF7TJOQCDU4GGUGF3P57SZLPX";
+
+    #[test]
+    fn can_parse_envelope() {
+        let raw = format!(
+            r#"{{
+                "data": {{"labels": {{"zone": "us-east"}}}},
+                "datacontenttype": "application/json",
+                "id": "2435a9d8-8ff9-4715-8d21-2f0dc128ec48",
+                "source": "{HOST_ID}",
+                "specversion": "1.0",
+                "time": "2023-02-14T19:21:09.018468Z",
+                "type": "com.wasmcloud.lattice.labels_changed",
+                "latticeid": "default"
+            }}"#
+        );
+        let envelope = EventEnvelope::from_slice(raw.as_bytes()).expect("Should parse envelope");
+        assert_eq!(envelope.id, "2435a9d8-8ff9-4715-8d21-2f0dc128ec48");
+        assert_eq!(envelope.lattice_id(), Some("default"));
+        assert_eq!(envelope.host_id(), Some(HOST_ID));
+        assert!(envelope.time.is_some());
+        let Event::HostLabelsChanged(evt) = envelope.data() else {
+            panic!("Should have parsed a labels changed event");
+        };
+        assert_eq!(evt.host_id, HOST_ID);
+    }
+
+    #[test]
+    fn can_parse_older_envelopes() {
+        // Older hosts could leave off the spec version, ID and time
+        let raw = format!(
+            r#"{{
+                "data": {{"labels": {{}}}},
+                "source": "{HOST_ID}",
+                "type": "com.wasmcloud.lattice.labels_changed"
+            }}"#
+        );
+        let envelope =
+            EventEnvelope::from_slice(raw.as_bytes()).expect("Should parse older envelope");
+        assert!(!envelope.id.is_empty(), "An ID should have been generated");
+        assert!(envelope.time.is_none());
+        assert_eq!(envelope.lattice_id(), None);
+        assert_eq!(envelope.host_id(), Some(HOST_ID));
+    }
+
+    #[test]
+    fn rejects_invalid_envelopes() {
+        let unsupported = r#"{"specversion": "2.0", "id": "1", "source": "wadm", "type": "foo"}"#;
+        assert!(matches!(
+            parse_cloudevent(unsupported.as_bytes()),
+            Err(EnvelopeError::UnsupportedSpecVersion(_))
+        ));
+
+        let no_type = r#"{"specversion": "1.0", "id": "1", "source": "wadm", "type": ""}"#;
+        assert!(matches!(
+            parse_cloudevent(no_type.as_bytes()),
+            Err(EnvelopeError::MissingAttribute("type"))
+        ));
+
+        assert!(matches!(
+            parse_cloudevent(b"not json"),
+            Err(EnvelopeError::Invalid(_))
+        ));
+
+        let envelope = EventEnvelope::from_slice(
+            br#"{"specversion": "1.0", "id": "1", "source": "wadm", "type": "foo", "data": {}}"#,
+        )
+        .expect("Unknown events should still parse");
+        assert_eq!(
+            envelope.host_id(),
+            None,
+            "Non host sources should not be used as a host ID"
+        );
+    }
+}
@@ -1,7 +1,9 @@
 mod data;
 mod deser;
+mod envelope;
 mod ser;
 mod types;
 
 pub use data::*;
+pub use envelope::*;
 pub use types::*;
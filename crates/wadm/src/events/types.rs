@@ -9,6 +9,7 @@ use std::{
 };
 
 use cloudevents::{AttributesReader, Data, Event as CloudEvent, EventBuilder, EventBuilderV10};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use wasmcloud_control_interface::{ComponentDescription, Link, ProviderDescription};
@@ -51,7 +52,8 @@ macro_rules! event_impl {
                     Data::Binary(raw) => serde_json::from_reader(std::io::Cursor::new(raw))
                         .map_err(ConversionError::from),
                     Data::Json(v) => serde_json::from_value(v).map_err(ConversionError::from),
-                    Data::String(_) => Err(ConversionError::NoData),
+                    // Older hosts could send the JSON data as a string
+                    Data::String(raw) => serde_json::from_str(&raw).map_err(ConversionError::from),
                 }
             }
         }
@@ -75,7 +77,8 @@ macro_rules! event_impl {
                     Data::Binary(raw) => serde_json::from_reader(std::io::Cursor::new(raw))
                         .map_err(ConversionError::from),
                     Data::Json(v) => serde_json::from_value(v).map_err(ConversionError::from),
-                    Data::String(_) => Err(ConversionError::NoData),
+                    // Older hosts could send the JSON data as a string
+                    Data::String(raw) => serde_json::from_str(&raw).map_err(ConversionError::from),
                 }?;
 
                 parsed.$data_attr = value.$event_attr().to_string();
@@ -104,7 +107,9 @@ pub enum Event {
     HostStarted(HostStarted),
     HostStopped(HostStopped),
     HostHeartbeat(HostHeartbeat),
+    HostLabelsChanged(HostLabelsChanged),
     LinkdefSet(LinkdefSet),
+    LinkdefSetFailed(LinkdefSetFailed),
     LinkdefDeleted(LinkdefDeleted),
     ConfigSet(ConfigSet),
     ConfigDeleted(ConfigDeleted),
@@ -112,6 +117,10 @@ pub enum Event {
     // for now to have them here even though they aren't technically lattice events
     ManifestPublished(ManifestPublished),
     ManifestUnpublished(ManifestUnpublished),
+    ManifestPaused(ManifestPaused),
+    ManifestResumed(ManifestResumed),
+    /// Any event that doesn't have a typed variant. The raw data is kept so nothing is dropped
+    Unknown(UnknownEvent),
 }
 
 impl Display for Event {
@@ -128,12 +137,17 @@ impl Display for Event {
             Event::HostStarted(_) => write!(f, "HostStarted"),
             Event::HostStopped(_) => write!(f, "HostStopped"),
             Event::HostHeartbeat(_) => write!(f, "HostHeartbeat"),
+            Event::HostLabelsChanged(_) => write!(f, "HostLabelsChanged"),
             Event::LinkdefSet(_) => write!(f, "LinkdefSet"),
+            Event::LinkdefSetFailed(_) => write!(f, "LinkdefSetFailed"),
             Event::LinkdefDeleted(_) => write!(f, "LinkdefDeleted"),
             Event::ConfigSet(_) => write!(f, "ConfigSet"),
             Event::ConfigDeleted(_) => write!(f, "ConfigDeleted"),
             Event::ManifestPublished(_) => write!(f, "ManifestPublished"),
             Event::ManifestUnpublished(_) => write!(f, "ManifestUnpublished"),
+            Event::ManifestPaused(_) => write!(f, "ManifestPaused"),
+            Event::ManifestResumed(_) => write!(f, "ManifestResumed"),
+            Event::Unknown(evt) => write!(f, "Unknown({})", evt.ty),
         }
     }
 }
@@ -164,7 +178,13 @@ impl TryFrom<CloudEvent> for Event {
             HostStarted::TYPE => HostStarted::try_from(value).map(Event::HostStarted),
             HostStopped::TYPE => HostStopped::try_from(value).map(Event::HostStopped),
             HostHeartbeat::TYPE => HostHeartbeat::try_from(value).map(Event::HostHeartbeat),
+            HostLabelsChanged::TYPE => {
+                HostLabelsChanged::try_from(value).map(Event::HostLabelsChanged)
+            }
             LinkdefSet::TYPE => LinkdefSet::try_from(value).map(Event::LinkdefSet),
+            LinkdefSetFailed::TYPE => {
+                LinkdefSetFailed::try_from(value).map(Event::LinkdefSetFailed)
+            }
             LinkdefDeleted::TYPE => LinkdefDeleted::try_from(value).map(Event::LinkdefDeleted),
             ConfigSet::TYPE => ConfigSet::try_from(value).map(Event::ConfigSet),
             ConfigDeleted::TYPE => ConfigDeleted::try_from(value).map(Event::ConfigDeleted),
@@ -174,7 +194,9 @@ impl TryFrom<CloudEvent> for Event {
             ManifestUnpublished::TYPE => {
                 ManifestUnpublished::try_from(value).map(Event::ManifestUnpublished)
             }
-            _ => Err(ConversionError::WrongEvent(value)),
+            ManifestPaused::TYPE => ManifestPaused::try_from(value).map(Event::ManifestPaused),
+            ManifestResumed::TYPE => ManifestResumed::try_from(value).map(Event::ManifestResumed),
+            _ => Ok(Event::Unknown(UnknownEvent::from(value))),
         }
     }
 }
@@ -183,25 +205,7 @@ impl TryFrom<Event> for CloudEvent {
     type Error = anyhow::Error;
 
     fn try_from(value: Event) -> Result<Self, Self::Error> {
-        let ty = match value {
-            Event::ComponentScaled(_) => ComponentScaled::TYPE,
-            Event::ComponentScaleFailed(_) => ComponentScaleFailed::TYPE,
-            Event::ProviderStarted(_) => ProviderStarted::TYPE,
-            Event::ProviderStopped(_) => ProviderStopped::TYPE,
-            Event::ProviderStartFailed(_) => ProviderStartFailed::TYPE,
-            Event::ProviderHealthCheckPassed(_) => ProviderHealthCheckPassed::TYPE,
-            Event::ProviderHealthCheckFailed(_) => ProviderHealthCheckFailed::TYPE,
-            Event::ProviderHealthCheckStatus(_) => ProviderHealthCheckStatus::TYPE,
-            Event::HostStarted(_) => HostStarted::TYPE,
-            Event::HostStopped(_) => HostStopped::TYPE,
-            Event::HostHeartbeat(_) => HostHeartbeat::TYPE,
-            Event::LinkdefSet(_) => LinkdefSet::TYPE,
-            Event::LinkdefDeleted(_) => LinkdefDeleted::TYPE,
-            Event::ConfigSet(_) => ConfigSet::TYPE,
-            Event::ConfigDeleted(_) => ConfigDeleted::TYPE,
-            Event::ManifestPublished(_) => ManifestPublished::TYPE,
-            Event::ManifestUnpublished(_) => ManifestUnpublished::TYPE,
-        };
+        let ty = value.raw_type().to_owned();
 
         EventBuilderV10::new()
             .id(uuid::Uuid::new_v4().to_string())
@@ -232,12 +236,17 @@ impl Serialize for Event {
             Event::HostStarted(evt) => evt.serialize(serializer),
             Event::HostStopped(evt) => evt.serialize(serializer),
             Event::HostHeartbeat(evt) => evt.serialize(serializer),
+            Event::HostLabelsChanged(evt) => evt.serialize(serializer),
             Event::LinkdefSet(evt) => evt.serialize(serializer),
+            Event::LinkdefSetFailed(evt) => evt.serialize(serializer),
             Event::LinkdefDeleted(evt) => evt.serialize(serializer),
             Event::ConfigSet(evt) => evt.serialize(serializer),
             Event::ConfigDeleted(evt) => evt.serialize(serializer),
             Event::ManifestPublished(evt) => evt.serialize(serializer),
             Event::ManifestUnpublished(evt) => evt.serialize(serializer),
+            Event::ManifestPaused(evt) => evt.serialize(serializer),
+            Event::ManifestResumed(evt) => evt.serialize(serializer),
+            Event::Unknown(evt) => evt.data.serialize(serializer),
         }
     }
 }
@@ -255,19 +264,24 @@ impl Event {
             Event::ComponentScaleFailed(_) => ComponentScaleFailed::TYPE,
             Event::ProviderStarted(_) => ProviderStarted::TYPE,
             Event::ProviderStopped(_) => ProviderStopped::TYPE,
-            Event::ProviderStartFailed(_) => ProviderStopped::TYPE,
+            Event::ProviderStartFailed(_) => ProviderStartFailed::TYPE,
             Event::ProviderHealthCheckPassed(_) => ProviderHealthCheckPassed::TYPE,
             Event::ProviderHealthCheckFailed(_) => ProviderHealthCheckFailed::TYPE,
             Event::ProviderHealthCheckStatus(_) => ProviderHealthCheckStatus::TYPE,
             Event::HostStarted(_) => HostStarted::TYPE,
             Event::HostStopped(_) => HostStopped::TYPE,
             Event::HostHeartbeat(_) => HostHeartbeat::TYPE,
+            Event::HostLabelsChanged(_) => HostLabelsChanged::TYPE,
             Event::LinkdefSet(_) => LinkdefSet::TYPE,
+            Event::LinkdefSetFailed(_) => LinkdefSetFailed::TYPE,
             Event::LinkdefDeleted(_) => LinkdefDeleted::TYPE,
             Event::ConfigSet(_) => ConfigSet::TYPE,
             Event::ConfigDeleted(_) => ConfigDeleted::TYPE,
             Event::ManifestPublished(_) => ManifestPublished::TYPE,
             Event::ManifestUnpublished(_) => ManifestUnpublished::TYPE,
+            Event::ManifestPaused(_) => ManifestPaused::TYPE,
+            Event::ManifestResumed(_) => ManifestResumed::TYPE,
+            Event::Unknown(evt) => &evt.ty,
         }
     }
 }
@@ -294,7 +308,7 @@ pub enum ConversionError {
 
 // Component Events
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct ComponentScaled {
     pub annotations: BTreeMap<String, String>,
     pub claims: Option<ComponentClaims>,
@@ -312,7 +326,7 @@ event_impl!(
     host_id
 );
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct ComponentScaleFailed {
     pub annotations: BTreeMap<String, String>,
     pub claims: Option<ComponentClaims>,
@@ -333,7 +347,7 @@ event_impl!(
 
 // Provider Events
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct ProviderStarted {
     pub annotations: BTreeMap<String, String>,
     #[serde(default)]
@@ -352,7 +366,7 @@ event_impl!(
     host_id
 );
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct ProviderStartFailed {
     pub error: String,
     pub provider_id: String,
@@ -368,7 +382,7 @@ event_impl!(
     host_id
 );
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct ProviderStopped {
     pub annotations: BTreeMap<String, String>,
     pub provider_id: String,
@@ -384,7 +398,7 @@ event_impl!(
     host_id
 );
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct ProviderHealthCheckPassed {
     #[serde(flatten)]
     pub data: ProviderHealthCheckInfo,
@@ -395,7 +409,7 @@ event_impl!(
     "com.wasmcloud.lattice.health_check_passed"
 );
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct ProviderHealthCheckFailed {
     #[serde(flatten)]
     pub data: ProviderHealthCheckInfo,
@@ -406,7 +420,7 @@ event_impl!(
     "com.wasmcloud.lattice.health_check_failed"
 );
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct ProviderHealthCheckStatus {
     #[serde(flatten)]
     pub data: ProviderHealthCheckInfo,
@@ -419,15 +433,16 @@ event_impl!(
 
 // Link Events
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct LinkdefSet {
     #[serde(flatten)]
+    #[schemars(with = "serde_json::Map<String, serde_json::Value>")]
     pub linkdef: Link,
 }
 
 event_impl!(LinkdefSet, "com.wasmcloud.lattice.linkdef_set");
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct LinkdefDeleted {
     pub source_id: String,
     pub name: String,
@@ -437,16 +452,26 @@ pub struct LinkdefDeleted {
 
 event_impl!(LinkdefDeleted, "com.wasmcloud.lattice.linkdef_deleted");
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct LinkdefSetFailed {
+    #[serde(flatten)]
+    #[schemars(with = "serde_json::Map<String, serde_json::Value>")]
+    pub linkdef: Link,
+    pub error: String,
+}
+
+event_impl!(LinkdefSetFailed, "com.wasmcloud.lattice.linkdef_set_failed");
+
 // Config Events
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct ConfigSet {
     pub config_name: String,
 }
 
 event_impl!(ConfigSet, "com.wasmcloud.lattice.config_set");
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct ConfigDeleted {
     pub config_name: String,
 }
@@ -455,7 +480,7 @@ event_impl!(ConfigDeleted, "com.wasmcloud.lattice.config_deleted");
 
 // Host Events
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct HostStarted {
     pub labels: HashMap<String, String>,
     pub friendly_name: String,
@@ -470,7 +495,7 @@ event_impl!(
     id
 );
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct HostStopped {
     pub labels: HashMap<String, String>,
     #[serde(default)]
@@ -484,11 +509,13 @@ event_impl!(
     id
 );
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct HostHeartbeat {
     /// Components running on this host.
+    #[schemars(with = "Vec<serde_json::Value>")]
     pub components: Vec<ComponentDescription>,
     /// Providers running on this host
+    #[schemars(with = "Vec<serde_json::Value>")]
     pub providers: Vec<ProviderDescription>,
     /// The host's unique ID
     #[serde(default, alias = "id")]
@@ -501,6 +528,7 @@ pub struct HostHeartbeat {
     /// The host's labels
     pub labels: HashMap<String, String>,
     /// The host version
+    #[schemars(with = "String")]
     pub version: semver::Version,
     /// The host uptime in human-readable form
     pub uptime_human: String,
@@ -515,9 +543,24 @@ event_impl!(
     host_id
 );
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct HostLabelsChanged {
+    /// The full set of labels the host now has
+    pub labels: HashMap<String, String>,
+    #[serde(default)]
+    pub host_id: String,
+}
+
+event_impl!(
+    HostLabelsChanged,
+    "com.wasmcloud.lattice.labels_changed",
+    source,
+    host_id
+);
+
 // Manifest Events
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct ManifestPublished {
     #[serde(flatten)]
     pub manifest: Manifest,
@@ -525,13 +568,63 @@ pub struct ManifestPublished {
 
 event_impl!(ManifestPublished, "com.wadm.manifest_published");
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct ManifestUnpublished {
     pub name: String,
 }
 
 event_impl!(ManifestUnpublished, "com.wadm.manifest_unpublished");
 
+/// Sent when reconciliation of a deployed manifest is paused. Its scalers are kept, but don't run
+/// until the manifest is resumed
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct ManifestPaused {
+    pub name: String,
+}
+
+event_impl!(ManifestPaused, "com.wadm.manifest_paused");
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct ManifestResumed {
+    pub name: String,
+}
+
+event_impl!(ManifestResumed, "com.wadm.manifest_resumed");
+
+// Unknown Events
+
+/// An event that wadm doesn't have a type for. This keeps the raw data around so it can still be
+/// inspected or passed along
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct UnknownEvent {
+    /// The raw cloudevent type
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// The source of the event, generally the ID of the host that sent it
+    pub source: String,
+    /// The raw event data. Data that isn't JSON is kept as a string and missing data is null
+    pub data: serde_json::Value,
+}
+
+impl From<CloudEvent> for UnknownEvent {
+    fn from(mut value: CloudEvent) -> Self {
+        let (_, _, data) = value.take_data();
+        let data = match data {
+            Some(Data::Json(v)) => v,
+            Some(Data::Binary(raw)) => serde_json::from_slice(&raw).unwrap_or_else(|_| {
+                serde_json::Value::String(String::from_utf8_lossy(&raw).into_owned())
+            }),
+            Some(Data::String(s)) => serde_json::Value::String(s),
+            None => serde_json::Value::Null,
+        };
+        UnknownEvent {
+            ty: value.ty().to_owned(),
+            source: value.source().to_string(),
+            data,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -555,11 +648,25 @@ mod test {
     fn test_non_supported_event() {
         let raw: cloudevents::Event = serde_json::from_str(NON_SUPPORTED_EVENT).unwrap();
 
-        let err = Event::new(raw).expect_err("Should have errored on a non-supported event");
+        let evt = Event::new(raw).expect("Should have parsed a non-supported event");
+
+        let Event::Unknown(unknown) = &evt else {
+            panic!("Should have returned an unknown event, got {evt:?}");
+        };
+        assert_eq!(unknown.ty, "com.wasmcloud.lattice.refmap_set");
+        assert_eq!(evt.raw_type(), "com.wasmcloud.lattice.refmap_set");
+        assert_eq!(
+            unknown.data["public_key"], "VAG3QITQQ2ODAOWB5TTQSDJ53XK3SHBEIFNK4AYJ5RKAX2UNSCAPHA5M",
+            "Raw data should be preserved"
+        );
 
-        assert!(
-            matches!(err, ConversionError::WrongEvent(_)),
-            "Should have returned wrong event error"
+        let roundtrip = CloudEvent::try_from(evt.clone()).expect("Should convert back");
+        assert_eq!(
+            Event::new(roundtrip)
+                .expect("Should parse again")
+                .raw_type(),
+            evt.raw_type(),
+            "Unknown events should keep their type when converted back to a cloudevent"
         );
     }
 
@@ -572,7 +679,13 @@ mod test {
 
         for evt in all_events.into_iter() {
             println!("EVT {:?}", evt);
-            Event::new(evt).expect("Should be able to parse event");
+            let ty = evt.ty().to_owned();
+            let parsed = Event::new(evt).expect("Should be able to parse event");
+            assert!(
+                !matches!(parsed, Event::Unknown(_)),
+                "Event {ty} should have a typed variant"
+            );
+            assert_eq!(parsed.raw_type(), ty);
         }
     }
 }
@@ -0,0 +1,177 @@
+//! Types for hibernating idle lattices. In installations with many mostly idle lattices, keeping a
+//! consumer, scalers and a reaper running for every lattice is wasteful. A [`LatticeActivity`]
+//! tracks when each lattice was last active so the lattices that have been idle for a while can
+//! be torn down, and requests that they are woken back up as soon as they see activity again
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::debug;
+
+use crate::clock::{self, SharedClock};
+
+/// A lattice that should be hibernated or woken up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatticeRef {
+    pub lattice_id: String,
+    pub multitenant_prefix: Option<String>,
+}
+
+#[derive(Debug)]
+struct Activity {
+    last_active: Instant,
+    multitenant_prefix: Option<String>,
+    hibernated: bool,
+}
+
+/// Tracks when lattices were last active. This is cheap to clone and all clones share the same
+/// state
+#[derive(Clone)]
+pub struct LatticeActivity {
+    lattices: Arc<Mutex<HashMap<String, Activity>>>,
+    wake: UnboundedSender<LatticeRef>,
+    clock: SharedClock,
+}
+
+impl LatticeActivity {
+    /// Creates a new activity tracker. The returned receiver gets a [`LatticeRef`] whenever a
+    /// hibernated lattice sees activity and should be woken up
+    pub fn new() -> (LatticeActivity, UnboundedReceiver<LatticeRef>) {
+        let (wake, receiver) = unbounded_channel();
+        (
+            LatticeActivity {
+                lattices: Arc::default(),
+                wake,
+                clock: clock::system(),
+            },
+            receiver,
+        )
+    }
+
+    /// Sets the clock used to measure how long lattices have been idle
+    pub fn with_clock(mut self, clock: SharedClock) -> LatticeActivity {
+        self.clock = clock;
+        self
+    }
+
+    /// Starts tracking the given lattice if it isn't already tracked, without counting this as
+    /// activity. This should be used for background traffic like heartbeats that shouldn't keep a
+    /// lattice awake
+    pub fn observe(&self, lattice_id: &str, multitenant_prefix: Option<&str>) {
+        let now = self.clock.instant();
+        let mut lattices = self.lattices.lock().unwrap_or_else(|e| e.into_inner());
+        lattices
+            .entry(lattice_id.to_owned())
+            .or_insert_with(|| Activity {
+                last_active: now,
+                multitenant_prefix: multitenant_prefix.map(ToOwned::to_owned),
+                hibernated: false,
+            });
+    }
+
+    /// Records activity for the given lattice. If the lattice was hibernated, it is marked as
+    /// awake and a wake request is sent. Returns whether the lattice was woken up
+    pub fn touch(&self, lattice_id: &str, multitenant_prefix: Option<&str>) -> bool {
+        let now = self.clock.instant();
+        let mut lattices = self.lattices.lock().unwrap_or_else(|e| e.into_inner());
+        let activity = lattices
+            .entry(lattice_id.to_owned())
+            .or_insert_with(|| Activity {
+                last_active: now,
+                multitenant_prefix: multitenant_prefix.map(ToOwned::to_owned),
+                hibernated: false,
+            });
+        activity.last_active = now;
+        if !activity.hibernated {
+            return false;
+        }
+        activity.hibernated = false;
+        debug!(%lattice_id, "Requesting wake for hibernated lattice");
+        // NOTE: This only fails if the receiver was dropped, in which case nothing is hibernating
+        // lattices anymore
+        let _ = self.wake.send(LatticeRef {
+            lattice_id: lattice_id.to_owned(),
+            multitenant_prefix: activity.multitenant_prefix.clone(),
+        });
+        true
+    }
+
+    /// Returns whether the given lattice is currently hibernated
+    pub fn is_hibernated(&self, lattice_id: &str) -> bool {
+        self.lattices
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(lattice_id)
+            .is_some_and(|activity| activity.hibernated)
+    }
+
+    /// Marks every lattice that hasn't been active for the given amount of time as hibernated and
+    /// returns them so they can be torn down
+    pub fn hibernate_idle(&self, idle_for: Duration) -> Vec<LatticeRef> {
+        let now = self.clock.instant();
+        let mut lattices = self.lattices.lock().unwrap_or_else(|e| e.into_inner());
+        lattices
+            .iter_mut()
+            .filter(|(_, activity)| {
+                !activity.hibernated
+                    && now.saturating_duration_since(activity.last_active) >= idle_for
+            })
+            .map(|(lattice_id, activity)| {
+                activity.hibernated = true;
+                LatticeRef {
+                    lattice_id: lattice_id.to_owned(),
+                    multitenant_prefix: activity.multitenant_prefix.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn can_hibernate_and_wake_lattices() {
+        let (activity, mut wake) = LatticeActivity::new();
+        activity.observe("idle", None);
+        activity.touch("busy", Some("account"));
+
+        std::thread::sleep(Duration::from_millis(20));
+        activity.touch("busy", Some("account"));
+        let hibernated = activity.hibernate_idle(Duration::from_millis(10));
+        assert_eq!(
+            hibernated,
+            vec![LatticeRef {
+                lattice_id: "idle".to_string(),
+                multitenant_prefix: None
+            }]
+        );
+        assert!(activity.is_hibernated("idle"));
+        assert!(!activity.is_hibernated("busy"));
+        assert!(
+            activity
+                .hibernate_idle(Duration::from_millis(10))
+                .is_empty(),
+            "Already hibernated lattices shouldn't be returned again"
+        );
+
+        // Observing shouldn't wake a lattice
+        activity.observe("idle", None);
+        assert!(activity.is_hibernated("idle"));
+        assert!(wake.try_recv().is_err());
+
+        assert!(
+            activity.touch("idle", None),
+            "Touching should wake the lattice"
+        );
+        assert!(!activity.is_hibernated("idle"));
+        assert_eq!(wake.try_recv().unwrap().lattice_id, "idle");
+        assert!(
+            !activity.touch("idle", None),
+            "Lattice should already be awake"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Introspection of a running wadm instance, used to answer the admin API. This reports the view
+//! of a single process (the lattices it manages, its consumers, scalers and work permits), which
+//! is mostly useful when debugging reconciles that are stuck
+
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use tokio::sync::Semaphore;
+use wadm_types::api::{
+    AppScalers, BuildInfo, ConsumerStatus, ManagedLattice, PermitUsage, ScalerSummary,
+};
+
+use crate::scaler::manager::AllScalers;
+
+/// Anything that can report the internal state of a wadm instance
+#[async_trait]
+pub trait Introspector {
+    /// Returns the version and build info of this instance
+    async fn build_info(&self) -> BuildInfo;
+
+    /// Returns the lattices this instance is managing
+    async fn lattices(&self) -> Vec<ManagedLattice>;
+
+    /// Returns the consumers this instance is running
+    async fn consumers(&self) -> Vec<ConsumerStatus>;
+
+    /// Returns the scalers this instance is running, per app
+    async fn scalers(&self) -> Vec<AppScalers>;
+
+    /// Returns the usage of the work permit pool
+    async fn permits(&self) -> PermitUsage;
+}
+
+/// Groups consumers by lattice, sorted by lattice ID. Only running consumers are included
+pub fn managed_lattices(consumers: &[ConsumerStatus]) -> Vec<ManagedLattice> {
+    let mut lattices: Vec<ManagedLattice> = Vec::new();
+    for consumer in consumers.iter().filter(|consumer| consumer.running) {
+        match lattices
+            .iter_mut()
+            .find(|lattice| lattice.id == consumer.lattice_id)
+        {
+            Some(lattice) => lattice.consumers.push(consumer.topic.clone()),
+            None => lattices.push(ManagedLattice {
+                id: consumer.lattice_id.clone(),
+                consumers: vec![consumer.topic.clone()],
+            }),
+        }
+    }
+    lattices.sort_by(|a, b| a.id.cmp(&b.id));
+    for lattice in lattices.iter_mut() {
+        lattice.consumers.sort();
+    }
+    lattices
+}
+
+/// Summarizes the scalers from a scaler manager for the given lattice, sorted by app name
+pub async fn app_scalers(lattice_id: &str, all: AllScalers) -> Vec<AppScalers> {
+    let mut apps = Vec::with_capacity(all.len());
+    for (name, scalers) in all.iter() {
+        let mut summaries = Vec::with_capacity(scalers.len());
+        for scaler in scalers.iter() {
+            summaries.push(ScalerSummary {
+                id: scaler.id().to_owned(),
+                name: scaler.name(),
+                kind: scaler.kind().to_owned(),
+                status: scaler.status().await,
+            });
+        }
+        apps.push(AppScalers {
+            lattice_id: lattice_id.to_owned(),
+            name: name.to_owned(),
+            scalers: summaries,
+        });
+    }
+    apps.sort_by(|a, b| a.name.cmp(&b.name));
+    apps
+}
+
+/// Returns the usage of a permit pool with the given limit, where `None` means there is no limit
+pub fn permit_usage(permits: &Semaphore, max: Option<usize>) -> PermitUsage {
+    let available = permits.available_permits();
+    PermitUsage {
+        max,
+        available,
+        // NOTE: While the pool is being resized this can be briefly off, as permits are removed
+        // as they are returned
+        in_use: max.map(|max| max.saturating_sub(available)),
+        ..Default::default()
+    }
+}
+
+/// Returns the combined usage of the given pools, keyed by the kind of work they limit. The
+/// combined limit is only set if every pool has one
+pub fn pooled_permit_usage<'a>(
+    pools: impl IntoIterator<Item = (&'a str, &'a Semaphore, Option<usize>)>,
+) -> PermitUsage {
+    let pools: BTreeMap<String, PermitUsage> = pools
+        .into_iter()
+        .map(|(name, permits, max)| (name.to_owned(), permit_usage(permits, max)))
+        .collect();
+    let sum = |get: fn(&PermitUsage) -> Option<usize>| {
+        pools
+            .values()
+            .map(get)
+            .try_fold(0usize, |total, value| Some(total.saturating_add(value?)))
+    };
+    PermitUsage {
+        max: sum(|usage| usage.max),
+        available: pools
+            .values()
+            .fold(0usize, |total, usage| total.saturating_add(usage.available)),
+        in_use: sum(|usage| usage.in_use),
+        pools,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_summarize_consumers_and_permits() {
+        let consumer = |topic: &str, lattice_id: &str, running: bool| ConsumerStatus {
+            topic: topic.to_string(),
+            lattice_id: lattice_id.to_string(),
+            running,
+            ..Default::default()
+        };
+        let lattices = managed_lattices(&[
+            consumer("wadm.evt.other", "other", true),
+            consumer("wadm.cmd.default", "default", true),
+            consumer("wadm.evt.default", "default", true),
+            consumer("wadm.cmd.other", "other", false),
+        ]);
+        assert_eq!(
+            lattices,
+            vec![
+                ManagedLattice {
+                    id: "default".to_string(),
+                    consumers: vec![
+                        "wadm.cmd.default".to_string(),
+                        "wadm.evt.default".to_string()
+                    ],
+                },
+                ManagedLattice {
+                    id: "other".to_string(),
+                    consumers: vec!["wadm.evt.other".to_string()],
+                },
+            ],
+            "Lattices should be grouped and stopped consumers left out"
+        );
+
+        let permits = Semaphore::new(4);
+        let _permit = permits.try_acquire().unwrap();
+        assert_eq!(
+            permit_usage(&permits, Some(4)),
+            PermitUsage {
+                max: Some(4),
+                available: 3,
+                in_use: Some(1),
+                ..Default::default()
+            }
+        );
+
+        let commands = Semaphore::new(2);
+        let usage = pooled_permit_usage([
+            ("events", &permits, Some(4)),
+            ("commands", &commands, Some(2)),
+        ]);
+        assert_eq!(
+            (usage.max, usage.available, usage.in_use),
+            (Some(6), 5, Some(1))
+        );
+        assert_eq!(usage.pools["commands"].available, 2);
+        let usage =
+            pooled_permit_usage([("events", &permits, None), ("commands", &commands, Some(2))]);
+        assert_eq!(
+            usage.max, None,
+            "A pool without a limit means there is no total limit"
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Per-lattice leader election for running multiple wadm processes. Only the process holding a
+//! lattice's lease reconciles that lattice, while the others stay on standby and take over once the
+//! lease expires.
+//!
+//! Leases are stored in a NATS KV bucket whose max age is the lease TTL. The leader renews its
+//! lease by updating the key before it expires. If the leader stops renewing (because it crashed
+//! or lost its connection), the key ages out and the next process to try can create it
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_nats::jetstream::kv::{CreateErrorKind, Store, UpdateErrorKind};
+use tracing::{debug, info, instrument, warn};
+
+/// Leases on lattices, held by a single wadm process
+#[derive(Clone)]
+pub struct LatticeLeases {
+    store: Store,
+    owner_id: String,
+    /// The lattices this process holds a lease for, along with the revision of the lease
+    held: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl LatticeLeases {
+    /// Creates a new set of leases stored in the given bucket. The bucket's max age should be set
+    /// to the lease TTL. The owner ID must be unique for each wadm process
+    pub fn new(store: Store, owner_id: impl Into<String>) -> LatticeLeases {
+        LatticeLeases {
+            store,
+            owner_id: owner_id.into(),
+            held: Arc::default(),
+        }
+    }
+
+    /// Returns the ID this process holds leases as
+    pub fn owner_id(&self) -> &str {
+        &self.owner_id
+    }
+
+    /// Returns whether this process currently holds the lease for the given lattice
+    pub fn is_leader(&self, lattice_id: &str) -> bool {
+        self.held().contains_key(lattice_id)
+    }
+
+    /// Returns the lattices this process currently holds a lease for
+    pub fn held_lattices(&self) -> Vec<String> {
+        self.held().keys().cloned().collect()
+    }
+
+    /// Tries to acquire the lease for the given lattice, or renews it if it is already held.
+    /// Returns whether this process holds the lease afterwards
+    #[instrument(level = "trace", skip(self))]
+    pub async fn try_acquire(&self, lattice_id: &str) -> Result<bool> {
+        let held_revision = self.held().get(lattice_id).copied();
+        let value = self.owner_id.clone().into_bytes();
+        let res = match held_revision {
+            Some(revision) => match self.store.update(lattice_id, value.into(), revision).await {
+                Ok(revision) => Some(revision),
+                Err(e) if e.kind() == UpdateErrorKind::WrongLastRevision => None,
+                Err(e) => return Err(anyhow::anyhow!("Unable to renew lease: {e:?}")),
+            },
+            None => match self.store.create(lattice_id, value.into()).await {
+                Ok(revision) => Some(revision),
+                Err(e) if e.kind() == CreateErrorKind::AlreadyExists => {
+                    self.adopt(lattice_id).await?
+                }
+                Err(e) => return Err(anyhow::anyhow!("Unable to acquire lease: {e:?}")),
+            },
+        };
+
+        let mut held = self.held();
+        match (res, held_revision) {
+            (Some(revision), None) => {
+                info!(%lattice_id, "Acquired lease for lattice, this process is now the leader");
+                held.insert(lattice_id.to_owned(), revision);
+                Ok(true)
+            }
+            (Some(revision), Some(_)) => {
+                held.insert(lattice_id.to_owned(), revision);
+                Ok(true)
+            }
+            (None, Some(_)) => {
+                warn!(%lattice_id, "Lost lease for lattice to another process");
+                held.remove(lattice_id);
+                Ok(false)
+            }
+            (None, None) => Ok(false),
+        }
+    }
+
+    /// Releases the lease for the given lattice if it is held, so another process can take over
+    /// without waiting for it to expire
+    #[instrument(level = "debug", skip(self))]
+    pub async fn release(&self, lattice_id: &str) {
+        let Some(revision) = self.held().remove(lattice_id) else {
+            return;
+        };
+        if let Err(e) = self
+            .store
+            .delete_expect_revision(lattice_id, Some(revision))
+            .await
+        {
+            warn!(error = %e, %lattice_id, "Unable to release lease, it will expire on its own");
+        }
+    }
+
+    /// Releases all held leases
+    pub async fn release_all(&self) {
+        for lattice_id in self.held_lattices() {
+            self.release(&lattice_id).await;
+        }
+    }
+
+    /// Takes over a lease that already exists if it belongs to this process. This happens when a
+    /// process restarts with the same ID before its old lease expires
+    async fn adopt(&self, lattice_id: &str) -> Result<Option<u64>> {
+        let Some(entry) = self
+            .store
+            .entry(lattice_id)
+            .await
+            .map_err(|e| anyhow::anyhow!("Unable to fetch lease: {e:?}"))?
+        else {
+            return Ok(None);
+        };
+        if entry.value.as_ref() != self.owner_id.as_bytes() {
+            debug!(%lattice_id, owner = %String::from_utf8_lossy(&entry.value), "Lattice is led by another process");
+            return Ok(None);
+        }
+        match self
+            .store
+            .update(lattice_id, entry.value, entry.revision)
+            .await
+        {
+            Ok(revision) => Ok(Some(revision)),
+            Err(e) if e.kind() == UpdateErrorKind::WrongLastRevision => Ok(None),
+            Err(e) => Err(anyhow::anyhow!("Unable to renew lease: {e:?}")),
+        }
+    }
+
+    fn held(&self) -> std::sync::MutexGuard<'_, HashMap<String, u64>> {
+        self.held.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
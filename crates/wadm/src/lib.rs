@@ -1,13 +1,30 @@
 use std::time::Duration;
 
+pub mod audit;
+pub mod claims;
+pub mod clock;
 pub mod commands;
+pub mod config;
 pub mod consumers;
+pub mod cordon;
+pub mod crash;
 pub mod events;
+pub mod hibernation;
+pub mod introspection;
+pub mod leadership;
 pub mod nats_utils;
+pub mod notifications;
+pub mod oci;
+pub mod policy;
 pub mod publisher;
 pub mod scaler;
+pub mod schemas;
+pub mod secrets;
 pub mod server;
+pub mod sharding;
 pub mod storage;
+pub mod topology;
+pub mod trace_context;
 pub mod workers;
 
 pub(crate) mod model;
@@ -17,14 +34,38 @@ pub mod test_util;
 /// Default amount of time events should stay in the stream. This is the 2x heartbeat interval, plus
 /// some wiggle room. Exported to make setting defaults easy
 pub const DEFAULT_EXPIRY_TIME: Duration = Duration::from_secs(70);
+/// Default prefix of the subjects lattice events are published on. {prefix}.evt.<lattice_id>.<type>
+pub const DEFAULT_EVENT_TOPIC_PREFIX: &str = "wasmbus";
+/// Default prefix of the subjects commands are published on. {prefix}.<lattice_id>
+pub const DEFAULT_COMMAND_TOPIC_PREFIX: &str = "wadm.cmd";
 /// Default topic to listen to for all lattice events
 pub const DEFAULT_EVENTS_TOPIC: &str = "wasmbus.evt.*.>";
 /// Default topic to listen to for all lattice events in a multitenant deployment
 pub const DEFAULT_MULTITENANT_EVENTS_TOPIC: &str = "*.wasmbus.evt.*.>";
-/// Default topic to listen to for all commands
+/// Default topic to listen to for all commands. Every lattice has its own topic and consumer, so a
+/// backlog of commands in one lattice doesn't hold up the others. wadm.cmd.<lattice_id>
 pub const DEFAULT_COMMANDS_TOPIC: &str = "wadm.cmd.*";
+/// Default topic to listen to for all commands in a multitenant deployment, where each account has
+/// its own lattices. wadm.cmd.<account_id>.<lattice_id>
+pub const DEFAULT_MULTITENANT_COMMANDS_TOPIC: &str = "wadm.cmd.*.*";
+/// Default topic that commands which failed on every attempt are sent to.
+/// wadm.dlq.<lattice_id>
+pub const DEFAULT_DEAD_LETTER_TOPIC: &str = "wadm.dlq.*";
+/// Default topic that messages which couldn't be decoded or kept failing are quarantined on.
+/// wadm.quarantine.<lattice_id>
+pub const DEFAULT_QUARANTINE_TOPIC: &str = "wadm.quarantine.*";
+/// Default topic that changes to the observed state of a lattice are logged on, along with the
+/// snapshots the log is compacted into. wadm.state_log.<lattice_id>.<changes|snapshot>
+pub const DEFAULT_STATE_LOG_TOPIC: &str = "wadm.state_log.*.*";
+/// Default topic that records of mutating API calls are sent to. wadm.audit.<lattice_id>
+pub const DEFAULT_AUDIT_TOPIC: &str = "wadm.audit.*";
+/// Default topic wadm replicas announce themselves on when sharding lattices between them
+pub const DEFAULT_MEMBERSHIP_TOPIC: &str = "wadm.members";
 /// Default topic to listen to for all status updates. wadm.status.<lattice_id>.<manifest_name>
 pub const DEFAULT_STATUS_TOPIC: &str = "wadm.status.*.*";
+/// Default topic that a report of each reconcile pass is sent to. Only the last report for each
+/// model is kept. wadm.reconcile.<lattice_id>.<manifest_name>
+pub const DEFAULT_RECONCILE_REPORT_TOPIC: &str = "wadm.reconcile.*.*";
 /// Default topic to listen to for all wadm event updates
 pub const DEFAULT_WADM_EVENTS_TOPIC: &str = "wadm.evt.*.>";
 /// Default internal wadm event consumer listen topic for the merged wadm and wasmbus events stream.
@@ -37,5 +78,21 @@ pub const MANAGED_BY_IDENTIFIER: &str = "wadm";
 pub const APP_SPEC_ANNOTATION: &str = "wasmcloud.dev/appspec";
 /// An annotation that denotes which scaler is managing a resource
 pub const SCALER_KEY: &str = "wasmcloud.dev/scaler";
+/// A host label that cordons the host, so scalers stop placing new components and providers on
+/// it. Set it to `true` to keep what is already running, or to [`CORDON_DRAIN_VALUE`] to also move
+/// managed workloads to other eligible hosts
+pub const CORDON_LABEL: &str = "wasmcloud.dev/cordon";
+/// The value of [`CORDON_LABEL`] that drains a cordoned host
+pub const CORDON_DRAIN_VALUE: &str = "drain";
+/// A host label with the most component instances the host should run. Scalers don't place new
+/// instances on a host once it runs this many, across all components
+pub const MAX_INSTANCES_LABEL: &str = "wasmcloud.dev/max-instances";
+/// A host label with the capacity of the host relative to other hosts, e.g. `4` for a host that
+/// can run twice as much as one labeled `2`. New instances are spread across hosts in proportion
+/// to their capacity
+pub const CAPACITY_LABEL: &str = "wasmcloud.dev/capacity";
+/// A host label with the memory in megabytes the host has for workloads. It is used as the
+/// relative capacity of hosts that don't set [`CAPACITY_LABEL`]
+pub const MEMORY_LABEL: &str = "wasmcloud.dev/memory-mb";
 /// The default link name. In the future, this will likely be pulled in from another crate
 pub const DEFAULT_LINK_NAME: &str = "default";
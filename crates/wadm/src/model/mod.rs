@@ -1,8 +1,12 @@
 //! Contains the internal storage definition of a manifest
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use wadm_types::{Manifest, LATEST_VERSION, VERSION_ANNOTATION_KEY};
+use wadm_types::{Manifest, NotificationRoute, LATEST_VERSION, VERSION_ANNOTATION_KEY};
 
 /// This struct represents a single manifest, with its version history. Internally these are stored
 /// as an indexmap keyed by version name
@@ -10,11 +14,48 @@ use wadm_types::{Manifest, LATEST_VERSION, VERSION_ANNOTATION_KEY};
 pub(crate) struct StoredManifest {
     // Ordering matters for how we store a manifest, so we need to use an index map to preserve
     // insertion order _and_ have quick access to specific versions
-    // NOTE(thomastaylor312): We probably should have a configurable limit for how many we keep
-    // around in history so they don't balloon forever
+    // How many versions are kept can be limited with the `max_versions` quota, see
+    // `prune_versions`
     manifests: IndexMap<String, Manifest>,
     // Set only if a version is deployed
     deployed_version: Option<String>,
+    // Incremented every time the deployed version changes, so anything watching a deploy can tell
+    // if it has been superseded
+    #[serde(default)]
+    deploy_generation: u64,
+    // The version that was deployed before the current one, used for rolling back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    previous_deployed_version: Option<String>,
+    // Overrides applied on top of the deployed version. These only last for the current
+    // deployment and are cleared the next time the manifest is deployed or undeployed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    overrides: Option<DeployOverrides>,
+    // The deployed version, with any overrides, with its images pinned to the digests they
+    // resolved to at deploy time. Like overrides, this only lasts for the current deployment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pinned: Option<PinnedImages>,
+    // Whether reconciliation of the deployed version is paused. Like overrides, this only lasts
+    // for the current deployment
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    paused: bool,
+}
+
+/// Overrides applied on top of a deployed version for a single deployment
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct DeployOverrides {
+    /// The JSON merge patch that was applied
+    patch: Value,
+    /// The deployed version with the patch applied
+    manifest: Manifest,
+}
+
+/// The images of a deployment pinned to digests
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PinnedImages {
+    /// Each image that was pinned, mapped to its reference with the digest
+    images: BTreeMap<String, String>,
+    /// The deployed version with its images pinned
+    manifest: Manifest,
 }
 
 impl StoredManifest {
@@ -49,17 +90,67 @@ impl StoredManifest {
         true
     }
 
+    /// Replaces every version with the given ones, keeping their order. The deployed version is
+    /// kept as it is since it is running, even if one of the given versions has the same name
+    pub fn replace_versions(&mut self, versions: impl IntoIterator<Item = Manifest>) {
+        let deployed = self
+            .deployed_version
+            .as_ref()
+            .and_then(|v| self.manifests.shift_remove_entry(v));
+        let mut replaced = StoredManifest::default();
+        replaced.manifests.extend(deployed.clone());
+        for manifest in versions {
+            match deployed.as_ref() {
+                // Move the deployed version to where it is in the given versions
+                Some((version, _)) if manifest.version() == version => {
+                    if let Some((version, kept)) = replaced.manifests.shift_remove_entry(version) {
+                        replaced.manifests.insert(version, kept);
+                    }
+                }
+                _ => {
+                    replaced.add_version(manifest);
+                }
+            }
+        }
+        self.manifests = replaced.manifests;
+        self.previous_deployed_version = None;
+    }
+
     /// Deletes the given version from the manifest. Returning true if it was deleted
     pub fn delete_version(&mut self, version: &str) -> bool {
+        if self.previous_deployed_version.as_deref() == Some(version) {
+            self.previous_deployed_version = None;
+        }
         self.manifests.shift_remove(version).is_some()
     }
 
+    /// Removes the oldest versions until at most `max` are left. The deployed version and the
+    /// current version are never removed, so more than `max` can be left. Returns the versions
+    /// that were removed
+    pub fn prune_versions(&mut self, max: usize) -> Vec<String> {
+        let current = self.current_version().to_owned();
+        let candidates: Vec<String> = self
+            .manifests
+            .keys()
+            .filter(|v| **v != current && !self.is_deployed(v))
+            .cloned()
+            .collect();
+        let mut removed = Vec::new();
+        for version in candidates {
+            if self.manifests.len() <= max {
+                break;
+            }
+            self.delete_version(&version);
+            removed.push(version);
+        }
+        removed
+    }
+
     /// Returns an iterator over all stored versions in creation order
     pub fn all_versions(&self) -> impl IntoIterator<Item = &String> {
         self.manifests.keys()
     }
 
-    #[allow(unused)]
     /// Returns a reference to the deployed version (if it is set)
     pub fn deployed_version(&self) -> Option<&str> {
         self.deployed_version.as_deref()
@@ -73,34 +164,86 @@ impl StoredManifest {
             .unwrap_or(false)
     }
 
+    /// Returns the version that was deployed before the currently deployed one (if any)
+    pub fn previous_deployed_version(&self) -> Option<&str> {
+        self.previous_deployed_version.as_deref()
+    }
+
+    /// Returns the current deploy generation. This changes every time the manifest is deployed,
+    /// undeployed or rolled back
+    pub fn deploy_generation(&self) -> u64 {
+        self.deploy_generation
+    }
+
     /// Sets this manifest as undeployed. Returning true if it was currently deployed
     pub fn undeploy(&mut self) -> bool {
+        self.deploy_generation += 1;
+        self.previous_deployed_version = None;
+        self.overrides = None;
+        self.pinned = None;
+        self.paused = false;
         self.deployed_version.take().is_some()
     }
 
+    /// Redeploys the previously deployed version, returning the version that was rolled back to.
+    /// Returns `None` if there is no previous version to roll back to.
+    ///
+    /// The previous version is cleared afterwards so a failing rollback can't bounce back to the
+    /// version it replaced
+    pub fn rollback(&mut self) -> Option<String> {
+        let previous = self
+            .previous_deployed_version
+            .take()
+            .filter(|v| self.manifests.contains_key(v))?;
+        self.deployed_version = Some(previous.clone());
+        self.deploy_generation += 1;
+        self.overrides = None;
+        self.pinned = None;
+        self.paused = false;
+        Some(previous)
+    }
+
     /// Attempts to deploy the given version. If none is passed or the version is "latest", it will
     /// deploy the latest version.
     ///
     /// Returns true if it was deployed, false otherwise
     pub fn deploy(&mut self, version: Option<String>) -> bool {
-        match version {
-            Some(v) if v == LATEST_VERSION => {
-                self.deployed_version = Some(self.current_version().to_owned());
-                true
-            }
-            None => {
-                self.deployed_version = Some(self.current_version().to_owned());
-                true
-            }
+        let version = match version {
+            Some(v) if v == LATEST_VERSION => self.current_version().to_owned(),
+            None => self.current_version().to_owned(),
             Some(v) => {
-                if self.manifests.contains_key(&v) {
-                    self.deployed_version = Some(v);
-                    true
-                } else {
-                    false
+                if !self.manifests.contains_key(&v) {
+                    return false;
                 }
+                v
+            }
+        };
+        // Redeploying the same version keeps whatever we would have rolled back to before
+        if self.deployed_version.as_deref() != Some(version.as_str()) {
+            if let Some(previous) = self.deployed_version.replace(version) {
+                self.previous_deployed_version = Some(previous);
             }
         }
+        self.deploy_generation += 1;
+        self.overrides = None;
+        self.pinned = None;
+        self.paused = false;
+        true
+    }
+
+    /// Pauses or resumes reconciliation of the deployed version. Returns false if nothing is
+    /// deployed
+    pub fn set_paused(&mut self, paused: bool) -> bool {
+        if self.deployed_version.is_none() {
+            return false;
+        }
+        self.paused = paused;
+        true
+    }
+
+    /// Returns whether reconciliation of the deployed version is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused
     }
 
     /// Returns a reference to the current manifest
@@ -123,11 +266,52 @@ impl StoredManifest {
         self.manifests.get(version)
     }
 
-    /// Returns the deployed version of the manifest (if it is deployed)
+    /// Returns the deployed version of the manifest (if it is deployed), with any overrides and
+    /// pinned images for the current deployment applied
     pub fn get_deployed(&self) -> Option<&Manifest> {
-        self.deployed_version
+        let deployed = self
+            .deployed_version
             .as_ref()
-            .and_then(|v| self.manifests.get(v))
+            .and_then(|v| self.manifests.get(v))?;
+        Some(
+            self.pinned
+                .as_ref()
+                .map(|pinned| &pinned.manifest)
+                .or(self.overrides.as_ref().map(|overrides| &overrides.manifest))
+                .unwrap_or(deployed),
+        )
+    }
+
+    /// Returns where notifications for the application are sent, from the deployed version or the
+    /// current version if nothing is deployed
+    pub fn notification_routes(&self) -> Vec<NotificationRoute> {
+        self.get_deployed()
+            .unwrap_or_else(|| self.get_current())
+            .notification_routes()
+    }
+
+    /// Sets the overrides for the current deployment. The manifest should be the deployed version
+    /// with the patch applied, as returned by [`apply_overrides`]
+    pub fn set_overrides(&mut self, patch: Value, manifest: Manifest) {
+        self.overrides = Some(DeployOverrides { patch, manifest });
+    }
+
+    /// Returns the JSON merge patch applied to the current deployment, if any
+    pub fn overrides(&self) -> Option<&Value> {
+        self.overrides.as_ref().map(|overrides| &overrides.patch)
+    }
+
+    /// Pins the images of the current deployment. The manifest should be the deployed version,
+    /// including any overrides, with the pinned images, as returned by
+    /// [`pin_images`](crate::oci::pin_images). Overrides must be set before pinning
+    pub fn set_pinned_images(&mut self, images: BTreeMap<String, String>, manifest: Manifest) {
+        self.pinned = Some(PinnedImages { images, manifest });
+    }
+
+    /// Returns each image pinned for the current deployment, mapped to its reference with the
+    /// digest
+    pub fn pinned_images(&self) -> Option<&BTreeMap<String, String>> {
+        self.pinned.as_ref().map(|pinned| &pinned.images)
     }
 
     /// Returns whether or not this is a new (empty) manifest
@@ -141,6 +325,191 @@ impl StoredManifest {
     }
 }
 
+/// Applies the given JSON merge patch (RFC 7386) to a manifest, returning the patched manifest.
+/// Patches can't change the name or version of the manifest
+pub(crate) fn apply_overrides(manifest: &Manifest, patch: &Value) -> Result<Manifest> {
+    if !patch.is_object() {
+        bail!("Overrides must be a JSON object");
+    }
+    let mut data = serde_json::to_value(manifest)?;
+    merge_patch(&mut data, patch);
+    let patched: Manifest = serde_json::from_value(data)
+        .map_err(|e| anyhow::anyhow!("Overrides produced an invalid manifest: {e}"))?;
+    if patched.metadata.name != manifest.metadata.name {
+        bail!("Overrides can't change the name of the manifest");
+    }
+    if patched.version() != manifest.version() {
+        bail!("Overrides can't change the version of the manifest");
+    }
+    Ok(patched)
+}
+
+/// The top level key of a manifest template that declares its variables
+const VARIABLES_KEY: &str = "variables";
+
+/// A variable declared by a manifest template
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VariableDeclaration {
+    /// The value to use when none is given. Variables without a default are required
+    #[serde(default)]
+    default: Option<Value>,
+    /// What the variable is for. Only for people reading the template
+    #[allow(dead_code)]
+    #[serde(default)]
+    description: Option<String>,
+}
+
+/// Returns whether the document is a manifest template, which declares its variables under a top
+/// level `variables` key
+pub(crate) fn is_template(document: &Value) -> bool {
+    document.get(VARIABLES_KEY).is_some()
+}
+
+/// Renders a manifest template, substituting the given values and the defaults of any variables
+/// without a value, then applies the overlay as a JSON merge patch (RFC 7386).
+///
+/// Variables are referenced in strings as `${name}` and `$${` is a literal `${`. A string that is
+/// only a reference, like `instances: ${replicas}`, takes the type of the value, so numbers and
+/// booleans can be templated too. Every variable used must be declared, every given value must be
+/// for a declared variable and variables without a default must be given a value. Documents that
+/// don't declare variables are used as-is, with only the overlay applied
+pub(crate) fn render_manifest(
+    mut document: Value,
+    values: &BTreeMap<String, String>,
+    overlay: Option<&Value>,
+) -> Result<Manifest> {
+    let declarations: BTreeMap<String, Option<VariableDeclaration>> = match document
+        .as_object_mut()
+        .and_then(|doc| doc.remove(VARIABLES_KEY))
+    {
+        Some(declared) => serde_json::from_value(declared)
+            .map_err(|e| anyhow::anyhow!("Invalid variable declarations: {e}"))?,
+        None => BTreeMap::new(),
+    };
+    if let Some(name) = values.keys().find(|name| !declarations.contains_key(*name)) {
+        bail!("A value was given for variable {name}, which the manifest doesn't declare");
+    }
+
+    let mut resolved: BTreeMap<&str, Value> = BTreeMap::new();
+    let mut missing = Vec::new();
+    for (name, declaration) in declarations.iter() {
+        match (
+            values.get(name),
+            declaration.as_ref().and_then(|d| d.default.as_ref()),
+        ) {
+            (Some(value), _) => {
+                resolved.insert(name, Value::String(value.to_owned()));
+            }
+            (None, Some(default)) => {
+                resolved.insert(name, default.to_owned());
+            }
+            (None, None) => missing.push(name.as_str()),
+        }
+    }
+    if !missing.is_empty() {
+        bail!(
+            "No value was given for required variables: {}",
+            missing.join(", ")
+        );
+    }
+    if !declarations.is_empty() {
+        substitute(&mut document, &resolved)?;
+    }
+
+    if let Some(overlay) = overlay {
+        if !overlay.is_object() {
+            bail!("Overlays must be a JSON object");
+        }
+        merge_patch(&mut document, overlay);
+    }
+    serde_json::from_value(document)
+        .map_err(|e| anyhow::anyhow!("Template produced an invalid manifest: {e}"))
+}
+
+/// Substitutes variable references in every string in the value
+fn substitute(value: &mut Value, variables: &BTreeMap<&str, Value>) -> Result<()> {
+    match value {
+        Value::String(s) => *value = substitute_str(s, variables)?,
+        Value::Array(items) => {
+            for item in items.iter_mut() {
+                substitute(item, variables)?;
+            }
+        }
+        Value::Object(map) => {
+            for item in map.values_mut() {
+                substitute(item, variables)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn substitute_str(s: &str, variables: &BTreeMap<&str, Value>) -> Result<Value> {
+    let lookup = |name: &str| {
+        variables
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Variable {name} is used but not declared"))
+    };
+    // A string that is only a reference keeps the type of the value
+    if let Some(name) = s
+        .strip_prefix("${")
+        .and_then(|rest| rest.strip_suffix('}'))
+        .filter(|name| !name.contains('}'))
+    {
+        return Ok(match lookup(name)? {
+            Value::String(value) => match serde_json::from_str::<Value>(value) {
+                Ok(typed @ (Value::Number(_) | Value::Bool(_))) => typed,
+                _ => Value::String(value.to_owned()),
+            },
+            other => other.to_owned(),
+        });
+    }
+
+    let mut rendered = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            rendered.push_str(&rest[..start - 1]);
+            rendered.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        rendered.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            bail!("Unterminated variable reference in '{s}'");
+        };
+        let name = &rest[start + 2..start + end];
+        match lookup(name)? {
+            Value::String(value) => rendered.push_str(value),
+            other => rendered.push_str(&other.to_string()),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(Value::String(rendered))
+}
+
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    // SAFETY: We just made sure the target is an object
+    let target = target.as_object_mut().unwrap();
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.as_str()).or_insert(Value::Null), value);
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -189,4 +558,202 @@ mod test {
             "Adding duplicate version should fail"
         );
     }
+
+    #[test]
+    fn test_rollback() {
+        let mut manifest = deserialize_yaml("../../tests/fixtures/manifests/simple2.yaml")
+            .expect("Should be able to parse");
+        let mut stored = StoredManifest::default();
+        for version in ["v0.0.1", "v0.0.2"] {
+            manifest
+                .metadata
+                .annotations
+                .insert(VERSION_ANNOTATION_KEY.to_string(), version.to_string());
+            assert!(stored.add_version(manifest.clone()));
+        }
+
+        assert!(
+            stored.rollback().is_none(),
+            "Shouldn't be able to roll back something that was never deployed"
+        );
+
+        assert!(stored.deploy(Some("v0.0.1".to_string())));
+        assert!(stored.previous_deployed_version().is_none());
+        let generation = stored.deploy_generation();
+
+        assert!(stored.deploy(None));
+        assert_eq!(stored.deployed_version(), Some("v0.0.2"));
+        assert_eq!(stored.previous_deployed_version(), Some("v0.0.1"));
+        assert!(
+            stored.deploy_generation() > generation,
+            "Deploying should bump the generation"
+        );
+
+        // Redeploying the same version shouldn't lose the previous version
+        assert!(stored.deploy(Some("v0.0.2".to_string())));
+        assert_eq!(stored.previous_deployed_version(), Some("v0.0.1"));
+
+        let generation = stored.deploy_generation();
+        assert_eq!(stored.rollback().as_deref(), Some("v0.0.1"));
+        assert_eq!(stored.deployed_version(), Some("v0.0.1"));
+        assert!(stored.deploy_generation() > generation);
+        assert!(
+            stored.rollback().is_none(),
+            "Should only be able to roll back once"
+        );
+
+        assert!(stored.deploy(None));
+        assert!(stored.set_paused(true));
+        assert!(stored.is_paused());
+        assert_eq!(stored.rollback().as_deref(), Some("v0.0.1"));
+        assert!(
+            !stored.is_paused(),
+            "Rolling back should resume reconciliation"
+        );
+
+        assert!(stored.undeploy());
+        assert!(
+            stored.previous_deployed_version().is_none(),
+            "Undeploying should clear the previous version"
+        );
+        assert!(
+            !stored.set_paused(true),
+            "Shouldn't be able to pause something that isn't deployed"
+        );
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mut manifest = deserialize_yaml("../../tests/fixtures/manifests/simple2.yaml")
+            .expect("Should be able to parse");
+        manifest
+            .metadata
+            .annotations
+            .insert(VERSION_ANNOTATION_KEY.to_string(), "v0.0.1".to_string());
+        let mut stored = StoredManifest::default();
+        assert!(stored.add_version(manifest.clone()));
+        assert!(stored.deploy(None));
+
+        let patch = serde_json::json!({
+            "metadata": {
+                "annotations": {
+                    "description": null,
+                    "team": "ops"
+                }
+            }
+        });
+        let patched = apply_overrides(&manifest, &patch).expect("Should be able to apply patch");
+        assert!(patched.description().is_none());
+        assert_eq!(
+            patched.metadata.annotations.get("team").map(String::as_str),
+            Some("ops")
+        );
+        assert_eq!(
+            patched.spec, manifest.spec,
+            "Untouched fields should be kept"
+        );
+
+        stored.set_overrides(patch.clone(), patched.clone());
+        assert_eq!(stored.get_deployed(), Some(&patched));
+        assert_eq!(stored.overrides(), Some(&patch));
+        assert_eq!(
+            stored.get_version("v0.0.1"),
+            Some(&manifest),
+            "The stored version shouldn't be changed"
+        );
+
+        assert!(stored.deploy(None));
+        assert!(
+            stored.overrides().is_none(),
+            "Deploying again should clear the overrides"
+        );
+        assert_eq!(stored.get_deployed(), Some(&manifest));
+
+        assert!(apply_overrides(
+            &manifest,
+            &serde_json::json!({"metadata": {"name": "other"}})
+        )
+        .is_err());
+        assert!(apply_overrides(
+            &manifest,
+            &serde_json::json!({"metadata": {"annotations": {"version": "v9"}}})
+        )
+        .is_err());
+        assert!(apply_overrides(&manifest, &serde_json::json!({"spec": null})).is_err());
+        assert!(apply_overrides(&manifest, &serde_json::json!([])).is_err());
+    }
+
+    #[test]
+    fn test_render_template() {
+        let template: Value = serde_yaml::from_str(
+            r#"
+apiVersion: core.oam.dev/v1beta1
+kind: Application
+metadata:
+  name: echo
+  annotations:
+    version: ${version}
+variables:
+  version:
+    description: The version of the app
+  replicas:
+    default: 1
+  image_tag:
+    default: "0.1.0"
+spec:
+  components:
+    - name: echo
+      type: component
+      properties:
+        image: ghcr.io/wasmcloud/echo:${image_tag}
+        config:
+          - name: greeting
+            properties:
+              message: "$${not_a_variable}"
+      traits:
+        - type: spreadscaler
+          properties:
+            instances: ${replicas}
+"#,
+        )
+        .unwrap();
+        assert!(is_template(&template));
+
+        let values = BTreeMap::from([
+            ("version".to_string(), "v1.0.0".to_string()),
+            ("replicas".to_string(), "5".to_string()),
+        ]);
+        let overlay = serde_json::json!({"metadata": {"annotations": {"env": "prod"}}});
+        let manifest = render_manifest(template.clone(), &values, Some(&overlay))
+            .expect("Should be able to render the template");
+        assert_eq!(manifest.version(), "v1.0.0");
+        assert_eq!(
+            manifest.metadata.annotations.get("env").map(String::as_str),
+            Some("prod")
+        );
+        let rendered = serde_json::to_value(&manifest).unwrap();
+        let component = &rendered["spec"]["components"][0];
+        assert_eq!(
+            component["properties"]["image"],
+            "ghcr.io/wasmcloud/echo:0.1.0"
+        );
+        assert_eq!(
+            component["properties"]["config"][0]["properties"]["message"],
+            "${not_a_variable}"
+        );
+        assert_eq!(component["traits"][0]["properties"]["instances"], 5);
+
+        assert!(
+            render_manifest(template.clone(), &BTreeMap::new(), None).is_err(),
+            "Variables without a default are required"
+        );
+        let mut undeclared = values.clone();
+        undeclared.insert("other".to_string(), "value".to_string());
+        assert!(render_manifest(template.clone(), &undeclared, None).is_err());
+        let mut unknown = template.clone();
+        unknown["spec"]["components"][0]["properties"]["image"] =
+            Value::String("ghcr.io/wasmcloud/echo:${tag}".to_string());
+        assert!(render_manifest(unknown, &values, None).is_err());
+        assert!(render_manifest(template, &values, Some(&serde_json::json!([]))).is_err());
+    }
 }
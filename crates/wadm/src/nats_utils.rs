@@ -1,10 +1,45 @@
 //! Helper utilities for interacting with NATS
+use std::time::Duration;
+
+use async_nats::{
+    jetstream::{
+        context::{PublishError, PublishErrorKind},
+        publish::PublishAck,
+        Context,
+    },
+    HeaderMap,
+};
+use bytes::Bytes;
+use tracing::warn;
+
 const EVENT_SUBJECT: &str = "evt";
 
+/// The number of times [`ensure_send`] tries to publish a message before giving up
+const SEND_ATTEMPTS: u32 = 3;
+/// How long to wait before retrying a failed publish. This doubles with each attempt
+const SEND_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// An error returned when a message couldn't be persisted to a stream
+#[derive(Debug, thiserror::Error)]
+pub enum SendError {
+    /// No stream captures the subject, or its leader didn't respond
+    #[error("No stream acknowledged the message on {subject} after {attempts} attempts")]
+    NoStream { subject: String, attempts: u32 },
+    /// The stream didn't acknowledge the message in time. It may still have been stored
+    #[error("Timed out waiting for the stream to acknowledge the message on {subject} after {attempts} attempts")]
+    TimedOut { subject: String, attempts: u32 },
+    /// The stream rejected the message, or it couldn't be sent
+    #[error("Unable to publish the message on {subject}: {source}")]
+    Rejected {
+        subject: String,
+        #[source]
+        source: PublishError,
+    },
+}
+
 /// A parser for NATS subjects that parses out a lattice ID for any given subject
 pub struct LatticeIdParser {
-    // NOTE(thomastaylor312): We don't actually support specific prefixes right now, but we could in
-    // the future as we already do for control topics. So this is just trying to future proof
+    /// The prefix events are published under, which can have more than one token
     prefix: String,
     multitenant: bool,
 }
@@ -15,7 +50,7 @@ impl LatticeIdParser {
     /// (e.g. `A****.wasmbus.evt.{lattice-id}.>`) if it doesn't match the normally expected pattern
     pub fn new(prefix: &str, multitenant: bool) -> LatticeIdParser {
         LatticeIdParser {
-            prefix: prefix.to_owned(),
+            prefix: prefix.trim_matches('.').to_owned(),
             multitenant,
         }
     }
@@ -24,38 +59,39 @@ impl LatticeIdParser {
     /// the account ID if it is multitenant.
     /// Returns None if it couldn't parse the topic
     pub fn parse(&self, subject: &str) -> Option<LatticeInformation> {
-        let separated: Vec<&str> = subject.split('.').collect();
         // For reference, topics look like the following:
         //
         // Normal: `{prefix}.evt.{lattice-id}.{event-type}`
         // Multitenant: `{account-id}.{prefix}.evt.{lattice-id}.{event-type}`
         //
         // Note that the account ID should be prefaced with an `A`
-        match separated[..] {
-            [prefix, evt, lattice_id, _event_type]
-                if prefix == self.prefix && evt == EVENT_SUBJECT =>
-            {
-                Some(LatticeInformation {
-                    lattice_id: lattice_id.to_owned(),
-                    multitenant_prefix: None,
-                    prefix: self.prefix.clone(),
-                })
-            }
-            [account_id, prefix, evt, lattice_id, _event_type]
-                if self.multitenant
-                    && prefix == self.prefix
-                    && evt == EVENT_SUBJECT
-                    && account_id.starts_with('A') =>
-            {
-                Some(LatticeInformation {
-                    lattice_id: lattice_id.to_owned(),
-                    multitenant_prefix: Some(account_id.to_owned()),
-                    prefix: self.prefix.clone(),
-                })
+        let (multitenant_prefix, rest) = match self.strip_prefix(subject) {
+            Some(rest) => (None, rest),
+            None if self.multitenant => {
+                let (account_id, rest) = subject.split_once('.')?;
+                if !account_id.starts_with('A') {
+                    return None;
+                }
+                (Some(account_id.to_owned()), self.strip_prefix(rest)?)
             }
+            None => return None,
+        };
+        match rest.split('.').collect::<Vec<_>>()[..] {
+            [evt, lattice_id, _event_type] if evt == EVENT_SUBJECT => Some(LatticeInformation {
+                lattice_id: lattice_id.to_owned(),
+                multitenant_prefix,
+                prefix: self.prefix.clone(),
+            }),
             _ => None,
         }
     }
+
+    /// Returns the rest of the subject after the prefix, if the subject starts with the prefix
+    fn strip_prefix<'a>(&self, subject: &'a str) -> Option<&'a str> {
+        subject
+            .strip_prefix(self.prefix.as_str())
+            .and_then(|rest| rest.strip_prefix('.'))
+    }
 }
 
 /// Simple helper struct for returning lattice information from a parsed event topic
@@ -90,9 +126,116 @@ impl LatticeInformation {
     }
 }
 
+/// Returns the subjects lattice events are published on for the given prefix, for use as stream
+/// subjects
+///
+/// Normal: `{prefix}.evt.*.>`
+/// Multitenant: `*.{prefix}.evt.*.>`
+pub fn event_subjects(prefix: &str, multitenant: bool) -> String {
+    let prefix = prefix.trim_matches('.');
+    match multitenant {
+        true => format!("*.{prefix}.{EVENT_SUBJECT}.*.>"),
+        false => format!("{prefix}.{EVENT_SUBJECT}.*.>"),
+    }
+}
+
+/// Returns the subjects commands for every lattice are published on for the given prefix, for use
+/// as stream subjects. See [`lattice_subject`]
+pub fn command_subjects(prefix: &str, multitenant: bool) -> String {
+    let prefix = prefix.trim_matches('.');
+    match multitenant {
+        true => format!("{prefix}.*.*"),
+        false => format!("{prefix}.*"),
+    }
+}
+
+/// Returns the subject under the given prefix that wadm uses for a lattice, such as the command
+/// topic of a lattice under `wadm.cmd`. In multitenant mode the account ID comes before the lattice
+/// ID, so lattices with the same ID in different accounts don't share subjects
+///
+/// Normal: `{prefix}.{lattice-id}`
+/// Multitenant: `{prefix}.{account-id}.{lattice-id}`
+pub fn lattice_subject(prefix: &str, lattice_id: &str, multitenant_prefix: Option<&str>) -> String {
+    let prefix = prefix.trim_end_matches('.');
+    match multitenant_prefix {
+        Some(account_id) => format!("{prefix}.{account_id}.{lattice_id}"),
+        None => format!("{prefix}.{lattice_id}"),
+    }
+}
+
+/// Publishes a message to JetStream and waits for the stream to acknowledge it, so the message is
+/// known to be persisted rather than only sent. Publishes that time out or that no stream responds
+/// to (like while a stream is electing a leader) are retried a few times before giving up.
+///
+/// NOTE: A publish that timed out may have been stored anyway, so retries can store a message
+/// twice unless it has a message ID header for the stream to deduplicate it with
+pub async fn ensure_send(
+    context: &Context,
+    subject: String,
+    headers: HeaderMap,
+    payload: impl Into<Bytes>,
+) -> Result<PublishAck, SendError> {
+    let payload = payload.into();
+    let mut attempt = 1;
+    loop {
+        let res = match context
+            .publish_with_headers(subject.clone(), headers.clone(), payload.clone())
+            .await
+        {
+            Ok(ack) => ack.await,
+            Err(e) => Err(e),
+        };
+        let e = match res {
+            Ok(ack) => return Ok(ack),
+            Err(e) if !is_retryable(e.kind()) => {
+                return Err(SendError::Rejected { subject, source: e })
+            }
+            Err(e) => e,
+        };
+        if attempt >= SEND_ATTEMPTS {
+            return Err(match e.kind() {
+                PublishErrorKind::TimedOut => SendError::TimedOut {
+                    subject,
+                    attempts: attempt,
+                },
+                _ => SendError::NoStream {
+                    subject,
+                    attempts: attempt,
+                },
+            });
+        }
+        warn!(error = %e, %subject, %attempt, "Message wasn't acknowledged by a stream, retrying");
+        tokio::time::sleep(SEND_RETRY_DELAY * 2u32.pow(attempt - 1)).await;
+        attempt += 1;
+    }
+}
+
+/// Whether a publish that failed this way could succeed if it is tried again
+fn is_retryable(kind: PublishErrorKind) -> bool {
+    // NOTE: A publish that no stream responds to shows up as a missing stream
+    matches!(
+        kind,
+        PublishErrorKind::TimedOut
+            | PublishErrorKind::StreamNotFound
+            | PublishErrorKind::BrokenPipe
+    )
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{
+        DEFAULT_COMMANDS_TOPIC, DEFAULT_COMMAND_TOPIC_PREFIX, DEFAULT_EVENT_TOPIC_PREFIX,
+        DEFAULT_MULTITENANT_EVENTS_TOPIC,
+    };
+
+    #[test]
+    fn only_retries_transient_publish_errors() {
+        assert!(is_retryable(PublishErrorKind::TimedOut));
+        assert!(is_retryable(PublishErrorKind::StreamNotFound));
+        assert!(!is_retryable(PublishErrorKind::WrongLastSequence));
+        assert!(!is_retryable(PublishErrorKind::Other));
+    }
 
     #[test]
     fn test_valid_subjects() {
@@ -152,6 +295,15 @@ mod test {
             "ACCOUNTID.wasmbus.evt.blahblah.*",
             "Should return the right event subject"
         );
+        assert_eq!(
+            lattice_subject("wadm.cmd", res.lattice_id(), res.multitenant_prefix()),
+            "wadm.cmd.ACCOUNTID.blahblah",
+            "Lattice subjects should be scoped to the account in multitenant mode"
+        );
+        assert_eq!(
+            lattice_subject("wadm.cmd.", "blahblah", None),
+            "wadm.cmd.blahblah"
+        );
     }
 
     #[test]
@@ -189,4 +341,36 @@ mod test {
             "Shouldn't parse long topic"
         );
     }
+
+    #[test]
+    fn parses_custom_prefixes() {
+        let parser = LatticeIdParser::new("edge.wasmbus.", true);
+        let res = parser
+            .parse("edge.wasmbus.evt.default.host_heartbeat")
+            .expect("Should parse a prefix with more than one token");
+        assert_eq!(res.lattice_id(), "default");
+        assert_eq!(res.event_subject(), "edge.wasmbus.evt.default.*");
+        let res = parser
+            .parse("ACCOUNTID.edge.wasmbus.evt.default.host_heartbeat")
+            .expect("Should parse a multitenant topic with a custom prefix");
+        assert_eq!(res.multitenant_prefix(), Some("ACCOUNTID"));
+        assert!(
+            parser.parse("wasmbus.evt.default.host_heartbeat").is_none(),
+            "Shouldn't parse the default prefix when a custom one is set"
+        );
+
+        assert_eq!(
+            event_subjects("edge.wasmbus", false),
+            "edge.wasmbus.evt.*.>"
+        );
+        assert_eq!(
+            event_subjects(DEFAULT_EVENT_TOPIC_PREFIX, true),
+            DEFAULT_MULTITENANT_EVENTS_TOPIC
+        );
+        assert_eq!(
+            command_subjects(DEFAULT_COMMAND_TOPIC_PREFIX, false),
+            DEFAULT_COMMANDS_TOPIC
+        );
+        assert_eq!(command_subjects("edge.cmd.", true), "edge.cmd.*.*");
+    }
 }
@@ -0,0 +1,263 @@
+//! Lifecycle notifications about what wadm does to applications, like deploying, rolling back or
+//! scaling them. These are meant for teams that want to pipe wadm activity into chat or incident
+//! tooling, so unlike the events wadm publishes to its own streams they are flat JSON documents with
+//! a human readable `text` field, which chat webhooks like Slack's display as is
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{commands::Command, publisher::Publisher};
+
+/// Something that happened to an application
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LifecycleEvent {
+    /// A version of the application was deployed
+    Deployed { version: String },
+    /// The application was undeployed
+    Undeployed,
+    /// A version of the application didn't finish deploying, so the previous one was deployed
+    RolledBack {
+        from_version: String,
+        to_version: String,
+    },
+    /// A component or provider of the application was scaled on a host. Providers are scaled to 1
+    /// when they are started and to 0 when they are stopped
+    Scaled {
+        id: String,
+        host_id: String,
+        instances: u32,
+    },
+    /// The application failed to reconcile
+    ReconcileFailed { message: String },
+}
+
+/// A notification about something that happened to an application in a lattice
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LifecycleNotification {
+    pub lattice_id: String,
+    pub application: String,
+    pub time: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: LifecycleEvent,
+    /// A human readable summary of the notification
+    pub text: String,
+}
+
+impl LifecycleNotification {
+    /// Creates a notification that the event happened just now
+    pub fn new(lattice_id: &str, application: &str, event: LifecycleEvent) -> Self {
+        let text = match &event {
+            LifecycleEvent::Deployed { version } => {
+                format!("Deployed version {version} of {application} in lattice {lattice_id}")
+            }
+            LifecycleEvent::Undeployed => {
+                format!("Undeployed {application} in lattice {lattice_id}")
+            }
+            LifecycleEvent::RolledBack {
+                from_version,
+                to_version,
+            } => format!(
+                "Rolled back {application} in lattice {lattice_id} from version {from_version} to {to_version}"
+            ),
+            LifecycleEvent::Scaled {
+                id,
+                host_id,
+                instances,
+            } => format!(
+                "Scaled {id} of {application} in lattice {lattice_id} to {instances} on host {host_id}"
+            ),
+            LifecycleEvent::ReconcileFailed { message } => {
+                format!("Failed to reconcile {application} in lattice {lattice_id}: {message}")
+            }
+        };
+        LifecycleNotification {
+            lattice_id: lattice_id.to_owned(),
+            application: application.to_owned(),
+            time: Utc::now(),
+            event,
+            text,
+        }
+    }
+
+    /// Returns the notification for a command that scales a component or provider, if it is one
+    pub fn for_command(lattice_id: &str, command: &Command) -> Option<Self> {
+        let (application, event) = match command {
+            Command::ScaleComponent(cmd) => (
+                &cmd.model_name,
+                LifecycleEvent::Scaled {
+                    id: cmd.component_id.clone(),
+                    host_id: cmd.host_id.clone(),
+                    instances: cmd.count,
+                },
+            ),
+            Command::StartProvider(cmd) => (
+                &cmd.model_name,
+                LifecycleEvent::Scaled {
+                    id: cmd.provider_id.clone(),
+                    host_id: cmd.host_id.clone(),
+                    instances: 1,
+                },
+            ),
+            Command::StopProvider(cmd) => (
+                &cmd.model_name,
+                LifecycleEvent::Scaled {
+                    id: cmd.provider_id.clone(),
+                    host_id: cmd.host_id.clone(),
+                    instances: 0,
+                },
+            ),
+            _ => return None,
+        };
+        Some(LifecycleNotification::new(lattice_id, application, event))
+    }
+}
+
+/// Anything lifecycle notifications can be sent to
+#[async_trait]
+pub trait NotificationSink {
+    /// Sends the notification to the sink
+    async fn send(&self, notification: &LifecycleNotification) -> anyhow::Result<()>;
+}
+
+/// A sink that publishes notifications as JSON to a single subject
+pub struct SubjectSink<P> {
+    publisher: P,
+    subject: String,
+}
+
+impl<P: Publisher> SubjectSink<P> {
+    pub fn new(publisher: P, subject: &str) -> SubjectSink<P> {
+        SubjectSink {
+            publisher,
+            subject: subject.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl<P: Publisher + Send + Sync> NotificationSink for SubjectSink<P> {
+    async fn send(&self, notification: &LifecycleNotification) -> anyhow::Result<()> {
+        self.publisher
+            .publish(serde_json::to_vec(notification)?, Some(&self.subject))
+            .await
+    }
+}
+
+/// Sends lifecycle notifications to all of the configured sinks. Notifications are sent in the
+/// background and failures are only logged, so a broken sink never holds up reconciliation
+#[derive(Clone, Default)]
+pub struct LifecycleNotifier {
+    sinks: Vec<Arc<dyn NotificationSink + Send + Sync>>,
+}
+
+impl LifecycleNotifier {
+    /// Creates a notifier without any sinks, which drops every notification
+    pub fn new() -> LifecycleNotifier {
+        LifecycleNotifier::default()
+    }
+
+    /// Adds a sink that every notification is sent to
+    pub fn with_sink(mut self, sink: impl NotificationSink + Send + Sync + 'static) -> Self {
+        self.sinks.push(Arc::new(sink));
+        self
+    }
+
+    /// Returns whether any sinks are configured
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Sends a notification about the event to every sink
+    pub fn notify(&self, lattice_id: &str, application: &str, event: LifecycleEvent) {
+        if self.is_empty() {
+            return;
+        }
+        self.send(LifecycleNotification::new(lattice_id, application, event));
+    }
+
+    /// Sends the notification to every sink
+    pub fn send(&self, notification: LifecycleNotification) {
+        if self.is_empty() {
+            return;
+        }
+        let sinks = self.sinks.clone();
+        tokio::spawn(async move {
+            for sink in sinks {
+                if let Err(e) = sink.send(&notification).await {
+                    warn!(error = ?e, text = %notification.text, "Unable to send lifecycle notification");
+                }
+            }
+        });
+    }
+}
+
+impl std::fmt::Debug for LifecycleNotifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LifecycleNotifier")
+            .field("sinks", &self.sinks.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::commands::StopProvider;
+
+    struct ChannelSink(mpsc::UnboundedSender<LifecycleNotification>);
+
+    #[async_trait]
+    impl NotificationSink for ChannelSink {
+        async fn send(&self, notification: &LifecycleNotification) -> anyhow::Result<()> {
+            self.0.send(notification.clone())?;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_lifecycle_notifications() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let notifier = LifecycleNotifier::new().with_sink(ChannelSink(tx));
+        notifier.notify(
+            "default",
+            "echo",
+            LifecycleEvent::RolledBack {
+                from_version: "v2".to_string(),
+                to_version: "v1".to_string(),
+            },
+        );
+        let notification = rx.recv().await.expect("Should have sent a notification");
+        assert_eq!(
+            notification.text,
+            "Rolled back echo in lattice default from version v2 to v1"
+        );
+        let json = serde_json::to_value(&notification).unwrap();
+        assert_eq!(json["type"], "rolled_back");
+        assert_eq!(json["from_version"], "v2");
+        assert_eq!(json["application"], "echo");
+
+        let stop = Command::StopProvider(StopProvider {
+            provider_id: "echo-httpserver".to_string(),
+            host_id: "host".to_string(),
+            model_name: "echo".to_string(),
+            ..Default::default()
+        });
+        let notification = LifecycleNotification::for_command("default", &stop)
+            .expect("Stopping a provider should be a scaling notification");
+        assert_eq!(
+            notification.event,
+            LifecycleEvent::Scaled {
+                id: "echo-httpserver".to_string(),
+                host_id: "host".to_string(),
+                instances: 0,
+            }
+        );
+    }
+}
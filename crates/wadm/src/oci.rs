@@ -0,0 +1,174 @@
+//! Resolution of OCI image references to the digests they point to. Tags like `latest` or `1.0`
+//! can be re-pushed at any time, so when digest pinning is enabled the images in a manifest are
+//! resolved once at deploy time and the digests are recorded in the stored manifest. Every host
+//! then starts the exact same artifact, and reconciling the app later doesn't pick up a different
+//! artifact because a tag moved
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use wadm_types::{CapabilityProperties, ComponentProperties, Manifest, Properties};
+
+/// The registry that references without a registry are pulled from
+pub const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// A parsed OCI image reference, like `ghcr.io/wasmcloud/http-server:0.23.0`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageReference {
+    /// The registry the image is stored in, like `ghcr.io` or `localhost:5000`
+    pub registry: String,
+    /// The repository within the registry, like `wasmcloud/http-server`
+    pub repository: String,
+    /// The tag of the image, if one was given
+    pub tag: Option<String>,
+    /// The digest of the image, like `sha256:...`, if one was given
+    pub digest: Option<String>,
+}
+
+impl ImageReference {
+    /// Returns the tag or digest to fetch the manifest of the image by
+    pub fn manifest_reference(&self) -> &str {
+        self.digest
+            .as_deref()
+            .or(self.tag.as_deref())
+            .unwrap_or("latest")
+    }
+}
+
+impl FromStr for ImageReference {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let reference = s.strip_prefix("oci://").unwrap_or(s);
+        if reference.is_empty() || reference.contains("://") {
+            anyhow::bail!("'{s}' is not an OCI image reference");
+        }
+        let (name, digest) = match reference.split_once('@') {
+            Some((name, digest)) => (name, Some(digest.to_owned())),
+            None => (reference, None),
+        };
+        // A tag can only be in the last path segment, since registries can have a port
+        let (name, tag) = match name.rsplit_once(':') {
+            Some((repo, tag)) if !tag.contains('/') => (repo, Some(tag.to_owned())),
+            _ => (name, None),
+        };
+        let (registry, repository) = match name.split_once('/') {
+            Some((first, rest))
+                if first.contains('.') || first.contains(':') || first == "localhost" =>
+            {
+                (first.to_owned(), rest.to_owned())
+            }
+            _ if name.contains('/') => (DEFAULT_REGISTRY.to_owned(), name.to_owned()),
+            // Official images on Docker Hub live under `library`
+            _ => (DEFAULT_REGISTRY.to_owned(), format!("library/{name}")),
+        };
+        if repository.is_empty() {
+            anyhow::bail!("'{s}' doesn't name a repository");
+        }
+        Ok(ImageReference {
+            registry,
+            repository,
+            tag,
+            digest,
+        })
+    }
+}
+
+/// Anything that can look up the digest an image reference currently points to
+#[async_trait]
+pub trait DigestResolver {
+    /// Returns the digest of the manifest the given reference points to, like `sha256:...`
+    async fn resolve_digest(&self, image: &ImageReference) -> Result<String>;
+}
+
+/// Resolves every image in the manifest that isn't already pinned to a digest, returning the
+/// manifest with the digests added to its images and a map of each original image to its pinned
+/// reference. Images that aren't OCI references, like `file://` paths, are left alone
+pub async fn pin_images(
+    manifest: &Manifest,
+    resolver: &(dyn DigestResolver + Send + Sync),
+) -> Result<(Manifest, BTreeMap<String, String>)> {
+    let mut pinned = manifest.clone();
+    let mut images: BTreeMap<String, String> = BTreeMap::new();
+    for component in pinned.spec.components.iter_mut() {
+        let (Properties::Component {
+            properties: ComponentProperties { image, .. },
+        }
+        | Properties::Capability {
+            properties: CapabilityProperties { image, .. },
+        }) = &mut component.properties;
+        let Some(image) = image.as_mut() else {
+            continue;
+        };
+        if let Some(resolved) = images.get(image.as_str()) {
+            *image = resolved.clone();
+            continue;
+        }
+        let Ok(reference) = image.parse::<ImageReference>() else {
+            continue;
+        };
+        if reference.digest.is_some() {
+            continue;
+        }
+        let digest = resolver
+            .resolve_digest(&reference)
+            .await
+            .with_context(|| format!("Unable to resolve the digest of image {image}"))?;
+        let resolved = format!("{image}@{digest}");
+        images.insert(image.clone(), resolved.clone());
+        *image = resolved;
+    }
+    Ok((pinned, images))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct FakeResolver;
+
+    #[async_trait]
+    impl DigestResolver for FakeResolver {
+        async fn resolve_digest(&self, image: &ImageReference) -> Result<String> {
+            Ok(format!("sha256:{}", image.repository.replace('/', "-")))
+        }
+    }
+
+    #[tokio::test]
+    async fn pins_images_to_digests() {
+        let reference: ImageReference = "localhost:5000/echo:0.1.0".parse().unwrap();
+        assert_eq!(reference.registry, "localhost:5000");
+        assert_eq!(reference.repository, "echo");
+        assert_eq!(reference.manifest_reference(), "0.1.0");
+        let reference: ImageReference = "redis".parse().unwrap();
+        assert_eq!(reference.registry, DEFAULT_REGISTRY);
+        assert_eq!(reference.repository, "library/redis");
+        assert_eq!(reference.manifest_reference(), "latest");
+        assert!("file://./echo.wasm".parse::<ImageReference>().is_err());
+
+        let manifest: Manifest = serde_yaml::from_slice(
+            &std::fs::read("../../tests/fixtures/manifests/simple.yaml").unwrap(),
+        )
+        .expect("Should be able to parse manifest");
+        let (pinned, images) = pin_images(&manifest, &FakeResolver).await.unwrap();
+        assert_eq!(images.len(), 2, "Both images should have been pinned");
+        for component in pinned.spec.components.iter() {
+            let (Properties::Component {
+                properties: ComponentProperties { image, .. },
+            }
+            | Properties::Capability {
+                properties: CapabilityProperties { image, .. },
+            }) = &component.properties;
+            let image = image.as_deref().unwrap();
+            assert!(image.contains("@sha256:"), "{image} should be pinned");
+        }
+
+        let (_, repinned) = pin_images(&pinned, &FakeResolver).await.unwrap();
+        assert!(
+            repinned.is_empty(),
+            "Images that are already pinned shouldn't be resolved again"
+        );
+    }
+}
@@ -0,0 +1,312 @@
+//! Integration with external policy services. Before commands that start components or providers
+//! or put links are published, each one is sent to a policy engine along with the manifest it came
+//! from, and commands the engine denies are dropped. This lets operators plug in OPA style services
+//! that decide what is allowed to run in a lattice without changing wadm
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use async_nats::jetstream::kv::Store as KvStore;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, warn};
+use wadm_types::Manifest;
+
+use crate::{
+    commands::Command,
+    publisher::{Encoding, Publisher},
+    server::ModelStorage,
+    trace_context::TraceContext,
+};
+
+/// The default amount of time to wait for a policy engine to answer a request
+pub const DEFAULT_POLICY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A request for a decision about a command wadm is about to publish
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyRequest {
+    pub lattice_id: String,
+    /// The command wadm wants to publish
+    pub command: Command,
+    /// The deployed version of the manifest the command is for, if it could be found
+    #[serde(default)]
+    pub manifest: Option<Manifest>,
+}
+
+/// The decision of a policy engine about a command
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyResponse {
+    /// Whether the command can be published
+    pub allowed: bool,
+    /// Why the command was denied, if it was
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Anything that can decide whether a command is allowed
+#[async_trait]
+pub trait PolicyEngine {
+    /// Returns the decision for the given request
+    async fn evaluate(&self, request: &PolicyRequest) -> Result<PolicyResponse>;
+}
+
+/// A [`PolicyEngine`] that sends each request as JSON to a NATS subject and expects a JSON
+/// [`PolicyResponse`] as the reply
+#[derive(Clone)]
+pub struct NatsPolicyEngine {
+    client: async_nats::Client,
+    subject: String,
+    timeout: Duration,
+}
+
+impl NatsPolicyEngine {
+    pub fn new(client: async_nats::Client, subject: &str) -> NatsPolicyEngine {
+        NatsPolicyEngine {
+            client,
+            subject: subject.to_owned(),
+            timeout: DEFAULT_POLICY_TIMEOUT,
+        }
+    }
+
+    /// Sets how long to wait for the policy engine to reply
+    pub fn with_timeout(mut self, timeout: Duration) -> NatsPolicyEngine {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[async_trait]
+impl PolicyEngine for NatsPolicyEngine {
+    #[instrument(level = "debug", skip_all, fields(subject = %self.subject))]
+    async fn evaluate(&self, request: &PolicyRequest) -> Result<PolicyResponse> {
+        let payload = serde_json::to_vec(request)?;
+        // Send the trace context along so the decision shows up in the same trace as the command
+        let mut headers = TraceContext::current()
+            .map(|trace_context| trace_context.to_headers())
+            .unwrap_or_default();
+        Encoding::Json.insert_header(&mut headers);
+        let reply = tokio::time::timeout(
+            self.timeout,
+            Publisher::request(&self.client, payload, Some(&self.subject), headers),
+        )
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out waiting for policy decision"))??;
+        serde_json::from_slice(&reply)
+            .map_err(|e| anyhow::anyhow!("Policy engine sent an invalid response: {e}"))
+    }
+}
+
+/// Checks the commands published for a lattice with a [`PolicyEngine`]. Cloning is cheap
+#[derive(Clone)]
+pub struct PolicyGate {
+    engine: Arc<dyn PolicyEngine + Send + Sync>,
+    manifests: Option<(ModelStorage, Option<String>)>,
+}
+
+impl PolicyGate {
+    /// Creates a gate that asks the given engine about every command that starts something or
+    /// puts a link
+    pub fn new(engine: impl PolicyEngine + Send + Sync + 'static) -> PolicyGate {
+        PolicyGate {
+            engine: Arc::new(engine),
+            manifests: None,
+        }
+    }
+
+    /// Looks up the manifests that commands are for in the given store, so they can be sent to the
+    /// engine. Without a store, requests are sent without a manifest
+    pub fn with_manifest_store(
+        mut self,
+        store: KvStore,
+        multitenant_prefix: Option<&str>,
+    ) -> PolicyGate {
+        self.manifests = Some((
+            ModelStorage::new(store),
+            multitenant_prefix.map(ToOwned::to_owned),
+        ));
+        self
+    }
+
+    /// Returns the commands that are allowed, along with the name of the model and the reason for
+    /// every command that was denied. Commands are denied if the engine can't be reached, so a
+    /// broken policy service never lets anything through
+    pub(crate) async fn check(
+        &self,
+        lattice_id: &str,
+        commands: Vec<Command>,
+    ) -> (Vec<Command>, Vec<(String, String)>) {
+        let mut manifests: HashMap<String, Option<Manifest>> = HashMap::new();
+        let mut allowed = Vec::with_capacity(commands.len());
+        let mut denied = Vec::new();
+        for command in commands {
+            let Some((model_name, description)) = checked_command(&command) else {
+                allowed.push(command);
+                continue;
+            };
+            let manifest = match manifests.get(&model_name) {
+                Some(manifest) => manifest.clone(),
+                None => {
+                    let manifest = self.deployed_manifest(lattice_id, &model_name).await;
+                    manifests.insert(model_name.clone(), manifest.clone());
+                    manifest
+                }
+            };
+            let request = PolicyRequest {
+                lattice_id: lattice_id.to_owned(),
+                command,
+                manifest,
+            };
+            match self.engine.evaluate(&request).await {
+                Ok(PolicyResponse { allowed: true, .. }) => allowed.push(request.command),
+                Ok(PolicyResponse { reason, .. }) => denied.push((
+                    model_name,
+                    format!(
+                        "Policy denied {description}: {}",
+                        reason.as_deref().unwrap_or("no reason given")
+                    ),
+                )),
+                Err(e) => {
+                    warn!(error = ?e, %description, "Unable to get policy decision, denying command");
+                    denied.push((
+                        model_name,
+                        format!("Unable to get policy decision for {description}: {e}"),
+                    ));
+                }
+            }
+        }
+        (allowed, denied)
+    }
+
+    async fn deployed_manifest(&self, lattice_id: &str, model_name: &str) -> Option<Manifest> {
+        let (store, multitenant_prefix) = self.manifests.as_ref()?;
+        match store
+            .get(multitenant_prefix.as_deref(), lattice_id, model_name)
+            .await
+        {
+            Ok(stored) => stored.and_then(|(stored, _)| stored.get_deployed().cloned()),
+            Err(e) => {
+                warn!(error = ?e, %model_name, "Unable to fetch manifest for policy request");
+                None
+            }
+        }
+    }
+}
+
+/// Returns the model name and a description of the command if it needs a policy decision. Only
+/// commands that start something or put a link are checked, so stopping and cleaning up is never
+/// held up by a policy
+fn checked_command(command: &Command) -> Option<(String, String)> {
+    match command {
+        Command::ScaleComponent(cmd) if cmd.count > 0 => Some((
+            cmd.model_name.clone(),
+            format!(
+                "scaling component {} from {} to {} on host {}",
+                cmd.component_id, cmd.reference, cmd.count, cmd.host_id
+            ),
+        )),
+        Command::StartProvider(cmd) => Some((
+            cmd.model_name.clone(),
+            format!(
+                "starting provider {} from {} on host {}",
+                cmd.provider_id, cmd.reference, cmd.host_id
+            ),
+        )),
+        Command::PutLink(cmd) => Some((
+            cmd.model_name.clone(),
+            format!(
+                "linking {} to {} with {}:{}",
+                cmd.source_id, cmd.target, cmd.wit_namespace, cmd.wit_package
+            ),
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::{PutLink, ScaleComponent, StopProvider};
+
+    /// Denies anything that uses an image from the untrusted registry
+    struct RegistryPolicy;
+
+    #[async_trait]
+    impl PolicyEngine for RegistryPolicy {
+        async fn evaluate(&self, request: &PolicyRequest) -> Result<PolicyResponse> {
+            match &request.command {
+                Command::ScaleComponent(cmd) if cmd.reference.starts_with("evil.io") => {
+                    Ok(PolicyResponse {
+                        allowed: false,
+                        reason: Some("untrusted registry".to_string()),
+                    })
+                }
+                Command::PutLink(_) => anyhow::bail!("policy service is down"),
+                _ => Ok(PolicyResponse {
+                    allowed: true,
+                    reason: None,
+                }),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_commands_with_policy_engine() {
+        let scale = |reference: &str, count: u32| {
+            Command::ScaleComponent(ScaleComponent {
+                component_id: "echo-component".to_string(),
+                host_id: "host".to_string(),
+                count,
+                reference: reference.to_string(),
+                model_name: "echo".to_string(),
+                annotations: Default::default(),
+                config: Vec::new(),
+            })
+        };
+        let stop = Command::StopProvider(StopProvider {
+            provider_id: "echo-httpserver".to_string(),
+            host_id: "host".to_string(),
+            model_name: "echo".to_string(),
+            ..Default::default()
+        });
+        let link = Command::PutLink(PutLink {
+            model_name: "echo".to_string(),
+            ..Default::default()
+        });
+
+        let gate = PolicyGate::new(RegistryPolicy);
+        let (allowed, denied) = gate
+            .check(
+                "default",
+                vec![
+                    scale("ghcr.io/echo:0.1.0", 1),
+                    scale("evil.io/echo:0.1.0", 1),
+                    scale("evil.io/echo:0.1.0", 0),
+                    stop.clone(),
+                    link,
+                ],
+            )
+            .await;
+        assert_eq!(
+            allowed,
+            vec![
+                scale("ghcr.io/echo:0.1.0", 1),
+                scale("evil.io/echo:0.1.0", 0),
+                stop
+            ],
+            "Scaling down and stopping should never be checked"
+        );
+        assert_eq!(denied.len(), 2, "{denied:?}");
+        assert!(
+            denied[0].1.contains("untrusted registry"),
+            "{}",
+            denied[0].1
+        );
+        assert!(
+            denied[1].1.contains("policy service is down"),
+            "Commands should be denied when the engine fails"
+        );
+
+        let response: PolicyResponse = serde_json::from_str(r#"{"allowed":true}"#).unwrap();
+        assert!(response.allowed && response.reason.is_none());
+    }
+}
@@ -2,7 +2,25 @@
 //! into various structs in wadm. Often times this is used for testing, but it also allows for
 //! flexibility for others who may want to publish to other sources
 
-use async_nats::{jetstream::Context, Client};
+use std::{fmt::Display, str::FromStr};
+
+use async_nats::{jetstream::Context, Client, HeaderMap};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::nats_utils::ensure_send;
+
+/// The header that holds the content type of an encoded message. Messages without it are JSON
+pub const CONTENT_TYPE_HEADER: &str = "Content-Type";
+/// The header JetStream uses to drop duplicate messages. A message published to a stream with the
+/// same ID as another message in the stream's duplicate window is only stored once
+pub const MESSAGE_ID_HEADER: &str = "Nats-Msg-Id";
+
+/// Returns an idempotency key for the given message data, for use as a [`MESSAGE_ID_HEADER`].
+/// Publishing the same data again within the duplicate window of the stream is a noop
+pub fn message_id(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
 
 #[async_trait::async_trait]
 pub trait Publisher {
@@ -12,6 +30,29 @@ pub trait Publisher {
     /// The destination is optional for two reasons: Sometimes a client cannot be scoped to a
     /// specific topic and also, some implementations may not use subject/topic based delivery
     async fn publish(&self, data: Vec<u8>, destination: Option<&str>) -> anyhow::Result<()>;
+
+    /// Publishes the given data along with headers, such as trace context. Publishers that can't
+    /// send headers ignore them, which is what the default implementation does
+    async fn publish_with_headers(
+        &self,
+        data: Vec<u8>,
+        destination: Option<&str>,
+        _headers: HeaderMap,
+    ) -> anyhow::Result<()> {
+        self.publish(data, destination).await
+    }
+
+    /// Sends the given data along with headers as a request and returns the data of the reply.
+    /// Publishers that can't receive replies, like ones that publish to a stream, return an error,
+    /// which is what the default implementation does
+    async fn request(
+        &self,
+        _data: Vec<u8>,
+        _destination: Option<&str>,
+        _headers: HeaderMap,
+    ) -> anyhow::Result<Vec<u8>> {
+        anyhow::bail!("This publisher doesn't support requests")
+    }
 }
 
 /// The publisher implementation for a normal NATS client constrained to the given topic. This only
@@ -27,24 +68,178 @@ impl Publisher for Client {
             .await
             .map_err(anyhow::Error::from)
     }
+
+    async fn publish_with_headers(
+        &self,
+        data: Vec<u8>,
+        destination: Option<&str>,
+        headers: HeaderMap,
+    ) -> anyhow::Result<()> {
+        let subject = match destination {
+            Some(s) => s.to_owned(),
+            None => anyhow::bail!("NATS publishes require a destination"),
+        };
+        self.publish_with_headers(subject, headers, data.into())
+            .await
+            .map_err(anyhow::Error::from)
+    }
+
+    async fn request(
+        &self,
+        data: Vec<u8>,
+        destination: Option<&str>,
+        headers: HeaderMap,
+    ) -> anyhow::Result<Vec<u8>> {
+        let subject = match destination {
+            Some(s) => s.to_owned(),
+            None => anyhow::bail!("NATS requests require a destination"),
+        };
+        self.request_with_headers(subject, headers, data.into())
+            .await
+            .map(|reply| reply.payload.to_vec())
+            .map_err(anyhow::Error::from)
+    }
 }
 
 /// The publisher implementation for a NATS jetstream client. This implementation will guarantee
-/// that a sent message is received by a stream
+/// that a sent message is received by a stream, retrying publishes that aren't acknowledged with
+/// [`ensure_send`]
 #[async_trait::async_trait]
 impl Publisher for Context {
     async fn publish(&self, data: Vec<u8>, destination: Option<&str>) -> anyhow::Result<()> {
+        Publisher::publish_with_headers(self, data, destination, HeaderMap::new()).await
+    }
+
+    async fn publish_with_headers(
+        &self,
+        data: Vec<u8>,
+        destination: Option<&str>,
+        headers: HeaderMap,
+    ) -> anyhow::Result<()> {
         let subject = match destination {
             Some(s) => s.to_owned(),
             None => anyhow::bail!("NATS publishes require a destination"),
         };
-        let ack = self
-            .publish(subject, data.into())
+        ensure_send(self, subject, headers, data)
             .await
-            .map_err(|e| anyhow::anyhow!("Unable to publish message").context(e))?;
-
-        ack.await
             .map(|_| ())
-            .map_err(|e| anyhow::anyhow!("Unable to verify receipt of message").context(e))
+            .map_err(anyhow::Error::from)
+    }
+}
+
+/// The encodings wadm can use for the messages it sends to itself. Messages are always decoded
+/// with the encoding in their content type header, so replicas using different encodings can read
+/// each other's messages while a fleet moves from one encoding to another
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    /// JSON, which every version of wadm can read
+    #[default]
+    Json,
+    /// Protobuf messages with a schema for each message, which are smaller and faster to encode
+    Protobuf,
+}
+
+impl Encoding {
+    /// Returns the encoding for the given content type, if it is one wadm knows about
+    pub fn from_content_type(content_type: &str) -> Option<Encoding> {
+        // Ignore any parameters, like a charset
+        let content_type = content_type.split(';').next().unwrap_or_default().trim();
+        [Encoding::Json, Encoding::Protobuf]
+            .into_iter()
+            .find(|encoding| encoding.content_type().eq_ignore_ascii_case(content_type))
+    }
+
+    /// The content type set on messages sent with this encoding
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Encoding::Json => "application/json",
+            Encoding::Protobuf => "application/protobuf",
+        }
+    }
+
+    /// Returns the encoding of a message with the given headers. Messages without a content type
+    /// are JSON, as that is all older versions of wadm sent
+    pub fn from_headers(headers: Option<&HeaderMap>) -> anyhow::Result<Encoding> {
+        match headers.and_then(|headers| headers.get(CONTENT_TYPE_HEADER)) {
+            Some(content_type) => Encoding::from_content_type(content_type.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Unsupported content type {content_type}")),
+            None => Ok(Encoding::Json),
+        }
+    }
+
+    /// Adds the content type of this encoding to the given headers
+    pub fn insert_header(&self, headers: &mut HeaderMap) {
+        headers.insert(CONTENT_TYPE_HEADER, self.content_type());
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(Encoding::Json),
+            "protobuf" | "proto" => Ok(Encoding::Protobuf),
+            _ => anyhow::bail!("Unknown encoding {s}, expected json or protobuf"),
+        }
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Encoding::Json => f.write_str("json"),
+            Encoding::Protobuf => f.write_str("protobuf"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encodings_round_trip_through_headers() {
+        for encoding in [Encoding::Json, Encoding::Protobuf] {
+            let mut headers = HeaderMap::new();
+            encoding.insert_header(&mut headers);
+            assert_eq!(Encoding::from_headers(Some(&headers)).unwrap(), encoding);
+            assert_eq!(encoding.to_string().parse::<Encoding>().unwrap(), encoding);
+        }
+
+        assert_eq!(
+            Encoding::from_headers(None).unwrap(),
+            Encoding::Json,
+            "Messages from older versions without a content type should be JSON"
+        );
+        assert_eq!(
+            Encoding::from_content_type("application/json; charset=utf-8"),
+            Some(Encoding::Json)
+        );
+        assert!(Encoding::from_content_type("application/msgpack").is_none());
+        assert_eq!("proto".parse::<Encoding>().unwrap(), Encoding::Protobuf);
+    }
+
+    struct StreamOnly;
+
+    #[async_trait::async_trait]
+    impl Publisher for StreamOnly {
+        async fn publish(&self, _: Vec<u8>, _: Option<&str>) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_and_message_ids() {
+        assert!(
+            StreamOnly
+                .request(Vec::new(), Some("wadm.policy"), HeaderMap::new())
+                .await
+                .is_err(),
+            "Publishers should have to opt in to requests"
+        );
+        assert_eq!(message_id(b"command"), message_id(b"command"));
+        assert_ne!(message_id(b"command"), message_id(b"other command"));
     }
 }
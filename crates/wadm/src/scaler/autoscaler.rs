@@ -0,0 +1,360 @@
+//! Contains the [`AutoScaler`], which adjusts the instances of a component's spreadscaler from a
+//! metric published on a NATS subject according to the component's autoscaler trait
+
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::StreamExt;
+use tokio::{sync::RwLock, task::JoinHandle};
+use tracing::{debug, instrument, trace, warn};
+use wadm_types::{api::StatusInfo, AutoscalerProperty, SpreadScalerProperty, TraitProperty};
+
+use crate::{
+    clock::SharedClock, commands::Command, events::Event, scaler::Scaler, workers::MetricSource,
+};
+
+use super::convert::BoxedScaler;
+
+/// The instances the autoscaler last configured and when
+struct Adjustment {
+    instances: usize,
+    at: Option<Instant>,
+}
+
+/// The AutoScaler wraps the scaler for a component and subscribes to the metric subject of the
+/// component's autoscaler trait. Each time it reconciles or handles an event, the wrapped scaler is
+/// configured with enough instances for the latest metric value, moving at most `step` instances
+/// at a time and waiting for the cooldown between adjustments.
+///
+/// NOTE: Like rollouts, adjustments only happen when the scaler reconciles or handles an event, so
+/// the cooldown is only as precise as the host heartbeat interval. The subscription is started the
+/// first time the scaler reconciles or handles an event, and the adjusted instances are only
+/// tracked in memory, so wadm goes back to the instances in the spreadscaler when it restarts
+pub(crate) struct AutoScaler<M> {
+    id: String,
+    kind: String,
+    name: String,
+    scaler: RwLock<BoxedScaler>,
+    spread_config: SpreadScalerProperty,
+    autoscaler: AutoscalerProperty,
+    metrics: M,
+    latest: Arc<RwLock<Option<f64>>>,
+    subscription: OnceLock<JoinHandle<()>>,
+    adjustment: RwLock<Adjustment>,
+    clock: SharedClock,
+}
+
+impl<M> Drop for AutoScaler<M> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.subscription.get() {
+            handle.abort()
+        }
+    }
+}
+
+impl<M> AutoScaler<M>
+where
+    M: MetricSource + Clone + Send + Sync + 'static,
+{
+    /// Wraps the given scaler, which should be configured with `spread_config`, so its instances
+    /// follow the metric from the given source according to the autoscaler properties. Cooldowns
+    /// are measured with the given clock
+    pub(crate) fn new(
+        scaler: BoxedScaler,
+        spread_config: SpreadScalerProperty,
+        autoscaler: &AutoscalerProperty,
+        metrics: M,
+        clock: SharedClock,
+    ) -> Self {
+        AutoScaler {
+            id: scaler.id().to_owned(),
+            kind: scaler.kind().to_owned(),
+            name: scaler.name(),
+            scaler: RwLock::new(scaler),
+            adjustment: RwLock::new(Adjustment {
+                instances: spread_config.instances,
+                at: None,
+            }),
+            spread_config,
+            autoscaler: autoscaler.to_owned(),
+            metrics,
+            latest: Arc::new(RwLock::new(None)),
+            subscription: OnceLock::new(),
+            clock,
+        }
+    }
+
+    /// Starts the subscription to the metric subject if it isn't running yet
+    fn subscribe(&self) {
+        self.subscription.get_or_init(|| {
+            let metrics = self.metrics.clone();
+            let latest = self.latest.clone();
+            let subject = self.autoscaler.subject.clone();
+            tokio::spawn(async move {
+                let mut values = match metrics.subscribe_metric(&subject).await {
+                    Ok(values) => values,
+                    Err(e) => {
+                        warn!(error = ?e, %subject, "Unable to subscribe to metric, instances won't be adjusted");
+                        return;
+                    }
+                };
+                while let Some(value) = values.next().await {
+                    trace!(%subject, %value, "Received metric");
+                    *latest.write().await = Some(value);
+                }
+            })
+        });
+    }
+
+    /// Configures the wrapped scaler with the instances for the latest metric value, returning
+    /// whether the instances changed
+    async fn adjust(&self) -> Result<bool> {
+        self.subscribe();
+        let latest = *self.latest.read().await;
+        let mut adjustment = self.adjustment.write().await;
+        let bounded = adjustment.instances.clamp(
+            self.autoscaler.min_instances,
+            self.autoscaler
+                .max_instances
+                .max(self.autoscaler.min_instances),
+        );
+        let instances = match latest {
+            // Instances outside of the bounds are always brought back within them right away
+            _ if bounded != adjustment.instances => bounded,
+            Some(_)
+                if adjustment.at.is_some_and(|at| {
+                    self.clock.elapsed(at) < Duration::from_secs(self.autoscaler.cooldown_seconds)
+                }) =>
+            {
+                return Ok(false)
+            }
+            Some(value) => {
+                let wanted = self.autoscaler.instances_for(value);
+                let step = self.autoscaler.step.max(1);
+                if wanted > adjustment.instances {
+                    adjustment.instances + step.min(wanted - adjustment.instances)
+                } else {
+                    adjustment.instances - step.min(adjustment.instances - wanted)
+                }
+            }
+            None => return Ok(false),
+        };
+        if instances == adjustment.instances {
+            return Ok(false);
+        }
+
+        debug!(from = adjustment.instances, to = instances, metric = ?latest, "Adjusting instances");
+        // NOTE: Updating the config also reconciles, but those commands are discarded so that
+        // reconciliation goes through the normal path and any wrapping scalers can track them
+        self.scaler
+            .write()
+            .await
+            .update_config(TraitProperty::SpreadScaler(SpreadScalerProperty {
+                instances,
+                ..self.spread_config.clone()
+            }))
+            .await?;
+        *adjustment = Adjustment {
+            instances,
+            at: Some(self.clock.instant()),
+        };
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl<M> Scaler for AutoScaler<M>
+where
+    M: MetricSource + Clone + Send + Sync + 'static,
+{
+    fn id(&self) -> &str {
+        // Pass through the ID of the wrapped scaler
+        &self.id
+    }
+
+    fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn status(&self) -> StatusInfo {
+        self.scaler.read().await.status().await
+    }
+
+    async fn update_config(&mut self, config: TraitProperty) -> Result<Vec<Command>> {
+        let spread_config = match config {
+            TraitProperty::SpreadScaler(prop) => prop,
+            _ => anyhow::bail!("Given config was not a spread scaler config object"),
+        };
+        self.spread_config = spread_config;
+        // Keep the instances the autoscaler already settled on
+        let instances = self.adjustment.read().await.instances;
+        self.scaler
+            .write()
+            .await
+            .update_config(TraitProperty::SpreadScaler(SpreadScalerProperty {
+                instances,
+                ..self.spread_config.clone()
+            }))
+            .await?;
+        self.reconcile().await
+    }
+
+    fn config(&self) -> Option<TraitProperty> {
+        Some(TraitProperty::SpreadScaler(self.spread_config.clone()))
+    }
+
+    #[instrument(level = "trace", skip_all, fields(scaler_id = %self.id))]
+    async fn handle_event(&self, event: &Event) -> Result<Vec<Command>> {
+        if self.adjust().await? {
+            self.scaler.read().await.reconcile().await
+        } else {
+            self.scaler.read().await.handle_event(event).await
+        }
+    }
+
+    #[instrument(level = "trace", skip_all, fields(scaler_id = %self.id))]
+    async fn reconcile(&self) -> Result<Vec<Command>> {
+        self.adjust().await?;
+        self.scaler.read().await.reconcile().await
+    }
+
+    async fn cleanup(&self) -> Result<Vec<Command>> {
+        self.scaler.read().await.cleanup().await
+    }
+
+    async fn references(&self) -> Vec<String> {
+        self.scaler.read().await.references().await
+    }
+
+    async fn take_over(&self, outdated: BoxedScaler) -> Option<BoxedScaler> {
+        self.scaler.read().await.take_over(outdated).await
+    }
+
+    async fn release_previous(&self) -> Option<BoxedScaler> {
+        self.scaler.read().await.release_previous().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+
+    use chrono::Utc;
+    use wadm_types::Spread;
+
+    use super::*;
+    use crate::{
+        clock,
+        commands::ScaleComponent,
+        scaler::spreadscaler::ComponentSpreadScaler,
+        storage::{Host, Store},
+        test_util::{TestLatticeSource, TestStore},
+    };
+
+    const LATTICE_ID: &str = "autoscaler";
+    const HOST_ID: &str = "NASDASDIMAREALHOST";
+
+    fn total_count(commands: &[Command]) -> u32 {
+        commands
+            .iter()
+            .map(|cmd| match cmd {
+                Command::ScaleComponent(ScaleComponent { count, .. }) => *count,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn adjusts_instances_from_metric() {
+        let store = Arc::new(TestStore::default());
+        store
+            .store(
+                LATTICE_ID,
+                HOST_ID.to_string(),
+                Host {
+                    components: HashMap::new(),
+                    friendly_name: "hey".to_string(),
+                    labels: HashMap::new(),
+                    providers: HashSet::new(),
+                    uptime_seconds: 123,
+                    version: None,
+                    id: HOST_ID.to_string(),
+                    last_seen: Utc::now(),
+                },
+            )
+            .await
+            .unwrap();
+
+        let spread_config = SpreadScalerProperty {
+            instances: 2,
+            spread: vec![Spread {
+                name: "Anywhere".to_string(),
+                ..Default::default()
+            }],
+            hosts: vec![],
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
+        };
+        let scaler = Box::new(ComponentSpreadScaler::new(
+            store.clone(),
+            "fakecloud.azurecr.io/echo:0.3.4".to_string(),
+            "autoscaler-echo".to_string(),
+            LATTICE_ID.to_string(),
+            "autoscaler".to_string(),
+            spread_config.clone(),
+            "echo",
+            vec![],
+        ));
+        let autoscaler = AutoScaler::new(
+            scaler,
+            spread_config,
+            &AutoscalerProperty {
+                subject: "metrics.echo.rps".to_string(),
+                target_value: 100,
+                min_instances: 1,
+                max_instances: 5,
+                step: 2,
+                cooldown_seconds: 0,
+            },
+            TestLatticeSource::default(),
+            clock::system(),
+        );
+
+        let commands = autoscaler.reconcile().await.unwrap();
+        assert_eq!(
+            total_count(&commands),
+            2,
+            "Instances shouldn't change until a metric is received"
+        );
+
+        *autoscaler.latest.write().await = Some(950.0);
+        let commands = autoscaler.reconcile().await.unwrap();
+        assert_eq!(
+            total_count(&commands),
+            4,
+            "Instances should only move by the step at a time"
+        );
+        let commands = autoscaler.reconcile().await.unwrap();
+        assert_eq!(
+            total_count(&commands),
+            5,
+            "Instances should stay within the max"
+        );
+
+        *autoscaler.latest.write().await = Some(0.0);
+        autoscaler.reconcile().await.unwrap();
+        let commands = autoscaler.reconcile().await.unwrap();
+        assert_eq!(
+            total_count(&commands),
+            1,
+            "Instances should scale down to the min"
+        );
+    }
+}
@@ -11,11 +11,12 @@ use tracing::instrument;
 use tracing::trace;
 use wadm_types::{
     api::{StatusInfo, StatusType},
-    TraitProperty,
+    SecretRef, TraitProperty,
 };
 
 use crate::commands::{DeleteConfig, PutConfig};
 use crate::events::{ConfigDeleted, ConfigSet};
+use crate::secrets::SecretResolver;
 use crate::workers::ConfigSource;
 use crate::{commands::Command, events::Event, scaler::Scaler};
 
@@ -29,6 +30,10 @@ pub struct ConfigScaler<ConfigSource> {
     // fairly heavy if the configuration is large. We should consider a more efficient way to store
     // this by fetching configuration from the manifest when it's needed, for example.
     config: Option<HashMap<String, String>>,
+    /// Configuration keys whose values are resolved from secret backends whenever the
+    /// configuration is put. Only the references are kept, not the resolved values
+    secret_refs: HashMap<String, SecretRef>,
+    secret_resolver: Option<SecretResolver>,
     status: RwLock<StatusInfo>,
 }
 
@@ -83,9 +88,17 @@ impl<C: ConfigSource + Send + Sync + Clone> Scaler for ConfigScaler<C> {
     #[instrument(level = "trace", skip_all, scaler_id = %self.id)]
     async fn reconcile(&self) -> Result<Vec<Command>> {
         debug!(self.config_name, "Fetching configuration");
+        let desired = match self.desired_config().await {
+            Ok(desired) => desired,
+            Err(e) => {
+                error!(error = %e, "Configscaler failed to resolve secret references");
+                *self.status.write().await = StatusInfo::failed(&format!("{e:#}"));
+                return Ok(Vec::new());
+            }
+        };
         match (
             self.config_bucket.get_config(&self.config_name).await,
-            self.config.as_ref(),
+            desired.as_ref(),
         ) {
             // If configuration is not supplied to the scaler, we just ensure that it exists
             (Ok(Some(_config)), None) => {
@@ -124,7 +137,7 @@ impl<C: ConfigSource + Send + Sync + Clone> Scaler for ConfigScaler<C> {
 
     #[instrument(level = "trace", skip_all)]
     async fn cleanup(&self) -> Result<Vec<Command>> {
-        if self.config.is_some() {
+        if self.config.is_some() || !self.secret_refs.is_empty() {
             Ok(vec![Command::DeleteConfig(DeleteConfig {
                 config_name: self.config_name.clone(),
             })])
@@ -155,9 +168,47 @@ impl<C: ConfigSource> ConfigScaler<C> {
             id,
             config_name: config_name.to_string(),
             config: config.cloned(),
+            secret_refs: HashMap::new(),
+            secret_resolver: None,
             status: RwLock::new(StatusInfo::reconciling("")),
         }
     }
+
+    /// Adds configuration keys whose values are resolved with the given resolver whenever the
+    /// configuration is put. Configuration with secret references is always managed by wadm
+    pub fn with_secret_refs(
+        mut self,
+        secret_refs: &HashMap<String, SecretRef>,
+        secret_resolver: Option<SecretResolver>,
+    ) -> Self {
+        if secret_refs.is_empty() {
+            return self;
+        }
+        // Include the references in the id so changing them updates the scaler
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        BTreeMap::from_iter(secret_refs.iter()).hash(&mut hasher);
+        self.id.extend(format!("-{}", hasher.finish()).chars());
+        self.secret_refs = secret_refs.clone();
+        self.secret_resolver = secret_resolver;
+        self
+    }
+
+    /// Returns the configuration that should exist, with any secret references resolved, or `None`
+    /// if the configuration is externally managed
+    async fn desired_config(&self) -> Result<Option<HashMap<String, String>>> {
+        if self.secret_refs.is_empty() {
+            return Ok(self.config.clone());
+        }
+        let resolver = self.secret_resolver.as_ref().ok_or_else(|| {
+            anyhow::anyhow!(
+                "Configuration {} has secret references but no secret backends are configured",
+                self.config_name
+            )
+        })?;
+        let mut config = self.config.clone().unwrap_or_default();
+        config.extend(resolver.resolve_all(&self.secret_refs).await?);
+        Ok(Some(config))
+    }
 }
 
 #[cfg(test)]
@@ -181,6 +232,7 @@ mod test {
             claims: HashMap::new(),
             inventory: Default::default(),
             links: Vec::new(),
+            auction_winners: Vec::new(),
             config: HashMap::new(),
         };
 
@@ -190,6 +242,7 @@ mod test {
                 "key".to_string(),
                 "value".to_string(),
             )])),
+            secret_refs: HashMap::new(),
         };
 
         let config_scaler =
@@ -295,6 +348,7 @@ mod test {
             claims: HashMap::new(),
             inventory: Default::default(),
             links: Vec::new(),
+            auction_winners: Vec::new(),
             config: HashMap::from_iter(vec![(
                 config.name.clone(),
                 config.properties.clone().expect("properties not found"),
@@ -342,6 +396,7 @@ mod test {
             claims: HashMap::new(),
             inventory: Default::default(),
             links: Vec::new(),
+            auction_winners: Vec::new(),
             config: HashMap::from_iter(vec![(
                 config.name.clone(),
                 HashMap::from_iter(vec![("key".to_string(), "wrong_value".to_string())]),
@@ -7,8 +7,9 @@ use anyhow::Result;
 use tracing::{error, warn};
 use wadm_types::{
     api::StatusInfo, CapabilityProperties, Component, ComponentProperties, ConfigProperty,
-    LinkProperty, Policy, Properties, SecretProperty, SharedApplicationComponentProperties,
-    SpreadScalerProperty, Trait, TraitProperty, DAEMONSCALER_TRAIT, LINK_TRAIT, SPREADSCALER_TRAIT,
+    HostConstraintProperty, LinkProperty, Policy, Properties, ProviderConfigPayload,
+    SecretProperty, SharedApplicationComponentProperties, SpreadScalerProperty, Trait,
+    TraitProperty, DAEMONSCALER_TRAIT, LINK_TRAIT, SPREADSCALER_TRAIT,
 };
 use wasmcloud_secrets_types::SECRET_PREFIX;
 
@@ -20,13 +21,16 @@ use crate::{
         Scaler,
     },
     storage::{snapshot::SnapshotStore, ReadStore},
-    workers::{ConfigSource, LinkSource, SecretSource},
+    workers::{ConfigSource, LinkSource, MetricSource, SecretSource},
     DEFAULT_LINK_NAME,
 };
 
 use super::{
+    autoscaler::AutoScaler,
     configscaler::ConfigScaler,
     daemonscaler::{provider::ProviderDaemonScaler, ComponentDaemonScaler},
+    rollout::RolloutController,
+    schedule::{Schedule, ScheduleScaler},
     secretscaler::SecretScaler,
     spreadscaler::{
         link::{LinkScaler, LinkScalerConfig},
@@ -63,7 +67,7 @@ pub(crate) fn manifest_components_to_scalers<S, P, L>(
 where
     S: ReadStore + Send + Sync + Clone + 'static,
     P: Publisher + Clone + Send + Sync + 'static,
-    L: LinkSource + ConfigSource + SecretSource + Clone + Send + Sync + 'static,
+    L: LinkSource + ConfigSource + SecretSource + MetricSource + Clone + Send + Sync + 'static,
 {
     let mut scalers: ScalerList = Vec::new();
     components
@@ -178,8 +182,36 @@ fn component_scalers<S, P, L>(
 ) where
     S: ReadStore + Send + Sync + Clone + 'static,
     P: Publisher + Clone + Send + Sync + 'static,
-    L: LinkSource + ConfigSource + SecretSource + Clone + Send + Sync + 'static,
+    L: LinkSource + ConfigSource + SecretSource + MetricSource + Clone + Send + Sync + 'static,
 {
+    let rollout = traits
+        .into_iter()
+        .flatten()
+        .find_map(|trt| match &trt.properties {
+            TraitProperty::Rollout(p) if trt.is_rollout() => Some(p),
+            _ => None,
+        });
+    let autoscaler = traits
+        .into_iter()
+        .flatten()
+        .find_map(|trt| match &trt.properties {
+            TraitProperty::Autoscaler(p) if trt.is_autoscaler() => Some(p),
+            _ => None,
+        });
+    let schedule = traits
+        .into_iter()
+        .flatten()
+        .find_map(|trt| match &trt.properties {
+            TraitProperty::Schedule(p) if trt.is_schedule() => match Schedule::try_from(p) {
+                Ok(schedule) => Some(schedule),
+                Err(e) => {
+                    warn!(error = %e, "Ignoring invalid schedule trait for component {component_name}");
+                    None
+                }
+            },
+            _ => None,
+        });
+    let host_constraint = host_constraint(traits);
     scalers.extend(traits.unwrap_or(&EMPTY_TRAIT_VEC).iter().filter_map(|trt| {
         // If an image is specified, then it's a component in the same manifest. Otherwise, it's a shared component
         let component_id = if properties.image.is_some() {
@@ -216,7 +248,7 @@ fn component_scalers<S, P, L>(
             (SPREADSCALER_TRAIT, TraitProperty::SpreadScaler(p), Some(image_ref)) => {
                 // If the image is not specified, then it's a reference to a shared provider
                 // in a different manifest
-                Some(Box::new(BackoffWrapper::new(
+                let scaler = Box::new(BackoffWrapper::new(
                     ComponentSpreadScaler::new(
                         snapshot_data.clone(),
                         image_ref.clone(),
@@ -226,14 +258,50 @@ fn component_scalers<S, P, L>(
                         p.to_owned(),
                         component_name,
                         config_names,
-                    ),
+                    )
+                    .with_host_constraint(host_constraint.clone())
+                    .with_anti_affinity(anti_affinity_ids(manifest_name, components, &p.anti_affinity))
+                    .with_auction_source(snapshot_data.auction_source()),
                     notifier.clone(),
                     config_scalers,
                     secret_scalers,
                     notifier_subject,
                     application_name,
                     Some(Duration::from_secs(5)),
-                )) as BoxedScaler)
+                )) as BoxedScaler;
+                // Components with a rollout trait are moved from their previous version in steps
+                let scaler = match rollout {
+                    Some(rollout) => {
+                        Box::new(RolloutController::new(
+                            scaler,
+                            p.to_owned(),
+                            rollout,
+                            snapshot_data.clock(),
+                        )) as BoxedScaler
+                    }
+                    None => scaler,
+                };
+                // Components with an autoscaler trait follow their metric and components with a
+                // schedule trait follow their schedule, whichever version is running
+                Some(match (autoscaler, &schedule) {
+                    (Some(autoscaler), _) => Box::new(AutoScaler::new(
+                        scaler,
+                        p.to_owned(),
+                        autoscaler,
+                        snapshot_data.clone(),
+                        snapshot_data.clock(),
+                    )) as BoxedScaler,
+                    (None, Some(schedule)) => Box::new(ScheduleScaler::new(
+                        scaler,
+                        p.to_owned(),
+                        schedule.clone(),
+                        notifier.clone(),
+                        notifier_subject,
+                        manifest_name,
+                        snapshot_data.clock(),
+                    )) as BoxedScaler,
+                    (None, None) => scaler,
+                })
             }
             (DAEMONSCALER_TRAIT, TraitProperty::SpreadScaler(p), Some(image_ref)) => {
                 Some(Box::new(BackoffWrapper::new(
@@ -246,7 +314,9 @@ fn component_scalers<S, P, L>(
                         p.to_owned(),
                         component_name,
                         config_names,
-                    ),
+                    )
+                    .with_host_constraint(host_constraint.clone())
+                    .with_anti_affinity(anti_affinity_ids(manifest_name, components, &p.anti_affinity)),
                     notifier.clone(),
                     config_scalers,
                     secret_scalers,
@@ -342,6 +412,7 @@ fn provider_scalers<S, P, L>(
         compute_component_id(application_name, properties.id.as_ref(), component_name)
     };
 
+    let host_constraint = host_constraint(traits);
     let mut scaler_specified = false;
     scalers.extend(traits.unwrap_or(&EMPTY_TRAIT_VEC).iter().filter_map(|trt| {
         match (trt.trait_type.as_str(), &trt.properties, &properties.image) {
@@ -362,7 +433,11 @@ fn provider_scalers<S, P, L>(
             (SPREADSCALER_TRAIT, TraitProperty::SpreadScaler(p), Some(image)) => {
                 scaler_specified = true;
                 let (config_scalers, mut config_names) =
-                    config_to_scalers(snapshot_data, application_name, &properties.config);
+                    config_to_scalers(
+ snapshot_data,
+ application_name,
+ &provider_config(component_name, properties),
+ );
                 let (secret_scalers, secret_names) = secrets_to_scalers(
                     snapshot_data,
                     application_name,
@@ -383,7 +458,10 @@ fn provider_scalers<S, P, L>(
                             provider_config: config_names,
                         },
                         component_name,
-                    ),
+                    )
+                    .with_host_constraint(host_constraint.clone())
+                    .with_anti_affinity(anti_affinity_ids(manifest_name, components, &p.anti_affinity))
+                    .with_auction_source(snapshot_data.auction_source()),
                     notifier.clone(),
                     config_scalers,
                     secret_scalers,
@@ -396,7 +474,11 @@ fn provider_scalers<S, P, L>(
             (DAEMONSCALER_TRAIT, TraitProperty::SpreadScaler(p), Some(image)) => {
                 scaler_specified = true;
                 let (config_scalers, mut config_names) =
-                    config_to_scalers(snapshot_data, application_name, &properties.config);
+                    config_to_scalers(
+ snapshot_data,
+ application_name,
+ &provider_config(component_name, properties),
+ );
                 let (secret_scalers, secret_names) = secrets_to_scalers(
                     snapshot_data,
                     application_name,
@@ -416,7 +498,9 @@ fn provider_scalers<S, P, L>(
                                 provider_config: config_names,
                             },
                             component_name,
-                        ),
+                        )
+                        .with_host_constraint(host_constraint.clone())
+                    .with_anti_affinity(anti_affinity_ids(manifest_name, components, &p.anti_affinity)),
                         notifier.clone(),
                         config_scalers,
                         secret_scalers,
@@ -471,8 +555,11 @@ fn provider_scalers<S, P, L>(
     // Allow providers to omit the spreadscaler entirely for simplicity
     if !scaler_specified {
         if let Some(image) = &properties.image {
-            let (config_scalers, mut config_names) =
-                config_to_scalers(snapshot_data, application_name, &properties.config);
+            let (config_scalers, mut config_names) = config_to_scalers(
+                snapshot_data,
+                application_name,
+                &provider_config(component_name, properties),
+            );
 
             let (secret_scalers, mut secret_names) = secrets_to_scalers(
                 snapshot_data,
@@ -491,12 +578,18 @@ fn provider_scalers<S, P, L>(
                         spread_config: SpreadScalerProperty {
                             instances: 1,
                             spread: vec![],
+                            hosts: Vec::new(),
+                            max_concurrent_changes: None,
+                            max_per_host: None,
+                            anti_affinity: vec![],
                         },
                         model_name: application_name.to_owned(),
                         provider_config: config_names,
                     },
                     component_name,
-                ),
+                )
+                .with_host_constraint(host_constraint.clone())
+                .with_auction_source(snapshot_data.auction_source()),
                 notifier.clone(),
                 config_scalers,
                 secret_scalers,
@@ -627,33 +720,72 @@ where
     )) as BoxedScaler
 }
 
+/// Returns the host constraint from a component's traits, if it has one
+pub(crate) fn host_constraint(traits: Option<&Vec<Trait>>) -> Option<HostConstraintProperty> {
+    traits
+        .into_iter()
+        .flatten()
+        .find_map(|trt| match &trt.properties {
+            TraitProperty::HostConstraint(p) if trt.is_host_constraint() => Some(p.to_owned()),
+            _ => None,
+        })
+}
+
 /// Returns a tuple which is a list of scalers and a list of the names of the configs that the
 /// scalers use.
 ///
-/// Any input [ConfigProperty] that has a `properties` field will be converted into a [ConfigScaler], and
-/// the name of the configuration will be modified to be unique to the model and component. If the properties
-/// field is not present, the name will be used as-is and assumed that it's managed externally to wadm.
-fn config_to_scalers<C: ConfigSource + Send + Sync + Clone>(
-    config_source: &C,
+/// Any input [ConfigProperty] that has a `properties` field or secret references will be converted into a
+/// [ConfigScaler], and the name of the configuration will be modified to be unique to the model and component.
+/// Otherwise, the name will be used as-is and assumed that it's managed externally to wadm.
+fn config_to_scalers<S, L>(
+    snapshot_data: &SnapshotStore<S, L>,
     manifest_name: &str,
     configs: &[ConfigProperty],
-) -> (Vec<ConfigScaler<C>>, Vec<String>) {
+) -> (Vec<ConfigScaler<SnapshotStore<S, L>>>, Vec<String>)
+where
+    S: ReadStore + Send + Sync + Clone,
+    L: LinkSource + ConfigSource + SecretSource + Send + Sync + Clone,
+{
     configs
         .iter()
         .map(|config| {
-            let name = if config.properties.is_some() {
+            let name = if config.properties.is_some() || !config.secret_refs.is_empty() {
                 compute_component_id(manifest_name, None, &config.name)
             } else {
                 config.name.clone()
             };
             (
-                ConfigScaler::new(config_source.clone(), &name, config.properties.as_ref()),
+                ConfigScaler::new(snapshot_data.clone(), &name, config.properties.as_ref())
+                    .with_secret_refs(
+                        &config.secret_refs,
+                        snapshot_data.secret_resolver().cloned(),
+                    ),
                 name,
             )
         })
         .unzip()
 }
 
+/// Returns the named configuration of a provider, along with a configuration holding its config
+/// payload if it has one. Invalid payloads are skipped, since validation rejects those manifests
+fn provider_config(component_name: &str, properties: &CapabilityProperties) -> Vec<ConfigProperty> {
+    let mut config = properties.config.clone();
+    match properties
+        .config_payload
+        .as_ref()
+        .map(ProviderConfigPayload::to_properties)
+    {
+        Some(Ok(payload)) => config.push(ConfigProperty {
+            name: format!("{component_name}-config-payload"),
+            properties: Some(payload),
+            secret_refs: HashMap::new(),
+        }),
+        Some(Err(e)) => warn!("Skipping invalid config payload for provider {component_name}: {e}"),
+        None => {}
+    }
+    config
+}
+
 fn secrets_to_scalers<S: SecretSource + Send + Sync + Clone>(
     secret_source: &S,
     manifest_name: &str,
@@ -678,6 +810,38 @@ fn secrets_to_scalers<S: SecretSource + Send + Sync + Clone>(
         .unzip()
 }
 
+/// Resolves the names of the components a scaler is never placed on the same host as into the IDs
+/// their components or providers run with. Names that don't match a component in the manifest are
+/// skipped, as validation rejects those manifests
+pub(crate) fn anti_affinity_ids(
+    manifest_name: &str,
+    components: &[Component],
+    names: &[String],
+) -> Vec<String> {
+    components
+        .iter()
+        .filter(|component| names.contains(&component.name))
+        .filter_map(|component| {
+            let (id, image, application) = match &component.properties {
+                Properties::Component { properties } => (
+                    properties.id.as_ref(),
+                    properties.image.as_ref(),
+                    properties.application.as_ref(),
+                ),
+                Properties::Capability { properties } => (
+                    properties.id.as_ref(),
+                    properties.image.as_ref(),
+                    properties.application.as_ref(),
+                ),
+            };
+            let (application_name, component_name) =
+                resolve_manifest_component(manifest_name, &component.name, image, application)
+                    .ok()?;
+            Some(compute_component_id(application_name, id, component_name))
+        })
+        .collect()
+}
+
 /// Based on the name of the model and the optionally provided ID, returns a unique ID for the
 /// component that is a sanitized version of the component reference and model name, separated
 /// by a dash.
@@ -743,7 +907,11 @@ fn resolve_manifest_component<'a>(
 
 #[cfg(test)]
 mod test {
-    use super::compute_component_id;
+    use std::collections::HashMap;
+
+    use wadm_types::{CapabilityProperties, ConfigProperty, ProviderConfigPayload};
+
+    use super::{compute_component_id, provider_config};
 
     #[test]
     fn compute_proper_component_id() {
@@ -777,4 +945,36 @@ mod test {
             "my_thing-thing_wasm"
         );
     }
+
+    #[test]
+    fn provider_config_includes_payload() {
+        let mut properties = CapabilityProperties {
+            image: Some("ghcr.io/wasmcloud/http-server:0.23.0".to_string()),
+            application: None,
+            id: None,
+            config: vec![ConfigProperty {
+                name: "external".to_string(),
+                properties: None,
+                secret_refs: HashMap::new(),
+            }],
+            secrets: vec![],
+            config_payload: Some(ProviderConfigPayload::Json(serde_json::json!({
+                "address": "0.0.0.0:8080",
+                "timeout_ms": 5000,
+            }))),
+        };
+        let config = provider_config("httpserver", &properties);
+        assert_eq!(config.len(), 2);
+        assert_eq!(config[1].name, "httpserver-config-payload");
+        let payload = config[1].properties.as_ref().unwrap();
+        assert_eq!(payload["address"], "0.0.0.0:8080");
+        assert_eq!(payload["timeout_ms"], "5000");
+
+        properties.config_payload = Some(ProviderConfigPayload::Json(serde_json::json!(1)));
+        assert_eq!(
+            provider_config("httpserver", &properties).len(),
+            1,
+            "Invalid payloads should be skipped"
+        );
+    }
 }
@@ -5,10 +5,14 @@ use anyhow::Result;
 use async_trait::async_trait;
 use tokio::sync::RwLock;
 use tracing::{instrument, trace};
-use wadm_types::{api::StatusInfo, Spread, SpreadScalerProperty, TraitProperty};
+use wadm_types::{
+    api::StatusInfo, HostConstraintProperty, Spread, SpreadScalerProperty, TraitProperty,
+};
 
 use crate::scaler::spreadscaler::{
-    compute_ineligible_hosts, eligible_hosts, spreadscaler_annotations,
+    can_place_on, compute_ineligible_hosts, eligible_hosts, host_constraint_status,
+    pinned_placement_message, pinned_spread, spreadscaler_annotations, unknown_pinned_hosts_status,
+    ChangeBudget,
 };
 use crate::{
     commands::{Command, ScaleComponent},
@@ -37,6 +41,10 @@ struct ComponentSpreadConfig {
     model_name: String,
     /// Configuration for this DaemonScaler
     spread_config: SpreadScalerProperty,
+    /// Constraint on the hosts the component can be placed on
+    host_constraint: Option<HostConstraintProperty>,
+    /// IDs of the components and providers the component is never placed on the same host as
+    anti_affinity: Vec<String>,
 }
 
 /// The ComponentDaemonScaler ensures that a certain number of instances are running on every host, according to a
@@ -76,16 +84,7 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ComponentDaemonScaler<S> {
             TraitProperty::SpreadScaler(prop) => prop,
             _ => anyhow::bail!("Given config was not a daemon scaler config object"),
         };
-        // If no spreads are specified, an empty spread is sufficient to match _every_ host
-        // in a lattice
-        let spread_config = if spread_config.spread.is_empty() {
-            SpreadScalerProperty {
-                instances: spread_config.instances,
-                spread: vec![Spread::default()],
-            }
-        } else {
-            spread_config
-        };
+        let spread_config = daemon_spread_config(spread_config);
         self.spread_config.spread_config = spread_config;
         self.reconcile().await
     }
@@ -119,6 +118,8 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ComponentDaemonScaler<S> {
                     Ok(Vec::new())
                 }
             }
+            // A label change can move a host into or out of any spread, so always reconcile
+            Event::HostLabelsChanged(_) => self.reconcile().await,
             // No other event impacts the job of this scaler so we can ignore it
             _ => Ok(Vec::new()),
         }
@@ -140,6 +141,8 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ComponentDaemonScaler<S> {
         let ineligible_hosts = compute_ineligible_hosts(
             &hosts,
             self.spread_config.spread_config.spread.iter().collect(),
+            &self.spread_config.spread_config.hosts,
+            self.spread_config.host_constraint.as_ref(),
         );
 
         // Remove any components that are managed by this scaler and running on ineligible hosts
@@ -173,6 +176,7 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ComponentDaemonScaler<S> {
         }
 
         let mut spread_status = vec![];
+        let mut budget = ChangeBudget::new(self.spread_config.spread_config.max_concurrent_changes);
 
         trace!(spread = ?self.spread_config.spread_config.spread, ?component_id, "Computing commands");
         let commands = self
@@ -181,7 +185,12 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ComponentDaemonScaler<S> {
             .spread
             .iter()
             .filter_map(|spread| {
-                let eligible_hosts = eligible_hosts(&hosts, spread);
+                let eligible_hosts = eligible_hosts(
+                    &hosts,
+                    spread,
+                    &self.spread_config.spread_config.hosts,
+                    self.spread_config.host_constraint.as_ref(),
+                );
                 if !eligible_hosts.is_empty() {
                     // Create a list of (host_id, current_count) tuples
                     // current_count is the number of component instances that are running for this spread on this host
@@ -221,8 +230,21 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ComponentDaemonScaler<S> {
                                 match current_count.cmp(&self.spread_config.spread_config.instances)
                                 {
                                     Ordering::Equal => None,
+                                    // Hosts that can't take new instances keep the ones they have, but
+                                    // aren't scaled up
+                                    Ordering::Less
+                                        if hosts.get(*host_id).is_some_and(|host| {
+                                            !can_place_on(host, &self.spread_config.anti_affinity)
+                                        }) =>
+                                    {
+                                        None
+                                    }
                                     // Scale component can handle both up and down scaling
                                     Ordering::Less | Ordering::Greater => {
+                                        let count = budget.step(
+                                            *current_count,
+                                            self.spread_config.spread_config.instances,
+                                        )?;
                                         Some(Command::ScaleComponent(ScaleComponent {
                                             reference: self
                                                 .spread_config
@@ -230,8 +252,7 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ComponentDaemonScaler<S> {
                                                 .to_owned(),
                                             component_id: component_id.to_owned(),
                                             host_id: host_id.to_string(),
-                                            count: self.spread_config.spread_config.instances
-                                                as u32,
+                                            count: count as u32,
                                             model_name: self.spread_config.model_name.to_owned(),
                                             annotations: spreadscaler_annotations(
                                                 &spread.name,
@@ -258,9 +279,20 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ComponentDaemonScaler<S> {
             .collect::<Vec<Command>>();
         trace!(?commands, "Calculated commands for component daemon scaler");
 
+        spread_status.extend(unknown_pinned_hosts_status(
+            &hosts,
+            &self.spread_config.spread_config.hosts,
+        ));
+        spread_status.extend(host_constraint_status(
+            &hosts,
+            self.spread_config.host_constraint.as_ref(),
+        ));
+
         let status = match (spread_status.is_empty(), commands.is_empty()) {
             // No failures, no commands, scaler satisfied
-            (true, true) => StatusInfo::deployed(""),
+            (true, true) => StatusInfo::deployed(&pinned_placement_message(
+                &self.spread_config.spread_config.hosts,
+            )),
             // No failures, commands generated, scaler is reconciling
             (true, false) => {
                 StatusInfo::reconciling(&format!("Scaling component on {} host(s)", commands.len()))
@@ -322,16 +354,7 @@ impl<S: ReadStore + Send + Sync> ComponentDaemonScaler<S> {
         ];
         id_parts.extend(config.iter().map(std::string::String::as_str));
         let id = compute_id_sha256(&id_parts);
-        // If no spreads are specified, an empty spread is sufficient to match _every_ host
-        // in a lattice
-        let spread_config = if spread_config.spread.is_empty() {
-            SpreadScalerProperty {
-                instances: spread_config.instances,
-                spread: vec![Spread::default()],
-            }
-        } else {
-            spread_config
-        };
+        let spread_config = daemon_spread_config(spread_config);
         Self {
             store,
             spread_config: ComponentSpreadConfig {
@@ -340,12 +363,45 @@ impl<S: ReadStore + Send + Sync> ComponentDaemonScaler<S> {
                 lattice_id,
                 spread_config,
                 model_name,
+                host_constraint: None,
+                anti_affinity: Vec::new(),
             },
             id,
             status: RwLock::new(StatusInfo::reconciling("")),
             config,
         }
     }
+
+    /// Only place the component on hosts that meet the given constraint
+    pub fn with_host_constraint(mut self, constraint: Option<HostConstraintProperty>) -> Self {
+        self.spread_config.host_constraint = constraint;
+        self
+    }
+
+    /// Never place the component on a host running any of the components or providers with the
+    /// given IDs
+    pub fn with_anti_affinity(mut self, ids: Vec<String>) -> Self {
+        self.spread_config.anti_affinity = ids;
+        self
+    }
+}
+
+/// Normalizes the spread configuration for a daemon scaler. An empty list of spreads matches every
+/// host, and scalers pinned to specific hosts bypass label selection with a single pinned spread
+pub(crate) fn daemon_spread_config(spread_config: SpreadScalerProperty) -> SpreadScalerProperty {
+    if !spread_config.hosts.is_empty() {
+        SpreadScalerProperty {
+            spread: vec![pinned_spread()],
+            ..spread_config
+        }
+    } else if spread_config.spread.is_empty() {
+        SpreadScalerProperty {
+            spread: vec![Spread::default()],
+            ..spread_config
+        }
+    } else {
+        spread_config
+    }
 }
 
 #[cfg(test)]
@@ -426,6 +482,10 @@ mod test {
                     weight: Some(384),
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let daemonscaler = ComponentDaemonScaler::new(
@@ -517,6 +577,10 @@ mod test {
                     weight: None,
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let blobby_spread_property = SpreadScalerProperty {
@@ -547,6 +611,10 @@ mod test {
                     weight: Some(33),
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let echo_daemonscaler = ComponentDaemonScaler::new(
@@ -835,6 +903,10 @@ mod test {
                 )]),
                 weight: None,
             }],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
         let blobby_daemonscaler = ComponentDaemonScaler::new(
             store.clone(),
@@ -992,6 +1064,8 @@ mod test {
                 lattice_id: lattice_id.to_string(),
                 inner: Event::HostHeartbeat(modifying_event.clone()),
                 acker: None,
+                expires_at: None,
+                trace_context: None,
             })
             .await
             .expect("should be able to handle an event");
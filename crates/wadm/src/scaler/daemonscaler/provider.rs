@@ -4,15 +4,16 @@ use anyhow::Result;
 use async_trait::async_trait;
 use tokio::sync::RwLock;
 use tracing::{instrument, trace};
-use wadm_types::{api::StatusInfo, Spread, SpreadScalerProperty, TraitProperty};
+use wadm_types::{api::StatusInfo, HostConstraintProperty, Spread, TraitProperty};
 
 use crate::commands::StopProvider;
 use crate::events::{HostHeartbeat, ProviderInfo, ProviderStarted, ProviderStopped};
-use crate::scaler::compute_id_sha256;
 use crate::scaler::spreadscaler::{
-    compute_ineligible_hosts, eligible_hosts, provider::ProviderSpreadConfig,
-    spreadscaler_annotations,
+    can_place_on, compute_ineligible_hosts, eligible_hosts, host_constraint_status,
+    pinned_placement_message, provider::ProviderSpreadConfig, spreadscaler_annotations,
+    unknown_pinned_hosts_status,
 };
+use crate::scaler::{compute_id_sha256, provider_reference};
 use crate::SCALER_KEY;
 use crate::{
     commands::{Command, StartProvider},
@@ -21,7 +22,7 @@ use crate::{
     storage::{Host, ReadStore},
 };
 
-use super::DAEMON_SCALER_KIND;
+use super::{daemon_spread_config, DAEMON_SCALER_KIND};
 
 /// The ProviderDaemonScaler ensures that a provider is running on every host, according to a
 /// [SpreadScalerProperty](crate::model::SpreadScalerProperty)
@@ -33,6 +34,10 @@ pub struct ProviderDaemonScaler<S> {
     store: S,
     id: String,
     status: RwLock<StatusInfo>,
+    /// Constraint on the hosts the provider can be placed on
+    host_constraint: Option<HostConstraintProperty>,
+    /// IDs of the components and providers the provider is never placed on the same host as
+    anti_affinity: Vec<String>,
 }
 
 #[async_trait]
@@ -49,6 +54,10 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ProviderDaemonScaler<S> {
         self.config.provider_id.to_string()
     }
 
+    async fn references(&self) -> Vec<String> {
+        vec![provider_reference(&self.config.provider_id)]
+    }
+
     async fn status(&self) -> StatusInfo {
         let _ = self.reconcile().await;
         self.status.read().await.to_owned()
@@ -59,16 +68,7 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ProviderDaemonScaler<S> {
             TraitProperty::SpreadScaler(prop) => prop,
             _ => anyhow::bail!("Given config was not a daemon scaler config object"),
         };
-        // If no spreads are specified, an empty spread is sufficient to match _every_ host
-        // in a lattice
-        let spread_config = if spread_config.spread.is_empty() {
-            SpreadScalerProperty {
-                instances: spread_config.instances,
-                spread: vec![Spread::default()],
-            }
-        } else {
-            spread_config
-        };
+        let spread_config = daemon_spread_config(spread_config);
         self.config.spread_config = spread_config;
         self.reconcile().await
     }
@@ -97,6 +97,8 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ProviderDaemonScaler<S> {
             {
                 self.reconcile().await
             }
+            // A label change can move a host into or out of any spread, so always reconcile
+            Event::HostLabelsChanged(_) => self.reconcile().await,
             // No other event impacts the job of this scaler so we can ignore it
             _ => Ok(Vec::new()),
         }
@@ -116,6 +118,8 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ProviderDaemonScaler<S> {
                 .spread
                 .iter()
                 .collect::<Vec<&Spread>>(),
+            &self.config.spread_config.hosts,
+            self.host_constraint.as_ref(),
         );
         // Remove any providers that are managed by this scaler and running on ineligible hosts
         let remove_ineligible: Vec<Command> = ineligible_hosts
@@ -160,13 +164,18 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ProviderDaemonScaler<S> {
         let mut spread_status = vec![];
 
         trace!(spread = ?self.config.spread_config.spread, ?provider_id, "Computing commands");
-        let commands = self
+        let mut commands = self
             .config
             .spread_config
             .spread
             .iter()
             .flat_map(|spread| {
-                let eligible_hosts = eligible_hosts(&hosts, spread);
+                let eligible_hosts = eligible_hosts(
+                    &hosts,
+                    spread,
+                    &self.config.spread_config.hosts,
+                    self.host_constraint.as_ref(),
+                );
                 if !eligible_hosts.is_empty() {
                     eligible_hosts
                         .iter()
@@ -186,15 +195,21 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ProviderDaemonScaler<S> {
                                     model_name: self.config.model_name.to_owned(),
                                     annotations: spreadscaler_annotations(&spread.name, &self.id),
                                 })),
-                                // Whenever instances > 0, we should start a provider if it's not already running
-                                (None, _n) => Some(Command::StartProvider(StartProvider {
-                                    reference: provider_ref.to_owned(),
-                                    provider_id: provider_id.to_owned(),
-                                    host_id: host.id.to_string(),
-                                    model_name: self.config.model_name.to_owned(),
-                                    annotations: spreadscaler_annotations(&spread.name, &self.id),
-                                    config: self.config.provider_config.clone(),
-                                })),
+                                // Whenever instances > 0, we should start a provider if it's not already running,
+                                // unless nothing new can be placed on the host
+                                (None, _n) if can_place_on(host, &self.anti_affinity) => {
+                                    Some(Command::StartProvider(StartProvider {
+                                        reference: provider_ref.to_owned(),
+                                        provider_id: provider_id.to_owned(),
+                                        host_id: host.id.to_string(),
+                                        model_name: self.config.model_name.to_owned(),
+                                        annotations: spreadscaler_annotations(
+                                            &spread.name,
+                                            &self.id,
+                                        ),
+                                        config: self.config.provider_config.clone(),
+                                    }))
+                                }
                                 _ => None,
                             }
                         })
@@ -210,12 +225,27 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ProviderDaemonScaler<S> {
                 }
             })
             .collect::<Vec<Command>>();
+        // Every provider command starts or stops a single instance
+        if let Some(max) = self.config.spread_config.max_concurrent_changes {
+            commands.truncate(max);
+        }
 
         trace!(?commands, "Calculated commands for provider daemonscaler");
 
+        spread_status.extend(unknown_pinned_hosts_status(
+            &hosts,
+            &self.config.spread_config.hosts,
+        ));
+        spread_status.extend(host_constraint_status(
+            &hosts,
+            self.host_constraint.as_ref(),
+        ));
+
         let status = match (spread_status.is_empty(), commands.is_empty()) {
             // No failures, no commands, scaler satisfied
-            (true, true) => StatusInfo::deployed(""),
+            (true, true) => {
+                StatusInfo::deployed(&pinned_placement_message(&self.config.spread_config.hosts))
+            }
             // No failures, commands generated, scaler is reconciling
             (true, false) => {
                 StatusInfo::reconciling(&format!("Scaling provider on {} host(s)", commands.len()))
@@ -245,6 +275,8 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ProviderDaemonScaler<S> {
             store: self.store.clone(),
             id: self.id.clone(),
             status: RwLock::new(StatusInfo::reconciling("")),
+            host_constraint: self.host_constraint.clone(),
+            anti_affinity: self.anti_affinity.clone(),
         };
 
         cleanerupper.reconcile().await
@@ -272,16 +304,7 @@ impl<S: ReadStore + Send + Sync> ProviderDaemonScaler<S> {
         );
         let id = compute_id_sha256(&id_parts);
 
-        // If no spreads are specified, an empty spread is sufficient to match _every_ host
-        // in a lattice
-        let spread_config = if config.spread_config.spread.is_empty() {
-            SpreadScalerProperty {
-                instances: config.spread_config.instances,
-                spread: vec![Spread::default()],
-            }
-        } else {
-            config.spread_config
-        };
+        let spread_config = daemon_spread_config(config.spread_config);
         Self {
             store,
             config: ProviderSpreadConfig {
@@ -290,8 +313,23 @@ impl<S: ReadStore + Send + Sync> ProviderDaemonScaler<S> {
             },
             id,
             status: RwLock::new(StatusInfo::reconciling("")),
+            host_constraint: None,
+            anti_affinity: Vec::new(),
         }
     }
+
+    /// Only place the provider on hosts that meet the given constraint
+    pub fn with_host_constraint(mut self, constraint: Option<HostConstraintProperty>) -> Self {
+        self.host_constraint = constraint;
+        self
+    }
+
+    /// Never place the provider on a host running any of the components or providers with the
+    /// given IDs
+    pub fn with_anti_affinity(mut self, ids: Vec<String>) -> Self {
+        self.anti_affinity = ids;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -326,6 +364,10 @@ mod test {
             spread_config: SpreadScalerProperty {
                 instances: 1,
                 spread: vec![],
+                hosts: Vec::new(),
+                max_concurrent_changes: None,
+                max_per_host: None,
+                anti_affinity: vec![],
             },
             provider_config: vec![],
         };
@@ -341,6 +383,10 @@ mod test {
             spread_config: SpreadScalerProperty {
                 instances: 1,
                 spread: vec![],
+                hosts: Vec::new(),
+                max_concurrent_changes: None,
+                max_per_host: None,
+                anti_affinity: vec![],
             },
             provider_config: vec!["foobar".to_string()],
         };
@@ -430,6 +476,10 @@ mod test {
                 requirements: BTreeMap::from_iter([("inda".to_string(), "cloud".to_string())]),
                 weight: Some(100),
             }],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let spreadscaler = ProviderDaemonScaler::new(
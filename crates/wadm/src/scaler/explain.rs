@@ -0,0 +1,298 @@
+//! Explains where the scaler of a component places it. For every host in the lattice this gives
+//! the reasons the host is or isn't a candidate, so "why isn't my component on that host" can be
+//! answered without reading through scaler logs
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{bail, Result};
+use wadm_types::{
+    api::HostPlacement, Manifest, Properties, Spread, TraitProperty, DAEMONSCALER_TRAIT,
+    SPREADSCALER_TRAIT,
+};
+
+use crate::{commands::Command, storage::Host};
+
+use super::{
+    convert::{anti_affinity_ids, compute_component_id, host_constraint},
+    spreadscaler::{describe_host_constraint, meets_host_constraint},
+};
+
+/// Returns the placement of the named component on every host, sorted by host ID. The planned
+/// instances come from the given commands, which should be the ones the scalers of the manifest
+/// would issue against the same hosts
+pub(crate) fn explain_placement(
+    manifest: &Manifest,
+    component_name: &str,
+    hosts: &HashMap<String, Host>,
+    commands: &[Command],
+) -> Result<Vec<HostPlacement>> {
+    let Some(component) = manifest.components().find(|c| c.name == component_name) else {
+        bail!(
+            "Application {} has no component named {component_name}",
+            manifest.metadata.name
+        );
+    };
+    let (id, image, is_provider) = match &component.properties {
+        Properties::Component { properties } => (properties.id.as_ref(), &properties.image, false),
+        Properties::Capability { properties } => (properties.id.as_ref(), &properties.image, true),
+    };
+    if image.is_none() {
+        bail!("Component {component_name} is shared from another application, which places it");
+    }
+    let id = compute_component_id(&manifest.metadata.name, id, component_name);
+    let Some((trait_type, spread_config)) =
+        component
+            .traits
+            .iter()
+            .flatten()
+            .find_map(|t| match &t.properties {
+                TraitProperty::SpreadScaler(p)
+                    if t.trait_type == SPREADSCALER_TRAIT || t.trait_type == DAEMONSCALER_TRAIT =>
+                {
+                    Some((t.trait_type.as_str(), p))
+                }
+                _ => None,
+            })
+    else {
+        bail!("Component {component_name} has no spreadscaler or daemonscaler, so it isn't placed by wadm");
+    };
+    let constraint = host_constraint(component.traits.as_ref());
+    let anti_affinity = anti_affinity_ids(
+        &manifest.metadata.name,
+        &manifest.spec.components,
+        &spread_config.anti_affinity,
+    );
+    let spreads = if spread_config.spread.is_empty() {
+        vec![Spread::default()]
+    } else {
+        spread_config.spread.clone()
+    };
+
+    let mut placements: Vec<HostPlacement> = hosts
+        .iter()
+        .map(|(host_id, host)| {
+            let mut reasons = Vec::new();
+            let mut eligible = true;
+            if host.is_draining() {
+                eligible = false;
+                reasons.push("Host is being drained".to_string());
+            }
+            if let Some(constraint) = constraint.as_ref() {
+                if !meets_host_constraint(host, Some(constraint)) {
+                    eligible = false;
+                    reasons.push(format!(
+                        "Host doesn't meet the host constraint ({})",
+                        describe_host_constraint(constraint)
+                    ));
+                }
+            }
+            if !spread_config.hosts.is_empty() {
+                if spread_config.hosts.contains(host_id) {
+                    reasons.push("Host is pinned".to_string());
+                } else {
+                    eligible = false;
+                    reasons.push(format!(
+                        "Host is not one of the pinned hosts ({})",
+                        spread_config.hosts.join(", ")
+                    ));
+                }
+            } else {
+                let (matched, mismatched): (Vec<_>, Vec<_>) = spreads
+                    .iter()
+                    .map(|spread| (spread, missing_labels(host, spread)))
+                    .partition(|(_, missing)| missing.is_empty());
+                if matched.is_empty() {
+                    eligible = false;
+                    reasons.extend(mismatched.into_iter().map(|(spread, missing)| {
+                        format!(
+                            "Host doesn't match spread {}: {}",
+                            spread.name,
+                            missing.join(", ")
+                        )
+                    }));
+                } else {
+                    reasons.push(format!(
+                        "Host matches spread {}",
+                        matched
+                            .iter()
+                            .map(|(spread, _)| spread.name.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
+                }
+            }
+            if eligible && host.is_cordoned() {
+                eligible = false;
+                reasons.push("Host is cordoned, so nothing new is placed on it".to_string());
+            }
+            if eligible {
+                for other in anti_affinity.iter().filter(|other| runs(host, other)) {
+                    eligible = false;
+                    reasons.push(format!(
+                        "Host runs {other}, which {component_name} is never placed with"
+                    ));
+                }
+            }
+            if eligible && !is_provider && host.free_instances() == Some(0) {
+                eligible = false;
+                reasons.push("Host has no room for more instances".to_string());
+            }
+
+            let current_instances = if is_provider {
+                runs(host, &id) as usize
+            } else {
+                host.components.get(&id).copied().unwrap_or_default()
+            };
+            // The last command for the host is the one that sticks
+            let planned_instances = commands
+                .iter()
+                .rev()
+                .find_map(|command| match command {
+                    Command::ScaleComponent(cmd)
+                        if cmd.component_id == id && &cmd.host_id == host_id =>
+                    {
+                        Some(cmd.count as usize)
+                    }
+                    Command::StartProvider(cmd)
+                        if cmd.provider_id == id && &cmd.host_id == host_id =>
+                    {
+                        Some(1)
+                    }
+                    Command::StopProvider(cmd)
+                        if cmd.provider_id == id && &cmd.host_id == host_id =>
+                    {
+                        Some(0)
+                    }
+                    _ => None,
+                })
+                .unwrap_or(current_instances);
+            if eligible {
+                reasons.push(format!("Host is a candidate for the {trait_type}"));
+            }
+
+            HostPlacement {
+                host_id: host_id.clone(),
+                friendly_name: host.friendly_name.clone(),
+                eligible,
+                matching_labels: matching_labels(host, &spreads, constraint.as_ref()),
+                reasons,
+                current_instances,
+                planned_instances,
+            }
+        })
+        .collect();
+    placements.sort_by(|a, b| a.host_id.cmp(&b.host_id));
+    Ok(placements)
+}
+
+/// Returns a description of every requirement of the spread the host's labels don't meet
+fn missing_labels(host: &Host, spread: &Spread) -> Vec<String> {
+    spread
+        .requirements
+        .iter()
+        .filter_map(|(key, wanted)| match host.labels.get(key) {
+            Some(value) if value == wanted => None,
+            Some(value) => Some(format!("{key} is {value}, wanted {wanted}")),
+            None => Some(format!("{key} is missing, wanted {wanted}")),
+        })
+        .collect()
+}
+
+/// Returns the labels of the host that match a spread requirement or the host constraint
+fn matching_labels(
+    host: &Host,
+    spreads: &[Spread],
+    constraint: Option<&wadm_types::HostConstraintProperty>,
+) -> BTreeMap<String, String> {
+    spreads
+        .iter()
+        .flat_map(|spread| spread.requirements.iter())
+        .chain(
+            constraint
+                .into_iter()
+                .flat_map(|c| c.required_labels.iter()),
+        )
+        .filter(|(key, wanted)| host.labels.get(*key) == Some(*wanted))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// Returns whether the host runs the component or provider with the given ID
+fn runs(host: &Host, id: &str) -> bool {
+    host.components.contains_key(id)
+        || host
+            .providers
+            .iter()
+            .any(|provider| provider.provider_id == id)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::commands::ScaleComponent;
+
+    fn host(id: &str, version: &str, labels: &[(&str, &str)]) -> (String, Host) {
+        (
+            id.to_string(),
+            Host {
+                components: HashMap::new(),
+                friendly_name: format!("{id}-friendly"),
+                labels: labels
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                providers: HashSet::new(),
+                uptime_seconds: 1,
+                version: Some(semver::Version::parse(version).unwrap()),
+                id: id.to_string(),
+                last_seen: Utc::now(),
+            },
+        )
+    }
+
+    #[test]
+    fn explains_why_hosts_are_excluded() {
+        let manifest: Manifest = serde_yaml::from_slice(
+            &std::fs::read("../../tests/fixtures/manifests/host-constraint.wadm.yaml").unwrap(),
+        )
+        .unwrap();
+        let feature = ("wasmcloud.dev/feature-wasi-p3", "true");
+        let hosts = HashMap::from([
+            host("a", "1.2.0", &[feature]),
+            host("b", "1.2.0", &[]),
+            host("c", "1.1.0", &[feature]),
+            host("d", "1.3.0", &[feature, (crate::CORDON_LABEL, "true")]),
+        ]);
+        let commands = vec![Command::ScaleComponent(ScaleComponent {
+            component_id: "host_constraint-http_component".to_string(),
+            host_id: "a".to_string(),
+            count: 2,
+            ..Default::default()
+        })];
+
+        let placements = explain_placement(&manifest, "http-component", &hosts, &commands).unwrap();
+        assert_eq!(
+            placements.iter().map(|p| p.eligible).collect::<Vec<_>>(),
+            vec![true, false, false, false]
+        );
+        assert_eq!(placements[0].planned_instances, 2);
+        assert_eq!(placements[0].current_instances, 0);
+        assert_eq!(
+            placements[0].matching_labels,
+            BTreeMap::from([(feature.0.to_string(), feature.1.to_string())])
+        );
+        assert!(
+            placements[1].reasons[0].contains("wasmcloud.dev/feature-wasi-p3=true"),
+            "{:?}",
+            placements[1].reasons
+        );
+        assert!(placements[2].reasons[0].contains("version >= 1.2.0"));
+        assert!(placements[3].reasons.iter().any(|r| r.contains("cordoned")));
+
+        assert!(explain_placement(&manifest, "nope", &hosts, &commands).is_err());
+    }
+}
@@ -1,6 +1,10 @@
 //! A struct that manages creating and removing scalers for all manifests
 
-use std::{collections::HashMap, ops::Deref, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Deref,
+    sync::{Arc, Weak},
+};
 
 use anyhow::Result;
 use async_nats::jetstream::{
@@ -18,19 +22,27 @@ use tokio::{
 };
 use tracing::{debug, error, instrument, trace, warn};
 use wadm_types::{
-    api::{Status, StatusInfo},
+    api::{HostInstances, Status, StatusInfo},
     Manifest,
 };
 
 use crate::{
+    commands::StopProvider,
     events::Event,
     publisher::Publisher,
-    scaler::{Command, Scaler},
-    storage::{snapshot::SnapshotStore, ReadStore},
-    workers::{CommandPublisher, ConfigSource, LinkSource, SecretSource, StatusPublisher},
+    scaler::{link_reference, provider_reference, Command, Scaler},
+    secrets::SecretResolver,
+    storage::{snapshot::SnapshotStore, Component, Host, ReadStore},
+    workers::{
+        is_managed_by, AuctionSource, CommandPublisher, ConfigSource, HostCircuitBreaker,
+        LinkSource, MetricSource, SecretSource, StatusPublisher,
+    },
+    APP_SPEC_ANNOTATION,
 };
 
-use super::convert::manifest_components_to_scalers;
+use super::{
+    convert::manifest_components_to_scalers, rollout::hand_over_scalers, PlacementStrategy,
+};
 
 pub type BoxedScaler = Box<dyn Scaler + Send + Sync + 'static>;
 pub type ScalerList = Vec<BoxedScaler>;
@@ -42,6 +54,10 @@ pub const WADM_NOTIFY_PREFIX: &str = "wadm.notify";
 pub enum Notifications {
     CreateScalers(Manifest),
     DeleteScalers(String),
+    /// Stop running the scalers for a manifest without removing them
+    PauseScalers(String),
+    /// Start running the paused scalers for a manifest again
+    ResumeScalers(String),
     /// Register expected events for a manifest. You can either trigger this with an event (which
     /// will result in calling `handle_event`) or without in order to calculate expected events with
     /// a full reconcile (like on first deploy), rather than just handling a single event
@@ -56,6 +72,12 @@ pub enum Notifications {
         scaler_id: String,
         event: CloudEvent,
     },
+    /// Reconcile a manifest scaler and publish the resulting commands, for scalers that change
+    /// on their own schedule rather than in response to events
+    ReconcileScaler {
+        name: String,
+        scaler_id: String,
+    },
 }
 
 /// A wrapper type returned when getting a list of scalers for a model
@@ -97,12 +119,41 @@ impl Deref for SingleScaler {
     }
 }
 
+/// A read only view of the scalers of a [`ScalerManager`]. Unlike a clone of the manager, this
+/// doesn't keep the manager alive, so it can be held onto for introspection
+#[derive(Clone)]
+pub struct ScalerView {
+    lattice_id: String,
+    scalers: Weak<RwLock<HashMap<String, ScalerList>>>,
+}
+
+impl ScalerView {
+    /// The lattice the scalers are for
+    pub fn lattice_id(&self) -> &str {
+        &self.lattice_id
+    }
+
+    /// Gets all current managed scalers. Returns `None` if the manager was dropped
+    pub async fn get_all_scalers(&self) -> Option<AllScalers> {
+        Some(AllScalers {
+            scalers: self.scalers.upgrade()?.read_owned().await,
+        })
+    }
+
+    /// Returns whether the manager was dropped
+    pub fn is_dropped(&self) -> bool {
+        self.scalers.strong_count() == 0
+    }
+}
+
 /// A manager that consumes notifications from a stream for a lattice and then either adds or removes the
 /// necessary scalers
 #[derive(Clone)]
 pub struct ScalerManager<StateStore, P: Clone, L: Clone> {
     handle: Option<Arc<JoinHandle<Result<()>>>>,
     scalers: Arc<RwLock<HashMap<String, ScalerList>>>,
+    /// The names of the manifests whose scalers are paused
+    paused: Arc<RwLock<HashSet<String>>>,
     client: P,
     subject: String,
     lattice_id: String,
@@ -123,11 +174,22 @@ impl<StateStore, P, L> ScalerManager<StateStore, P, L>
 where
     StateStore: ReadStore + Send + Sync + Clone + 'static,
     P: Publisher + Clone + Send + Sync + 'static,
-    L: LinkSource + ConfigSource + SecretSource + Clone + Send + Sync + 'static,
+    L: LinkSource
+        + ConfigSource
+        + SecretSource
+        + MetricSource
+        + AuctionSource
+        + Clone
+        + Send
+        + Sync
+        + 'static,
 {
     /// Creates a new ScalerManager configured to notify messages to `wadm.notify.{lattice_id}`
     /// using the given jetstream client. Also creates an ephemeral consumer for notifications on
-    /// the given stream
+    /// the given stream. Hosts with an open circuit in the given circuit breaker are not used by
+    /// any scalers, which also measure time with the circuit breaker's clock. Secret references in
+    /// configuration are resolved with the given resolver. With [`PlacementStrategy::Auction`],
+    /// spread scalers run auctions with the link getter before placing new instances
     #[allow(clippy::too_many_arguments)]
     pub async fn new(
         client: P,
@@ -139,6 +201,9 @@ where
         command_publisher: CommandPublisher<P>,
         status_publisher: StatusPublisher<P>,
         link_getter: L,
+        circuit_breaker: HostCircuitBreaker,
+        secret_resolver: Option<SecretResolver>,
+        placement: PlacementStrategy,
     ) -> Result<ScalerManager<StateStore, P, L>> {
         // Create the consumer first so that we can make sure we don't miss anything during the
         // first reconcile pass
@@ -179,11 +244,28 @@ where
             .filter_map(|manifest| manifest.transpose())
             .map(|res| res.map(|(manifest, _)| manifest))
             .collect::<Result<Vec<_>>>()?;
+        let paused: HashSet<String> = all_manifests
+            .iter()
+            .filter(|manifest| manifest.is_paused())
+            .map(|manifest| manifest.name().to_owned())
+            .collect();
         let snapshot_data = SnapshotStore::new(
             state_store.clone(),
             link_getter.clone(),
             lattice_id.to_owned(),
-        );
+        )
+        .with_clock(circuit_breaker.clock())
+        .with_circuit_breaker(circuit_breaker);
+        let snapshot_data = match secret_resolver {
+            Some(resolver) => snapshot_data.with_secret_resolver(resolver),
+            None => snapshot_data,
+        };
+        let snapshot_data = match placement {
+            PlacementStrategy::Auction => {
+                snapshot_data.with_auction_source(Arc::new(link_getter.clone()))
+            }
+            PlacementStrategy::Labels => snapshot_data,
+        };
         let scalers: HashMap<String, ScalerList> = all_manifests
             .into_iter()
             .filter_map(|manifest| {
@@ -207,6 +289,7 @@ where
         let mut manager = ScalerManager {
             handle: None,
             scalers,
+            paused: Arc::new(RwLock::new(paused)),
             client,
             subject,
             lattice_id: lattice_id.to_owned(),
@@ -239,6 +322,7 @@ where
         ScalerManager {
             handle: None,
             scalers: Arc::new(RwLock::new(HashMap::new())),
+            paused: Arc::default(),
             client,
             subject: format!("{WADM_NOTIFY_PREFIX}.{lattice_id}"),
             lattice_id: lattice_id.to_owned(),
@@ -254,6 +338,43 @@ where
         self.snapshot_data.refresh().await
     }
 
+    /// Returns how many instances of each of the given manifest's components are running on each
+    /// host, based on the current snapshot data
+    pub(crate) async fn instance_distribution(&self, name: &str) -> Result<Vec<HostInstances>> {
+        let components = self
+            .snapshot_data
+            .list::<Component>(&self.lattice_id)
+            .await?;
+        let mut hosts: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
+        for (id, component) in components {
+            for (host_id, infos) in component.instances {
+                let count: usize = infos
+                    .iter()
+                    .filter(|info| {
+                        info.annotations
+                            .get(APP_SPEC_ANNOTATION)
+                            .is_some_and(|app| app == name)
+                    })
+                    .map(|info| info.count)
+                    .sum();
+                if count > 0 {
+                    *hosts
+                        .entry(host_id)
+                        .or_default()
+                        .entry(id.clone())
+                        .or_default() += count;
+                }
+            }
+        }
+        Ok(hosts
+            .into_iter()
+            .map(|(host_id, components)| HostInstances {
+                host_id,
+                components,
+            })
+            .collect())
+    }
+
     /// Adds scalers for the given manifest. Emitting an event to notify other wadm processes that
     /// they should create them as well. Only returns an error if it can't notify. Returns the
     /// scaler list for immediate use in reconciliation
@@ -317,6 +438,14 @@ where
         .map(|scaler| SingleScaler { scaler })
     }
 
+    /// Returns a view of the scalers that doesn't keep this manager alive
+    pub fn view(&self) -> ScalerView {
+        ScalerView {
+            lattice_id: self.lattice_id.clone(),
+            scalers: Arc::downgrade(&self.scalers),
+        }
+    }
+
     /// Gets all current managed scalers
     #[instrument(level = "trace", skip(self), fields(lattice_id = %self.lattice_id))]
     pub async fn get_all_scalers(&self) -> AllScalers {
@@ -364,8 +493,39 @@ where
         }
     }
 
+    /// Pauses or resumes the scalers for the given model name, notifying other wadm processes to do
+    /// the same. Paused scalers are kept, but shouldn't be run until they are resumed. Scalers are
+    /// always resumed when they are replaced or removed
+    #[instrument(level = "debug", skip(self), fields(lattice_id = %self.lattice_id))]
+    pub async fn set_paused(&self, name: &str, paused: bool) -> Result<()> {
+        self.set_raw_paused(name, paused).await;
+        let notification = if paused {
+            Notifications::PauseScalers(name.to_owned())
+        } else {
+            Notifications::ResumeScalers(name.to_owned())
+        };
+        self.client
+            .publish(serde_json::to_vec(&notification)?, Some(&self.subject))
+            .await
+    }
+
+    /// Returns whether the scalers for the given model name are paused
+    pub async fn is_paused(&self, name: &str) -> bool {
+        self.paused.read().await.contains(name)
+    }
+
+    async fn set_raw_paused(&self, name: &str, paused: bool) {
+        let mut all = self.paused.write().await;
+        if paused {
+            all.insert(name.to_owned());
+        } else {
+            all.remove(name);
+        }
+    }
+
     /// An internal function to allow pushing the scalers without any of the publishing
     async fn add_raw_scalers(&self, name: &str, scalers: ScalerList) {
+        self.set_raw_paused(name, false).await;
         self.scalers.write().await.insert(name.to_owned(), scalers);
     }
 
@@ -373,6 +533,7 @@ where
     /// CAUTION: This function does not do any cleanup, so it should only be used in scenarios
     /// where you are prepared to handle that yourself.
     pub(crate) async fn remove_raw_scalers(&self, name: &str) -> Option<ScalerList> {
+        self.set_raw_paused(name, false).await;
         self.scalers.write().await.remove(name)
     }
 
@@ -402,6 +563,13 @@ where
                 return Some(Err(e));
             }
         };
+        let commands = match self.release_references(name, &scalers, commands).await {
+            Ok(c) => c,
+            Err(e) => {
+                self.scalers.write().await.insert(name.to_owned(), scalers);
+                return Some(Err(e));
+            }
+        };
         trace!(?commands, "Publishing cleanup commands");
         if let Err(e) = self.command_publisher.publish_commands(commands).await {
             error!(error = %e, "Unable to publish cleanup commands");
@@ -412,6 +580,52 @@ where
         }
     }
 
+    /// Narrows down the cleanup commands of a removed model to what it manages, keeping shared
+    /// resources that other deployed models still reference. When the model was the last one
+    /// referencing a provider, instances of it that were kept running for models that have since
+    /// been undeployed are stopped too
+    async fn release_references(
+        &self,
+        name: &str,
+        scalers: &ScalerList,
+        commands: Vec<Command>,
+    ) -> Result<Vec<Command>> {
+        let commands =
+            retain_managed(&self.snapshot_data, &self.lattice_id, name, commands).await?;
+        let released: HashSet<String> =
+            futures::future::join_all(scalers.iter().map(|scaler| scaler.references()))
+                .await
+                .into_iter()
+                .flatten()
+                .collect();
+        let (deployed, referenced) = {
+            let all = self.scalers.read().await;
+            let referenced: HashSet<String> = futures::future::join_all(
+                all.values()
+                    .flat_map(|scalers| scalers.iter().map(|scaler| scaler.references())),
+            )
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+            (all.keys().cloned().collect::<HashSet<_>>(), referenced)
+        };
+        let mut commands = retain_unreferenced(commands, &referenced);
+        let unreferenced: HashSet<&str> = released
+            .iter()
+            .filter(|reference| !referenced.contains(*reference))
+            .map(String::as_str)
+            .collect();
+        let hosts = self.snapshot_data.list::<Host>(&self.lattice_id).await?;
+        commands.extend(orphaned_provider_stops(
+            hosts.values(),
+            &unreferenced,
+            &deployed,
+            name,
+        ));
+        Ok(commands)
+    }
+
     #[instrument(level = "debug", skip_all, fields(lattice_id = %self.lattice_id))]
     async fn notify(&self, mut messages: MessageStream) -> Result<()> {
         loop {
@@ -430,6 +644,7 @@ where
                             match notification {
                                 Notifications::CreateScalers(manifest) => {
                                     // We don't want to trigger the notification, so just create the scalers and then insert
+                                    let old_scalers = self.remove_raw_scalers(&manifest.metadata.name).await;
                                     let scalers = manifest_components_to_scalers(
                                         &manifest.spec.components,
                                         &manifest.policy_lookup(),
@@ -439,6 +654,11 @@ where
                                         &self.client,
                                         &self.snapshot_data,
                                     );
+                                    // NOTE: Anything that isn't taken over is cleaned up by the wadm
+                                    // instance that handled the published manifest
+                                    if let Some(old_scalers) = old_scalers {
+                                        hand_over_scalers(&scalers, old_scalers).await;
+                                    }
                                     let num_scalers = scalers.len();
                                     self.add_raw_scalers(&manifest.metadata.name, scalers).await;
                                     trace!(name = %manifest.metadata.name, %num_scalers, "Finished creating scalers for manifest");
@@ -479,6 +699,14 @@ where
                                     // doesn't tear down everything or leaves something hanging. If that is
                                     // the case, a new part of the reaper logic should handle it
                                 },
+                                Notifications::PauseScalers(name) => {
+                                    trace!(%name, "Pausing scalers for manifest");
+                                    self.set_raw_paused(&name, true).await;
+                                }
+                                Notifications::ResumeScalers(name) => {
+                                    trace!(%name, "Resuming scalers for manifest");
+                                    self.set_raw_paused(&name, false).await;
+                                }
                                 // NOTE(thomastaylor312): Please note that both of the
                                 // ExpectedEvents blocks are "cheating". If the scaler is a backoff
                                 // wrapped scaler, calling `reconcile` or `handle_event` will
@@ -531,6 +759,29 @@ where
                                         debug!(%name, "Received request to remove event for non-existent scalers, ignoring");
                                     }
                                 }
+                                Notifications::ReconcileScaler { name, scaler_id } => {
+                                    trace!(%name, %scaler_id, "Reconciling scaler on request");
+                                    if self.is_paused(&name).await {
+                                        debug!(%name, "Reconciliation of manifest is paused, ignoring reconcile request");
+                                    } else if let Some(scaler) = self.get_specific_scaler(&name, &scaler_id).await {
+                                        let res = match self.refresh_data().await {
+                                            Ok(()) => scaler.reconcile().await,
+                                            Err(e) => Err(e),
+                                        };
+                                        match res {
+                                            Ok(commands) => {
+                                                if let Err(e) = self.command_publisher.publish_commands(commands).await {
+                                                    error!(error = %e, %name, %scaler_id, "Unable to publish commands for reconciled scaler");
+                                                }
+                                            }
+                                            Err(e) => {
+                                                error!(error = %e, %name, %scaler_id, "Unable to reconcile scaler");
+                                            }
+                                        }
+                                    } else {
+                                        debug!(%name, "Received request to reconcile non-existent scaler, ignoring");
+                                    }
+                                }
                             }
                             // Always ack if we get here
                             if let Err(e) = msg.double_ack().await {
@@ -553,3 +804,364 @@ where
         }
     }
 }
+
+/// Drops the cleanup commands for a model that would stop a component or provider the model didn't
+/// start, like an instance of the same image that was started by hand or by another model. Only
+/// instances carrying wadm's managed annotations for the model are stopped
+async fn retain_managed<S: ReadStore>(
+    store: &S,
+    lattice_id: &str,
+    model_name: &str,
+    commands: Vec<Command>,
+) -> Result<Vec<Command>> {
+    let mut managed = Vec::with_capacity(commands.len());
+    for command in commands {
+        let is_managed = match &command {
+            Command::ScaleComponent(cmd) => store
+                .get::<Component>(lattice_id, &cmd.component_id)
+                .await?
+                .and_then(|component| {
+                    component.instances.get(&cmd.host_id).map(|instances| {
+                        instances
+                            .iter()
+                            .any(|info| is_managed_by(&info.annotations, model_name))
+                    })
+                })
+                .unwrap_or(false),
+            Command::StopProvider(cmd) => store
+                .get::<Host>(lattice_id, &cmd.host_id)
+                .await?
+                .map(|host| {
+                    host.providers.iter().any(|provider| {
+                        provider.provider_id == cmd.provider_id
+                            && is_managed_by(&provider.annotations, model_name)
+                    })
+                })
+                .unwrap_or(false),
+            _ => true,
+        };
+        if is_managed {
+            managed.push(command);
+        } else {
+            debug!(
+                ?command,
+                "Not cleaning up something this model doesn't manage"
+            );
+        }
+    }
+    Ok(managed)
+}
+
+/// Drops the cleanup commands for shared resources, like providers and links, that are still
+/// referenced by other deployed models
+fn retain_unreferenced(commands: Vec<Command>, referenced: &HashSet<String>) -> Vec<Command> {
+    commands
+        .into_iter()
+        .filter(|command| {
+            let reference = match command {
+                Command::StopProvider(cmd) => provider_reference(&cmd.provider_id),
+                Command::DeleteLink(cmd) => link_reference(
+                    &cmd.source_id,
+                    &cmd.wit_namespace,
+                    &cmd.wit_package,
+                    &cmd.link_name,
+                ),
+                _ => return true,
+            };
+            let keep = !referenced.contains(&reference);
+            if !keep {
+                debug!(%reference, "Not cleaning up a resource another model still references");
+            }
+            keep
+        })
+        .collect()
+}
+
+/// Returns the commands to stop the wadm managed instances of the given providers that belong to
+/// models that are no longer deployed. These are left running when their model is undeployed while
+/// another model still references them. Instances belonging to `model_name` are already handled by
+/// its own scalers
+fn orphaned_provider_stops<'a>(
+    hosts: impl IntoIterator<Item = &'a Host>,
+    unreferenced: &HashSet<&str>,
+    deployed: &HashSet<String>,
+    model_name: &str,
+) -> Vec<Command> {
+    hosts
+        .into_iter()
+        .flat_map(|host| {
+            host.providers.iter().filter_map(|provider| {
+                if !unreferenced.contains(provider_reference(&provider.provider_id).as_str()) {
+                    return None;
+                }
+                let owner = provider.annotations.get(APP_SPEC_ANNOTATION)?;
+                if owner == model_name
+                    || deployed.contains(owner)
+                    || !is_managed_by(&provider.annotations, owner)
+                {
+                    return None;
+                }
+                Some(Command::StopProvider(StopProvider {
+                    provider_id: provider.provider_id.clone(),
+                    host_id: host.id.clone(),
+                    model_name: owner.clone(),
+                    annotations: BTreeMap::default(),
+                }))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, HashMap, HashSet};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        events::ProviderInfo,
+        storage::{Store, WadmComponentInfo},
+        test_util::{NoopPublisher, TestLatticeSource, TestStore},
+    };
+
+    #[tokio::test]
+    async fn can_compute_instance_distribution() {
+        let lattice_id = "instance_distribution";
+        let store = Arc::new(TestStore::default());
+        let info = |app: &str, count: usize| WadmComponentInfo {
+            annotations: BTreeMap::from([(APP_SPEC_ANNOTATION.to_string(), app.to_string())]),
+            count,
+        };
+        store
+            .store(
+                lattice_id,
+                "hello".to_string(),
+                Component {
+                    id: "hello".to_string(),
+                    instances: HashMap::from([
+                        (
+                            "NASDASDIMAREALHOSTONE".to_string(),
+                            HashSet::from([info("myapp", 2), info("otherapp", 5)]),
+                        ),
+                        (
+                            "NASDASDIMAREALHOSTTWO".to_string(),
+                            HashSet::from([info("otherapp", 1)]),
+                        ),
+                    ]),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let command_publisher = CommandPublisher::new(NoopPublisher, "doesntmatter");
+        let status_publisher = StatusPublisher::new(NoopPublisher, None, "doesntmatter");
+        let manager = ScalerManager::test_new(
+            NoopPublisher,
+            lattice_id,
+            store,
+            command_publisher,
+            status_publisher,
+            TestLatticeSource::default(),
+        )
+        .await;
+        manager.refresh_data().await.unwrap();
+
+        let instances = manager
+            .instance_distribution("myapp")
+            .await
+            .expect("Should be able to compute distribution");
+        assert_eq!(
+            instances,
+            vec![HostInstances {
+                host_id: "NASDASDIMAREALHOSTONE".to_string(),
+                components: BTreeMap::from([("hello".to_string(), 2)]),
+            }],
+            "Only instances for the app should be counted"
+        );
+        assert!(manager
+            .instance_distribution("notanapp")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn pauses_scalers_until_replaced() {
+        let manager = ScalerManager::test_new(
+            NoopPublisher,
+            "pause",
+            Arc::new(TestStore::default()),
+            CommandPublisher::new(NoopPublisher, "doesntmatter"),
+            StatusPublisher::new(NoopPublisher, None, "doesntmatter"),
+            TestLatticeSource::default(),
+        )
+        .await;
+        let manifest: Manifest = serde_yaml::from_slice(
+            &std::fs::read("../../tests/fixtures/manifests/simple2.yaml").unwrap(),
+        )
+        .unwrap();
+        let name = manifest.metadata.name.clone();
+
+        manager.set_paused(&name, true).await.unwrap();
+        assert!(manager.is_paused(&name).await);
+        manager.set_paused(&name, false).await.unwrap();
+        assert!(!manager.is_paused(&name).await);
+
+        manager.set_paused(&name, true).await.unwrap();
+        let scalers = manager.scalers_for_manifest(&manifest);
+        manager.add_scalers(&manifest, scalers).await.unwrap();
+        assert!(
+            !manager.is_paused(&name).await,
+            "Deploying a manifest again should resume it"
+        );
+    }
+
+    #[tokio::test]
+    async fn only_cleans_up_managed_instances() {
+        let lattice_id = "managed_cleanup";
+        let store = TestStore::default();
+        let managed = |app: &str| {
+            let mut annotations = BTreeMap::new();
+            crate::workers::insert_managed_annotations(&mut annotations, app);
+            annotations
+        };
+        store
+            .store(
+                lattice_id,
+                "hello".to_string(),
+                Component {
+                    id: "hello".to_string(),
+                    instances: HashMap::from([
+                        (
+                            "host1".to_string(),
+                            HashSet::from([WadmComponentInfo {
+                                annotations: managed("myapp"),
+                                count: 1,
+                            }]),
+                        ),
+                        (
+                            "host2".to_string(),
+                            HashSet::from([WadmComponentInfo {
+                                annotations: BTreeMap::new(),
+                                count: 1,
+                            }]),
+                        ),
+                    ]),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let host = |id: &str, annotations| Host {
+            id: id.to_string(),
+            providers: HashSet::from([ProviderInfo {
+                provider_id: "httpserver".to_string(),
+                provider_ref: "ghcr.io/httpserver:0.1.0".to_string(),
+                annotations,
+            }]),
+            ..Default::default()
+        };
+        store
+            .store(
+                lattice_id,
+                "host1".to_string(),
+                host("host1", managed("otherapp")),
+            )
+            .await
+            .unwrap();
+        store
+            .store(
+                lattice_id,
+                "host2".to_string(),
+                host("host2", managed("myapp")),
+            )
+            .await
+            .unwrap();
+
+        let scale = |host_id: &str| {
+            Command::ScaleComponent(crate::commands::ScaleComponent {
+                component_id: "hello".to_string(),
+                host_id: host_id.to_string(),
+                count: 0,
+                model_name: "myapp".to_string(),
+                ..Default::default()
+            })
+        };
+        let stop = |host_id: &str| {
+            Command::StopProvider(crate::commands::StopProvider {
+                provider_id: "httpserver".to_string(),
+                host_id: host_id.to_string(),
+                model_name: "myapp".to_string(),
+                ..Default::default()
+            })
+        };
+        let commands = retain_managed(
+            &store,
+            lattice_id,
+            "myapp",
+            vec![scale("host1"), scale("host2"), stop("host1"), stop("host2")],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            commands,
+            vec![scale("host1"), stop("host2")],
+            "Only instances started by wadm for the app should be stopped"
+        );
+    }
+
+    #[test]
+    fn keeps_resources_other_apps_reference() {
+        let stop = |host_id: &str, model_name: &str| {
+            Command::StopProvider(StopProvider {
+                provider_id: "httpserver".to_string(),
+                host_id: host_id.to_string(),
+                model_name: model_name.to_string(),
+                ..Default::default()
+            })
+        };
+        let delete = Command::DeleteLink(crate::commands::DeleteLink {
+            source_id: "hello".to_string(),
+            wit_namespace: "wasi".to_string(),
+            wit_package: "http".to_string(),
+            link_name: "default".to_string(),
+            model_name: "myapp".to_string(),
+        });
+        let referenced = HashSet::from([provider_reference("httpserver")]);
+        assert_eq!(
+            retain_unreferenced(vec![stop("host1", "myapp"), delete.clone()], &referenced),
+            vec![delete],
+            "A provider another app still references shouldn't be stopped"
+        );
+
+        let managed = |app: &str| {
+            let mut annotations = BTreeMap::new();
+            crate::workers::insert_managed_annotations(&mut annotations, app);
+            annotations
+        };
+        let host = |id: &str, annotations| Host {
+            id: id.to_string(),
+            providers: HashSet::from([ProviderInfo {
+                provider_id: "httpserver".to_string(),
+                provider_ref: "ghcr.io/httpserver:0.1.0".to_string(),
+                annotations,
+            }]),
+            ..Default::default()
+        };
+        let hosts = [
+            host("host1", managed("undeployed")),
+            host("host2", managed("deployed")),
+            host("host3", managed("myapp")),
+            host("host4", BTreeMap::new()),
+        ];
+        let unreferenced = HashSet::from(["provider/httpserver"]);
+        let deployed = HashSet::from(["deployed".to_string()]);
+        assert_eq!(
+            orphaned_provider_stops(&hosts, &unreferenced, &deployed, "myapp"),
+            vec![stop("host1", "undeployed")],
+            "Only instances kept running for undeployed apps should be stopped"
+        );
+        assert!(orphaned_provider_stops(&hosts, &HashSet::new(), &deployed, "myapp").is_empty());
+    }
+}
@@ -1,4 +1,10 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use anyhow::Result;
 use async_trait::async_trait;
@@ -7,7 +13,7 @@ use tokio::{
     sync::{Mutex, RwLock},
     task::JoinHandle,
 };
-use tracing::{error, instrument, trace, Instrument};
+use tracing::{error, instrument, trace, warn, Instrument};
 use wadm_types::{api::StatusInfo, TraitProperty};
 
 use crate::{
@@ -17,20 +23,36 @@ use crate::{
     workers::{get_commands_and_result, ConfigSource, SecretSource},
 };
 
+mod autoscaler;
 pub mod configscaler;
-mod convert;
+pub(crate) mod convert;
 pub mod daemonscaler;
+mod explain;
 pub mod manager;
+mod placement;
+pub mod planner;
+pub mod rollout;
+mod schedule;
 pub mod secretscaler;
 pub mod spreadscaler;
 pub mod statusscaler;
 
+pub use placement::{PlacementStrategy, SharedAuctionSource};
+
 use manager::Notifications;
 
 use self::configscaler::ConfigScaler;
 use self::secretscaler::SecretScaler;
 
 const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a scaler backs off after the first failure event. Every failure in a row after that
+/// doubles the time, up to [`MAX_BACKOFF`]
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+/// The longest a scaler backs off between attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// The number of failures in a row after which a scaler stops retrying. The application has to be
+/// put or deployed again for the scaler to try again
+const MAX_FAILED_ATTEMPTS: u32 = 8;
 const DEFAULT_SCALER_KIND: &str = "Scaler";
 
 /// A trait describing a struct that can be configured to compute the difference between
@@ -86,6 +108,37 @@ pub trait Scaler {
     /// This purposefully does not consume the scaler so that if there is a failure it can be kept
     /// around
     async fn cleanup(&self) -> Result<Vec<Command>>;
+
+    /// Returns the configuration this scaler is currently enforcing, if it can be expressed as a
+    /// [`TraitProperty`]. By default this returns `None`
+    fn config(&self) -> Option<TraitProperty> {
+        None
+    }
+
+    /// Offers an outdated scaler from the previously deployed version of a manifest to this
+    /// scaler. Scalers that manage a rollout can take ownership of it and phase it out over time
+    /// rather than having it cleaned up all at once. The outdated scaler is returned if it was not
+    /// taken over, which is the default behavior
+    async fn take_over(
+        &self,
+        outdated: Box<dyn Scaler + Send + Sync>,
+    ) -> Option<Box<dyn Scaler + Send + Sync>> {
+        Some(outdated)
+    }
+
+    /// Returns the resources in the lattice this scaler keeps running that other manifests could
+    /// also need, like a provider or a link (see [`provider_reference`] and [`link_reference`]).
+    /// They are only removed once the last manifest referencing them is undeployed. By default
+    /// this returns nothing
+    async fn references(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Releases any outdated scaler this scaler has taken over so that it can be handed to a
+    /// replacement for this scaler. By default this returns `None`
+    async fn release_previous(&self) -> Option<Box<dyn Scaler + Send + Sync>> {
+        None
+    }
 }
 
 /// The BackoffWrapper is a wrapper around a scaler that is responsible for
@@ -102,7 +155,9 @@ pub trait Scaler {
 ///    to download larger images from an OCI repository without being bombarded with repeat requests.
 /// 3. `backoff_status`: If a scaler receives an event that it was expecting, but it was a failure
 ///    event, the scaler should back off exponentially while reporting that failure status. This both
-///    allows for diagnosing issues with reconciliation and prevents thrashing.
+///    allows for diagnosing issues with reconciliation and prevents thrashing. After
+///    [`MAX_FAILED_ATTEMPTS`] failures in a row the scaler stops retrying altogether, so a host isn't
+///    sent start commands forever for an image that can't run.
 ///
 /// All of the above effectively allows the inner Scaler to only worry about the logic around
 /// reconciling and handling events, rather than be concerned about whether or not
@@ -128,7 +183,8 @@ pub(crate) struct BackoffWrapper<T, P, C> {
     /// The status of the scaler, set when the scaler is backing off due to a
     /// failure event.
     backoff_status: Arc<RwLock<Option<StatusInfo>>>,
-    // TODO(#253): Figure out where/when/how to store the backoff and exponentially repeat it
+    /// The number of failure events received in a row, which sets how long the scaler backs off
+    failed_attempts: AtomicU32,
     /// Responsible for cleaning up the backoff status after a specified duration
     status_cleaner: Mutex<Option<JoinHandle<()>>>,
 }
@@ -161,6 +217,7 @@ where
             event_cleaner: Mutex::new(None),
             cleanup_timeout: cleanup_timeout.unwrap_or(DEFAULT_WAIT_TIMEOUT),
             backoff_status: Arc::new(RwLock::new(None)),
+            failed_attempts: AtomicU32::new(0),
             status_cleaner: Mutex::new(None),
         }
     }
@@ -243,10 +300,21 @@ where
                     Event::ComponentScaleFailed(evt) => evt.error.clone(),
                     _ => format!("Received a failed event of type '{}'", event.raw_type()),
                 };
-                *self.backoff_status.write().await = Some(StatusInfo::failed(&failed_message));
-                // TODO(#253): Here we could refer to a stored previous duration and increase it
-                self.set_timed_status_cleanup(std::time::Duration::from_secs(5))
-                    .await;
+                let attempts = self.failed_attempts.fetch_add(1, Ordering::Relaxed) + 1;
+                let (status, backoff) = backoff_status(attempts, &failed_message);
+                warn!(attempts, ?backoff, message = %failed_message, "Scaler received failure event, backing off");
+                *self.backoff_status.write().await = Some(status);
+                match backoff {
+                    Some(backoff) => self.set_timed_status_cleanup(backoff).await,
+                    // Out of attempts, so the backoff status is kept until the scaler is replaced
+                    None => {
+                        if let Some(handle) = self.status_cleaner.lock().await.take() {
+                            handle.abort();
+                        }
+                    }
+                }
+            } else {
+                self.failed_attempts.store(0, Ordering::Relaxed);
             }
             let data = serde_json::to_vec(&Notifications::RemoveExpectedEvent {
                 name: model_name.to_owned(),
@@ -512,6 +580,14 @@ where
         self.scaler.update_config(config).await
     }
 
+    fn config(&self) -> Option<TraitProperty> {
+        self.scaler.config()
+    }
+
+    async fn references(&self) -> Vec<String> {
+        self.scaler.references().await
+    }
+
     async fn handle_event(&self, event: &Event) -> Result<Vec<Command>> {
         self.handle_event_internal(event).await
     }
@@ -600,6 +676,46 @@ fn evt_matches_expected(incoming: &Event, expected: &Event) -> bool {
     }
 }
 
+/// Returns the status of a scaler that has received the given number of failure events in a row,
+/// along with how long it should back off before trying again. No duration is returned once the
+/// scaler is out of attempts
+fn backoff_status(failed_attempts: u32, message: &str) -> (StatusInfo, Option<Duration>) {
+    if failed_attempts >= MAX_FAILED_ATTEMPTS {
+        return (
+            StatusInfo::failed(&format!(
+                "Backoff: gave up after {failed_attempts} failed attempts, put or deploy the application again to retry. Last error: {message}"
+            )),
+            None,
+        );
+    }
+    let backoff = INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(failed_attempts.saturating_sub(1)))
+        .min(MAX_BACKOFF);
+    (
+        StatusInfo::failed(&format!(
+            "Backoff: attempt {failed_attempts} of {MAX_FAILED_ATTEMPTS} failed, retrying in {}s: {message}",
+            backoff.as_secs()
+        )),
+        Some(backoff),
+    )
+}
+
+/// Returns the reference to a provider, no matter which hosts it runs on
+pub(crate) fn provider_reference(provider_id: &str) -> String {
+    format!("provider/{provider_id}")
+}
+
+/// Returns the reference to a link. Only one link can exist for a source with the same name and
+/// WIT namespace and package
+pub(crate) fn link_reference(
+    source_id: &str,
+    wit_namespace: &str,
+    wit_package: &str,
+    name: &str,
+) -> String {
+    format!("link/{source_id}/{wit_namespace}:{wit_package}/{name}")
+}
+
 /// Computes the sha256 digest of the given parameters to form a unique ID for a scaler
 pub(crate) fn compute_id_sha256(params: &[&str]) -> String {
     let mut hasher = Sha256::new();
@@ -609,3 +725,31 @@ pub(crate) fn compute_id_sha256(params: &[&str]) -> String {
     let hash = hasher.finalize();
     format!("{hash:x}")
 }
+
+#[cfg(test)]
+mod test {
+    use wadm_types::api::StatusType;
+
+    use super::*;
+
+    #[test]
+    fn backs_off_exponentially_until_out_of_attempts() {
+        let delays: Vec<_> = (1..MAX_FAILED_ATTEMPTS)
+            .map(|attempts| backoff_status(attempts, "bad image").1.unwrap())
+            .collect();
+        assert_eq!(delays[0], INITIAL_BACKOFF);
+        assert_eq!(delays[1], INITIAL_BACKOFF * 2);
+        assert_eq!(delays[2], INITIAL_BACKOFF * 4);
+        assert_eq!(delays.last(), Some(&MAX_BACKOFF));
+
+        let (status, _) = backoff_status(1, "bad image");
+        assert_eq!(status.status_type, StatusType::Failed);
+        assert!(status.message.starts_with("Backoff:"), "{}", status.message);
+        assert!(status.message.ends_with("bad image"));
+
+        let (status, backoff) = backoff_status(MAX_FAILED_ATTEMPTS, "bad image");
+        assert!(backoff.is_none());
+        assert_eq!(status.status_type, StatusType::Failed);
+        assert!(status.message.contains("gave up"), "{}", status.message);
+    }
+}
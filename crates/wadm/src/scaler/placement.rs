@@ -0,0 +1,237 @@
+//! Resource aware placement of new instances. Hosts can report how much they can run with the
+//! [`MAX_INSTANCES_LABEL`](crate::MAX_INSTANCES_LABEL), [`CAPACITY_LABEL`](crate::CAPACITY_LABEL)
+//! and [`MEMORY_LABEL`](crate::MEMORY_LABEL) labels. New instances go to the hosts with the least
+//! load for their capacity, so small hosts aren't overloaded while large ones sit idle. With
+//! [`PlacementStrategy::Auction`], spread scalers also run an auction before placing new instances
+//! and only place them on hosts that answer
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use tracing::warn;
+
+use crate::storage::Host;
+use crate::workers::AuctionSource;
+
+/// An auction source that can be shared by every scaler in a lattice
+pub type SharedAuctionSource = Arc<dyn AuctionSource + Send + Sync>;
+
+/// How scalers find the hosts that can take new instances
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlacementStrategy {
+    /// Match spread requirements against the host labels in the lattice state
+    #[default]
+    Labels,
+    /// Match spread requirements against the lattice state, then run an auction and only place
+    /// new instances on the hosts that answer it. Hosts check the requirements against their live
+    /// labels, so this avoids hosts whose labels in the lattice state are stale at the cost of
+    /// waiting for the auction whenever instances need to be placed
+    Auction,
+}
+
+impl FromStr for PlacementStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "labels" => Ok(PlacementStrategy::Labels),
+            "auction" => Ok(PlacementStrategy::Auction),
+            _ => Err(format!(
+                "Unknown placement strategy {s}, expected labels or auction"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PlacementStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlacementStrategy::Labels => write!(f, "labels"),
+            PlacementStrategy::Auction => write!(f, "auction"),
+        }
+    }
+}
+
+/// Returns the hosts that answered an auction. If the auction failed, this logs the error and
+/// returns `None` so placement falls back to the labels in the lattice state
+pub(crate) fn auction_winners(
+    subject: &str,
+    res: anyhow::Result<Vec<String>>,
+) -> Option<HashSet<String>> {
+    match res {
+        Ok(hosts) => Some(hosts.into_iter().collect()),
+        Err(e) => {
+            warn!(error = %e, %subject, "Auction failed, placing by the labels in the lattice state");
+            None
+        }
+    }
+}
+
+/// Returns the capacity weight of each host. Hosts that don't report a
+/// weight get the average weight of the hosts that do, so they are neither favored nor avoided
+fn weights<'a>(hosts: &[(&'a String, &'a Host)]) -> Vec<u128> {
+    let reported: Vec<u64> = hosts
+        .iter()
+        .filter_map(|(_, host)| host.capacity_weight())
+        .collect();
+    let default = if reported.is_empty() {
+        1
+    } else {
+        (reported.iter().sum::<u64>() / reported.len() as u64).max(1)
+    };
+    hosts
+        .iter()
+        .map(|(_, host)| host.capacity_weight().unwrap_or(default).max(1) as u128)
+        .collect()
+}
+
+/// Compares the load of two hosts, given the instances on each host and its weight. Hosts with
+/// the same load are ordered by ID so placement is the same on every reconcile
+fn compare_load(
+    (a_id, a_instances, a_weight): (&String, usize, u128),
+    (b_id, b_instances, b_weight): (&String, usize, u128),
+) -> Ordering {
+    (a_instances as u128 * b_weight)
+        .cmp(&(b_instances as u128 * a_weight))
+        .then_with(|| a_id.cmp(b_id))
+}
+
+/// Orders the given hosts from the least to the most loaded for their capacity
+pub(crate) fn rank_hosts<'a>(
+    hosts: impl IntoIterator<Item = (&'a String, &'a Host)>,
+) -> Vec<(&'a String, &'a Host)> {
+    let hosts: Vec<(&String, &Host)> = hosts.into_iter().collect();
+    let mut ranked: Vec<((&String, &Host), u128)> =
+        hosts.iter().copied().zip(weights(&hosts)).collect();
+    ranked.sort_by(|((a_id, a), a_weight), ((b_id, b), b_weight)| {
+        compare_load(
+            (a_id, a.instance_count(), *a_weight),
+            (b_id, b.instance_count(), *b_weight),
+        )
+    });
+    ranked.into_iter().map(|(host, _)| host).collect()
+}
+
+/// Chooses a host for each of the `wanted` new instances, returning the ID of the host for each
+/// instance in the order they should be placed. Every instance goes to the least loaded host that
+/// still has room, where `room` returns how many more instances a host can take for the caller.
+/// Fewer hosts are returned than wanted if the hosts run out of room
+pub(crate) fn place_instances<'a>(
+    hosts: impl IntoIterator<Item = (&'a String, &'a Host)>,
+    wanted: usize,
+    room: impl Fn(&String, &Host) -> usize,
+) -> Vec<&'a String> {
+    let hosts: Vec<(&String, &Host)> = hosts.into_iter().collect();
+    // The instances on each host, how many more it can take and its weight
+    let mut candidates: Vec<(&String, usize, usize, u128)> = hosts
+        .iter()
+        .zip(weights(&hosts))
+        .map(|((host_id, host), weight)| {
+            let room = room(host_id, host).min(host.free_instances().unwrap_or(usize::MAX));
+            (*host_id, host.instance_count(), room, weight)
+        })
+        .collect();
+    let mut placements = Vec::new();
+    while placements.len() < wanted {
+        let Some(best) = candidates
+            .iter_mut()
+            .filter(|(_, _, room, _)| *room > 0)
+            .min_by(
+                |(a_id, a_instances, _, a_weight), (b_id, b_instances, _, b_weight)| {
+                    compare_load(
+                        (a_id, *a_instances, *a_weight),
+                        (b_id, *b_instances, *b_weight),
+                    )
+                },
+            )
+        else {
+            break;
+        };
+        best.1 += 1;
+        best.2 -= 1;
+        placements.push(best.0);
+    }
+    placements
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{CAPACITY_LABEL, MAX_INSTANCES_LABEL};
+
+    fn host(id: &str, instances: usize, labels: &[(&str, &str)]) -> Host {
+        Host {
+            id: id.to_string(),
+            components: HashMap::from([("existing".to_string(), instances)]),
+            labels: labels
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_placement_strategies() {
+        for strategy in [PlacementStrategy::Labels, PlacementStrategy::Auction] {
+            assert_eq!(strategy.to_string().parse(), Ok(strategy));
+        }
+        assert!("bidding".parse::<PlacementStrategy>().is_err());
+        assert_eq!(
+            auction_winners("echo", Err(anyhow::anyhow!("timed out"))),
+            None,
+            "A failed auction should fall back to placing by labels"
+        );
+    }
+
+    #[test]
+    fn places_instances_by_capacity() {
+        let hosts = HashMap::from([
+            ("big".to_string(), host("big", 4, &[(CAPACITY_LABEL, "4")])),
+            (
+                "small".to_string(),
+                host(
+                    "small",
+                    0,
+                    &[(CAPACITY_LABEL, "1"), (MAX_INSTANCES_LABEL, "2")],
+                ),
+            ),
+            ("unlabeled".to_string(), host("unlabeled", 5, &[])),
+        ]);
+
+        let ranked = rank_hosts(hosts.iter())
+            .into_iter()
+            .map(|(id, _)| id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranked,
+            vec!["small", "big", "unlabeled"],
+            "Hosts should be ranked by their load for their capacity"
+        );
+
+        let placements = place_instances(hosts.iter(), 10, |_, _| usize::MAX);
+        assert_eq!(
+            placements.iter().filter(|id| **id == "small").count(),
+            2,
+            "Hosts shouldn't get more than their max instances"
+        );
+        assert_eq!(placements.len(), 10);
+        assert!(
+            placements.iter().filter(|id| **id == "big").count()
+                > placements.iter().filter(|id| **id == "unlabeled").count(),
+            "Larger hosts should take more instances"
+        );
+
+        let placements = place_instances(hosts.iter(), 10, |id, _| usize::from(id != "unlabeled"));
+        assert_eq!(
+            placements,
+            vec!["small", "big"],
+            "Placement should stop once every host is out of room"
+        );
+    }
+}
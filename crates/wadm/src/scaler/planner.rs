@@ -0,0 +1,275 @@
+//! Contains a planner that computes the commands wadm would issue to deploy a manifest, without
+//! actually issuing them. This is what powers dry run deploys in the API
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tracing::{instrument, trace, warn};
+use wadm_types::{api::HostPlacement, Manifest};
+
+use crate::{
+    commands::Command,
+    publisher::Publisher,
+    storage::{snapshot::SnapshotStore, Host, ReadStore},
+    workers::{get_commands_and_result, ConfigSource, LinkSource, MetricSource, SecretSource},
+};
+
+use super::{
+    convert::{manifest_components_to_scalers, ScalerList},
+    explain::explain_placement,
+    manager::WADM_NOTIFY_PREFIX,
+    rollout::hand_over_scalers,
+};
+
+/// A trait used for dynamically creating a source of lattice data (links, config and secrets) for
+/// a specific lattice.
+///
+/// This is needed because sources like control interface clients are scoped to a lattice. See the
+/// main wadm binary code for an example of how to implement this
+pub trait LatticeSourceCreator {
+    type Output: LinkSource
+        + ConfigSource
+        + SecretSource
+        + MetricSource
+        + Clone
+        + Send
+        + Sync
+        + 'static;
+
+    /// Returns a lattice source for the given lattice ID and optional multitenant prefix
+    fn create(&self, lattice_id: &str, multitenant_prefix: Option<&str>) -> Self::Output;
+}
+
+/// Anything that can compute the list of commands needed to deploy a manifest to a lattice
+#[async_trait]
+pub trait CommandPlanner {
+    /// Returns the commands that would be issued to move the lattice from the `deployed` manifest
+    /// (if any) to the `staged` manifest, based on the current observed state of the lattice
+    async fn plan(
+        &self,
+        lattice_id: &str,
+        multitenant_prefix: Option<&str>,
+        deployed: Option<&Manifest>,
+        staged: &Manifest,
+    ) -> Result<Vec<Command>>;
+
+    /// Returns how the scaler of the named component of the `staged` manifest places it on every
+    /// host in the lattice, along with why each host is or isn't a candidate. The planned
+    /// instances are the ones the scaler would ask for if `staged` replaced `deployed`
+    async fn explain_placement(
+        &self,
+        lattice_id: &str,
+        multitenant_prefix: Option<&str>,
+        deployed: Option<&Manifest>,
+        staged: &Manifest,
+        component: &str,
+    ) -> Result<Vec<HostPlacement>>;
+}
+
+/// A [`CommandPlanner`] that builds the same scalers used for a real deploy and runs them against
+/// a snapshot of the current lattice state
+#[derive(Clone)]
+pub struct ScalerPlanner<S, C> {
+    state_store: S,
+    source_creator: C,
+}
+
+impl<S, C> ScalerPlanner<S, C> {
+    /// Creates a new planner that reads observed state from the given store and uses the given
+    /// creator to access lattice data
+    pub fn new(state_store: S, source_creator: C) -> ScalerPlanner<S, C> {
+        ScalerPlanner {
+            state_store,
+            source_creator,
+        }
+    }
+}
+
+#[async_trait]
+impl<S, C> CommandPlanner for ScalerPlanner<S, C>
+where
+    S: ReadStore + Send + Sync + Clone + 'static,
+    C: LatticeSourceCreator + Send + Sync,
+{
+    #[instrument(level = "debug", skip_all, fields(name = %staged.metadata.name, version = %staged.version()))]
+    async fn plan(
+        &self,
+        lattice_id: &str,
+        multitenant_prefix: Option<&str>,
+        deployed: Option<&Manifest>,
+        staged: &Manifest,
+    ) -> Result<Vec<Command>> {
+        let snapshot_data = SnapshotStore::new(
+            self.state_store.clone(),
+            self.source_creator.create(lattice_id, multitenant_prefix),
+            lattice_id.to_owned(),
+        );
+        snapshot_data.refresh().await?;
+
+        // NOTE: Scalers normally tell other wadm instances about the events they expect. We don't
+        // want any of that to happen for a plan, so everything is sent to a publisher that drops
+        // the data on the floor
+        let subject = format!("{WADM_NOTIFY_PREFIX}.{lattice_id}");
+        let to_scalers = |manifest: &Manifest| -> ScalerList {
+            manifest_components_to_scalers(
+                &manifest.spec.components,
+                &manifest.policy_lookup(),
+                lattice_id,
+                &manifest.metadata.name,
+                &subject,
+                &DiscardPublisher,
+                &snapshot_data,
+            )
+        };
+
+        // This mirrors what happens when a manifest is published. Any scaler from the currently
+        // deployed version that no longer exists will be handed over to a rollout or cleaned up
+        let scalers = to_scalers(staged);
+        let outdated = match deployed {
+            Some(deployed) => hand_over_scalers(&scalers, to_scalers(deployed)).await,
+            None => Vec::new(),
+        };
+
+        let (mut commands, res) = get_commands_and_result(
+            scalers.iter().map(|s| s.reconcile()),
+            "Errors occurred while planning deployment",
+        )
+        .await;
+        res?;
+
+        let (cleanup_commands, res) = get_commands_and_result(
+            outdated.iter().map(|s| s.cleanup()),
+            "Errors occurred while planning cleanup of the deployed version",
+        )
+        .await;
+        if let Err(e) = res {
+            warn!(error = ?e, "Unable to plan cleanup for some outdated scalers");
+        }
+        commands.extend(cleanup_commands);
+
+        trace!(?commands, "Computed deployment plan");
+        Ok(commands)
+    }
+
+    #[instrument(level = "debug", skip(self, deployed, staged), fields(name = %staged.metadata.name, version = %staged.version()))]
+    async fn explain_placement(
+        &self,
+        lattice_id: &str,
+        multitenant_prefix: Option<&str>,
+        deployed: Option<&Manifest>,
+        staged: &Manifest,
+        component: &str,
+    ) -> Result<Vec<HostPlacement>> {
+        let commands = self
+            .plan(lattice_id, multitenant_prefix, deployed, staged)
+            .await?;
+        let hosts = self.state_store.list::<Host>(lattice_id).await?;
+        explain_placement(staged, component, &hosts, &commands)
+    }
+}
+
+/// A publisher that discards everything sent to it
+#[derive(Clone)]
+struct DiscardPublisher;
+
+#[async_trait]
+impl Publisher for DiscardPublisher {
+    async fn publish(&self, _: Vec<u8>, _: Option<&str>) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Arc;
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{
+        storage::{Host, Store},
+        test_util::{TestLatticeSource, TestStore},
+    };
+
+    impl LatticeSourceCreator for TestLatticeSource {
+        type Output = TestLatticeSource;
+
+        fn create(&self, _: &str, _: Option<&str>) -> Self::Output {
+            self.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn can_plan_deploy() {
+        let lattice_id = "dry_run_plan";
+        let store = Arc::new(TestStore::default());
+        store
+            .store(
+                lattice_id,
+                "NASDASDIMAREALHOST".to_string(),
+                Host {
+                    components: HashMap::new(),
+                    friendly_name: "hey".to_string(),
+                    labels: HashMap::new(),
+                    providers: HashSet::new(),
+                    uptime_seconds: 123,
+                    version: None,
+                    id: "NASDASDIMAREALHOST".to_string(),
+                    last_seen: Utc::now(),
+                },
+            )
+            .await
+            .unwrap();
+
+        let manifest: Manifest = serde_yaml::from_slice(
+            &std::fs::read("../../tests/fixtures/manifests/simple.yaml").unwrap(),
+        )
+        .unwrap();
+        let lattice_source = TestLatticeSource {
+            config: HashMap::from([(
+                "httpaddr".to_string(),
+                HashMap::from([("address".to_string(), "0.0.0.0:8080".to_string())]),
+            )]),
+            ..Default::default()
+        };
+        let planner = ScalerPlanner::new(store.clone(), lattice_source);
+
+        let commands = planner
+            .plan(lattice_id, None, None, &manifest)
+            .await
+            .expect("Should be able to plan a deploy");
+
+        assert!(
+            commands.iter().any(|cmd| matches!(
+                cmd,
+                Command::ScaleComponent(scale) if scale.component_id == "http_hello_world" && scale.count == 4
+            )),
+            "Should have planned to scale the component, got {commands:?}"
+        );
+        assert!(
+            commands.iter().any(|cmd| matches!(
+                cmd,
+                Command::StartProvider(start) if start.provider_id == "http_server"
+            )),
+            "Should have planned to start the provider, got {commands:?}"
+        );
+
+        // Nothing should have been written to the store
+        assert_eq!(
+            store.list::<Host>(lattice_id).await.unwrap().len(),
+            1,
+            "Planning should not modify state"
+        );
+
+        // Planning against the same deployed version should not clean anything up
+        let redeploy = planner
+            .plan(lattice_id, None, Some(&manifest), &manifest)
+            .await
+            .expect("Should be able to plan a redeploy");
+        assert_eq!(
+            commands.len(),
+            redeploy.len(),
+            "Redeploying the same version shouldn't add cleanup commands"
+        );
+    }
+}
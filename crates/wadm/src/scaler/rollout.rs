@@ -0,0 +1,504 @@
+//! Contains the [`RolloutController`], which orchestrates moving a component from the previously
+//! deployed version of a manifest to a new one according to the component's rollout trait
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+use tracing::{debug, instrument, trace, warn};
+use wadm_types::{
+    api::{StatusInfo, StatusType},
+    RolloutProperty, SpreadScalerProperty, TraitProperty,
+};
+
+use crate::{clock::SharedClock, commands::Command, events::Event, scaler::Scaler};
+
+use super::convert::{BoxedScaler, ScalerList};
+
+/// An in progress rollout from a previous version of a component
+struct Rollout {
+    /// The scaler for the previously deployed version of the component
+    previous: BoxedScaler,
+    /// The configuration the previous scaler was deployed with, used to scale it down at each
+    /// step. If this isn't available, the previous version is left as is until the rollout is done
+    previous_config: Option<SpreadScalerProperty>,
+    /// The index of the step currently being rolled out
+    step: usize,
+    /// When the current step was first seen as healthy
+    healthy_since: Option<Instant>,
+}
+
+/// The outcome of checking whether a rollout can move forward
+enum Advance {
+    /// Nothing changed
+    Waiting,
+    /// The rollout moved to a new step and the scalers were reconfigured
+    NextStep,
+    /// The rollout finished, containing the commands to clean up the previous version
+    Finished(Vec<Command>),
+}
+
+/// The RolloutController wraps the scaler for a new version of a component. When the scaler for
+/// the previously deployed version is handed to it with [`Scaler::take_over`], the new version is
+/// started in steps, each of which must be healthy for the configured bake time before the next
+/// step starts. The previous version is scaled down as each step completes and is cleaned up once
+/// the new version is fully rolled out.
+///
+/// When there is no previous version to replace, this passes everything straight through to the
+/// wrapped scaler.
+///
+/// NOTE: Steps only move forward when the controller reconciles or handles an event. Host
+/// heartbeats guarantee this happens regularly, so bake times are only as precise as the heartbeat
+/// interval. Rollout progress is also only tracked in memory, so if wadm restarts in the middle of
+/// a rollout the new version is scaled up fully and the previous version is not cleaned up
+pub(crate) struct RolloutController {
+    id: String,
+    kind: String,
+    name: String,
+    scaler: RwLock<BoxedScaler>,
+    spread_config: SpreadScalerProperty,
+    steps: Vec<u8>,
+    bake_time: Duration,
+    rollout: RwLock<Option<Rollout>>,
+    clock: SharedClock,
+}
+
+impl RolloutController {
+    /// Wraps the given scaler, which should be configured with `spread_config`, so that it is
+    /// rolled out according to the given rollout properties. Bake times are measured with the given
+    /// clock
+    pub(crate) fn new(
+        scaler: BoxedScaler,
+        spread_config: SpreadScalerProperty,
+        rollout: &RolloutProperty,
+        clock: SharedClock,
+    ) -> Self {
+        RolloutController {
+            id: scaler.id().to_owned(),
+            kind: scaler.kind().to_owned(),
+            name: scaler.name(),
+            scaler: RwLock::new(scaler),
+            spread_config,
+            steps: rollout.effective_steps(),
+            bake_time: Duration::from_secs(rollout.bake_time_seconds),
+            rollout: RwLock::new(None),
+            clock,
+        }
+    }
+
+    /// Configures the wrapped and previous scalers with the instances for the current step
+    async fn configure(&self, rollout: &mut Rollout) -> Result<()> {
+        let percent = self.steps[rollout.step] as usize;
+        // Always run at least one instance so the new version can be checked for health
+        let instances = (self.spread_config.instances * percent)
+            .div_ceil(100)
+            .max(1);
+        // NOTE: Updating the config also reconciles, but those commands are discarded so that
+        // reconciliation goes through the normal path and any wrapping scalers can track them
+        self.scaler
+            .write()
+            .await
+            .update_config(TraitProperty::SpreadScaler(SpreadScalerProperty {
+                instances,
+                ..self.spread_config.clone()
+            }))
+            .await?;
+
+        // The previous version is only scaled down by the steps that have already completed
+        if let Some(previous_config) = rollout.previous_config.as_ref() {
+            let completed = rollout
+                .step
+                .checked_sub(1)
+                .map(|step| self.steps[step] as usize)
+                .unwrap_or_default();
+            rollout
+                .previous
+                .update_config(TraitProperty::SpreadScaler(SpreadScalerProperty {
+                    instances: previous_config.instances * (100 - completed) / 100,
+                    ..previous_config.clone()
+                }))
+                .await?;
+        }
+        trace!(step = rollout.step, %percent, %instances, "Configured rollout step");
+        Ok(())
+    }
+
+    /// Moves the rollout to the next step if the current one has been healthy for long enough
+    async fn advance(&self) -> Result<Advance> {
+        let mut current = self.rollout.write().await;
+        let Some(rollout) = current.as_mut() else {
+            return Ok(Advance::Waiting);
+        };
+
+        let status = self.scaler.read().await.status().await;
+        if status.status_type != StatusType::Deployed {
+            rollout.healthy_since = None;
+            return Ok(Advance::Waiting);
+        }
+        let healthy_since = *rollout
+            .healthy_since
+            .get_or_insert_with(|| self.clock.instant());
+        if self.clock.elapsed(healthy_since) < self.bake_time {
+            return Ok(Advance::Waiting);
+        }
+
+        if rollout.step + 1 < self.steps.len() {
+            rollout.step += 1;
+            rollout.healthy_since = None;
+            debug!(
+                step = rollout.step,
+                percent = self.steps[rollout.step],
+                "Advancing rollout"
+            );
+            self.configure(rollout).await?;
+            Ok(Advance::NextStep)
+        } else {
+            debug!("Rollout complete, cleaning up previous version");
+            let commands = rollout.previous.cleanup().await?;
+            current.take();
+            Ok(Advance::Finished(commands))
+        }
+    }
+
+    /// Reconciles the wrapped scaler and the previous version, if any
+    async fn reconcile_all(&self) -> Result<Vec<Command>> {
+        let mut commands = self.scaler.read().await.reconcile().await?;
+        if let Some(rollout) = self.rollout.read().await.as_ref() {
+            commands.extend(rollout.previous.reconcile().await?);
+        }
+        Ok(commands)
+    }
+}
+
+#[async_trait]
+impl Scaler for RolloutController {
+    fn id(&self) -> &str {
+        // Pass through the ID of the wrapped scaler
+        &self.id
+    }
+
+    fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn status(&self) -> StatusInfo {
+        let status = self.scaler.read().await.status().await;
+        match self.rollout.read().await.as_ref() {
+            Some(rollout) if status.status_type != StatusType::Failed => {
+                StatusInfo::reconciling(&format!(
+                    "Rolling out new version at {}% (step {} of {})",
+                    self.steps[rollout.step],
+                    rollout.step + 1,
+                    self.steps.len()
+                ))
+            }
+            _ => status,
+        }
+    }
+
+    async fn update_config(&mut self, config: TraitProperty) -> Result<Vec<Command>> {
+        let spread_config = match config {
+            TraitProperty::SpreadScaler(prop) => prop,
+            _ => anyhow::bail!("Given config was not a spread scaler config object"),
+        };
+        self.spread_config = spread_config;
+        match self.rollout.write().await.as_mut() {
+            Some(rollout) => self.configure(rollout).await?,
+            None => {
+                self.scaler
+                    .write()
+                    .await
+                    .update_config(TraitProperty::SpreadScaler(self.spread_config.clone()))
+                    .await?;
+            }
+        }
+        self.reconcile().await
+    }
+
+    fn config(&self) -> Option<TraitProperty> {
+        Some(TraitProperty::SpreadScaler(self.spread_config.clone()))
+    }
+
+    #[instrument(level = "trace", skip_all, fields(scaler_id = %self.id))]
+    async fn handle_event(&self, event: &Event) -> Result<Vec<Command>> {
+        match self.advance().await? {
+            Advance::Waiting => {
+                let mut commands = self.scaler.read().await.handle_event(event).await?;
+                if let Some(rollout) = self.rollout.read().await.as_ref() {
+                    commands.extend(rollout.previous.handle_event(event).await?);
+                }
+                Ok(commands)
+            }
+            Advance::NextStep => self.reconcile_all().await,
+            Advance::Finished(mut commands) => {
+                commands.extend(self.reconcile_all().await?);
+                Ok(commands)
+            }
+        }
+    }
+
+    #[instrument(level = "trace", skip_all, fields(scaler_id = %self.id))]
+    async fn reconcile(&self) -> Result<Vec<Command>> {
+        let mut commands = match self.advance().await? {
+            Advance::Finished(commands) => commands,
+            Advance::Waiting | Advance::NextStep => Vec::new(),
+        };
+        commands.extend(self.reconcile_all().await?);
+        Ok(commands)
+    }
+
+    async fn cleanup(&self) -> Result<Vec<Command>> {
+        let mut commands = self.scaler.read().await.cleanup().await?;
+        if let Some(rollout) = self.rollout.read().await.as_ref() {
+            commands.extend(rollout.previous.cleanup().await?);
+        }
+        Ok(commands)
+    }
+
+    async fn references(&self) -> Vec<String> {
+        let mut references = self.scaler.read().await.references().await;
+        if let Some(rollout) = self.rollout.read().await.as_ref() {
+            references.extend(rollout.previous.references().await);
+        }
+        references
+    }
+
+    #[instrument(level = "debug", skip_all, fields(scaler_id = %self.id, outdated_id = %outdated.id()))]
+    async fn take_over(&self, outdated: BoxedScaler) -> Option<BoxedScaler> {
+        // Only the same kind of scaler for the same component can be rolled out from
+        if outdated.kind() != self.kind || outdated.name() != self.name {
+            return Some(outdated);
+        }
+        let mut current = self.rollout.write().await;
+        if current.is_some() {
+            return Some(outdated);
+        }
+
+        let previous_config = match outdated.config() {
+            Some(TraitProperty::SpreadScaler(config)) => Some(config),
+            _ => None,
+        };
+        let mut rollout = Rollout {
+            previous: outdated,
+            previous_config,
+            step: 0,
+            healthy_since: None,
+        };
+        if let Err(e) = self.configure(&mut rollout).await {
+            warn!(error = ?e, "Unable to start rollout, previous version will be cleaned up");
+            return Some(rollout.previous);
+        }
+        debug!(steps = ?self.steps, "Started rollout from previous version");
+        *current = Some(rollout);
+        None
+    }
+
+    async fn release_previous(&self) -> Option<BoxedScaler> {
+        self.rollout
+            .write()
+            .await
+            .take()
+            .map(|rollout| rollout.previous)
+    }
+}
+
+/// Hands the scalers from the previously deployed version of a manifest to the scalers for the new
+/// version so any rollouts can phase them out gradually. Returns the outdated scalers that were not
+/// taken over and should be cleaned up.
+///
+/// Like everywhere else, this relies on the idea that an ID is a unique identifier for a scaler, so
+/// any old scaler with the same ID as a new one is simply replaced. If that scaler was already in
+/// the middle of a rollout, the previous version it was phasing out is handed over instead
+pub(crate) async fn hand_over_scalers(new: &ScalerList, old: ScalerList) -> ScalerList {
+    let mut outdated = Vec::with_capacity(old.len());
+    for scaler in old {
+        if new.iter().any(|s| s.id() == scaler.id()) {
+            outdated.extend(scaler.release_previous().await);
+        } else {
+            outdated.push(scaler);
+        }
+    }
+
+    let mut remaining = Vec::with_capacity(outdated.len());
+    'outdated: for mut scaler in outdated {
+        for new_scaler in new.iter() {
+            match new_scaler.take_over(scaler).await {
+                Some(returned) => scaler = returned,
+                None => continue 'outdated,
+            }
+        }
+        remaining.push(scaler);
+    }
+    remaining
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::Arc,
+    };
+
+    use chrono::Utc;
+    use wadm_types::RolloutStrategy;
+
+    use super::*;
+    use crate::{
+        clock::ManualClock,
+        commands::ScaleComponent,
+        scaler::spreadscaler::{spreadscaler_annotations, ComponentSpreadScaler},
+        storage::{Component, Host, Store, WadmComponentInfo},
+        test_util::TestStore,
+    };
+
+    const LATTICE_ID: &str = "rollout";
+    const MODEL_NAME: &str = "rollout_test";
+    const HOST_ID: &str = "NASDASDIMAREALHOST";
+    const COMPONENT_ID: &str = "rollout_test-echo";
+
+    fn spread_scaler(store: Arc<TestStore>, reference: &str) -> BoxedScaler {
+        Box::new(ComponentSpreadScaler::new(
+            store,
+            reference.to_string(),
+            COMPONENT_ID.to_string(),
+            LATTICE_ID.to_string(),
+            MODEL_NAME.to_string(),
+            SpreadScalerProperty {
+                instances: 4,
+                spread: vec![],
+                hosts: vec![],
+                max_concurrent_changes: None,
+                max_per_host: None,
+                anti_affinity: vec![],
+            },
+            "echo",
+            vec![],
+        ))
+    }
+
+    async fn set_instances(store: &TestStore, running: &[(&str, usize)]) {
+        store
+            .store(
+                LATTICE_ID,
+                COMPONENT_ID.to_string(),
+                Component {
+                    id: COMPONENT_ID.to_string(),
+                    name: "Echo".to_string(),
+                    issuer: "AASDASDASDASD".to_string(),
+                    instances: HashMap::from_iter([(
+                        HOST_ID.to_string(),
+                        running
+                            .iter()
+                            .map(|(scaler_id, count)| WadmComponentInfo {
+                                annotations: spreadscaler_annotations("default", scaler_id),
+                                count: *count,
+                            })
+                            .collect(),
+                    )]),
+                    reference: "fakecloud.azurecr.io/echo:0.3.4".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+    }
+
+    fn scale_count(commands: &[Command], scaler_id: &str) -> Option<u32> {
+        commands.iter().find_map(|cmd| match cmd {
+            Command::ScaleComponent(ScaleComponent {
+                annotations, count, ..
+            }) if annotations == &spreadscaler_annotations("default", scaler_id) => Some(*count),
+            _ => None,
+        })
+    }
+
+    #[tokio::test]
+    async fn can_roll_out_canary() {
+        let store = Arc::new(TestStore::default());
+        store
+            .store(
+                LATTICE_ID,
+                HOST_ID.to_string(),
+                Host {
+                    components: HashMap::new(),
+                    friendly_name: "hey".to_string(),
+                    labels: HashMap::new(),
+                    providers: HashSet::new(),
+                    uptime_seconds: 123,
+                    version: None,
+                    id: HOST_ID.to_string(),
+                    last_seen: Utc::now(),
+                },
+            )
+            .await
+            .unwrap();
+
+        let old = spread_scaler(store.clone(), "fakecloud.azurecr.io/echo:0.3.4");
+        let old_id = old.id().to_owned();
+        set_instances(&store, &[(&old_id, 4)]).await;
+
+        let new = spread_scaler(store.clone(), "fakecloud.azurecr.io/echo:0.3.5");
+        let new_id = new.id().to_owned();
+        let clock = ManualClock::default();
+        let controller = RolloutController::new(
+            new,
+            SpreadScalerProperty {
+                instances: 4,
+                spread: vec![],
+                hosts: vec![],
+                max_concurrent_changes: None,
+                max_per_host: None,
+                anti_affinity: vec![],
+            },
+            &RolloutProperty {
+                strategy: RolloutStrategy::Canary,
+                steps: vec![25],
+                bake_time_seconds: 60,
+            },
+            Arc::new(clock.clone()),
+        );
+        let scalers: ScalerList = vec![Box::new(controller)];
+        assert!(
+            hand_over_scalers(&scalers, vec![old]).await.is_empty(),
+            "The previous version should have been taken over"
+        );
+        let controller = &scalers[0];
+
+        // The first step only starts a single instance of the new version
+        let commands = controller.reconcile().await.unwrap();
+        assert_eq!(scale_count(&commands, &new_id), Some(1));
+        assert_eq!(scale_count(&commands, &old_id), None);
+        let status = controller.status().await;
+        assert_eq!(status.status_type, StatusType::Reconciling);
+        assert!(status.message.contains("25% (step 1 of 2)"));
+
+        // Once the canary is healthy, the new version is scaled up and the old one down
+        set_instances(&store, &[(&old_id, 4), (&new_id, 1)]).await;
+        let commands = controller.reconcile().await.unwrap();
+        assert_eq!(
+            scale_count(&commands, &new_id),
+            None,
+            "The next step shouldn't start until the bake time has passed"
+        );
+        clock.advance(Duration::from_secs(60));
+        let commands = controller.reconcile().await.unwrap();
+        assert_eq!(scale_count(&commands, &new_id), Some(4));
+        assert_eq!(scale_count(&commands, &old_id), Some(3));
+
+        // When the final step is healthy, the previous version is cleaned up
+        set_instances(&store, &[(&old_id, 3), (&new_id, 4)]).await;
+        controller.reconcile().await.unwrap();
+        clock.advance(Duration::from_secs(60));
+        let commands = controller.reconcile().await.unwrap();
+        assert_eq!(scale_count(&commands, &old_id), Some(0));
+        assert_eq!(scale_count(&commands, &new_id), None);
+
+        set_instances(&store, &[(&new_id, 4)]).await;
+        assert!(controller.reconcile().await.unwrap().is_empty());
+        assert_eq!(controller.status().await.status_type, StatusType::Deployed);
+    }
+}
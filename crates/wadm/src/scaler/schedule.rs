@@ -0,0 +1,424 @@
+//! Contains the [`ScheduleScaler`], which changes the instances of a component's spreadscaler at the
+//! times given by the component's schedule trait
+
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_nats::{header::NATS_MESSAGE_ID, HeaderMap};
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Days, NaiveDateTime, TimeZone, Timelike, Utc};
+use tokio::{sync::RwLock, task::JoinHandle};
+use tracing::{debug, instrument, trace, warn};
+use wadm_types::{
+    api::StatusInfo,
+    cron::{CronExpression, ScheduleTimezone},
+    ScheduleProperty, SpreadScalerProperty, TraitProperty,
+};
+
+use crate::{
+    clock::SharedClock, commands::Command, events::Event, publisher::Publisher, scaler::Scaler,
+};
+
+use super::{convert::BoxedScaler, manager::Notifications};
+
+/// How far back and ahead a schedule looks for the start of a window
+const SEARCH_DAYS: u64 = 366;
+/// The longest the timer sleeps at once, so changes to the system clock are picked up
+const MAX_TIMER_SLEEP: Duration = Duration::from_secs(60 * 60);
+
+/// A parsed schedule trait
+#[derive(Clone)]
+pub(crate) struct Schedule {
+    timezone: ScheduleTimezone,
+    windows: Vec<(CronExpression, usize)>,
+}
+
+impl TryFrom<&ScheduleProperty> for Schedule {
+    type Error = anyhow::Error;
+
+    fn try_from(property: &ScheduleProperty) -> Result<Self> {
+        Ok(Schedule {
+            timezone: property.timezone().map_err(anyhow::Error::msg)?,
+            windows: property
+                .windows
+                .iter()
+                .map(|window| {
+                    window
+                        .cron
+                        .parse()
+                        .map(|cron| (cron, window.instances))
+                        .map_err(anyhow::Error::msg)
+                })
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl Schedule {
+    fn to_local(&self, time: DateTime<Utc>) -> NaiveDateTime {
+        match self.timezone {
+            ScheduleTimezone::Offset(seconds) => {
+                (time + chrono::Duration::seconds(seconds as i64)).naive_utc()
+            }
+            ScheduleTimezone::Local => time.with_timezone(&chrono::Local).naive_local(),
+        }
+    }
+
+    /// Converts a time in the schedule's timezone to UTC. Returns `None` for times that are skipped
+    /// when daylight saving time starts
+    fn to_utc(&self, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+        match self.timezone {
+            ScheduleTimezone::Offset(seconds) => {
+                Some((local - chrono::Duration::seconds(seconds as i64)).and_utc())
+            }
+            ScheduleTimezone::Local => chrono::Local
+                .from_local_datetime(&local)
+                .earliest()
+                .map(|time| time.with_timezone(&Utc)),
+        }
+    }
+
+    /// Returns the instances of the window that started most recently, or `None` if no window has
+    /// started within the last year. If windows start at the same time, the last one listed wins
+    pub(crate) fn instances_at(&self, now: DateTime<Utc>) -> Option<usize> {
+        let local = self.to_local(now);
+        self.windows
+            .iter()
+            .filter_map(|(cron, instances)| Some((latest_start(cron, local)?, *instances)))
+            .reduce(|latest, window| if window.0 >= latest.0 { window } else { latest })
+            .map(|(_, instances)| instances)
+    }
+
+    /// Returns the next time after `now` that a window starts
+    pub(crate) fn next_start(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = self.to_local(now);
+        self.windows
+            .iter()
+            .filter_map(|(cron, _)| self.to_utc(next_start(cron, local)?))
+            .filter(|start| *start > now)
+            .min()
+    }
+}
+
+fn date_matches(cron: &CronExpression, date: chrono::NaiveDate) -> bool {
+    cron.matches_date(
+        date.day(),
+        date.month(),
+        date.weekday().num_days_from_sunday(),
+    )
+}
+
+/// Returns the latest time at or before `at` that the expression matches
+fn latest_start(cron: &CronExpression, at: NaiveDateTime) -> Option<NaiveDateTime> {
+    let now = (at.hour(), at.minute());
+    (0..=SEARCH_DAYS).find_map(|days_ago| {
+        let date = at.date().checked_sub_days(Days::new(days_ago))?;
+        if !date_matches(cron, date) {
+            return None;
+        }
+        let (hour, minute) = cron
+            .times()
+            .rev()
+            .find(|time| days_ago > 0 || *time <= now)?;
+        date.and_hms_opt(hour, minute, 0)
+    })
+}
+
+/// Returns the earliest time after `at` that the expression matches
+fn next_start(cron: &CronExpression, at: NaiveDateTime) -> Option<NaiveDateTime> {
+    let now = (at.hour(), at.minute());
+    (0..=SEARCH_DAYS).find_map(|days_ahead| {
+        let date = at.date().checked_add_days(Days::new(days_ahead))?;
+        if !date_matches(cron, date) {
+            return None;
+        }
+        let (hour, minute) = cron.times().find(|time| days_ahead > 0 || *time > now)?;
+        date.and_hms_opt(hour, minute, 0)
+    })
+}
+
+/// The ScheduleScaler wraps the scaler for a component and configures it with the instances of the
+/// schedule window that started most recently, falling back to the instances in the spreadscaler
+/// when no window has started yet. A timer wakes up when the next window starts and asks the scaler
+/// manager to reconcile this scaler, so the change doesn't have to wait for an event.
+///
+/// NOTE: The timer is started the first time the scaler reconciles or handles an event. Every wadm
+/// instance runs a timer, but the reconcile requests they send are deduplicated by the notify stream
+pub(crate) struct ScheduleScaler<P> {
+    id: String,
+    kind: String,
+    name: String,
+    scaler: RwLock<BoxedScaler>,
+    spread_config: SpreadScalerProperty,
+    schedule: Arc<Schedule>,
+    instances: RwLock<usize>,
+    notifier: P,
+    notify_subject: String,
+    model_name: String,
+    timer: OnceLock<JoinHandle<()>>,
+    clock: SharedClock,
+}
+
+impl<P> Drop for ScheduleScaler<P> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.timer.get() {
+            handle.abort()
+        }
+    }
+}
+
+impl<P> ScheduleScaler<P>
+where
+    P: Publisher + Clone + Send + Sync + 'static,
+{
+    /// Wraps the given scaler, which should be configured with `spread_config`, so its instances
+    /// follow the given schedule. Reconcile requests are published with the notifier to the given
+    /// subject
+    pub(crate) fn new(
+        scaler: BoxedScaler,
+        spread_config: SpreadScalerProperty,
+        schedule: Schedule,
+        notifier: P,
+        notify_subject: &str,
+        model_name: &str,
+        clock: SharedClock,
+    ) -> Self {
+        ScheduleScaler {
+            id: scaler.id().to_owned(),
+            kind: scaler.kind().to_owned(),
+            name: scaler.name(),
+            scaler: RwLock::new(scaler),
+            instances: RwLock::new(spread_config.instances),
+            spread_config,
+            schedule: Arc::new(schedule),
+            notifier,
+            notify_subject: notify_subject.to_owned(),
+            model_name: model_name.to_owned(),
+            timer: OnceLock::new(),
+            clock,
+        }
+    }
+
+    /// Starts the timer for the next window if it isn't running yet
+    fn start_timer(&self) {
+        self.timer.get_or_init(|| {
+            let schedule = self.schedule.clone();
+            let clock = self.clock.clone();
+            let notifier = self.notifier.clone();
+            let subject = self.notify_subject.clone();
+            let name = self.model_name.clone();
+            let scaler_id = self.id.clone();
+            tokio::spawn(async move {
+                loop {
+                    let now = clock.now();
+                    let next = schedule.next_start(now);
+                    let wait = next
+                        .and_then(|next| (next - now).to_std().ok())
+                        .unwrap_or(MAX_TIMER_SLEEP);
+                    if wait > MAX_TIMER_SLEEP {
+                        tokio::time::sleep(MAX_TIMER_SLEEP).await;
+                        continue;
+                    }
+                    tokio::time::sleep(wait).await;
+                    let Some(next) = next else {
+                        continue;
+                    };
+
+                    trace!(%name, %scaler_id, start = %next, "Schedule window started, requesting reconcile");
+                    let data = match serde_json::to_vec(&Notifications::ReconcileScaler {
+                        name: name.clone(),
+                        scaler_id: scaler_id.clone(),
+                    }) {
+                        Ok(data) => data,
+                        Err(e) => {
+                            warn!(error = %e, "Unable to serialize reconcile request");
+                            continue;
+                        }
+                    };
+                    let mut headers = HeaderMap::new();
+                    headers.insert(
+                        NATS_MESSAGE_ID,
+                        format!("{scaler_id}-{}", next.timestamp()).as_str(),
+                    );
+                    if let Err(e) = notifier
+                        .publish_with_headers(data, Some(&subject), headers)
+                        .await
+                    {
+                        warn!(error = ?e, %name, %scaler_id, "Unable to request reconcile for schedule window");
+                    }
+                }
+            })
+        });
+    }
+
+    /// Configures the wrapped scaler with the instances for the current window, returning whether
+    /// the instances changed
+    async fn adjust(&self) -> Result<bool> {
+        self.start_timer();
+        let wanted = self
+            .schedule
+            .instances_at(self.clock.now())
+            .unwrap_or(self.spread_config.instances);
+        let mut instances = self.instances.write().await;
+        if wanted == *instances {
+            return Ok(false);
+        }
+
+        debug!(
+            from = *instances,
+            to = wanted,
+            "Changing instances for schedule window"
+        );
+        // NOTE: Updating the config also reconciles, but those commands are discarded so that
+        // reconciliation goes through the normal path and any wrapping scalers can track them
+        self.scaler
+            .write()
+            .await
+            .update_config(TraitProperty::SpreadScaler(SpreadScalerProperty {
+                instances: wanted,
+                ..self.spread_config.clone()
+            }))
+            .await?;
+        *instances = wanted;
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl<P> Scaler for ScheduleScaler<P>
+where
+    P: Publisher + Clone + Send + Sync + 'static,
+{
+    fn id(&self) -> &str {
+        // Pass through the ID of the wrapped scaler
+        &self.id
+    }
+
+    fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn status(&self) -> StatusInfo {
+        self.scaler.read().await.status().await
+    }
+
+    async fn update_config(&mut self, config: TraitProperty) -> Result<Vec<Command>> {
+        let spread_config = match config {
+            TraitProperty::SpreadScaler(prop) => prop,
+            _ => anyhow::bail!("Given config was not a spread scaler config object"),
+        };
+        self.spread_config = spread_config;
+        // Keep the instances of the current window
+        let instances = *self.instances.read().await;
+        self.scaler
+            .write()
+            .await
+            .update_config(TraitProperty::SpreadScaler(SpreadScalerProperty {
+                instances,
+                ..self.spread_config.clone()
+            }))
+            .await?;
+        self.reconcile().await
+    }
+
+    fn config(&self) -> Option<TraitProperty> {
+        Some(TraitProperty::SpreadScaler(self.spread_config.clone()))
+    }
+
+    #[instrument(level = "trace", skip_all, fields(scaler_id = %self.id))]
+    async fn handle_event(&self, event: &Event) -> Result<Vec<Command>> {
+        if self.adjust().await? {
+            self.scaler.read().await.reconcile().await
+        } else {
+            self.scaler.read().await.handle_event(event).await
+        }
+    }
+
+    #[instrument(level = "trace", skip_all, fields(scaler_id = %self.id))]
+    async fn reconcile(&self) -> Result<Vec<Command>> {
+        self.adjust().await?;
+        self.scaler.read().await.reconcile().await
+    }
+
+    async fn cleanup(&self) -> Result<Vec<Command>> {
+        self.scaler.read().await.cleanup().await
+    }
+
+    async fn references(&self) -> Vec<String> {
+        self.scaler.read().await.references().await
+    }
+
+    async fn take_over(&self, outdated: BoxedScaler) -> Option<BoxedScaler> {
+        self.scaler.read().await.take_over(outdated).await
+    }
+
+    async fn release_previous(&self) -> Option<BoxedScaler> {
+        self.scaler.read().await.release_previous().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wadm_types::ScheduleWindow;
+
+    use super::*;
+
+    #[test]
+    fn follows_schedule_windows() {
+        let schedule = Schedule::try_from(&ScheduleProperty {
+            timezone: Some("+02:00".to_string()),
+            windows: vec![
+                ScheduleWindow {
+                    cron: "0 9 * * MON-FRI".to_string(),
+                    instances: 20,
+                },
+                ScheduleWindow {
+                    cron: "0 18 * * MON-FRI".to_string(),
+                    instances: 2,
+                },
+            ],
+        })
+        .unwrap();
+
+        // 2024-06-03 was a Monday, so 07:00 UTC is 09:00 in the schedule's timezone
+        let monday_morning = Utc.with_ymd_and_hms(2024, 6, 3, 7, 0, 0).unwrap();
+        assert_eq!(schedule.instances_at(monday_morning), Some(20));
+        assert_eq!(
+            schedule.instances_at(monday_morning - chrono::Duration::minutes(1)),
+            Some(2),
+            "The evening window from the previous Friday should still apply"
+        );
+        assert_eq!(
+            schedule.next_start(monday_morning),
+            Some(Utc.with_ymd_and_hms(2024, 6, 3, 16, 0, 0).unwrap())
+        );
+
+        // Friday evening lasts until Monday morning
+        let friday_evening = Utc.with_ymd_and_hms(2024, 6, 7, 16, 0, 0).unwrap();
+        assert_eq!(schedule.instances_at(friday_evening), Some(2));
+        assert_eq!(
+            schedule.next_start(friday_evening),
+            Some(monday_morning + chrono::Duration::days(7))
+        );
+
+        let never = Schedule::try_from(&ScheduleProperty {
+            timezone: None,
+            windows: vec![ScheduleWindow {
+                cron: "0 0 30 2 *".to_string(),
+                instances: 5,
+            }],
+        })
+        .unwrap();
+        assert_eq!(
+            never.instances_at(monday_morning),
+            None,
+            "Windows that never start shouldn't apply"
+        );
+        assert_eq!(never.next_start(monday_morning), None);
+    }
+}
@@ -204,6 +204,7 @@ mod test {
             claims: HashMap::new(),
             inventory: Default::default(),
             links: Vec::new(),
+            auction_winners: Vec::new(),
             config: HashMap::new(),
         };
 
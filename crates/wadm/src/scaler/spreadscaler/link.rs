@@ -1,7 +1,7 @@
 use anyhow::Result;
 use async_trait::async_trait;
 use tokio::sync::RwLock;
-use tracing::instrument;
+use tracing::{instrument, trace};
 use wadm_types::{api::StatusInfo, TraitProperty};
 
 use crate::{
@@ -10,7 +10,7 @@ use crate::{
         Event, LinkdefDeleted, LinkdefSet, ProviderHealthCheckInfo, ProviderHealthCheckPassed,
         ProviderHealthCheckStatus,
     },
-    scaler::{compute_id_sha256, Scaler},
+    scaler::{compute_id_sha256, link_reference, Scaler},
     storage::ReadStore,
     workers::LinkSource,
 };
@@ -214,7 +214,29 @@ where
         Ok(commands)
     }
 
+    async fn references(&self) -> Vec<String> {
+        vec![link_reference(
+            &self.config.source_id,
+            &self.config.wit_namespace,
+            &self.config.wit_package,
+            &self.config.name,
+        )]
+    }
+
     async fn cleanup(&self) -> Result<Vec<Command>> {
+        // Links don't have annotations, so a link is only ours if it still points where we put
+        // it. If something else has replaced it since, it's left alone
+        let is_ours = self.ctl_client.get_links().await?.iter().any(|link| {
+            link.source_id() == self.config.source_id
+                && link.name() == self.config.name
+                && link.wit_namespace() == self.config.wit_namespace
+                && link.wit_package() == self.config.wit_package
+                && link.target() == self.config.target
+        });
+        if !is_ours {
+            trace!(source_id = %self.config.source_id, link_name = %self.config.name, "Link doesn't exist or wasn't put by this scaler, skipping cleanup");
+            return Ok(Vec::new());
+        }
         Ok(vec![Command::DeleteLink(DeleteLink {
             model_name: self.config.model_name.to_owned(),
             source_id: self.config.source_id.to_owned(),
@@ -468,6 +490,54 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn only_cleans_up_links_it_put() {
+        let lattice_id = "link-cleanup".to_string();
+        let link = |target: &str| {
+            Link::builder()
+                .source_id("component")
+                .target(target)
+                .wit_namespace("namespace")
+                .wit_package("package")
+                .interfaces(vec!["interface".to_string()])
+                .name("default")
+                .build()
+                .unwrap()
+        };
+        let scaler = |links| {
+            LinkScaler::new(
+                TestStore::default(),
+                LinkScalerConfig {
+                    source_id: "component".to_string(),
+                    target: "provider".to_string(),
+                    wit_namespace: "namespace".to_string(),
+                    wit_package: "package".to_string(),
+                    wit_interfaces: vec!["interface".to_string()],
+                    name: "default".to_string(),
+                    source_config: vec![],
+                    target_config: vec![],
+                    lattice_id: lattice_id.clone(),
+                    model_name: "model".to_string(),
+                },
+                TestLatticeSource {
+                    links,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let commands = scaler(vec![link("provider")]).cleanup().await.unwrap();
+        assert!(matches!(commands[..], [Command::DeleteLink(_)]));
+        assert!(
+            scaler(vec![link("someone-elses-provider")])
+                .cleanup()
+                .await
+                .unwrap()
+                .is_empty(),
+            "Links that were replaced by something else shouldn't be deleted"
+        );
+    }
+
     #[tokio::test]
     async fn can_put_linkdef_from_triggering_events() {
         let lattice_id = "can_put_linkdef_from_triggering_events";
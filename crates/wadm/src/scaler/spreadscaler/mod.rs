@@ -6,14 +6,18 @@ use async_trait::async_trait;
 use tokio::sync::RwLock;
 use tracing::{instrument, trace, warn};
 use wadm_types::{
-    api::StatusInfo, Spread, SpreadScalerProperty, TraitProperty, DEFAULT_SPREAD_WEIGHT,
+    api::StatusInfo, HostConstraintProperty, Spread, SpreadScalerProperty, TraitProperty,
+    DEFAULT_SPREAD_WEIGHT,
 };
 
 use crate::events::HostHeartbeat;
 use crate::{
     commands::{Command, ScaleComponent},
     events::{Event, HostStarted, HostStopped},
-    scaler::Scaler,
+    scaler::{
+        placement::{auction_winners, place_instances},
+        Scaler, SharedAuctionSource,
+    },
     storage::{Component, Host, ReadStore},
     SCALER_KEY,
 };
@@ -27,6 +31,8 @@ pub mod provider;
 const SPREAD_KEY: &str = "wasmcloud.dev/spread_name";
 
 pub const SPREAD_SCALER_KIND: &str = "SpreadScaler";
+/// The name of the spread used when a scaler is pinned to specific hosts
+pub const PINNED_SPREAD_NAME: &str = "pinned";
 
 /// Config for a ComponentSpreadScaler
 #[derive(Clone)]
@@ -41,6 +47,10 @@ struct ComponentSpreadConfig {
     model_name: String,
     /// Configuration for this SpreadScaler
     spread_config: SpreadScalerProperty,
+    /// Constraint on the hosts the component can be placed on
+    host_constraint: Option<HostConstraintProperty>,
+    /// IDs of the components and providers the component is never placed on the same host as
+    anti_affinity: Vec<String>,
 }
 
 /// The ComponentSpreadScaler ensures that a certain number of instances are running,
@@ -56,6 +66,8 @@ pub struct ComponentSpreadScaler<S> {
     status: RwLock<StatusInfo>,
     /// Named configuration to pass to the component.
     pub config: Vec<String>,
+    /// Where to run auctions for new instances, if placement by auction is enabled
+    auction: Option<SharedAuctionSource>,
 }
 
 #[async_trait]
@@ -87,6 +99,12 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ComponentSpreadScaler<S> {
         self.reconcile().await
     }
 
+    fn config(&self) -> Option<TraitProperty> {
+        Some(TraitProperty::SpreadScaler(
+            self.spread_config.spread_config.clone(),
+        ))
+    }
+
     #[instrument(level = "trace", skip_all, fields(scaler_id = %self.id))]
     async fn handle_event(&self, event: &Event) -> Result<Vec<Command>> {
         // NOTE(brooksmtownsend): We could be more efficient here and instead of running
@@ -113,6 +131,8 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ComponentSpreadScaler<S> {
                     Ok(Vec::new())
                 }
             }
+            // A label change can move a host into or out of any spread, so always reconcile
+            Event::HostLabelsChanged(_) => self.reconcile().await,
             // No other event impacts the job of this scaler so we can ignore it
             _ => Ok(Vec::new()),
         }
@@ -137,6 +157,8 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ComponentSpreadScaler<S> {
                 .iter()
                 .map(|(s, _)| s)
                 .collect::<Vec<&Spread>>(),
+            &self.spread_config.spread_config.hosts,
+            self.spread_config.host_constraint.as_ref(),
         );
 
         // Remove any components that are managed by this scaler and running on ineligible hosts
@@ -169,67 +191,108 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ComponentSpreadScaler<S> {
             return Ok(remove_ineligible);
         }
 
+        let auctioned = self.auction_winners(component.as_ref(), &hosts).await;
+
         let mut spread_status = vec![];
+        let mut budget = ChangeBudget::new(self.spread_config.spread_config.max_concurrent_changes);
         trace!(spread_requirements = ?self.spread_requirements, ?component_id, "Computing commands");
         let commands = self
             .spread_requirements
             .iter()
             .filter_map(|(spread, count)| {
                 // Narrow down eligible hosts to those that match this spread's requirements
-                let eligible_hosts = eligible_hosts(&hosts, spread);
+                let eligible_hosts = eligible_hosts(&hosts, spread, &self.spread_config.spread_config.hosts, self.spread_config.host_constraint.as_ref());
                 if !eligible_hosts.is_empty() {
                     // In the future we may want more information from this chain, but for now
                     // we just need the number of running components that match this spread's annotations
 
                     // Parse the instances into a map of host_id -> number of running components managed
                     // by this scaler. Ignoring ones where we aren't running anything
-                    let running_components_per_host: HashMap<&String, usize> = component
-                        .as_ref()
-                        .map(|component| &component.instances)
-                        .map(|instances| {
-                            instances
-                                .iter()
-                                .filter_map(|(host_id, instances)| {
-                                    let count = instances
-                                        .iter()
-                                        .filter_map(|info| {
-                                            spreadscaler_annotations(&spread.name, self.id()).iter().all(
-                                                |(key, value)| {
-                                                    info.annotations
-                                                        .get(key)
-                                                        .map(|v| v == value)
-                                                        .unwrap_or(false)
-                                                },
-                                            ).then_some(info.count)
-                                        })
-                                        .sum();
-                                    (count > 0).then_some((host_id, count))
-                                }).collect()
-                        })
-                        .unwrap_or_default();
+                    let running_components_per_host = running_per_host(component.as_ref(), &spread.name, self.id());
                     let current_count: usize = running_components_per_host.values().sum();
                     trace!(current = %current_count, expected = %count, "Calculated running components, reconciling with expected count");
-                    // Here we'll generate commands for the proper host depending on where they are running
-                    match current_count.cmp(count) {
-                        Ordering::Equal => None,
-                        // Start components to reach desired instances
-                        Ordering::Less =>{
-                            // Right now just start on the first available host. We can be smarter about it later
-                            Some(vec![Command::ScaleComponent(ScaleComponent {
+                    let max_per_host = self.spread_config.spread_config.max_per_host;
+                    // Bring hosts running more than the per host limit back down to it first. The instances
+                    // are placed on other hosts by the reconciles that follow
+                    if let Some(max) = max_per_host {
+                        let over_limit = running_components_per_host
+                            .iter()
+                            .filter(|(_, running)| **running > max)
+                            .map(|(host_id, _)| Command::ScaleComponent(ScaleComponent {
                                 component_id: component_id.to_owned(),
                                 reference: self.spread_config.component_reference.to_owned(),
-                                // SAFETY: We already checked that the list of hosts is not empty, so we can unwrap here
-                                host_id: eligible_hosts.keys().next().unwrap().to_string(),
-                                count: *count as u32,
+                                host_id: host_id.to_string(),
+                                count: max as u32,
                                 model_name: self.spread_config.model_name.to_owned(),
                                 annotations: spreadscaler_annotations(&spread.name, self.id()),
-                                        config: self.config.clone(),
-                            })])
+                                config: self.config.clone(),
+                            }))
+                            .collect::<Vec<Command>>();
+                        if !over_limit.is_empty() {
+                            return Some(over_limit);
+                        }
+                    }
+                    // Here we'll generate commands for the proper host depending on where they are running
+                    match current_count.cmp(count) {
+                        Ordering::Equal => None,
+                        // Start components on the least loaded hosts that can take them, staying under the
+                        // per host limit and the capacity the hosts report
+                        Ordering::Less => {
+                            // Hosts already running the component don't answer auctions for it, so they stay
+                            // placeable as long as the lattice state says they're eligible
+                            let placeable = eligible_hosts
+                                .iter()
+                                .filter(|(_, host)| can_place_on(host, &self.spread_config.anti_affinity))
+                                .filter(|(host_id, _)| {
+                                    auctioned.get(&spread.name).is_none_or(|winners| {
+                                        winners.contains(host_id.as_str()) || running_components_per_host.contains_key(*host_id)
+                                    })
+                                })
+                                .map(|(host_id, host)| (*host_id, *host))
+                                .collect::<Vec<(&String, &Host)>>();
+                            if placeable.is_empty() {
+                                trace!(?spread.name, "No eligible hosts for spread can take new instances");
+                                spread_status.push(StatusInfo::failed(&format!("Could not satisfy spread {} for {}, all eligible hosts are cordoned or run components it can't share a host with.", spread.name, self.spread_config.component_reference)));
+                                return None;
+                            }
+                            let wanted = count - current_count;
+                            let placements = place_instances(placeable, wanted, |host_id, _| {
+                                let running = running_components_per_host.get(host_id).copied().unwrap_or_default();
+                                max_per_host.map(|max| max.saturating_sub(running)).unwrap_or(usize::MAX)
+                            });
+                            if placements.len() < wanted {
+                                let limit = match max_per_host {
+                                    Some(max) => format!("with at most {max} per host"),
+                                    None => "for the capacity they report".to_string(),
+                                };
+                                spread_status.push(StatusInfo::failed(&format!("Could not satisfy spread {} for {}, {} more instance(s) don't fit on the eligible hosts {limit}.", spread.name, self.spread_config.component_reference, wanted - placements.len())));
+                            }
+                            let to_start = budget.take(placements.len());
+                            let mut per_host: BTreeMap<&String, usize> = BTreeMap::new();
+                            for host_id in placements.into_iter().take(to_start) {
+                                *per_host.entry(host_id).or_default() += 1;
+                            }
+                            let commands = per_host
+                                .into_iter()
+                                .map(|(host_id, to_start)| Command::ScaleComponent(ScaleComponent {
+                                    component_id: component_id.to_owned(),
+                                    reference: self.spread_config.component_reference.to_owned(),
+                                    host_id: host_id.to_string(),
+                                    count: (running_components_per_host.get(host_id).copied().unwrap_or_default() + to_start) as u32,
+                                    model_name: self.spread_config.model_name.to_owned(),
+                                    annotations: spreadscaler_annotations(&spread.name, self.id()),
+                                    config: self.config.clone(),
+                                }))
+                                .collect::<Vec<Command>>();
+                            (!commands.is_empty()).then_some(commands)
                         }
                         // Stop components to reach desired instances
                         Ordering::Greater => {
                             // Components across all available hosts that exceed our desired number
-                            let count_to_stop = current_count - count;
+                            let count_to_stop = budget.take(current_count - count);
+                            if count_to_stop == 0 {
+                                return None;
+                            }
                             let (_, commands) = running_components_per_host.into_iter().fold((0usize, Vec::new()), |(mut current_stopped, mut commands), (host_id, instance_count)| {
                                 let remaining_to_stop = count_to_stop - current_stopped;
                                 // Desired count on the host, subtracting the number we need to stop
@@ -266,9 +329,20 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ComponentSpreadScaler<S> {
             .collect::<Vec<Command>>();
         trace!(?commands, "Calculated commands for component scaler");
 
+        spread_status.extend(unknown_pinned_hosts_status(
+            &hosts,
+            &self.spread_config.spread_config.hosts,
+        ));
+        spread_status.extend(host_constraint_status(
+            &hosts,
+            self.spread_config.host_constraint.as_ref(),
+        ));
+
         let status = match (spread_status.is_empty(), commands.is_empty()) {
             // No failures, no commands, scaler satisfied
-            (true, true) => StatusInfo::deployed(""),
+            (true, true) => StatusInfo::deployed(&pinned_placement_message(
+                &self.spread_config.spread_config.hosts,
+            )),
             // No failures, commands generated, scaler is reconciling
             (true, false) => {
                 StatusInfo::reconciling(&format!("Scaling component on {} host(s)", commands.len()))
@@ -302,6 +376,8 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ComponentSpreadScaler<S> {
             id: self.id.clone(),
             status: RwLock::new(StatusInfo::reconciling("")),
             config: self.config.clone(),
+            // NOTE: Cleaning up never places instances, so there is nothing to auction
+            auction: None,
         };
 
         cleanerupper.reconcile().await
@@ -343,12 +419,120 @@ impl<S: ReadStore + Send + Sync> ComponentSpreadScaler<S> {
                 lattice_id,
                 spread_config,
                 model_name,
+                host_constraint: None,
+                anti_affinity: Vec::new(),
             },
             id,
             config,
             status: RwLock::new(StatusInfo::reconciling("")),
+            auction: None,
         }
     }
+
+    /// Runs an auction before placing new instances, only placing them on hosts that answer
+    pub fn with_auction_source(mut self, auction: Option<SharedAuctionSource>) -> Self {
+        self.auction = auction;
+        self
+    }
+
+    /// Only place the component on hosts that meet the given constraint
+    pub fn with_host_constraint(mut self, constraint: Option<HostConstraintProperty>) -> Self {
+        self.spread_config.host_constraint = constraint;
+        self
+    }
+
+    /// Never place the component on a host running any of the components or providers with the
+    /// given IDs
+    pub fn with_anti_affinity(mut self, ids: Vec<String>) -> Self {
+        self.spread_config.anti_affinity = ids;
+        self
+    }
+}
+
+impl<S: ReadStore + Send + Sync> ComponentSpreadScaler<S> {
+    /// Runs an auction for each spread that needs more instances, returning the hosts that answered
+    /// keyed by spread name. Spreads without an auction are placed by the lattice state alone
+    async fn auction_winners(
+        &self,
+        component: Option<&Component>,
+        hosts: &HashMap<String, Host>,
+    ) -> HashMap<String, HashSet<String>> {
+        let Some(source) = self.auction.as_ref() else {
+            return HashMap::new();
+        };
+        let mut auctioned = HashMap::new();
+        for (spread, count) in self.spread_requirements.iter() {
+            let running: usize = running_per_host(component, &spread.name, &self.id)
+                .values()
+                .sum();
+            if running >= *count
+                || eligible_hosts(
+                    hosts,
+                    spread,
+                    &self.spread_config.spread_config.hosts,
+                    self.spread_config.host_constraint.as_ref(),
+                )
+                .is_empty()
+            {
+                continue;
+            }
+            let res = source
+                .auction_component(
+                    &self.spread_config.component_reference,
+                    &self.spread_config.component_id,
+                    auction_constraints(spread, &self.spread_config.spread_config.hosts),
+                )
+                .await;
+            if let Some(winners) = auction_winners(&self.spread_config.component_id, res) {
+                trace!(spread = %spread.name, ?winners, "Hosts answered auction");
+                auctioned.insert(spread.name.clone(), winners);
+            }
+        }
+        auctioned
+    }
+}
+
+/// Returns the number of instances of the component running on each host for the given spread,
+/// leaving out hosts that aren't running any
+fn running_per_host<'a>(
+    component: Option<&'a Component>,
+    spread_name: &str,
+    scaler_id: &str,
+) -> HashMap<&'a String, usize> {
+    let annotations = spreadscaler_annotations(spread_name, scaler_id);
+    component
+        .map(|component| {
+            component
+                .instances
+                .iter()
+                .filter_map(|(host_id, instances)| {
+                    let count = instances
+                        .iter()
+                        .filter(|info| {
+                            annotations
+                                .iter()
+                                .all(|(key, value)| info.annotations.get(key) == Some(value))
+                        })
+                        .map(|info| info.count)
+                        .sum();
+                    (count > 0).then_some((host_id, count))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the constraints to run an auction for a spread with. Pinned hosts ignore the spread
+/// requirements, so their auctions don't have any constraints
+pub(crate) fn auction_constraints(
+    spread: &Spread,
+    pinned_hosts: &[String],
+) -> BTreeMap<String, String> {
+    if pinned_hosts.is_empty() {
+        spread.requirements.clone()
+    } else {
+        BTreeMap::new()
+    }
 }
 
 /// Helper function to create a predictable annotations map for a spread
@@ -362,14 +546,24 @@ pub(crate) fn spreadscaler_annotations(
     ])
 }
 
-/// Helper function that computes a list of eligible hosts to match with a spread
+/// Helper function that computes a list of eligible hosts to match with a spread. If any hosts are
+/// pinned, only those hosts are eligible and the spread requirements are ignored. Hosts that don't
+/// meet the host constraint or are being drained are never eligible
 pub(crate) fn eligible_hosts<'a>(
     all_hosts: &'a HashMap<String, Host>,
     spread: &Spread,
+    pinned_hosts: &[String],
+    host_constraint: Option<&HostConstraintProperty>,
 ) -> HashMap<&'a String, &'a Host> {
     all_hosts
         .iter()
-        .filter(|(_id, host)| {
+        .filter(|(id, host)| {
+            if host.is_draining() || !meets_host_constraint(host, host_constraint) {
+                return false;
+            }
+            if !pinned_hosts.is_empty() {
+                return pinned_hosts.contains(id);
+            }
             spread
                 .requirements
                 .iter()
@@ -378,15 +572,33 @@ pub(crate) fn eligible_hosts<'a>(
         .collect()
 }
 
+/// Helper function that returns whether new instances can be placed on an eligible host. Nothing
+/// new is placed on cordoned hosts or on hosts running any of the components or providers in
+/// `anti_affinity`, but what already runs there is left alone
+pub(crate) fn can_place_on(host: &Host, anti_affinity: &[String]) -> bool {
+    !host.is_cordoned()
+        && !anti_affinity.iter().any(|id| {
+            host.components.contains_key(id)
+                || host
+                    .providers
+                    .iter()
+                    .any(|provider| &provider.provider_id == id)
+        })
+}
+
 /// Helper function that computes a list of ineligible hosts that match none of the spread requirements
 pub(crate) fn compute_ineligible_hosts<'a>(
     all_hosts: &'a HashMap<String, Host>,
     spreads: Vec<&Spread>,
+    pinned_hosts: &[String],
+    host_constraint: Option<&HostConstraintProperty>,
 ) -> HashMap<&'a String, &'a Host> {
     // Find all host IDs that are eligible for any spread
     let eligible_ids = spreads
         .iter()
-        .flat_map(|spread| eligible_hosts(all_hosts, spread).into_keys())
+        .flat_map(|spread| {
+            eligible_hosts(all_hosts, spread, pinned_hosts, host_constraint).into_keys()
+        })
         .collect::<HashSet<_>>();
 
     // Filter out all hosts that are eligible for any spread, leaving only ineligible hosts
@@ -396,10 +608,154 @@ pub(crate) fn compute_ineligible_hosts<'a>(
         .collect::<HashMap<_, _>>()
 }
 
+/// Returns the spread used for scalers that are pinned to specific hosts. Pinned placement bypasses
+/// label selection, so this spread has no requirements
+pub(crate) fn pinned_spread() -> Spread {
+    Spread {
+        name: PINNED_SPREAD_NAME.to_string(),
+        ..Default::default()
+    }
+}
+
+/// Helper function that returns a failed status if any of the pinned hosts are not known to the
+/// lattice
+pub(crate) fn unknown_pinned_hosts_status(
+    all_hosts: &HashMap<String, Host>,
+    pinned_hosts: &[String],
+) -> Option<StatusInfo> {
+    let unknown = pinned_hosts
+        .iter()
+        .filter(|id| !all_hosts.contains_key(*id))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    (!unknown.is_empty()).then(|| {
+        StatusInfo::failed(&format!(
+            "Pinned host(s) {} not found in lattice.",
+            unknown.join(", ")
+        ))
+    })
+}
+
+/// Helper function that returns whether a host meets a host constraint. Hosts that don't report a
+/// version never meet a minimum version
+pub(crate) fn meets_host_constraint(
+    host: &Host,
+    host_constraint: Option<&HostConstraintProperty>,
+) -> bool {
+    let Some(constraint) = host_constraint else {
+        return true;
+    };
+    let version_ok = match constraint.min_host_version.as_deref() {
+        None => true,
+        Some(min) => match semver::Version::parse(min) {
+            Ok(min) => host.version.as_ref().is_some_and(|version| *version >= min),
+            // NOTE: Manifests are validated before they are deployed, so this should only happen
+            // for manifests stored by an older version of wadm
+            Err(e) => {
+                warn!(error = %e, %min, "Ignoring invalid minimum host version");
+                true
+            }
+        },
+    };
+    version_ok
+        && constraint
+            .required_labels
+            .iter()
+            .all(|(key, value)| host.labels.get(key) == Some(value))
+}
+
+/// Helper function that returns a failed status if no host in the lattice meets the host
+/// constraint
+pub(crate) fn host_constraint_status(
+    all_hosts: &HashMap<String, Host>,
+    host_constraint: Option<&HostConstraintProperty>,
+) -> Option<StatusInfo> {
+    let constraint = host_constraint?;
+    if all_hosts
+        .values()
+        .any(|host| meets_host_constraint(host, Some(constraint)))
+    {
+        return None;
+    }
+    Some(StatusInfo::failed(&format!(
+        "No hosts meet the host constraint ({}).",
+        describe_host_constraint(constraint)
+    )))
+}
+
+/// Helper function that describes the requirements of a host constraint, like
+/// `version >= 1.2.0, os=linux`
+pub(crate) fn describe_host_constraint(constraint: &HostConstraintProperty) -> String {
+    let mut requirements = Vec::new();
+    if let Some(min) = constraint.min_host_version.as_deref() {
+        requirements.push(format!("version >= {min}"));
+    }
+    requirements.extend(
+        constraint
+            .required_labels
+            .iter()
+            .map(|(key, value)| format!("{key}={value}")),
+    );
+    requirements.join(", ")
+}
+
+/// Helper function that describes a pinned placement for use in status messages. Returns an empty
+/// string if the scaler isn't pinned
+pub(crate) fn pinned_placement_message(pinned_hosts: &[String]) -> String {
+    if pinned_hosts.is_empty() {
+        String::new()
+    } else {
+        format!("Pinned to host(s) {}", pinned_hosts.join(", "))
+    }
+}
+
+/// Tracks how many more instances a scaler can change in a single reconcile, so that large changes
+/// are made in steps. As every command sets an absolute count, the remaining changes are picked up
+/// by the reconciles that follow the events for the finished step
+pub(crate) struct ChangeBudget {
+    remaining: Option<usize>,
+}
+
+impl ChangeBudget {
+    /// Creates a budget with the given limit, where `None` means changes aren't limited
+    pub(crate) fn new(limit: Option<usize>) -> ChangeBudget {
+        ChangeBudget { remaining: limit }
+    }
+
+    /// Takes up to `wanted` changes from the budget, returning how many can be made
+    pub(crate) fn take(&mut self, wanted: usize) -> usize {
+        match self.remaining.as_mut() {
+            Some(remaining) => {
+                let allowed = wanted.min(*remaining);
+                *remaining -= allowed;
+                allowed
+            }
+            None => wanted,
+        }
+    }
+
+    /// Returns the count to scale to when moving from `current` towards `desired`, or `None` if
+    /// the budget doesn't allow any change
+    pub(crate) fn step(&mut self, current: usize, desired: usize) -> Option<usize> {
+        let change = self.take(current.abs_diff(desired));
+        (change > 0).then(|| {
+            if desired > current {
+                current + change
+            } else {
+                current - change
+            }
+        })
+    }
+}
+
 /// Given a spread config, return a vector of tuples that represents the spread
 /// and the actual number of components to start for a specific spread requirement
 fn compute_spread(spread_config: &SpreadScalerProperty) -> Vec<(Spread, usize)> {
     let requested_instances = spread_config.instances;
+    // Pinned hosts take precedence over any spread requirements
+    if !spread_config.hosts.is_empty() {
+        return vec![(pinned_spread(), requested_instances)];
+    }
     let mut requested_spreads = spread_config.spread.clone();
     requested_spreads.sort_by_key(|s| Reverse(s.weight.unwrap_or(DEFAULT_SPREAD_WEIGHT)));
 
@@ -481,7 +837,7 @@ mod test {
 
     use anyhow::Result;
     use chrono::Utc;
-    use wadm_types::{Spread, SpreadScalerProperty};
+    use wadm_types::{api::StatusType, Spread, SpreadScalerProperty};
     use wasmcloud_control_interface::Link;
 
     use crate::{
@@ -498,6 +854,7 @@ mod test {
         storage::{Component, Host, Store, WadmComponentInfo},
         test_util::{NoopPublisher, TestLatticeSource, TestStore},
         workers::{CommandPublisher, EventWorker, StatusPublisher},
+        CORDON_DRAIN_VALUE, CORDON_LABEL,
     };
 
     const MODEL_NAME: &str = "spreadscaler_test";
@@ -514,6 +871,10 @@ mod test {
                 requirements: BTreeMap::new(),
                 weight: Some(100),
             }],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let simple_spread_res = compute_spread(&simple_spread);
@@ -534,6 +895,10 @@ mod test {
                     weight: Some(100),
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let multi_spread_even_res = compute_spread(&multi_spread_even);
@@ -555,6 +920,10 @@ mod test {
                     weight: Some(40),
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let multi_spread_even_res = compute_spread(&multi_spread_odd);
@@ -576,6 +945,10 @@ mod test {
                     weight: Some(100),
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let multi_spread_even_res = compute_spread(&multi_spread_odd);
@@ -597,6 +970,10 @@ mod test {
                     weight: None,
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let multi_spread_even_no_weight = compute_spread(&multi_spread_even_no_weight);
@@ -607,6 +984,10 @@ mod test {
         let simple_spread_replica_only = SpreadScalerProperty {
             instances: 12,
             spread: vec![],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let simple_replica_only = compute_spread(&simple_spread_replica_only);
@@ -642,6 +1023,10 @@ mod test {
                     weight: Some(384),
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
         let complex_spread_res = compute_spread(&complex_spread);
         assert_eq!(complex_spread_res[0].1, 85);
@@ -708,6 +1093,10 @@ mod test {
                     weight: Some(384),
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let spreadscaler = ComponentSpreadScaler::new(
@@ -754,6 +1143,471 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn can_pin_to_hosts() -> Result<()> {
+        let lattice_id = "pinned_hosts";
+        let component_reference = "fakecloud.azurecr.io/echo:0.3.4".to_string();
+        let component_id = "fakecloud_azurecr_io_echo_0_3_4".to_string();
+        let pinned_host = "NASDASDIMAREALHOST";
+        let other_host = "NASDASDIMAREALHOST2";
+
+        let store = Arc::new(TestStore::default());
+        for host_id in [pinned_host, other_host] {
+            store
+                .store(
+                    lattice_id,
+                    host_id.to_string(),
+                    Host {
+                        components: HashMap::new(),
+                        friendly_name: "hey".to_string(),
+                        labels: HashMap::from_iter([("cloud".to_string(), "fake".to_string())]),
+                        providers: HashSet::new(),
+                        uptime_seconds: 123,
+                        version: None,
+                        id: host_id.to_string(),
+                        last_seen: Utc::now(),
+                    },
+                )
+                .await?;
+        }
+
+        // The spread requirements match no hosts, but should be ignored in favor of the pinned host
+        let pinned = SpreadScalerProperty {
+            instances: 3,
+            spread: vec![Spread {
+                name: "NoMatch".to_string(),
+                requirements: BTreeMap::from_iter([("cloud".to_string(), "real".to_string())]),
+                weight: None,
+            }],
+            hosts: vec![pinned_host.to_string()],
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
+        };
+
+        let spreadscaler = ComponentSpreadScaler::new(
+            store.clone(),
+            component_reference.to_string(),
+            component_id.to_string(),
+            lattice_id.to_string(),
+            MODEL_NAME.to_string(),
+            pinned,
+            "fake_component",
+            vec![],
+        );
+
+        let cmds = spreadscaler.reconcile().await?;
+        assert_eq!(
+            cmds,
+            vec![Command::ScaleComponent(ScaleComponent {
+                component_id: component_id.to_string(),
+                reference: component_reference.to_string(),
+                host_id: pinned_host.to_string(),
+                count: 3,
+                model_name: MODEL_NAME.to_string(),
+                annotations: spreadscaler_annotations(PINNED_SPREAD_NAME, spreadscaler.id()),
+                config: vec![]
+            })]
+        );
+
+        // Pinning to a host that isn't in the lattice should be surfaced in the status
+        let missing = SpreadScalerProperty {
+            instances: 3,
+            spread: vec![],
+            hosts: vec!["NOTAREALHOST".to_string()],
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
+        };
+        let spreadscaler = ComponentSpreadScaler::new(
+            store.clone(),
+            component_reference.to_string(),
+            component_id.to_string(),
+            lattice_id.to_string(),
+            MODEL_NAME.to_string(),
+            missing,
+            "fake_component",
+            vec![],
+        );
+
+        assert!(spreadscaler.reconcile().await?.is_empty());
+        let status = spreadscaler.status().await;
+        assert_eq!(status.status_type, StatusType::Failed);
+        assert!(status
+            .message
+            .contains("Pinned host(s) NOTAREALHOST not found in lattice"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn respects_cordoned_hosts() -> Result<()> {
+        let lattice_id = "cordoned_hosts";
+        let component_reference = "fakecloud.azurecr.io/echo:0.3.4".to_string();
+        let component_id = "fakecloud_azurecr_io_echo_0_3_4".to_string();
+        let cordoned_host = "NASDASDIMAREALHOST";
+        let open_host = "NASDASDIMAREALHOST2";
+
+        let store = Arc::new(TestStore::default());
+        let host = |host_id: &str, cordon: Option<&str>, components: HashMap<String, usize>| Host {
+            components,
+            friendly_name: "hey".to_string(),
+            labels: HashMap::from_iter(
+                cordon.map(|value| (CORDON_LABEL.to_string(), value.to_string())),
+            ),
+            providers: HashSet::new(),
+            uptime_seconds: 123,
+            version: None,
+            id: host_id.to_string(),
+            last_seen: Utc::now(),
+        };
+        store
+            .store(
+                lattice_id,
+                cordoned_host.to_string(),
+                host(cordoned_host, Some("true"), HashMap::new()),
+            )
+            .await?;
+        store
+            .store(
+                lattice_id,
+                open_host.to_string(),
+                host(open_host, None, HashMap::new()),
+            )
+            .await?;
+
+        let spreadscaler = ComponentSpreadScaler::new(
+            store.clone(),
+            component_reference.to_string(),
+            component_id.to_string(),
+            lattice_id.to_string(),
+            MODEL_NAME.to_string(),
+            SpreadScalerProperty {
+                instances: 3,
+                spread: vec![Spread {
+                    name: "Anywhere".to_string(),
+                    ..Default::default()
+                }],
+                hosts: vec![],
+                max_concurrent_changes: None,
+                max_per_host: None,
+                anti_affinity: vec![],
+            },
+            "fake_component",
+            vec![],
+        );
+
+        let cmds = spreadscaler.reconcile().await?;
+        assert_eq!(
+            cmds,
+            vec![Command::ScaleComponent(ScaleComponent {
+                component_id: component_id.to_string(),
+                reference: component_reference.to_string(),
+                host_id: open_host.to_string(),
+                count: 3,
+                model_name: MODEL_NAME.to_string(),
+                annotations: spreadscaler_annotations("Anywhere", spreadscaler.id()),
+                config: vec![]
+            })],
+            "New instances should only be placed on hosts that aren't cordoned"
+        );
+
+        // Draining the host the component is running on should remove it from there
+        store
+            .store(
+                lattice_id,
+                open_host.to_string(),
+                host(
+                    open_host,
+                    Some(CORDON_DRAIN_VALUE),
+                    HashMap::from([(component_id.clone(), 3)]),
+                ),
+            )
+            .await?;
+        let cmds = spreadscaler.reconcile().await?;
+        assert_eq!(
+            cmds,
+            vec![Command::ScaleComponent(ScaleComponent {
+                component_id: component_id.to_string(),
+                reference: component_reference.to_string(),
+                host_id: open_host.to_string(),
+                count: 0,
+                model_name: MODEL_NAME.to_string(),
+                annotations: BTreeMap::new(),
+                config: vec![]
+            })]
+        );
+
+        // Once drained, there is nowhere left to place the component
+        store
+            .store(
+                lattice_id,
+                open_host.to_string(),
+                host(open_host, Some(CORDON_DRAIN_VALUE), HashMap::new()),
+            )
+            .await?;
+        assert!(spreadscaler.reconcile().await?.is_empty());
+        let status = spreadscaler.status().await;
+        assert_eq!(status.status_type, StatusType::Failed);
+        assert!(status.message.contains("all eligible hosts are cordoned"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn places_on_auction_winners() -> Result<()> {
+        let lattice_id = "auction_placement";
+        let component_reference = "fakecloud.azurecr.io/echo:0.3.4".to_string();
+        let component_id = "fakecloud_azurecr_io_echo_0_3_4".to_string();
+
+        // Both hosts are in the east region according to the lattice state, but only one of them
+        // still is and answers the auction
+        let store = Arc::new(TestStore::default());
+        for host_id in ["stale-host", "live-host"] {
+            store
+                .store(
+                    lattice_id,
+                    host_id.to_string(),
+                    Host {
+                        id: host_id.to_string(),
+                        labels: HashMap::from([("region".to_string(), "east".to_string())]),
+                        last_seen: Utc::now(),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+        }
+        let source = TestLatticeSource {
+            auction_winners: vec!["live-host".to_string()],
+            ..Default::default()
+        };
+
+        let spreadscaler = ComponentSpreadScaler::new(
+            store.clone(),
+            component_reference.to_string(),
+            component_id.to_string(),
+            lattice_id.to_string(),
+            MODEL_NAME.to_string(),
+            SpreadScalerProperty {
+                instances: 4,
+                spread: vec![Spread {
+                    name: "East".to_string(),
+                    requirements: BTreeMap::from([("region".to_string(), "east".to_string())]),
+                    weight: None,
+                }],
+                hosts: vec![],
+                max_concurrent_changes: None,
+                max_per_host: None,
+                anti_affinity: vec![],
+            },
+            "fake_component",
+            vec![],
+        )
+        .with_auction_source(Some(Arc::new(source)));
+
+        let cmds = spreadscaler.reconcile().await?;
+        assert_eq!(
+            cmds,
+            vec![Command::ScaleComponent(ScaleComponent {
+                component_id: component_id.to_string(),
+                reference: component_reference.to_string(),
+                host_id: "live-host".to_string(),
+                count: 4,
+                model_name: MODEL_NAME.to_string(),
+                annotations: spreadscaler_annotations("East", spreadscaler.id()),
+                config: vec![]
+            })],
+            "New instances should only be placed on hosts that answered the auction"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn respects_placement_limits() -> Result<()> {
+        let lattice_id = "placement_limits";
+        let component_reference = "fakecloud.azurecr.io/echo:0.3.4".to_string();
+        let component_id = "fakecloud_azurecr_io_echo_0_3_4".to_string();
+        let other_id = "other_component".to_string();
+
+        let store = Arc::new(TestStore::default());
+        for (host_id, components) in [
+            ("host-a", HashMap::from([(other_id.clone(), 1)])),
+            ("host-b", HashMap::new()),
+            ("host-c", HashMap::new()),
+        ] {
+            store
+                .store(
+                    lattice_id,
+                    host_id.to_string(),
+                    Host {
+                        components,
+                        friendly_name: "hey".to_string(),
+                        labels: HashMap::new(),
+                        providers: HashSet::new(),
+                        uptime_seconds: 123,
+                        version: None,
+                        id: host_id.to_string(),
+                        last_seen: Utc::now(),
+                    },
+                )
+                .await?;
+        }
+
+        let spread_property = |instances: usize| SpreadScalerProperty {
+            instances,
+            spread: vec![Spread {
+                name: "Anywhere".to_string(),
+                ..Default::default()
+            }],
+            hosts: vec![],
+            max_concurrent_changes: None,
+            max_per_host: Some(2),
+            anti_affinity: vec!["other".to_string()],
+        };
+        let spreadscaler = ComponentSpreadScaler::new(
+            store.clone(),
+            component_reference.to_string(),
+            component_id.to_string(),
+            lattice_id.to_string(),
+            MODEL_NAME.to_string(),
+            spread_property(3),
+            "fake_component",
+            vec![],
+        )
+        .with_anti_affinity(vec![other_id.clone()]);
+        let scale = |host_id: &str, count: u32| {
+            Command::ScaleComponent(ScaleComponent {
+                component_id: component_id.to_string(),
+                reference: component_reference.to_string(),
+                host_id: host_id.to_string(),
+                count,
+                model_name: MODEL_NAME.to_string(),
+                annotations: spreadscaler_annotations("Anywhere", spreadscaler.id()),
+                config: vec![],
+            })
+        };
+
+        assert_eq!(
+            spreadscaler.reconcile().await?,
+            vec![scale("host-b", 2), scale("host-c", 1)],
+            "Instances should be spread out to stay under the limit and away from the other component"
+        );
+
+        // Instances that don't fit anywhere should be reported, but the rest still placed
+        let spreadscaler = ComponentSpreadScaler::new(
+            store.clone(),
+            component_reference.to_string(),
+            component_id.to_string(),
+            lattice_id.to_string(),
+            MODEL_NAME.to_string(),
+            spread_property(5),
+            "fake_component",
+            vec![],
+        )
+        .with_anti_affinity(vec![other_id.clone()]);
+        assert_eq!(
+            spreadscaler.reconcile().await?,
+            vec![scale("host-b", 2), scale("host-c", 2)]
+        );
+        let status = spreadscaler.status().await;
+        assert_eq!(status.status_type, StatusType::Failed);
+        assert!(status.message.contains("1 more instance(s) don't fit"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn limits_concurrent_changes() -> Result<()> {
+        let lattice_id = "concurrent_changes";
+        let component_reference = "fakecloud.azurecr.io/echo:0.3.4".to_string();
+        let component_id = "fakecloud_azurecr_io_echo_0_3_4".to_string();
+        let host_id = "NASDASDIMAREALHOST";
+
+        let store = Arc::new(TestStore::default());
+        store
+            .store(
+                lattice_id,
+                host_id.to_string(),
+                Host {
+                    components: HashMap::new(),
+                    friendly_name: "hey".to_string(),
+                    labels: HashMap::new(),
+                    providers: HashSet::new(),
+                    uptime_seconds: 123,
+                    version: None,
+                    id: host_id.to_string(),
+                    last_seen: Utc::now(),
+                },
+            )
+            .await?;
+
+        let spreadscaler = ComponentSpreadScaler::new(
+            store.clone(),
+            component_reference.to_string(),
+            component_id.to_string(),
+            lattice_id.to_string(),
+            MODEL_NAME.to_string(),
+            SpreadScalerProperty {
+                instances: 5,
+                spread: vec![],
+                hosts: vec![],
+                max_concurrent_changes: Some(2),
+                max_per_host: None,
+                anti_affinity: vec![],
+            },
+            "fake_component",
+            vec![],
+        );
+        let scale_to = |count: u32| {
+            vec![Command::ScaleComponent(ScaleComponent {
+                component_id: component_id.to_string(),
+                reference: component_reference.to_string(),
+                host_id: host_id.to_string(),
+                count,
+                model_name: MODEL_NAME.to_string(),
+                annotations: spreadscaler_annotations("default", spreadscaler.id()),
+                config: vec![],
+            })]
+        };
+
+        assert_eq!(spreadscaler.reconcile().await?, scale_to(2));
+
+        // Once the first step is running, the next reconcile takes the next step
+        for (running, next) in [(2, 4), (4, 5)] {
+            store
+                .store(
+                    lattice_id,
+                    component_id.to_string(),
+                    Component {
+                        id: component_id.to_string(),
+                        reference: component_reference.to_string(),
+                        instances: HashMap::from_iter([(
+                            host_id.to_string(),
+                            HashSet::from_iter([WadmComponentInfo {
+                                annotations: spreadscaler_annotations("default", spreadscaler.id()),
+                                count: running,
+                            }]),
+                        )]),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            assert_eq!(spreadscaler.reconcile().await?, scale_to(next));
+        }
+
+        let mut budget = ChangeBudget::new(Some(3));
+        assert_eq!(
+            budget.step(10, 2),
+            Some(7),
+            "Scaling down should be limited"
+        );
+        assert_eq!(budget.step(0, 5), None, "The budget should be used up");
+        assert_eq!(ChangeBudget::new(None).step(0, 5), Some(5));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn can_scale_up_and_down() -> Result<()> {
         let lattice_id = "computing_spread_commands";
@@ -790,6 +1644,10 @@ mod test {
                     weight: Some(25), // 103
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let blobby_spread_property = SpreadScalerProperty {
@@ -820,6 +1678,10 @@ mod test {
                     weight: Some(33), // 3
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let echo_spreadscaler = ComponentSpreadScaler::new(
@@ -1079,6 +1941,10 @@ mod test {
                     weight: Some(25),
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let spreadscaler = ComponentSpreadScaler::new(
@@ -1174,6 +2040,10 @@ mod test {
             // Makes it so we always get at least 2 commands
             instances: 9,
             spread: Vec::new(),
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let spreadscaler = ComponentSpreadScaler::new(
@@ -1361,6 +2231,10 @@ mod test {
                     weight: Some(33), // 3
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let blobby_spreadscaler = ComponentSpreadScaler::new(
@@ -1551,6 +2425,8 @@ mod test {
                 lattice_id: lattice_id.to_string(),
                 inner: Event::ComponentScaled(modifying_event.clone()),
                 acker: None,
+                expires_at: None,
+                trace_context: None,
             })
             .await
             .expect("should be able to handle an event");
@@ -1669,11 +2545,72 @@ mod test {
 
         // The first three hosts match at least one of the spread requirements (resilient: true || region: east)
         // The last host is in west and not resilient.
-        let ineligible = compute_ineligible_hosts(&hosts, spreads.iter().collect());
+        let ineligible = compute_ineligible_hosts(&hosts, spreads.iter().collect(), &[], None);
 
         assert_eq!(ineligible.len(), 1);
         assert!(ineligible
             .iter()
             .any(|(id, _host)| *id == "NASDASDIMAREALHOST4"));
     }
+
+    #[test]
+    fn can_exclude_hosts_not_meeting_host_constraint() {
+        let host = |id: &str, version: Option<&str>, labels: &[(&str, &str)]| {
+            (
+                id.to_string(),
+                Host {
+                    components: HashMap::new(),
+                    friendly_name: "hey".to_string(),
+                    labels: labels
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                    providers: HashSet::new(),
+                    uptime_seconds: 123,
+                    version: version.map(|v| semver::Version::parse(v).unwrap()),
+                    id: id.to_string(),
+                    last_seen: Utc::now(),
+                },
+            )
+        };
+        let hosts = HashMap::from_iter([
+            host("old", Some("1.1.0"), &[("gpu", "true")]),
+            host("new", Some("1.3.0"), &[("gpu", "true")]),
+            host("nogpu", Some("1.4.0"), &[]),
+            host("unknown", None, &[("gpu", "true")]),
+        ]);
+        let constraint = HostConstraintProperty {
+            min_host_version: Some("1.2.0".to_string()),
+            required_labels: BTreeMap::from([("gpu".to_string(), "true".to_string())]),
+        };
+
+        let eligible = eligible_hosts(&hosts, &Spread::default(), &[], Some(&constraint));
+        assert_eq!(
+            eligible.keys().map(|id| id.as_str()).collect::<Vec<_>>(),
+            vec!["new"]
+        );
+        let ineligible =
+            compute_ineligible_hosts(&hosts, vec![&Spread::default()], &[], Some(&constraint));
+        assert_eq!(ineligible.len(), 3);
+        assert!(host_constraint_status(&hosts, Some(&constraint)).is_none());
+
+        // Pinned hosts still have to meet the constraint
+        assert!(eligible_hosts(
+            &hosts,
+            &pinned_spread(),
+            &["old".to_string()],
+            Some(&constraint)
+        )
+        .is_empty());
+
+        let hosts = HashMap::from_iter([host("old", Some("1.1.0"), &[("gpu", "true")])]);
+        let status = host_constraint_status(&hosts, Some(&constraint))
+            .expect("Should report when no hosts meet the constraint");
+        assert_eq!(status.status_type, StatusType::Failed);
+        assert_eq!(
+            status.message,
+            "No hosts meet the host constraint (version >= 1.2.0, gpu=true)."
+        );
+        assert!(host_constraint_status(&hosts, None).is_none());
+    }
 }
@@ -1,13 +1,15 @@
 use std::{
     cmp::Ordering,
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet},
 };
 
 use anyhow::Result;
 use async_trait::async_trait;
 use tokio::sync::{OnceCell, RwLock};
 use tracing::{instrument, trace};
-use wadm_types::{api::StatusInfo, Spread, SpreadScalerProperty, TraitProperty};
+use wadm_types::{
+    api::StatusInfo, HostConstraintProperty, Spread, SpreadScalerProperty, TraitProperty,
+};
 
 use crate::{
     commands::{Command, StartProvider, StopProvider},
@@ -17,16 +19,20 @@ use crate::{
     },
     scaler::{
         compute_id_sha256,
+        placement::{auction_winners, rank_hosts},
         spreadscaler::{
-            compute_ineligible_hosts, compute_spread, eligible_hosts, spreadscaler_annotations,
+            auction_constraints, can_place_on, compute_ineligible_hosts, compute_spread,
+            eligible_hosts, host_constraint_status, pinned_placement_message,
+            spreadscaler_annotations, unknown_pinned_hosts_status,
         },
-        Scaler,
+        Scaler, SharedAuctionSource,
     },
     storage::{Host, ReadStore},
     SCALER_KEY,
 };
 
 use super::SPREAD_SCALER_KIND;
+use crate::scaler::provider_reference;
 
 /// Config for a ProviderSpreadConfig
 #[derive(Clone)]
@@ -59,6 +65,12 @@ pub struct ProviderSpreadScaler<S: ReadStore + Send + Sync> {
     provider_id: OnceCell<String>,
     id: String,
     status: RwLock<StatusInfo>,
+    /// Constraint on the hosts the provider can be placed on
+    host_constraint: Option<HostConstraintProperty>,
+    /// IDs of the components and providers the provider is never placed on the same host as
+    anti_affinity: Vec<String>,
+    /// Where to run auctions for new instances, if placement by auction is enabled
+    auction: Option<SharedAuctionSource>,
 }
 
 #[async_trait]
@@ -75,6 +87,10 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ProviderSpreadScaler<S> {
         self.config.provider_id.to_string()
     }
 
+    async fn references(&self) -> Vec<String> {
+        vec![provider_reference(&self.config.provider_id)]
+    }
+
     async fn status(&self) -> StatusInfo {
         let _ = self.reconcile().await;
         self.status.read().await.to_owned()
@@ -115,6 +131,8 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ProviderSpreadScaler<S> {
             {
                 self.reconcile().await
             }
+            // A label change can move a host into or out of any spread, so always reconcile
+            Event::HostLabelsChanged(_) => self.reconcile().await,
             // No other event impacts the job of this scaler so we can ignore it
             _ => Ok(Vec::new()),
         }
@@ -132,6 +150,8 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ProviderSpreadScaler<S> {
                 .iter()
                 .map(|(s, _)| s)
                 .collect::<Vec<&Spread>>(),
+            &self.config.spread_config.hosts,
+            self.host_constraint.as_ref(),
         );
         // Remove any providers that are managed by this scaler and running on ineligible hosts
         let remove_ineligible: Vec<Command> = ineligible_hosts
@@ -173,13 +193,15 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ProviderSpreadScaler<S> {
             return Ok(remove_ineligible);
         }
 
+        let auctioned = self.auction_winners(&hosts).await;
+
         let mut spread_status = vec![];
 
-        let commands = self
+        let mut commands = self
             .spread_requirements
             .iter()
             .flat_map(|(spread, count)| {
-                let eligible_hosts = eligible_hosts(&hosts, spread);
+                let eligible_hosts = eligible_hosts(&hosts, spread, &self.config.spread_config.hosts, self.host_constraint.as_ref());
                 let eligible_count = eligible_hosts.len();
                 // Partition hosts into ones running this provider (no matter what is running it), and others
                 let (running, other): (HashMap<&String, &Host>, HashMap<&String, &Host>) =
@@ -243,11 +265,14 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ProviderSpreadScaler<S> {
                         // end up in an infinite loop of stopping other managed providers.
                         let num_to_start = count.saturating_sub(current_running).saturating_sub(running_for_other.len());
 
-                        // Take `num_to_start` commands from this iterator
-                        let commands = other
+                        // Take `num_to_start` commands from this iterator, starting with the least loaded hosts
+                        let commands = rank_hosts(other)
                             .into_iter()
+                            .filter(|(host_id, _host)| {
+                                auctioned.get(&spread.name).is_none_or(|winners| winners.contains(host_id.as_str()))
+                            })
                             .filter(|(_host_id, host)| {
-                                !host.providers.contains(&ProviderInfo {
+                                can_place_on(host, &self.anti_affinity) && !host.providers.contains(&ProviderInfo {
                                     provider_id: provider_id.to_string(),
                                     provider_ref: provider_ref.to_string(),
                                     annotations: BTreeMap::default(),
@@ -281,12 +306,27 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ProviderSpreadScaler<S> {
                 }
             })
             .collect::<Vec<Command>>();
+        // Every provider command starts or stops a single instance
+        if let Some(max) = self.config.spread_config.max_concurrent_changes {
+            commands.truncate(max);
+        }
 
         trace!(?commands, "Calculated commands for provider scaler");
 
+        spread_status.extend(unknown_pinned_hosts_status(
+            &hosts,
+            &self.config.spread_config.hosts,
+        ));
+        spread_status.extend(host_constraint_status(
+            &hosts,
+            self.host_constraint.as_ref(),
+        ));
+
         let status = match (spread_status.is_empty(), commands.is_empty()) {
             // No failures, no commands, scaler satisfied
-            (true, true) => StatusInfo::deployed(""),
+            (true, true) => {
+                StatusInfo::deployed(&pinned_placement_message(&self.config.spread_config.hosts))
+            }
             // No failures, commands generated, scaler is reconciling
             (true, false) => {
                 StatusInfo::reconciling(&format!("Scaling provider on {} host(s)", commands.len()))
@@ -319,6 +359,10 @@ impl<S: ReadStore + Send + Sync + Clone> Scaler for ProviderSpreadScaler<S> {
             provider_id: self.provider_id.clone(),
             id: self.id.clone(),
             status: RwLock::new(StatusInfo::reconciling("")),
+            host_constraint: self.host_constraint.clone(),
+            anti_affinity: self.anti_affinity.clone(),
+            // NOTE: Cleaning up never places instances, so there is nothing to auction
+            auction: None,
         };
 
         cleanerupper.reconcile().await
@@ -353,8 +397,74 @@ impl<S: ReadStore + Send + Sync> ProviderSpreadScaler<S> {
             config,
             id,
             status: RwLock::new(StatusInfo::reconciling("")),
+            host_constraint: None,
+            anti_affinity: Vec::new(),
+            auction: None,
         }
     }
+
+    /// Runs an auction before placing new instances, only placing them on hosts that answer
+    pub fn with_auction_source(mut self, auction: Option<SharedAuctionSource>) -> Self {
+        self.auction = auction;
+        self
+    }
+
+    /// Runs an auction for each spread that needs more instances, returning the hosts that answered
+    /// keyed by spread name. Spreads without an auction are placed by the lattice state alone
+    async fn auction_winners(
+        &self,
+        hosts: &HashMap<String, Host>,
+    ) -> HashMap<String, HashSet<String>> {
+        let Some(source) = self.auction.as_ref() else {
+            return HashMap::new();
+        };
+        let provider = ProviderInfo {
+            provider_id: self.config.provider_id.clone(),
+            provider_ref: self.config.provider_reference.clone(),
+            annotations: BTreeMap::default(),
+        };
+        let mut auctioned = HashMap::new();
+        for (spread, count) in self.spread_requirements.iter() {
+            let eligible = eligible_hosts(
+                hosts,
+                spread,
+                &self.config.spread_config.hosts,
+                self.host_constraint.as_ref(),
+            );
+            let running = eligible
+                .values()
+                .filter(|host| host.providers.contains(&provider))
+                .count();
+            if running >= *count || running == eligible.len() {
+                continue;
+            }
+            let res = source
+                .auction_provider(
+                    &self.config.provider_reference,
+                    &self.config.provider_id,
+                    auction_constraints(spread, &self.config.spread_config.hosts),
+                )
+                .await;
+            if let Some(winners) = auction_winners(&self.config.provider_id, res) {
+                trace!(spread = %spread.name, ?winners, "Hosts answered auction");
+                auctioned.insert(spread.name.clone(), winners);
+            }
+        }
+        auctioned
+    }
+
+    /// Only place the provider on hosts that meet the given constraint
+    pub fn with_host_constraint(mut self, constraint: Option<HostConstraintProperty>) -> Self {
+        self.host_constraint = constraint;
+        self
+    }
+
+    /// Never place the provider on a host running any of the components or providers with the
+    /// given IDs
+    pub fn with_anti_affinity(mut self, ids: Vec<String>) -> Self {
+        self.anti_affinity = ids;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -393,6 +503,10 @@ mod test {
             spread_config: SpreadScalerProperty {
                 instances: 1,
                 spread: vec![],
+                hosts: Vec::new(),
+                max_concurrent_changes: None,
+                max_per_host: None,
+                anti_affinity: vec![],
             },
             provider_config: vec![],
         };
@@ -408,6 +522,10 @@ mod test {
             spread_config: SpreadScalerProperty {
                 instances: 1,
                 spread: vec![],
+                hosts: Vec::new(),
+                max_concurrent_changes: None,
+                max_per_host: None,
+                anti_affinity: vec![],
             },
             provider_config: vec!["foobar".to_string()],
         };
@@ -501,6 +619,10 @@ mod test {
                     weight: Some(100),
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let spreadscaler = ProviderSpreadScaler::new(
@@ -612,6 +734,10 @@ mod test {
                     weight: Some(2),
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let spreadscaler = ProviderSpreadScaler::new(
@@ -921,6 +1047,10 @@ mod test {
                     weight: Some(100),
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let spreadscaler = ProviderSpreadScaler::new(
@@ -1058,6 +1188,10 @@ mod test {
                     weight: Some(100),
                 },
             ],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let spreadscaler = ProviderSpreadScaler::new(
@@ -1123,6 +1257,10 @@ mod test {
                 requirements: BTreeMap::from_iter([("cloud".to_string(), "fake".to_string())]),
                 weight: Some(100),
             }],
+            hosts: Vec::new(),
+            max_concurrent_changes: None,
+            max_per_host: None,
+            anti_affinity: vec![],
         };
 
         let spreadscaler = ProviderSpreadScaler::new(
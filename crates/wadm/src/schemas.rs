@@ -0,0 +1,200 @@
+//! JSON Schemas for the commands and events wadm sends and receives. These are published when wadm
+//! starts so integrations that produce or consume wadm messages can validate payloads against the
+//! version of wadm that is actually running. The schemas of the manifest and the API types are
+//! generated the same way, so clients in other languages and editors can be generated from them
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use async_nats::jetstream::kv::Store;
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
+use serde::Serialize;
+use wadm_types::{api::*, Manifest};
+
+use crate::commands::Command;
+use crate::events::*;
+
+/// The key the command schemas are stored under in the schema bucket
+pub const COMMANDS_SCHEMA_KEY: &str = "commands";
+/// The key the event schemas are stored under in the schema bucket
+pub const EVENTS_SCHEMA_KEY: &str = "events";
+
+/// The schema of the commands wadm publishes to its command stream
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandSchemas {
+    /// The version of wadm the schema was generated from
+    pub version: String,
+    /// The schema of the body of a published command. The schema version and expiry are sent in
+    /// headers
+    pub schema: RootSchema,
+}
+
+/// The schemas of the data of every event wadm understands
+#[derive(Debug, Clone, Serialize)]
+pub struct EventSchemas {
+    /// The version of wadm the schemas were generated from
+    pub version: String,
+    /// The schema of the event data, keyed by the cloud event type
+    pub schemas: BTreeMap<String, RootSchema>,
+}
+
+/// The schemas of the manifest and of every request and response of the wadm API
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiSchemas {
+    /// The version of wadm the schemas were generated from
+    pub version: String,
+    /// The schema of an application manifest
+    pub manifest: RootSchema,
+    /// The schemas of the API types, keyed by type name
+    pub schemas: BTreeMap<String, RootSchema>,
+}
+
+/// Returns the schema of the commands published by this version of wadm
+pub fn command_schemas() -> CommandSchemas {
+    CommandSchemas {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        schema: schema_for!(Command),
+    }
+}
+
+/// Returns the schemas of the events handled by this version of wadm
+pub fn event_schemas() -> EventSchemas {
+    fn insert<T: EventType + JsonSchema>(schemas: &mut BTreeMap<String, RootSchema>) {
+        schemas.insert(T::TYPE.to_owned(), schema_for!(T));
+    }
+
+    let mut schemas = BTreeMap::new();
+    insert::<ComponentScaled>(&mut schemas);
+    insert::<ComponentScaleFailed>(&mut schemas);
+    insert::<ProviderStarted>(&mut schemas);
+    insert::<ProviderStartFailed>(&mut schemas);
+    insert::<ProviderStopped>(&mut schemas);
+    insert::<ProviderHealthCheckPassed>(&mut schemas);
+    insert::<ProviderHealthCheckFailed>(&mut schemas);
+    insert::<ProviderHealthCheckStatus>(&mut schemas);
+    insert::<LinkdefSet>(&mut schemas);
+    insert::<LinkdefSetFailed>(&mut schemas);
+    insert::<LinkdefDeleted>(&mut schemas);
+    insert::<ConfigSet>(&mut schemas);
+    insert::<ConfigDeleted>(&mut schemas);
+    insert::<HostStarted>(&mut schemas);
+    insert::<HostStopped>(&mut schemas);
+    insert::<HostHeartbeat>(&mut schemas);
+    insert::<HostLabelsChanged>(&mut schemas);
+    insert::<ManifestPublished>(&mut schemas);
+    insert::<ManifestUnpublished>(&mut schemas);
+    insert::<ManifestPaused>(&mut schemas);
+    insert::<ManifestResumed>(&mut schemas);
+    EventSchemas {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        schemas,
+    }
+}
+
+/// Returns the schemas of the manifest and the API types of this version of wadm
+pub fn api_schemas() -> ApiSchemas {
+    fn insert<T: JsonSchema>(schemas: &mut BTreeMap<String, RootSchema>) {
+        schemas.insert(T::schema_name(), schema_for!(T));
+    }
+
+    let mut schemas = BTreeMap::new();
+    insert::<GetModelRequest>(&mut schemas);
+    insert::<GetModelResponse>(&mut schemas);
+    insert::<ListModelsResponse>(&mut schemas);
+    insert::<PutModelResponse>(&mut schemas);
+    insert::<LintModelResponse>(&mut schemas);
+    insert::<VersionResponse>(&mut schemas);
+    insert::<DeleteModelRequest>(&mut schemas);
+    insert::<DeleteModelResponse>(&mut schemas);
+    insert::<DeployModelRequest>(&mut schemas);
+    insert::<DeployModelResponse>(&mut schemas);
+    insert::<UndeployModelRequest>(&mut schemas);
+    insert::<StatusRequest>(&mut schemas);
+    insert::<StatusResponse>(&mut schemas);
+    insert::<Status>(&mut schemas);
+    insert::<ExportModelsResponse>(&mut schemas);
+    insert::<ImportModelsRequest>(&mut schemas);
+    insert::<ImportModelsResponse>(&mut schemas);
+    insert::<TopologyRequest>(&mut schemas);
+    insert::<TopologyResponse>(&mut schemas);
+    insert::<PlacementRequest>(&mut schemas);
+    insert::<PlacementResponse>(&mut schemas);
+    insert::<DeadLetterListResponse>(&mut schemas);
+    insert::<DeadLetterReplayRequest>(&mut schemas);
+    insert::<DeadLetterReplayResponse>(&mut schemas);
+    insert::<AuditQueryRequest>(&mut schemas);
+    insert::<AuditQueryResponse>(&mut schemas);
+    insert::<ShardStatusResponse>(&mut schemas);
+    insert::<ReloadConfigResponse>(&mut schemas);
+    insert::<CordonHostRequest>(&mut schemas);
+    insert::<CordonHostResponse>(&mut schemas);
+    insert::<AdminInfoResponse>(&mut schemas);
+    insert::<AdminLatticesResponse>(&mut schemas);
+    insert::<AdminConsumersResponse>(&mut schemas);
+    insert::<AdminScalersResponse>(&mut schemas);
+    insert::<AdminPermitsResponse>(&mut schemas);
+    ApiSchemas {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        manifest: schema_for!(Manifest),
+        schemas,
+    }
+}
+
+/// Writes the command and event schemas to the given bucket, replacing the schemas of any version
+/// of wadm that was running before
+pub async fn publish_schemas(store: &Store) -> Result<()> {
+    store
+        .put(
+            COMMANDS_SCHEMA_KEY,
+            serde_json::to_vec(&command_schemas())?.into(),
+        )
+        .await
+        .context("Unable to publish command schemas")?;
+    store
+        .put(
+            EVENTS_SCHEMA_KEY,
+            serde_json::to_vec(&event_schemas())?.into(),
+        )
+        .await
+        .context("Unable to publish event schemas")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn generates_schemas_for_commands_and_events() {
+        let commands = serde_json::to_value(command_schemas().schema).unwrap();
+        let definitions = commands["definitions"].as_object().unwrap();
+        for command in ["ScaleComponent", "StartProvider", "PutLink", "DeleteConfig"] {
+            assert!(
+                definitions.contains_key(command),
+                "Command schema should define {command}"
+            );
+        }
+
+        let events = event_schemas();
+        let heartbeat = serde_json::to_value(&events.schemas[HostHeartbeat::TYPE]).unwrap();
+        assert_eq!(
+            heartbeat["properties"]["version"]["type"], "string",
+            "Versions should be described as the string they are serialized to"
+        );
+        let link = serde_json::to_value(&events.schemas[LinkdefSetFailed::TYPE]).unwrap();
+        assert!(
+            link["properties"].get("error").is_some(),
+            "Fields next to flattened foreign types should be kept"
+        );
+
+        let api = api_schemas();
+        let manifest = serde_json::to_value(&api.manifest).unwrap();
+        assert!(manifest["properties"].get("spec").is_some());
+        let status = serde_json::to_value(&api.schemas["StatusResponse"]).unwrap();
+        assert!(
+            status["definitions"].get("ComponentStatus").is_some(),
+            "Responses should define the types they contain"
+        );
+        assert!(api.schemas.contains_key("DeployModelRequest"));
+    }
+}
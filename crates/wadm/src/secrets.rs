@@ -0,0 +1,260 @@
+//! Resolution of the secret references in manifest configuration. Manifests only store references
+//! to secrets, like `{backend: vault, key: apps/database, field: password}`. When wadm puts the
+//! configuration they are part of, each reference is resolved from the backend with that name, so
+//! the plaintext values never end up in the stored manifest.
+//!
+//! Backends are pluggable through the [`SecretBackend`] trait. This module has backends for a NATS
+//! KV bucket, environment variables and files in a directory
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use async_nats::jetstream::kv::Store;
+use async_trait::async_trait;
+use wadm_types::SecretRef;
+
+/// The default prefix of the environment variables the [`EnvBackend`] reads secrets from
+pub const DEFAULT_ENV_PREFIX: &str = "WADM_SECRET_";
+
+/// Anything secrets can be fetched from
+#[async_trait]
+pub trait SecretBackend {
+    /// Returns the value of the secret with the given key, or `None` if it doesn't exist
+    async fn get_secret(&self, key: &str) -> Result<Option<String>>;
+}
+
+/// Resolves secret references using the backends registered by name
+#[derive(Clone, Default)]
+pub struct SecretResolver {
+    backends: HashMap<String, Arc<dyn SecretBackend + Send + Sync>>,
+}
+
+impl std::fmt::Debug for SecretResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretResolver")
+            .field("backends", &self.backends.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl SecretResolver {
+    /// Creates a resolver without any backends
+    pub fn new() -> SecretResolver {
+        SecretResolver::default()
+    }
+
+    /// Registers a backend that secret references can use by the given name
+    pub fn with_backend(
+        mut self,
+        name: impl Into<String>,
+        backend: impl SecretBackend + Send + Sync + 'static,
+    ) -> SecretResolver {
+        self.backends.insert(name.into(), Arc::new(backend));
+        self
+    }
+
+    /// Returns whether any backends are registered
+    pub fn is_empty(&self) -> bool {
+        self.backends.is_empty()
+    }
+
+    /// Resolves a single secret reference. If the reference has a field, the secret is parsed as a
+    /// JSON object and the value of that field is returned
+    pub async fn resolve(&self, secret: &SecretRef) -> Result<String> {
+        let backend = self
+            .backends
+            .get(&secret.backend)
+            .with_context(|| format!("Secret backend '{}' is not configured", secret.backend))?;
+        let value = backend
+            .get_secret(&secret.key)
+            .await
+            .with_context(|| {
+                format!(
+                    "Unable to fetch secret {} from backend '{}'",
+                    secret.key, secret.backend
+                )
+            })?
+            .with_context(|| {
+                format!(
+                    "Secret {} doesn't exist in backend '{}'",
+                    secret.key, secret.backend
+                )
+            })?;
+        let Some(field) = secret.field.as_deref() else {
+            return Ok(value);
+        };
+        // The error isn't included so the secret isn't printed
+        let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&value)
+            .ok()
+            .with_context(|| format!("Secret {} is not a JSON object", secret.key))?;
+        match object.get(field) {
+            Some(serde_json::Value::String(s)) => Ok(s.to_owned()),
+            Some(other) => Ok(other.to_string()),
+            None => anyhow::bail!("Secret {} doesn't have field '{field}'", secret.key),
+        }
+    }
+
+    /// Resolves all of the given secret references, returning the resolved value for each key
+    pub async fn resolve_all(
+        &self,
+        secrets: &HashMap<String, SecretRef>,
+    ) -> Result<HashMap<String, String>> {
+        let mut resolved = HashMap::with_capacity(secrets.len());
+        for (key, secret) in secrets {
+            resolved.insert(key.to_owned(), self.resolve(secret).await?);
+        }
+        Ok(resolved)
+    }
+}
+
+/// A backend that reads secrets from the keys of a NATS KV bucket
+pub struct KvBackend {
+    store: Store,
+}
+
+impl KvBackend {
+    pub fn new(store: Store) -> KvBackend {
+        KvBackend { store }
+    }
+}
+
+#[async_trait]
+impl SecretBackend for KvBackend {
+    async fn get_secret(&self, key: &str) -> Result<Option<String>> {
+        self.store
+            .get(key)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?
+            .map(|value| String::from_utf8(value.to_vec()).context("Secret is not valid UTF-8"))
+            .transpose()
+    }
+}
+
+/// A backend that reads secrets from environment variables. Only variables with the configured
+/// prefix can be read, so manifests can't read the rest of wadm's environment
+pub struct EnvBackend {
+    prefix: String,
+}
+
+impl EnvBackend {
+    /// Creates a backend where the secret with key `db_password` is read from the variable
+    /// `{prefix}DB_PASSWORD`
+    pub fn new(prefix: impl Into<String>) -> EnvBackend {
+        EnvBackend {
+            prefix: prefix.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl SecretBackend for EnvBackend {
+    async fn get_secret(&self, key: &str) -> Result<Option<String>> {
+        let name = format!("{}{}", self.prefix, key.to_uppercase().replace('-', "_"));
+        match std::env::var(&name) {
+            Ok(value) => Ok(Some(value)),
+            Err(std::env::VarError::NotPresent) => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Unable to read environment variable {name}")),
+        }
+    }
+}
+
+/// A backend that reads secrets from files in a directory, like the ones secrets are mounted in
+/// by Kubernetes. Trailing newlines are trimmed from the files
+pub struct FileBackend {
+    dir: PathBuf,
+}
+
+impl FileBackend {
+    pub fn new(dir: impl Into<PathBuf>) -> FileBackend {
+        FileBackend { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl SecretBackend for FileBackend {
+    async fn get_secret(&self, key: &str) -> Result<Option<String>> {
+        // Keys can name files in subdirectories but can't leave the secrets directory
+        if !Path::new(key)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            anyhow::bail!("Secret key {key} must be a relative path within the secrets directory");
+        }
+        match tokio::fs::read_to_string(self.dir.join(key)).await {
+            Ok(value) => Ok(Some(value.trim_end_matches(['\r', '\n']).to_owned())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Unable to read secret file {key}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct StaticBackend(HashMap<String, String>);
+
+    #[async_trait]
+    impl SecretBackend for StaticBackend {
+        async fn get_secret(&self, key: &str) -> Result<Option<String>> {
+            Ok(self.0.get(key).cloned())
+        }
+    }
+
+    #[tokio::test]
+    async fn resolves_secret_refs() {
+        let dir = std::env::temp_dir().join(format!("wadm-secrets-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(dir.join("token"), "file-token\n")
+            .await
+            .unwrap();
+        let resolver = SecretResolver::new()
+            .with_backend(
+                "static",
+                StaticBackend(HashMap::from([(
+                    "apps/database".to_string(),
+                    r#"{"password": "hunter2", "port": 5432}"#.to_string(),
+                )])),
+            )
+            .with_backend("file", FileBackend::new(&dir));
+        let secret = |backend: &str, key: &str, field: Option<&str>| SecretRef {
+            backend: backend.to_string(),
+            key: key.to_string(),
+            field: field.map(ToOwned::to_owned),
+        };
+
+        let resolved = resolver
+            .resolve_all(&HashMap::from([
+                (
+                    "password".to_string(),
+                    secret("static", "apps/database", Some("password")),
+                ),
+                (
+                    "port".to_string(),
+                    secret("static", "apps/database", Some("port")),
+                ),
+                ("token".to_string(), secret("file", "token", None)),
+            ]))
+            .await
+            .unwrap();
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+        assert_eq!(resolved["password"], "hunter2");
+        assert_eq!(resolved["port"], "5432");
+        assert_eq!(resolved["token"], "file-token");
+
+        assert!(resolver
+            .resolve(&secret("vault", "apps/database", None))
+            .await
+            .is_err());
+        assert!(resolver
+            .resolve(&secret("static", "apps/database", Some("user")))
+            .await
+            .is_err());
+        assert!(resolver
+            .resolve(&secret("file", "../etc/passwd", None))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,409 @@
+//! Authentication and authorization for the wadm API.
+//!
+//! Every request on the NATS API is passed to an [`Authorizer`] before it is handled, so operators
+//! can restrict who can act on which lattice. Token based authentication is used when exposing the
+//! API over HTTP. Tokens are either static tokens configured by an operator or HS256 signed JWTs
+//! from a trusted issuer. Each token is scoped to the lattices it is allowed to act on
+
+use std::collections::{BTreeSet, HashMap};
+
+use async_nats::HeaderMap;
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use jwt::{RegisteredClaims, VerifyWithKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// The scheme expected at the start of an `Authorization` header value
+const BEARER_PREFIX: &str = "Bearer ";
+/// The lattice scope that allows access to all lattices
+pub const ALL_LATTICES: &str = "*";
+
+/// An error returned when a request can't be authenticated or isn't allowed to access a lattice
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AuthError {
+    /// No token was given
+    #[error("No API token was provided")]
+    MissingToken,
+    /// The token wasn't a known static token or a valid JWT
+    #[error("Invalid API token: {0}")]
+    InvalidToken(String),
+    /// The token is valid but isn't scoped to the requested lattice
+    #[error("Token for {subject} does not have access to lattice {lattice_id}")]
+    Forbidden { subject: String, lattice_id: String },
+}
+
+/// The lattices an authenticated token is allowed to act on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LatticeScope {
+    /// The token can act on any lattice
+    All,
+    /// The token can only act on the given lattices
+    Lattices(BTreeSet<String>),
+}
+
+impl LatticeScope {
+    /// Returns whether this scope allows access to the given lattice
+    pub fn allows(&self, lattice_id: &str) -> bool {
+        match self {
+            LatticeScope::All => true,
+            LatticeScope::Lattices(lattices) => lattices.contains(lattice_id),
+        }
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for LatticeScope {
+    /// Builds a scope from a list of lattice IDs. A [`ALL_LATTICES`] entry allows all lattices
+    fn from_iter<T: IntoIterator<Item = S>>(iter: T) -> Self {
+        let mut lattices = BTreeSet::new();
+        for lattice in iter {
+            let lattice = lattice.as_ref();
+            if lattice == ALL_LATTICES {
+                return LatticeScope::All;
+            }
+            lattices.insert(lattice.to_owned());
+        }
+        LatticeScope::Lattices(lattices)
+    }
+}
+
+/// An authenticated caller
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    /// A name for the caller, used for logging. This is the name given to a static token or the
+    /// subject of a JWT
+    pub subject: String,
+    /// The lattices the caller can act on
+    pub scope: LatticeScope,
+}
+
+/// The claims wadm reads from a JWT
+#[derive(Debug, Deserialize)]
+struct TokenClaims {
+    #[serde(flatten)]
+    registered: RegisteredClaims,
+    /// The lattices the token is scoped to
+    #[serde(default)]
+    lattices: Vec<String>,
+}
+
+/// Validates HS256 signed JWTs from a single issuer
+#[derive(Clone)]
+struct JwtValidator {
+    key: Hmac<Sha256>,
+    issuer: String,
+}
+
+impl JwtValidator {
+    fn validate(&self, token: &str) -> Result<Principal, AuthError> {
+        let claims: TokenClaims = token
+            .verify_with_key(&self.key)
+            .map_err(|e| AuthError::InvalidToken(e.to_string()))?;
+        if claims.registered.issuer.as_deref() != Some(self.issuer.as_str()) {
+            return Err(AuthError::InvalidToken("untrusted issuer".to_string()));
+        }
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        if claims.registered.expiration.is_some_and(|exp| exp <= now) {
+            return Err(AuthError::InvalidToken("token has expired".to_string()));
+        }
+        if claims.registered.not_before.is_some_and(|nbf| nbf > now) {
+            return Err(AuthError::InvalidToken(
+                "token is not valid yet".to_string(),
+            ));
+        }
+        Ok(Principal {
+            subject: claims.registered.subject.unwrap_or_default(),
+            scope: claims.lattices.iter().collect(),
+        })
+    }
+}
+
+/// Authenticates API tokens and checks that they are allowed to act on a lattice
+#[derive(Clone, Default)]
+pub struct TokenAuthenticator {
+    /// Static tokens, keyed by the hex encoded sha256 digest of the token so that lookups don't
+    /// compare the raw token
+    static_tokens: HashMap<String, Principal>,
+    jwt: Option<JwtValidator>,
+}
+
+impl TokenAuthenticator {
+    /// Creates a new authenticator that doesn't accept any tokens
+    pub fn new() -> TokenAuthenticator {
+        TokenAuthenticator::default()
+    }
+
+    /// Adds a static token with the given name and lattice scope
+    pub fn with_static_token(
+        mut self,
+        token: &str,
+        subject: impl Into<String>,
+        scope: LatticeScope,
+    ) -> TokenAuthenticator {
+        self.static_tokens.insert(
+            token_digest(token),
+            Principal {
+                subject: subject.into(),
+                scope,
+            },
+        );
+        self
+    }
+
+    /// Accepts HS256 signed JWTs from the given issuer. The lattices a JWT can act on are read
+    /// from its `lattices` claim
+    pub fn with_jwt_issuer(
+        mut self,
+        secret: &[u8],
+        issuer: impl Into<String>,
+    ) -> anyhow::Result<TokenAuthenticator> {
+        self.jwt = Some(JwtValidator {
+            key: Hmac::new_from_slice(secret)
+                .map_err(|e| anyhow::anyhow!("Invalid JWT secret: {e}"))?,
+            issuer: issuer.into(),
+        });
+        Ok(self)
+    }
+
+    /// Authenticates the given `Authorization` header value, which should be a bearer token
+    pub fn authenticate(&self, authorization: Option<&str>) -> Result<Principal, AuthError> {
+        let token = authorization
+            .map(|value| value.strip_prefix(BEARER_PREFIX).unwrap_or(value).trim())
+            .filter(|token| !token.is_empty())
+            .ok_or(AuthError::MissingToken)?;
+        if let Some(principal) = self.static_tokens.get(&token_digest(token)) {
+            return Ok(principal.clone());
+        }
+        match self.jwt.as_ref() {
+            Some(validator) => validator.validate(token),
+            None => Err(AuthError::InvalidToken("unknown token".to_string())),
+        }
+    }
+
+    /// Authenticates the given `Authorization` header value and checks that it is allowed to act
+    /// on the given lattice
+    pub fn authorize(
+        &self,
+        authorization: Option<&str>,
+        lattice_id: &str,
+    ) -> Result<Principal, AuthError> {
+        let principal = self.authenticate(authorization)?;
+        if principal.scope.allows(lattice_id) {
+            Ok(principal)
+        } else {
+            Err(AuthError::Forbidden {
+                subject: principal.subject,
+                lattice_id: lattice_id.to_owned(),
+            })
+        }
+    }
+}
+
+/// An API request that is about to be handled
+#[derive(Debug, Clone, Copy)]
+pub struct ApiRequest<'a> {
+    /// The NATS subject the request was sent on
+    pub subject: &'a str,
+    /// The account the request was scoped to when wadm is running in multitenant mode
+    pub account_id: Option<&'a str>,
+    pub lattice_id: &'a str,
+    /// The category of the operation, like `model` or `host`
+    pub category: &'a str,
+    /// The operation, like `put` or `deploy`
+    pub operation: &'a str,
+    /// The name of the application (or host, for host operations) the request acts on, if any
+    pub name: Option<&'a str>,
+    /// Whether the operation only reads data
+    pub read_only: bool,
+    pub headers: Option<&'a HeaderMap>,
+}
+
+/// Decides whether API requests are allowed. Admin requests, which aren't scoped to a lattice, are
+/// not passed to the authorizer
+#[async_trait]
+pub trait Authorizer {
+    /// Returns an error if the request isn't allowed
+    async fn authorize(&self, request: &ApiRequest<'_>) -> Result<(), AuthError>;
+}
+
+/// An [`Authorizer`] that allows every request. This is what wadm uses when no authorizer is set
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllowAll;
+
+#[async_trait]
+impl Authorizer for AllowAll {
+    async fn authorize(&self, _request: &ApiRequest<'_>) -> Result<(), AuthError> {
+        Ok(())
+    }
+}
+
+/// An [`Authorizer`] that scopes NATS accounts to lattices. The account of a request is the
+/// account it was scoped to in multitenant mode, or otherwise the account the NATS server reports
+/// in the request info header. Requests from accounts that aren't configured are denied
+#[derive(Debug, Clone, Default)]
+pub struct AccountAuthorizer {
+    accounts: HashMap<String, LatticeScope>,
+}
+
+impl AccountAuthorizer {
+    /// Creates a new authorizer that denies every request
+    pub fn new() -> AccountAuthorizer {
+        AccountAuthorizer::default()
+    }
+
+    /// Allows the given account to act on the lattices in the scope
+    pub fn with_account(
+        mut self,
+        account: impl Into<String>,
+        scope: LatticeScope,
+    ) -> AccountAuthorizer {
+        self.accounts.insert(account.into(), scope);
+        self
+    }
+}
+
+#[async_trait]
+impl Authorizer for AccountAuthorizer {
+    async fn authorize(&self, request: &ApiRequest<'_>) -> Result<(), AuthError> {
+        let account = request
+            .account_id
+            .map(ToOwned::to_owned)
+            .or_else(|| crate::audit::request_account(request.headers))
+            .unwrap_or_else(|| "unknown account".to_string());
+        match self.accounts.get(&account) {
+            Some(scope) if scope.allows(request.lattice_id) => Ok(()),
+            _ => Err(AuthError::Forbidden {
+                subject: account,
+                lattice_id: request.lattice_id.to_owned(),
+            }),
+        }
+    }
+}
+
+fn token_digest(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use jwt::SignWithKey;
+    use serde_json::json;
+
+    use super::*;
+
+    const SECRET: &[u8] = b"super-secret";
+
+    fn sign(claims: serde_json::Value) -> String {
+        let key: Hmac<Sha256> = Hmac::new_from_slice(SECRET).unwrap();
+        claims.sign_with_key(&key).unwrap()
+    }
+
+    #[test]
+    fn can_authorize_tokens() {
+        let auth = TokenAuthenticator::new()
+            .with_static_token("admin-token", "admin", LatticeScope::All)
+            .with_static_token("dev-token", "dev", ["dev"].into_iter().collect())
+            .with_jwt_issuer(SECRET, "https://issuer.example.com")
+            .unwrap();
+
+        assert_eq!(auth.authenticate(None), Err(AuthError::MissingToken));
+        assert!(matches!(
+            auth.authenticate(Some("Bearer nope")),
+            Err(AuthError::InvalidToken(_))
+        ));
+
+        let admin = auth
+            .authorize(Some("Bearer admin-token"), "prod")
+            .expect("Admin token should have access to all lattices");
+        assert_eq!(admin.subject, "admin");
+
+        assert!(auth.authorize(Some("Bearer dev-token"), "dev").is_ok());
+        assert_eq!(
+            auth.authorize(Some("Bearer dev-token"), "prod"),
+            Err(AuthError::Forbidden {
+                subject: "dev".to_string(),
+                lattice_id: "prod".to_string()
+            })
+        );
+
+        let exp = chrono::Utc::now().timestamp() + 60;
+        let token = sign(json!({
+            "iss": "https://issuer.example.com",
+            "sub": "ci",
+            "exp": exp,
+            "lattices": ["staging"],
+        }));
+        let principal = auth
+            .authorize(Some(&format!("Bearer {token}")), "staging")
+            .expect("Valid JWT should be authorized");
+        assert_eq!(principal.subject, "ci");
+        assert!(matches!(
+            auth.authorize(Some(&format!("Bearer {token}")), "prod"),
+            Err(AuthError::Forbidden { .. })
+        ));
+
+        let wrong_issuer = sign(json!({"iss": "someone-else", "lattices": ["*"]}));
+        assert!(matches!(
+            auth.authenticate(Some(&wrong_issuer)),
+            Err(AuthError::InvalidToken(_))
+        ));
+
+        let expired = sign(json!({
+            "iss": "https://issuer.example.com",
+            "exp": exp - 120,
+            "lattices": ["*"],
+        }));
+        assert!(matches!(
+            auth.authenticate(Some(&expired)),
+            Err(AuthError::InvalidToken(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn can_scope_accounts_to_lattices() {
+        let authz = AccountAuthorizer::new()
+            .with_account("ACME", ["prod", "staging"].into_iter().collect())
+            .with_account("OPS", LatticeScope::All);
+        let request = |account_id, lattice_id, headers| ApiRequest {
+            subject: "wadm.api.prod.model.deploy.echo",
+            account_id,
+            lattice_id,
+            category: "model",
+            operation: "deploy",
+            name: Some("echo"),
+            read_only: false,
+            headers,
+        };
+
+        assert!(authz
+            .authorize(&request(Some("ACME"), "prod", None))
+            .await
+            .is_ok());
+        assert_eq!(
+            authz.authorize(&request(Some("ACME"), "dev", None)).await,
+            Err(AuthError::Forbidden {
+                subject: "ACME".to_string(),
+                lattice_id: "dev".to_string()
+            })
+        );
+        assert!(authz.authorize(&request(None, "prod", None)).await.is_err());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            crate::audit::REQUEST_INFO_HEADER,
+            r#"{"acc":"OPS","user":"alice"}"#,
+        );
+        assert!(authz
+            .authorize(&request(None, "dev", Some(&headers)))
+            .await
+            .is_ok());
+        assert!(AllowAll
+            .authorize(&request(None, "dev", None))
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,243 @@
+//! Contains helpers for exporting every model in a lattice as a single bundle and importing a
+//! bundle into a lattice, which is how models are promoted between environments and backed up
+
+use wadm_types::{
+    api::{BundledModel, ImportConflictPolicy},
+    Manifest, VERSION_ANNOTATION_KEY,
+};
+
+use crate::model::StoredManifest;
+
+/// What importing a model did
+#[derive(Debug)]
+pub(crate) enum ImportOutcome {
+    /// The model didn't exist, so it was created
+    Created(StoredManifest),
+    /// The existing model was changed
+    Updated(StoredManifest),
+    /// The existing model was left as it is
+    Skipped,
+}
+
+/// Returns the given model as it is kept in a bundle
+pub(crate) fn bundle_model(model: &StoredManifest) -> BundledModel {
+    BundledModel {
+        name: model.name().to_owned(),
+        versions: model
+            .all_versions()
+            .into_iter()
+            .filter_map(|version| model.get_version(version))
+            .cloned()
+            .collect(),
+        deployed_version: model.deployed_version().map(ToOwned::to_owned),
+    }
+}
+
+/// Imports a bundled model on top of the existing model with the same name, if there is one, using
+/// the given policy to resolve the conflict. Returns an error if the bundled model is invalid
+pub(crate) fn import_model(
+    existing: Option<StoredManifest>,
+    model: BundledModel,
+    policy: ImportConflictPolicy,
+) -> Result<ImportOutcome, String> {
+    if model.versions.is_empty() {
+        return Err(format!("Application {} has no versions", model.name));
+    }
+    if let Some(manifest) = model
+        .versions
+        .iter()
+        .find(|manifest| manifest.metadata.name != model.name)
+    {
+        return Err(format!(
+            "Version {} is named {}, but it is bundled as {}",
+            manifest.version(),
+            manifest.metadata.name,
+            model.name
+        ));
+    }
+
+    let mut stored = match existing.filter(|existing| !existing.is_empty()) {
+        Some(stored) => stored,
+        None => {
+            let mut stored = StoredManifest::default();
+            for manifest in model.versions {
+                stored.add_version(manifest);
+            }
+            return Ok(ImportOutcome::Created(stored));
+        }
+    };
+    match policy {
+        ImportConflictPolicy::Skip => Ok(ImportOutcome::Skipped),
+        ImportConflictPolicy::Overwrite => {
+            stored.replace_versions(model.versions);
+            Ok(ImportOutcome::Updated(stored))
+        }
+        ImportConflictPolicy::VersionBump => {
+            let mut changed = false;
+            for mut manifest in model.versions {
+                if already_imported(&stored, &manifest) {
+                    continue;
+                }
+                if stored.get_version(manifest.version()).is_some() {
+                    let version = bumped_version(&stored, manifest.version());
+                    manifest
+                        .metadata
+                        .annotations
+                        .insert(VERSION_ANNOTATION_KEY.to_string(), version);
+                }
+                changed |= stored.add_version(manifest);
+            }
+            Ok(if changed {
+                ImportOutcome::Updated(stored)
+            } else {
+                ImportOutcome::Skipped
+            })
+        }
+    }
+}
+
+/// Returns whether the model has the given version with the same contents, either under its own
+/// version or one it was bumped to by an earlier import. This keeps importing the same bundle
+/// again from adding more versions
+fn already_imported(model: &StoredManifest, manifest: &Manifest) -> bool {
+    let version = manifest.version();
+    let bumped_prefix = format!("{version}-import.");
+    model
+        .all_versions()
+        .into_iter()
+        .filter(|v| *v == version || v.starts_with(&bumped_prefix))
+        .filter_map(|v| model.get_version(v))
+        .any(|stored| {
+            let mut stored = stored.clone();
+            stored
+                .metadata
+                .annotations
+                .insert(VERSION_ANNOTATION_KEY.to_string(), version.to_owned());
+            stored == *manifest
+        })
+}
+
+/// Returns the first version like `{version}-import.1` that doesn't exist in the model
+fn bumped_version(model: &StoredManifest, version: &str) -> String {
+    let mut n = 1;
+    loop {
+        let bumped = format!("{version}-import.{n}");
+        if model.get_version(&bumped).is_none() {
+            return bumped;
+        }
+        n += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wadm_types::{Metadata, Specification};
+
+    use super::*;
+
+    fn manifest(version: &str, description: &str) -> Manifest {
+        Manifest {
+            api_version: "core.oam.dev/v1beta1".to_string(),
+            kind: "Application".to_string(),
+            metadata: Metadata {
+                name: "echo".to_string(),
+                annotations: [
+                    (VERSION_ANNOTATION_KEY.to_string(), version.to_string()),
+                    ("description".to_string(), description.to_string()),
+                ]
+                .into(),
+                labels: Default::default(),
+            },
+            spec: Specification {
+                components: vec![],
+                policies: vec![],
+            },
+        }
+    }
+
+    fn stored(versions: &[&str], deployed: Option<&str>) -> StoredManifest {
+        let mut stored = StoredManifest::default();
+        for version in versions {
+            stored.add_version(manifest(version, "original"));
+        }
+        if let Some(version) = deployed {
+            stored.deploy(Some(version.to_string()));
+        }
+        stored
+    }
+
+    fn versions(model: &StoredManifest) -> Vec<&str> {
+        model
+            .all_versions()
+            .into_iter()
+            .map(|v| v.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn imports_bundles_with_each_conflict_policy() {
+        let exported = bundle_model(&stored(&["v1", "v2"], Some("v1")));
+        assert_eq!(exported.name, "echo");
+        assert_eq!(exported.deployed_version.as_deref(), Some("v1"));
+        assert_eq!(exported.versions.len(), 2);
+
+        let ImportOutcome::Created(created) =
+            import_model(None, exported, ImportConflictPolicy::Skip).unwrap()
+        else {
+            panic!("Models that don't exist should be created");
+        };
+        assert_eq!(versions(&created), vec!["v1", "v2"]);
+        assert_eq!(
+            created.deployed_version(),
+            None,
+            "Importing shouldn't deploy anything"
+        );
+
+        let bundled = BundledModel {
+            name: "echo".to_string(),
+            versions: vec![manifest("v1", "changed"), manifest("v3", "new")],
+            deployed_version: None,
+        };
+        let existing = || Some(stored(&["v1", "v2"], Some("v1")));
+        assert!(matches!(
+            import_model(existing(), bundled.clone(), ImportConflictPolicy::Skip).unwrap(),
+            ImportOutcome::Skipped
+        ));
+
+        let ImportOutcome::Updated(overwritten) =
+            import_model(existing(), bundled.clone(), ImportConflictPolicy::Overwrite).unwrap()
+        else {
+            panic!("Overwriting should update the model");
+        };
+        assert_eq!(versions(&overwritten), vec!["v1", "v3"]);
+        assert_eq!(
+            overwritten.get_deployed().unwrap().description(),
+            Some("original"),
+            "The deployed version should be kept as it is"
+        );
+
+        let ImportOutcome::Updated(bumped) = import_model(
+            existing(),
+            bundled.clone(),
+            ImportConflictPolicy::VersionBump,
+        )
+        .unwrap() else {
+            panic!("Bumping versions should update the model");
+        };
+        assert_eq!(versions(&bumped), vec!["v1", "v2", "v1-import.1", "v3"]);
+        assert!(
+            matches!(
+                import_model(Some(bumped), bundled, ImportConflictPolicy::VersionBump).unwrap(),
+                ImportOutcome::Skipped
+            ),
+            "Importing the same bundle again shouldn't add more versions"
+        );
+
+        let renamed = BundledModel {
+            name: "other".to_string(),
+            versions: vec![manifest("v1", "original")],
+            deployed_version: None,
+        };
+        assert!(import_model(None, renamed, ImportConflictPolicy::Skip).is_err());
+    }
+}
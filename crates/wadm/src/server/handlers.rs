@@ -1,38 +1,106 @@
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::anyhow;
-use async_nats::{jetstream::stream::Stream, Client, Message, Subject};
+use async_nats::{
+    jetstream::{
+        consumer::{pull::OrderedConfig, DeliverPolicy},
+        stream::Stream,
+    },
+    Client, HeaderMap, Message, Subject,
+};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
 use serde_json::json;
-use tracing::{debug, error, instrument, trace};
-use wadm_types::api::{ModelSummary, StatusInfo, StatusType};
-use wadm_types::validation::{is_valid_manifest_name, validate_manifest_version, ValidationOutput};
+use tracing::{debug, error, instrument, trace, warn};
+use wadm_types::api::{CommandAck, ModelSummary, ReconcileReport, StatusInfo, StatusType};
+use wadm_types::validation::{
+    is_valid_manifest_name, lint_manifest, validate_manifest_version, ValidationFailure,
+    ValidationFailureLevel, ValidationOutput,
+};
+use wadm_types::{aliases::AliasRegistry, ComponentProperties, LATEST_VERSION};
 use wadm_types::{
     api::{
-        DeleteModelRequest, DeleteModelResponse, DeleteResult, DeployModelRequest,
-        DeployModelResponse, DeployResult, GetModelRequest, GetModelResponse, GetResult,
-        ListModelsResponse, PutModelResponse, PutResult, Status, StatusResponse, StatusResult,
-        UndeployModelRequest, VersionInfo, VersionResponse,
+        AdminConsumersResponse, AdminInfoResponse, AdminLatticesResponse, AdminPermitsResponse,
+        AdminScalersResponse, AuditQueryRequest, AuditQueryResponse, AuditRecord, BundledModel,
+        CordonHostRequest, CordonHostResponse, DeadLetterListResponse, DeadLetterReplayRequest,
+        DeadLetterReplayResponse, DeadLetterSummary, DeleteModelRequest, DeleteModelResponse,
+        DeleteResult, DeployModelRequest, DeployModelResponse, DeployResult, ExportModelsResponse,
+        GetModelRequest, GetModelResponse, GetResult, ImportModelsRequest, ImportModelsResponse,
+        LintModelResponse, LintResult, ListModelsResponse, ModelBundle, PlacementRequest,
+        PlacementResponse, PutModelResponse, PutResult, ReloadConfigResponse, ShardStatusResponse,
+        Status, StatusRequest, StatusResponse, StatusResult, TopologyFormat, TopologyRequest,
+        TopologyResponse, UndeployModelRequest, VersionInfo, VersionResponse,
+        ADMIN_CONSUMERS_SUBJECT, ADMIN_INFO_SUBJECT, ADMIN_LATTICES_SUBJECT, ADMIN_PERMITS_SUBJECT,
+        ADMIN_SCALERS_SUBJECT, MODEL_BUNDLE_VERSION,
     },
     CapabilityProperties, Manifest, Properties,
 };
-use wadm_types::{ComponentProperties, LATEST_VERSION};
 
-use crate::{model::StoredManifest, publisher::Publisher};
+use crate::{
+    audit::{caller_identity, put_summary, AuditLog},
+    claims::ClaimsVerifier,
+    config::ConfigReloader,
+    cordon::HostCordoner,
+    introspection::Introspector,
+    model::{apply_overrides, StoredManifest},
+    oci::{pin_images, DigestResolver},
+    publisher::Publisher,
+    scaler::planner::CommandPlanner,
+    sharding::ShardMembership,
+    topology::TopologySource,
+    workers::{CommandAckStore, DeadLetterSource},
+};
+
+use super::{
+    bundle::{bundle_model, import_model, ImportOutcome},
+    parser::parse_manifest,
+    quota::ManifestQuotas,
+    rollback::RollbackWatcher,
+    storage::ModelStorage,
+    undeploy::{undeploy_stages, StagedUndeploy},
+    ManifestNotifier,
+};
 
-use super::{parser::parse_manifest, storage::ModelStorage, ManifestNotifier};
+/// The maximum size of the overrides that can be given when deploying a model. Overrides are meant
+/// for small operational tweaks, anything bigger should be a new version
+const MAX_OVERRIDES_SIZE: usize = 16 * 1024;
+/// The number of audit records returned when a query doesn't give a limit
+const DEFAULT_AUDIT_PAGE_SIZE: usize = 100;
+/// The most audit records returned by a single query
+const MAX_AUDIT_PAGE_SIZE: usize = 1000;
 
 pub(crate) struct Handler<P> {
     pub(crate) store: ModelStorage,
     pub(crate) client: Client,
     pub(crate) notifier: ManifestNotifier<P>,
     pub(crate) status_stream: Stream,
+    pub(crate) reconcile_reports: Option<Stream>,
+    pub(crate) command_acks: Option<CommandAckStore>,
+    pub(crate) planner: Option<Arc<dyn CommandPlanner + Send + Sync>>,
+    pub(crate) rollback: Option<RollbackWatcher<P>>,
+    pub(crate) topology: Option<Arc<dyn TopologySource + Send + Sync>>,
+    pub(crate) dead_letters: Option<Arc<dyn DeadLetterSource + Send + Sync>>,
+    pub(crate) cordoner: Option<Arc<dyn HostCordoner + Send + Sync>>,
+    pub(crate) undeploy_stage_delay: Duration,
+    pub(crate) shards: Option<ShardMembership>,
+    pub(crate) reloader: Option<Arc<dyn ConfigReloader + Send + Sync>>,
+    pub(crate) introspector: Option<Arc<dyn Introspector + Send + Sync>>,
+    pub(crate) aliases: Option<Arc<AliasRegistry>>,
+    pub(crate) digests: Option<Arc<dyn DigestResolver + Send + Sync>>,
+    pub(crate) audit: Option<Arc<dyn AuditLog + Send + Sync>>,
+    pub(crate) quotas: ManifestQuotas,
+    pub(crate) claims: Option<Arc<dyn ClaimsVerifier + Send + Sync>>,
+    /// Whether deploys that fail claims verification are allowed, with a warning
+    pub(crate) permissive_claims: bool,
 }
 
-impl<P: Publisher> Handler<P> {
+impl<P: Publisher + Clone + Send + Sync + 'static> Handler<P> {
     #[instrument(level = "debug", skip(self, msg))]
     pub async fn put_model(&self, msg: Message, account_id: Option<&str>, lattice_id: &str) {
         trace!("Parsing incoming manifest");
-        let manifest = match parse_manifest(msg.payload.into(), msg.headers.as_ref()) {
+        let mut manifest = match parse_manifest(msg.payload.into(), msg.headers.as_ref()) {
             Ok(m) => m,
             Err(e) => {
                 self.send_error(msg.reply, format!("Unable to parse manifest: {e:?}"))
@@ -41,6 +109,18 @@ impl<P: Publisher> Handler<P> {
             }
         };
 
+        // Aliases are expanded before anything else so the stored manifest is the full version
+        if let Some(aliases) = self.aliases.as_ref() {
+            if let Err(errors) = aliases.expand(&mut manifest) {
+                self.send_error(
+                    msg.reply,
+                    format!("Unable to expand capability aliases: {}", errors.join("\n")),
+                )
+                .await;
+                return;
+            }
+        }
+
         trace!(
             ?manifest,
             "Manifest is valid. Fetching current manifests from store"
@@ -92,12 +172,26 @@ impl<P: Publisher> Handler<P> {
             self.send_error(msg.reply, error_message.to_string()).await;
             return;
         }
+        if let Err(error_message) = self.notifier.check_routes(&manifest) {
+            self.send_error(msg.reply, error_message).await;
+            return;
+        }
+        if let Err(error_message) = self.quotas.check_replicas(&manifest) {
+            self.send_error(msg.reply, error_message).await;
+            return;
+        }
 
         let all_stored_manifests = self
             .store
             .list(account_id, lattice_id)
             .await
             .unwrap_or_default();
+        if current_manifests.is_empty() {
+            if let Err(error_message) = self.quotas.check_new_model(all_stored_manifests.len()) {
+                self.send_error(msg.reply, error_message).await;
+                return;
+            }
+        }
         let deployed_shared_apps: Vec<&Manifest> = all_stored_manifests
             .iter()
             // Only keep deployed, shared applications
@@ -114,7 +208,7 @@ impl<P: Publisher> Handler<P> {
         // - Undeploy the application with the shared component
         // - Deploy the new application looking for the shared component (error)
         let missing_shared_components = manifest.missing_shared_components(&deployed_shared_apps);
-        let message = if missing_shared_components.is_empty() {
+        let mut message = if missing_shared_components.is_empty() {
             format!(
                 "Successfully put manifest {} {}",
                 manifest_name,
@@ -130,6 +224,10 @@ impl<P: Publisher> Handler<P> {
         };
 
         let incoming_version = manifest.version().to_owned();
+        let summary = put_summary(
+            (!current_manifests.is_empty()).then(|| current_manifests.get_current()),
+            &manifest,
+        );
         if !current_manifests.add_version(manifest) {
             self.send_error(
                 msg.reply,
@@ -138,6 +236,22 @@ impl<P: Publisher> Handler<P> {
             .await;
             return;
         }
+        if let Some(max) = self.quotas.max_versions {
+            let pruned = current_manifests.prune_versions(max);
+            if current_manifests.count() > max {
+                self.send_error(
+                    msg.reply,
+                    format!("Application {manifest_name} already has the most versions allowed ({max}) and the oldest can't be removed because it is deployed. Deploy a newer version or delete old versions first"),
+                )
+                .await;
+                return;
+            }
+            if !pruned.is_empty() {
+                message.push_str(&format!(
+                    ". Removed the oldest versions {pruned:?} to keep at most {max} versions"
+                ));
+            }
+        }
 
         let resp = PutModelResponse {
             // If we successfully insert, the given manifest version will be the new current version
@@ -169,6 +283,15 @@ impl<P: Publisher> Handler<P> {
             return;
         }
 
+        self.record_audit(
+            msg.headers.as_ref(),
+            account_id,
+            lattice_id,
+            "put",
+            &manifest_name,
+            summary,
+        )
+        .await;
         trace!("Storage complete, sending reply");
         self.send_reply(
             msg.reply,
@@ -179,6 +302,69 @@ impl<P: Publisher> Handler<P> {
         .await
     }
 
+    /// Validates and lints the manifest in the message without storing it, replying with all of
+    /// the errors and warnings found
+    #[instrument(level = "debug", skip(self, msg))]
+    pub async fn lint_model(&self, msg: Message) {
+        let mut manifest = match parse_manifest(msg.payload.into(), msg.headers.as_ref()) {
+            Ok(m) => m,
+            Err(e) => {
+                self.send_error(msg.reply, format!("Unable to parse manifest: {e:?}"))
+                    .await;
+                return;
+            }
+        };
+        let error = |msg: String| ValidationFailure::new(ValidationFailureLevel::Error, msg);
+
+        let mut failures = Vec::new();
+        if let Some(aliases) = self.aliases.as_ref() {
+            if let Err(errors) = aliases.expand(&mut manifest) {
+                failures.extend(errors.into_iter().map(error));
+            }
+        }
+        failures.extend(
+            validate_manifest_version(manifest.version())
+                .errors()
+                .into_iter()
+                .cloned(),
+        );
+        if !is_valid_manifest_name(manifest.metadata.name.trim()) {
+            failures.push(error(format!(
+                "Manifest name {} contains invalid characters. Manifest names can only contain alphanumeric characters, dashes, and underscores.",
+                manifest.metadata.name.trim()
+            )));
+        }
+        match wadm_types::validation::validate_manifest(&manifest).await {
+            Ok(found) => failures.extend(found),
+            Err(e) => {
+                self.send_error(msg.reply, format!("Unable to validate manifest: {e:?}"))
+                    .await;
+                return;
+            }
+        }
+        if let Err(e) = self.notifier.check_routes(&manifest) {
+            failures.push(error(e));
+        }
+        failures.extend(lint_manifest(&manifest));
+
+        let resp = LintModelResponse {
+            result: if failures.valid() {
+                LintResult::Valid
+            } else {
+                LintResult::Invalid
+            },
+            message: format!(
+                "Found {} errors and {} warnings in manifest {}",
+                failures.errors().len(),
+                failures.warnings().len(),
+                manifest.metadata.name
+            ),
+            failures,
+        };
+        self.send_reply(msg.reply, serde_json::to_vec(&resp).unwrap_or_default())
+            .await
+    }
+
     #[instrument(level = "debug", skip(self, msg))]
     pub async fn get_model(
         &self,
@@ -343,6 +529,190 @@ impl<P: Publisher> Handler<P> {
             .await
     }
 
+    /// Replies with every model in the lattice, with all of their versions, as a single bundle
+    #[instrument(level = "debug", skip(self, msg))]
+    pub async fn export_models(&self, msg: Message, account_id: Option<&str>, lattice_id: &str) {
+        let stored_manifests = match self.store.list(account_id, lattice_id).await {
+            Ok(d) => d,
+            Err(e) => {
+                error!(error = %e, "Unable to fetch data");
+                self.send_error(msg.reply, "Internal storage error".to_string())
+                    .await;
+                return;
+            }
+        };
+        let mut models: Vec<BundledModel> = stored_manifests.iter().map(bundle_model).collect();
+        models.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let reply = ExportModelsResponse {
+            result: GetResult::Success,
+            message: format!(
+                "Exported {} applications from lattice {lattice_id}",
+                models.len()
+            ),
+            bundle: Some(ModelBundle {
+                version: MODEL_BUNDLE_VERSION,
+                lattice_id: lattice_id.to_owned(),
+                exported_at: Utc::now().to_rfc3339(),
+                models,
+            }),
+        };
+        self.send_reply(msg.reply, serde_json::to_vec(&reply).unwrap_or_default())
+            .await
+    }
+
+    /// Imports a bundle of models, using the conflict policy of the request for models that
+    /// already exist. Each model is validated like a put and imported on its own, so one invalid
+    /// model doesn't stop the others from being imported. Nothing is deployed
+    #[instrument(level = "info", skip(self, msg))]
+    pub async fn import_models(&self, msg: Message, account_id: Option<&str>, lattice_id: &str) {
+        let req: ImportModelsRequest = match serde_json::from_slice(&msg.payload) {
+            Ok(r) => r,
+            Err(e) => {
+                self.send_error(msg.reply, format!("Unable to parse import request: {e:?}"))
+                    .await;
+                return;
+            }
+        };
+        if req.bundle.version > MODEL_BUNDLE_VERSION {
+            self.send_error(
+                msg.reply,
+                format!(
+                    "Unsupported bundle version {}, this version of wadm can import bundles up to version {MODEL_BUNDLE_VERSION}",
+                    req.bundle.version
+                ),
+            )
+            .await;
+            return;
+        }
+        let mut model_count = match self.store.list(account_id, lattice_id).await {
+            Ok(models) => models.len(),
+            Err(e) => {
+                error!(error = %e, "Unable to fetch data");
+                self.send_error(msg.reply, "Internal storage error".to_string())
+                    .await;
+                return;
+            }
+        };
+
+        let mut reply = ImportModelsResponse {
+            result: GetResult::Success,
+            message: String::new(),
+            created: Vec::new(),
+            updated: Vec::new(),
+            skipped: Vec::new(),
+            failed: Default::default(),
+        };
+        let source_lattice = req.bundle.lattice_id;
+        for model in req.bundle.models {
+            let name = model.name.clone();
+            if let Err(e) = self.check_import(&model).await {
+                reply.failed.insert(name, e);
+                continue;
+            }
+            let (existing, current_revision) =
+                match self.store.get(account_id, lattice_id, &name).await {
+                    Ok(Some((stored, revision))) => (Some(stored), revision),
+                    Ok(None) => (None, 0),
+                    Err(e) => {
+                        error!(error = %e, %name, "Unable to fetch data from store");
+                        reply
+                            .failed
+                            .insert(name, "Internal storage error".to_string());
+                        continue;
+                    }
+                };
+            let (mut stored, created) = match import_model(existing, model, req.conflict_policy) {
+                Ok(ImportOutcome::Created(stored)) => {
+                    if let Err(e) = self.quotas.check_new_model(model_count) {
+                        reply.failed.insert(name, e);
+                        continue;
+                    }
+                    (stored, true)
+                }
+                Ok(ImportOutcome::Updated(stored)) => (stored, false),
+                Ok(ImportOutcome::Skipped) => {
+                    reply.skipped.push(name);
+                    continue;
+                }
+                Err(e) => {
+                    reply.failed.insert(name, e);
+                    continue;
+                }
+            };
+            if let Some(max) = self.quotas.max_versions {
+                stored.prune_versions(max);
+            }
+            let versions = stored.count();
+            if let Err(e) = self
+                .store
+                .set(account_id, lattice_id, stored, Some(current_revision))
+                .await
+            {
+                error!(error = %e, %name, "Unable to store imported model");
+                reply
+                    .failed
+                    .insert(name, "Internal storage error".to_string());
+                continue;
+            }
+            self.record_audit(
+                msg.headers.as_ref(),
+                account_id,
+                lattice_id,
+                "import",
+                &name,
+                format!(
+                    "Imported from lattice {source_lattice} with the {:?} conflict policy, {versions} versions stored",
+                    req.conflict_policy
+                ),
+            )
+            .await;
+            if created {
+                model_count += 1;
+                reply.created.push(name);
+            } else {
+                reply.updated.push(name);
+            }
+        }
+
+        if reply.created.is_empty() && reply.updated.is_empty() && !reply.failed.is_empty() {
+            reply.result = GetResult::Error;
+        }
+        reply.message = format!(
+            "Imported applications into lattice {lattice_id}: {} created, {} updated, {} skipped, {} failed",
+            reply.created.len(),
+            reply.updated.len(),
+            reply.skipped.len(),
+            reply.failed.len()
+        );
+        self.send_reply(msg.reply, serde_json::to_vec(&reply).unwrap_or_default())
+            .await
+    }
+
+    /// Validates every version of a model being imported the same way as a put
+    async fn check_import(&self, model: &BundledModel) -> Result<(), String> {
+        if !is_valid_manifest_name(&model.name) {
+            return Err(format!(
+                "Manifest name {} contains invalid characters. Manifest names can only contain alphanumeric characters, dashes, and underscores.",
+                model.name
+            ));
+        }
+        for manifest in model.versions.iter() {
+            if let Some(failure) = validate_manifest_version(manifest.version())
+                .errors()
+                .first()
+            {
+                return Err(failure.msg.clone());
+            }
+            validate_manifest(manifest)
+                .await
+                .map_err(|e| e.to_string())?;
+            self.notifier.check_routes(manifest)?;
+            self.quotas.check_replicas(manifest)?;
+        }
+        Ok(())
+    }
+
     // NOTE(thomastaylor312): This method differs from the wadm 0.3 docs as it doesn't include
     // timestamp (at least for now). However, this is guaranteed to return the list of versions
     // ordered by time of creation. When we document, we should change this to reflect that
@@ -365,6 +735,10 @@ impl<P: Publisher> Handler<P> {
                     .map(|v| {
                         let deployed = manifest.is_deployed(&v);
                         VersionInfo {
+                            overrides: deployed.then(|| manifest.overrides().cloned()).flatten(),
+                            pinned_images: deployed
+                                .then(|| manifest.pinned_images().cloned())
+                                .flatten(),
                             version: v,
                             deployed,
                         }
@@ -412,8 +786,13 @@ impl<P: Publisher> Handler<P> {
                 }
             }
         };
+        // Grab where notifications go before anything is deleted
+        let routes = match self.store.get(account_id, lattice_id, name).await {
+            Ok(Some((current, _))) => current.notification_routes(),
+            _ => Vec::new(),
+        };
         // TODO(#451): if shared and deployed, make sure that no other shared apps are using it
-        let reply_data = if let Some(version) = req.version {
+        let reply_data = if let Some(version) = req.version.clone() {
             match self.store.get(account_id, lattice_id, name).await {
                 Ok(Some((mut current, current_revision))) => {
                     let deleted = current.delete_version(&version);
@@ -517,7 +896,11 @@ impl<P: Publisher> Handler<P> {
         // ignored
         if reply_data.undeploy || matches!(reply_data.result, DeleteResult::Noop) {
             trace!("Sending undeploy notification");
-            if let Err(e) = self.notifier.undeployed(lattice_id, name).await {
+            if let Err(e) = self
+                .notifier
+                .undeployed(account_id, lattice_id, name, routes)
+                .await
+            {
                 error!(error = ?e, "Error when attempting to send undeploy notification during delete");
                 self.send_reply(
                     msg.reply,
@@ -535,6 +918,22 @@ impl<P: Publisher> Handler<P> {
             }
         }
 
+        if matches!(reply_data.result, DeleteResult::Deleted) {
+            let summary = match req.version.as_deref() {
+                Some(version) => format!("Deleted version {version}"),
+                None => "Deleted all versions".to_string(),
+            };
+            self.record_audit(
+                msg.headers.as_ref(),
+                account_id,
+                lattice_id,
+                "delete",
+                name,
+                summary,
+            )
+            .await;
+        }
+
         // NOTE: We control all the data getting sent in here, but we unwrap to default just in case
         self.send_reply(
             msg.reply,
@@ -552,7 +951,11 @@ impl<P: Publisher> Handler<P> {
         name: &str,
     ) {
         let req: DeployModelRequest = if msg.payload.is_empty() {
-            DeployModelRequest { version: None }
+            DeployModelRequest {
+                version: None,
+                dry_run: false,
+                overrides: None,
+            }
         } else {
             match serde_json::from_reader(std::io::Cursor::new(msg.payload)) {
                 Ok(r) => r,
@@ -582,6 +985,7 @@ impl<P: Publisher> Handler<P> {
                             message: format!("Application with the name {name} not found"),
                             name: name.to_string(),
                             version: req.version.clone(),
+                            commands: None,
                         })
                         .unwrap_or_default(),
                     )
@@ -624,6 +1028,7 @@ impl<P: Publisher> Handler<P> {
                             message: format!("Application with the name '{name}' does not have a version '{v}' to deploy"),
                             name: name.to_string(),
                             version: Some(v.to_string()),
+                            commands: None,
                         })
                         .unwrap_or_default(),
                     )
@@ -635,6 +1040,35 @@ impl<P: Publisher> Handler<P> {
             None => manifests.get_current(),
         };
 
+        // Apply any overrides for this deployment, so everything below validates what will
+        // actually be deployed
+        let overridden = match req.overrides.as_ref() {
+            Some(patch)
+                if serde_json::to_vec(patch).map_or(0, |p| p.len()) > MAX_OVERRIDES_SIZE =>
+            {
+                self.send_error(
+                    msg.reply,
+                    format!("Overrides can't be larger than {MAX_OVERRIDES_SIZE} bytes. Put a new version of the application instead"),
+                )
+                .await;
+                return;
+            }
+            Some(patch) => match apply_overrides(staged_model, patch) {
+                Ok(m) => Some(m),
+                Err(e) => {
+                    self.send_error(msg.reply, format!("Unable to apply overrides: {e}"))
+                        .await;
+                    return;
+                }
+            },
+            None => None,
+        };
+        let staged_model = overridden.as_ref().unwrap_or(staged_model);
+        if let Err(error_message) = self.quotas.check_replicas(staged_model) {
+            self.send_error(msg.reply, error_message).await;
+            return;
+        }
+
         // Retrieve all the existing identifiers of deployed components and providers, and check if the staged model has any duplicates
         let mut existing_ids: HashMap<String, String> = HashMap::new();
         for model_summary in stored_models.iter() {
@@ -724,6 +1158,56 @@ impl<P: Publisher> Handler<P> {
             return;
         }
 
+        let mut claims_warning = None;
+        if let Some(verifier) = self.claims.as_ref() {
+            let unclaimed = match verifier.verify(lattice_id, account_id, staged_model).await {
+                Ok(unclaimed) => unclaimed,
+                Err(e) if self.permissive_claims => {
+                    warn!(error = ?e, "Unable to verify component claims, deploying anyway");
+                    Vec::new()
+                }
+                Err(e) => {
+                    error!(error = ?e, "Unable to verify component claims");
+                    self.send_error(
+                        msg.reply,
+                        format!("Unable to verify component claims: {e}. This is likely a transient error, so please retry the request"),
+                    )
+                    .await;
+                    return;
+                }
+            };
+            if !unclaimed.is_empty() {
+                let message = format!(
+                    "Application has links its components don't have claims for: {}",
+                    unclaimed.join("; ")
+                );
+                if !self.permissive_claims {
+                    self.send_error(msg.reply, message).await;
+                    return;
+                }
+                warn!(
+                    ?unclaimed,
+                    "Deploying application with links its components don't have claims for"
+                );
+                claims_warning = Some(message);
+            }
+        }
+
+        if req.dry_run {
+            trace!("Dry run requested, computing deployment plan");
+            self.send_deploy_plan(
+                msg.reply,
+                account_id,
+                lattice_id,
+                manifests.get_deployed(),
+                staged_model,
+            )
+            .await;
+            return;
+        }
+
+        let previous_version = manifests.deployed_version().map(ToOwned::to_owned);
+        let with_overrides = req.overrides.is_some();
         if !manifests.deploy(req.version.clone()) {
             trace!("Requested version does not exist");
             self.send_reply(
@@ -737,31 +1221,60 @@ impl<P: Publisher> Handler<P> {
                     ),
                     name: name.to_string(),
                     version: req.version,
+                    commands: None,
                 })
                 .unwrap_or_default(),
             )
             .await;
             return;
         }
+        if let (Some(patch), Some(overridden)) = (req.overrides, overridden) {
+            trace!(?patch, "Deploying with overrides");
+            manifests.set_overrides(patch, overridden);
+        }
+        if let Some(resolver) = self.digests.as_ref() {
+            trace!("Pinning images to digests");
+            // SAFETY: We just deployed the manifest above, so there is a deployed version
+            match pin_images(manifests.get_deployed().unwrap(), resolver.as_ref()).await {
+                Ok((_, images)) if images.is_empty() => (),
+                Ok((pinned, images)) => {
+                    debug!(?images, "Pinned images to digests");
+                    manifests.set_pinned_images(images, pinned);
+                }
+                Err(e) => {
+                    self.send_error(msg.reply, format!("Unable to pin images to digests: {e:#}"))
+                        .await;
+                    return;
+                }
+            }
+        }
         // SAFETY: We can unwrap here because we know we _just_ successfully deployed the manifest so they should all exist
-        let manifest = manifests
-            .get_version(manifests.deployed_version().unwrap())
-            .unwrap()
-            .to_owned();
+        let manifest = manifests.get_deployed().unwrap().to_owned();
 
         let manifest_version = manifest.version().to_string();
+        // Only deploys that replaced another version have something to roll back to
+        let generation = manifests
+            .previous_deployed_version()
+            .map(|_| manifests.deploy_generation());
         let reply = self
             .store
             .set(account_id, lattice_id, manifests, Some(current_revision))
             .await
             .map(|_| DeployModelResponse {
                 result: DeployResult::Acknowledged,
-                message: format!(
-                    "Successfully deployed application {name} {}",
-                    manifest.version()
-                ),
+                message: match claims_warning {
+                    Some(warning) => format!(
+                        "Successfully deployed application {name} {}. Warning: {warning}",
+                        manifest.version()
+                    ),
+                    None => format!(
+                        "Successfully deployed application {name} {}",
+                        manifest.version()
+                    ),
+                },
                 name: name.to_string(),
                 version: Some(manifest_version.clone()),
+                commands: None,
             })
             .unwrap_or_else(|e| {
                 error!(error = %e, "Unable to store updated data");
@@ -770,10 +1283,15 @@ impl<P: Publisher> Handler<P> {
                     message: "Internal storage error".to_string(),
                     name: name.to_string(),
                     version: Some(manifest_version.clone()),
+                    commands: None,
                 }
             });
         trace!("Manifest saved in store, sending notification");
-        if let Err(e) = self.notifier.deployed(lattice_id, manifest).await {
+        if let Err(e) = self
+            .notifier
+            .deployed(account_id, lattice_id, manifest)
+            .await
+        {
             error!(error = ?e, "Error when attempting to send deployed notification");
             self.send_reply(
                 msg.reply,
@@ -784,12 +1302,37 @@ impl<P: Publisher> Handler<P> {
                     message: "Error notifying processors of newly deployed manifest. This is likely a transient error, so please retry the request".to_string(),
                     name: name.to_string(),
                     version: Some(manifest_version),
+                    commands: None,
                 })
                 .unwrap_or_default(),
             )
             .await;
             return;
         }
+        if let (Some(rollback), Some(generation), DeployResult::Acknowledged) =
+            (self.rollback.as_ref(), generation, &reply.result)
+        {
+            trace!(generation, "Watching deploy for automatic rollback");
+            rollback.watch(account_id, lattice_id, name, &manifest_version, generation);
+        }
+        if matches!(reply.result, DeployResult::Acknowledged) {
+            let mut summary = format!("Deployed version {manifest_version}");
+            if let Some(previous) = previous_version {
+                summary.push_str(&format!(" replacing {previous}"));
+            }
+            if with_overrides {
+                summary.push_str(" with overrides");
+            }
+            self.record_audit(
+                msg.headers.as_ref(),
+                account_id,
+                lattice_id,
+                "deploy",
+                name,
+                summary,
+            )
+            .await;
+        }
         trace!(resp = ?reply, "Sending response");
         self.send_reply(
             msg.reply,
@@ -800,36 +1343,204 @@ impl<P: Publisher> Handler<P> {
         .await;
     }
 
+    /// Computes the commands that would be issued to deploy the staged manifest and replies with
+    /// them. Nothing is stored or published
+    async fn send_deploy_plan(
+        &self,
+        reply: Option<Subject>,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        deployed: Option<&Manifest>,
+        staged: &Manifest,
+    ) {
+        let planner = match self.planner.as_ref() {
+            Some(p) => p,
+            None => {
+                self.send_error(
+                    reply,
+                    "Dry run deploys are not supported by this server".to_string(),
+                )
+                .await;
+                return;
+            }
+        };
+        let name = staged.metadata.name.clone();
+        let version = staged.version().to_string();
+        let commands = match planner
+            .plan(lattice_id, account_id, deployed, staged)
+            .await
+            .and_then(|commands| {
+                commands
+                    .into_iter()
+                    .map(|cmd| serde_json::to_value(cmd).map_err(anyhow::Error::from))
+                    .collect::<anyhow::Result<Vec<_>>>()
+            }) {
+            Ok(commands) => commands,
+            Err(e) => {
+                error!(error = ?e, "Unable to compute deployment plan");
+                self.send_error(reply, format!("Unable to compute deployment plan: {e:?}"))
+                    .await;
+                return;
+            }
+        };
+        let reply_data = DeployModelResponse {
+            result: DeployResult::Acknowledged,
+            message: format!(
+                "Dry run of application {name} {version} would issue {} commands",
+                commands.len()
+            ),
+            name,
+            version: Some(version),
+            commands: Some(commands),
+        };
+        trace!(resp = ?reply_data, "Sending dry run response");
+        self.send_reply(
+            reply,
+            // NOTE: We are constructing all data here, so this shouldn't fail, but just in
+            // case we unwrap to nothing
+            serde_json::to_vec(&reply_data).unwrap_or_default(),
+        )
+        .await;
+    }
+
+    /// Explains where the scaler of a component places it, for the given manifest or the stored
+    /// model with the given name
     #[instrument(level = "debug", skip(self, msg))]
-    pub async fn undeploy_model(
+    pub async fn model_placement(
         &self,
         msg: Message,
         account_id: Option<&str>,
         lattice_id: &str,
-        name: &str,
+        name: Option<&str>,
     ) {
-        let req: UndeployModelRequest = if msg.payload.is_empty() {
-            UndeployModelRequest {}
-        } else {
-            match serde_json::from_reader(std::io::Cursor::new(msg.payload)) {
-                Ok(r) => r,
-                Err(e) => {
-                    self.send_error(
-                        msg.reply,
-                        format!("Unable to parse undeploy application request: {e:?}"),
-                    )
-                    .await;
-                    return;
-                }
+        let req: PlacementRequest = match serde_json::from_slice(&msg.payload) {
+            Ok(r) => r,
+            Err(e) => {
+                self.send_error(
+                    msg.reply,
+                    format!("Unable to parse placement request: {e:?}"),
+                )
+                .await;
+                return;
             }
         };
-        trace!(?req, "Got request");
+        trace!(component = %req.component, "Got request");
 
-        trace!("Fetching current data from store");
-        let (mut manifests, current_revision) =
-            match self.store.get(account_id, lattice_id, name).await {
-                Ok(Some(m)) => m,
-                Ok(None) => {
+        let Some(planner) = self.planner.as_ref() else {
+            self.send_error(
+                msg.reply,
+                "Explaining placement is not supported by this server".to_string(),
+            )
+            .await;
+            return;
+        };
+        let Some(name) = name.or(req.manifest.as_ref().map(|m| m.metadata.name.as_str())) else {
+            self.send_error(
+                msg.reply,
+                "A placement request needs an application name in the subject or a manifest"
+                    .to_string(),
+            )
+            .await;
+            return;
+        };
+        let stored = match self.store.get(account_id, lattice_id, name).await {
+            Ok(stored) => stored.map(|(manifests, _)| manifests),
+            Err(e) => {
+                error!(error = %e, "Unable to fetch data");
+                self.send_error(msg.reply, "Internal storage error".to_string())
+                    .await;
+                return;
+            }
+        };
+        let deployed = stored.as_ref().and_then(|m| m.get_deployed());
+        let staged = match (req.manifest.as_ref(), stored.as_ref()) {
+            (Some(manifest), _) => Some(manifest),
+            (None, Some(stored)) => match req.version.as_deref() {
+                Some(version) => stored.get_version(version),
+                None => Some(deployed.unwrap_or_else(|| stored.get_current())),
+            },
+            (None, None) => None,
+        };
+        let Some(staged) = staged else {
+            let version = req
+                .version
+                .map(|v| format!(" version {v}"))
+                .unwrap_or_default();
+            self.send_reply(
+                msg.reply,
+                // NOTE: We are constructing all data here, so this shouldn't fail, but just in
+                // case we unwrap to nothing
+                serde_json::to_vec(&PlacementResponse {
+                    result: GetResult::NotFound,
+                    message: format!("Application {name}{version} not found"),
+                    hosts: Vec::new(),
+                })
+                .unwrap_or_default(),
+            )
+            .await;
+            return;
+        };
+
+        let response = match planner
+            .explain_placement(lattice_id, account_id, deployed, staged, &req.component)
+            .await
+        {
+            Ok(hosts) => PlacementResponse {
+                result: GetResult::Success,
+                message: format!(
+                    "{} of {} hosts are candidates for {} of application {name} {}",
+                    hosts.iter().filter(|h| h.eligible).count(),
+                    hosts.len(),
+                    req.component,
+                    staged.version()
+                ),
+                hosts,
+            },
+            Err(e) => PlacementResponse {
+                result: GetResult::Error,
+                message: format!("Unable to explain placement: {e}"),
+                hosts: Vec::new(),
+            },
+        };
+        self.send_reply(
+            msg.reply,
+            // NOTE: We are constructing all data here, so this shouldn't fail, but just in
+            // case we unwrap to nothing
+            serde_json::to_vec(&response).unwrap_or_default(),
+        )
+        .await;
+    }
+
+    #[instrument(level = "debug", skip(self, msg))]
+    pub async fn undeploy_model(
+        &self,
+        msg: Message,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        name: &str,
+    ) {
+        let req: UndeployModelRequest = if msg.payload.is_empty() {
+            UndeployModelRequest::default()
+        } else {
+            match serde_json::from_reader(std::io::Cursor::new(msg.payload)) {
+                Ok(r) => r,
+                Err(e) => {
+                    self.send_error(
+                        msg.reply,
+                        format!("Unable to parse undeploy application request: {e:?}"),
+                    )
+                    .await;
+                    return;
+                }
+            }
+        };
+        trace!(?req, "Got request");
+
+        trace!("Fetching current data from store");
+        let (mut manifests, current_revision) =
+            match self.store.get(account_id, lattice_id, name).await {
+                Ok(Some(m)) => m,
+                Ok(None) => {
                     self.send_reply(
                         msg.reply,
                         // NOTE: We are constructing all data here, so this shouldn't fail, but just in
@@ -839,6 +1550,7 @@ impl<P: Publisher> Handler<P> {
                             message: format!("Application with the name {name} not found"),
                             name: name.to_string(),
                             version: None,
+                            commands: None,
                         })
                         .unwrap_or_default(),
                     )
@@ -852,8 +1564,47 @@ impl<P: Publisher> Handler<P> {
                     return;
                 }
             };
-        // TODO(#451): if shared, make sure that no other shared apps are using it
+        if manifests.get_deployed().is_some_and(|m| m.shared()) {
+            let stored_models = match self.store.list(account_id, lattice_id).await {
+                Ok(m) => m,
+                Err(e) => {
+                    error!(error = %e, "Unable to fetch data");
+                    self.send_error(msg.reply, "Internal storage error".to_string())
+                        .await;
+                    return;
+                }
+            };
+            let stages = undeploy_stages(name, &stored_models);
+            if stages.len() > 1 {
+                if !req.cascade {
+                    let dependents = stages
+                        .iter()
+                        .flatten()
+                        .filter(|app| *app != name)
+                        .collect::<Vec<_>>();
+                    self.send_error(
+                        msg.reply,
+                        format!("Application {name} is shared and still used by deployed applications {dependents:?}. Undeploy them first or set cascade to undeploy them along with it"),
+                    )
+                    .await;
+                    return;
+                }
+                self.cascade_undeploy(
+                    msg.reply,
+                    msg.headers.as_ref(),
+                    account_id,
+                    lattice_id,
+                    name,
+                    stages,
+                    &req,
+                )
+                .await;
+                return;
+            }
+        }
 
+        let routes = manifests.notification_routes();
+        let undeployed_version = manifests.deployed_version().map(ToOwned::to_owned);
         let reply = if manifests.undeploy() {
             trace!("Manifest undeployed. Storing updated manifest");
 
@@ -865,6 +1616,7 @@ impl<P: Publisher> Handler<P> {
                     message: format!("Successfully undeployed application {name}"),
                     name: name.to_string(),
                     version: None,
+                    commands: None,
                 })
                 .unwrap_or_else(|e| {
                     error!(error = %e, "Unable to store updated data");
@@ -873,6 +1625,7 @@ impl<P: Publisher> Handler<P> {
                         message: "Internal storage error".to_string(),
                         name: name.to_string(),
                         version: None,
+                        commands: None,
                     }
                 })
         } else {
@@ -882,12 +1635,17 @@ impl<P: Publisher> Handler<P> {
                 message: format!("Application {name} was already undeployed"),
                 name: name.to_string(),
                 version: None,
+                commands: None,
             }
         };
         // We always want to resend in an undeploy in case things failed last time
         if matches!(reply.result, DeployResult::Acknowledged) {
             trace!("Sending undeploy notification");
-            if let Err(e) = self.notifier.undeployed(lattice_id, name).await {
+            if let Err(e) = self
+                .notifier
+                .undeployed(account_id, lattice_id, name, routes)
+                .await
+            {
                 error!(error = ?e, "Error when attempting to send undeploy notification");
                 self.send_reply(
                     msg.reply,
@@ -898,6 +1656,7 @@ impl<P: Publisher> Handler<P> {
                         message: "Error notifying processors of undeployed manifest. This is likely a transient error, so please retry the request".to_string(),
                         name: name.to_string(),
                         version: None,
+                        commands: None,
                     })
                     .unwrap_or_default(),
                 )
@@ -905,6 +1664,17 @@ impl<P: Publisher> Handler<P> {
                 return;
             }
         }
+        if let (Some(version), DeployResult::Acknowledged) = (undeployed_version, &reply.result) {
+            self.record_audit(
+                msg.headers.as_ref(),
+                account_id,
+                lattice_id,
+                "undeploy",
+                name,
+                format!("Undeployed version {version}"),
+            )
+            .await;
+        }
         trace!(resp = ?reply, "Sending response");
         self.send_reply(
             msg.reply,
@@ -915,6 +1685,176 @@ impl<P: Publisher> Handler<P> {
         .await;
     }
 
+    /// Pauses or resumes reconciliation of a deployed model. While paused, its scalers are kept
+    /// but don't issue any commands, so manual changes to the lattice aren't undone
+    #[instrument(level = "debug", skip(self, msg))]
+    pub async fn pause_model(
+        &self,
+        msg: Message,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        name: &str,
+        paused: bool,
+    ) {
+        trace!("Fetching current data from store");
+        let (mut manifests, current_revision) =
+            match self.store.get(account_id, lattice_id, name).await {
+                Ok(Some(m)) => m,
+                Ok(None) => {
+                    self.send_reply(
+                        msg.reply,
+                        // NOTE: We are constructing all data here, so this shouldn't fail, but just in
+                        // case we unwrap to nothing
+                        serde_json::to_vec(&DeployModelResponse {
+                            result: DeployResult::NotFound,
+                            message: format!("Application with the name {name} not found"),
+                            name: name.to_string(),
+                            version: None,
+                            commands: None,
+                        })
+                        .unwrap_or_default(),
+                    )
+                    .await;
+                    return;
+                }
+                Err(e) => {
+                    error!(error = %e, "Unable to fetch data");
+                    self.send_error(msg.reply, "Internal storage error".to_string())
+                        .await;
+                    return;
+                }
+            };
+
+        let version = manifests.deployed_version().map(ToOwned::to_owned);
+        let routes = manifests.notification_routes();
+        let action = if paused { "paused" } else { "resumed" };
+        let reply = if !manifests.set_paused(paused) {
+            DeployModelResponse {
+                result: DeployResult::Error,
+                message: format!("Application {name} is not deployed"),
+                name: name.to_string(),
+                version: None,
+                commands: None,
+            }
+        } else {
+            trace!(paused, "Storing updated manifest");
+            self.store
+                .set(account_id, lattice_id, manifests, Some(current_revision))
+                .await
+                .map(|_| DeployModelResponse {
+                    result: DeployResult::Acknowledged,
+                    message: format!("Successfully {action} reconciliation of application {name}"),
+                    name: name.to_string(),
+                    version: version.clone(),
+                    commands: None,
+                })
+                .unwrap_or_else(|e| {
+                    error!(error = %e, "Unable to store updated data");
+                    DeployModelResponse {
+                        result: DeployResult::Error,
+                        message: "Internal storage error".to_string(),
+                        name: name.to_string(),
+                        version: None,
+                        commands: None,
+                    }
+                })
+        };
+        // Like with undeploys, the notification is always resent in case it failed last time
+        if matches!(reply.result, DeployResult::Acknowledged) {
+            trace!("Sending pause notification");
+            if let Err(e) = self
+                .notifier
+                .paused(account_id, lattice_id, name, paused, routes)
+                .await
+            {
+                error!(error = ?e, "Error when attempting to send pause notification");
+                self.send_reply(
+                    msg.reply,
+                    // NOTE: We are constructing all data here, so this shouldn't fail, but just in
+                    // case we unwrap to nothing
+                    serde_json::to_vec(&DeployModelResponse {
+                        result: DeployResult::Error,
+                        message: format!("Error notifying processors that the manifest was {action}. This is likely a transient error, so please retry the request"),
+                        name: name.to_string(),
+                        version,
+                        commands: None,
+                    })
+                    .unwrap_or_default(),
+                )
+                .await;
+                return;
+            }
+        }
+        trace!(resp = ?reply, "Sending response");
+        self.send_reply(msg.reply, serde_json::to_vec(&reply).unwrap_or_default())
+            .await;
+    }
+
+    /// Undeploys the first stage of a cascading undeploy right away and the rest in the
+    /// background, waiting between each stage
+    #[allow(clippy::too_many_arguments)]
+    async fn cascade_undeploy(
+        &self,
+        reply: Option<Subject>,
+        headers: Option<&HeaderMap>,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        name: &str,
+        mut stages: Vec<Vec<String>>,
+        req: &UndeployModelRequest,
+    ) {
+        let undeployer = StagedUndeploy {
+            store: self.store.clone(),
+            notifier: self.notifier.clone(),
+        };
+        let first_stage = stages.remove(0);
+        for app in first_stage.iter() {
+            if let Err(e) = undeployer.undeploy(account_id, lattice_id, app).await {
+                error!(error = ?e, %app, "Unable to undeploy dependent application");
+                self.send_error(
+                    reply,
+                    format!("Unable to undeploy dependent application {app}. This is likely a transient error, so please retry the request"),
+                )
+                .await;
+                return;
+            }
+        }
+        let delay = req
+            .stage_delay_seconds
+            .map(Duration::from_secs)
+            .unwrap_or(self.undeploy_stage_delay);
+        let message = format!(
+            "Undeploying application {name} after its dependents. Undeployed {first_stage:?}, then undeploying {stages:?} with {}s between each stage",
+            delay.as_secs()
+        );
+        self.record_audit(
+            headers,
+            account_id,
+            lattice_id,
+            "undeploy",
+            name,
+            format!(
+                "Undeploying along with its dependents in stages: {first_stage:?} then {stages:?}"
+            ),
+        )
+        .await;
+        undeployer.spawn(account_id, lattice_id, stages, delay);
+        self.send_reply(
+            reply,
+            // NOTE: We are constructing all data here, so this shouldn't fail, but just in
+            // case we unwrap to nothing
+            serde_json::to_vec(&DeployModelResponse {
+                result: DeployResult::Acknowledged,
+                message,
+                name: name.to_string(),
+                version: None,
+                commands: None,
+            })
+            .unwrap_or_default(),
+        )
+        .await;
+    }
+
     #[instrument(level = "debug", skip(self, msg))]
     pub async fn model_status(
         &self,
@@ -923,11 +1863,32 @@ impl<P: Publisher> Handler<P> {
         lattice_id: &str,
         name: &str,
     ) {
+        let req: StatusRequest = if msg.payload.is_empty() {
+            StatusRequest::default()
+        } else {
+            match serde_json::from_reader(std::io::Cursor::new(msg.payload)) {
+                Ok(r) => r,
+                Err(e) => {
+                    self.send_error(msg.reply, format!("Unable to parse status request: {e:?}"))
+                        .await;
+                    return;
+                }
+            }
+        };
+        trace!(?req, "Got request");
+
+        if let Some(at) = req.at {
+            self.model_status_at(msg.reply, lattice_id, name, &at).await;
+            return;
+        }
+
         trace!("Fetching current manifest status");
         let status = self
             .get_manifest_status(lattice_id, name)
             .await
             .unwrap_or_default();
+        let last_reconcile = self.get_last_reconcile_report(lattice_id, name).await;
+        let failed_commands = self.get_failed_commands(lattice_id, name).await;
 
         self.send_reply(
             msg.reply,
@@ -937,31 +1898,568 @@ impl<P: Publisher> Handler<P> {
                 result: StatusResult::Ok,
                 message: format!("Successfully fetched status for application {name}"),
                 status: Some(status),
+                recorded_at: None,
+                last_reconcile,
+                failed_commands,
             })
             .unwrap_or_default(),
         )
         .await;
     }
 
-    /// Sends a reply to the topic with the given data, logging an error if one occurs when
-    /// sending the reply
-    #[instrument(level = "debug", skip(self, data))]
-    pub async fn send_reply(&self, reply: Option<Subject>, data: Vec<u8>) {
-        let reply_topic = match reply {
-            Some(t) => t,
-            None => {
-                debug!("No reply topic was sent. Skipping reply");
+    /// Replies with the status a model had at the given RFC 3339 timestamp
+    async fn model_status_at(
+        &self,
+        reply: Option<Subject>,
+        lattice_id: &str,
+        name: &str,
+        at: &str,
+    ) {
+        let at = match DateTime::parse_from_rfc3339(at) {
+            Ok(at) => at.with_timezone(&Utc),
+            Err(e) => {
+                self.send_error(
+                    reply,
+                    format!("Invalid timestamp '{at}', expected an RFC 3339 timestamp: {e}"),
+                )
+                .await;
                 return;
             }
         };
 
-        if let Err(e) = self.client.publish(reply_topic, data.into()).await {
-            error!(error = %e, "Unable to send reply");
-        }
-    }
+        trace!(%at, "Fetching manifest status from history");
+        let response = match self.get_manifest_status_at(lattice_id, name, at).await {
+            Ok(Some((status, recorded_at))) => StatusResponse {
+                result: StatusResult::Ok,
+                message: format!(
+                    "Successfully fetched status for application {name} at {}",
+                    at.to_rfc3339()
+                ),
+                status: Some(status),
+                recorded_at: Some(recorded_at.to_rfc3339()),
+                last_reconcile: None,
+                failed_commands: Vec::new(),
+            },
+            Ok(None) => StatusResponse {
+                result: StatusResult::NotFound,
+                message: format!(
+                    "No status was recorded for application {name} at or before {}. It may be older than the status history that is kept",
+                    at.to_rfc3339()
+                ),
+                status: None,
+                recorded_at: None,
+                last_reconcile: None,
+                failed_commands: Vec::new(),
+            },
+            Err(e) => {
+                error!(error = ?e, "Unable to fetch status history");
+                self.send_error(reply, "Unable to fetch status history".to_string())
+                    .await;
+                return;
+            }
+        };
 
-    /// Sends an error reply
-    #[instrument(level = "error", skip(self, error_message))]
+        self.send_reply(
+            reply,
+            // NOTE: We are constructing all data here, so this shouldn't fail, but just in
+            // case we unwrap to nothing
+            serde_json::to_vec(&response).unwrap_or_default(),
+        )
+        .await;
+    }
+
+    #[instrument(level = "debug", skip(self, msg))]
+    pub async fn lattice_topology(&self, msg: Message, account_id: Option<&str>, lattice_id: &str) {
+        let req: TopologyRequest = if msg.payload.is_empty() {
+            TopologyRequest::default()
+        } else {
+            match serde_json::from_reader(std::io::Cursor::new(msg.payload)) {
+                Ok(r) => r,
+                Err(e) => {
+                    self.send_error(
+                        msg.reply,
+                        format!("Unable to parse topology request: {e:?}"),
+                    )
+                    .await;
+                    return;
+                }
+            }
+        };
+        trace!(?req, "Got request");
+
+        let Some(source) = self.topology.as_ref() else {
+            self.send_error(
+                msg.reply,
+                "Lattice topology is not supported by this wadm instance".to_string(),
+            )
+            .await;
+            return;
+        };
+
+        let stored_manifests = match self.store.list(account_id, lattice_id).await {
+            Ok(d) => d,
+            Err(e) => {
+                error!(error = %e, "Unable to fetch data");
+                self.send_error(msg.reply, "Internal storage error".to_string())
+                    .await;
+                return;
+            }
+        };
+        let deployed = stored_manifests
+            .iter()
+            .filter_map(|manifest| manifest.get_deployed())
+            .collect::<Vec<_>>();
+
+        let topology = match source.topology(lattice_id, account_id, &deployed).await {
+            Ok(topology) => topology,
+            Err(e) => {
+                error!(error = ?e, "Unable to build lattice topology");
+                self.send_error(msg.reply, format!("Unable to build lattice topology: {e}"))
+                    .await;
+                return;
+            }
+        };
+
+        let (topology, dot) = match req.format {
+            TopologyFormat::Json => (Some(topology), None),
+            TopologyFormat::Dot => (None, Some(topology.to_dot())),
+        };
+        self.send_reply(
+            msg.reply,
+            // NOTE: We are constructing all data here, so this shouldn't fail, but just in
+            // case we unwrap to nothing
+            serde_json::to_vec(&TopologyResponse {
+                result: GetResult::Success,
+                message: format!("Successfully built topology for lattice {lattice_id}"),
+                topology,
+                dot,
+            })
+            .unwrap_or_default(),
+        )
+        .await;
+    }
+
+    #[instrument(level = "debug", skip(self, msg))]
+    pub async fn shard_status(&self, msg: Message, lattice_id: &str) {
+        let Some(membership) = self.shards.as_ref() else {
+            self.send_error(
+                msg.reply,
+                "Sharding is not enabled on this wadm instance".to_string(),
+            )
+            .await;
+            return;
+        };
+        let owner = membership.owner(lattice_id);
+        self.send_reply(
+            msg.reply,
+            // NOTE: We are constructing all data here, so this shouldn't fail, but just in
+            // case we unwrap to nothing
+            serde_json::to_vec(&ShardStatusResponse {
+                result: GetResult::Success,
+                message: format!("Lattice {lattice_id} is owned by {owner}"),
+                owner: Some(owner),
+                members: membership.members(),
+            })
+            .unwrap_or_default(),
+        )
+        .await;
+    }
+
+    #[instrument(level = "info", skip(self, msg))]
+    pub async fn reload_config(&self, msg: Message) {
+        let Some(reloader) = self.reloader.as_ref() else {
+            self.send_error(
+                msg.reply,
+                "Config reloading is not enabled on this wadm instance".to_string(),
+            )
+            .await;
+            return;
+        };
+        let changed = match reloader.reload().await {
+            Ok(changed) => changed,
+            Err(e) => {
+                error!(error = ?e, "Unable to reload config");
+                self.send_error(msg.reply, format!("Unable to reload config: {e:?}"))
+                    .await;
+                return;
+            }
+        };
+        let message = if changed.is_empty() {
+            "Reloaded config, nothing changed".to_string()
+        } else {
+            format!("Reloaded config, changed {}", changed.join(", "))
+        };
+        self.send_reply(
+            msg.reply,
+            // NOTE: We are constructing all data here, so this shouldn't fail, but just in
+            // case we unwrap to nothing
+            serde_json::to_vec(&ReloadConfigResponse {
+                result: GetResult::Success,
+                message,
+                changed: changed.into_iter().map(ToOwned::to_owned).collect(),
+            })
+            .unwrap_or_default(),
+        )
+        .await;
+    }
+
+    #[instrument(level = "debug", skip(self, msg))]
+    pub async fn introspect(&self, msg: Message, operation: &str) {
+        let Some(introspector) = self.introspector.as_ref() else {
+            self.send_error(
+                msg.reply,
+                "Introspection is not enabled on this wadm instance".to_string(),
+            )
+            .await;
+            return;
+        };
+        // NOTE: We are constructing all data here, so serializing shouldn't fail, but just in
+        // case we unwrap to nothing
+        let data = match operation {
+            ADMIN_INFO_SUBJECT => serde_json::to_vec(&AdminInfoResponse {
+                result: GetResult::Success,
+                message: "Successfully fetched build info".to_string(),
+                info: Some(introspector.build_info().await),
+            }),
+            ADMIN_LATTICES_SUBJECT => serde_json::to_vec(&AdminLatticesResponse {
+                result: GetResult::Success,
+                message: "Successfully fetched managed lattices".to_string(),
+                lattices: introspector.lattices().await,
+            }),
+            ADMIN_CONSUMERS_SUBJECT => serde_json::to_vec(&AdminConsumersResponse {
+                result: GetResult::Success,
+                message: "Successfully fetched consumers".to_string(),
+                consumers: introspector.consumers().await,
+            }),
+            ADMIN_SCALERS_SUBJECT => serde_json::to_vec(&AdminScalersResponse {
+                result: GetResult::Success,
+                message: "Successfully fetched scalers".to_string(),
+                apps: introspector.scalers().await,
+            }),
+            ADMIN_PERMITS_SUBJECT => serde_json::to_vec(&AdminPermitsResponse {
+                result: GetResult::Success,
+                message: "Successfully fetched permit usage".to_string(),
+                permits: Some(introspector.permits().await),
+            }),
+            _ => {
+                self.send_error(msg.reply, format!("Unsupported subject: {}", msg.subject))
+                    .await;
+                return;
+            }
+        };
+        self.send_reply(msg.reply, data.unwrap_or_default()).await;
+    }
+
+    #[instrument(level = "debug", skip(self, msg))]
+    pub async fn list_dead_letters(&self, msg: Message, lattice_id: &str) {
+        let Some(source) = self.dead_letters.as_ref() else {
+            self.send_error(
+                msg.reply,
+                "Dead letters are not supported by this wadm instance".to_string(),
+            )
+            .await;
+            return;
+        };
+
+        let dead_letters = match source.list(lattice_id).await {
+            Ok(letters) => letters,
+            Err(e) => {
+                error!(error = ?e, "Unable to list dead letters");
+                self.send_error(msg.reply, format!("Unable to list dead letters: {e}"))
+                    .await;
+                return;
+            }
+        };
+        let dead_letters = dead_letters
+            .into_iter()
+            .map(|stored| DeadLetterSummary {
+                sequence: stored.sequence,
+                attempts: stored.letter.attempts,
+                error: stored.letter.error,
+                failed_at: stored.letter.failed_at.to_rfc3339(),
+                command: serde_json::to_value(&stored.letter.command).unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+
+        self.send_reply(
+            msg.reply,
+            // NOTE: We are constructing all data here, so this shouldn't fail, but just in
+            // case we unwrap to nothing
+            serde_json::to_vec(&DeadLetterListResponse {
+                result: GetResult::Success,
+                message: format!(
+                    "Found {} dead letters for lattice {lattice_id}",
+                    dead_letters.len()
+                ),
+                dead_letters,
+            })
+            .unwrap_or_default(),
+        )
+        .await;
+    }
+
+    #[instrument(level = "debug", skip(self, msg))]
+    pub async fn replay_dead_letters(&self, msg: Message, lattice_id: &str) {
+        let req: DeadLetterReplayRequest = if msg.payload.is_empty() {
+            DeadLetterReplayRequest::default()
+        } else {
+            match serde_json::from_reader(std::io::Cursor::new(msg.payload)) {
+                Ok(r) => r,
+                Err(e) => {
+                    self.send_error(
+                        msg.reply,
+                        format!("Unable to parse dead letter replay request: {e:?}"),
+                    )
+                    .await;
+                    return;
+                }
+            }
+        };
+        trace!(?req, "Got request");
+
+        if !req.all && req.sequences.is_empty() {
+            self.send_error(
+                msg.reply,
+                "Either a list of sequences or all must be set to replay dead letters".to_string(),
+            )
+            .await;
+            return;
+        }
+
+        let Some(source) = self.dead_letters.as_ref() else {
+            self.send_error(
+                msg.reply,
+                "Dead letters are not supported by this wadm instance".to_string(),
+            )
+            .await;
+            return;
+        };
+
+        let sequences = (!req.all).then_some(req.sequences.as_slice());
+        let outcome = match source.replay(lattice_id, sequences).await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                error!(error = ?e, "Unable to replay dead letters");
+                self.send_error(msg.reply, format!("Unable to replay dead letters: {e}"))
+                    .await;
+                return;
+            }
+        };
+
+        let (result, message) = if outcome.replayed.is_empty() && !outcome.not_found.is_empty() {
+            (
+                GetResult::NotFound,
+                "None of the requested dead letters were found".to_string(),
+            )
+        } else {
+            (
+                GetResult::Success,
+                format!(
+                    "Replayed {} dead letters for lattice {lattice_id}",
+                    outcome.replayed.len()
+                ),
+            )
+        };
+        self.send_reply(
+            msg.reply,
+            // NOTE: We are constructing all data here, so this shouldn't fail, but just in
+            // case we unwrap to nothing
+            serde_json::to_vec(&DeadLetterReplayResponse {
+                result,
+                message,
+                replayed: outcome.replayed,
+                not_found: outcome.not_found,
+            })
+            .unwrap_or_default(),
+        )
+        .await;
+    }
+
+    #[instrument(level = "info", skip(self, msg))]
+    pub async fn cordon_host(
+        &self,
+        msg: Message,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        host_id: &str,
+        cordon: bool,
+    ) {
+        let req: CordonHostRequest = if msg.payload.is_empty() {
+            CordonHostRequest::default()
+        } else {
+            match serde_json::from_reader(std::io::Cursor::new(msg.payload)) {
+                Ok(r) => r,
+                Err(e) => {
+                    self.send_error(msg.reply, format!("Unable to parse cordon request: {e:?}"))
+                        .await;
+                    return;
+                }
+            }
+        };
+        trace!(?req, "Got request");
+
+        let Some(cordoner) = self.cordoner.as_ref() else {
+            self.send_error(
+                msg.reply,
+                "Cordoning hosts is not supported by this wadm instance".to_string(),
+            )
+            .await;
+            return;
+        };
+
+        let (res, message) = if cordon {
+            (
+                cordoner
+                    .cordon(lattice_id, account_id, host_id, req.drain)
+                    .await,
+                if req.drain {
+                    format!(
+                        "Cordoned host {host_id}, managed workloads will be moved to other hosts"
+                    )
+                } else {
+                    format!("Cordoned host {host_id}")
+                },
+            )
+        } else {
+            (
+                cordoner.uncordon(lattice_id, account_id, host_id).await,
+                format!("Uncordoned host {host_id}"),
+            )
+        };
+        if let Err(e) = res {
+            error!(error = ?e, "Unable to update host cordon");
+            self.send_error(msg.reply, format!("Unable to update host cordon: {e}"))
+                .await;
+            return;
+        }
+
+        self.send_reply(
+            msg.reply,
+            // NOTE: We are constructing all data here, so this shouldn't fail, but just in
+            // case we unwrap to nothing
+            serde_json::to_vec(&CordonHostResponse {
+                result: GetResult::Success,
+                message,
+            })
+            .unwrap_or_default(),
+        )
+        .await;
+    }
+
+    /// Sends a reply to the topic with the given data, logging an error if one occurs when
+    /// sending the reply
+    /// Records a mutating call in the audit log, if one is configured. Failing to record a call
+    /// doesn't fail the call, since the change was already made
+    async fn record_audit(
+        &self,
+        headers: Option<&HeaderMap>,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        operation: &str,
+        name: &str,
+        summary: String,
+    ) {
+        let Some(audit) = self.audit.as_ref() else {
+            return;
+        };
+        let (caller, caller_verified) = caller_identity(headers);
+        let record = AuditRecord {
+            sequence: 0,
+            time: Utc::now().to_rfc3339(),
+            operation: operation.to_owned(),
+            account_id: account_id.map(ToOwned::to_owned),
+            lattice_id: lattice_id.to_owned(),
+            name: name.to_owned(),
+            caller,
+            caller_verified,
+            summary,
+        };
+        if let Err(e) = audit.record(&record).await {
+            error!(error = ?e, ?record, "Unable to record call in audit log");
+        }
+    }
+
+    #[instrument(level = "debug", skip(self, msg))]
+    pub async fn query_audit_log(&self, msg: Message, account_id: Option<&str>, lattice_id: &str) {
+        let Some(audit) = self.audit.as_ref() else {
+            self.send_error(
+                msg.reply,
+                "The audit log is not supported by this wadm instance".to_string(),
+            )
+            .await;
+            return;
+        };
+        let req: AuditQueryRequest = if msg.payload.is_empty() {
+            AuditQueryRequest::default()
+        } else {
+            match serde_json::from_slice(&msg.payload) {
+                Ok(r) => r,
+                Err(e) => {
+                    self.send_error(
+                        msg.reply,
+                        format!("Unable to parse audit log request: {e:?}"),
+                    )
+                    .await;
+                    return;
+                }
+            }
+        };
+        let limit = req
+            .limit
+            .unwrap_or(DEFAULT_AUDIT_PAGE_SIZE)
+            .min(MAX_AUDIT_PAGE_SIZE);
+        let (records, next_sequence) = match audit
+            .query(
+                account_id,
+                lattice_id,
+                req.start_sequence.unwrap_or_default(),
+                limit,
+            )
+            .await
+        {
+            Ok(page) => page,
+            Err(e) => {
+                error!(error = ?e, "Unable to query audit log");
+                self.send_error(msg.reply, format!("Unable to query audit log: {e}"))
+                    .await;
+                return;
+            }
+        };
+        self.send_reply(
+            msg.reply,
+            // NOTE: We are constructing all data here, so this shouldn't fail, but just in
+            // case we unwrap to nothing
+            serde_json::to_vec(&AuditQueryResponse {
+                result: GetResult::Success,
+                message: format!(
+                    "Found {} audit records for lattice {lattice_id}",
+                    records.len()
+                ),
+                records,
+                next_sequence,
+            })
+            .unwrap_or_default(),
+        )
+        .await;
+    }
+
+    #[instrument(level = "debug", skip(self, data))]
+    pub async fn send_reply(&self, reply: Option<Subject>, data: Vec<u8>) {
+        let reply_topic = match reply {
+            Some(t) => t,
+            None => {
+                debug!("No reply topic was sent. Skipping reply");
+                return;
+            }
+        };
+
+        if let Err(e) = self.client.publish(reply_topic, data.into()).await {
+            error!(error = %e, "Unable to send reply");
+        }
+    }
+
+    /// Sends an error reply
+    #[instrument(level = "error", skip(self, error_message))]
     pub async fn send_error(&self, reply: Option<Subject>, error_message: String) {
         // SAFETY: We control the construction of the JSON here and all data going in, so this
         // shouldn't fail except in some sort of really odd case. In those cases, we just unwrap to
@@ -991,6 +2489,96 @@ impl<P: Publisher> Handler<P> {
             _ => None,
         }
     }
+
+    /// Returns the report of the last reconcile pass of a model, if reports are stored and the model
+    /// has been reconciled
+    async fn get_last_reconcile_report(
+        &self,
+        lattice_id: &str,
+        name: &str,
+    ) -> Option<ReconcileReport> {
+        let msg = self
+            .reconcile_reports
+            .as_ref()?
+            .get_last_raw_message_by_subject(&format!("wadm.reconcile.{lattice_id}.{name}"))
+            .await
+            .ok()?;
+        match serde_json::from_slice(&msg.payload) {
+            Ok(report) => Some(report),
+            Err(e) => {
+                warn!(error = ?e, "Stored reconcile report is invalid, ignoring it");
+                None
+            }
+        }
+    }
+
+    async fn get_failed_commands(&self, lattice_id: &str, name: &str) -> Vec<CommandAck> {
+        let Some(acks) = self.command_acks.as_ref() else {
+            return Vec::new();
+        };
+        acks.failures(lattice_id, name).await.unwrap_or_else(|e| {
+            warn!(error = ?e, "Unable to fetch command acks, returning status without them");
+            Vec::new()
+        })
+    }
+
+    /// Returns the last status recorded for a model at or before the given time, along with when
+    /// it was recorded
+    async fn get_manifest_status_at(
+        &self,
+        lattice_id: &str,
+        name: &str,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<(Status, DateTime<Utc>)>> {
+        let subject = format!("wadm.status.{lattice_id}.{name}");
+        // Most lookups are for something recent, so check the latest status before walking the
+        // history. If there isn't one, there is no history either
+        let last = match self
+            .status_stream
+            .get_last_raw_message_by_subject(&subject)
+            .await
+        {
+            Ok(msg) => msg,
+            Err(_) => return Ok(None),
+        };
+        let last_recorded = timestamp_to_utc(last.time.unix_timestamp(), last.time.nanosecond());
+        if last_recorded <= at {
+            return Ok(Some((
+                serde_json::from_slice(&last.payload)?,
+                last_recorded,
+            )));
+        }
+
+        // NOTE: The latest status is newer than the requested time, so walking from the start of
+        // the history will always stop once we reach it
+        let consumer = self
+            .status_stream
+            .create_consumer(OrderedConfig {
+                filter_subject: subject,
+                deliver_policy: DeliverPolicy::All,
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| anyhow!("{e:?}"))?;
+        let mut messages = consumer.messages().await.map_err(|e| anyhow!("{e:?}"))?;
+        let mut found = None;
+        while let Some(msg) = messages.next().await {
+            let msg = msg.map_err(|e| anyhow!("{e:?}"))?;
+            let info = msg.info().map_err(|e| anyhow!("{e:?}"))?;
+            let recorded =
+                timestamp_to_utc(info.published.unix_timestamp(), info.published.nanosecond());
+            if recorded > at || info.stream_sequence >= last.sequence {
+                break;
+            }
+            found = Some((serde_json::from_slice(&msg.payload)?, recorded));
+        }
+        Ok(found)
+    }
+}
+
+/// Converts a unix timestamp in seconds plus nanoseconds to a UTC datetime
+fn timestamp_to_utc(seconds: i64, nanoseconds: u32) -> DateTime<Utc> {
+    DateTime::from_timestamp(seconds, nanoseconds).unwrap_or_default()
 }
 
 /// Helper function to create a [`ModelSummary`] from a [`StoredManifest`] and [`Status`]
@@ -1,22 +1,50 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use async_nats::{
     jetstream::{kv::Store, stream::Stream},
     Client, Subscriber,
 };
 use futures::StreamExt;
 use tracing::{info, instrument, warn};
-use wadm_types::api::DEFAULT_WADM_TOPIC_PREFIX;
+use wadm_types::{
+    aliases::AliasRegistry,
+    api::{ADMIN_RELOAD_SUBJECT, API_VERSION, DEFAULT_WADM_TOPIC_PREFIX, READ_ONLY_API_SEGMENT},
+};
 
-use crate::publisher::Publisher;
+use crate::{
+    audit::AuditLog,
+    claims::ClaimsVerifier,
+    config::ConfigReloader,
+    cordon::HostCordoner,
+    hibernation::LatticeActivity,
+    introspection::Introspector,
+    oci::DigestResolver,
+    publisher::Publisher,
+    scaler::planner::CommandPlanner,
+    sharding::ShardMembership,
+    topology::TopologySource,
+    workers::{CommandAckStore, DeadLetterSource},
+};
 
+pub mod auth;
+mod bundle;
 mod handlers;
 mod notifier;
 mod parser;
+mod quota;
+mod rollback;
 mod storage;
+mod undeploy;
 
+use auth::{AllowAll, ApiRequest, Authorizer};
 use handlers::Handler;
-pub use notifier::ManifestNotifier;
+pub use notifier::{ManifestNotifier, NotificationWebhooks};
 pub use parser::CONTENT_TYPE_HEADER;
+pub use quota::ManifestQuotas;
+use rollback::RollbackWatcher;
 pub(crate) use storage::ModelStorage;
+pub use undeploy::DEFAULT_UNDEPLOY_STAGE_DELAY;
 
 const QUEUE_GROUP: &str = "wadm_server";
 
@@ -26,9 +54,11 @@ pub struct Server<P> {
     subscriber: Subscriber,
     prefix: String,
     multitenant: bool,
+    activity: Option<LatticeActivity>,
+    authorizer: Arc<dyn Authorizer + Send + Sync>,
 }
 
-impl<P: Publisher> Server<P> {
+impl<P: Publisher + Clone + Send + Sync + 'static> Server<P> {
     /// Returns a new server configured with the given store, NATS client, and optional topic
     /// prefix. Returns an error if it can't subscribe on the right topics
     ///
@@ -77,13 +107,198 @@ impl<P: Publisher> Server<P> {
                 client,
                 notifier,
                 status_stream,
+                reconcile_reports: None,
+                command_acks: None,
+                planner: None,
+                rollback: None,
+                topology: None,
+                dead_letters: None,
+                cordoner: None,
+                undeploy_stage_delay: DEFAULT_UNDEPLOY_STAGE_DELAY,
+                shards: None,
+                reloader: None,
+                introspector: None,
+                aliases: None,
+                digests: None,
+                audit: None,
+                quotas: ManifestQuotas::default(),
+                claims: None,
+                permissive_claims: false,
             },
             subscriber,
             prefix,
             multitenant,
+            activity: None,
+            authorizer: Arc::new(AllowAll),
         })
     }
 
+    /// Configures the server with a [`CommandPlanner`] used to compute the commands for dry run
+    /// deploys and to explain component placement. If no planner is set, those requests will
+    /// return an error
+    pub fn with_command_planner(
+        mut self,
+        planner: impl CommandPlanner + Send + Sync + 'static,
+    ) -> Server<P> {
+        self.handler.planner = Some(Arc::new(planner));
+        self
+    }
+
+    /// Configures the server with a [`TopologySource`] used to answer lattice topology requests. If
+    /// no source is set, topology requests will return an error
+    pub fn with_topology_source(
+        mut self,
+        source: impl TopologySource + Send + Sync + 'static,
+    ) -> Server<P> {
+        self.handler.topology = Some(Arc::new(source));
+        self
+    }
+
+    /// Configures the server with a [`DeadLetterSource`] used to list and replay dead lettered
+    /// commands. If no source is set, dead letter requests will return an error
+    pub fn with_dead_letter_source(
+        mut self,
+        source: impl DeadLetterSource + Send + Sync + 'static,
+    ) -> Server<P> {
+        self.handler.dead_letters = Some(Arc::new(source));
+        self
+    }
+
+    /// Configures the server with a [`HostCordoner`] used to cordon and uncordon hosts. If no
+    /// cordoner is set, host requests will return an error
+    pub fn with_host_cordoner(
+        mut self,
+        cordoner: impl HostCordoner + Send + Sync + 'static,
+    ) -> Server<P> {
+        self.handler.cordoner = Some(Arc::new(cordoner));
+        self
+    }
+
+    /// Records every API request as activity for its lattice, waking the lattice if it was
+    /// hibernated
+    pub fn with_lattice_activity(mut self, activity: LatticeActivity) -> Server<P> {
+        self.activity = Some(activity);
+        self
+    }
+
+    /// Configures the server with the [`ShardMembership`] of this replica, used to answer which
+    /// replica a lattice is sharded to. If not set, shard requests will return an error
+    pub fn with_shard_membership(mut self, membership: ShardMembership) -> Server<P> {
+        self.handler.shards = Some(membership);
+        self
+    }
+
+    /// Configures the server with a [`ConfigReloader`] used to answer admin reload requests. If no
+    /// reloader is set, reload requests will return an error
+    pub fn with_config_reloader(
+        mut self,
+        reloader: impl ConfigReloader + Send + Sync + 'static,
+    ) -> Server<P> {
+        self.handler.reloader = Some(Arc::new(reloader));
+        self
+    }
+
+    /// Configures the server with an [`Introspector`] used to answer the admin requests that report
+    /// the internal state of this instance. If not set, those requests will return an error
+    pub fn with_introspector(
+        mut self,
+        introspector: impl Introspector + Send + Sync + 'static,
+    ) -> Server<P> {
+        self.handler.introspector = Some(Arc::new(introspector));
+        self
+    }
+
+    /// Configures the server with an [`AliasRegistry`] used to expand the capability aliases in
+    /// manifests when they are put. If not set, manifests that use aliases are rejected
+    pub fn with_capability_aliases(mut self, aliases: AliasRegistry) -> Server<P> {
+        self.handler.aliases = Some(Arc::new(aliases));
+        self
+    }
+
+    /// Configures the server with a [`DigestResolver`] used to pin the images of a manifest to the
+    /// digests they point to when it is deployed. If not set, images are deployed as given
+    pub fn with_digest_resolver(
+        mut self,
+        resolver: impl DigestResolver + Send + Sync + 'static,
+    ) -> Server<P> {
+        self.handler.digests = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Configures the server with an [`AuditLog`] that every put, delete, deploy and undeploy is
+    /// recorded in. If not set, calls aren't recorded and audit log queries will return an error
+    pub fn with_audit_log(mut self, audit: impl AuditLog + Send + Sync + 'static) -> Server<P> {
+        self.handler.audit = Some(Arc::new(audit));
+        self
+    }
+
+    /// Configures the server with an [`Authorizer`] that every request is checked with before it is
+    /// handled. If not set, all requests are allowed
+    pub fn with_authorizer(
+        mut self,
+        authorizer: impl Authorizer + Send + Sync + 'static,
+    ) -> Server<P> {
+        self.authorizer = Arc::new(authorizer);
+        self
+    }
+
+    /// Configures the server with a [`ClaimsVerifier`] used to check that components have claims for
+    /// the contracts they are linked to before a manifest is deployed. Deploys that fail the check
+    /// are rejected, unless `permissive` is set, in which case they are deployed with a warning. If
+    /// not set, claims aren't checked
+    pub fn with_claims_verifier(
+        mut self,
+        verifier: impl ClaimsVerifier + Send + Sync + 'static,
+        permissive: bool,
+    ) -> Server<P> {
+        self.handler.claims = Some(Arc::new(verifier));
+        self.handler.permissive_claims = permissive;
+        self
+    }
+
+    /// Configures the server with the stream that reports of each reconcile pass are stored in, so
+    /// the last one is returned along with the status of a model. If not set, statuses are returned
+    /// without a report
+    pub fn with_reconcile_reports(mut self, stream: Stream) -> Server<P> {
+        self.handler.reconcile_reports = Some(stream);
+        self
+    }
+
+    /// Configures the server with the store that the response to each command is recorded in, so
+    /// commands that failed on their last attempt are returned along with the status of a model.
+    /// If not set, statuses are returned without failed commands
+    pub fn with_command_acks(mut self, acks: CommandAckStore) -> Server<P> {
+        self.handler.command_acks = Some(acks);
+        self
+    }
+
+    /// Configures the quotas enforced when models are put and deployed. If not set, no quotas are
+    /// enforced
+    pub fn with_quotas(mut self, quotas: ManifestQuotas) -> Server<P> {
+        self.handler.quotas = quotas;
+        self
+    }
+
+    /// Sets the default time to wait between stages when undeploying a shared application along
+    /// with its dependents. Requests can override this
+    pub fn with_undeploy_stage_delay(mut self, delay: Duration) -> Server<P> {
+        self.handler.undeploy_stage_delay = delay;
+        self
+    }
+
+    /// Enables automatic rollbacks. If a newly deployed version doesn't reach a deployed status
+    /// within the given timeout, the previously deployed version is redeployed
+    pub fn with_rollback_timeout(mut self, timeout: Duration) -> Server<P> {
+        self.handler.rollback = Some(RollbackWatcher {
+            store: self.handler.store.clone(),
+            client: self.handler.client.clone(),
+            notifier: self.handler.notifier.clone(),
+            status_stream: self.handler.status_stream.clone(),
+            timeout,
+        });
+        self
+    }
+
     /// Starts the server, consuming it.
     ///
     /// This function will run until it either returns an error (which should always be fatal) or
@@ -101,6 +316,18 @@ impl<P: Publisher> Server<P> {
             // strings. But we need to pass the message to consume the data off of it in the
             // handlers
             let subject = msg.subject.clone();
+            // Admin requests aren't scoped to a lattice, so they are handled before parsing
+            match self.admin_operation(&subject) {
+                Some(ADMIN_RELOAD_SUBJECT) => {
+                    self.handler.reload_config(msg).await;
+                    continue;
+                }
+                Some(operation) => {
+                    self.handler.introspect(msg, operation).await;
+                    continue;
+                }
+                None => (),
+            }
             let parsed = match self.parse_subject(&subject) {
                 Ok(p) => p,
                 Err(e) => {
@@ -111,6 +338,39 @@ impl<P: Publisher> Server<P> {
                 }
             };
 
+            if parsed.read_only && !parsed.is_read_operation() {
+                self.handler
+                    .send_error(
+                        msg.reply,
+                        format!(
+                            "Operation {}.{} is not available on the read-only API",
+                            parsed.category, parsed.operation
+                        ),
+                    )
+                    .await;
+                continue;
+            }
+
+            let request = ApiRequest {
+                subject: &subject,
+                account_id: parsed.account_id,
+                lattice_id: parsed.lattice_id,
+                category: parsed.category,
+                operation: parsed.operation,
+                name: parsed.object_name,
+                read_only: parsed.is_read_operation(),
+                headers: msg.headers.as_ref(),
+            };
+            if let Err(e) = self.authorizer.authorize(&request).await {
+                warn!(%subject, error = %e, "Denied API request");
+                self.handler.send_error(msg.reply, e.to_string()).await;
+                continue;
+            }
+
+            if let Some(activity) = self.activity.as_ref() {
+                activity.touch(parsed.lattice_id, parsed.account_id);
+            }
+
             match parsed {
                 ParsedSubject {
                     account_id,
@@ -118,6 +378,7 @@ impl<P: Publisher> Server<P> {
                     category: "model",
                     operation: "list",
                     object_name: None,
+                    ..
                 } => {
                     warn!("Received deprecated subject: model.list. Please use model.get instead");
                     self.handler
@@ -130,6 +391,7 @@ impl<P: Publisher> Server<P> {
                     category: "model",
                     operation: "get",
                     object_name: Some(name),
+                    ..
                 } => {
                     self.handler
                         .get_model(msg, account_id, lattice_id, name)
@@ -141,6 +403,7 @@ impl<P: Publisher> Server<P> {
                     category: "model",
                     operation: "get",
                     object_name: None,
+                    ..
                 } => self.handler.list_models(msg, account_id, lattice_id).await,
                 ParsedSubject {
                     account_id,
@@ -148,13 +411,45 @@ impl<P: Publisher> Server<P> {
                     category: "model",
                     operation: "put",
                     object_name: None,
+                    ..
                 } => self.handler.put_model(msg, account_id, lattice_id).await,
+                ParsedSubject {
+                    account_id,
+                    lattice_id,
+                    category: "model",
+                    operation: "export",
+                    object_name: None,
+                    ..
+                } => {
+                    self.handler
+                        .export_models(msg, account_id, lattice_id)
+                        .await
+                }
+                ParsedSubject {
+                    account_id,
+                    lattice_id,
+                    category: "model",
+                    operation: "import",
+                    object_name: None,
+                    ..
+                } => {
+                    self.handler
+                        .import_models(msg, account_id, lattice_id)
+                        .await
+                }
+                ParsedSubject {
+                    category: "model",
+                    operation: "lint",
+                    object_name: None,
+                    ..
+                } => self.handler.lint_model(msg).await,
                 ParsedSubject {
                     account_id,
                     lattice_id,
                     category: "model",
                     operation: "del",
                     object_name: Some(name),
+                    ..
                 } => {
                     self.handler
                         .delete_model(msg, account_id, lattice_id, name)
@@ -166,6 +461,7 @@ impl<P: Publisher> Server<P> {
                     category: "model",
                     operation: "versions",
                     object_name: Some(name),
+                    ..
                 } => {
                     self.handler
                         .list_versions(msg, account_id, lattice_id, name)
@@ -177,6 +473,7 @@ impl<P: Publisher> Server<P> {
                     category: "model",
                     operation: "deploy",
                     object_name: Some(name),
+                    ..
                 } => {
                     self.handler
                         .deploy_model(msg, account_id, lattice_id, name)
@@ -188,28 +485,115 @@ impl<P: Publisher> Server<P> {
                     category: "model",
                     operation: "undeploy",
                     object_name: Some(name),
+                    ..
                 } => {
                     self.handler
                         .undeploy_model(msg, account_id, lattice_id, name)
                         .await
                 }
+                ParsedSubject {
+                    account_id,
+                    lattice_id,
+                    category: "model",
+                    operation: operation @ ("pause" | "resume"),
+                    object_name: Some(name),
+                    ..
+                } => {
+                    self.handler
+                        .pause_model(msg, account_id, lattice_id, name, operation == "pause")
+                        .await
+                }
                 ParsedSubject {
                     account_id,
                     lattice_id,
                     category: "model",
                     operation: "status",
                     object_name: Some(name),
+                    ..
                 } => {
                     self.handler
                         .model_status(msg, account_id, lattice_id, name)
                         .await
                 }
+                ParsedSubject {
+                    account_id,
+                    lattice_id,
+                    category: "model",
+                    operation: "placement",
+                    object_name,
+                    ..
+                } => {
+                    self.handler
+                        .model_placement(msg, account_id, lattice_id, object_name)
+                        .await
+                }
+                ParsedSubject {
+                    account_id,
+                    lattice_id,
+                    category: "lattice",
+                    operation: "topology",
+                    object_name: None,
+                    ..
+                } => {
+                    self.handler
+                        .lattice_topology(msg, account_id, lattice_id)
+                        .await
+                }
+                ParsedSubject {
+                    account_id: _,
+                    lattice_id,
+                    category: "lattice",
+                    operation: "shard",
+                    object_name: None,
+                    ..
+                } => self.handler.shard_status(msg, lattice_id).await,
+                ParsedSubject {
+                    account_id,
+                    lattice_id,
+                    category: "host",
+                    operation: operation @ ("cordon" | "uncordon"),
+                    object_name: Some(host_id),
+                    ..
+                } => {
+                    self.handler
+                        .cordon_host(msg, account_id, lattice_id, host_id, operation == "cordon")
+                        .await
+                }
+                ParsedSubject {
+                    account_id,
+                    lattice_id,
+                    category: "audit",
+                    operation: "query",
+                    object_name: None,
+                    ..
+                } => {
+                    self.handler
+                        .query_audit_log(msg, account_id, lattice_id)
+                        .await
+                }
+                ParsedSubject {
+                    account_id: _,
+                    lattice_id,
+                    category: "dlq",
+                    operation: "list",
+                    object_name: None,
+                    ..
+                } => self.handler.list_dead_letters(msg, lattice_id).await,
+                ParsedSubject {
+                    account_id: _,
+                    lattice_id,
+                    category: "dlq",
+                    operation: "replay",
+                    object_name: None,
+                    ..
+                } => self.handler.replay_dead_letters(msg, lattice_id).await,
                 ParsedSubject {
                     account_id: _,
                     lattice_id: _,
                     category: "model",
                     operation: "history",
                     object_name: Some(_name),
+                    ..
                 } => {
                     // TODO(thomastaylor312): For now I don't want to figure out how we want to
                     // store this history. Obviously it should be a different key (which we don't
@@ -227,49 +611,95 @@ impl<P: Publisher> Server<P> {
         Err(anyhow::anyhow!("Subscriber terminated"))
     }
 
+    /// Returns the admin subject (like `admin.reload`) the given subject is for, if it is one
+    fn admin_operation<'a>(&self, subject: &'a str) -> Option<&'a str> {
+        split_subject(&self.prefix, self.multitenant, subject)
+            .ok()
+            .map(|(_, rest)| rest)
+            .filter(|rest| rest.starts_with("admin."))
+    }
+
     fn parse_subject<'a>(&self, subject: &'a str) -> anyhow::Result<ParsedSubject<'a>> {
-        // Topic structure: wadm.api.{lattice-id}.{category}.{operation}.{object}
-        // Multitenant topic structure: {account-id}.wadm.api.{lattice-id}.{category}.{operation}.{object}
-        // First, clean off the account if multitenant, then prefix and then split and iterate
-        let (account_id, subject) = if self.multitenant {
-            if let Some((account_id, rest)) = subject.split_once('.') {
-                (Some(account_id), rest)
-            } else {
-                anyhow::bail!("Expected to find account ID in multitenant subject")
-            }
+        parse_subject(&self.prefix, self.multitenant, subject)
+    }
+}
+
+/// Splits the account ID (in multitenant mode), the API prefix and the API version off the front
+/// of the subject, returning the account ID and the rest of the subject.
+///
+/// Subjects without a version are the compatibility shim for clients from before the API was
+/// versioned, and are answered as the current version. A segment matching the version right after
+/// the prefix is always the version, so a lattice named like the version (`v1`) can only be reached
+/// with versioned subjects, like `wadm.api.v1.v1.model.list`
+fn split_subject<'a>(
+    prefix: &str,
+    multitenant: bool,
+    subject: &'a str,
+) -> anyhow::Result<(Option<&'a str>, &'a str)> {
+    // Multitenant subjects have the account ID in front of the prefix
+    let (account_id, subject) = if multitenant {
+        if let Some((account_id, rest)) = subject.split_once('.') {
+            (Some(account_id), rest)
         } else {
-            (None, subject)
-        };
+            anyhow::bail!("Expected to find account ID in multitenant subject")
+        }
+    } else {
+        (None, subject)
+    };
+    let rest = subject
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .ok_or_else(|| anyhow::anyhow!("Expected subject to start with {prefix}"))?;
+    let rest = rest
+        .strip_prefix(API_VERSION)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .unwrap_or(rest);
+    Ok((account_id, rest))
+}
 
-        let mut trimmed = subject
-            .trim_start_matches(&self.prefix)
-            .trim_start_matches('.')
-            .split('.')
-            .fuse();
+fn parse_subject<'a>(
+    prefix: &str,
+    multitenant: bool,
+    subject: &'a str,
+) -> anyhow::Result<ParsedSubject<'a>> {
+    // Topic structure: wadm.api.{lattice-id}.{category}.{operation}.{object}
+    // Versioned topic structure: wadm.api.v1.{lattice-id}.{category}.{operation}.{object}
+    // Multitenant topic structure: {account-id}.wadm.api.{lattice-id}.{category}.{operation}.{object}
+    // Read-only topic structure: wadm.api.{lattice-id}.ro.{category}.{operation}.{object}
+    // First, clean off the account if multitenant, then prefix and version and then split and
+    // iterate
+    let (account_id, subject) = split_subject(prefix, multitenant, subject)?;
+    let mut trimmed = subject.split('.').fuse();
 
-        let lattice_id = trimmed
-            .next()
-            .ok_or_else(|| anyhow::anyhow!("Expected to find lattice ID"))?;
-        let category = trimmed
+    let lattice_id = trimmed
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Expected to find lattice ID"))?;
+    let mut category = trimmed
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Expected to find API category"))?;
+    let read_only = category == READ_ONLY_API_SEGMENT;
+    if read_only {
+        category = trimmed
             .next()
             .ok_or_else(|| anyhow::anyhow!("Expected to find API category"))?;
-        let operation = trimmed
-            .next()
-            .ok_or_else(|| anyhow::anyhow!("Expected to find operation"))?;
-        // Some commands don't have names, so this is optional
-        let object_name = trimmed.next();
-        // Catch malformed long subjects
-        if trimmed.next().is_some() {
-            anyhow::bail!("Found extra components of subject, ensure your manifest name consists of only alphanumeric characters, dashes, and underscores.")
-        }
-        Ok(ParsedSubject {
-            account_id,
-            lattice_id,
-            category,
-            operation,
-            object_name,
-        })
     }
+    let operation = trimmed
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Expected to find operation"))?;
+    // Some commands don't have names, so this is optional
+    let object_name = trimmed.next();
+    // Catch malformed long subjects
+    if trimmed.next().is_some() {
+        anyhow::bail!("Found extra components of subject, ensure your manifest name consists of only alphanumeric characters, dashes, and underscores.")
+    }
+    Ok(ParsedSubject {
+        account_id,
+        lattice_id,
+        category,
+        operation,
+        object_name,
+        read_only,
+    })
 }
 
 struct ParsedSubject<'a> {
@@ -278,4 +708,76 @@ struct ParsedSubject<'a> {
     category: &'a str,
     operation: &'a str,
     object_name: Option<&'a str>,
+    /// Whether the request came in on the read-only API
+    read_only: bool,
+}
+
+impl ParsedSubject<'_> {
+    /// Returns whether this is an operation that doesn't change anything, and so is allowed on the
+    /// read-only API
+    fn is_read_operation(&self) -> bool {
+        matches!(
+            (self.category, self.operation),
+            (
+                "model",
+                "get"
+                    | "list"
+                    | "versions"
+                    | "status"
+                    | "history"
+                    | "lint"
+                    | "placement"
+                    | "export"
+            ) | ("lattice", "topology" | "shard")
+                | ("dlq", "list")
+                | ("audit", "query")
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_versioned_and_unversioned_subjects() {
+        let parse = |subject| parse_subject("wadm.api", false, subject).unwrap();
+        for subject in [
+            "wadm.api.default.model.get.echo",
+            "wadm.api.v1.default.model.get.echo",
+        ] {
+            let parsed = parse(subject);
+            assert_eq!(
+                (parsed.lattice_id, parsed.category, parsed.operation),
+                ("default", "model", "get"),
+                "{subject} should be parsed"
+            );
+            assert_eq!(parsed.object_name, Some("echo"));
+        }
+        let parsed = parse("wadm.api.v1.default.ro.model.list");
+        assert!(parsed.read_only);
+        assert_eq!(parsed.lattice_id, "default");
+        let parsed = parse("wadm.api.v1.v1.model.get.echo");
+        assert_eq!(
+            (parsed.lattice_id, parsed.category, parsed.operation),
+            ("v1", "model", "get"),
+            "A lattice named like the version should work with a version"
+        );
+        assert_eq!(parsed.object_name, Some("echo"));
+        // Without a version, the lattice name is taken as the version rather than misrouting the
+        // request to a lattice named like the category
+        assert!(parse_subject("wadm.api", false, "wadm.api.v1.model.list").is_err());
+
+        let parsed =
+            parse_subject("wadm.api", true, "ACCOUNT.wadm.api.v1.default.model.list").unwrap();
+        assert_eq!(parsed.account_id, Some("ACCOUNT"));
+        assert_eq!(parsed.lattice_id, "default");
+        assert_eq!(
+            split_subject("wadm.api", false, "wadm.api.v1.admin.info")
+                .unwrap()
+                .1,
+            "admin.info"
+        );
+        assert!(parse_subject("wadm.api", false, "other.api.default.model.list").is_err());
+    }
 }
@@ -1,16 +1,35 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
 use cloudevents::Event as CloudEvent;
-use tracing::{instrument, trace};
-use wadm_types::Manifest;
+use tracing::{instrument, trace, warn};
+use wadm_types::{Manifest, NotificationRoute};
 
 use crate::{
-    events::{Event, ManifestPublished, ManifestUnpublished},
+    events::{Event, ManifestPaused, ManifestPublished, ManifestResumed, ManifestUnpublished},
+    nats_utils::lattice_subject,
+    notifications::{LifecycleEvent, LifecycleNotifier},
     publisher::Publisher,
 };
 
+/// A set of named webhooks that applications can route their lifecycle notifications to
+#[async_trait]
+pub trait NotificationWebhooks {
+    /// Returns whether a webhook with the given name is configured
+    fn contains(&self, name: &str) -> bool;
+
+    /// Sends the given event to the webhook with the given name
+    async fn send(&self, name: &str, event: &CloudEvent) -> anyhow::Result<()>;
+}
+
 /// A notifier that publishes changes about manifests with the given publisher
+#[derive(Clone)]
 pub struct ManifestNotifier<P> {
     prefix: String,
     publisher: P,
+    route_publisher: Option<Arc<dyn Publisher + Send + Sync>>,
+    webhooks: Option<Arc<dyn NotificationWebhooks + Send + Sync>>,
+    lifecycle: LifecycleNotifier,
 }
 
 impl<P: Publisher> ManifestNotifier<P> {
@@ -21,44 +40,210 @@ impl<P: Publisher> ManifestNotifier<P> {
         ManifestNotifier {
             prefix: prefix.trim().trim_matches(trimmer).to_owned(),
             publisher,
+            route_publisher: None,
+            webhooks: None,
+            lifecycle: LifecycleNotifier::default(),
+        }
+    }
+
+    /// Sets the notifier that is told about deploys, undeploys and rollbacks
+    pub fn with_lifecycle_notifier(mut self, lifecycle: LifecycleNotifier) -> ManifestNotifier<P> {
+        self.lifecycle = lifecycle;
+        self
+    }
+
+    /// Sets the publisher used to send notifications to the subjects applications route them to.
+    /// Unlike the main publisher, this shouldn't require the subject to be part of a stream. If not
+    /// set, notifications aren't sent to subjects
+    pub fn with_route_publisher(
+        mut self,
+        publisher: impl Publisher + Send + Sync + 'static,
+    ) -> ManifestNotifier<P> {
+        self.route_publisher = Some(Arc::new(publisher));
+        self
+    }
+
+    /// Sets the webhooks applications can route notifications to. If not set, manifests that route
+    /// notifications to a webhook are rejected
+    pub fn with_webhooks(
+        mut self,
+        webhooks: impl NotificationWebhooks + Send + Sync + 'static,
+    ) -> ManifestNotifier<P> {
+        self.webhooks = Some(Arc::new(webhooks));
+        self
+    }
+
+    /// Checks that every webhook the manifest routes notifications to is configured, returning an
+    /// error message naming the first one that isn't
+    pub fn check_routes(&self, manifest: &Manifest) -> Result<(), String> {
+        for route in manifest.notification_routes() {
+            let NotificationRoute::Webhook(name) = route else {
+                continue;
+            };
+            if !self
+                .webhooks
+                .as_ref()
+                .is_some_and(|webhooks| webhooks.contains(&name))
+            {
+                return Err(format!(
+                    "Manifest routes notifications to webhook '{name}', which is not configured in wadm"
+                ));
+            }
         }
+        Ok(())
     }
 
     #[instrument(level = "trace", skip(self))]
     async fn send_event(
         &self,
+        account_id: Option<&str>,
         lattice_id: &str,
         event_subject_key: &str,
         event: Event,
+        routes: Vec<NotificationRoute>,
     ) -> anyhow::Result<()> {
         let event: CloudEvent = event.try_into()?;
+        let data = serde_json::to_vec(&event)?;
         // NOTE(thomastaylor312): A future improvement could be retries here
         trace!("Sending notification event");
         self.publisher
             .publish(
-                serde_json::to_vec(&event)?,
-                Some(&format!("{}.{lattice_id}.{event_subject_key}", self.prefix)),
+                data.clone(),
+                Some(&format!(
+                    "{}.{event_subject_key}",
+                    lattice_subject(&self.prefix, lattice_id, account_id)
+                )),
             )
+            .await?;
+        if !routes.is_empty() {
+            self.send_to_routes(routes, event, data);
+        }
+        Ok(())
+    }
+
+    /// Sends the event to the routes declared by the application in the background. These are a
+    /// courtesy for the teams that own the application, so failures are only logged
+    fn send_to_routes(&self, routes: Vec<NotificationRoute>, event: CloudEvent, data: Vec<u8>) {
+        let route_publisher = self.route_publisher.clone();
+        let webhooks = self.webhooks.clone();
+        tokio::spawn(async move {
+            for route in routes {
+                let res = match &route {
+                    NotificationRoute::Subject(subject) => match route_publisher.as_ref() {
+                        Some(publisher) => publisher.publish(data.clone(), Some(subject)).await,
+                        None => Err(anyhow::anyhow!("No publisher configured for subjects")),
+                    },
+                    NotificationRoute::Webhook(name) => match webhooks.as_ref() {
+                        Some(webhooks) => webhooks.send(name, &event).await,
+                        None => Err(anyhow::anyhow!("No webhooks configured")),
+                    },
+                };
+                if let Err(e) = res {
+                    warn!(error = ?e, ?route, "Unable to send notification to application route");
+                }
+            }
+        });
+    }
+
+    pub async fn deployed(
+        &self,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        manifest: Manifest,
+    ) -> anyhow::Result<()> {
+        self.lifecycle.notify(
+            lattice_id,
+            &manifest.metadata.name,
+            LifecycleEvent::Deployed {
+                version: manifest.version().to_owned(),
+            },
+        );
+        self.publish_deployed(account_id, lattice_id, manifest)
             .await
     }
 
-    pub async fn deployed(&self, lattice_id: &str, manifest: Manifest) -> anyhow::Result<()> {
+    /// Sends a deployed notification for the version of the manifest that was rolled back to
+    pub async fn rolled_back(
+        &self,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        from_version: &str,
+        manifest: Manifest,
+    ) -> anyhow::Result<()> {
+        self.lifecycle.notify(
+            lattice_id,
+            &manifest.metadata.name,
+            LifecycleEvent::RolledBack {
+                from_version: from_version.to_owned(),
+                to_version: manifest.version().to_owned(),
+            },
+        );
+        self.publish_deployed(account_id, lattice_id, manifest)
+            .await
+    }
+
+    async fn publish_deployed(
+        &self,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        manifest: Manifest,
+    ) -> anyhow::Result<()> {
+        let routes = manifest.notification_routes();
         self.send_event(
+            account_id,
             lattice_id,
             "manifest_published",
             Event::ManifestPublished(ManifestPublished { manifest }),
+            routes,
         )
         .await
     }
 
-    pub async fn undeployed(&self, lattice_id: &str, name: &str) -> anyhow::Result<()> {
+    /// Sends an undeployed notification. The routes should come from the manifest that was
+    /// deployed, as it might not be stored anymore
+    pub async fn undeployed(
+        &self,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        name: &str,
+        routes: Vec<NotificationRoute>,
+    ) -> anyhow::Result<()> {
+        self.lifecycle
+            .notify(lattice_id, name, LifecycleEvent::Undeployed);
         self.send_event(
+            account_id,
             lattice_id,
             "manifest_unpublished",
             Event::ManifestUnpublished(ManifestUnpublished {
                 name: name.to_owned(),
             }),
+            routes,
         )
         .await
     }
+
+    /// Sends a notification that reconciliation of the given manifest was paused or resumed
+    pub async fn paused(
+        &self,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        name: &str,
+        paused: bool,
+        routes: Vec<NotificationRoute>,
+    ) -> anyhow::Result<()> {
+        let name = name.to_owned();
+        let (event_subject_key, event) = if paused {
+            (
+                "manifest_paused",
+                Event::ManifestPaused(ManifestPaused { name }),
+            )
+        } else {
+            (
+                "manifest_resumed",
+                Event::ManifestResumed(ManifestResumed { name }),
+            )
+        };
+        self.send_event(account_id, lattice_id, event_subject_key, event, routes)
+            .await
+    }
 }
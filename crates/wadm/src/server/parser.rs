@@ -1,7 +1,15 @@
+use std::collections::BTreeMap;
+
+use anyhow::Context;
 use async_nats::HeaderMap;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
 
+use wadm_types::api::{TEMPLATE_OVERLAY_HEADER, TEMPLATE_VARIABLES_HEADER};
 use wadm_types::Manifest;
 
+use crate::model::{is_template, render_manifest};
+
 /// The name of the header in the NATS request to use for content type inference. The header value
 /// should be a valid MIME type
 pub const CONTENT_TYPE_HEADER: &str = "Content-Type";
@@ -14,16 +22,44 @@ const JSON_MIME: &str = "application/json";
 /// Parse the incoming bytes to a manifest
 ///
 /// This function takes the optional headers from a NATS request to use them as a type hint for
-/// parsing
+/// parsing. If the manifest is a template, or the headers give template variables or an overlay,
+/// the manifest is rendered with them before it is returned
 pub fn parse_manifest(data: Vec<u8>, headers: Option<&HeaderMap>) -> anyhow::Result<Manifest> {
     // There is far too much cloning here, but there is no way to just return a reference to a &str
     let content_type = headers
         .and_then(|map| map.get(CONTENT_TYPE_HEADER).cloned())
         .map(|value| value.as_str().to_owned());
+    let variables: BTreeMap<String, String> =
+        header_json(headers, TEMPLATE_VARIABLES_HEADER)?.unwrap_or_default();
+    let overlay: Option<Value> = header_json(headers, TEMPLATE_OVERLAY_HEADER)?;
+
+    match parse_as::<Value>(&data, content_type.as_deref()) {
+        Ok(document) if is_template(&document) || !variables.is_empty() || overlay.is_some() => {
+            render_manifest(document, &variables, overlay.as_ref())
+        }
+        _ => parse_as(&data, content_type.as_deref()),
+    }
+}
+
+/// Parses the JSON value of a header, if it is set
+fn header_json<T: DeserializeOwned>(
+    headers: Option<&HeaderMap>,
+    name: &str,
+) -> anyhow::Result<Option<T>> {
+    headers
+        .and_then(|map| map.get(name))
+        .map(|value| {
+            serde_json::from_str(value.as_str())
+                .with_context(|| format!("Unable to parse the {name} header"))
+        })
+        .transpose()
+}
+
+fn parse_as<T: DeserializeOwned>(data: &[u8], content_type: Option<&str>) -> anyhow::Result<T> {
     if let Some(content_type) = content_type {
-        match content_type.as_str() {
-            JSON_MIME => serde_json::from_slice(&data).map_err(anyhow::Error::from),
-            YAML_MIME => serde_yaml::from_slice(&data).map_err(anyhow::Error::from),
+        match content_type {
+            JSON_MIME => serde_json::from_slice(data).map_err(anyhow::Error::from),
+            YAML_MIME => serde_yaml::from_slice(data).map_err(anyhow::Error::from),
             _ => {
                 // If the user passed a non-supported mime type, we should let them know rather than
                 // just falling back
@@ -38,9 +74,9 @@ pub fn parse_manifest(data: Vec<u8>, headers: Option<&HeaderMap>) -> anyhow::Res
 }
 
 /// Parse the bytes as yaml or json (in that order)
-fn parse_yaml_or_json(data: Vec<u8>) -> anyhow::Result<Manifest> {
-    serde_yaml::from_slice(&data).or_else(|e| {
-        serde_json::from_slice(&data).map_err(|err| {
+fn parse_yaml_or_json<T: DeserializeOwned>(data: &[u8]) -> anyhow::Result<T> {
+    serde_yaml::from_slice(data).or_else(|e| {
+        serde_json::from_slice(data).map_err(|err| {
             // Combine both errors in case one was a legit parsing failure due to invalid data
             anyhow::anyhow!("JSON parsing failed: {err:?}")
                 .context(format!("YAML parsing failed: {e:?}"))
@@ -0,0 +1,134 @@
+//! Quotas on the models stored in each lattice, so a single tenant of a shared wadm can't store an
+//! unbounded number of models, versions or instances
+
+use wadm_types::{Manifest, TraitProperty};
+
+/// Limits enforced when models are put and deployed. Each limit applies separately to every
+/// lattice (and account, in multitenant mode). Limits that aren't set aren't enforced
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ManifestQuotas {
+    /// The most models that can be stored in a lattice
+    pub max_models: Option<usize>,
+    /// The most instances a single version of a model can ask for, summed over all of its
+    /// components. Autoscalers and schedules count with the most instances they can scale to
+    pub max_replicas: Option<usize>,
+    /// The most versions kept for each model. When a new version is put, the oldest versions that
+    /// aren't deployed are removed to stay within the limit
+    pub max_versions: Option<usize>,
+}
+
+impl ManifestQuotas {
+    /// Returns an error if a new model can't be added to a lattice that already has the given
+    /// number of models
+    pub(crate) fn check_new_model(&self, existing_models: usize) -> Result<(), String> {
+        match self.max_models {
+            Some(max) if existing_models >= max => Err(format!(
+                "Lattice already has {existing_models} applications, which is the most allowed ({max}). Delete an application before putting a new one"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns an error if the manifest asks for more instances than allowed
+    pub(crate) fn check_replicas(&self, manifest: &Manifest) -> Result<(), String> {
+        let Some(max) = self.max_replicas else {
+            return Ok(());
+        };
+        let replicas = total_replicas(manifest);
+        if replicas > max {
+            return Err(format!(
+                "Application {} version {} asks for up to {replicas} instances, but at most {max} are allowed per application",
+                manifest.metadata.name,
+                manifest.version()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the most instances the manifest can run, summed over all of its components
+pub(crate) fn total_replicas(manifest: &Manifest) -> usize {
+    manifest
+        .components()
+        .map(|component| {
+            component
+                .traits
+                .iter()
+                .flatten()
+                .map(|t| match &t.properties {
+                    TraitProperty::SpreadScaler(props) => props.instances,
+                    TraitProperty::Autoscaler(props) => {
+                        props.max_instances.max(props.min_instances)
+                    }
+                    TraitProperty::Schedule(props) => props
+                        .windows
+                        .iter()
+                        .map(|window| window.instances)
+                        .max()
+                        .unwrap_or_default(),
+                    _ => 0,
+                })
+                .max()
+                .unwrap_or_default()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use wadm_types::VERSION_ANNOTATION_KEY;
+
+    use super::*;
+    use crate::model::StoredManifest;
+
+    #[test]
+    fn enforces_manifest_quotas() {
+        let manifest: Manifest = serde_yaml::from_slice(
+            &std::fs::read("../../tests/fixtures/manifests/autoscaler.wadm.yaml").unwrap(),
+        )
+        .unwrap();
+        let replicas = total_replicas(&manifest);
+        assert_eq!(
+            replicas, 15,
+            "Autoscaled components should count with the most instances they can scale to"
+        );
+
+        let unlimited = ManifestQuotas::default();
+        assert!(unlimited.check_new_model(usize::MAX).is_ok());
+        assert!(unlimited.check_replicas(&manifest).is_ok());
+
+        let quotas = ManifestQuotas {
+            max_models: Some(2),
+            max_replicas: Some(replicas),
+            max_versions: None,
+        };
+        assert!(quotas.check_new_model(1).is_ok());
+        assert!(quotas.check_new_model(2).is_err());
+        assert!(quotas.check_replicas(&manifest).is_ok());
+        let quotas = ManifestQuotas {
+            max_replicas: Some(replicas - 1),
+            ..quotas
+        };
+        assert!(quotas.check_replicas(&manifest).is_err());
+
+        let mut stored = StoredManifest::default();
+        for version in ["v1", "v2", "v3", "v4"] {
+            let mut manifest = manifest.clone();
+            manifest
+                .metadata
+                .annotations
+                .insert(VERSION_ANNOTATION_KEY.to_string(), version.to_string());
+            stored.add_version(manifest);
+            if version == "v1" {
+                stored.deploy(None);
+            }
+        }
+        assert_eq!(stored.prune_versions(2), vec!["v2", "v3"]);
+        assert_eq!(
+            stored.all_versions().into_iter().collect::<Vec<_>>(),
+            vec!["v1", "v4"],
+            "The deployed and current versions should be kept"
+        );
+        assert!(stored.prune_versions(1).is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+//! Contains a watcher that rolls a deployed manifest back to its previous version if the new
+//! version doesn't finish deploying in time
+
+use std::time::Duration;
+
+use async_nats::{jetstream::stream::Stream, Client};
+use tokio::time::Instant;
+use tracing::{debug, error, info, instrument, trace, warn};
+use wadm_types::api::{Status, StatusInfo, StatusType};
+
+use crate::publisher::Publisher;
+
+use super::{storage::ModelStorage, ManifestNotifier};
+
+/// How often the status of a deploy is checked while it is being watched
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Watches newly deployed versions and redeploys the previous version of a manifest if the new one
+/// fails to reach a deployed status within the configured timeout
+// NOTE: Watches only live in memory on the wadm instance that handled the deploy request. If that
+// instance goes away before the timeout, the deploy simply won't be rolled back
+#[derive(Clone)]
+pub(crate) struct RollbackWatcher<P> {
+    pub(crate) store: ModelStorage,
+    pub(crate) client: Client,
+    pub(crate) notifier: ManifestNotifier<P>,
+    pub(crate) status_stream: Stream,
+    pub(crate) timeout: Duration,
+}
+
+impl<P: Publisher + Clone + Send + Sync + 'static> RollbackWatcher<P> {
+    /// Starts watching the given deploy generation of a manifest in the background
+    pub fn watch(
+        &self,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        name: &str,
+        version: &str,
+        generation: u64,
+    ) {
+        let watcher = self.clone();
+        let account_id = account_id.map(ToOwned::to_owned);
+        let lattice_id = lattice_id.to_owned();
+        let name = name.to_owned();
+        let version = version.to_owned();
+        tokio::spawn(async move {
+            watcher
+                .watch_deploy(
+                    account_id.as_deref(),
+                    &lattice_id,
+                    &name,
+                    &version,
+                    generation,
+                )
+                .await
+        });
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn watch_deploy(
+        &self,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        name: &str,
+        version: &str,
+        generation: u64,
+    ) {
+        let subject = format!("wadm.status.{lattice_id}.{name}");
+        // Any status published before the deploy is for the old version, so we only count
+        // statuses newer than this as the new version converging
+        let initial_sequence = self.last_status(&subject).await.map(|(seq, _)| seq);
+        let deadline = Instant::now() + self.timeout;
+
+        let converged = loop {
+            let status = self.last_status(&subject).await;
+            match status {
+                Some((seq, StatusType::Deployed))
+                    if initial_sequence.is_none_or(|initial| seq > initial) =>
+                {
+                    break true
+                }
+                // Once we're out of time, a deployed status counts no matter when it was published.
+                // A deploy that didn't change anything won't publish a new status
+                Some((_, status_type)) if Instant::now() >= deadline => {
+                    break status_type == StatusType::Deployed
+                }
+                None if Instant::now() >= deadline => break false,
+                _ => {}
+            }
+            tokio::time::sleep(CHECK_INTERVAL.min(self.timeout)).await;
+        };
+
+        if converged {
+            debug!("Deployed version converged, no rollback needed");
+            return;
+        }
+
+        if let Err(e) = self
+            .rollback(account_id, lattice_id, name, version, generation, &subject)
+            .await
+        {
+            error!(error = ?e, "Unable to roll back failed deploy");
+        }
+    }
+
+    async fn rollback(
+        &self,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        name: &str,
+        version: &str,
+        generation: u64,
+        subject: &str,
+    ) -> anyhow::Result<()> {
+        let (mut manifests, current_revision) =
+            match self.store.get(account_id, lattice_id, name).await? {
+                Some(m) => m,
+                None => {
+                    debug!("Application was deleted, skipping rollback");
+                    return Ok(());
+                }
+            };
+        if manifests.deploy_generation() != generation {
+            debug!("Application was deployed or undeployed since this deploy, skipping rollback");
+            return Ok(());
+        }
+        let previous = match manifests.rollback() {
+            Some(v) => v,
+            None => {
+                warn!("Deployed version did not converge, but there is no version to roll back to");
+                return Ok(());
+            }
+        };
+        // SAFETY: Rolling back only succeeds if the previous version exists
+        let manifest = manifests
+            .get_version(&previous)
+            .expect("Rolled back version should exist")
+            .to_owned();
+
+        info!(%previous, "Deployed version did not converge in time, rolling back");
+        self.store
+            .set(account_id, lattice_id, manifests, Some(current_revision))
+            .await?;
+
+        let status = Status::new(
+            StatusInfo::failed(&format!(
+                "Version {version} did not finish deploying within {} seconds, rolled back to version {previous}",
+                self.timeout.as_secs()
+            )),
+            Vec::new(),
+        );
+        trace!(?status, "Publishing rollback status");
+        if let Err(e) = self
+            .client
+            .publish(subject.to_owned(), serde_json::to_vec(&status)?.into())
+            .await
+        {
+            warn!(error = ?e, "Unable to publish rollback status");
+        }
+
+        self.notifier
+            .rolled_back(account_id, lattice_id, version, manifest)
+            .await
+    }
+
+    /// Returns the stream sequence and status type of the last status published for a manifest
+    async fn last_status(&self, subject: &str) -> Option<(u64, StatusType)> {
+        let msg = self
+            .status_stream
+            .get_last_raw_message_by_subject(subject)
+            .await
+            .ok()?;
+        serde_json::from_slice::<Status>(&msg.payload)
+            .ok()
+            .map(|status| (msg.sequence, status.info.status_type))
+    }
+}
@@ -0,0 +1,232 @@
+//! Contains helpers for undeploying shared applications along with the applications that depend on
+//! them. Dependent applications are torn down first, so shared infrastructure like providers stays
+//! up until everything using it has had time to drain
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use tracing::{debug, error, info, instrument};
+
+use crate::{model::StoredManifest, publisher::Publisher};
+
+use super::{storage::ModelStorage, ManifestNotifier};
+
+/// The default amount of time to wait between stages of a cascading undeploy
+pub const DEFAULT_UNDEPLOY_STAGE_DELAY: Duration = Duration::from_secs(5);
+
+/// Returns the deployed applications that need to be undeployed along with the given one, grouped
+/// into stages in reverse dependency order. Applications in the first stage have no deployed
+/// dependents, and the given application is always in the last stage.
+///
+/// If nothing depends on the given application, this returns a single stage containing only it
+pub(crate) fn undeploy_stages(name: &str, models: &[StoredManifest]) -> Vec<Vec<String>> {
+    // Map of each deployed application to the shared applications it depends on
+    let dependencies: BTreeMap<&str, BTreeSet<&str>> = models
+        .iter()
+        .filter_map(|model| {
+            model
+                .get_deployed()
+                .map(|manifest| (model.name(), manifest.shared_dependencies()))
+        })
+        .collect();
+
+    // Find everything that transitively depends on the application being undeployed
+    let mut remaining = BTreeSet::from([name]);
+    loop {
+        let dependents: Vec<&str> = dependencies
+            .iter()
+            .filter(|(app, deps)| {
+                !remaining.contains(*app) && deps.iter().any(|dep| remaining.contains(dep))
+            })
+            .map(|(app, _)| *app)
+            .collect();
+        if dependents.is_empty() {
+            break;
+        }
+        remaining.extend(dependents);
+    }
+
+    let mut stages = Vec::new();
+    while !remaining.is_empty() {
+        // An application can be undeployed once nothing left to undeploy depends on it
+        let stage: Vec<&str> = remaining
+            .iter()
+            .filter(|app| {
+                !remaining.iter().any(|other| {
+                    dependencies
+                        .get(other)
+                        .is_some_and(|deps| deps.contains(*app))
+                })
+            })
+            .copied()
+            .collect();
+        // NOTE: This can only be empty if there is a dependency cycle, in which case there is no
+        // right order, so we undeploy everything left at once
+        let stage = if stage.is_empty() {
+            remaining.iter().copied().collect()
+        } else {
+            stage
+        };
+        for app in stage.iter() {
+            remaining.remove(app);
+        }
+        stages.push(stage.into_iter().map(ToOwned::to_owned).collect());
+    }
+    stages
+}
+
+/// Undeploys applications one stage at a time, waiting between each stage
+#[derive(Clone)]
+pub(crate) struct StagedUndeploy<P> {
+    pub(crate) store: ModelStorage,
+    pub(crate) notifier: ManifestNotifier<P>,
+}
+
+impl<P: Publisher + Clone + Send + Sync + 'static> StagedUndeploy<P> {
+    /// Undeploys the given application and notifies the processors, returning an error if it
+    /// couldn't be stored or the notification couldn't be sent. Applications that no longer exist
+    /// are skipped
+    #[instrument(level = "debug", skip(self))]
+    pub async fn undeploy(
+        &self,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        name: &str,
+    ) -> anyhow::Result<()> {
+        let Some((mut manifests, current_revision)) =
+            self.store.get(account_id, lattice_id, name).await?
+        else {
+            debug!("Application no longer exists, skipping");
+            return Ok(());
+        };
+        let routes = manifests.notification_routes();
+        if manifests.undeploy() {
+            self.store
+                .set(account_id, lattice_id, manifests, Some(current_revision))
+                .await?;
+        }
+        self.notifier
+            .undeployed(account_id, lattice_id, name, routes)
+            .await
+    }
+
+    /// Undeploys the given stages in the background, waiting the given delay before each one. If
+    /// any application in a stage can't be undeployed, the later stages are left deployed so that
+    /// shared applications don't disappear while something still uses them
+    pub fn spawn(
+        &self,
+        account_id: Option<&str>,
+        lattice_id: &str,
+        stages: Vec<Vec<String>>,
+        delay: Duration,
+    ) {
+        let undeployer = self.clone();
+        let account_id = account_id.map(ToOwned::to_owned);
+        let lattice_id = lattice_id.to_owned();
+        tokio::spawn(async move {
+            for stage in stages {
+                tokio::time::sleep(delay).await;
+                for name in stage.iter() {
+                    if let Err(e) = undeployer
+                        .undeploy(account_id.as_deref(), &lattice_id, name)
+                        .await
+                    {
+                        error!(error = ?e, %lattice_id, %name, "Unable to undeploy application, stopping cascading undeploy");
+                        return;
+                    }
+                }
+                info!(%lattice_id, applications = ?stage, "Undeployed stage of cascading undeploy");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wadm_types::{
+        CapabilityProperties, Component, Manifest, Metadata, Properties,
+        SharedApplicationComponentProperties, Specification, SHARED_ANNOTATION_KEY,
+    };
+
+    use super::*;
+
+    fn model(name: &str, shared: bool, uses: &[&str]) -> StoredManifest {
+        let mut manifest = Manifest {
+            api_version: "core.oam.dev/v1beta1".to_string(),
+            kind: "Application".to_string(),
+            metadata: Metadata {
+                name: name.to_string(),
+                annotations: Default::default(),
+                labels: Default::default(),
+            },
+            spec: Specification {
+                components: uses
+                    .iter()
+                    .map(|app| Component {
+                        name: format!("{app}-provider"),
+                        properties: Properties::Capability {
+                            properties: CapabilityProperties {
+                                image: None,
+                                application: Some(SharedApplicationComponentProperties {
+                                    name: app.to_string(),
+                                    component: "provider".to_string(),
+                                }),
+                                id: None,
+                                config: vec![],
+                                secrets: vec![],
+                                config_payload: None,
+                            },
+                        },
+                        traits: None,
+                    })
+                    .collect(),
+                policies: vec![],
+            },
+        };
+        if shared {
+            manifest
+                .metadata
+                .annotations
+                .insert(SHARED_ANNOTATION_KEY.to_string(), "true".to_string());
+        }
+        let mut model = StoredManifest::default();
+        model.add_version(manifest);
+        model.deploy(None);
+        model
+    }
+
+    #[test]
+    fn orders_undeploy_by_dependencies() {
+        let mut stale = model("stale", false, &["infra"]);
+        stale.undeploy();
+        let models = vec![
+            model("infra", true, &[]),
+            model("messaging", true, &["infra"]),
+            model("api", false, &["infra", "messaging"]),
+            model("worker", false, &["messaging"]),
+            model("other", false, &[]),
+            // Undeployed applications shouldn't be undeployed again
+            stale,
+        ];
+
+        assert_eq!(
+            undeploy_stages("infra", &models),
+            vec![
+                vec!["api".to_string(), "worker".to_string()],
+                vec!["messaging".to_string()],
+                vec!["infra".to_string()],
+            ]
+        );
+        assert_eq!(
+            undeploy_stages("messaging", &models),
+            vec![
+                vec!["api".to_string(), "worker".to_string()],
+                vec!["messaging".to_string()],
+            ]
+        );
+        assert_eq!(
+            undeploy_stages("other", &models),
+            vec![vec!["other".to_string()]]
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! Sharding of lattices across wadm replicas. This is an alternative to leader election for large
+//! installations with many lattices: instead of a single process leading each lattice on a first
+//! come, first served basis, lattices are spread evenly over all running replicas.
+//!
+//! Each replica periodically announces itself on a membership topic. Every replica builds the same
+//! consistent hash ring from the members it has heard from, so they all agree on which replica
+//! owns which lattice without any coordination. When a replica joins or leaves, only the lattices
+//! that hash to it move
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_nats::{Client, Subscriber};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, instrument, warn};
+
+use crate::clock::{self, SharedClock};
+
+/// The number of points each member gets on the hash ring. More points spread lattices more
+/// evenly between members
+const VIRTUAL_NODES: usize = 64;
+
+/// The message each replica publishes on the membership topic
+#[derive(Debug, Serialize, Deserialize)]
+struct Announcement {
+    member_id: String,
+    /// Set when the replica is shutting down, so the others can take over its lattices right away
+    #[serde(default)]
+    leaving: bool,
+}
+
+/// A consistent hash ring that maps keys to members
+#[derive(Debug, Clone, Default)]
+pub struct HashRing {
+    ring: BTreeMap<u64, String>,
+}
+
+impl HashRing {
+    /// Creates a ring containing the given members
+    pub fn new<S: AsRef<str>>(members: impl IntoIterator<Item = S>) -> HashRing {
+        let ring = members
+            .into_iter()
+            .flat_map(|member| {
+                let member = member.as_ref().to_owned();
+                (0..VIRTUAL_NODES).map(move |i| (hash(&format!("{member}-{i}")), member.clone()))
+            })
+            .collect();
+        HashRing { ring }
+    }
+
+    /// Returns the member that owns the given key, or `None` if the ring is empty
+    pub fn owner(&self, key: &str) -> Option<&str> {
+        self.ring
+            .range(hash(key)..)
+            .next()
+            .or_else(|| self.ring.iter().next())
+            .map(|(_, member)| member.as_str())
+    }
+}
+
+/// Hashes the given data. This has to be stable across processes and versions, so we can't use
+/// the standard library hasher
+fn hash(data: &str) -> u64 {
+    let digest = Sha256::digest(data.as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+struct MembershipState {
+    /// When each member other than this one last announced itself
+    last_seen: HashMap<String, Instant>,
+    ring: HashRing,
+}
+
+/// The set of wadm replicas sharing lattices, as seen by this replica. This is cheap to clone and
+/// all clones share the same state
+#[derive(Clone)]
+pub struct ShardMembership {
+    client: Client,
+    topic: String,
+    member_id: String,
+    ttl: Duration,
+    state: Arc<Mutex<MembershipState>>,
+    clock: SharedClock,
+}
+
+impl ShardMembership {
+    /// Creates a new membership for this replica. Members that haven't announced themselves
+    /// within the given TTL are considered gone. The member ID must be unique for each replica
+    pub fn new(
+        client: Client,
+        topic: impl Into<String>,
+        member_id: impl Into<String>,
+        ttl: Duration,
+    ) -> ShardMembership {
+        let member_id = member_id.into();
+        ShardMembership {
+            client,
+            topic: topic.into(),
+            ttl,
+            state: Arc::new(Mutex::new(MembershipState {
+                last_seen: HashMap::new(),
+                ring: HashRing::new([&member_id]),
+            })),
+            member_id,
+            clock: clock::system(),
+        }
+    }
+
+    /// Sets the clock used to expire members that haven't announced themselves within the TTL
+    pub fn with_clock(mut self, clock: SharedClock) -> ShardMembership {
+        self.clock = clock;
+        self
+    }
+
+    /// Returns the ID this replica is known by
+    pub fn member_id(&self) -> &str {
+        &self.member_id
+    }
+
+    /// Returns all known members, including this one, sorted by ID
+    pub fn members(&self) -> Vec<String> {
+        let mut members: Vec<String> = self.state().last_seen.keys().cloned().collect();
+        members.push(self.member_id.clone());
+        members.sort();
+        members
+    }
+
+    /// Returns the member that owns the given lattice
+    pub fn owner(&self, lattice_id: &str) -> String {
+        self.state()
+            .ring
+            .owner(lattice_id)
+            .unwrap_or(&self.member_id)
+            .to_owned()
+    }
+
+    /// Returns whether this replica owns the given lattice
+    pub fn owns(&self, lattice_id: &str) -> bool {
+        self.owner(lattice_id) == self.member_id
+    }
+
+    /// Subscribes to announcements from other members. Messages from this subscriber should be
+    /// passed to [`ShardMembership::record`]
+    pub async fn subscribe(&self) -> Result<Subscriber> {
+        self.client
+            .subscribe(self.topic.clone())
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))
+    }
+
+    /// Announces this replica to the other members. This should be called well within the TTL
+    #[instrument(level = "trace", skip(self))]
+    pub async fn announce(&self) -> Result<()> {
+        self.publish(false).await
+    }
+
+    /// Tells the other members this replica is going away so they can take over its lattices
+    pub async fn leave(&self) {
+        if let Err(e) = self.publish(true).await {
+            warn!(error = ?e, "Unable to announce departure, other members will notice once it expires");
+        }
+    }
+
+    /// Records an announcement received on the membership topic. Returns whether the set of
+    /// members changed
+    pub fn record(&self, payload: &[u8]) -> bool {
+        let announcement: Announcement = match serde_json::from_slice(payload) {
+            Ok(a) => a,
+            Err(e) => {
+                warn!(error = %e, "Ignoring invalid membership announcement");
+                return false;
+            }
+        };
+        if announcement.member_id == self.member_id {
+            return false;
+        }
+        let mut state = self.state();
+        let changed = if announcement.leaving {
+            state.last_seen.remove(&announcement.member_id).is_some()
+        } else {
+            state
+                .last_seen
+                .insert(announcement.member_id.clone(), self.clock.instant())
+                .is_none()
+        };
+        if changed {
+            info!(member_id = %announcement.member_id, leaving = announcement.leaving, "Shard membership changed");
+            self.rebuild(&mut state);
+        }
+        changed
+    }
+
+    /// Removes members that haven't announced themselves within the TTL. Returns whether the set
+    /// of members changed
+    pub fn expire_stale(&self) -> bool {
+        let now = self.clock.instant();
+        let mut state = self.state();
+        let before = state.last_seen.len();
+        state.last_seen.retain(|member_id, last_seen| {
+            let alive = now.saturating_duration_since(*last_seen) < self.ttl;
+            if !alive {
+                info!(%member_id, "Shard member expired");
+            }
+            alive
+        });
+        let changed = state.last_seen.len() != before;
+        if changed {
+            self.rebuild(&mut state);
+        }
+        changed
+    }
+
+    async fn publish(&self, leaving: bool) -> Result<()> {
+        let data = serde_json::to_vec(&Announcement {
+            member_id: self.member_id.clone(),
+            leaving,
+        })?;
+        self.client
+            .publish(self.topic.clone(), data.into())
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))
+    }
+
+    fn rebuild(&self, state: &mut MembershipState) {
+        state.ring = HashRing::new(
+            state
+                .last_seen
+                .keys()
+                .map(String::as_str)
+                .chain([self.member_id.as_str()]),
+        );
+        debug!(
+            members = state.last_seen.len() + 1,
+            "Rebuilt shard hash ring"
+        );
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MembershipState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hash_ring_only_moves_lattices_of_changed_members() {
+        let lattices: Vec<String> = (0..500).map(|i| format!("lattice-{i}")).collect();
+        let before = HashRing::new(["wadm-a", "wadm-b", "wadm-c"]);
+        let after = HashRing::new(["wadm-a", "wadm-b", "wadm-c", "wadm-d"]);
+
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for lattice in lattices.iter() {
+            let old = before.owner(lattice).unwrap();
+            let new = after.owner(lattice).unwrap();
+            assert!(
+                old == new || new == "wadm-d",
+                "Lattices should only move to the new member"
+            );
+            *counts.entry(new).or_default() += 1;
+        }
+        assert_eq!(counts.len(), 4, "Every member should own some lattices");
+        assert!(
+            counts.values().all(|count| *count > 50),
+            "Lattices should be spread evenly: {counts:?}"
+        );
+
+        // Every replica has to agree on the owner
+        let same = HashRing::new(["wadm-c", "wadm-a", "wadm-b"]);
+        assert!(lattices
+            .iter()
+            .all(|lattice| before.owner(lattice) == same.owner(lattice)));
+        assert_eq!(HashRing::default().owner("lattice-0"), None);
+    }
+}
@@ -0,0 +1,294 @@
+//! Rebuilding of the lattice state from what is actually running. Every resource wadm starts is
+//! annotated with the app it belongs to, so the state for each app can be reconstructed from the
+//! host inventories. This is used when upgrading from a wadm version that didn't keep state in KV
+//! or after the state bucket was lost
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::{debug, warn};
+
+use crate::events::HostHeartbeat;
+use crate::storage::{
+    Component, Host, Provider, ProviderStatus, StateKind, Store, WadmComponentInfo,
+};
+use crate::workers::{ClaimsSource, HostSource, InventorySource};
+use crate::{APP_SPEC_ANNOTATION, MANAGED_BY_ANNOTATION, MANAGED_BY_IDENTIFIER};
+
+/// The state of a lattice as found on its hosts
+#[derive(Debug, Default)]
+pub struct LatticeSnapshot {
+    pub hosts: HashMap<String, Host>,
+    pub components: HashMap<String, Component>,
+    pub providers: HashMap<String, Provider>,
+    /// Hosts that responded to the host query but whose inventory couldn't be fetched
+    pub unreachable_hosts: Vec<String>,
+}
+
+/// The resources found for a single app
+#[derive(Debug, Default, Clone, Serialize, PartialEq, Eq)]
+pub struct AppResources {
+    /// The number of running component instances
+    pub component_instances: usize,
+    /// The number of running providers, counted once per host
+    pub providers: usize,
+}
+
+impl LatticeSnapshot {
+    /// Returns the resources managed by wadm, grouped by the app they belong to
+    pub fn apps(&self) -> BTreeMap<String, AppResources> {
+        let mut apps: BTreeMap<String, AppResources> = BTreeMap::new();
+        for info in self
+            .components
+            .values()
+            .flat_map(|component| component.instances.values().flatten())
+        {
+            if let Some(app) = managed_app(&info.annotations) {
+                apps.entry(app.to_owned()).or_default().component_instances += info.count;
+            }
+        }
+        for provider in self.hosts.values().flat_map(|host| host.providers.iter()) {
+            if let Some(app) = managed_app(&provider.annotations) {
+                apps.entry(app.to_owned()).or_default().providers += 1;
+            }
+        }
+        apps
+    }
+}
+
+fn managed_app(annotations: &BTreeMap<String, String>) -> Option<&str> {
+    (annotations.get(MANAGED_BY_ANNOTATION).map(String::as_str) == Some(MANAGED_BY_IDENTIFIER))
+        .then(|| annotations.get(APP_SPEC_ANNOTATION).map(String::as_str))
+        .flatten()
+}
+
+/// Queries every host in the lattice for its inventory and builds the state wadm would have if it
+/// had seen every event. Hosts whose inventory can't be fetched are skipped and listed in the
+/// snapshot
+pub async fn scan_lattice<C>(client: &C) -> Result<LatticeSnapshot>
+where
+    C: HostSource + InventorySource + ClaimsSource + Sync,
+{
+    let claims = client.get_claims().await?;
+    let mut snapshot = LatticeSnapshot::default();
+    for host_id in client.get_hosts().await? {
+        let inventory = match client.get_inventory(&host_id).await {
+            Ok(inventory) => inventory,
+            Err(e) => {
+                warn!(error = %e, %host_id, "Unable to get host inventory, skipping host");
+                snapshot.unreachable_hosts.push(host_id);
+                continue;
+            }
+        };
+        let version = semver::Version::parse(inventory.version()).ok();
+        // The inventory is the same data a heartbeat carries, so it is stored the same way
+        let heartbeat = HostHeartbeat {
+            components: inventory.components().clone(),
+            providers: inventory.providers().clone(),
+            host_id: inventory.host_id().to_owned(),
+            issuer: String::new(),
+            friendly_name: inventory.friendly_name().to_owned(),
+            labels: inventory.labels().clone().into_iter().collect(),
+            version: version
+                .clone()
+                .unwrap_or_else(|| semver::Version::new(0, 0, 0)),
+            uptime_human: inventory.uptime_human().to_owned(),
+            uptime_seconds: inventory.uptime_seconds(),
+        };
+
+        for description in heartbeat.components.iter() {
+            let component = snapshot
+                .components
+                .entry(description.id().to_owned())
+                .or_insert_with(|| {
+                    let claim = claims.get(description.id());
+                    Component {
+                        id: description.id().to_owned(),
+                        name: description
+                            .name()
+                            .map(ToOwned::to_owned)
+                            .or_else(|| claim.map(|claim| claim.name.clone()))
+                            .unwrap_or_default(),
+                        issuer: claim.map(|claim| claim.issuer.clone()).unwrap_or_default(),
+                        reference: description.image_ref().to_owned(),
+                        ..Default::default()
+                    }
+                });
+            component
+                .instances
+                .entry(host_id.clone())
+                .or_insert_with(HashSet::new)
+                .insert(WadmComponentInfo {
+                    annotations: description.annotations().cloned().unwrap_or_default(),
+                    count: description.max_instances() as usize,
+                });
+        }
+        for description in heartbeat.providers.iter() {
+            let provider = snapshot
+                .providers
+                .entry(description.id().to_owned())
+                .or_insert_with(|| Provider {
+                    id: description.id().to_owned(),
+                    name: description.name().map(String::from).unwrap_or_default(),
+                    reference: description
+                        .image_ref()
+                        .map(String::from)
+                        .unwrap_or_default(),
+                    ..Default::default()
+                });
+            // Like with heartbeats, the next health check sets the real status
+            provider
+                .hosts
+                .insert(host_id.clone(), ProviderStatus::default());
+        }
+
+        let mut host = Host::from(&heartbeat);
+        host.version = version;
+        snapshot.hosts.insert(host_id, host);
+    }
+    debug!(
+        hosts = snapshot.hosts.len(),
+        components = snapshot.components.len(),
+        providers = snapshot.providers.len(),
+        "Scanned lattice"
+    );
+    Ok(snapshot)
+}
+
+/// Replaces the state of the lattice in the store with the given snapshot. Entries in the store
+/// that aren't in the snapshot are deleted, unless some hosts were unreachable, as their resources
+/// might be among them
+pub async fn seed_state<S>(store: &S, lattice_id: &str, snapshot: LatticeSnapshot) -> Result<()>
+where
+    S: Store + Sync,
+{
+    let prune = snapshot.unreachable_hosts.is_empty();
+    replace_kind(store, lattice_id, snapshot.hosts, prune).await?;
+    replace_kind(store, lattice_id, snapshot.components, prune).await?;
+    replace_kind(store, lattice_id, snapshot.providers, prune).await
+}
+
+async fn replace_kind<S, T>(
+    store: &S,
+    lattice_id: &str,
+    data: HashMap<String, T>,
+    prune: bool,
+) -> Result<()>
+where
+    S: Store + Sync,
+    T: Serialize + DeserializeOwned + StateKind + Send + Sync + Clone,
+{
+    if prune {
+        let stale: Vec<String> = store
+            .list::<T>(lattice_id)
+            .await?
+            .into_keys()
+            .filter(|id| !data.contains_key(id))
+            .collect();
+        if !stale.is_empty() {
+            debug!(kind = T::KIND, count = stale.len(), "Deleting stale state");
+            store.delete_many::<T, _, _>(lattice_id, stale).await?;
+        }
+    }
+    store.store_many(lattice_id, data).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use wasmcloud_control_interface::{ComponentDescription, HostInventory, ProviderDescription};
+
+    use super::*;
+    use crate::storage::ReadStore;
+    use crate::test_util::{TestLatticeSource, TestStore};
+
+    #[tokio::test]
+    async fn can_rebuild_state_from_inventory() {
+        let lattice_id = "migrate";
+        let annotations = BTreeMap::from([
+            (
+                MANAGED_BY_ANNOTATION.to_string(),
+                MANAGED_BY_IDENTIFIER.to_string(),
+            ),
+            (APP_SPEC_ANNOTATION.to_string(), "hello".to_string()),
+        ]);
+        let inventory = HostInventory::builder()
+            .host_id("host1".into())
+            .friendly_name("host-one".into())
+            .version("1.0.0".into())
+            .uptime_human("1m".into())
+            .uptime_seconds(60)
+            .labels(BTreeMap::new())
+            .components(vec![
+                ComponentDescription::builder()
+                    .id("hello-http".into())
+                    .image_ref("hello.wasm".into())
+                    .revision(0)
+                    .max_instances(2)
+                    .annotations(annotations.clone())
+                    .build()
+                    .unwrap(),
+                ComponentDescription::builder()
+                    .id("unmanaged".into())
+                    .image_ref("other.wasm".into())
+                    .revision(0)
+                    .max_instances(1)
+                    .build()
+                    .unwrap(),
+            ])
+            .providers(vec![ProviderDescription::builder()
+                .id("httpserver")
+                .image_ref("httpserver.par")
+                .revision(0)
+                .annotations(annotations)
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+        let source = TestLatticeSource {
+            inventory: Arc::new(tokio::sync::RwLock::new(HashMap::from([(
+                "host1".to_string(),
+                inventory,
+            )]))),
+            ..Default::default()
+        };
+
+        let store = TestStore::default();
+        store
+            .store(
+                lattice_id,
+                "gone".to_string(),
+                Component {
+                    id: "gone".to_string(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let snapshot = scan_lattice(&source).await.unwrap();
+        assert_eq!(
+            snapshot.apps(),
+            BTreeMap::from([(
+                "hello".to_string(),
+                AppResources {
+                    component_instances: 2,
+                    providers: 1,
+                }
+            )]),
+            "Only resources managed by wadm should be grouped into apps"
+        );
+        seed_state(&store, lattice_id, snapshot).await.unwrap();
+
+        let components = store.list::<Component>(lattice_id).await.unwrap();
+        assert_eq!(components.len(), 2, "Stale components should be removed");
+        assert_eq!(components["hello-http"].count_for_host("host1"), 2);
+        let hosts = store.list::<Host>(lattice_id).await.unwrap();
+        assert_eq!(hosts["host1"].version, Some(semver::Version::new(1, 0, 0)));
+        let providers = store.list::<Provider>(lattice_id).await.unwrap();
+        assert!(providers["httpserver"].hosts.contains_key("host1"));
+    }
+}
@@ -2,10 +2,12 @@ use async_trait::async_trait;
 use serde::{de::DeserializeOwned, Serialize};
 use std::{collections::HashMap, ops::Deref};
 
+pub mod migrate;
 pub mod nats_kv;
 pub mod reaper;
 pub(crate) mod snapshot;
 mod state;
+pub mod state_log;
 
 pub use state::{Component, Host, Provider, ProviderStatus, WadmComponentInfo};
 
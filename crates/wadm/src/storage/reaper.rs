@@ -3,17 +3,30 @@
 
 use std::collections::HashMap;
 
-use chrono::{Duration, Utc};
+use chrono::Duration;
 use tokio::{task::JoinHandle, time};
 use tracing::{debug, error, info, instrument, trace, warn};
 
 use super::{Component, Host, Provider, Store};
+use crate::clock::{self, SharedClock};
+use crate::workers::InventoryCache;
 
 /// A struct that can reap various pieces of data from the given store
 pub struct Reaper<S> {
     store: S,
     interval: Duration,
     handles: HashMap<String, JoinHandle<()>>,
+    clock: SharedClock,
+    eviction: Option<HeartbeatEviction>,
+    inventories: Option<InventoryCache>,
+}
+
+/// Evicts hosts once they have missed a number of heartbeats, instead of after two reaper
+/// intervals
+#[derive(Debug, Clone, Copy)]
+struct HeartbeatEviction {
+    heartbeat_interval: Duration,
+    missed_heartbeats: u32,
 }
 
 impl<S: Store + Clone + Send + Sync + 'static> Reaper<S> {
@@ -32,28 +45,57 @@ impl<S: Store + Clone + Send + Sync + 'static> Reaper<S> {
         store: S,
         check_interval: std::time::Duration,
         lattices_to_observe: impl IntoIterator<Item = String>,
+    ) -> Reaper<S> {
+        Reaper::new_with_clock(store, check_interval, lattices_to_observe, clock::system())
+    }
+
+    /// Creates a new reaper like [`Reaper::new`] that uses the given clock to decide how long ago
+    /// hosts were last seen
+    pub fn new_with_clock(
+        store: S,
+        check_interval: std::time::Duration,
+        lattices_to_observe: impl IntoIterator<Item = String>,
+        clock: SharedClock,
     ) -> Reaper<S> {
         let interval = Duration::from_std(check_interval)
             .expect("The given duration is out of bounds for a max duration value");
-        let cloned_store = store.clone();
-        let handles = lattices_to_observe.into_iter().map(move |id| {
-            (
-                id.clone(),
-                tokio::spawn(
-                    Undertaker {
-                        store: cloned_store.clone(),
-                        lattice_id: id,
-                        interval,
-                    }
-                    .reap(),
-                ),
-            )
-        });
-        Reaper {
+        let mut reaper = Reaper {
             store,
             interval,
-            handles: handles.collect(),
+            handles: HashMap::new(),
+            clock,
+            eviction: None,
+            inventories: None,
+        };
+        for lattice_id in lattices_to_observe {
+            reaper.observe(&lattice_id);
         }
+        reaper
+    }
+
+    /// Evicts hosts from the store once they have missed the given number of heartbeats, sent every
+    /// `heartbeat_interval`. Hosts are checked at least once per heartbeat so they are evicted soon
+    /// after missing the last one. This panics under the same conditions as [`Reaper::new`]
+    pub fn with_heartbeat_eviction(
+        mut self,
+        heartbeat_interval: std::time::Duration,
+        missed_heartbeats: u32,
+    ) -> Reaper<S> {
+        self.eviction = Some(HeartbeatEviction {
+            heartbeat_interval: Duration::from_std(heartbeat_interval)
+                .expect("The given duration is out of bounds for a max duration value"),
+            missed_heartbeats: missed_heartbeats.max(1),
+        });
+        self.restart();
+        self
+    }
+
+    /// Forgets the cached inventories of hosts when they are reaped, so the cache doesn't keep
+    /// hosts that are gone
+    pub fn with_inventory_cache(mut self, inventories: InventoryCache) -> Reaper<S> {
+        self.inventories = Some(inventories);
+        self.restart();
+        self
     }
 
     /// Adds a new lattice to be reaped
@@ -71,6 +113,9 @@ impl<S: Store + Clone + Send + Sync + 'static> Reaper<S> {
                     store: self.store.clone(),
                     lattice_id: lattice_id.to_owned(),
                     interval: self.interval,
+                    clock: self.clock.clone(),
+                    eviction: self.eviction,
+                    inventories: self.inventories.clone(),
                 }
                 .reap(),
             ),
@@ -83,12 +128,37 @@ impl<S: Store + Clone + Send + Sync + 'static> Reaper<S> {
             handle.abort();
         }
     }
+
+    /// Changes how often the reaper checks for things to reap, restarting the checks for every
+    /// observed lattice. This panics under the same conditions as [`Reaper::new`]
+    pub fn set_interval(&mut self, check_interval: std::time::Duration) {
+        let interval = Duration::from_std(check_interval)
+            .expect("The given duration is out of bounds for a max duration value");
+        if interval == self.interval {
+            return;
+        }
+        info!(interval = %interval, "Changing reaper interval");
+        self.interval = interval;
+        self.restart();
+    }
+
+    /// Restarts the checks for every observed lattice so they pick up changed settings
+    fn restart(&mut self) {
+        let lattices: Vec<String> = self.handles.keys().cloned().collect();
+        for lattice_id in lattices {
+            self.remove(&lattice_id);
+            self.observe(&lattice_id);
+        }
+    }
 }
 
 struct Undertaker<S> {
     store: S,
     lattice_id: String,
     interval: Duration,
+    clock: SharedClock,
+    eviction: Option<HeartbeatEviction>,
+    inventories: Option<InventoryCache>,
 }
 
 impl<S: Store + Clone + Send + Sync + 'static> Undertaker<S> {
@@ -96,7 +166,11 @@ impl<S: Store + Clone + Send + Sync + 'static> Undertaker<S> {
     async fn reap(self) {
         debug!("Starting reaper");
         // SAFETY: We created this Duration from a std Duration, so it should unwrap back just fine
-        let mut ticker = time::interval(self.interval.to_std().unwrap());
+        let tick = match self.eviction {
+            Some(eviction) => self.interval.min(eviction.heartbeat_interval),
+            None => self.interval,
+        };
+        let mut ticker = time::interval(tick.to_std().unwrap());
         ticker.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
 
         loop {
@@ -129,8 +203,21 @@ impl<S: Store + Clone + Send + Sync + 'static> Undertaker<S> {
             }
         };
 
+        let now = self.clock.now();
         let hosts_to_remove = hosts.into_iter().filter_map(|(id, host)| {
-            let elapsed = Utc::now() - host.last_seen;
+            let elapsed = now - host.last_seen;
+            if let Some(eviction) = self.eviction {
+                let missed = (elapsed.num_milliseconds()
+                    / eviction.heartbeat_interval.num_milliseconds().max(1))
+                    as u32;
+                if missed >= eviction.missed_heartbeats {
+                    info!(%id, friendly_name = %host.friendly_name, %missed, "Host has missed too many heartbeats. Will reap node");
+                    return Some(id);
+                } else if missed > 0 {
+                    debug!(%id, friendly_name = %host.friendly_name, %missed, "Host has missed heartbeats");
+                }
+                return None;
+            }
             if elapsed > (self.interval * 2) {
                 info!(%id, friendly_name = %host.friendly_name, "Host has not been seen for 2 intervals. Will reap node");
                 Some(id)
@@ -140,14 +227,20 @@ impl<S: Store + Clone + Send + Sync + 'static> Undertaker<S> {
             } else {
                 None
             }
-        });
+        }).collect::<Vec<_>>();
 
         if let Err(e) = self
             .store
-            .delete_many::<Host, _, _>(&self.lattice_id, hosts_to_remove)
+            .delete_many::<Host, _, _>(&self.lattice_id, &hosts_to_remove)
             .await
         {
-            error!(error = %e, "Error when deleting hosts from store. Will retry on next tick")
+            error!(error = %e, "Error when deleting hosts from store. Will retry on next tick");
+            return;
+        }
+        if let Some(inventories) = self.inventories.as_ref() {
+            for id in hosts_to_remove.iter() {
+                inventories.invalidate(id);
+            }
         }
     }
 
@@ -253,9 +346,14 @@ mod test {
         sync::Arc,
     };
 
+    use chrono::Utc;
+
+    use wasmcloud_control_interface::HostInventory;
+
     use crate::{
         storage::{ProviderStatus, ReadStore, WadmComponentInfo},
-        test_util::TestStore,
+        test_util::{TestLatticeSource, TestStore},
+        workers::InventorySource,
     };
 
     #[tokio::test]
@@ -384,6 +482,74 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn evicts_hosts_that_miss_heartbeats() {
+        let store = Arc::new(TestStore::default());
+        let lattice_id = "reaper";
+        let host = |id: &str, last_seen| {
+            (
+                id.to_string(),
+                Host {
+                    id: id.to_string(),
+                    last_seen,
+                    ..Default::default()
+                },
+            )
+        };
+        store
+            .store_many(
+                lattice_id,
+                [
+                    host("fresh", Utc::now()),
+                    // Missed one heartbeat, which isn't enough to be evicted
+                    host("late", Utc::now() - Duration::milliseconds(150)),
+                    host("gone", Utc::now() - Duration::milliseconds(350)),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let source = TestLatticeSource::default();
+        for id in ["fresh", "gone"] {
+            source.inventory.write().await.insert(
+                id.to_string(),
+                HostInventory::builder()
+                    .friendly_name(id.into())
+                    .labels(BTreeMap::new())
+                    .host_id(id.into())
+                    .version("1.0.0".into())
+                    .uptime_human("1m".into())
+                    .uptime_seconds(60)
+                    .build()
+                    .unwrap(),
+            );
+        }
+        let inventories = InventoryCache::new().with_ttl(std::time::Duration::from_secs(60));
+        let cached = inventories.wrap(source);
+        cached.get_inventory("fresh").await.unwrap();
+        cached.get_inventory("gone").await.unwrap();
+
+        // A long reaper interval shows that hosts are checked every heartbeat instead
+        let _reaper = Reaper::new(
+            store.clone(),
+            std::time::Duration::from_secs(60),
+            [lattice_id.to_owned()],
+        )
+        .with_heartbeat_eviction(std::time::Duration::from_millis(100), 3)
+        .with_inventory_cache(inventories.clone());
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let hosts = store.list::<Host>(lattice_id).await.unwrap();
+        let mut ids: Vec<_> = hosts.keys().map(String::as_str).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["fresh", "late"]);
+        assert!(inventories.contains("fresh"));
+        assert!(
+            !inventories.contains("gone"),
+            "Evicted hosts should be dropped from the inventory cache"
+        );
+    }
+
     #[tokio::test]
     async fn test_stale_component() {
         let store = Arc::new(TestStore::default());
@@ -1,13 +1,17 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use futures::stream::BoxStream;
 use tokio::sync::RwLock;
 use tracing::debug;
 use wasmcloud_control_interface::Link;
 use wasmcloud_secrets_types::SecretConfig;
 
+use crate::clock::{self, SharedClock};
+use crate::scaler::SharedAuctionSource;
+use crate::secrets::SecretResolver;
 use crate::storage::{Component, Host, Provider, ReadStore, StateKind};
-use crate::workers::{ConfigSource, LinkSource, SecretSource};
+use crate::workers::{ConfigSource, HostCircuitBreaker, LinkSource, MetricSource, SecretSource};
 
 // NOTE(thomastaylor312): This type is real ugly and we should probably find a better way to
 // structure the ReadStore trait so it doesn't have the generic T we have to work around here. This
@@ -29,6 +33,10 @@ pub struct SnapshotStore<S, L> {
     lattice_id: String,
     stored_state: Arc<RwLock<InMemoryData>>,
     links: Arc<RwLock<Vec<Link>>>,
+    circuit_breaker: Option<HostCircuitBreaker>,
+    clock: SharedClock,
+    secret_resolver: Option<SecretResolver>,
+    auction_source: Option<SharedAuctionSource>,
 }
 
 impl<S, L> Clone for SnapshotStore<S, L>
@@ -43,6 +51,10 @@ where
             lattice_id: self.lattice_id.clone(),
             stored_state: self.stored_state.clone(),
             links: self.links.clone(),
+            circuit_breaker: self.circuit_breaker.clone(),
+            clock: self.clock.clone(),
+            secret_resolver: self.secret_resolver.clone(),
+            auction_source: self.auction_source.clone(),
         }
     }
 }
@@ -60,9 +72,54 @@ where
             lattice_id,
             stored_state: Default::default(),
             links: Arc::new(RwLock::new(Vec::new())),
+            circuit_breaker: None,
+            clock: clock::system(),
+            secret_resolver: None,
+            auction_source: None,
         }
     }
 
+    /// Sets a circuit breaker to consult when refreshing data. Hosts with an open circuit are left
+    /// out of the snapshot so that scalers place work on other hosts until they recover
+    pub fn with_circuit_breaker(mut self, circuit_breaker: HostCircuitBreaker) -> Self {
+        self.circuit_breaker = Some(circuit_breaker);
+        self
+    }
+
+    /// Sets the clock that scalers created from this store use for bake times and cooldowns
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Returns the clock scalers created from this store should use
+    pub fn clock(&self) -> SharedClock {
+        self.clock.clone()
+    }
+
+    /// Sets the resolver config scalers created from this store use for secret references
+    pub fn with_secret_resolver(mut self, secret_resolver: SecretResolver) -> Self {
+        self.secret_resolver = Some(secret_resolver);
+        self
+    }
+
+    /// Sets the auction source spread scalers created from this store use to find hosts that can
+    /// take new instances
+    pub fn with_auction_source(mut self, auction_source: SharedAuctionSource) -> Self {
+        self.auction_source = Some(auction_source);
+        self
+    }
+
+    /// Returns the auction source spread scalers should use, if placement by auction is enabled
+    pub fn auction_source(&self) -> Option<SharedAuctionSource> {
+        self.auction_source.clone()
+    }
+
+    /// Returns the resolver for secret references, if one is configured
+    pub fn secret_resolver(&self) -> Option<&SecretResolver> {
+        self.secret_resolver.as_ref()
+    }
+
     /// Refreshes the snapshotted data, returning an error if it couldn't update the data
     pub async fn refresh(&self) -> anyhow::Result<()> {
         // SAFETY: All of these unwraps are safe because we _just_ deserialized from JSON
@@ -85,6 +142,16 @@ where
             .list::<Host>(&self.lattice_id)
             .await?
             .into_iter()
+            .filter(|(key, _)| {
+                let available = self
+                    .circuit_breaker
+                    .as_ref()
+                    .is_none_or(|breaker| breaker.is_available(key));
+                if !available {
+                    debug!(host_id = %key, "Circuit is open for host, leaving it out of snapshot");
+                }
+                available
+            })
             .map(|(key, val)| (key, serde_json::to_value(val).unwrap()))
             .collect::<HashMap<_, _>>();
 
@@ -191,3 +258,14 @@ where
         self.lattice_source.get_secret(name).await
     }
 }
+
+#[async_trait::async_trait]
+impl<S, L> MetricSource for SnapshotStore<S, L>
+where
+    S: Send + Sync,
+    L: MetricSource + Send + Sync,
+{
+    async fn subscribe_metric(&self, subject: &str) -> anyhow::Result<BoxStream<'static, f64>> {
+        self.lattice_source.subscribe_metric(subject).await
+    }
+}
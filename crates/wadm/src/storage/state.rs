@@ -8,6 +8,7 @@ use serde::{Deserialize, Serialize};
 
 use super::StateKind;
 use crate::events::{ComponentScaled, HostHeartbeat, HostStarted, ProviderInfo, ProviderStarted};
+use crate::{CAPACITY_LABEL, CORDON_DRAIN_VALUE, CORDON_LABEL, MAX_INSTANCES_LABEL, MEMORY_LABEL};
 
 /// A wasmCloud Capability provider
 // NOTE: We probably aren't going to use this _right now_ so we've kept it pretty minimal. But it is
@@ -253,6 +254,48 @@ impl StateKind for Host {
     const KIND: &'static str = "host";
 }
 
+impl Host {
+    /// Returns whether the host is cordoned, meaning nothing new should be placed on it. Setting
+    /// the cordon label to `false` is the same as not setting it
+    pub fn is_cordoned(&self) -> bool {
+        self.labels
+            .get(CORDON_LABEL)
+            .is_some_and(|value| !value.eq_ignore_ascii_case("false"))
+    }
+
+    /// Returns whether the host is being drained, meaning managed workloads should be moved off of
+    /// it. A draining host is always cordoned
+    pub fn is_draining(&self) -> bool {
+        self.labels
+            .get(CORDON_LABEL)
+            .is_some_and(|value| value.eq_ignore_ascii_case(CORDON_DRAIN_VALUE))
+    }
+
+    /// Returns the total number of component instances running on the host
+    pub fn instance_count(&self) -> usize {
+        self.components.values().sum()
+    }
+
+    /// Returns how many more component instances the host can take, or `None` if it doesn't
+    /// report a limit with the [`MAX_INSTANCES_LABEL`] label
+    pub fn free_instances(&self) -> Option<usize> {
+        self.labels
+            .get(MAX_INSTANCES_LABEL)
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .map(|max| max.saturating_sub(self.instance_count()))
+    }
+
+    /// Returns the capacity of the host relative to other hosts, from the [`CAPACITY_LABEL`] or
+    /// else the [`MEMORY_LABEL`]. Returns `None` if the host reports neither
+    pub fn capacity_weight(&self) -> Option<u64> {
+        [CAPACITY_LABEL, MEMORY_LABEL].iter().find_map(|label| {
+            self.labels
+                .get(*label)
+                .and_then(|value| value.trim().parse::<u64>().ok())
+        })
+    }
+}
+
 impl From<HostStarted> for Host {
     fn from(value: HostStarted) -> Self {
         Host {
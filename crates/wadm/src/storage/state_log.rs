@@ -0,0 +1,527 @@
+//! An event sourced log of the observed state of each lattice. Every change made to the state store
+//! is also appended to a log, and a lattice whose state is missing when wadm starts is rebuilt from
+//! it, so a restart doesn't have to wait for a heartbeat from every host to know what is running.
+//! The log is compacted into a snapshot periodically so it doesn't grow forever
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_nats::jetstream::{
+    consumer::{pull::OrderedConfig, DeliverPolicy},
+    stream::Stream,
+    Context,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::StreamExt;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::OnceCell;
+use tracing::{debug, info, instrument, warn};
+
+use super::{Component, Host, Provider, ReadStore, StateKind, Store};
+
+/// Lattices this store has seen, along with whether their state has been rebuilt from the log
+type SeenLattices = HashMap<String, Arc<OnceCell<()>>>;
+
+/// A change made to the state of a lattice
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum StateChange {
+    /// Items of the given kind were stored, keyed by ID
+    Put {
+        kind: String,
+        data: BTreeMap<String, Value>,
+    },
+    /// Items of the given kind were deleted
+    Delete { kind: String, ids: Vec<String> },
+}
+
+/// The state of a lattice after every change up to a point in its log
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    /// The sequence of the last change included in this snapshot
+    pub sequence: u64,
+    /// The stored items, keyed by state kind and then by ID
+    pub state: BTreeMap<String, BTreeMap<String, Value>>,
+}
+
+impl StateSnapshot {
+    /// Applies a change that was logged at the given sequence
+    pub fn apply(&mut self, sequence: u64, change: StateChange) {
+        self.sequence = self.sequence.max(sequence);
+        match change {
+            StateChange::Put { kind, data } => self.state.entry(kind).or_default().extend(data),
+            StateChange::Delete { kind, ids } => {
+                if let Some(items) = self.state.get_mut(&kind) {
+                    for id in ids.iter() {
+                        items.remove(id);
+                    }
+                    if items.is_empty() {
+                        self.state.remove(&kind);
+                    }
+                }
+            }
+        }
+    }
+
+    fn items<T: DeserializeOwned>(&self, kind: &str) -> HashMap<String, T> {
+        self.state
+            .get(kind)
+            .into_iter()
+            .flatten()
+            .filter_map(|(id, value)| match serde_json::from_value(value.clone()) {
+                Ok(item) => Some((id.clone(), item)),
+                Err(e) => {
+                    warn!(error = %e, %kind, %id, "Skipping logged state that couldn't be decoded");
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Anything that can keep a log of the state changes of each lattice
+#[async_trait]
+pub trait StateLog {
+    /// Appends a change to the log of the given lattice
+    async fn append(&self, lattice_id: &str, change: &StateChange) -> Result<()>;
+
+    /// Returns the state of the lattice after every change in its log
+    async fn load(&self, lattice_id: &str) -> Result<StateSnapshot>;
+
+    /// Replaces every change in the log of the lattice up to the sequence of the given snapshot
+    /// with the snapshot
+    async fn compact(&self, lattice_id: &str, snapshot: &StateSnapshot) -> Result<()>;
+}
+
+/// A [`StateLog`] backed by a JetStream stream. Changes are published to
+/// `{topic_prefix}.{lattice_id}.changes` and snapshots to `{topic_prefix}.{lattice_id}.snapshot`.
+/// The stream should not be a work queue, as the log is read again every time it is loaded
+#[derive(Clone)]
+pub struct StreamStateLog {
+    context: Context,
+    stream: Stream,
+    topic_prefix: String,
+}
+
+impl StreamStateLog {
+    pub fn new(context: Context, stream: Stream, topic_prefix: &str) -> StreamStateLog {
+        StreamStateLog {
+            context,
+            stream,
+            topic_prefix: topic_prefix.trim_end_matches('.').to_owned(),
+        }
+    }
+
+    fn changes_topic(&self, lattice_id: &str) -> String {
+        format!("{}.{lattice_id}.changes", self.topic_prefix)
+    }
+
+    fn snapshot_topic(&self, lattice_id: &str) -> String {
+        format!("{}.{lattice_id}.snapshot", self.topic_prefix)
+    }
+
+    async fn publish(&self, subject: String, data: Vec<u8>) -> Result<()> {
+        self.context
+            .publish(subject, data.into())
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StateLog for StreamStateLog {
+    async fn append(&self, lattice_id: &str, change: &StateChange) -> Result<()> {
+        self.publish(self.changes_topic(lattice_id), serde_json::to_vec(change)?)
+            .await
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn load(&self, lattice_id: &str) -> Result<StateSnapshot> {
+        let mut snapshot = match self
+            .stream
+            .get_last_raw_message_by_subject(&self.snapshot_topic(lattice_id))
+            .await
+        {
+            Ok(msg) => serde_json::from_slice(&msg.payload)?,
+            // NOTE: There is no snapshot until the log is compacted for the first time
+            Err(_) => StateSnapshot::default(),
+        };
+        let consumer = self
+            .stream
+            .create_consumer(OrderedConfig {
+                filter_subject: self.changes_topic(lattice_id),
+                deliver_policy: DeliverPolicy::ByStartSequence {
+                    start_sequence: snapshot.sequence + 1,
+                },
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+        if consumer.cached_info().num_pending == 0 {
+            return Ok(snapshot);
+        }
+        let mut messages = consumer
+            .messages()
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+        while let Some(msg) = messages.next().await {
+            let msg = msg.map_err(|e| anyhow::anyhow!("{e:?}"))?;
+            let info = msg.info().map_err(|e| anyhow::anyhow!("{e:?}"))?;
+            match serde_json::from_slice(&msg.payload) {
+                Ok(change) => snapshot.apply(info.stream_sequence, change),
+                Err(e) => {
+                    warn!(error = %e, sequence = %info.stream_sequence, "Skipping state change that couldn't be decoded")
+                }
+            }
+            if info.pending == 0 {
+                break;
+            }
+        }
+        Ok(snapshot)
+    }
+
+    #[instrument(level = "debug", skip(self, snapshot), fields(sequence = %snapshot.sequence))]
+    async fn compact(&self, lattice_id: &str, snapshot: &StateSnapshot) -> Result<()> {
+        self.publish(
+            self.snapshot_topic(lattice_id),
+            serde_json::to_vec(snapshot)?,
+        )
+        .await?;
+        // NOTE: Changes appended while compacting come after the snapshot, so they are kept
+        self.stream
+            .purge()
+            .filter(self.changes_topic(lattice_id))
+            .sequence(snapshot.sequence + 1)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+        self.stream
+            .purge()
+            .filter(self.snapshot_topic(lattice_id))
+            .keep(1)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+        Ok(())
+    }
+}
+
+/// A [`Store`] that logs every change made to the store it wraps. The first time a lattice is used,
+/// its state is rebuilt from the log if the wrapped store has no hosts for it. Without a log this
+/// passes everything straight through. Cloning is cheap and clones share the same log
+#[derive(Clone)]
+pub struct LoggedStore<S> {
+    inner: S,
+    log: Option<Arc<dyn StateLog + Send + Sync>>,
+    lattices: Arc<Mutex<SeenLattices>>,
+}
+
+impl<S: Store + Send + Sync> LoggedStore<S> {
+    /// Wraps the given store without a log
+    pub fn new(inner: S) -> LoggedStore<S> {
+        LoggedStore {
+            inner,
+            log: None,
+            lattices: Arc::default(),
+        }
+    }
+
+    /// Logs changes to the given log and rebuilds missing state from it
+    pub fn with_log(mut self, log: impl StateLog + Send + Sync + 'static) -> LoggedStore<S> {
+        self.log = Some(Arc::new(log));
+        self
+    }
+
+    /// Rebuilds the state of the lattice from the log the first time it is used. Failures are only
+    /// logged, as the state will still be filled in by the next heartbeats
+    async fn ensure_rebuilt(&self, lattice_id: &str) {
+        let Some(log) = self.log.as_deref() else {
+            return;
+        };
+        let rebuilt = self
+            .lattices
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(lattice_id.to_owned())
+            .or_default()
+            .clone();
+        rebuilt
+            .get_or_init(|| async {
+                if let Err(e) = self.rebuild(log, lattice_id).await {
+                    warn!(error = %e, %lattice_id, "Unable to rebuild state from the state log");
+                }
+            })
+            .await;
+    }
+
+    #[instrument(level = "debug", skip(self, log))]
+    async fn rebuild(&self, log: &(dyn StateLog + Send + Sync), lattice_id: &str) -> Result<()> {
+        if !self.inner.list::<Host>(lattice_id).await?.is_empty() {
+            debug!("State store already has hosts, not rebuilding from the state log");
+            return Ok(());
+        }
+        let snapshot = log.load(lattice_id).await?;
+        // NOTE: Rebuilt hosts are treated as if they were just seen. Otherwise hosts that sent their
+        // last heartbeat before wadm stopped would be reaped before they can send another one
+        let now = Utc::now();
+        let hosts = snapshot
+            .items::<Host>(Host::KIND)
+            .into_iter()
+            .map(|(id, host)| {
+                (
+                    id,
+                    Host {
+                        last_seen: now,
+                        ..host
+                    },
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        info!(
+            hosts = hosts.len(),
+            sequence = snapshot.sequence,
+            "Rebuilding lattice state from the state log"
+        );
+        self.inner.store_many(lattice_id, hosts).await?;
+        self.inner
+            .store_many(lattice_id, snapshot.items::<Component>(Component::KIND))
+            .await?;
+        self.inner
+            .store_many(lattice_id, snapshot.items::<Provider>(Provider::KIND))
+            .await?;
+        Ok(())
+    }
+
+    async fn append(&self, lattice_id: &str, change: StateChange) {
+        if let Some(log) = self.log.as_deref() {
+            if let Err(e) = log.append(lattice_id, &change).await {
+                warn!(error = %e, %lattice_id, "Unable to append change to the state log");
+            }
+        }
+    }
+
+    /// Compacts the log of every lattice this store has used into a snapshot
+    pub async fn compact(&self) {
+        let Some(log) = self.log.as_deref() else {
+            return;
+        };
+        let lattices: Vec<String> = self
+            .lattices
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys()
+            .cloned()
+            .collect();
+        for lattice_id in lattices {
+            let res = match log.load(&lattice_id).await {
+                Ok(snapshot) => log.compact(&lattice_id, &snapshot).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                warn!(error = %e, %lattice_id, "Unable to compact state log. Will retry on next interval");
+            }
+        }
+    }
+
+    /// Compacts the log on the given interval. This never returns, so it should be spawned
+    pub async fn compact_every(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // The first tick fires right away, before anything has been logged
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            self.compact().await;
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Store + Send + Sync> ReadStore for LoggedStore<S> {
+    type Error = S::Error;
+
+    async fn get<T>(&self, lattice_id: &str, id: &str) -> Result<Option<T>, Self::Error>
+    where
+        T: DeserializeOwned + StateKind,
+    {
+        self.ensure_rebuilt(lattice_id).await;
+        self.inner.get(lattice_id, id).await
+    }
+
+    async fn list<T>(&self, lattice_id: &str) -> Result<HashMap<String, T>, Self::Error>
+    where
+        T: DeserializeOwned + StateKind,
+    {
+        self.ensure_rebuilt(lattice_id).await;
+        self.inner.list(lattice_id).await
+    }
+}
+
+#[async_trait]
+impl<S: Store + Send + Sync> Store for LoggedStore<S> {
+    async fn store_many<T, D>(&self, lattice_id: &str, data: D) -> Result<(), Self::Error>
+    where
+        T: Serialize + DeserializeOwned + StateKind + Send + Sync + Clone,
+        D: IntoIterator<Item = (String, T)> + Send,
+    {
+        self.ensure_rebuilt(lattice_id).await;
+        let data: Vec<(String, T)> = data.into_iter().collect();
+        let logged = self.log.as_ref().map(|_| {
+            data.iter()
+                .filter_map(|(id, item)| Some((id.clone(), serde_json::to_value(item).ok()?)))
+                .collect()
+        });
+        self.inner.store_many(lattice_id, data).await?;
+        if let Some(data) = logged {
+            let change = StateChange::Put {
+                kind: T::KIND.to_owned(),
+                data,
+            };
+            self.append(lattice_id, change).await;
+        }
+        Ok(())
+    }
+
+    async fn delete_many<T, D, K>(&self, lattice_id: &str, data: D) -> Result<(), Self::Error>
+    where
+        T: Serialize + DeserializeOwned + StateKind + Send + Sync,
+        D: IntoIterator<Item = K> + Send,
+        K: AsRef<str>,
+    {
+        self.ensure_rebuilt(lattice_id).await;
+        let ids: Vec<String> = data.into_iter().map(|id| id.as_ref().to_owned()).collect();
+        self.inner
+            .delete_many::<T, _, _>(lattice_id, ids.iter())
+            .await?;
+        let change = StateChange::Delete {
+            kind: T::KIND.to_owned(),
+            ids,
+        };
+        self.append(lattice_id, change).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use chrono::DateTime;
+
+    use super::*;
+    use crate::test_util::TestStore;
+
+    /// A log kept in memory, which compacts the same way as the stream
+    #[derive(Clone, Default)]
+    struct MemoryLog {
+        snapshots: Arc<Mutex<HashMap<String, StateSnapshot>>>,
+        changes: Arc<Mutex<Vec<(u64, String, StateChange)>>>,
+        last_sequence: Arc<AtomicU64>,
+    }
+
+    #[async_trait]
+    impl StateLog for MemoryLog {
+        async fn append(&self, lattice_id: &str, change: &StateChange) -> Result<()> {
+            let sequence = self.last_sequence.fetch_add(1, Ordering::Relaxed) + 1;
+            self.changes
+                .lock()
+                .unwrap()
+                .push((sequence, lattice_id.to_owned(), change.clone()));
+            Ok(())
+        }
+
+        async fn load(&self, lattice_id: &str) -> Result<StateSnapshot> {
+            let mut snapshot = self
+                .snapshots
+                .lock()
+                .unwrap()
+                .get(lattice_id)
+                .cloned()
+                .unwrap_or_default();
+            for (sequence, lattice, change) in self.changes.lock().unwrap().iter() {
+                if lattice == lattice_id && *sequence > snapshot.sequence {
+                    snapshot.apply(*sequence, change.clone());
+                }
+            }
+            Ok(snapshot)
+        }
+
+        async fn compact(&self, lattice_id: &str, snapshot: &StateSnapshot) -> Result<()> {
+            self.snapshots
+                .lock()
+                .unwrap()
+                .insert(lattice_id.to_owned(), snapshot.clone());
+            self.changes
+                .lock()
+                .unwrap()
+                .retain(|(sequence, lattice, _)| {
+                    lattice != lattice_id || *sequence > snapshot.sequence
+                });
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn rebuilds_compacted_state_from_the_log() {
+        let log = MemoryLog::default();
+        let store = LoggedStore::new(TestStore::default()).with_log(log.clone());
+        let host = |id: &str| Host {
+            id: id.to_string(),
+            last_seen: DateTime::<Utc>::MIN_UTC,
+            ..Default::default()
+        };
+        store
+            .store_many(
+                "default",
+                [
+                    ("host1".to_string(), host("host1")),
+                    ("host2".to_string(), host("host2")),
+                ],
+            )
+            .await
+            .unwrap();
+        store
+            .store(
+                "default",
+                "echo".to_string(),
+                Component {
+                    id: "echo".to_string(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        store.compact().await;
+        store.delete::<Host>("default", "host2").await.unwrap();
+        assert_eq!(
+            log.changes.lock().unwrap().len(),
+            1,
+            "Compacting should only leave the changes made after the snapshot"
+        );
+
+        // A new store that starts without any state rebuilds it from the log
+        let rebuilt = LoggedStore::new(TestStore::default()).with_log(log.clone());
+        let hosts = rebuilt.list::<Host>("default").await.unwrap();
+        assert_eq!(hosts.keys().collect::<Vec<_>>(), vec!["host1"]);
+        assert!(
+            hosts["host1"].last_seen > DateTime::<Utc>::MIN_UTC,
+            "Rebuilt hosts should be treated as just seen so they aren't reaped right away"
+        );
+        assert!(rebuilt
+            .get::<Component>("default", "echo")
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            log.changes.lock().unwrap().len(),
+            1,
+            "Rebuilding shouldn't log the rebuilt state again"
+        );
+        assert!(rebuilt.list::<Host>("other").await.unwrap().is_empty());
+    }
+}
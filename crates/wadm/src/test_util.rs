@@ -1,6 +1,10 @@
 use std::convert::Infallible;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
 
+use futures::{stream::BoxStream, StreamExt};
 use serde::{de::DeserializeOwned, Serialize};
 use tokio::sync::RwLock;
 use wasmcloud_control_interface::{HostInventory, Link};
@@ -9,8 +13,8 @@ use wasmcloud_secrets_types::SecretConfig;
 use crate::publisher::Publisher;
 use crate::storage::StateKind;
 use crate::workers::{
-    secret_config_from_map, Claims, ClaimsSource, ConfigSource, InventorySource, LinkSource,
-    SecretSource,
+    secret_config_from_map, AuctionSource, Claims, ClaimsSource, ConfigSource, HostSource,
+    InventorySource, LinkSource, MetricSource, SecretSource,
 };
 
 fn generate_key<T: StateKind>(lattice_id: &str) -> String {
@@ -113,6 +117,8 @@ pub struct TestLatticeSource {
     pub inventory: Arc<RwLock<HashMap<String, HostInventory>>>,
     pub links: Vec<Link>,
     pub config: HashMap<String, HashMap<String, String>>,
+    /// The hosts that answer every auction
+    pub auction_winners: Vec<String>,
 }
 
 #[async_trait::async_trait]
@@ -122,6 +128,13 @@ impl ClaimsSource for TestLatticeSource {
     }
 }
 
+#[async_trait::async_trait]
+impl HostSource for TestLatticeSource {
+    async fn get_hosts(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self.inventory.read().await.keys().cloned().collect())
+    }
+}
+
 #[async_trait::async_trait]
 impl InventorySource for TestLatticeSource {
     async fn get_inventory(&self, host_id: &str) -> anyhow::Result<HostInventory> {
@@ -129,6 +142,27 @@ impl InventorySource for TestLatticeSource {
     }
 }
 
+#[async_trait::async_trait]
+impl AuctionSource for TestLatticeSource {
+    async fn auction_component(
+        &self,
+        _reference: &str,
+        _component_id: &str,
+        _constraints: BTreeMap<String, String>,
+    ) -> anyhow::Result<Vec<String>> {
+        Ok(self.auction_winners.clone())
+    }
+
+    async fn auction_provider(
+        &self,
+        _reference: &str,
+        _provider_id: &str,
+        _constraints: BTreeMap<String, String>,
+    ) -> anyhow::Result<Vec<String>> {
+        Ok(self.auction_winners.clone())
+    }
+}
+
 #[async_trait::async_trait]
 impl LinkSource for TestLatticeSource {
     async fn get_links(&self) -> anyhow::Result<Vec<Link>> {
@@ -155,6 +189,13 @@ impl SecretSource for TestLatticeSource {
     }
 }
 
+#[async_trait::async_trait]
+impl MetricSource for TestLatticeSource {
+    async fn subscribe_metric(&self, _subject: &str) -> anyhow::Result<BoxStream<'static, f64>> {
+        Ok(futures::stream::pending().boxed())
+    }
+}
+
 /// A publisher that does nothing
 #[derive(Clone, Default)]
 pub struct NoopPublisher;
@@ -0,0 +1,372 @@
+//! Contains types for building a graph of the lattice topology that wadm knows about, which powers
+//! the topology API
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tracing::instrument;
+use wadm_types::{
+    api::{Topology, TopologyEdge, TopologyEdgeKind, TopologyNode, TopologyNodeKind},
+    Manifest,
+};
+
+use crate::{
+    scaler::planner::LatticeSourceCreator,
+    storage::{Component, Host, Provider, ReadStore},
+    workers::LinkSource,
+    APP_SPEC_ANNOTATION,
+};
+
+/// Anything that can build the topology of a lattice
+#[async_trait]
+pub trait TopologySource {
+    /// Returns the topology of the given lattice. The `deployed` manifests are used to add nodes
+    /// for applications, which are connected to the components and providers they manage
+    async fn topology(
+        &self,
+        lattice_id: &str,
+        multitenant_prefix: Option<&str>,
+        deployed: &[&Manifest],
+    ) -> Result<Topology>;
+}
+
+/// A [`TopologySource`] that builds the topology from the observed lattice state in a store and
+/// the links reported by the lattice
+#[derive(Clone)]
+pub struct StateTopology<S, C> {
+    state_store: S,
+    source_creator: C,
+}
+
+impl<S, C> StateTopology<S, C> {
+    /// Creates a new topology source that reads observed state from the given store and uses the
+    /// given creator to fetch links
+    pub fn new(state_store: S, source_creator: C) -> StateTopology<S, C> {
+        StateTopology {
+            state_store,
+            source_creator,
+        }
+    }
+}
+
+#[async_trait]
+impl<S, C> TopologySource for StateTopology<S, C>
+where
+    S: ReadStore + Send + Sync,
+    C: LatticeSourceCreator + Send + Sync,
+{
+    #[instrument(level = "debug", skip(self, deployed))]
+    async fn topology(
+        &self,
+        lattice_id: &str,
+        multitenant_prefix: Option<&str>,
+        deployed: &[&Manifest],
+    ) -> Result<Topology> {
+        let hosts = self.state_store.list::<Host>(lattice_id).await?;
+        let components = self.state_store.list::<Component>(lattice_id).await?;
+        let providers = self.state_store.list::<Provider>(lattice_id).await?;
+        let links = self
+            .source_creator
+            .create(lattice_id, multitenant_prefix)
+            .get_links()
+            .await?;
+
+        let mut graph = GraphBuilder::default();
+        for manifest in deployed {
+            graph.node(
+                TopologyNodeKind::Application,
+                &manifest.metadata.name,
+                format!("{}@{}", manifest.metadata.name, manifest.version()),
+            );
+        }
+
+        for (id, host) in hosts.iter() {
+            graph.node(TopologyNodeKind::Host, id, host.friendly_name.clone());
+            for (component_id, count) in host.components.iter() {
+                graph.edge(
+                    (TopologyNodeKind::Component, component_id),
+                    (TopologyNodeKind::Host, id),
+                    TopologyEdgeKind::RunsOn,
+                    Some(format!("{count} instances")),
+                );
+            }
+            for provider in host.providers.iter() {
+                graph.edge(
+                    (TopologyNodeKind::Provider, &provider.provider_id),
+                    (TopologyNodeKind::Host, id),
+                    TopologyEdgeKind::RunsOn,
+                    None,
+                );
+                if let Some(app) = provider.annotations.get(APP_SPEC_ANNOTATION) {
+                    graph.edge(
+                        (TopologyNodeKind::Application, app),
+                        (TopologyNodeKind::Provider, &provider.provider_id),
+                        TopologyEdgeKind::Manages,
+                        None,
+                    );
+                }
+            }
+        }
+
+        for (id, component) in components.iter() {
+            graph.node(
+                TopologyNodeKind::Component,
+                id,
+                non_empty(&component.name, id),
+            );
+            let apps = component
+                .instances
+                .values()
+                .flatten()
+                .filter_map(|info| info.annotations.get(APP_SPEC_ANNOTATION))
+                .collect::<BTreeSet<_>>();
+            for app in apps {
+                graph.edge(
+                    (TopologyNodeKind::Application, app),
+                    (TopologyNodeKind::Component, id),
+                    TopologyEdgeKind::Manages,
+                    None,
+                );
+            }
+        }
+
+        for (id, provider) in providers.iter() {
+            graph.node(
+                TopologyNodeKind::Provider,
+                id,
+                non_empty(&provider.name, id),
+            );
+        }
+
+        for link in links.iter() {
+            let kind_of = |id: &str| {
+                if providers.contains_key(id) {
+                    TopologyNodeKind::Provider
+                } else {
+                    TopologyNodeKind::Component
+                }
+            };
+            graph.edge(
+                (kind_of(link.source_id()), link.source_id()),
+                (kind_of(link.target()), link.target()),
+                TopologyEdgeKind::Link,
+                Some(format!(
+                    "{}:{}/{}",
+                    link.wit_namespace(),
+                    link.wit_package(),
+                    link.interfaces().join(",")
+                )),
+            );
+        }
+
+        Ok(graph.build())
+    }
+}
+
+fn non_empty(name: &str, fallback: &str) -> String {
+    if name.is_empty() {
+        fallback.to_owned()
+    } else {
+        name.to_owned()
+    }
+}
+
+/// A helper for building a graph with unique nodes. Any node referenced by an edge that wasn't
+/// explicitly added is added with its ID as the label
+#[derive(Default)]
+struct GraphBuilder {
+    nodes: BTreeMap<String, TopologyNode>,
+    edges: Vec<TopologyEdge>,
+}
+
+impl GraphBuilder {
+    fn node(&mut self, kind: TopologyNodeKind, id: &str, label: String) {
+        let node_id = kind.node_id(id);
+        self.nodes.insert(
+            node_id.clone(),
+            TopologyNode {
+                id: node_id,
+                kind,
+                label,
+            },
+        );
+    }
+
+    fn edge(
+        &mut self,
+        (from_kind, from): (TopologyNodeKind, &str),
+        (to_kind, to): (TopologyNodeKind, &str),
+        kind: TopologyEdgeKind,
+        label: Option<String>,
+    ) {
+        for (node_kind, id) in [(from_kind, from), (to_kind, to)] {
+            if !self.nodes.contains_key(&node_kind.node_id(id)) {
+                self.node(node_kind, id, id.to_owned());
+            }
+        }
+        self.edges.push(TopologyEdge {
+            from: from_kind.node_id(from),
+            to: to_kind.node_id(to),
+            kind,
+            label,
+        });
+    }
+
+    fn build(mut self) -> Topology {
+        self.edges
+            .sort_by(|a, b| (&a.from, &a.to).cmp(&(&b.from, &b.to)));
+        self.edges.dedup();
+        Topology {
+            nodes: self.nodes.into_values().collect(),
+            edges: self.edges,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use wasmcloud_control_interface::Link;
+
+    use super::*;
+    use crate::{
+        events::ProviderInfo,
+        storage::{Store, WadmComponentInfo},
+        test_util::{TestLatticeSource, TestStore},
+    };
+
+    #[tokio::test]
+    async fn can_build_topology() {
+        let lattice_id = "topology";
+        let host_id = "NASDASDIMAREALHOST";
+        let app_annotations =
+            BTreeMap::from([(APP_SPEC_ANNOTATION.to_string(), "hello".to_string())]);
+        let store = Arc::new(TestStore::default());
+        store
+            .store(
+                lattice_id,
+                host_id.to_string(),
+                Host {
+                    components: HashMap::from([("hello-echo".to_string(), 2)]),
+                    friendly_name: "hey".to_string(),
+                    labels: HashMap::new(),
+                    providers: HashSet::from([ProviderInfo {
+                        provider_id: "hello-httpserver".to_string(),
+                        provider_ref: "fakecloud.io/httpserver:0.1.0".to_string(),
+                        annotations: app_annotations.clone(),
+                    }]),
+                    uptime_seconds: 123,
+                    version: None,
+                    id: host_id.to_string(),
+                    last_seen: Utc::now(),
+                },
+            )
+            .await
+            .unwrap();
+        store
+            .store(
+                lattice_id,
+                "hello-echo".to_string(),
+                Component {
+                    id: "hello-echo".to_string(),
+                    name: "echo".to_string(),
+                    instances: HashMap::from([(
+                        host_id.to_string(),
+                        HashSet::from([WadmComponentInfo {
+                            count: 2,
+                            annotations: app_annotations,
+                        }]),
+                    )]),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        store
+            .store(
+                lattice_id,
+                "hello-httpserver".to_string(),
+                Provider {
+                    id: "hello-httpserver".to_string(),
+                    name: "httpserver".to_string(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let lattice_source = TestLatticeSource {
+            links: vec![Link::builder()
+                .source_id("hello-httpserver")
+                .target("hello-echo")
+                .name("default")
+                .wit_namespace("wasi")
+                .wit_package("http")
+                .interfaces(vec!["incoming-handler".to_string()])
+                .build()
+                .unwrap()],
+            ..Default::default()
+        };
+        let manifest: Manifest = serde_yaml::from_slice(
+            &std::fs::read("../../tests/fixtures/manifests/simple.yaml").unwrap(),
+        )
+        .unwrap();
+
+        let topology = StateTopology::new(store, lattice_source)
+            .topology(lattice_id, None, &[&manifest])
+            .await
+            .expect("Should be able to build topology");
+
+        let node_ids = topology
+            .nodes
+            .iter()
+            .map(|n| n.id.as_str())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(
+            node_ids,
+            BTreeSet::from([
+                "app:hello",
+                format!("app:{}", manifest.metadata.name).as_str(),
+                "component:hello-echo",
+                "host:NASDASDIMAREALHOST",
+                "provider:hello-httpserver",
+            ])
+        );
+
+        let has_edge = |from: &str, to: &str, kind: TopologyEdgeKind| {
+            topology
+                .edges
+                .iter()
+                .any(|e| e.from == from && e.to == to && e.kind == kind)
+        };
+        assert!(has_edge(
+            "component:hello-echo",
+            "host:NASDASDIMAREALHOST",
+            TopologyEdgeKind::RunsOn
+        ));
+        assert!(has_edge(
+            "provider:hello-httpserver",
+            "host:NASDASDIMAREALHOST",
+            TopologyEdgeKind::RunsOn
+        ));
+        assert!(has_edge(
+            "app:hello",
+            "component:hello-echo",
+            TopologyEdgeKind::Manages
+        ));
+        assert!(has_edge(
+            "app:hello",
+            "provider:hello-httpserver",
+            TopologyEdgeKind::Manages
+        ));
+        assert!(has_edge(
+            "provider:hello-httpserver",
+            "component:hello-echo",
+            TopologyEdgeKind::Link
+        ));
+    }
+}
@@ -0,0 +1,119 @@
+//! Propagation of [W3C trace context](https://www.w3.org/TR/trace-context/) through wadm. Context
+//! is taken from the events and commands wadm receives, set as the parent of the span that handles
+//! them and added to the commands wadm publishes while handling them. This lets a single
+//! distributed trace follow an event through the decision wadm makes to the control action sent to
+//! the host
+//!
+//! Nothing is propagated unless wadm is exporting traces, as the context comes from the
+//! OpenTelemetry data of the current span
+
+use std::collections::HashMap;
+
+use async_nats::HeaderMap;
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// The header (and CloudEvent extension attribute) holding the trace and parent span IDs
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+/// The header (and CloudEvent extension attribute) holding vendor specific trace state
+pub const TRACESTATE_HEADER: &str = "tracestate";
+
+/// The trace context of a message, as the W3C `traceparent` and `tracestate` values
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceContext {
+    fields: HashMap<String, String>,
+}
+
+impl TraceContext {
+    /// Returns the trace context from the given NATS headers, if they have one
+    pub fn from_headers(headers: &HeaderMap) -> Option<TraceContext> {
+        TraceContext::from_fields(|name| headers.get(name).map(|value| value.to_string()))
+    }
+
+    /// Returns the trace context from the distributed tracing extension of the given CloudEvent, if
+    /// it has one
+    pub fn from_cloudevent(event: &cloudevents::Event) -> Option<TraceContext> {
+        TraceContext::from_fields(|name| event.extension(name).map(ToString::to_string))
+    }
+
+    /// Returns the trace context of the current span. Returns `None` if the span isn't being
+    /// exported
+    pub fn current() -> Option<TraceContext> {
+        let mut fields = HashMap::new();
+        TraceContextPropagator::new().inject_context(&Span::current().context(), &mut fields);
+        TraceContext::from_fields(|name| fields.remove(name))
+    }
+
+    fn from_fields(mut get: impl FnMut(&str) -> Option<String>) -> Option<TraceContext> {
+        let traceparent = get(TRACEPARENT_HEADER).filter(|value| !value.is_empty())?;
+        let mut fields = HashMap::from([(TRACEPARENT_HEADER.to_owned(), traceparent)]);
+        if let Some(tracestate) = get(TRACESTATE_HEADER).filter(|value| !value.is_empty()) {
+            fields.insert(TRACESTATE_HEADER.to_owned(), tracestate);
+        }
+        Some(TraceContext { fields })
+    }
+
+    /// Returns the `traceparent` value
+    pub fn traceparent(&self) -> &str {
+        self.fields
+            .get(TRACEPARENT_HEADER)
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+
+    /// Sets this context as the parent of the given span. This must be called before the span is
+    /// entered. Invalid contexts are ignored
+    pub fn attach(&self, span: &Span) {
+        span.set_parent(TraceContextPropagator::new().extract(&self.fields));
+    }
+
+    /// Returns NATS headers carrying this context
+    pub fn to_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in self.fields.iter() {
+            headers.insert(name.as_str(), value.as_str());
+        }
+        headers
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_extract_trace_context() {
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+        let mut headers = HeaderMap::new();
+        assert!(TraceContext::from_headers(&headers).is_none());
+        headers.insert(TRACEPARENT_HEADER, traceparent);
+        headers.insert(TRACESTATE_HEADER, "wasmcloud=1");
+        let from_headers = TraceContext::from_headers(&headers).expect("Should find context");
+        assert_eq!(from_headers.traceparent(), traceparent);
+        assert_eq!(
+            TraceContext::from_headers(&from_headers.to_headers()),
+            Some(from_headers.clone()),
+            "Context should round trip through headers"
+        );
+
+        let event: cloudevents::Event = serde_json::from_value(serde_json::json!({
+            "specversion": "1.0",
+            "id": "01J0000000",
+            "source": "host",
+            "type": "com.wasmcloud.lattice.host_heartbeat",
+            "traceparent": traceparent,
+            "tracestate": "wasmcloud=1",
+            "data": {},
+        }))
+        .unwrap();
+        assert_eq!(TraceContext::from_cloudevent(&event), Some(from_headers));
+
+        assert!(
+            TraceContext::current().is_none(),
+            "Spans that aren't exported shouldn't have a context"
+        );
+    }
+}
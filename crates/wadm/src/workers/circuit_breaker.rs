@@ -0,0 +1,335 @@
+//! A per host circuit breaker for control interface calls. A host that repeatedly fails to respond
+//! has its circuit opened for a cooldown period, during which calls to it fail immediately and it
+//! is left out of the state scalers use for placement so work is routed to other hosts
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::clock::{self, SharedClock};
+
+use opentelemetry_metrics::{
+    metrics::{Counter, ObservableGauge},
+    KeyValue,
+};
+use tracing::{debug, warn};
+
+/// Default number of consecutive failures before a host's circuit is opened
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+/// Default amount of time a host's circuit stays open before calls are allowed through again
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+/// Default number of attempts made for a single call before giving up
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 2;
+/// Default delay between attempts, which is multiplied by the attempt number
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// The state of a circuit for a single host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Calls are flowing normally
+    Closed,
+    /// The cooldown has passed and calls are allowed through to check if the host has recovered.
+    /// A single failure will open the circuit again
+    HalfOpen,
+    /// The host has failed too many times and calls to it are rejected
+    Open,
+}
+
+impl CircuitState {
+    /// The value this state is reported as in metrics
+    fn metric_value(&self) -> u64 {
+        match self {
+            CircuitState::Closed => 0,
+            CircuitState::HalfOpen => 1,
+            CircuitState::Open => 2,
+        }
+    }
+}
+
+/// Error returned when a call is rejected because the host's circuit is open
+#[derive(Debug, thiserror::Error)]
+#[error("Circuit is open for host {0}, not sending control interface request")]
+pub struct CircuitOpenError(pub String);
+
+#[derive(Debug, Default)]
+struct Circuit {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+#[derive(Clone)]
+struct Metrics {
+    opened: Counter<u64>,
+    // NOTE: This is only held to keep the callback registered for as long as the breaker lives
+    _state: ObservableGauge<u64>,
+}
+
+/// A circuit breaker that tracks control interface failures for each host. Cloning is cheap and
+/// all clones share the same state, so the same breaker can be given to everything that talks to
+/// or places work on hosts
+#[derive(Clone)]
+pub struct HostCircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    max_attempts: u32,
+    retry_delay: Duration,
+    circuits: Arc<RwLock<HashMap<String, Circuit>>>,
+    metrics: Metrics,
+    clock: SharedClock,
+}
+
+impl Default for HostCircuitBreaker {
+    fn default() -> Self {
+        HostCircuitBreaker::new(DEFAULT_FAILURE_THRESHOLD, DEFAULT_COOLDOWN)
+    }
+}
+
+impl HostCircuitBreaker {
+    /// Creates a new circuit breaker that opens a host's circuit after `failure_threshold`
+    /// consecutive failures and keeps it open for `cooldown`.
+    ///
+    /// This registers metrics with the global meter provider, so it should be called after any
+    /// provider is installed
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> HostCircuitBreaker {
+        HostCircuitBreaker::new_with_clock(failure_threshold, cooldown, clock::system())
+    }
+
+    /// Creates a new circuit breaker like [`HostCircuitBreaker::new`] that measures cooldowns with
+    /// the given clock
+    pub fn new_with_clock(
+        failure_threshold: u32,
+        cooldown: Duration,
+        clock: SharedClock,
+    ) -> HostCircuitBreaker {
+        let circuits: Arc<RwLock<HashMap<String, Circuit>>> = Arc::default();
+        let meter = opentelemetry_metrics::global::meter("wadm");
+        let observed = circuits.clone();
+        let observed_clock = clock.clone();
+        let metrics = Metrics {
+            opened: meter
+                .u64_counter("wadm.ctl.circuit.opened")
+                .with_description("Number of times a host's control interface circuit was opened")
+                .init(),
+            _state: meter
+                .u64_observable_gauge("wadm.ctl.circuit.state")
+                .with_description(
+                    "State of each host's control interface circuit. 0 is closed, 1 is half open and 2 is open",
+                )
+                .with_callback(move |observer| {
+                    let circuits = observed.read().unwrap_or_else(|e| e.into_inner());
+                    let now = observed_clock.instant();
+                    for (host_id, circuit) in circuits.iter() {
+                        observer.observe(
+                            circuit_state(circuit, cooldown, now).metric_value(),
+                            &[KeyValue::new("host_id", host_id.clone())],
+                        );
+                    }
+                })
+                .init(),
+        };
+        HostCircuitBreaker {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            circuits,
+            metrics,
+            clock,
+        }
+    }
+
+    /// Sets the number of attempts made for a single call and the base delay between them
+    pub fn with_retries(mut self, max_attempts: u32, retry_delay: Duration) -> HostCircuitBreaker {
+        self.max_attempts = max_attempts.max(1);
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Returns the clock this breaker measures cooldowns with
+    pub fn clock(&self) -> SharedClock {
+        self.clock.clone()
+    }
+
+    /// Returns the current state of the circuit for the given host
+    pub fn state(&self, host_id: &str) -> CircuitState {
+        self.circuits
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(host_id)
+            .map(|circuit| circuit_state(circuit, self.cooldown, self.clock.instant()))
+            .unwrap_or(CircuitState::Closed)
+    }
+
+    /// Returns whether work can currently be sent to the given host
+    pub fn is_available(&self, host_id: &str) -> bool {
+        self.state(host_id) != CircuitState::Open
+    }
+
+    /// Records a successful call to the given host, closing its circuit
+    pub fn record_success(&self, host_id: &str) {
+        let mut circuits = self.circuits.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(circuit) = circuits.remove(host_id) {
+            if circuit.opened_at.is_some() {
+                debug!(%host_id, "Host responded successfully, closing circuit");
+            }
+        }
+    }
+
+    /// Records a failed call to the given host. Returns true if the circuit is now open
+    pub fn record_failure(&self, host_id: &str) -> bool {
+        let mut circuits = self.circuits.write().unwrap_or_else(|e| e.into_inner());
+        let circuit = circuits.entry(host_id.to_owned()).or_default();
+        circuit.consecutive_failures = circuit.consecutive_failures.saturating_add(1);
+        if circuit.consecutive_failures >= self.failure_threshold {
+            // A failure while half open (or the threshold being hit for the first time) restarts
+            // the cooldown
+            let now = self.clock.instant();
+            if circuit_state(circuit, self.cooldown, now) != CircuitState::Open {
+                warn!(%host_id, failures = circuit.consecutive_failures, cooldown = ?self.cooldown, "Opening circuit for unresponsive host");
+                circuit.opened_at = Some(now);
+                self.metrics
+                    .opened
+                    .add(1, &[KeyValue::new("host_id", host_id.to_owned())]);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Calls the given operation for the given host, retrying failures up to the configured number
+    /// of attempts. Calls are rejected with a [`CircuitOpenError`] without running the operation if
+    /// the host's circuit is open.
+    ///
+    /// Only errors returned by the operation count as failures. Responses that the host sent back,
+    /// even negative ones, mean the host is responsive and should be returned as `Ok`
+    pub async fn call<T, E, F, Fut>(&self, host_id: &str, mut operation: F) -> anyhow::Result<T>
+    where
+        E: std::fmt::Debug,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 0;
+        loop {
+            if !self.is_available(host_id) {
+                return Err(CircuitOpenError(host_id.to_owned()).into());
+            }
+            attempt += 1;
+            match operation().await {
+                Ok(res) => {
+                    self.record_success(host_id);
+                    return Ok(res);
+                }
+                Err(e) => {
+                    let opened = self.record_failure(host_id);
+                    if opened || attempt >= self.max_attempts {
+                        return Err(anyhow::anyhow!("{e:?}"));
+                    }
+                    debug!(%host_id, %attempt, error = ?e, "Control interface call failed, retrying");
+                    tokio::time::sleep(self.retry_delay * attempt).await;
+                }
+            }
+        }
+    }
+}
+
+fn circuit_state(circuit: &Circuit, cooldown: Duration, now: Instant) -> CircuitState {
+    match circuit.opened_at {
+        Some(opened_at) if now.saturating_duration_since(opened_at) < cooldown => {
+            CircuitState::Open
+        }
+        Some(_) => CircuitState::HalfOpen,
+        None => CircuitState::Closed,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[tokio::test]
+    async fn can_open_and_close_circuits() {
+        let breaker =
+            HostCircuitBreaker::new(2, Duration::from_millis(50)).with_retries(1, Duration::ZERO);
+        let host = "NASDASDIMAREALHOST";
+
+        let failing = || async { Err::<(), _>("timed out") };
+        assert!(breaker.call(host, failing).await.is_err());
+        assert_eq!(breaker.state(host), CircuitState::Closed);
+        assert!(breaker.call(host, failing).await.is_err());
+        assert_eq!(breaker.state(host), CircuitState::Open);
+        assert!(
+            breaker.is_available("NASDASDIMAREALHOST2"),
+            "Other hosts should not be affected"
+        );
+
+        // Calls are rejected without running while the circuit is open
+        let mut called = false;
+        let err = breaker
+            .call(host, || {
+                called = true;
+                async { Ok::<_, &str>(()) }
+            })
+            .await
+            .expect_err("Call should be rejected");
+        assert!(err.is::<CircuitOpenError>());
+        assert!(
+            !called,
+            "Operation should not run while the circuit is open"
+        );
+
+        // After the cooldown a failure reopens the circuit and a success closes it
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(breaker.state(host), CircuitState::HalfOpen);
+        assert!(breaker.call(host, failing).await.is_err());
+        assert_eq!(breaker.state(host), CircuitState::Open);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        breaker
+            .call(host, || async { Ok::<_, &str>(()) })
+            .await
+            .expect("Call should go through once the host recovers");
+        assert_eq!(breaker.state(host), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn cooldown_follows_clock() {
+        let clock = ManualClock::default();
+        let breaker =
+            HostCircuitBreaker::new_with_clock(1, DEFAULT_COOLDOWN, Arc::new(clock.clone()))
+                .with_retries(1, Duration::ZERO);
+        let host = "NASDASDIMAREALHOST";
+
+        assert!(breaker
+            .call(host, || async { Err::<(), _>("timed out") })
+            .await
+            .is_err());
+        assert_eq!(breaker.state(host), CircuitState::Open);
+        clock.advance(DEFAULT_COOLDOWN - Duration::from_secs(1));
+        assert_eq!(
+            breaker.state(host),
+            CircuitState::Open,
+            "Circuit should stay open until the cooldown has passed on the clock"
+        );
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(breaker.state(host), CircuitState::HalfOpen);
+    }
+
+    #[tokio::test]
+    async fn retries_are_bounded() {
+        let breaker = HostCircuitBreaker::new(10, DEFAULT_COOLDOWN).with_retries(3, Duration::ZERO);
+        let mut attempts = 0;
+        let res = breaker
+            .call("NASDASDIMAREALHOST", || {
+                attempts += 1;
+                async { Err::<(), _>("timed out") }
+            })
+            .await;
+        assert!(res.is_err());
+        assert_eq!(attempts, 3);
+    }
+}
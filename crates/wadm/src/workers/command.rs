@@ -1,25 +1,275 @@
-use tracing::{instrument, trace};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use opentelemetry_metrics::{metrics::Counter, KeyValue};
+use tracing::{error, instrument, trace, warn};
+use wadm_types::api::{CommandAck, CommandOutcome};
+use wasmcloud_control_interface::RegistryCredential;
 
 use crate::{
+    clock::{self, SharedClock},
     commands::*,
     consumers::{
         manager::{WorkError, WorkResult, Worker},
         ScopedMessage,
     },
+    oci::ImageReference,
+};
+
+use super::{
+    insert_managed_annotations, reconcile_report::describe_command, CommandAckStore,
+    ControlRateLimiter, DeadLetter, DeadLetterQueue, HostCircuitBreaker,
 };
 
-use super::insert_managed_annotations;
+/// How often a command that is waiting on the rate limiter tells the server it is still being
+/// worked on. This needs to be shorter than the ack wait of the command consumer
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How failed commands are retried before they are given up on
+#[derive(Clone, Copy, Debug)]
+pub struct CommandRetryPolicy {
+    /// The total number of times a command is attempted, including the first attempt. The command
+    /// consumer must be allowed to deliver a message at least this many times
+    pub max_attempts: u64,
+    /// The delay before the first retry. Each subsequent retry doubles the delay
+    pub base_delay: Duration,
+    /// The longest a retry will be delayed
+    pub max_delay: Duration,
+}
+
+impl Default for CommandRetryPolicy {
+    fn default() -> Self {
+        CommandRetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl CommandRetryPolicy {
+    /// Returns how long to wait before retrying a command that failed on the given attempt
+    /// (starting at 1)
+    pub fn delay_for(&self, attempt: u64) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31) as u32;
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(exponent))
+            .min(self.max_delay)
+    }
+}
 
 /// A worker implementation for handling incoming commands
 #[derive(Clone)]
 pub struct CommandWorker {
     client: wasmcloud_control_interface::Client,
+    circuit_breaker: HostCircuitBreaker,
+    retry_policy: CommandRetryPolicy,
+    dead_letter: Option<DeadLetterQueue>,
+    acks: Option<CommandAckStore>,
+    expired: Counter<u64>,
+    clock: SharedClock,
+    registry_credentials: Arc<HashMap<String, RegistryCredential>>,
+    rate_limiter: ControlRateLimiter,
+    multitenant_prefix: Option<String>,
 }
 
 impl CommandWorker {
     /// Creates a new command worker with the given connection pool.
     pub fn new(ctl_client: wasmcloud_control_interface::Client) -> CommandWorker {
-        CommandWorker { client: ctl_client }
+        CommandWorker {
+            client: ctl_client,
+            circuit_breaker: HostCircuitBreaker::default(),
+            retry_policy: CommandRetryPolicy::default(),
+            dead_letter: None,
+            acks: None,
+            expired: opentelemetry_metrics::global::meter("wadm")
+                .u64_counter("wadm.commands.expired")
+                .with_description(
+                    "Number of commands that were discarded because they expired before they could be executed",
+                )
+                .init(),
+            clock: clock::system(),
+            registry_credentials: Arc::default(),
+            rate_limiter: ControlRateLimiter::default(),
+            multitenant_prefix: None,
+        }
+    }
+
+    /// Sets the account of the lattice this worker handles commands for, when wadm is running in
+    /// multitenant mode. Dead letters remember it so they are replayed to the right account
+    pub fn with_multitenant_prefix(
+        mut self,
+        multitenant_prefix: impl Into<String>,
+    ) -> CommandWorker {
+        self.multitenant_prefix = Some(multitenant_prefix.into());
+        self
+    }
+
+    /// Sets how failed commands are retried
+    pub fn with_retry_policy(mut self, retry_policy: CommandRetryPolicy) -> CommandWorker {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the queue that commands are sent to once they have failed on every attempt. If this
+    /// isn't set, those commands are dropped
+    pub fn with_dead_letter_queue(mut self, dead_letter: DeadLetterQueue) -> CommandWorker {
+        self.dead_letter = Some(dead_letter);
+        self
+    }
+
+    /// Sets the store the response to each command is recorded in. Commands that fail on their last
+    /// attempt are reported in the status of their model. If this isn't set, responses are only
+    /// logged
+    pub fn with_ack_store(mut self, acks: CommandAckStore) -> CommandWorker {
+        self.acks = Some(acks);
+        self
+    }
+
+    /// Records the response to the command, if there is an ack store. Failing to record it is only
+    /// logged, since the command itself was still handled
+    async fn record_ack(
+        &self,
+        message: &ScopedMessage<Command>,
+        outcome: CommandOutcome,
+        response: String,
+    ) {
+        let Some(acks) = self.acks.as_ref() else {
+            return;
+        };
+        let attempts = message.delivery_count().unwrap_or(1);
+        let ack = CommandAck {
+            command: describe_command(message.as_ref()),
+            outcome,
+            message: response,
+            attempts,
+            last_attempt: outcome != CommandOutcome::Accepted
+                && attempts >= self.retry_policy.max_attempts,
+            time: self.clock.now().to_rfc3339(),
+        };
+        if let Err(e) = acks
+            .record(&message.lattice_id, message.as_ref(), ack)
+            .await
+        {
+            warn!(error = %e, "Unable to record command ack");
+        }
+    }
+
+    /// Handles a command that failed to execute, either delaying it to be retried or dead
+    /// lettering it if it has run out of attempts
+    async fn handle_failure(
+        &self,
+        mut message: ScopedMessage<Command>,
+        err: anyhow::Error,
+    ) -> WorkResult<()> {
+        let attempts = message.delivery_count().unwrap_or(1);
+        if attempts < self.retry_policy.max_attempts {
+            let delay = self.retry_policy.delay_for(attempts);
+            warn!(error = %err, %attempts, ?delay, "Command failed, will retry");
+            message.nack_with_delay(delay).await;
+            return Err(WorkError::Other(err.into()));
+        }
+
+        let Some(dead_letter) = self.dead_letter.as_ref() else {
+            warn!(error = %err, %attempts, "Command failed on its last attempt, dropping it");
+            return message.term().await.map_err(WorkError::from);
+        };
+        let letter = DeadLetter {
+            lattice_id: message.lattice_id.clone(),
+            multitenant_prefix: self.multitenant_prefix.clone(),
+            command: message.as_ref().clone(),
+            attempts,
+            error: err.to_string(),
+            failed_at: self.clock.now(),
+        };
+        match dead_letter.send(&letter).await {
+            Ok(()) => {
+                warn!(error = %err, %attempts, "Command failed on its last attempt, sent it to the dead letter queue");
+                message.ack().await.map_err(WorkError::from)
+            }
+            Err(e) => {
+                // Leave the command to be redelivered so it isn't lost. If the consumer doesn't
+                // have any deliveries left, there isn't anything else we can do
+                error!(error = %e, "Unable to dead letter failed command");
+                message.nack().await;
+                Err(WorkError::Other(err.into()))
+            }
+        }
+    }
+
+    /// Sets the clock used to check whether commands have expired
+    pub fn with_clock(mut self, clock: SharedClock) -> CommandWorker {
+        self.clock = clock;
+        self
+    }
+
+    /// Sets the credentials for private registries, keyed by registry (e.g. `ghcr.io`). Before a
+    /// component or provider is started from one of these registries, its credentials are put into
+    /// the lattice so hosts can pull the image without being configured with them
+    pub fn with_registry_credentials(
+        mut self,
+        credentials: HashMap<String, RegistryCredential>,
+    ) -> CommandWorker {
+        self.registry_credentials = Arc::new(credentials);
+        self
+    }
+
+    /// Puts the credentials for the registry of the given image into the lattice, if there are
+    /// any. Failing to put them is only logged, since the image may not need them
+    async fn put_registry_credentials(&self, image: &str) {
+        let Some((registry, credential)) = image
+            .parse::<ImageReference>()
+            .ok()
+            .and_then(|image| self.registry_credentials.get_key_value(&image.registry))
+        else {
+            return;
+        };
+        trace!(%registry, "Putting registry credentials before starting image");
+        match self
+            .client
+            .put_registries(HashMap::from([(registry.clone(), credential.clone())]))
+            .await
+        {
+            Ok(ack) if !ack.succeeded() => {
+                warn!(%registry, error = %ack.message(), "Unable to put registry credentials")
+            }
+            Ok(_) => (),
+            Err(e) => warn!(%registry, error = %e, "Unable to put registry credentials"),
+        }
+    }
+
+    /// Sets the limiter that spreads out control interface calls. This should be shared with the
+    /// command workers of every lattice so global limits apply across all of them
+    pub fn with_rate_limiter(mut self, rate_limiter: ControlRateLimiter) -> CommandWorker {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    /// Waits until the rate limiter allows a call for the message's lattice, keeping the message
+    /// from being redelivered while it waits
+    async fn wait_for_rate_limit(&self, message: &ScopedMessage<Command>) {
+        let mut wait = self.rate_limiter.reserve(&message.lattice_id);
+        if !wait.is_zero() {
+            trace!(?wait, "Waiting for rate limit before sending command");
+        }
+        while !wait.is_zero() {
+            let step = wait.min(PROGRESS_INTERVAL);
+            tokio::time::sleep(step).await;
+            wait -= step;
+            if !wait.is_zero() {
+                if let Err(e) = message.in_progress().await {
+                    warn!(error = %e, "Unable to mark command as in progress while rate limited");
+                }
+            }
+        }
+    }
+
+    /// Sets the circuit breaker used for commands sent to specific hosts. This should be shared
+    /// with the scaler managers for the lattice so they stop placing work on unresponsive hosts
+    pub fn with_circuit_breaker(mut self, circuit_breaker: HostCircuitBreaker) -> CommandWorker {
+        self.circuit_breaker = circuit_breaker;
+        self
     }
 }
 
@@ -29,36 +279,60 @@ impl Worker for CommandWorker {
 
     #[instrument(level = "trace", skip_all)]
     async fn do_work(&self, mut message: ScopedMessage<Self::Message>) -> WorkResult<()> {
+        // The lattice has likely changed since an expired command was computed, so executing it
+        // could undo work. The next reconcile will publish a fresh command if it is still needed
+        if message.is_expired_at(self.clock.now()) {
+            warn!(command = ?message.as_ref(), expires_at = ?message.expires_at(), "Discarding expired command");
+            self.expired.add(
+                1,
+                &[KeyValue::new("lattice_id", message.lattice_id.clone())],
+            );
+            return message.ack().await.map_err(WorkError::from);
+        }
+        self.wait_for_rate_limit(&message).await;
         let res = match message.as_ref() {
             Command::ScaleComponent(component) => {
                 trace!(command = ?component, "Handling scale component command");
                 // Order here is intentional to prevent scalers from overwriting managed annotations
                 let mut annotations = component.annotations.clone();
                 insert_managed_annotations(&mut annotations, &component.model_name);
-                self.client
-                    .scale_component(
-                        &component.host_id,
-                        &component.reference,
-                        &component.component_id,
-                        component.count,
-                        Some(annotations.into_iter().collect()),
-                        component.config.clone(),
-                    )
+                // Scaling down never pulls the image
+                if component.count > 0 {
+                    self.put_registry_credentials(&component.reference).await;
+                }
+                self.circuit_breaker
+                    .call(&component.host_id, || {
+                        self.client.scale_component(
+                            &component.host_id,
+                            &component.reference,
+                            &component.component_id,
+                            component.count,
+                            Some(annotations.clone().into_iter().collect()),
+                            component.config.clone(),
+                        )
+                    })
                     .await
             }
             Command::StartProvider(prov) => {
                 trace!(command = ?prov, "Handling start provider command");
+                // NOTE: The host control interface has no operation to pre-fetch an artifact
+                // without starting it, so images can't be warmed up on the target hosts before
+                // scaling. Until hosts support that, downloading the provider is part of starting
+                // it, which is what dominates convergence time for new providers
                 // Order here is intentional to prevent scalers from overwriting managed annotations
                 let mut annotations = prov.annotations.clone();
                 insert_managed_annotations(&mut annotations, &prov.model_name);
-                self.client
-                    .start_provider(
-                        &prov.host_id,
-                        &prov.reference,
-                        &prov.provider_id,
-                        Some(annotations.into_iter().collect()),
-                        prov.config.clone(),
-                    )
+                self.put_registry_credentials(&prov.reference).await;
+                self.circuit_breaker
+                    .call(&prov.host_id, || {
+                        self.client.start_provider(
+                            &prov.host_id,
+                            &prov.reference,
+                            &prov.provider_id,
+                            Some(annotations.clone().into_iter().collect()),
+                            prov.config.clone(),
+                        )
+                    })
                     .await
             }
             Command::StopProvider(prov) => {
@@ -66,15 +340,20 @@ impl Worker for CommandWorker {
                 // Order here is intentional to prevent scalers from overwriting managed annotations
                 let mut annotations = prov.annotations.clone();
                 insert_managed_annotations(&mut annotations, &prov.model_name);
-                self.client
-                    .stop_provider(&prov.host_id, &prov.provider_id)
+                self.circuit_breaker
+                    .call(&prov.host_id, || {
+                        self.client.stop_provider(&prov.host_id, &prov.provider_id)
+                    })
                     .await
             }
             Command::PutLink(ld) => {
                 trace!(command = ?ld, "Handling put linkdef command");
                 // TODO(thomastaylor312): We should probably change ScopedMessage to allow us `pub`
                 // access to the inner type so we don't have to clone, but no need to worry for now
-                self.client.put_link(ld.clone().try_into()?).await
+                self.client
+                    .put_link(ld.clone().try_into()?)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("{e:?}"))
             }
             Command::DeleteLink(ld) => {
                 trace!(command = ?ld, "Handling delete linkdef command");
@@ -86,32 +365,65 @@ impl Worker for CommandWorker {
                         &ld.wit_package,
                     )
                     .await
+                    .map_err(|e| anyhow::anyhow!("{e:?}"))
             }
             Command::PutConfig(put_config) => {
                 trace!(command = ?put_config, "Handling put config command");
                 self.client
                     .put_config(&put_config.config_name, put_config.config.clone())
                     .await
+                    .map_err(|e| anyhow::anyhow!("{e:?}"))
             }
             Command::DeleteConfig(delete_config) => {
                 trace!(command = ?delete_config, "Handling delete config command");
-                self.client.delete_config(&delete_config.config_name).await
+                self.client
+                    .delete_config(&delete_config.config_name)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("{e:?}"))
             }
-        }
-        .map_err(|e| anyhow::anyhow!("{e:?}"));
+        };
 
         match res {
             Ok(ack) if !ack.succeeded() => {
-                message.nack().await;
-                Err(WorkError::Other(
-                    anyhow::anyhow!("{}", ack.message()).into(),
-                ))
+                self.record_ack(&message, CommandOutcome::Rejected, ack.message().to_owned())
+                    .await;
+                self.handle_failure(message, anyhow::anyhow!("{}", ack.message()))
+                    .await
+            }
+            Ok(_) => {
+                self.record_ack(&message, CommandOutcome::Accepted, String::new())
+                    .await;
+                message.ack().await.map_err(WorkError::from)
             }
-            Ok(_) => message.ack().await.map_err(WorkError::from),
             Err(e) => {
-                message.nack().await;
-                Err(WorkError::Other(e.into()))
+                self.record_ack(&message, CommandOutcome::Failed, e.to_string())
+                    .await;
+                self.handle_failure(message, e).await
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn retry_delay_backs_off_exponentially() {
+        let policy = CommandRetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(5),
+        };
+        assert_eq!(policy.delay_for(1), Duration::from_millis(500));
+        assert_eq!(policy.delay_for(2), Duration::from_secs(1));
+        assert_eq!(policy.delay_for(3), Duration::from_secs(2));
+        assert_eq!(policy.delay_for(4), Duration::from_secs(4));
+        assert_eq!(
+            policy.delay_for(5),
+            Duration::from_secs(5),
+            "Delay should be capped at the max delay"
+        );
+        assert_eq!(policy.delay_for(u64::MAX), Duration::from_secs(5));
+    }
+}
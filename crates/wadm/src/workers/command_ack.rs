@@ -0,0 +1,217 @@
+//! Acknowledgements of the commands sent to the lattice. The last response to each command is kept
+//! per model, so commands that keep failing show up in the model's status instead of only in logs
+
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::Result;
+use async_nats::jetstream::kv::{Operation, Store};
+use chrono::{DateTime, Utc};
+use tracing::{instrument, trace};
+use wadm_types::api::{CommandAck, CommandOutcome};
+
+use crate::commands::Command;
+
+/// The default amount of time acks are kept for. Acks for commands that haven't been sent again
+/// since (e.g. for a host that has gone away) are dropped once they are this old
+pub const DEFAULT_COMMAND_ACK_TTL: Duration = Duration::from_secs(3600);
+
+/// The number of times to try storing an ack when other acks for the model are stored at the same
+/// time
+const MAX_UPDATE_ATTEMPTS: usize = 3;
+
+/// The acks for a single model, keyed by the target of each command
+type ModelAcks = BTreeMap<String, CommandAck>;
+
+/// Returns the key the acks for a model are stored under
+pub fn command_ack_key(lattice_id: &str, model_name: &str) -> String {
+    // Keys can only contain some characters, and the separator can't show up in either part
+    let sanitize = |s: &str| {
+        s.chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>()
+    };
+    format!("{}.{}", sanitize(lattice_id), sanitize(model_name))
+}
+
+/// Stores the last ack for each command in a KV bucket, with one entry per model
+#[derive(Clone)]
+pub struct CommandAckStore {
+    store: Store,
+    ttl: Duration,
+}
+
+impl CommandAckStore {
+    /// Creates a store that keeps acks in the given bucket for [`DEFAULT_COMMAND_ACK_TTL`]
+    pub fn new(store: Store) -> CommandAckStore {
+        CommandAckStore {
+            store,
+            ttl: DEFAULT_COMMAND_ACK_TTL,
+        }
+    }
+
+    /// Sets how long acks are kept for
+    pub fn with_ttl(mut self, ttl: Duration) -> CommandAckStore {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Records the ack for the given command, replacing the last ack for the same target. Commands
+    /// that aren't for a model, like config commands, aren't recorded
+    #[instrument(level = "trace", skip(self, command, ack))]
+    pub async fn record(&self, lattice_id: &str, command: &Command, ack: CommandAck) -> Result<()> {
+        let Some(model_name) = command.model_name() else {
+            return Ok(());
+        };
+        let key = command_ack_key(lattice_id, model_name);
+        let target = command.target_id();
+        let mut attempt = 1;
+        loop {
+            let (mut acks, revision) = self.get(&key).await?;
+            insert_ack(&mut acks, target.clone(), ack.clone(), Utc::now(), self.ttl);
+            let data = serde_json::to_vec(&acks)?.into();
+            let res = match revision {
+                Some(revision) => self
+                    .store
+                    .update(&key, data, revision)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("{e:?}")),
+                None => self
+                    .store
+                    .create(&key, data)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("{e:?}")),
+            };
+            match res {
+                Ok(_) => return Ok(()),
+                Err(e) if attempt >= MAX_UPDATE_ATTEMPTS => {
+                    return Err(e.context(format!("Unable to store command ack for {key}")))
+                }
+                Err(e) => {
+                    trace!(error = %e, %key, "Command acks were updated concurrently, retrying");
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Returns the acks of the commands for the given model that failed on their last attempt
+    pub async fn failures(&self, lattice_id: &str, model_name: &str) -> Result<Vec<CommandAck>> {
+        let (acks, _) = self.get(&command_ack_key(lattice_id, model_name)).await?;
+        Ok(last_attempt_failures(acks, Utc::now(), self.ttl))
+    }
+
+    async fn get(&self, key: &str) -> Result<(ModelAcks, Option<u64>)> {
+        let Some(entry) = self
+            .store
+            .entry(key)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?
+        else {
+            return Ok((ModelAcks::new(), None));
+        };
+        if matches!(entry.operation, Operation::Delete | Operation::Purge) {
+            return Ok((ModelAcks::new(), Some(entry.revision)));
+        }
+        Ok((serde_json::from_slice(&entry.value)?, Some(entry.revision)))
+    }
+}
+
+/// Whether the ack was received longer than the TTL ago. Acks with an invalid time are stale
+fn is_stale(ack: &CommandAck, now: DateTime<Utc>, ttl: Duration) -> bool {
+    let expires = DateTime::parse_from_rfc3339(&ack.time)
+        .ok()
+        .and_then(|time| chrono::Duration::from_std(ttl).ok().map(|ttl| time + ttl));
+    !matches!(expires, Some(expires) if expires >= now)
+}
+
+/// Replaces the ack for the target, dropping any acks that are stale
+fn insert_ack(
+    acks: &mut ModelAcks,
+    target: String,
+    ack: CommandAck,
+    now: DateTime<Utc>,
+    ttl: Duration,
+) {
+    acks.retain(|_, ack| !is_stale(ack, now, ttl));
+    acks.insert(target, ack);
+}
+
+fn last_attempt_failures(acks: ModelAcks, now: DateTime<Utc>, ttl: Duration) -> Vec<CommandAck> {
+    acks.into_values()
+        .filter(|ack| {
+            ack.outcome != CommandOutcome::Accepted && ack.last_attempt && !is_stale(ack, now, ttl)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ack(outcome: CommandOutcome, last_attempt: bool, time: DateTime<Utc>) -> CommandAck {
+        CommandAck {
+            command: "Stop provider httpserver on host host1".to_string(),
+            outcome,
+            message: String::new(),
+            attempts: 3,
+            last_attempt,
+            time: time.to_rfc3339(),
+        }
+    }
+
+    #[test]
+    fn reports_failures_until_the_command_succeeds() {
+        let ttl = Duration::from_secs(60);
+        let now = Utc::now();
+        let old = now - chrono::Duration::seconds(120);
+        let mut acks = ModelAcks::new();
+        insert_ack(
+            &mut acks,
+            "provider/httpserver/host1".to_string(),
+            ack(CommandOutcome::Rejected, true, now),
+            now,
+            ttl,
+        );
+        insert_ack(
+            &mut acks,
+            "provider/httpserver/host2".to_string(),
+            ack(CommandOutcome::Failed, false, now),
+            now,
+            ttl,
+        );
+        acks.insert(
+            "provider/httpserver/host3".to_string(),
+            ack(CommandOutcome::Failed, true, old),
+        );
+        assert_eq!(
+            last_attempt_failures(acks.clone(), now, ttl),
+            vec![ack(CommandOutcome::Rejected, true, now)],
+            "Only fresh failures on the last attempt should be reported"
+        );
+
+        insert_ack(
+            &mut acks,
+            "provider/httpserver/host1".to_string(),
+            ack(CommandOutcome::Accepted, false, now),
+            now,
+            ttl,
+        );
+        assert!(last_attempt_failures(acks.clone(), now, ttl).is_empty());
+        assert!(
+            !acks.contains_key("provider/httpserver/host3"),
+            "Stale acks should be dropped when storing a new one"
+        );
+
+        assert_eq!(
+            command_ack_key("default", "my.app"),
+            "default.my_app",
+            "Model names shouldn't be able to add key tokens"
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_nats::{
+    jetstream::{
+        consumer::{pull::Config as PullConfig, AckPolicy, DeliverPolicy},
+        stream::{RawMessageErrorKind, Stream},
+        Context,
+    },
+    HeaderMap,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, warn};
+
+use crate::{
+    commands::{Command, CommandEnvelope, PublishedCommand},
+    nats_utils::lattice_subject,
+    publisher::{Encoding, Publisher},
+};
+
+/// The maximum number of dead letters returned when listing a lattice's dead letters
+const MAX_DEAD_LETTERS_LISTED: u64 = 1000;
+
+/// A command that could not be executed after all of its attempts, along with information about
+/// why it failed
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// The lattice the command was sent to
+    pub lattice_id: String,
+    /// The account of the lattice, when wadm is running in multitenant mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multitenant_prefix: Option<String>,
+    /// The command that failed
+    pub command: Command,
+    /// The number of times the command was attempted
+    pub attempts: u64,
+    /// The error returned by the last attempt
+    pub error: String,
+    /// When the command was dead lettered
+    pub failed_at: DateTime<Utc>,
+}
+
+/// Publishes commands that have exhausted their retries to a dead letter topic so operators can
+/// inspect and replay them
+#[derive(Clone)]
+pub struct DeadLetterQueue {
+    publisher: Arc<dyn Publisher + Send + Sync>,
+    topic_prefix: String,
+}
+
+impl DeadLetterQueue {
+    /// Creates a new dead letter queue that publishes with the given publisher. Dead letters are
+    /// sent to `{topic_prefix}.{lattice_id}`
+    pub fn new(
+        publisher: impl Publisher + Send + Sync + 'static,
+        topic_prefix: &str,
+    ) -> DeadLetterQueue {
+        DeadLetterQueue {
+            publisher: Arc::new(publisher),
+            topic_prefix: topic_prefix.trim_end_matches('.').to_owned(),
+        }
+    }
+
+    /// Returns the topic dead letters for the given lattice are published on
+    pub fn topic(&self, lattice_id: &str) -> String {
+        format!("{}.{lattice_id}", self.topic_prefix)
+    }
+
+    /// Publishes the given dead letter, returning an error if it couldn't be sent
+    #[instrument(level = "debug", skip_all, fields(lattice_id = %letter.lattice_id))]
+    pub async fn send(&self, letter: &DeadLetter) -> Result<()> {
+        let data = serde_json::to_vec(letter)?;
+        self.publisher
+            .publish(data, Some(&self.topic(&letter.lattice_id)))
+            .await
+    }
+}
+
+/// A dead letter along with its sequence in the dead letter stream
+#[derive(Clone, Debug)]
+pub struct StoredDeadLetter {
+    /// The sequence of the dead letter, used to select it for replay
+    pub sequence: u64,
+    /// The dead letter
+    pub letter: DeadLetter,
+}
+
+/// The outcome of replaying dead letters
+#[derive(Clone, Debug, Default)]
+pub struct ReplayOutcome {
+    /// The sequences of the dead letters that were sent back to be executed
+    pub replayed: Vec<u64>,
+    /// The requested sequences that weren't dead letters for the lattice
+    pub not_found: Vec<u64>,
+}
+
+/// Anything that can list and replay dead lettered commands
+#[async_trait]
+pub trait DeadLetterSource {
+    /// Returns the dead letters for the given lattice, oldest first
+    async fn list(&self, lattice_id: &str) -> Result<Vec<StoredDeadLetter>>;
+
+    /// Sends the dead letters with the given sequences back to the lattice's command topic and
+    /// removes them from the dead letters. If no sequences are given, all dead letters for the
+    /// lattice are replayed
+    async fn replay(&self, lattice_id: &str, sequences: Option<&[u64]>) -> Result<ReplayOutcome>;
+}
+
+/// A [`DeadLetterSource`] backed by the JetStream stream that a [`DeadLetterQueue`] publishes to
+#[derive(Clone)]
+pub struct StreamDeadLetters {
+    context: Context,
+    stream: Stream,
+    topic_prefix: String,
+    command_topic_prefix: String,
+}
+
+impl StreamDeadLetters {
+    /// Creates a new source from the dead letter stream. The topic prefixes should match those
+    /// used by the [`DeadLetterQueue`] and the command consumers, respectively
+    pub fn new(
+        context: Context,
+        stream: Stream,
+        topic_prefix: &str,
+        command_topic_prefix: &str,
+    ) -> StreamDeadLetters {
+        StreamDeadLetters {
+            context,
+            stream,
+            topic_prefix: topic_prefix.trim_end_matches('.').to_owned(),
+            command_topic_prefix: command_topic_prefix.trim_end_matches('.').to_owned(),
+        }
+    }
+
+    fn topic(&self, lattice_id: &str) -> String {
+        format!("{}.{lattice_id}", self.topic_prefix)
+    }
+}
+
+#[async_trait]
+impl DeadLetterSource for StreamDeadLetters {
+    #[instrument(level = "debug", skip(self))]
+    async fn list(&self, lattice_id: &str) -> Result<Vec<StoredDeadLetter>> {
+        let consumer = self
+            .stream
+            .create_consumer(PullConfig {
+                filter_subject: self.topic(lattice_id),
+                ack_policy: AckPolicy::None,
+                deliver_policy: DeliverPolicy::All,
+                inactive_threshold: Duration::from_secs(30),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+        let pending = consumer.cached_info().num_pending;
+        if pending == 0 {
+            return Ok(Vec::new());
+        }
+        let messages = consumer
+            .fetch()
+            .max_messages(pending.min(MAX_DEAD_LETTERS_LISTED) as usize)
+            .messages()
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+        let letters = messages
+            .map_err(|e| anyhow::anyhow!("{e:?}"))
+            .try_filter_map(|msg| async move {
+                let sequence = msg
+                    .info()
+                    .map_err(|e| anyhow::anyhow!("{e:?}"))?
+                    .stream_sequence;
+                match serde_json::from_slice(&msg.payload) {
+                    Ok(letter) => Ok(Some(StoredDeadLetter { sequence, letter })),
+                    Err(e) => {
+                        warn!(error = %e, %sequence, "Skipping dead letter that couldn't be decoded");
+                        Ok(None)
+                    }
+                }
+            })
+            .try_collect()
+            .await;
+        let name = consumer.cached_info().name.clone();
+        if let Err(e) = self.stream.delete_consumer(&name).await {
+            warn!(error = %e, "Unable to delete dead letter consumer, it will be removed once inactive");
+        }
+        letters
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn replay(&self, lattice_id: &str, sequences: Option<&[u64]>) -> Result<ReplayOutcome> {
+        let sequences = match sequences {
+            Some(sequences) => sequences.to_vec(),
+            None => self
+                .list(lattice_id)
+                .await?
+                .into_iter()
+                .map(|stored| stored.sequence)
+                .collect(),
+        };
+        let topic = self.topic(lattice_id);
+        let mut outcome = ReplayOutcome::default();
+        for sequence in sequences {
+            let msg = match self.stream.get_raw_message(sequence).await {
+                Ok(msg) if msg.subject.as_str() == topic => msg,
+                Ok(_) => {
+                    outcome.not_found.push(sequence);
+                    continue;
+                }
+                Err(e) if e.kind() == RawMessageErrorKind::NoMessageFound => {
+                    outcome.not_found.push(sequence);
+                    continue;
+                }
+                Err(e) => return Err(anyhow::anyhow!("{e:?}")),
+            };
+            let letter: DeadLetter = serde_json::from_slice(&msg.payload)?;
+            let command_topic = lattice_subject(
+                &self.command_topic_prefix,
+                lattice_id,
+                letter.multitenant_prefix.as_deref(),
+            );
+            // NOTE: Replayed commands are never expired, as the whole point is to run them again
+            let envelope = CommandEnvelope::new(PublishedCommand {
+                command: letter.command,
+                expires_at: None,
+            });
+            let mut headers = HeaderMap::new();
+            let data = envelope.encode(Encoding::Json, &mut headers)?;
+            Publisher::publish_with_headers(&self.context, data, Some(&command_topic), headers)
+                .await?;
+            // NOTE: If this fails, the command was still replayed but will be listed until it is
+            // removed. That is better than dropping the command
+            if let Err(e) = self.stream.delete_message(sequence).await {
+                warn!(error = %e, %sequence, "Unable to remove replayed dead letter");
+            }
+            outcome.replayed.push(sequence);
+        }
+        Ok(outcome)
+    }
+}
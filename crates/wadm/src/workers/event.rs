@@ -1,23 +1,30 @@
 use std::collections::BTreeMap;
-use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::collections::{hash_map::Entry, BTreeSet, HashMap, HashSet};
 
 use anyhow::Result;
-use tracing::{debug, instrument, trace, warn};
+use opentelemetry_metrics::{metrics::Counter, KeyValue};
+use tracing::{debug, info, instrument, trace, warn};
 use wadm_types::api::{ScalerStatus, Status, StatusInfo};
 use wasmcloud_control_interface::{ComponentDescription, ProviderDescription};
 
-use crate::commands::Command;
+use crate::commands::{Command, ScaleComponent, StopProvider};
 use crate::consumers::{
-    manager::{WorkError, WorkResult, Worker},
+    manager::{WorkError, WorkKey, WorkResult, Worker},
     ScopedMessage,
 };
 use crate::events::*;
 use crate::publisher::Publisher;
 use crate::scaler::manager::{ScalerList, ScalerManager};
+use crate::scaler::rollout::hand_over_scalers;
 use crate::storage::{Component, Host, Provider, ProviderStatus, Store, WadmComponentInfo};
 use crate::APP_SPEC_ANNOTATION;
 
 use super::event_helpers::*;
+use super::issuers::IssuerAllowList;
+use super::reconcile_report::{ReconcilePass, ReconcileReporter};
+
+/// The prefix of the labels that hosts set on themselves, like `hostcore.os`
+const HOST_BUILTIN_LABEL_PREFIX: &str = "hostcore.";
 
 pub struct EventWorker<StateStore, C: Clone, P: Clone> {
     store: StateStore,
@@ -25,6 +32,9 @@ pub struct EventWorker<StateStore, C: Clone, P: Clone> {
     command_publisher: CommandPublisher<P>,
     status_publisher: StatusPublisher<P>,
     scalers: ScalerManager<StateStore, P, C>,
+    inventory_skipped: Counter<u64>,
+    issuers: Option<IssuerAllowList>,
+    reports: Option<ReconcileReporter<P>>,
 }
 
 impl<StateStore, C, P> EventWorker<StateStore, C, P>
@@ -35,6 +45,8 @@ where
         + LinkSource
         + ConfigSource
         + SecretSource
+        + MetricSource
+        + AuctionSource
         + Clone
         + Send
         + Sync
@@ -55,9 +67,32 @@ where
             command_publisher,
             status_publisher,
             scalers: manager,
+            inventory_skipped: opentelemetry_metrics::global::meter("wadm")
+                .u64_counter("wadm.heartbeat.inventory_skipped")
+                .with_description(
+                    "Number of host heartbeats whose components already matched the store, so no claims were fetched",
+                )
+                .init(),
+            issuers: None,
+            reports: None,
         }
     }
 
+    /// Only lets components and providers signed by the issuers in the allow list run. Commands
+    /// that would start anything else are turned into commands that stop it, and the status of its
+    /// manifest is set to failed
+    pub fn with_issuer_allow_list(mut self, issuers: IssuerAllowList) -> Self {
+        self.issuers = Some(issuers);
+        self
+    }
+
+    /// Publishes a report of every reconcile pass of a manifest, so users can see what wadm last
+    /// did for their application
+    pub fn with_reconcile_reporter(mut self, reports: ReconcileReporter<P>) -> Self {
+        self.reports = Some(reports);
+        self
+    }
+
     // BEGIN HANDLERS
     // NOTE(thomastaylor312): These use anyhow errors because in the _single_ case where we have to
     // call the lattice controller, we no longer just have error types from the store. To handle the
@@ -183,39 +218,54 @@ where
         Ok(())
     }
 
-    #[instrument(level = "debug", skip(self, host), fields(host_id = %host.id))]
-    async fn handle_host_started(
+    #[instrument(level = "debug", skip(self, host), fields(host_id = %host.host_id))]
+    async fn handle_host_labels_changed(
         &self,
         lattice_id: &str,
-        host: &HostStarted,
+        host: &HostLabelsChanged,
     ) -> anyhow::Result<()> {
-        debug!("Updating store with new host");
-        // New hosts have nothing running on them yet, so just drop it in the store
+        debug!("Updating store with new host labels");
+        // If we haven't seen the host yet, the next heartbeat will add it with the right labels
+        let Some(mut current) = self.store.get::<Host>(lattice_id, &host.host_id).await? else {
+            trace!("Host not found in store, skipping label update");
+            return Ok(());
+        };
+        current.labels = host.labels.clone();
         self.store
-            .store(lattice_id, host.id.clone(), Host::from(host))
+            .store(lattice_id, host.host_id.clone(), current)
             .await
             .map_err(anyhow::Error::from)
     }
 
     #[instrument(level = "debug", skip(self, host), fields(host_id = %host.id))]
-    async fn handle_host_stopped(
+    async fn handle_host_started(
         &self,
         lattice_id: &str,
-        host: &HostStopped,
+        host: &HostStarted,
     ) -> anyhow::Result<()> {
-        debug!("Handling host stopped event");
-        // NOTE(thomastaylor312): Generally to get a host stopped event, the host should have
-        // already sent a bunch of stop component/provider events, but for correctness sake, we fetch
-        // the current host and make sure all the components and providers are removed
-        trace!("Fetching current host data");
-        let current: Host = match self.store.get(lattice_id, &host.id).await? {
-            Some(h) => h,
-            None => {
-                debug!("Got host stopped event for a host we didn't have in the store");
-                return Ok(());
+        // A host that restarted has lost everything it was running, but the store still has it
+        // running until the next heartbeat. Clear it out now so the scalers that run for this
+        // event put the lost instances back right away
+        let hosts = self.store.list::<Host>(lattice_id).await?;
+        if let Some(previous) = restarted_host(&hosts, host) {
+            info!(previous_host_id = %previous.id, "Host restarted, removing the instances it was running");
+            self.remove_host_instances(lattice_id, previous).await?;
+            if previous.id != host.id {
+                self.store.delete::<Host>(lattice_id, &previous.id).await?;
             }
-        };
+        }
+
+        debug!("Updating store with new host");
+        // New hosts have nothing running on them yet, so just drop it in the store
+        self.store
+            .store(lattice_id, host.id.clone(), Host::from(host))
+            .await
+            .map_err(anyhow::Error::from)
+    }
 
+    /// Removes the instances of components and providers that the store has running on the given
+    /// host, for when the host has stopped or restarted and no longer runs anything
+    async fn remove_host_instances(&self, lattice_id: &str, current: &Host) -> anyhow::Result<()> {
         trace!("Fetching components from store to remove stopped instances");
         let all_components = self.store.list::<Component>(lattice_id).await?;
 
@@ -253,16 +303,16 @@ where
         #[allow(clippy::type_complexity)]
         let (providers_to_update, providers_to_delete): (Vec<(String, Provider)>, Vec<(String, Provider)>) = current
             .providers
-            .into_iter()
+            .iter()
             .filter_map(|info| {
-                let key = info.provider_id;
+                let key = info.provider_id.clone();
                 // NOTE: We can do this without cloning, but it led to some confusing code involving
                 // `remove` from the owned `all_providers` map. This is more readable at the expense of
                 // a clone for few providers
                 match all_providers.get(&key).cloned() {
                     // If we successfully remove the host, map it to the right type, otherwise we can
                     // continue onward
-                    Some(mut prov) => prov.hosts.remove(&host.id).map(|_| (key, prov)),
+                    Some(mut prov) => prov.hosts.remove(&current.id).map(|_| (key, prov)),
                     None => {
                         warn!(key = %key, "Didn't find provider in storage even though host said it existed");
                         None
@@ -283,6 +333,31 @@ where
             )
             .await?;
 
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self, host), fields(host_id = %host.id))]
+    async fn handle_host_stopped(
+        &self,
+        lattice_id: &str,
+        host: &HostStopped,
+    ) -> anyhow::Result<()> {
+        debug!("Handling host stopped event");
+        // NOTE(thomastaylor312): Generally to get a host stopped event, the host should have
+        // already sent a bunch of stop component/provider events, but for correctness sake, we fetch
+        // the current host and make sure all the components and providers are removed
+        trace!("Fetching current host data");
+        let current: Host = match self.store.get(lattice_id, &host.id).await? {
+            Some(h) => h,
+            None => {
+                debug!("Got host stopped event for a host we didn't have in the store");
+                return Ok(());
+            }
+        };
+
+        self.remove_host_instances(lattice_id, &current).await?;
+        self.ctl_client.invalidate_inventory(&host.id);
+
         // Order matters here: Now that we've cleaned stuff up, remove the host. We do this last
         // because if any of the above fails after we remove the host, we won't be able to fetch the
         // data to remove the components and providers on a retry.
@@ -602,6 +677,15 @@ where
             // component ID to all instances on this host
             .collect::<Vec<ComponentDescription>>();
 
+        // Claims are only needed to fill in components that changed, so skip asking the lattice
+        // for them when the store already matches the heartbeat
+        if components_to_update.is_empty() {
+            trace!("Stored components match the heartbeat, skipping claims lookup");
+            self.inventory_skipped
+                .add(1, &[KeyValue::new("lattice_id", lattice_id.to_owned())]);
+            return Ok(());
+        }
+
         let components_to_store = self
             .populate_component_info(&components, &host.host_id, components_to_update)
             .await?;
@@ -681,6 +765,7 @@ where
         data: &ManifestPublished,
     ) -> anyhow::Result<()> {
         debug!(name = %data.manifest.metadata.name, "Handling published manifest");
+        let mut pass = ReconcilePass::start(ManifestPublished::TYPE);
 
         let old_scalers = self
             .scalers
@@ -693,9 +778,9 @@ where
         let cleanup_commands = if let Some(old_scalers) = old_scalers {
             // This relies on the idea that an ID is a unique identifier for a scaler, and any
             // change in the ID is indicative of the fact that the scaler is outdated and should be cleaned up.
-            let (_updated_component, outdated_component): (ScalerList, ScalerList) = old_scalers
-                .into_iter()
-                .partition(|old| scalers.iter().any(|new| new.id() == old.id()));
+            // Outdated scalers that are being rolled out from are taken over by their replacement
+            // and cleaned up once the rollout is done
+            let outdated_component = hand_over_scalers(&scalers, old_scalers).await;
 
             // Clean up any resources from scalers that no longer exist
             let futs = outdated_component
@@ -725,13 +810,22 @@ where
 
         let scalers = self.scalers.add_scalers(&data.manifest, scalers).await?;
 
-        let (commands, res) = get_commands_and_result(
-            scalers.iter().map(|s| s.reconcile()),
-            "Errors occurred during initial reconciliation",
-        )
-        .await;
+        let (commands, res) = pass
+            .run_scalers(
+                scalers.iter().map(|s| (s.name(), s.reconcile())),
+                "Errors occurred during initial reconciliation",
+            )
+            .await;
 
-        let status = detailed_scaler_status(&scalers).await;
+        let (commands, status) = self
+            .enforce_issuers(
+                lattice_id,
+                None,
+                commands,
+                self.manifest_status(&data.manifest.metadata.name, &scalers)
+                    .await,
+            )
+            .await;
 
         trace!(?status, "Setting status");
         if let Err(e) = self
@@ -745,24 +839,99 @@ where
         trace!(?commands, "Publishing commands");
         // Handle the result from initial reconciliation. This lets us handle the net new stuff
         // immediately
-        self.command_publisher.publish_commands(commands).await?;
+        let name = data.manifest.metadata.name.as_str();
+        if let Err(e) = self.publish_commands_with_report(commands, &mut pass).await {
+            self.publish_report(name, pass).await;
+            return Err(e);
+        }
 
         // Now publish the cleanup commands from the old scalers. This will cause the new scalers to
         // react to the components/providers/linkdefs disappearing and create new ones with the new
         // versions
         if let Err(e) = self
-            .command_publisher
-            .publish_commands(cleanup_commands)
+            .publish_commands_with_report(cleanup_commands, &mut pass)
             .await
         {
             warn!(error = ?e, "Failed to publish cleanup commands from old application, some resources may be left behind");
         }
+        self.publish_report(name, pass).await;
+
+        res
+    }
+
+    /// Returns the detailed status of the given scalers for a manifest, along with where the
+    /// manifest's component instances are currently running
+    async fn manifest_status(&self, name: &str, scalers: &ScalerList) -> Status {
+        let status = detailed_scaler_status(scalers).await;
+        match self.scalers.instance_distribution(name).await {
+            Ok(instances) => status.with_instances(instances),
+            Err(e) => {
+                warn!(error = ?e, "Unable to compute instance distribution for status");
+                status
+            }
+        }
+    }
+
+    /// Pauses or resumes the scalers for the given manifest. Resuming reconciles the scalers right
+    /// away so anything that changed while they were paused is caught up on
+    #[instrument(level = "debug", skip(self))]
+    async fn handle_manifest_paused(
+        &self,
+        lattice_id: &str,
+        name: &str,
+        paused: bool,
+    ) -> anyhow::Result<()> {
+        let Some(scalers) = self.scalers.get_scalers(name).await else {
+            debug!("No scalers currently exist for model");
+            return Ok(());
+        };
+        self.scalers.set_paused(name, paused).await?;
+        self.scalers.refresh_data().await?;
+        if paused {
+            let mut status = self.manifest_status(name, &scalers).await;
+            status.info.message = "Reconciliation is paused".to_string();
+            if let Err(e) = self.status_publisher.publish_status(name, status).await {
+                warn!(error = ?e, "Failed to set status for paused manifest");
+            }
+            return Ok(());
+        }
+
+        let mut pass = ReconcilePass::start(ManifestResumed::TYPE);
+        let (commands, res) = pass
+            .run_scalers(
+                scalers.iter().map(|s| (s.name(), s.reconcile())),
+                "Errors occurred while reconciling resumed manifest",
+            )
+            .await;
+
+        let (commands, status) = self
+            .enforce_issuers(
+                lattice_id,
+                None,
+                commands,
+                self.manifest_status(name, &scalers).await,
+            )
+            .await;
+        trace!(?status, "Setting status");
+        if let Err(e) = self.status_publisher.publish_status(name, status).await {
+            warn!(error = ?e, "Failed to set status for scaler");
+        };
+
+        trace!(?commands, "Publishing commands");
+        let published = self.publish_commands_with_report(commands, &mut pass).await;
+        self.publish_report(name, pass).await;
+        published?;
 
         res
     }
 
     #[instrument(level = "debug", skip(self))]
-    async fn run_scalers_with_hint(&self, event: &Event, name: &str) -> anyhow::Result<()> {
+    async fn run_scalers_with_hint(
+        &self,
+        lattice_id: &str,
+        event: &Event,
+        name: &str,
+    ) -> anyhow::Result<()> {
         let scalers = match self.scalers.get_scalers(name).await {
             Some(scalers) => scalers,
             None => {
@@ -770,67 +939,270 @@ where
                 return Ok(());
             }
         };
+        if self.scalers.is_paused(name).await {
+            debug!("Reconciliation of model is paused, not running scalers");
+            return Ok(());
+        }
         // Refresh the snapshot data before running
         self.scalers.refresh_data().await?;
-        let (commands, res) = get_commands_and_result(
-            scalers.iter().map(|s| s.handle_event(event)),
-            "Errors occurred while handling event",
-        )
-        .await;
+        let mut pass = ReconcilePass::start(event.raw_type());
+        let (commands, res) = pass
+            .run_scalers(
+                scalers.iter().map(|s| (s.name(), s.handle_event(event))),
+                "Errors occurred while handling event",
+            )
+            .await;
 
-        let status = detailed_scaler_status(&scalers).await;
+        let (commands, status) = self
+            .enforce_issuers(
+                lattice_id,
+                Some(event),
+                commands,
+                self.manifest_status(name, &scalers).await,
+            )
+            .await;
         trace!(?status, "Setting status");
         if let Err(e) = self.status_publisher.publish_status(name, status).await {
             warn!(error = ?e, "Failed to set status for scaler");
         };
 
         trace!(?commands, "Publishing commands");
-        self.command_publisher.publish_commands(commands).await?;
+        let published = self.publish_commands_with_report(commands, &mut pass).await;
+        self.publish_report(name, pass).await;
+        published?;
 
         res
     }
 
     #[instrument(level = "debug", skip(self))]
-    async fn run_all_scalers(&self, event: &Event) -> anyhow::Result<()> {
+    async fn run_all_scalers(&self, lattice_id: &str, event: &Event) -> anyhow::Result<()> {
         let scalers = self.scalers.get_all_scalers().await;
         // Refresh the snapshot data before running
         self.scalers.refresh_data().await?;
-        let futs = scalers.iter().map(|(name, scalers)| async move {
-            let (commands, res) = get_commands_and_result(
-                scalers.iter().map(|scaler| scaler.handle_event(event)),
-                "Errors occurred while handling event with all scalers",
-            )
-            .await;
-
-            let status = detailed_scaler_status(scalers).await;
+        let mut paused = HashSet::new();
+        for name in scalers.keys() {
+            if self.scalers.is_paused(name).await {
+                paused.insert(name);
+            }
+        }
+        let futs = scalers
+            .iter()
+            .filter(|(name, _)| !paused.contains(name))
+            .map(|(name, scalers)| async move {
+                let mut pass = ReconcilePass::start(event.raw_type());
+                let (commands, res) = pass
+                    .run_scalers(
+                        scalers
+                            .iter()
+                            .map(|scaler| (scaler.name(), scaler.handle_event(event))),
+                        "Errors occurred while handling event with all scalers",
+                    )
+                    .await;
+
+                let (commands, status) = self
+                    .enforce_issuers(
+                        lattice_id,
+                        None,
+                        commands,
+                        self.manifest_status(name, scalers).await,
+                    )
+                    .await;
 
-            trace!(?status, "Setting status");
-            if let Err(e) = self.status_publisher.publish_status(name, status).await {
-                warn!(error = ?e, "Failed to set status for scaler");
-            };
+                trace!(?status, "Setting status");
+                if let Err(e) = self.status_publisher.publish_status(name, status).await {
+                    warn!(error = ?e, "Failed to set status for scaler");
+                };
+                pass.issued(&commands);
 
-            (commands, res)
-        });
+                (commands, res, (name, pass))
+            });
 
         // Resolve futures, computing commands for scalers, publishing statuses, and combining any errors
-        let (commands, res) = futures::future::join_all(futs).await.into_iter().fold(
-            (vec![], Ok(())),
-            |(mut cmds, res), (mut new_cmds, new_res)| {
+        let (commands, res, passes) = futures::future::join_all(futs).await.into_iter().fold(
+            (vec![], Ok(()), vec![]),
+            |(mut cmds, res, mut passes), (mut new_cmds, new_res, pass)| {
                 cmds.append(&mut new_cmds);
+                passes.push(pass);
                 let res = match (res, new_res) {
                     (Ok(_), Ok(_)) => Ok(()),
                     (Ok(_), Err(e)) | (Err(e), Ok(_)) => Err(e),
                     (Err(e), Err(e2)) => Err(e.context(e2)),
                 };
-                (cmds, res)
+                (cmds, res, passes)
             },
         );
 
         trace!(?commands, "Publishing commands");
-        self.command_publisher.publish_commands(commands).await?;
+        let published = self.command_publisher.publish_commands(commands).await;
+        for (name, mut pass) in passes {
+            if let Err(e) = published.as_ref() {
+                pass.error(format!("Unable to publish commands: {e}"));
+            }
+            self.publish_report(name, pass).await;
+        }
+        published?;
 
         res
     }
+
+    /// Publishes commands, recording them and any error publishing them in the given pass
+    async fn publish_commands_with_report(
+        &self,
+        commands: Vec<Command>,
+        pass: &mut ReconcilePass,
+    ) -> anyhow::Result<()> {
+        pass.issued(&commands);
+        let published = self.command_publisher.publish_commands(commands).await;
+        if let Err(e) = published.as_ref() {
+            pass.error(format!("Unable to publish commands: {e}"));
+        }
+        published
+    }
+
+    /// Publishes the report of a finished reconcile pass, if reports are enabled
+    async fn publish_report(&self, name: &str, pass: ReconcilePass) {
+        let Some(reports) = self.reports.as_ref() else {
+            return;
+        };
+        if let Err(e) = reports.publish_report(name, &pass.finish()).await {
+            warn!(error = ?e, "Failed to publish reconcile report");
+        }
+    }
+
+    /// Keeps images signed by an issuer that isn't in the allow list from running. Commands that
+    /// would start them are turned into commands that stop them, and if the event shows one was
+    /// started anyway, a command to stop it is added. If anything was kept from running, the
+    /// status is set to failed
+    async fn enforce_issuers(
+        &self,
+        lattice_id: &str,
+        event: Option<&Event>,
+        mut commands: Vec<Command>,
+        status: Status,
+    ) -> (Vec<Command>, Status) {
+        let Some(issuers) = self.issuers.as_ref() else {
+            return (commands, status);
+        };
+        match event {
+            Some(Event::ComponentScaled(scaled)) if scaled.max_instances > 0 => {
+                if let (Some(claims), Some(model_name)) = (
+                    scaled.claims.as_ref(),
+                    scaled.annotations.get(APP_SPEC_ANNOTATION),
+                ) {
+                    if !issuers.observe(&scaled.image_ref, &claims.issuer) {
+                        commands.push(Command::from(ScaleComponent {
+                            component_id: scaled.component_id.clone(),
+                            host_id: scaled.host_id.clone(),
+                            count: 0,
+                            reference: scaled.image_ref.clone(),
+                            model_name: model_name.clone(),
+                            annotations: scaled.annotations.clone(),
+                            config: Vec::new(),
+                        }));
+                    }
+                }
+            }
+            Some(Event::ProviderStarted(started)) => {
+                if let (Some(claims), Some(model_name)) = (
+                    started.claims.as_ref(),
+                    started.annotations.get(APP_SPEC_ANNOTATION),
+                ) {
+                    if !issuers.observe(&started.image_ref, &claims.issuer) {
+                        commands.push(Command::from(StopProvider {
+                            provider_id: started.provider_id.clone(),
+                            host_id: started.host_id.clone(),
+                            model_name: model_name.clone(),
+                            annotations: started.annotations.clone(),
+                        }));
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        let mut denied = BTreeSet::new();
+        let mut allowed = Vec::with_capacity(commands.len());
+        for command in commands {
+            match command {
+                Command::ScaleComponent(mut cmd) if cmd.count > 0 => {
+                    let issuer = match issuers.denied_issuer(&cmd.reference) {
+                        Some(issuer) => Some(issuer),
+                        None => self
+                            .store
+                            .get::<Component>(lattice_id, &cmd.component_id)
+                            .await
+                            .ok()
+                            .flatten()
+                            .filter(|c| c.reference == cmd.reference && !issuers.allows(&c.issuer))
+                            .map(|c| c.issuer),
+                    };
+                    if let Some(issuer) = issuer {
+                        denied.insert(format!("Refusing to run component {} from {}, its issuer {issuer} is not allowed in this lattice", cmd.component_id, cmd.reference));
+                        cmd.count = 0;
+                    }
+                    allowed.push(Command::ScaleComponent(cmd));
+                }
+                Command::StartProvider(cmd) => {
+                    let issuer = match issuers.denied_issuer(&cmd.reference) {
+                        Some(issuer) => Some(issuer),
+                        None => self
+                            .store
+                            .get::<Provider>(lattice_id, &cmd.provider_id)
+                            .await
+                            .ok()
+                            .flatten()
+                            .filter(|p| p.reference == cmd.reference && !issuers.allows(&p.issuer))
+                            .map(|p| p.issuer),
+                    };
+                    match issuer {
+                        Some(issuer) => {
+                            denied.insert(format!("Refusing to run provider {} from {}, its issuer {issuer} is not allowed in this lattice", cmd.provider_id, cmd.reference));
+                        }
+                        None => allowed.push(Command::StartProvider(cmd)),
+                    }
+                }
+                command => allowed.push(command),
+            }
+        }
+        if denied.is_empty() {
+            return (allowed, status);
+        }
+        warn!(
+            ?denied,
+            "Kept images from issuers that aren't allowed from running"
+        );
+        let message = denied.into_iter().collect::<Vec<_>>().join("; ");
+        (
+            allowed,
+            Status {
+                info: StatusInfo::failed(&message),
+                ..status
+            },
+        )
+    }
+}
+
+/// Returns the host in the store that the started host is a restart of. This is either a host
+/// with the same ID, or the only other host with exactly the same labels, as long as those labels
+/// include some that weren't set by the host itself. Hosts usually get a new ID when they restart,
+/// so the labels are what identify the machine. If the labels turn out not to be unique after all,
+/// the other host's next heartbeat puts it back in the store
+fn restarted_host<'a>(hosts: &'a HashMap<String, Host>, started: &HostStarted) -> Option<&'a Host> {
+    if let Some(host) = hosts.get(&started.id) {
+        return Some(host);
+    }
+    if !started
+        .labels
+        .keys()
+        .any(|key| !key.starts_with(HOST_BUILTIN_LABEL_PREFIX))
+    {
+        return None;
+    }
+    let mut same_labels = hosts.values().filter(|host| host.labels == started.labels);
+    match (same_labels.next(), same_labels.next()) {
+        (Some(host), None) => Some(host),
+        _ => None,
+    }
 }
 
 #[async_trait::async_trait]
@@ -842,6 +1214,8 @@ where
         + LinkSource
         + ConfigSource
         + SecretSource
+        + MetricSource
+        + AuctionSource
         + Clone
         + Send
         + Sync
@@ -875,6 +1249,10 @@ where
                 .handle_host_stopped(&message.lattice_id, host)
                 .await
                 .map(|_| None),
+            Event::HostLabelsChanged(host) => self
+                .handle_host_labels_changed(&message.lattice_id, host)
+                .await
+                .map(|_| None),
             Event::ProviderStarted(provider) => self
                 .handle_provider_started(&message.lattice_id, provider)
                 .await
@@ -924,9 +1302,27 @@ where
                     None => Ok(None),
                 }
             }
+            Event::ManifestPaused(ManifestPaused { name })
+            | Event::ManifestResumed(ManifestResumed { name }) => {
+                let paused = matches!(message.as_ref(), Event::ManifestPaused(_));
+                if let Err(e) = self
+                    .handle_manifest_paused(&message.lattice_id, name, paused)
+                    .await
+                {
+                    message.nack().await;
+                    return Err(WorkError::Other(e.into()));
+                }
+                return message.ack().await.map_err(WorkError::from);
+            }
+            // Events we don't have a type for can't affect state or scalers, so skip running them
+            Event::Unknown(evt) => {
+                trace!(event_type = %evt.ty, "Got unknown event. Skipping");
+                return message.ack().await.map_err(WorkError::from);
+            }
             // All other events we don't care about for state. Explicitly mention them in order
             // to make sure we don't forget to handle them when new events are added.
             Event::LinkdefSet(_)
+            | Event::LinkdefSetFailed(_)
             | Event::LinkdefDeleted(_)
             | Event::ConfigSet(_)
             | Event::ConfigDeleted(_)
@@ -938,8 +1334,11 @@ where
         };
 
         let res = match res {
-            Ok(Some(name)) => self.run_scalers_with_hint(&message, name).await,
-            Ok(None) => self.run_all_scalers(&message).await,
+            Ok(Some(name)) => {
+                self.run_scalers_with_hint(&message.lattice_id, &message, name)
+                    .await
+            }
+            Ok(None) => self.run_all_scalers(&message.lattice_id, &message).await,
             Err(e) => Err(e),
         }
         .map_err(Box::<dyn std::error::Error + Send + 'static>::from);
@@ -951,6 +1350,28 @@ where
 
         message.ack().await.map_err(WorkError::from)
     }
+
+    // NOTE: Manifest events only touch their own manifest's scalers and host events only touch
+    // the state of their own host, so those are keyed. Everything else, like a component being
+    // scaled, can update state shared by several hosts and manifests, so it has to be handled on
+    // its own
+    fn work_key(&self, message: &Self::Message) -> Option<WorkKey> {
+        match message {
+            Event::ManifestPublished(data) => {
+                Some(WorkKey::App(data.manifest.metadata.name.clone()))
+            }
+            Event::ManifestUnpublished(ManifestUnpublished { name })
+            | Event::ManifestPaused(ManifestPaused { name })
+            | Event::ManifestResumed(ManifestResumed { name }) => Some(WorkKey::App(name.clone())),
+            Event::HostHeartbeat(HostHeartbeat { host_id, .. })
+            | Event::HostLabelsChanged(HostLabelsChanged { host_id, .. })
+            | Event::HostStarted(HostStarted { id: host_id, .. })
+            | Event::HostStopped(HostStopped { id: host_id, .. }) => {
+                Some(WorkKey::Host(host_id.clone()))
+            }
+            _ => None,
+        }
+    }
 }
 
 /// Helper that runs any iterable of futures and returns a list of commands and the proper result to
@@ -2233,6 +2654,197 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn test_heartbeat_reconciles_unchanged_inventory() {
+        let store = Arc::new(TestStore::default());
+        let lattice_source = TestLatticeSource::default();
+        let lattice_id = "unchanged_inventory";
+
+        let command_publisher = CommandPublisher::new(NoopPublisher, "doesntmatter");
+        let status_publisher = StatusPublisher::new(NoopPublisher, None, "doesntmatter");
+        let worker = EventWorker::new(
+            store.clone(),
+            lattice_source.clone(),
+            command_publisher.clone(),
+            status_publisher.clone(),
+            ScalerManager::test_new(
+                NoopPublisher,
+                lattice_id,
+                store.clone(),
+                command_publisher,
+                status_publisher.clone(),
+                lattice_source,
+            )
+            .await,
+        );
+
+        let host_id = "cloudcity";
+        let heartbeat = |max_instances: u32| HostHeartbeat {
+            components: vec![ComponentDescription::builder()
+                .id("lando".into())
+                .image_ref("bespin.io/lando:latest".into())
+                .revision(0)
+                .max_instances(max_instances)
+                .build()
+                .expect("failed to build description")],
+            friendly_name: "cloudcity-1980".to_string(),
+            labels: HashMap::default(),
+            issuer: "".to_string(),
+            providers: vec![],
+            uptime_human: "60s".into(),
+            uptime_seconds: 60,
+            version: semver::Version::parse("0.61.0").unwrap(),
+            host_id: host_id.to_string(),
+        };
+
+        worker
+            .handle_host_heartbeat(lattice_id, &heartbeat(2))
+            .await
+            .expect("Should be able to handle host heartbeat");
+        assert!(
+            store
+                .get::<Component>(lattice_id, "lando")
+                .await
+                .unwrap()
+                .is_some(),
+            "Component should be stored from the first heartbeat"
+        );
+
+        // Remove the component to simulate the store drifting from what the host is running
+        store
+            .delete::<Component>(lattice_id, "lando")
+            .await
+            .unwrap();
+        worker
+            .handle_host_heartbeat(lattice_id, &heartbeat(2))
+            .await
+            .expect("Should be able to handle host heartbeat");
+        assert!(
+            store
+                .get::<Component>(lattice_id, "lando")
+                .await
+                .unwrap()
+                .is_some(),
+            "An unchanged heartbeat should still correct drift in the store"
+        );
+
+        // A change in the inventory should be reconciled
+        worker
+            .handle_host_heartbeat(lattice_id, &heartbeat(3))
+            .await
+            .expect("Should be able to handle host heartbeat");
+        let component = store
+            .get::<Component>(lattice_id, "lando")
+            .await
+            .unwrap()
+            .expect("Changed inventory should be reconciled");
+        assert_eq!(component.count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_host_restart_clears_instances() {
+        let store = Arc::new(TestStore::default());
+        let lattice_source = TestLatticeSource::default();
+        let lattice_id = "host_restart";
+
+        let command_publisher = CommandPublisher::new(NoopPublisher, "doesntmatter");
+        let status_publisher = StatusPublisher::new(NoopPublisher, None, "doesntmatter");
+        let worker = EventWorker::new(
+            store.clone(),
+            lattice_source.clone(),
+            command_publisher.clone(),
+            status_publisher.clone(),
+            ScalerManager::test_new(
+                NoopPublisher,
+                lattice_id,
+                store.clone(),
+                command_publisher,
+                status_publisher.clone(),
+                lattice_source,
+            )
+            .await,
+        );
+
+        let labels = HashMap::from([
+            ("hostcore.os".to_string(), "linux".to_string()),
+            ("node".to_string(), "bespin-1".to_string()),
+        ]);
+        let heartbeat = |host_id: &str| HostHeartbeat {
+            components: vec![ComponentDescription::builder()
+                .id("lando".into())
+                .image_ref("bespin.io/lando:latest".into())
+                .revision(0)
+                .max_instances(2)
+                .build()
+                .expect("failed to build description")],
+            friendly_name: "cloudcity-1980".to_string(),
+            labels: labels.clone(),
+            issuer: "".to_string(),
+            providers: vec![],
+            uptime_human: "60s".into(),
+            uptime_seconds: 60,
+            version: semver::Version::parse("0.61.0").unwrap(),
+            host_id: host_id.to_string(),
+        };
+        let started = |host_id: &str| HostStarted {
+            labels: labels.clone(),
+            friendly_name: "cloudcity-1980".to_string(),
+            id: host_id.to_string(),
+        };
+
+        // A host restarting with the same ID has nothing running anymore
+        worker
+            .handle_host_heartbeat(lattice_id, &heartbeat("cloudcity"))
+            .await
+            .expect("Should be able to handle host heartbeat");
+        worker
+            .handle_host_started(lattice_id, &started("cloudcity"))
+            .await
+            .expect("Should be able to handle host started");
+        assert!(
+            store
+                .get::<Component>(lattice_id, "lando")
+                .await
+                .unwrap()
+                .is_none(),
+            "Instances on a restarted host should be removed"
+        );
+
+        // A host coming back with a new ID on the same labels replaces the old host
+        worker
+            .handle_host_heartbeat(lattice_id, &heartbeat("cloudcity"))
+            .await
+            .expect("Should be able to handle host heartbeat");
+        worker
+            .handle_host_started(lattice_id, &started("cloudcity2"))
+            .await
+            .expect("Should be able to handle host started");
+        let hosts = store.list::<Host>(lattice_id).await.unwrap();
+        assert!(
+            !hosts.contains_key("cloudcity") && hosts.contains_key("cloudcity2"),
+            "The new host should replace the restarted one"
+        );
+        assert!(store
+            .get::<Component>(lattice_id, "lando")
+            .await
+            .unwrap()
+            .is_none());
+
+        // Hosts that only share the labels hosts set on themselves are not restarts
+        worker
+            .handle_host_started(
+                lattice_id,
+                &HostStarted {
+                    labels: HashMap::from([("hostcore.os".to_string(), "linux".to_string())]),
+                    friendly_name: "hoth-1980".to_string(),
+                    id: "hoth".to_string(),
+                },
+            )
+            .await
+            .expect("Should be able to handle host started");
+        assert_eq!(store.list::<Host>(lattice_id).await.unwrap().len(), 2);
+    }
+
     fn assert_component(
         components: &HashMap<String, Component>,
         component_id: &str,
@@ -1,14 +1,27 @@
 use anyhow::{bail, Context};
 use async_nats::jetstream::stream::Stream;
+use futures::{stream::BoxStream, StreamExt};
 use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
+use std::time::Duration;
 use wasmcloud_secrets_types::SecretConfig;
 
 use tracing::{debug, instrument, trace, warn};
-use wadm_types::api::Status;
+use wadm_types::api::{Status, StatusType};
 use wasmcloud_control_interface::{HostInventory, Link};
 
-use crate::{commands::Command, publisher::Publisher, APP_SPEC_ANNOTATION};
+use crate::{
+    clock::{self, SharedClock},
+    commands::{
+        coalesce_commands, Command, CommandEnvelope, PublishedCommand, COMMAND_EXPIRES_HEADER,
+        DEFAULT_COMMAND_VALIDITY,
+    },
+    notifications::{LifecycleEvent, LifecycleNotification, LifecycleNotifier},
+    policy::PolicyGate,
+    publisher::{message_id, Encoding, Publisher, MESSAGE_ID_HEADER},
+    trace_context::TraceContext,
+    APP_SPEC_ANNOTATION,
+};
 
 /// A subset of needed claims to help populate state
 #[derive(Debug, Clone)]
@@ -29,12 +42,44 @@ pub trait ClaimsSource {
     async fn get_claims(&self) -> anyhow::Result<HashMap<String, Claims>>;
 }
 
+/// A trait for anything that can list the IDs of the hosts in a lattice
+#[async_trait::async_trait]
+pub trait HostSource {
+    async fn get_hosts(&self) -> anyhow::Result<Vec<String>>;
+}
+
+/// A trait for anything that can run auctions to find the hosts that can take a component or
+/// provider right now. Hosts answer auctions by checking the constraints against their live
+/// labels, so this still finds the right hosts when the labels in the lattice state are stale
+#[async_trait::async_trait]
+pub trait AuctionSource {
+    /// Returns the IDs of the hosts that match the constraints and aren't running the component
+    async fn auction_component(
+        &self,
+        reference: &str,
+        component_id: &str,
+        constraints: BTreeMap<String, String>,
+    ) -> anyhow::Result<Vec<String>>;
+
+    /// Returns the IDs of the hosts that match the constraints and aren't running the provider
+    async fn auction_provider(
+        &self,
+        reference: &str,
+        provider_id: &str,
+        constraints: BTreeMap<String, String>,
+    ) -> anyhow::Result<Vec<String>>;
+}
+
 /// NOTE(brooksmtownsend): This trait exists in order to query the hosts inventory
 /// upon receiving a heartbeat since the heartbeat doesn't contain enough
 /// information to properly update the stored data for components
 #[async_trait::async_trait]
 pub trait InventorySource {
     async fn get_inventory(&self, host_id: &str) -> anyhow::Result<HostInventory>;
+
+    /// Forgets anything cached about the inventory of the given host, like when it stops. Sources
+    /// that don't cache inventories don't need to do anything
+    fn invalidate_inventory(&self, _host_id: &str) {}
 }
 
 /// A trait for anything that can fetch the links in a lattice
@@ -62,6 +107,14 @@ pub trait SecretSource {
     async fn get_secret(&self, name: &str) -> anyhow::Result<Option<SecretConfig>>;
 }
 
+/// A trait for anything that can subscribe to a metric published on a subject
+#[async_trait::async_trait]
+pub trait MetricSource {
+    /// Subscribes to the given subject, returning a stream of every metric value published to it.
+    /// Values that aren't a number are skipped
+    async fn subscribe_metric(&self, subject: &str) -> anyhow::Result<BoxStream<'static, f64>>;
+}
+
 /// Converts the configuration map of strings to a secret config
 pub fn secret_config_from_map(map: HashMap<String, String>) -> anyhow::Result<SecretConfig> {
     match (
@@ -123,6 +176,21 @@ impl ClaimsSource for wasmcloud_control_interface::Client {
     }
 }
 
+#[async_trait::async_trait]
+impl HostSource for wasmcloud_control_interface::Client {
+    async fn get_hosts(&self) -> anyhow::Result<Vec<String>> {
+        let responses = self
+            .get_hosts()
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+        Ok(responses
+            .into_iter()
+            .filter_map(|ctl_resp| ctl_resp.into_data())
+            .map(|host| host.id().to_owned())
+            .collect())
+    }
+}
+
 #[async_trait::async_trait]
 impl InventorySource for wasmcloud_control_interface::Client {
     async fn get_inventory(&self, host_id: &str) -> anyhow::Result<HostInventory> {
@@ -142,6 +210,43 @@ impl InventorySource for wasmcloud_control_interface::Client {
     }
 }
 
+#[async_trait::async_trait]
+impl AuctionSource for wasmcloud_control_interface::Client {
+    async fn auction_component(
+        &self,
+        reference: &str,
+        component_id: &str,
+        constraints: BTreeMap<String, String>,
+    ) -> anyhow::Result<Vec<String>> {
+        let responses = self
+            .perform_component_auction(reference, component_id, constraints)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+        Ok(responses
+            .into_iter()
+            .filter_map(|ctl_resp| ctl_resp.into_data())
+            .map(|ack| ack.host_id().to_owned())
+            .collect())
+    }
+
+    async fn auction_provider(
+        &self,
+        reference: &str,
+        provider_id: &str,
+        constraints: BTreeMap<String, String>,
+    ) -> anyhow::Result<Vec<String>> {
+        let responses = self
+            .perform_provider_auction(reference, provider_id, constraints)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+        Ok(responses
+            .into_iter()
+            .filter_map(|ctl_resp| ctl_resp.into_data())
+            .map(|ack| ack.host_id().to_owned())
+            .collect())
+    }
+}
+
 // NOTE(thomastaylor312): A future improvement here that would make things more efficient is if this
 // was just a cache of the links. On startup, it could fetch once, and then it could subscribe to
 // the KV store for updates. This would allow us to not have to fetch every time we need to get
@@ -210,7 +315,33 @@ impl SecretSource for wasmcloud_control_interface::Client {
     }
 }
 
-/// A struct for publishing status updates
+#[async_trait::async_trait]
+impl MetricSource for wasmcloud_control_interface::Client {
+    async fn subscribe_metric(&self, subject: &str) -> anyhow::Result<BoxStream<'static, f64>> {
+        let subscriber = self
+            .nats_client()
+            .subscribe(subject.to_owned())
+            .await
+            .with_context(|| format!("Unable to subscribe to metric subject {subject}"))?;
+        Ok(subscriber
+            .filter_map(|msg| async move {
+                let value = std::str::from_utf8(&msg.payload)
+                    .ok()
+                    .and_then(|payload| payload.trim().parse::<f64>().ok())
+                    .filter(|value| value.is_finite());
+                if value.is_none() {
+                    trace!(subject = %msg.subject, "Skipping metric that isn't a number");
+                }
+                value
+            })
+            .boxed())
+    }
+}
+
+/// A struct for publishing status updates. The status of every manifest is recomputed by the event
+/// worker as events and command results arrive, and the last status published to the status stream
+/// is what the status API returns. Statuses that didn't change aren't published, so subscribers to
+/// the status topic only see actual changes
 #[derive(Clone)]
 pub struct StatusPublisher<Pub> {
     publisher: Pub,
@@ -218,6 +349,8 @@ pub struct StatusPublisher<Pub> {
     status_stream: Option<Stream>,
     // Topic prefix, e.g. wadm.status.default
     topic_prefix: String,
+    lifecycle: LifecycleNotifier,
+    lattice_id: String,
 }
 
 impl<Pub> StatusPublisher<Pub> {
@@ -232,8 +365,22 @@ impl<Pub> StatusPublisher<Pub> {
             publisher,
             status_stream,
             topic_prefix: topic_prefix.to_owned(),
+            lifecycle: LifecycleNotifier::default(),
+            lattice_id: String::new(),
         }
     }
+
+    /// Sets the notifier that is told when a manifest in the given lattice starts failing to
+    /// reconcile
+    pub fn with_lifecycle_notifier(
+        mut self,
+        lifecycle: LifecycleNotifier,
+        lattice_id: &str,
+    ) -> StatusPublisher<Pub> {
+        self.lifecycle = lifecycle;
+        self.lattice_id = lattice_id.to_owned();
+        self
+    }
 }
 
 impl<Pub: Publisher> StatusPublisher<Pub> {
@@ -261,10 +408,22 @@ impl<Pub: Publisher> StatusPublisher<Pub> {
                 trace!(%name, "Status hasn't changed since last update. Skipping");
                 Ok(())
             }
-            _ => {
+            prev_status => {
                 self.publisher
                     .publish(serde_json::to_vec(&status)?, Some(&topic))
-                    .await
+                    .await?;
+                let was_failing =
+                    prev_status.is_some_and(|prev| prev.info.status_type == StatusType::Failed);
+                if status.info.status_type == StatusType::Failed && !was_failing {
+                    self.lifecycle.notify(
+                        &self.lattice_id,
+                        name,
+                        LifecycleEvent::ReconcileFailed {
+                            message: status.info.message,
+                        },
+                    );
+                }
+                Ok(())
             }
         }
     }
@@ -275,6 +434,12 @@ impl<Pub: Publisher> StatusPublisher<Pub> {
 pub struct CommandPublisher<Pub> {
     publisher: Pub,
     topic: String,
+    validity: Duration,
+    clock: SharedClock,
+    lifecycle: LifecycleNotifier,
+    lattice_id: String,
+    policy: Option<PolicyGate>,
+    encoding: Encoding,
 }
 
 impl<Pub> CommandPublisher<Pub> {
@@ -284,34 +449,143 @@ impl<Pub> CommandPublisher<Pub> {
         CommandPublisher {
             publisher,
             topic: topic.to_owned(),
+            validity: DEFAULT_COMMAND_VALIDITY,
+            clock: clock::system(),
+            lifecycle: LifecycleNotifier::default(),
+            lattice_id: String::new(),
+            policy: None,
+            encoding: Encoding::default(),
         }
     }
+
+    /// Sets the notifier that is told about the components and providers that published commands
+    /// for the given lattice scale
+    pub fn with_lifecycle_notifier(
+        mut self,
+        lifecycle: LifecycleNotifier,
+        lattice_id: &str,
+    ) -> CommandPublisher<Pub> {
+        self.lifecycle = lifecycle;
+        self.lattice_id = lattice_id.to_owned();
+        self
+    }
+
+    /// Sets the encoding commands are published with. Command consumers read the encoding from the
+    /// content type of each command, so this can be changed without affecting other replicas
+    pub fn with_encoding(mut self, encoding: Encoding) -> CommandPublisher<Pub> {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Sets how long published commands stay valid for. Commands that haven't been executed by
+    /// then are discarded by the command worker
+    pub fn with_validity(mut self, validity: Duration) -> CommandPublisher<Pub> {
+        self.validity = validity;
+        self
+    }
+
+    /// Sets the clock used to compute when published commands expire
+    pub fn with_clock(mut self, clock: SharedClock) -> CommandPublisher<Pub> {
+        self.clock = clock;
+        self
+    }
+
+    /// Checks commands that start components or providers or put links with the given gate before
+    /// they are published for the given lattice. Denied commands are dropped and reported as failed
+    /// reconciles to the lifecycle notifier
+    pub fn with_policy_gate(
+        mut self,
+        policy: PolicyGate,
+        lattice_id: &str,
+    ) -> CommandPublisher<Pub> {
+        self.policy = Some(policy);
+        self.lattice_id = lattice_id.to_owned();
+        self
+    }
 }
 
-impl<Pub: Publisher> CommandPublisher<Pub> {
+impl<Pub: Publisher + Sync> CommandPublisher<Pub> {
     #[instrument(level = "trace", skip(self))]
     pub async fn publish_commands(&self, commands: Vec<Command>) -> anyhow::Result<()> {
-        futures::future::join_all(
+        let total = commands.len();
+        let commands = coalesce_commands(commands);
+        if commands.len() < total {
+            trace!(
+                dropped = total - commands.len(),
+                "Dropped commands superseded by later commands for the same target"
+            );
+        }
+        let commands = match self.policy.as_ref() {
+            Some(policy) if !commands.is_empty() => {
+                let (allowed, denied) = policy.check(&self.lattice_id, commands).await;
+                for (model_name, message) in denied {
+                    warn!(%model_name, %message, "Dropping command denied by policy");
+                    self.lifecycle.notify(
+                        &self.lattice_id,
+                        &model_name,
+                        LifecycleEvent::ReconcileFailed { message },
+                    );
+                }
+                allowed
+            }
+            _ => commands,
+        };
+        // Commands carry the trace of whatever caused them so the work they lead to shows up in the
+        // same trace
+        let headers = TraceContext::current()
+            .map(|trace_context| trace_context.to_headers())
+            .unwrap_or_default();
+        let now = self.clock.now();
+        let notifications: Vec<LifecycleNotification> = if self.lifecycle.is_empty() {
+            Vec::new()
+        } else {
             commands
-                .into_iter()
-                // Generally commands are purely internal to wadm and so shouldn't have an error serializing. If it does, warn and continue onward
-                .filter_map(|command| {
-                    match serde_json::to_vec(&command) {
-                        Ok(data) => Some(data),
-                        Err(e) => {
-                            warn!(error = %e, ?command, "Got malformed command when trying to serialize. Skipping this command");
-                            None
-                        }
-                    }
-                })
-                .map(|data| self.publisher.publish(data, Some(&self.topic))),
-        )
-        .await
-        .into_iter()
-        .collect::<anyhow::Result<()>>()
+                .iter()
+                .filter_map(|command| LifecycleNotification::for_command(&self.lattice_id, command))
+                .collect()
+        };
+        // NOTE: Commands are published one at a time so they land in the stream in the order they
+        // were coalesced into, e.g. config before the components that use it
+        for command in commands {
+            let command =
+                CommandEnvelope::new(PublishedCommand::new_at(command, self.validity, now));
+            let mut headers = headers.clone();
+            // Generally commands are purely internal to wadm and so shouldn't have an error serializing. If it does, warn and continue onward
+            let data = match command.encode(self.encoding, &mut headers) {
+                Ok(data) => data,
+                Err(e) => {
+                    warn!(error = %e, ?command, "Got malformed command when trying to serialize. Skipping this command");
+                    continue;
+                }
+            };
+            // NOTE: The expiry is part of the ID so this only drops the same command being
+            // published twice, like when a publish is retried
+            let mut id_data = data.clone();
+            if let Some(expires_at) = headers.get(COMMAND_EXPIRES_HEADER) {
+                id_data.extend_from_slice(expires_at.as_str().as_bytes());
+            }
+            headers.insert(MESSAGE_ID_HEADER, message_id(&id_data).as_str());
+            self.publisher
+                .publish_with_headers(data, Some(&self.topic), headers)
+                .await?;
+        }
+        for notification in notifications {
+            self.lifecycle.send(notification);
+        }
+        Ok(())
     }
 }
 
+/// Returns whether the annotations mark something as created by wadm for the given model
+pub fn is_managed_by(annotations: &BTreeMap<String, String>, model_name: &str) -> bool {
+    annotations
+        .get(crate::MANAGED_BY_ANNOTATION)
+        .is_some_and(|managed_by| managed_by == crate::MANAGED_BY_IDENTIFIER)
+        && annotations
+            .get(APP_SPEC_ANNOTATION)
+            .is_some_and(|app| app == model_name)
+}
+
 /// Inserts managed annotations to the given `annotations` HashMap.
 pub fn insert_managed_annotations(annotations: &mut BTreeMap<String, String>, model_name: &str) {
     annotations.extend([
@@ -0,0 +1,277 @@
+//! A caching layer for [`InventorySource`]s. When a burst of events for the same host each need
+//! its inventory, only one request is sent to the host at a time and the answer is reused for a
+//! short time, so a heartbeat storm doesn't turn into a storm of control interface requests
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use futures::stream::BoxStream;
+use tokio::sync::Mutex as AsyncMutex;
+use tracing::trace;
+use wasmcloud_control_interface::{HostInventory, Link};
+use wasmcloud_secrets_types::SecretConfig;
+
+use super::{
+    AuctionSource, Claims, ClaimsSource, ConfigSource, HostSource, InventorySource, LinkSource,
+    MetricSource, SecretSource,
+};
+
+/// The default amount of time a fetched inventory is reused for
+pub const DEFAULT_INVENTORY_TTL: Duration = Duration::from_secs(2);
+
+/// The last inventory fetched for a host, along with when it was fetched
+type CachedInventory = Arc<AsyncMutex<Option<(Instant, HostInventory)>>>;
+
+/// The inventories fetched for each host, shared by every [`CoalescingInventory`] made from it.
+/// Host IDs are unique across lattices, so a single cache can be shared by the sources of all
+/// lattices. Cloning is cheap and all clones share the cache
+#[derive(Clone)]
+pub struct InventoryCache {
+    ttl: Duration,
+    hosts: Arc<Mutex<HashMap<String, CachedInventory>>>,
+}
+
+impl Default for InventoryCache {
+    fn default() -> InventoryCache {
+        InventoryCache::new()
+    }
+}
+
+impl InventoryCache {
+    /// Creates an empty cache that reuses inventories for [`DEFAULT_INVENTORY_TTL`]
+    pub fn new() -> InventoryCache {
+        InventoryCache {
+            ttl: DEFAULT_INVENTORY_TTL,
+            hosts: Arc::default(),
+        }
+    }
+
+    /// Sets how long a fetched inventory is reused for
+    pub fn with_ttl(mut self, ttl: Duration) -> InventoryCache {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Wraps the given source so its inventory requests go through this cache
+    pub fn wrap<S>(&self, source: S) -> CoalescingInventory<S> {
+        CoalescingInventory {
+            source,
+            cache: self.clone(),
+        }
+    }
+
+    /// Forgets the cached inventory of a host, like when it stops or is evicted
+    pub fn invalidate(&self, host_id: &str) {
+        self.lock().remove(host_id);
+    }
+
+    /// Returns whether there is a cached inventory entry for the host
+    #[cfg(test)]
+    pub(crate) fn contains(&self, host_id: &str) -> bool {
+        self.lock().contains_key(host_id)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, CachedInventory>> {
+        self.hosts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the cached inventory of the host, dropping the inventories of other hosts that have
+    /// gone stale along the way so hosts that are never seen again don't stay in the cache. Entries
+    /// that someone is still using are kept
+    fn entry(&self, host_id: &str) -> CachedInventory {
+        let mut hosts = self.lock();
+        hosts.retain(|id, cached| {
+            id == host_id
+                || Arc::strong_count(cached) > 1
+                || cached.try_lock().is_ok_and(|cached| {
+                    cached
+                        .as_ref()
+                        .is_some_and(|(fetched, _)| fetched.elapsed() < self.ttl)
+                })
+        });
+        hosts.entry(host_id.to_owned()).or_default().clone()
+    }
+}
+
+/// Wraps a source so there is at most one inventory request in flight per host, and inventories
+/// are reused for a short time. Callers that ask for a host's inventory while a request for it is
+/// in flight wait for that request instead of sending their own. Failed requests aren't cached.
+/// All other sources are passed straight through. Cloning is cheap and all clones share the cache
+#[derive(Clone)]
+pub struct CoalescingInventory<S> {
+    source: S,
+    cache: InventoryCache,
+}
+
+impl<S> CoalescingInventory<S> {
+    /// Wraps the given source with its own cache, reusing inventories for
+    /// [`DEFAULT_INVENTORY_TTL`]
+    pub fn new(source: S) -> CoalescingInventory<S> {
+        InventoryCache::new().wrap(source)
+    }
+
+    /// Sets how long a fetched inventory is reused for
+    pub fn with_ttl(mut self, ttl: Duration) -> CoalescingInventory<S> {
+        self.cache.ttl = ttl;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: InventorySource + Send + Sync> InventorySource for CoalescingInventory<S> {
+    async fn get_inventory(&self, host_id: &str) -> anyhow::Result<HostInventory> {
+        let cached = self.cache.entry(host_id);
+        // Holding the lock while fetching is what makes concurrent callers wait for the request
+        // that is already in flight
+        let mut cached = cached.lock().await;
+        if let Some((fetched, inventory)) = cached.as_ref() {
+            if fetched.elapsed() < self.cache.ttl {
+                trace!(%host_id, "Using cached host inventory");
+                return Ok(inventory.clone());
+            }
+        }
+        let inventory = self.source.get_inventory(host_id).await?;
+        *cached = Some((Instant::now(), inventory.clone()));
+        Ok(inventory)
+    }
+
+    fn invalidate_inventory(&self, host_id: &str) {
+        self.cache.invalidate(host_id);
+        self.source.invalidate_inventory(host_id);
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: ClaimsSource + Send + Sync> ClaimsSource for CoalescingInventory<S> {
+    async fn get_claims(&self) -> anyhow::Result<HashMap<String, Claims>> {
+        self.source.get_claims().await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: HostSource + Send + Sync> HostSource for CoalescingInventory<S> {
+    async fn get_hosts(&self) -> anyhow::Result<Vec<String>> {
+        self.source.get_hosts().await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: LinkSource + Send + Sync> LinkSource for CoalescingInventory<S> {
+    async fn get_links(&self) -> anyhow::Result<Vec<Link>> {
+        self.source.get_links().await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: ConfigSource + Send + Sync> ConfigSource for CoalescingInventory<S> {
+    async fn get_config(&self, name: &str) -> anyhow::Result<Option<HashMap<String, String>>> {
+        self.source.get_config(name).await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: SecretSource + Send + Sync> SecretSource for CoalescingInventory<S> {
+    async fn get_secret(&self, name: &str) -> anyhow::Result<Option<SecretConfig>> {
+        self.source.get_secret(name).await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: MetricSource + Send + Sync> MetricSource for CoalescingInventory<S> {
+    async fn subscribe_metric(&self, subject: &str) -> anyhow::Result<BoxStream<'static, f64>> {
+        self.source.subscribe_metric(subject).await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: AuctionSource + Send + Sync> AuctionSource for CoalescingInventory<S> {
+    async fn auction_component(
+        &self,
+        reference: &str,
+        component_id: &str,
+        constraints: BTreeMap<String, String>,
+    ) -> anyhow::Result<Vec<String>> {
+        self.source
+            .auction_component(reference, component_id, constraints)
+            .await
+    }
+
+    async fn auction_provider(
+        &self,
+        reference: &str,
+        provider_id: &str,
+        constraints: BTreeMap<String, String>,
+    ) -> anyhow::Result<Vec<String>> {
+        self.source
+            .auction_provider(reference, provider_id, constraints)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// Counts the inventory requests it gets, taking a little while to answer each one
+    #[derive(Default)]
+    struct SlowInventory {
+        requests: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl InventorySource for SlowInventory {
+        async fn get_inventory(&self, host_id: &str) -> anyhow::Result<HostInventory> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(HostInventory::builder()
+                .host_id(host_id.into())
+                .friendly_name("host-one".into())
+                .version("1.0.0".into())
+                .uptime_human("1m".into())
+                .uptime_seconds(60)
+                .labels(BTreeMap::new())
+                .build()
+                .unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn coalesces_inventory_requests() {
+        let cache =
+            CoalescingInventory::new(SlowInventory::default()).with_ttl(Duration::from_millis(100));
+
+        let inventories = futures::future::join_all(
+            (0..10).map(|i| cache.get_inventory(if i % 2 == 0 { "host1" } else { "host2" })),
+        )
+        .await;
+        assert!(inventories.iter().all(Result::is_ok));
+        assert_eq!(
+            cache.source.requests.load(Ordering::SeqCst),
+            2,
+            "Only one request should be sent for each host"
+        );
+
+        cache.invalidate_inventory("host1");
+        cache.get_inventory("host1").await.unwrap();
+        assert_eq!(cache.source.requests.load(Ordering::SeqCst), 3);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        cache.get_inventory("host2").await.unwrap();
+        assert_eq!(
+            cache.source.requests.load(Ordering::SeqCst),
+            4,
+            "Inventories should be fetched again once they are stale"
+        );
+        assert_eq!(
+            cache.cache.lock().keys().collect::<Vec<_>>(),
+            vec!["host2"],
+            "Stale inventories of other hosts should be pruned"
+        );
+    }
+}
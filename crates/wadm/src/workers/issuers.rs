@@ -0,0 +1,114 @@
+//! An allow list of the issuers that components and providers must be signed by. Platform teams can
+//! use this to make sure only artifacts signed by keys they trust run in a lattice. The issuer of an
+//! image is only known once wadm has seen its claims, so an image from an unknown issuer can start
+//! once, after which it is stopped and wadm refuses to start it again
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, RwLock},
+};
+
+/// The issuers allowed in each lattice. Lattices without their own list use the global one, and
+/// every issuer is allowed in lattices without any list
+#[derive(Debug, Clone, Default)]
+pub struct IssuerPolicy {
+    allowed: BTreeSet<String>,
+    lattices: HashMap<String, BTreeSet<String>>,
+}
+
+impl IssuerPolicy {
+    /// Creates a policy that allows the given issuers in every lattice. If no issuers are given,
+    /// every issuer is allowed
+    pub fn new<S: Into<String>>(allowed: impl IntoIterator<Item = S>) -> IssuerPolicy {
+        IssuerPolicy {
+            allowed: allowed.into_iter().map(Into::into).collect(),
+            lattices: HashMap::new(),
+        }
+    }
+
+    /// Allows only the given issuers in the given lattice, instead of the global issuers
+    pub fn with_lattice<S: Into<String>>(
+        mut self,
+        lattice_id: impl Into<String>,
+        allowed: impl IntoIterator<Item = S>,
+    ) -> IssuerPolicy {
+        self.lattices.insert(
+            lattice_id.into(),
+            allowed.into_iter().map(Into::into).collect(),
+        );
+        self
+    }
+
+    /// Returns the allow list for the given lattice, or `None` if every issuer is allowed there
+    pub fn for_lattice(&self, lattice_id: &str) -> Option<IssuerAllowList> {
+        let allowed = self.lattices.get(lattice_id).unwrap_or(&self.allowed);
+        (!allowed.is_empty()).then(|| IssuerAllowList {
+            allowed: Arc::new(allowed.clone()),
+            denied: Arc::default(),
+        })
+    }
+}
+
+/// The issuers allowed in a single lattice, along with the images that were found to be signed by
+/// an issuer that isn't allowed. Cloning is cheap and all clones share the denied images
+#[derive(Debug, Clone)]
+pub struct IssuerAllowList {
+    allowed: Arc<BTreeSet<String>>,
+    /// The issuer of every denied image, keyed by image reference
+    denied: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl IssuerAllowList {
+    /// Returns whether the issuer is allowed. Images that aren't signed have no issuer and can't be
+    /// checked, so they are allowed
+    pub fn allows(&self, issuer: &str) -> bool {
+        issuer.is_empty() || self.allowed.contains(issuer)
+    }
+
+    /// Records the issuer of the given image, returning whether it is allowed. Images that aren't
+    /// allowed are remembered, so they are refused even once they are no longer running
+    pub(crate) fn observe(&self, reference: &str, issuer: &str) -> bool {
+        if self.allows(issuer) {
+            return true;
+        }
+        self.denied
+            .write()
+            .unwrap()
+            .insert(reference.to_owned(), issuer.to_owned());
+        false
+    }
+
+    /// Returns the issuer of the given image if it was found to be one that isn't allowed
+    pub(crate) fn denied_issuer(&self, reference: &str) -> Option<String> {
+        self.denied.read().unwrap().get(reference).cloned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn allows_issuers_per_lattice() {
+        let policy = IssuerPolicy::new(["ATRUSTED"]).with_lattice("edge", ["AEDGE", "ATRUSTED"]);
+        assert!(IssuerPolicy::default().for_lattice("default").is_none());
+
+        let default = policy.for_lattice("default").unwrap();
+        assert!(default.allows("ATRUSTED"));
+        assert!(!default.allows("AEDGE"));
+        assert!(default.allows(""), "Unsigned images can't be checked");
+        assert!(policy.for_lattice("edge").unwrap().allows("AEDGE"));
+
+        assert!(default.observe("ghcr.io/trusted:0.1.0", "ATRUSTED"));
+        assert!(!default.observe("ghcr.io/evil:0.1.0", "AEVIL"));
+        assert_eq!(
+            default
+                .clone()
+                .denied_issuer("ghcr.io/evil:0.1.0")
+                .as_deref(),
+            Some("AEVIL"),
+            "Clones should share the denied images"
+        );
+        assert!(default.denied_issuer("ghcr.io/trusted:0.1.0").is_none());
+    }
+}
@@ -2,11 +2,25 @@
 //! handling events and commands. These are essentially the default things that drive work forward
 //! in wadm
 
+mod circuit_breaker;
 mod command;
+mod command_ack;
+mod dead_letter;
 mod event;
 mod event_helpers;
+mod inventory_cache;
+mod issuers;
+mod rate_limit;
+mod reconcile_report;
 
-pub use command::CommandWorker;
+pub use circuit_breaker::*;
+pub use command::{CommandRetryPolicy, CommandWorker};
+pub use command_ack::{command_ack_key, CommandAckStore, DEFAULT_COMMAND_ACK_TTL};
+pub use dead_letter::*;
 pub(crate) use event::get_commands_and_result;
 pub use event::EventWorker;
 pub use event_helpers::*;
+pub use inventory_cache::{CoalescingInventory, InventoryCache, DEFAULT_INVENTORY_TTL};
+pub use issuers::{IssuerAllowList, IssuerPolicy};
+pub use rate_limit::{ControlRateLimiter, RateLimit};
+pub use reconcile_report::ReconcileReporter;
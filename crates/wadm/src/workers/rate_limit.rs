@@ -0,0 +1,176 @@
+//! Rate limiting of control interface calls. A large reconcile can publish thousands of commands
+//! at once, and sending all of them to hosts at the same time causes host side timeouts. Calls are
+//! spread out with token buckets, one shared by every lattice and one per lattice
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A limit on how many calls can be made
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// The number of calls allowed each second, on average
+    pub per_second: f64,
+    /// The number of calls that can be made at once after a quiet period
+    pub burst: u32,
+}
+
+/// A token bucket. Calls reserve a token when they are made, and wait until the token would have
+/// been available if the bucket was empty. Reserving ahead of time keeps calls in the order they
+/// were made without holding a lock while they wait
+#[derive(Debug)]
+struct TokenBucket {
+    limit: RateLimit,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    /// The tokens left in the bucket. This goes negative when calls are waiting for tokens
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, now: Instant) -> TokenBucket {
+        TokenBucket {
+            limit,
+            state: Mutex::new(BucketState {
+                tokens: limit.burst.max(1) as f64,
+                updated: now,
+            }),
+        }
+    }
+
+    /// Reserves a token, returning how long to wait before making the call
+    fn reserve_at(&self, now: Instant) -> Duration {
+        // A rate that isn't positive would never refill the bucket, so it is treated as no limit
+        if self.limit.per_second.is_nan() || self.limit.per_second <= 0.0 {
+            return Duration::ZERO;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let elapsed = now.saturating_duration_since(state.updated).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.limit.per_second)
+            .min(self.limit.burst.max(1) as f64)
+            - 1.0;
+        state.updated = now;
+        if state.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.tokens / self.limit.per_second)
+        }
+    }
+}
+
+/// Limits the rate of control interface calls across all lattices and for each lattice. Cloning
+/// is cheap and all clones share the same buckets, so a single limiter should be given to the
+/// command workers of every lattice
+#[derive(Clone, Default)]
+pub struct ControlRateLimiter {
+    global: Option<Arc<TokenBucket>>,
+    per_lattice: Option<RateLimit>,
+    lattices: Arc<Mutex<HashMap<String, Arc<TokenBucket>>>>,
+}
+
+impl ControlRateLimiter {
+    /// Creates a limiter that doesn't limit anything
+    pub fn new() -> ControlRateLimiter {
+        ControlRateLimiter::default()
+    }
+
+    /// Limits the calls made to all lattices combined
+    pub fn with_global_limit(mut self, limit: RateLimit) -> ControlRateLimiter {
+        self.global = Some(Arc::new(TokenBucket::new(limit, Instant::now())));
+        self
+    }
+
+    /// Limits the calls made to each lattice
+    pub fn with_lattice_limit(mut self, limit: RateLimit) -> ControlRateLimiter {
+        self.per_lattice = Some(limit);
+        self
+    }
+
+    /// Reserves a call to the given lattice, returning how long to wait before making it. The call
+    /// waits for whichever limit is further behind
+    pub fn reserve(&self, lattice_id: &str) -> Duration {
+        self.reserve_at(lattice_id, Instant::now())
+    }
+
+    fn reserve_at(&self, lattice_id: &str, now: Instant) -> Duration {
+        let lattice = self.per_lattice.map(|limit| {
+            self.lattices
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(lattice_id.to_owned())
+                .or_insert_with(|| Arc::new(TokenBucket::new(limit, now)))
+                .clone()
+        });
+        [self.global.as_deref(), lattice.as_deref()]
+            .into_iter()
+            .flatten()
+            .map(|bucket| bucket.reserve_at(now))
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn spreads_calls_out_after_a_burst() {
+        let now = Instant::now();
+        let limiter = ControlRateLimiter::new()
+            .with_global_limit(RateLimit {
+                per_second: 10.0,
+                burst: 5,
+            })
+            .with_lattice_limit(RateLimit {
+                per_second: 2.0,
+                burst: 2,
+            });
+        // The global bucket is created before `now`, so give it a moment to fill
+        let now = now + Duration::from_secs(1);
+
+        let waits = (0..4)
+            .map(|_| limiter.reserve_at("default", now))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            waits,
+            vec![
+                Duration::ZERO,
+                Duration::ZERO,
+                Duration::from_millis(500),
+                Duration::from_secs(1)
+            ],
+            "Calls past the lattice burst should wait for the lattice limit"
+        );
+
+        // Another lattice has its own bucket, but shares the global one
+        let waits = (0..3)
+            .map(|_| limiter.reserve_at("other", now))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            waits,
+            vec![
+                Duration::ZERO,
+                Duration::from_millis(100),
+                Duration::from_millis(500)
+            ]
+        );
+
+        // Tokens refill over time
+        assert_eq!(
+            limiter.reserve_at("default", now + Duration::from_secs(10)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            ControlRateLimiter::new().reserve("default"),
+            Duration::ZERO,
+            "A limiter without limits should never wait"
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! Reports of what wadm did each time it reconciled a manifest. Only the last report for each
+//! manifest is kept, and it is returned along with the manifest's status so users can see what wadm
+//! last did for their application without digging through logs
+
+use std::time::Instant;
+
+use anyhow::Result;
+use chrono::Utc;
+use tracing::instrument;
+use wadm_types::api::ReconcileReport;
+
+use crate::{commands::Command, publisher::Publisher};
+
+use super::event::get_commands_and_result;
+
+/// The most commands described in a single report. Passes that issue more only report how many
+/// there were beyond this
+const MAX_REPORTED_COMMANDS: usize = 50;
+
+/// Publishes the report of each reconcile pass of a manifest
+#[derive(Clone)]
+pub struct ReconcileReporter<Pub> {
+    publisher: Pub,
+    // Topic prefix, e.g. wadm.reconcile.default
+    topic_prefix: String,
+}
+
+impl<Pub> ReconcileReporter<Pub> {
+    /// Creates a new reporter configured with the given publisher that will send to the reconcile
+    /// report topic using the given prefix
+    pub fn new(publisher: Pub, topic_prefix: &str) -> ReconcileReporter<Pub> {
+        ReconcileReporter {
+            publisher,
+            topic_prefix: topic_prefix.to_owned(),
+        }
+    }
+}
+
+impl<Pub: Publisher> ReconcileReporter<Pub> {
+    #[instrument(level = "trace", skip(self, report))]
+    pub async fn publish_report(&self, name: &str, report: &ReconcileReport) -> Result<()> {
+        self.publisher
+            .publish(
+                serde_json::to_vec(report)?,
+                Some(&format!("{}.{name}", self.topic_prefix)),
+            )
+            .await
+    }
+}
+
+/// A reconcile pass of a single manifest that is in progress, which collects what ends up in its
+/// report
+pub(crate) struct ReconcilePass {
+    trigger: String,
+    started: Instant,
+    commands: Vec<String>,
+    commands_issued: usize,
+    drift: Vec<String>,
+    errors: Vec<String>,
+}
+
+impl ReconcilePass {
+    /// Starts timing a pass with the given trigger
+    pub(crate) fn start(trigger: impl Into<String>) -> ReconcilePass {
+        ReconcilePass {
+            trigger: trigger.into(),
+            started: Instant::now(),
+            commands: Vec::new(),
+            commands_issued: 0,
+            drift: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Runs the given futures, keyed by the name of the scaler they are for, and returns their
+    /// commands and result like [`get_commands_and_result`]. Scalers that returned commands are
+    /// recorded as drift, and the error of each scaler that failed is recorded
+    pub(crate) async fn run_scalers<Fut, I>(
+        &mut self,
+        futs: I,
+        error_message: &str,
+    ) -> (Vec<Command>, Result<()>)
+    where
+        Fut: futures::Future<Output = Result<Vec<Command>>>,
+        I: IntoIterator<Item = (String, Fut)>,
+    {
+        let results = futures::future::join_all(
+            futs.into_iter()
+                .map(|(name, fut)| async move { (name, fut.await) }),
+        )
+        .await;
+        for (name, res) in results.iter() {
+            match res {
+                Ok(commands) if !commands.is_empty() => self.drift.push(name.clone()),
+                Ok(_) => (),
+                Err(e) => self.errors.push(format!("{name}: {e:#}")),
+            }
+        }
+        get_commands_and_result(
+            results
+                .into_iter()
+                .map(|(_, res)| futures::future::ready(res)),
+            error_message,
+        )
+        .await
+    }
+
+    /// Records commands that are about to be published
+    pub(crate) fn issued(&mut self, commands: &[Command]) {
+        self.commands_issued += commands.len();
+        let room = MAX_REPORTED_COMMANDS.saturating_sub(self.commands.len());
+        self.commands
+            .extend(commands.iter().take(room).map(describe_command));
+    }
+
+    /// Records an error that isn't from a scaler, like failing to publish commands
+    pub(crate) fn error(&mut self, error: impl std::fmt::Display) {
+        self.errors.push(error.to_string());
+    }
+
+    /// Finishes the pass, returning its report
+    pub(crate) fn finish(self) -> ReconcileReport {
+        ReconcileReport {
+            time: Utc::now().to_rfc3339(),
+            trigger: self.trigger,
+            duration_ms: self.started.elapsed().as_millis() as u64,
+            commands_issued: self.commands_issued,
+            commands: self.commands,
+            drift: self.drift,
+            errors: self.errors,
+        }
+    }
+}
+
+/// Returns a short description of what the command does
+pub(crate) fn describe_command(command: &Command) -> String {
+    match command {
+        Command::ScaleComponent(cmd) => format!(
+            "Scale component {} from {} to {} on host {}",
+            cmd.component_id, cmd.reference, cmd.count, cmd.host_id
+        ),
+        Command::StartProvider(cmd) => format!(
+            "Start provider {} from {} on host {}",
+            cmd.provider_id, cmd.reference, cmd.host_id
+        ),
+        Command::StopProvider(cmd) => {
+            format!("Stop provider {} on host {}", cmd.provider_id, cmd.host_id)
+        }
+        Command::PutLink(cmd) => format!(
+            "Link {} to {} with {}:{}/{}",
+            cmd.source_id,
+            cmd.target,
+            cmd.wit_namespace,
+            cmd.wit_package,
+            cmd.interfaces.join(",")
+        ),
+        Command::DeleteLink(cmd) => format!(
+            "Delete link from {} with {}:{} named {}",
+            cmd.source_id, cmd.wit_namespace, cmd.wit_package, cmd.link_name
+        ),
+        Command::PutConfig(cmd) => format!("Put config {}", cmd.config_name),
+        Command::DeleteConfig(cmd) => format!("Delete config {}", cmd.config_name),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::{DeleteConfig, ScaleComponent};
+
+    #[tokio::test]
+    async fn reports_drift_commands_and_errors() {
+        let scale = Command::ScaleComponent(ScaleComponent {
+            component_id: "echo-component".to_string(),
+            host_id: "host".to_string(),
+            count: 2,
+            reference: "ghcr.io/echo:0.1.0".to_string(),
+            model_name: "echo".to_string(),
+            ..Default::default()
+        });
+        let mut pass = ReconcilePass::start("manifest_published");
+        let (commands, res) = pass
+            .run_scalers(
+                [
+                    (
+                        "echo-spreadscaler".to_string(),
+                        futures::future::ready(Ok(vec![scale.clone()])),
+                    ),
+                    (
+                        "echo-linkscaler".to_string(),
+                        futures::future::ready(Ok(Vec::new())),
+                    ),
+                    (
+                        "echo-configscaler".to_string(),
+                        futures::future::ready(Err(anyhow::anyhow!("config source is down"))),
+                    ),
+                ],
+                "Errors occurred",
+            )
+            .await;
+        assert_eq!(commands, vec![scale.clone()]);
+        assert!(res.is_err());
+
+        pass.issued(&commands);
+        let cleanup = vec![
+            Command::DeleteConfig(DeleteConfig {
+                config_name: "old".to_string(),
+            });
+            MAX_REPORTED_COMMANDS
+        ];
+        pass.issued(&cleanup);
+        pass.error("Unable to publish commands");
+        let report = pass.finish();
+
+        assert_eq!(report.trigger, "manifest_published");
+        assert_eq!(report.drift, vec!["echo-spreadscaler"]);
+        assert_eq!(report.commands_issued, MAX_REPORTED_COMMANDS + 1);
+        assert_eq!(report.commands.len(), MAX_REPORTED_COMMANDS);
+        assert_eq!(
+            report.commands[0],
+            "Scale component echo-component from ghcr.io/echo:0.1.0 to 2 on host host"
+        );
+        assert_eq!(
+            report.errors,
+            vec![
+                "echo-configscaler: config source is down",
+                "Unable to publish commands"
+            ]
+        );
+    }
+}
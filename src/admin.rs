@@ -0,0 +1,101 @@
+//! Answers the admin introspection requests with the state of this process
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use async_trait::async_trait;
+use tokio::sync::Semaphore;
+use wadm::{
+    config::SharedConfig,
+    consumers::{manager::ConsumerManager, CommandConsumer, EventConsumer},
+    introspection::{app_scalers, managed_lattices, pooled_permit_usage, Introspector},
+    scaler::manager::ScalerView,
+};
+use wadm_types::api::{AppScalers, BuildInfo, ConsumerStatus, ManagedLattice, PermitUsage};
+
+type ViewMap = HashMap<(Option<String>, String), ScalerView>;
+
+/// Views of the scalers of every lattice this process has a scaler manager for, keyed by the
+/// multitenant prefix and lattice ID. Views of managers that were dropped are pruned when read
+#[derive(Clone, Default)]
+pub(crate) struct ScalerViews {
+    views: Arc<Mutex<ViewMap>>,
+}
+
+impl ScalerViews {
+    pub(crate) fn insert(&self, multitenant_prefix: Option<&str>, view: ScalerView) {
+        self.views.lock().unwrap_or_else(|e| e.into_inner()).insert(
+            (
+                multitenant_prefix.map(ToOwned::to_owned),
+                view.lattice_id().to_owned(),
+            ),
+            view,
+        );
+    }
+
+    fn current(&self) -> Vec<ScalerView> {
+        let mut views = self.views.lock().unwrap_or_else(|e| e.into_inner());
+        views.retain(|_, view| !view.is_dropped());
+        views.values().cloned().collect()
+    }
+}
+
+pub(crate) struct ProcessIntrospector {
+    pub(crate) events: ConsumerManager<EventConsumer>,
+    pub(crate) commands: ConsumerManager<CommandConsumer>,
+    pub(crate) scalers: ScalerViews,
+    pub(crate) event_permits: Arc<Semaphore>,
+    pub(crate) command_permits: Arc<Semaphore>,
+    pub(crate) config: SharedConfig,
+    pub(crate) api_prefix: String,
+    pub(crate) multitenant: bool,
+    pub(crate) started: Instant,
+}
+
+#[async_trait]
+impl Introspector for ProcessIntrospector {
+    async fn build_info(&self) -> BuildInfo {
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            api_prefix: self.api_prefix.clone(),
+            multitenant: self.multitenant,
+            uptime_seconds: self.started.elapsed().as_secs(),
+        }
+    }
+
+    async fn lattices(&self) -> Vec<ManagedLattice> {
+        managed_lattices(&self.consumers().await)
+    }
+
+    async fn consumers(&self) -> Vec<ConsumerStatus> {
+        let (mut consumers, commands) =
+            tokio::join!(self.events.statuses(), self.commands.statuses());
+        consumers.extend(commands);
+        consumers.sort_by(|a, b| a.topic.cmp(&b.topic));
+        consumers
+    }
+
+    async fn scalers(&self) -> Vec<AppScalers> {
+        let mut apps = Vec::new();
+        for view in self.scalers.current() {
+            if let Some(all) = view.get_all_scalers().await {
+                apps.extend(app_scalers(view.lattice_id(), all).await);
+            }
+        }
+        apps.sort_by(|a, b| (&a.lattice_id, &a.name).cmp(&(&b.lattice_id, &b.name)));
+        apps
+    }
+
+    async fn permits(&self) -> PermitUsage {
+        let config = self.config.current();
+        pooled_permit_usage([
+            ("events", self.event_permits.as_ref(), config.event_jobs()),
+            (
+                "commands",
+                self.command_permits.as_ref(),
+                config.command_jobs(),
+            ),
+        ])
+    }
+}
@@ -1,44 +1,173 @@
 //! A module for connection pools and generators. This is needed because control interface clients
 //! (and possibly other things like nats connections in the future) are lattice scoped or need
 //! different credentials
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Context as _;
+use serde::Deserialize;
+use wadm::scaler::planner::LatticeSourceCreator;
 use wasmcloud_control_interface::{Client, ClientBuilder};
 
 // Copied from https://github.com/wasmCloud/control-interface-client/blob/main/src/broker.rs#L1, not public
 const DEFAULT_TOPIC_PREFIX: &str = "wasmbus.ctl";
 
-/// A client constructor for wasmCloud control interface clients, identified by a lattice ID
-// NOTE: Yes, this sounds java-y. Deal with it.
+/// Clients keyed by the multitenant prefix and lattice ID
+type ClientMap = HashMap<(Option<String>, String), Client>;
+
+/// The connection settings of a single lattice. Anything not given uses the settings of the pool
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LatticeConnection {
+    /// The topic prefix to use for control interface operations in this lattice
+    pub topic_prefix: Option<String>,
+    /// How long to wait for a reply to a control interface request, in seconds
+    pub timeout_seconds: Option<u64>,
+    /// How long to collect offers for an auction in this lattice, in seconds
+    pub auction_timeout_seconds: Option<u64>,
+    /// A NATS credentials file to connect to this lattice with, for lattices in an account the
+    /// main credentials can't reach
+    pub creds_file: Option<PathBuf>,
+}
+
+impl LatticeConnection {
+    /// Loads the connection settings of each lattice from a JSON file mapping lattice IDs to their
+    /// settings
+    pub async fn from_file(path: &Path) -> anyhow::Result<BTreeMap<String, LatticeConnection>> {
+        let data = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("Unable to read lattice connections {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("Invalid lattice connections file {}", path.display()))
+    }
+}
+
+/// The settings of a lattice along with the NATS client for its credentials, if it has its own
+#[derive(Clone)]
+struct LatticeSettings {
+    connection: LatticeConnection,
+    client: Option<async_nats::Client>,
+}
+
+/// A pool of wasmCloud control interface clients, identified by a lattice ID. Clients are created
+/// the first time a lattice is used and shared by everything that talks to that lattice. Cloning
+/// is cheap and all clones share the same clients
 #[derive(Clone)]
-pub struct ControlClientConstructor {
+pub struct ControlClientPool {
     client: async_nats::Client,
     /// The topic prefix to use for operations
     topic_prefix: Option<String>,
+    timeout: Option<Duration>,
+    auction_timeout: Option<Duration>,
+    lattices: Arc<HashMap<String, LatticeSettings>>,
+    clients: Arc<Mutex<ClientMap>>,
 }
 
-impl ControlClientConstructor {
+impl ControlClientPool {
     /// Creates a new client pool that is all backed using the same NATS client and an optional
     /// topic prefix. The given NATS client should be using credentials that can access all desired
-    /// lattices.
-    pub fn new(
-        client: async_nats::Client,
-        topic_prefix: Option<String>,
-    ) -> ControlClientConstructor {
-        ControlClientConstructor {
+    /// lattices, unless a lattice is given its own client with [`ControlClientPool::with_lattice`]
+    pub fn new(client: async_nats::Client, topic_prefix: Option<String>) -> ControlClientPool {
+        ControlClientPool {
             client,
             topic_prefix,
+            timeout: None,
+            auction_timeout: None,
+            lattices: Arc::default(),
+            clients: Arc::default(),
         }
     }
 
-    /// Get the client for the given lattice ID
+    /// Sets how long clients wait for a reply to a control interface request. Clients use the
+    /// control interface default if this isn't set
+    pub fn with_timeout(mut self, timeout: Duration) -> ControlClientPool {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long clients collect offers for an auction. Clients use the control interface
+    /// default if this isn't set
+    pub fn with_auction_timeout(mut self, timeout: Duration) -> ControlClientPool {
+        self.auction_timeout = Some(timeout);
+        self
+    }
+
+    /// Uses the given settings for a lattice. If a NATS client is given, control interface calls
+    /// for the lattice use it instead of the client of the pool
+    pub fn with_lattice(
+        mut self,
+        lattice_id: impl Into<String>,
+        connection: LatticeConnection,
+        client: Option<async_nats::Client>,
+    ) -> ControlClientPool {
+        Arc::make_mut(&mut self.lattices)
+            .insert(lattice_id.into(), LatticeSettings { connection, client });
+        self
+    }
+
+    /// Get the client for the given lattice ID, creating it if this is the first time the lattice
+    /// is used
     pub fn get_connection(&self, id: &str, multitenant_prefix: Option<&str>) -> Client {
-        let builder = ClientBuilder::new(self.client.clone()).lattice(id);
+        self.clients
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry((multitenant_prefix.map(ToOwned::to_owned), id.to_owned()))
+            .or_insert_with(|| self.build(id, multitenant_prefix))
+            .clone()
+    }
+
+    fn build(&self, id: &str, multitenant_prefix: Option<&str>) -> Client {
+        let settings = self.lattices.get(id);
+        let client = settings
+            .and_then(|settings| settings.client.clone())
+            .unwrap_or_else(|| self.client.clone());
+        let mut builder = ClientBuilder::new(client)
+            .lattice(id)
+            .topic_prefix(self.topic_prefix_for(id, multitenant_prefix));
+        if let Some(timeout) = self.timeout_for(id) {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.auction_timeout_for(id) {
+            builder = builder.auction_timeout(timeout);
+        }
+        builder.build()
+    }
 
-        let builder = builder.topic_prefix(topic_prefix(
+    fn topic_prefix_for(&self, id: &str, multitenant_prefix: Option<&str>) -> String {
+        let lattice_prefix = self
+            .lattices
+            .get(id)
+            .and_then(|settings| settings.connection.topic_prefix.as_deref());
+        topic_prefix(
             multitenant_prefix,
-            self.topic_prefix.as_deref(),
-        ));
+            lattice_prefix.or(self.topic_prefix.as_deref()),
+        )
+    }
 
-        builder.build()
+    fn timeout_for(&self, id: &str) -> Option<Duration> {
+        self.lattices
+            .get(id)
+            .and_then(|settings| settings.connection.timeout_seconds)
+            .map(Duration::from_secs)
+            .or(self.timeout)
+    }
+
+    fn auction_timeout_for(&self, id: &str) -> Option<Duration> {
+        self.lattices
+            .get(id)
+            .and_then(|settings| settings.connection.auction_timeout_seconds)
+            .map(Duration::from_secs)
+            .or(self.auction_timeout)
+    }
+}
+
+impl LatticeSourceCreator for ControlClientPool {
+    type Output = Client;
+
+    fn create(&self, lattice_id: &str, multitenant_prefix: Option<&str>) -> Self::Output {
+        self.get_connection(lattice_id, multitenant_prefix)
     }
 }
 
@@ -59,3 +188,53 @@ fn topic_prefix(multitenant_prefix: Option<&str>, topic_prefix: Option<&str>) ->
         _ => DEFAULT_TOPIC_PREFIX.to_string(),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn shares_clients_and_applies_lattice_settings() {
+        // NOTE: Retrying the initial connect hands back a client without a server to connect to
+        let client = async_nats::ConnectOptions::new()
+            .retry_on_initial_connect()
+            .connect("127.0.0.1:1")
+            .await
+            .unwrap();
+        let connections: BTreeMap<String, LatticeConnection> =
+            serde_json::from_value(serde_json::json!({
+                "edge": {"topic_prefix": "edge.ctl", "timeout_seconds": 10, "auction_timeout_seconds": 30},
+            }))
+            .unwrap();
+        let pool = connections.into_iter().fold(
+            ControlClientPool::new(client, None).with_timeout(Duration::from_secs(2)),
+            |pool, (lattice_id, connection)| pool.with_lattice(lattice_id, connection, None),
+        );
+
+        assert_eq!(pool.topic_prefix_for("edge", None), "edge.ctl");
+        assert_eq!(pool.topic_prefix_for("edge", Some("acct")), "acct.edge.ctl");
+        assert_eq!(pool.topic_prefix_for("default", None), DEFAULT_TOPIC_PREFIX);
+        assert_eq!(pool.timeout_for("edge"), Some(Duration::from_secs(10)));
+        assert_eq!(pool.timeout_for("default"), Some(Duration::from_secs(2)));
+        assert_eq!(
+            pool.auction_timeout_for("edge"),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            pool.auction_timeout_for("default"),
+            None,
+            "Lattices without an auction timeout should use the control interface default"
+        );
+
+        let first = pool.get_connection("edge", None);
+        let second = pool.clone().create("edge", None);
+        assert_eq!(first.lattice(), "edge");
+        assert_eq!(second.lattice(), "edge");
+        pool.get_connection("edge", Some("acct"));
+        assert_eq!(
+            pool.clients.lock().unwrap().len(),
+            2,
+            "Clients should be created once per lattice and shared by every clone"
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! The `export-schemas` command, which writes the JSON Schemas of the manifest and the API types so
+//! clients in other languages and editor validation can be generated from them
+
+use std::path::PathBuf;
+
+use anyhow::Context;
+use wadm::schemas::api_schemas;
+
+/// Options for the `export-schemas` command
+#[derive(clap::Args, Debug)]
+pub(crate) struct ExportSchemasArgs {
+    /// The directory to write the schemas to, as `manifest.schema.json` and one
+    /// `{type}.schema.json` file per API type. All schemas are printed to stdout as a single JSON
+    /// document if this isn't set
+    #[arg(long = "output-dir")]
+    pub(crate) output_dir: Option<PathBuf>,
+}
+
+/// Generates the schemas and writes them where the args say to
+pub(crate) async fn export_schemas(args: &ExportSchemasArgs) -> anyhow::Result<()> {
+    let schemas = api_schemas();
+    let Some(dir) = args.output_dir.as_ref() else {
+        println!("{}", serde_json::to_string_pretty(&schemas)?);
+        return Ok(());
+    };
+    tokio::fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("Unable to create schema directory {}", dir.display()))?;
+    let files = std::iter::once(("manifest".to_string(), &schemas.manifest)).chain(
+        schemas
+            .schemas
+            .iter()
+            .map(|(name, schema)| (name.clone(), schema)),
+    );
+    for (name, schema) in files {
+        let path = dir.join(format!("{name}.schema.json"));
+        tokio::fs::write(&path, serde_json::to_vec_pretty(schema)?)
+            .await
+            .with_context(|| format!("Unable to write schema {}", path.display()))?;
+    }
+    println!(
+        "Wrote {} schemas for wadm {} to {}",
+        schemas.schemas.len() + 1,
+        schemas.version,
+        dir.display()
+    );
+    Ok(())
+}
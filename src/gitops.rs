@@ -0,0 +1,416 @@
+//! Keeping the manifests in a git repository or an OCI artifact deployed, so the repository or
+//! artifact is the declarative source of truth for what runs in each lattice. Every directory at
+//! the root of the repository or artifact is a lattice, and the manifests in it are put and
+//! deployed into that lattice when they are added or changed and deleted when they are removed
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use wadm::oci::{DigestResolver, ImageReference};
+
+use crate::manifest_source::{DirectorySource, LoadedManifests, ManifestSource, Syncer};
+use crate::registry::RegistryDigestResolver;
+
+/// The name of the manifest source, added to every manifest deployed from the repository or
+/// artifact
+const SOURCE_NAME: &str = "gitops";
+
+/// Options for keeping the manifests in a git repository or OCI artifact deployed
+#[derive(clap::Args, Debug)]
+pub(crate) struct GitOpsArgs {
+    /// A git repository of manifests to keep deployed. Each directory in the repository is a
+    /// lattice, and the manifests in it are put and deployed into that lattice when they are added
+    /// or changed and deleted when they are removed. Requires `git` to be installed
+    #[arg(
+        long = "gitops-repo",
+        env = "WADM_GITOPS_REPO",
+        conflicts_with_all = ["gitops_artifact", "manifest_dir"]
+    )]
+    pub(crate) gitops_repo: Option<String>,
+
+    /// The branch of the git repository to deploy. Defaults to the default branch
+    #[arg(
+        long = "gitops-branch",
+        env = "WADM_GITOPS_BRANCH",
+        requires = "gitops_repo"
+    )]
+    pub(crate) gitops_branch: Option<String>,
+
+    /// An OCI artifact of manifests to keep deployed, laid out like the git repository. The files
+    /// are the layers of the artifact, named by their title, like the ones pushed with
+    /// `oras push ghcr.io/myorg/manifests:prod default/app.yaml`. Registry credentials are the
+    /// same ones used for resolving image digests
+    #[arg(
+        long = "gitops-artifact",
+        env = "WADM_GITOPS_ARTIFACT",
+        conflicts_with = "manifest_dir"
+    )]
+    pub(crate) gitops_artifact: Option<ImageReference>,
+
+    /// The directory within the repository or artifact that has the lattice directories. Defaults
+    /// to the root
+    #[arg(long = "gitops-path", env = "WADM_GITOPS_PATH")]
+    pub(crate) gitops_path: Option<PathBuf>,
+
+    /// How often, in seconds, to check the repository or artifact for changes
+    #[arg(
+        long = "gitops-interval",
+        env = "WADM_GITOPS_INTERVAL",
+        default_value = "60"
+    )]
+    pub(crate) gitops_interval: u64,
+
+    /// The directory to fetch the repository or artifact into. Defaults to a directory in the
+    /// system temp directory
+    #[arg(long = "gitops-checkout-dir", env = "WADM_GITOPS_CHECKOUT_DIR")]
+    pub(crate) gitops_checkout_dir: Option<PathBuf>,
+}
+
+impl GitOpsArgs {
+    /// Returns the fetcher for the configured repository or artifact, or `None` if neither is
+    /// configured
+    pub(crate) fn fetcher(
+        &self,
+        registry: RegistryDigestResolver,
+    ) -> Option<Box<dyn Fetcher + Send + Sync>> {
+        if let Some(url) = self.gitops_repo.as_deref() {
+            return Some(Box::new(GitRepository::new(
+                url,
+                self.gitops_branch.as_deref(),
+            )));
+        }
+        self.gitops_artifact
+            .clone()
+            .map(|reference| -> Box<dyn Fetcher + Send + Sync> {
+                Box::new(OciArtifact::new(reference, registry))
+            })
+    }
+}
+
+/// Anything that can fetch a tree of manifests into a directory
+#[async_trait]
+pub(crate) trait Fetcher {
+    /// Fetches the latest revision into the directory, replacing what was there before. Returns
+    /// the revision that was fetched, like a commit or digest
+    async fn fetch(&self, dir: &Path) -> anyhow::Result<String>;
+}
+
+/// Fetches a branch of a git repository with the `git` CLI, so the credential helpers and SSH
+/// config of the environment are used
+pub(crate) struct GitRepository {
+    url: String,
+    branch: Option<String>,
+}
+
+impl GitRepository {
+    pub(crate) fn new(url: &str, branch: Option<&str>) -> GitRepository {
+        GitRepository {
+            url: url.to_owned(),
+            branch: branch.map(ToOwned::to_owned),
+        }
+    }
+}
+
+#[async_trait]
+impl Fetcher for GitRepository {
+    async fn fetch(&self, dir: &Path) -> anyhow::Result<String> {
+        if tokio::fs::try_exists(dir.join(".git")).await? {
+            git(dir, &["remote", "set-url", "origin", &self.url]).await?;
+            git(
+                dir,
+                &[
+                    "fetch",
+                    "--depth",
+                    "1",
+                    "origin",
+                    self.branch.as_deref().unwrap_or("HEAD"),
+                ],
+            )
+            .await?;
+            git(dir, &["reset", "--hard", "FETCH_HEAD"]).await?;
+            git(dir, &["clean", "-ffdx"]).await?;
+        } else {
+            remove_dir(dir).await?;
+            if let Some(parent) = dir.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            let dir = dir
+                .to_str()
+                .context("Checkout directory must be valid UTF-8")?;
+            let mut args = vec!["clone", "--depth", "1"];
+            if let Some(branch) = self.branch.as_deref() {
+                args.extend(["--branch", branch]);
+            }
+            args.extend(["--", &self.url, dir]);
+            git(Path::new("."), &args).await?;
+        }
+        git(dir, &["rev-parse", "HEAD"]).await
+    }
+}
+
+/// Runs a git command in the given directory, returning its output
+async fn git(dir: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = tokio::process::Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        // Never wait for someone to type in credentials
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .await
+        .context("Unable to run git")?;
+    // Only the subcommand is included, since the arguments can have credentials in the URL
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// Fetches the files in an OCI artifact. The files are only downloaded when the digest of the
+/// artifact changes
+pub(crate) struct OciArtifact {
+    reference: ImageReference,
+    registry: RegistryDigestResolver,
+    /// The digest of the artifact that was last fetched
+    fetched: Mutex<Option<String>>,
+}
+
+impl OciArtifact {
+    pub(crate) fn new(reference: ImageReference, registry: RegistryDigestResolver) -> OciArtifact {
+        OciArtifact {
+            reference,
+            registry,
+            fetched: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl Fetcher for OciArtifact {
+    async fn fetch(&self, dir: &Path) -> anyhow::Result<String> {
+        let mut fetched = self.fetched.lock().await;
+        let digest = self.registry.resolve_digest(&self.reference).await?;
+        if fetched.as_deref() == Some(digest.as_str()) {
+            return Ok(digest);
+        }
+        let (digest, files) = self.registry.pull_artifact(&self.reference).await?;
+        remove_dir(dir).await?;
+        for (name, data) in files {
+            let path = Path::new(&name);
+            if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+                warn!(%name, "Artifact has a file outside of its root, skipping");
+                continue;
+            }
+            let path = dir.join(path);
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&path, data)
+                .await
+                .with_context(|| format!("Unable to write {}", path.display()))?;
+        }
+        *fetched = Some(digest.clone());
+        Ok(digest)
+    }
+}
+
+async fn remove_dir(dir: &Path) -> anyhow::Result<()> {
+    match tokio::fs::remove_dir_all(dir).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Unable to clear {}", dir.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Loads the manifests for each lattice from the directories in the root. Hidden directories, like
+/// `.git`, and files in the root are ignored
+async fn load_lattices(root: &Path) -> anyhow::Result<BTreeMap<String, LoadedManifests>> {
+    let mut entries = tokio::fs::read_dir(root)
+        .await
+        .with_context(|| format!("Unable to read {}", root.display()))?;
+    let mut lattices = BTreeMap::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let Ok(lattice) = entry.file_name().into_string() else {
+            continue;
+        };
+        if lattice.starts_with('.') || !entry.file_type().await.is_ok_and(|ty| ty.is_dir()) {
+            continue;
+        }
+        let loaded = match DirectorySource::new(entry.path()).load().await {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                warn!(error = ?e, %lattice, "Unable to load manifests for lattice");
+                LoadedManifests::default()
+            }
+        };
+        lattices.insert(lattice, loaded);
+    }
+    Ok(lattices)
+}
+
+/// Keeps the manifests from the repository or artifact deployed, fetching it on the given
+/// interval. This only returns if the first fetch fails
+pub(crate) async fn sync_gitops(
+    fetcher: Box<dyn Fetcher + Send + Sync>,
+    args: &GitOpsArgs,
+    client: async_nats::Client,
+    api_prefix: String,
+) -> anyhow::Result<()> {
+    let checkout = args
+        .gitops_checkout_dir
+        .clone()
+        .unwrap_or_else(|| std::env::temp_dir().join("wadm-gitops"));
+    let root = match args.gitops_path.as_deref() {
+        Some(path) if !path.components().all(|c| matches!(c, Component::Normal(_))) => {
+            anyhow::bail!("GitOps path must be a relative path within the repository or artifact")
+        }
+        Some(path) => checkout.join(path),
+        None => checkout.clone(),
+    };
+    let mut revision = fetcher
+        .fetch(&checkout)
+        .await
+        .context("Unable to fetch manifests")?;
+    info!(%revision, "Fetched manifests");
+
+    let mut syncers: BTreeMap<String, Syncer> = BTreeMap::new();
+    let mut ticker = tokio::time::interval(Duration::from_secs(args.gitops_interval));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    ticker.tick().await;
+    loop {
+        match load_lattices(&root).await {
+            Ok(lattices) => {
+                let found: HashSet<String> = lattices.keys().cloned().collect();
+                for (lattice, loaded) in lattices {
+                    let syncer = match syncers.entry(lattice) {
+                        std::collections::btree_map::Entry::Occupied(entry) => entry.into_mut(),
+                        std::collections::btree_map::Entry::Vacant(entry) => {
+                            let syncer = Syncer::new(
+                                SOURCE_NAME,
+                                wadm_client::Client::from_nats_client(
+                                    entry.key(),
+                                    Some(&api_prefix),
+                                    client.clone(),
+                                ),
+                            );
+                            syncer.remove_orphans(&loaded).await;
+                            entry.insert(syncer)
+                        }
+                    };
+                    syncer.sync(loaded).await;
+                }
+                // Everything in a lattice whose directory was removed is deleted
+                for (_, syncer) in syncers
+                    .iter_mut()
+                    .filter(|(lattice, _)| !found.contains(*lattice))
+                {
+                    syncer
+                        .sync(LoadedManifests {
+                            complete: true,
+                            ..Default::default()
+                        })
+                        .await;
+                }
+            }
+            Err(e) => warn!(error = ?e, "Unable to load manifests, will try again"),
+        }
+
+        ticker.tick().await;
+        match fetcher.fetch(&checkout).await {
+            Ok(fetched) if fetched != revision => {
+                info!(%revision, %fetched, "Fetched new revision of manifests");
+                revision = fetched;
+            }
+            Ok(_) => {}
+            Err(e) => warn!(error = ?e, "Unable to fetch manifests, will try again"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn fetches_manifests_for_each_lattice_from_git() {
+        let base = std::env::temp_dir().join(format!("wadm-gitops-{}", uuid::Uuid::new_v4()));
+        let repo = base.join("repo");
+        tokio::fs::create_dir_all(repo.join("default"))
+            .await
+            .unwrap();
+        tokio::fs::create_dir_all(repo.join("edge")).await.unwrap();
+        let manifest = tokio::fs::read("tests/fixtures/manifests/simple.wadm.yaml")
+            .await
+            .unwrap();
+        tokio::fs::write(repo.join("default").join("simple.yaml"), &manifest)
+            .await
+            .unwrap();
+        tokio::fs::write(repo.join("edge").join(".gitkeep"), "")
+            .await
+            .unwrap();
+        tokio::fs::write(repo.join("README.md"), "Not a lattice")
+            .await
+            .unwrap();
+        let commit = |message: &'static str| {
+            let repo = repo.clone();
+            async move {
+                git(&repo, &["add", "-A"]).await.unwrap();
+                git(
+                    &repo,
+                    &[
+                        "-c",
+                        "user.name=wadm",
+                        "-c",
+                        "user.email=wadm@example.com",
+                        "commit",
+                        "-qm",
+                        message,
+                    ],
+                )
+                .await
+                .unwrap()
+            }
+        };
+        git(&repo, &["init", "-q"]).await.unwrap();
+        commit("Add manifests").await;
+
+        let checkout = base.join("checkout");
+        let fetcher = GitRepository::new(repo.to_str().unwrap(), None);
+        let first = fetcher.fetch(&checkout).await.unwrap();
+        let lattices = load_lattices(&checkout).await.unwrap();
+        assert_eq!(
+            lattices.keys().collect::<Vec<_>>(),
+            ["default", "edge"],
+            "Only directories should be lattices"
+        );
+        assert_eq!(lattices["default"].manifests.len(), 1);
+        assert!(lattices["edge"].manifests.is_empty());
+
+        tokio::fs::rename(
+            repo.join("default").join("simple.yaml"),
+            repo.join("edge").join("simple.yaml"),
+        )
+        .await
+        .unwrap();
+        commit("Move manifest").await;
+        let second = fetcher.fetch(&checkout).await.unwrap();
+        assert_ne!(first, second, "A new commit should be fetched");
+        let lattices = load_lattices(&checkout).await.unwrap();
+        assert!(
+            !lattices.contains_key("default"),
+            "Directories removed from the repository should be removed from the checkout"
+        );
+        assert_eq!(lattices["edge"].manifests.len(), 1);
+
+        tokio::fs::remove_dir_all(base).await.unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+//! Health endpoints for orchestrators. `/livez` answers as long as wadm is running. `/readyz` checks
+//! the NATS connection, the JetStream account limits and the health of the wadm streams, reporting
+//! not ready once usage crosses a threshold of a hard limit so restarts or alerts happen before
+//! writes start failing. The JSON Schemas of the commands and events this version of wadm uses are
+//! served on `/schemas/commands` and `/schemas/events`
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use async_nats::connection::State;
+use async_nats::jetstream::{
+    account::Limits,
+    stream::{self, DiscardPolicy},
+    Context,
+};
+use http_body_util::Full;
+use hyper::{body::Bytes, server::conn::http1, service::service_fn, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tracing::{debug, warn};
+use wadm::schemas;
+
+/// The result of a readiness check
+#[derive(Debug, Serialize)]
+pub(crate) struct Readiness {
+    pub(crate) ready: bool,
+    pub(crate) checks: Vec<Check>,
+}
+
+/// A single check that is part of readiness
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct Check {
+    pub(crate) name: String,
+    pub(crate) ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) message: Option<String>,
+}
+
+impl Check {
+    fn ok(name: impl Into<String>) -> Check {
+        Check {
+            name: name.into(),
+            ok: true,
+            message: None,
+        }
+    }
+
+    fn failed(name: impl Into<String>, message: impl Into<String>) -> Check {
+        Check {
+            name: name.into(),
+            ok: false,
+            message: Some(message.into()),
+        }
+    }
+}
+
+/// Checks whether wadm is ready to do work
+pub(crate) struct ReadinessChecker {
+    pub(crate) client: async_nats::Client,
+    pub(crate) context: Context,
+    /// The streams wadm uses
+    pub(crate) streams: Vec<String>,
+    /// The percentage of a limit that can be used before reporting not ready
+    pub(crate) threshold: u8,
+}
+
+impl ReadinessChecker {
+    pub(crate) async fn check(&self) -> Readiness {
+        let mut checks = Vec::new();
+        let state = self.client.connection_state();
+        checks.push(if matches!(state, State::Connected) {
+            Check::ok("nats_connection")
+        } else {
+            Check::failed("nats_connection", format!("Connection is {state}"))
+        });
+
+        match self.context.query_account().await {
+            Ok(account) => {
+                checks.extend(limit_checks(
+                    "account",
+                    [
+                        account.memory,
+                        account.storage,
+                        account.streams as u64,
+                        account.consumers as u64,
+                    ],
+                    &account.limits,
+                    self.threshold,
+                ));
+                // Accounts with tiered limits (per replica count) have their limits on each tier
+                // instead of the account
+                for (name, tier) in account.tiers.iter() {
+                    checks.extend(limit_checks(
+                        &format!("tier_{name}"),
+                        [
+                            tier.memory,
+                            tier.storage,
+                            tier.streams as u64,
+                            tier.consumers as u64,
+                        ],
+                        &tier.limits,
+                        self.threshold,
+                    ));
+                }
+            }
+            Err(e) => checks.push(Check::failed(
+                "account",
+                format!("Unable to get JetStream account info: {e}"),
+            )),
+        }
+
+        for name in self.streams.iter() {
+            let check_name = format!("stream_{name}");
+            match self.context.get_stream(name).await {
+                Ok(stream) => checks.extend(stream_checks(
+                    check_name,
+                    stream.cached_info(),
+                    self.threshold,
+                )),
+                Err(e) => checks.push(Check::failed(
+                    check_name,
+                    format!("Unable to get stream info: {e}"),
+                )),
+            }
+        }
+
+        Readiness {
+            ready: checks.iter().all(|check| check.ok),
+            checks,
+        }
+    }
+}
+
+/// Checks the memory, storage, stream and consumer usage (in that order) against the given limits
+fn limit_checks(prefix: &str, usage: [u64; 4], limits: &Limits, threshold: u8) -> Vec<Check> {
+    [
+        ("memory", limits.max_memory),
+        ("storage", limits.max_storage),
+        ("streams", limits.max_streams),
+        ("consumers", limits.max_consumers),
+    ]
+    .into_iter()
+    .zip(usage)
+    .map(|((resource, limit), used)| {
+        usage_check(format!("{prefix}_{resource}"), used, limit, threshold)
+    })
+    .collect()
+}
+
+/// Checks that the usage is under the threshold percentage of the limit. Limits that aren't set
+/// (or are 0, which JetStream treats as unlimited for streams) always pass
+fn usage_check(name: String, used: u64, limit: Option<i64>, threshold: u8) -> Check {
+    let Some(limit) = limit.filter(|limit| *limit > 0) else {
+        return Check::ok(name);
+    };
+    let allowed = limit as u128 * threshold as u128 / 100;
+    if (used as u128) < allowed {
+        Check::ok(name)
+    } else {
+        Check::failed(
+            name,
+            format!("Using {used} of {limit}, which is over the {threshold}% threshold"),
+        )
+    }
+}
+
+/// Checks that the stream has a leader and, for streams that reject new messages once full, that
+/// it isn't close to its limits. Streams that discard old messages are expected to hit their limits
+fn stream_checks(name: String, info: &stream::Info, threshold: u8) -> Vec<Check> {
+    if info
+        .cluster
+        .as_ref()
+        .is_some_and(|cluster| cluster.leader.is_none())
+    {
+        return vec![Check::failed(name, "Stream has no leader")];
+    }
+    if info.config.discard != DiscardPolicy::New {
+        return vec![Check::ok(name)];
+    }
+    vec![
+        usage_check(
+            format!("{name}_bytes"),
+            info.state.bytes,
+            Some(info.config.max_bytes),
+            threshold,
+        ),
+        usage_check(
+            format!("{name}_messages"),
+            info.state.messages,
+            Some(info.config.max_messages),
+            threshold,
+        ),
+    ]
+}
+
+fn json_response(body: &impl Serialize) -> hyper::http::Result<Response<Full<Bytes>>> {
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Full::new(Bytes::from(
+            serde_json::to_vec(body).unwrap_or_default(),
+        )))
+}
+
+/// Serves the health endpoints on the given address until the process exits
+pub(crate) async fn serve_health(
+    address: SocketAddr,
+    checker: ReadinessChecker,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Unable to serve health endpoints on {address}"))?;
+    debug!(%address, "Serving health endpoints");
+    let checker = Arc::new(checker);
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!(error = %e, "Unable to accept health check connection");
+                continue;
+            }
+        };
+        let checker = checker.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| {
+                let checker = checker.clone();
+                async move {
+                    match req.uri().path() {
+                        "/livez" => Response::builder().body(Full::new(Bytes::from("ok"))),
+                        "/readyz" => {
+                            let readiness = checker.check().await;
+                            if !readiness.ready {
+                                warn!(checks = ?readiness.checks.iter().filter(|c| !c.ok).collect::<Vec<_>>(), "wadm is not ready");
+                            }
+                            Response::builder()
+                                .status(if readiness.ready {
+                                    StatusCode::OK
+                                } else {
+                                    StatusCode::SERVICE_UNAVAILABLE
+                                })
+                                .header("Content-Type", "application/json")
+                                .body(Full::new(Bytes::from(
+                                    serde_json::to_vec(&readiness).unwrap_or_default(),
+                                )))
+                        }
+                        "/schemas/commands" => json_response(&schemas::command_schemas()),
+                        "/schemas/events" => json_response(&schemas::event_schemas()),
+                        _ => Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body(Full::new(Bytes::new())),
+                    }
+                }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!(error = %e, "Error serving health check");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flips_to_not_ready_before_limits_are_hit() {
+        let limits = Limits {
+            max_memory: Some(1000),
+            max_storage: None,
+            max_streams: Some(10),
+            max_consumers: Some(100),
+            ..Default::default()
+        };
+        let checks = limit_checks("account", [899, 1_000_000, 10, 5], &limits, 90);
+        assert_eq!(
+            checks.iter().map(|c| c.ok).collect::<Vec<_>>(),
+            vec![true, true, false, true],
+            "Only the streams limit should be over the threshold"
+        );
+
+        assert!(!usage_check("memory".into(), 900, Some(1000), 90).ok);
+        assert!(usage_check("memory".into(), 900, Some(1000), 100).ok);
+        assert!(usage_check("memory".into(), 900, Some(0), 90).ok);
+    }
+}
@@ -0,0 +1,565 @@
+//! An HTTP gateway for the wadm API, for environments where sending NATS requests (from CI, for
+//! example) is awkward. Each REST route is forwarded as a request on the matching versioned API
+//! subject, so it is answered by the same handlers, authorization and quotas as a request sent over
+//! NATS. The lattice is given with the `lattice` query parameter and defaults to `default`:
+//!
+//! - `GET /models` lists the models
+//! - `PUT /models` puts the manifest in the body
+//! - `GET /models/{name}` gets a model, `?version=` gets a specific version
+//! - `DELETE /models/{name}` deletes a model, `?version=` deletes a specific version
+//! - `GET /models/{name}/versions` lists the versions of a model
+//! - `POST /models/{name}/deploy` and `POST /models/{name}/undeploy`, with the optional request in
+//!   the body
+//! - `POST /models/{name}/pause` and `POST /models/{name}/resume`
+//! - `GET /models/{name}/status` and `GET /models/{name}/placement`
+//! - `GET /topology` gets the topology of the lattice
+//!
+//! The JSON Schemas of this version of wadm are served by the gateway itself rather than forwarded,
+//! and don't need a token:
+//!
+//! - `GET /schemas` gets the schemas of the manifest and the API types, the same as
+//!   `wadm export-schemas` prints
+//! - `GET /schemas/commands` and `GET /schemas/events` get the schemas of commands and events
+//!
+//! When API tokens are configured, every request needs an `Authorization: Bearer` header with a
+//! token that is scoped to the lattice of the request, and the caller of the API request is the
+//! name of the token. Without tokens the gateway can only be served on a loopback address, since
+//! requests are sent with wadm's own NATS credentials
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use async_nats::{HeaderMap, RequestErrorKind};
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::{Bytes, Incoming},
+    server::conn::http1,
+    service::service_fn,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+use wadm::{
+    schemas,
+    server::{
+        auth::{AuthError, TokenAuthenticator},
+        CONTENT_TYPE_HEADER,
+    },
+};
+use wadm_types::api::{
+    DeleteModelRequest, GetModelRequest, API_VERSION, CALLER_HEADER, TEMPLATE_OVERLAY_HEADER,
+    TEMPLATE_VARIABLES_HEADER,
+};
+
+/// The lattice requests are sent to when they don't have a `lattice` query parameter
+const DEFAULT_LATTICE: &str = "default";
+
+/// The headers passed through from the HTTP request to the API request
+const FORWARDED_HEADERS: [&str; 3] = [
+    CONTENT_TYPE_HEADER,
+    TEMPLATE_VARIABLES_HEADER,
+    TEMPLATE_OVERLAY_HEADER,
+];
+
+/// Options for serving the wadm API over HTTP
+#[derive(clap::Args, Debug)]
+pub(crate) struct HttpGatewayArgs {
+    /// Address to serve the wadm API over HTTP on. REST routes like `PUT /models` and
+    /// `POST /models/{name}/deploy` are forwarded to the NATS API, so they are handled exactly the
+    /// same. The HTTP API isn't served unless this is set. Only loopback addresses are allowed
+    /// unless API tokens are configured. Not supported in multitenant mode, as the account of a
+    /// request can't be known
+    #[arg(
+        long = "http-addr",
+        env = "WADM_HTTP_ADDR",
+        conflicts_with = "multitenant"
+    )]
+    pub(crate) http_addr: Option<SocketAddr>,
+
+    /// A static token that can use the HTTP API, in the form `NAME:TOKEN=LATTICE[,LATTICE...]`.
+    /// Can be given multiple times. A lattice of `*` allows all lattices. The name is used as the
+    /// caller of requests made with the token
+    #[arg(long = "http-token", value_parser = parse_http_token)]
+    pub(crate) http_tokens: Vec<(String, String, Vec<String>)>,
+
+    /// The secret used to verify HS256 signed JWTs for the HTTP API. The lattices a JWT can use are
+    /// read from its `lattices` claim
+    #[arg(
+        long = "http-jwt-secret",
+        env = "WADM_HTTP_JWT_SECRET",
+        requires = "http_jwt_issuer",
+        hide_env_values = true
+    )]
+    pub(crate) http_jwt_secret: Option<String>,
+
+    /// The issuer JWTs for the HTTP API must come from
+    #[arg(
+        long = "http-jwt-issuer",
+        env = "WADM_HTTP_JWT_ISSUER",
+        requires = "http_jwt_secret"
+    )]
+    pub(crate) http_jwt_issuer: Option<String>,
+}
+
+impl HttpGatewayArgs {
+    /// Returns the authenticator for the configured tokens, or `None` if no tokens are configured
+    pub(crate) fn authenticator(&self) -> anyhow::Result<Option<TokenAuthenticator>> {
+        if self.http_tokens.is_empty() && self.http_jwt_secret.is_none() {
+            return Ok(None);
+        }
+        let auth = self.http_tokens.iter().fold(
+            TokenAuthenticator::new(),
+            |auth, (name, token, lattices)| {
+                auth.with_static_token(token, name.clone(), lattices.iter().collect())
+            },
+        );
+        let auth = match (&self.http_jwt_secret, &self.http_jwt_issuer) {
+            (Some(secret), Some(issuer)) => auth.with_jwt_issuer(secret.as_bytes(), issuer)?,
+            _ => auth,
+        };
+        Ok(Some(auth))
+    }
+}
+
+fn parse_http_token(s: &str) -> Result<(String, String, Vec<String>), String> {
+    let invalid = || "Invalid HTTP token, expected NAME:TOKEN=LATTICE[,LATTICE...]".to_owned();
+    let (name, rest) = s.split_once(':').ok_or_else(invalid)?;
+    // Lattice IDs can't contain `=`, but tokens (like base64 ones) can
+    let (token, lattices) = rest.rsplit_once('=').ok_or_else(invalid)?;
+    if name.is_empty() || token.is_empty() {
+        return Err(invalid());
+    }
+    Ok((
+        name.to_owned(),
+        token.to_owned(),
+        lattices
+            .split(',')
+            .filter(|lattice| !lattice.is_empty())
+            .map(ToOwned::to_owned)
+            .collect(),
+    ))
+}
+
+/// An API request an HTTP request maps to
+#[derive(Debug, PartialEq)]
+struct Route {
+    lattice: String,
+    /// The operation, relative to the lattice, e.g. `model.deploy.echo`
+    operation: String,
+    /// The body to send instead of the HTTP body, for routes that take their request from the
+    /// query string
+    body: Option<Vec<u8>>,
+}
+
+/// Maps the method, path and query of an HTTP request to the API request it is for. Returns `None`
+/// if there is no such route
+fn route(method: &Method, path: &str, query: Option<&str>) -> anyhow::Result<Option<Route>> {
+    let param = |key: &str| {
+        query
+            .into_iter()
+            .flat_map(|query| query.split('&'))
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value.to_owned())
+    };
+    let lattice = param("lattice").unwrap_or_else(|| DEFAULT_LATTICE.to_owned());
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    for segment in segments.iter().copied().chain([lattice.as_str()]) {
+        // Anything that could change which subject a request goes to can't be allowed through
+        if segment.is_empty()
+            || segment.contains(['.', '*', '>'])
+            || segment.contains(char::is_whitespace)
+        {
+            anyhow::bail!("Invalid path or lattice segment {segment:?}");
+        }
+    }
+    let version_body = |to_json: fn(String) -> serde_json::Result<Vec<u8>>| {
+        param("version").map(to_json).transpose()
+    };
+
+    let (operation, body) = match (method, segments.as_slice()) {
+        (&Method::GET, ["models"]) => ("model.get".to_owned(), None),
+        (&Method::PUT, ["models"]) => ("model.put".to_owned(), None),
+        (&Method::GET, ["models", name]) => (
+            format!("model.get.{name}"),
+            version_body(|version| {
+                serde_json::to_vec(&GetModelRequest {
+                    version: Some(version),
+                })
+            })?,
+        ),
+        (&Method::DELETE, ["models", name]) => (
+            format!("model.del.{name}"),
+            version_body(|version| {
+                serde_json::to_vec(&DeleteModelRequest {
+                    version: Some(version),
+                })
+            })?,
+        ),
+        (&Method::GET, ["models", name, operation @ ("versions" | "status" | "placement")])
+        | (
+            &Method::POST,
+            ["models", name, operation @ ("deploy" | "undeploy" | "pause" | "resume")],
+        ) => (format!("model.{operation}.{name}"), None),
+        (&Method::GET, ["topology"]) => ("lattice.topology".to_owned(), None),
+        _ => return Ok(None),
+    };
+    Ok(Some(Route {
+        lattice,
+        operation,
+        body,
+    }))
+}
+
+/// Returns the schemas served on the given path, if there are any
+fn schemas(path: &str) -> Option<serde_json::Result<Vec<u8>>> {
+    match path.trim_end_matches('/') {
+        "/schemas" => Some(serde_json::to_vec(&schemas::api_schemas())),
+        "/schemas/commands" => Some(serde_json::to_vec(&schemas::command_schemas())),
+        "/schemas/events" => Some(serde_json::to_vec(&schemas::event_schemas())),
+        _ => None,
+    }
+}
+
+/// Returns the HTTP status for an API response, based on its `result` field
+fn response_status(body: &[u8]) -> StatusCode {
+    let result = serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|value| value.get("result")?.as_str().map(str::to_owned));
+    match result.as_deref() {
+        Some("notfound") => StatusCode::NOT_FOUND,
+        Some("error") => StatusCode::BAD_REQUEST,
+        _ => StatusCode::OK,
+    }
+}
+
+fn text_response(status: StatusCode, body: String) -> hyper::http::Result<Response<Full<Bytes>>> {
+    Response::builder()
+        .status(status)
+        .body(Full::new(Bytes::from(body)))
+}
+
+/// Forwards a single HTTP request to the API, if it is allowed by the given authenticator
+async fn forward(
+    client: &async_nats::Client,
+    prefix: &str,
+    auth: Option<&TokenAuthenticator>,
+    req: Request<Incoming>,
+) -> hyper::http::Result<Response<Full<Bytes>>> {
+    if req.method() == Method::GET {
+        match schemas(req.uri().path()) {
+            Some(Ok(body)) => {
+                return Response::builder()
+                    .header("Content-Type", "application/json")
+                    .body(Full::new(Bytes::from(body)))
+            }
+            Some(Err(e)) => {
+                return text_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Unable to serialize schemas: {e}"),
+                )
+            }
+            None => {}
+        }
+    }
+    let route = match route(req.method(), req.uri().path(), req.uri().query()) {
+        Ok(Some(route)) => route,
+        Ok(None) => return text_response(StatusCode::NOT_FOUND, String::new()),
+        Err(e) => return text_response(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    let caller = match auth {
+        Some(auth) => {
+            let authorization = req
+                .headers()
+                .get(hyper::header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok());
+            match auth.authorize(authorization, &route.lattice) {
+                Ok(principal) => Some(principal.subject),
+                Err(e) => {
+                    debug!(error = %e, lattice = %route.lattice, "Rejected HTTP API request");
+                    return match e {
+                        AuthError::Forbidden { .. } => {
+                            text_response(StatusCode::FORBIDDEN, e.to_string())
+                        }
+                        AuthError::MissingToken | AuthError::InvalidToken(_) => Response::builder()
+                            .status(StatusCode::UNAUTHORIZED)
+                            .header(hyper::header::WWW_AUTHENTICATE, "Bearer")
+                            .body(Full::new(Bytes::from(e.to_string()))),
+                    };
+                }
+            }
+        }
+        None => None,
+    };
+    let mut headers = HeaderMap::new();
+    for name in FORWARDED_HEADERS {
+        if let Some(value) = req.headers().get(name).and_then(|v| v.to_str().ok()) {
+            headers.insert(name, value);
+        }
+    }
+    // The caller comes from the token rather than a header the client can set to anything
+    if let Some(caller) = caller {
+        headers.insert(CALLER_HEADER, caller.as_str());
+    }
+    let body = match route.body {
+        Some(body) => Bytes::from(body),
+        None => match req.into_body().collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(e) => {
+                return text_response(
+                    StatusCode::BAD_REQUEST,
+                    format!("Unable to read request body: {e}"),
+                )
+            }
+        },
+    };
+
+    let subject = format!(
+        "{prefix}.{API_VERSION}.{}.{}",
+        route.lattice, route.operation
+    );
+    match client.request_with_headers(subject, headers, body).await {
+        Ok(msg) => Response::builder()
+            .status(response_status(&msg.payload))
+            .header("Content-Type", "application/json")
+            .body(Full::new(msg.payload)),
+        Err(e) => {
+            let status = match e.kind() {
+                RequestErrorKind::NoResponders => StatusCode::SERVICE_UNAVAILABLE,
+                RequestErrorKind::TimedOut => StatusCode::GATEWAY_TIMEOUT,
+                RequestErrorKind::Other => StatusCode::BAD_GATEWAY,
+            };
+            warn!(error = %e, operation = %route.operation, "Unable to forward HTTP request to the API");
+            text_response(status, format!("Unable to send API request: {e}"))
+        }
+    }
+}
+
+/// Serves the HTTP API on the given address until the process exits, forwarding requests to the
+/// API with the given prefix. Requests are only forwarded if they are allowed by the authenticator,
+/// when one is given. Without one, only loopback addresses can be served on
+pub(crate) async fn serve_http_gateway(
+    address: SocketAddr,
+    client: async_nats::Client,
+    api_prefix: String,
+    auth: Option<TokenAuthenticator>,
+) -> anyhow::Result<()> {
+    if auth.is_none() && !address.ip().is_loopback() {
+        anyhow::bail!(
+            "Refusing to serve the HTTP API on {address} without authentication. Configure API tokens or use a loopback address"
+        );
+    }
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Unable to serve the HTTP API on {address}"))?;
+    info!(%address, "Serving the HTTP API");
+    serve(listener, client, api_prefix, auth).await
+}
+
+async fn serve(
+    listener: TcpListener,
+    client: async_nats::Client,
+    api_prefix: String,
+    auth: Option<TokenAuthenticator>,
+) -> anyhow::Result<()> {
+    let prefix: Arc<str> = api_prefix.trim().trim_matches('.').into();
+    let auth = auth.map(Arc::new);
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!(error = %e, "Unable to accept HTTP API connection");
+                continue;
+            }
+        };
+        let client = client.clone();
+        let prefix = prefix.clone();
+        let auth = auth.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| {
+                let client = client.clone();
+                let prefix = prefix.clone();
+                let auth = auth.clone();
+                async move { forward(&client, &prefix, auth.as_deref(), req).await }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!(error = %e, "Error serving HTTP API request");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use wadm::server::auth::LatticeScope;
+
+    use super::*;
+
+    #[test]
+    fn maps_rest_routes_to_api_operations() {
+        let operation = |method, path, query| {
+            route(&method, path, query)
+                .unwrap()
+                .map(|route| (route.lattice, route.operation))
+        };
+        assert_eq!(
+            operation(Method::PUT, "/models", None),
+            Some(("default".into(), "model.put".into()))
+        );
+        assert_eq!(
+            operation(Method::POST, "/models/echo/deploy", Some("lattice=prod")),
+            Some(("prod".into(), "model.deploy.echo".into()))
+        );
+        assert_eq!(
+            operation(Method::GET, "/models/echo/status", None),
+            Some(("default".into(), "model.status.echo".into()))
+        );
+        assert_eq!(operation(Method::GET, "/models/echo/deploy", None), None);
+
+        let get = route(&Method::GET, "/models/echo", Some("version=v1"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(get.body.as_deref(), Some(br#"{"version":"v1"}"#.as_slice()));
+
+        assert!(
+            route(&Method::GET, "/models/echo.model.del.other", None).is_err(),
+            "Names that would change the subject should be rejected"
+        );
+        assert!(route(&Method::GET, "/models", Some("lattice=*")).is_err());
+    }
+
+    #[test]
+    fn uses_api_result_for_status() {
+        assert_eq!(
+            response_status(br#"{"result":"notfound","message":""}"#),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            response_status(br#"{"result":"error","message":"bad"}"#),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            response_status(br#"{"result":"acknowledged"}"#),
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn can_parse_http_tokens() {
+        assert_eq!(
+            parse_http_token("ci:c2VjcmV0==prod,staging").unwrap(),
+            (
+                "ci".to_owned(),
+                "c2VjcmV0=".to_owned(),
+                vec!["prod".to_owned(), "staging".to_owned()]
+            )
+        );
+        assert!(parse_http_token("no-lattices").is_err());
+        assert!(parse_http_token(":token=prod").is_err());
+    }
+
+    #[tokio::test]
+    async fn only_serves_loopback_without_auth() {
+        let client = async_nats::ConnectOptions::new()
+            .retry_on_initial_connect()
+            .connect("127.0.0.1:1")
+            .await
+            .unwrap();
+        let res = serve_http_gateway(
+            "0.0.0.0:0".parse().unwrap(),
+            client,
+            "wadm.api".to_owned(),
+            None,
+        )
+        .await;
+        assert!(
+            res.is_err(),
+            "Should refuse to serve on a public address without authentication"
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_unauthorized_requests() {
+        // No NATS server is needed, authorized requests just time out instead of being answered
+        let client = async_nats::ConnectOptions::new()
+            .retry_on_initial_connect()
+            .request_timeout(Some(Duration::from_millis(100)))
+            .connect("127.0.0.1:1")
+            .await
+            .unwrap();
+        let auth = TokenAuthenticator::new().with_static_token(
+            "dev-token",
+            "dev",
+            ["dev"].into_iter().collect::<LatticeScope>(),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, client, "wadm.api".to_owned(), Some(auth)));
+
+        let http = reqwest::Client::new();
+        let status = |lattice: &'static str, token: Option<&'static str>| {
+            let req = http.get(format!("http://{address}/models?lattice={lattice}"));
+            let req = match token {
+                Some(token) => req.bearer_auth(token),
+                None => req,
+            };
+            async move { req.send().await.unwrap().status().as_u16() }
+        };
+        assert_eq!(status("dev", None).await, StatusCode::UNAUTHORIZED.as_u16());
+        assert_eq!(
+            status("dev", Some("wrong-token")).await,
+            StatusCode::UNAUTHORIZED.as_u16()
+        );
+        assert_eq!(
+            status("prod", Some("dev-token")).await,
+            StatusCode::FORBIDDEN.as_u16()
+        );
+        assert_eq!(
+            status("dev", Some("dev-token")).await,
+            StatusCode::GATEWAY_TIMEOUT.as_u16(),
+            "Authorized requests should be forwarded to the API"
+        );
+    }
+
+    #[tokio::test]
+    async fn serves_schemas() {
+        let client = async_nats::ConnectOptions::new()
+            .retry_on_initial_connect()
+            .connect("127.0.0.1:1")
+            .await
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let auth =
+            TokenAuthenticator::new().with_static_token("dev-token", "dev", LatticeScope::All);
+        tokio::spawn(serve(listener, client, "wadm.api".to_owned(), Some(auth)));
+
+        let get = |path: &'static str| async move {
+            let resp = reqwest::get(format!("http://{address}{path}"))
+                .await
+                .unwrap();
+            assert_eq!(resp.status().as_u16(), StatusCode::OK.as_u16(), "{path}");
+            resp.json::<serde_json::Value>().await.unwrap()
+        };
+        assert_eq!(
+            get("/schemas").await,
+            serde_json::to_value(schemas::api_schemas()).unwrap(),
+            "Should serve the same schemas as export-schemas prints"
+        );
+        assert_eq!(
+            get("/schemas/commands").await,
+            serde_json::to_value(schemas::command_schemas()).unwrap()
+        );
+        assert_eq!(
+            get("/schemas/events").await,
+            serde_json::to_value(schemas::event_schemas()).unwrap()
+        );
+    }
+}
@@ -10,10 +10,15 @@ use tracing_subscriber::fmt::{
     time::SystemTime,
     FmtContext, FormatEvent, FormatFields,
 };
-use tracing_subscriber::{layer::SubscriberExt, registry::LookupSpan, EnvFilter, Layer};
+use tracing_subscriber::{
+    layer::SubscriberExt, registry::LookupSpan, reload, EnvFilter, Layer, Registry,
+};
 
 const TRACING_PATH: &str = "/v1/traces";
 
+/// A handle for changing the log filter after tracing has been configured
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
 /// A struct that allows us to dynamically choose JSON formatting without using dynamic dispatch.
 /// This is just so we avoid any sort of possible slow down in logging code
 enum JsonOrNot {
@@ -46,8 +51,8 @@ pub fn configure_tracing(
     structured_logging: bool,
     tracing_enabled: bool,
     tracing_endpoint: Option<String>,
-) {
-    let env_filter_layer = get_env_filter();
+) -> LogFilterHandle {
+    let (env_filter_layer, handle) = reload::Layer::new(get_env_filter());
     let log_layer = get_log_layer(structured_logging);
     let subscriber = tracing_subscriber::Registry::default()
         .with(env_filter_layer)
@@ -56,7 +61,7 @@ pub fn configure_tracing(
         if let Err(e) = tracing::subscriber::set_global_default(subscriber) {
             eprintln!("Logger/tracer was already initted, continuing: {}", e);
         }
-        return;
+        return handle;
     }
 
     let mut tracing_endpoint =
@@ -100,6 +105,25 @@ pub fn configure_tracing(
     if let Err(e) = res {
         eprintln!("Logger/tracer was already initted, continuing: {}", e);
     }
+    handle
+}
+
+/// Replaces the log filter with the given directives, in the same format as `RUST_LOG`. If no
+/// directives are given, the filter from `RUST_LOG` is used again
+pub fn set_log_filter(handle: &LogFilterHandle, directives: Option<&str>) {
+    let filter = match directives {
+        Some(directives) => match EnvFilter::try_new(directives) {
+            Ok(filter) => filter,
+            Err(e) => {
+                tracing::warn!(error = %e, %directives, "Invalid log level, keeping the current one");
+                return;
+            }
+        },
+        None => get_env_filter(),
+    };
+    if let Err(e) = handle.reload(filter) {
+        tracing::warn!(error = %e, "Unable to change log level");
+    }
 }
 
 fn get_log_layer<S>(structured_logging: bool) -> Box<dyn Layer<S> + Send + Sync + 'static>
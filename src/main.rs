@@ -1,39 +1,95 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
+use anyhow::Context as _;
 use async_nats::jetstream::{stream::Stream, Context};
 use clap::Parser;
+use futures::FutureExt;
 use tokio::sync::Semaphore;
 use tracing::log::debug;
-use wadm_types::api::DEFAULT_WADM_TOPIC_PREFIX;
+use tracing::{info, warn};
+use wadm_types::{aliases::AliasRegistry, api::DEFAULT_WADM_TOPIC_PREFIX};
 
 use wadm::{
+    audit::StreamAuditLog,
+    claims::LatticeClaimsVerifier,
+    config::{RuntimeConfig, SharedConfig},
     consumers::{
-        manager::{ConsumerManager, WorkerCreator},
+        manager::{ConsumerManager, ShutdownReport, WorkerCreator},
+        middleware::{MiddlewareWorker, WorkMetrics},
+        quarantine::Quarantine,
         *,
     },
-    nats_utils::LatticeIdParser,
-    scaler::manager::{ScalerManager, WADM_NOTIFY_PREFIX},
-    server::{ManifestNotifier, Server},
-    storage::{nats_kv::NatsKvStore, reaper::Reaper},
-    workers::{CommandPublisher, CommandWorker, EventWorker, StatusPublisher},
-    DEFAULT_COMMANDS_TOPIC, DEFAULT_EVENTS_TOPIC, DEFAULT_MULTITENANT_EVENTS_TOPIC,
-    DEFAULT_STATUS_TOPIC, DEFAULT_WADM_EVENTS_TOPIC, DEFAULT_WADM_EVENT_CONSUMER_TOPIC,
+    cordon::LabelCordoner,
+    crash::{self, CrashReporter, FileCrashReporter, KvCrashReporter},
+    hibernation::LatticeActivity,
+    leadership::LatticeLeases,
+    nats_utils::{command_subjects, event_subjects, lattice_subject, LatticeIdParser},
+    notifications::{LifecycleNotifier, SubjectSink},
+    policy::{NatsPolicyEngine, PolicyGate},
+    publisher::Encoding,
+    scaler::{
+        manager::{ScalerManager, WADM_NOTIFY_PREFIX},
+        planner::ScalerPlanner,
+        PlacementStrategy,
+    },
+    schemas,
+    secrets::SecretResolver,
+    server::{auth::AccountAuthorizer, ManifestNotifier, ManifestQuotas, Server},
+    sharding::ShardMembership,
+    storage::{
+        nats_kv::NatsKvStore,
+        reaper::Reaper,
+        state_log::{LoggedStore, StreamStateLog},
+    },
+    topology::StateTopology,
+    workers::{
+        CoalescingInventory, CommandAckStore, CommandPublisher, CommandRetryPolicy, CommandWorker,
+        ControlRateLimiter, DeadLetterQueue, EventWorker, HostCircuitBreaker, InventoryCache,
+        IssuerPolicy, RateLimit, ReconcileReporter, StatusPublisher, StreamDeadLetters,
+    },
+    DEFAULT_AUDIT_TOPIC, DEFAULT_COMMAND_TOPIC_PREFIX, DEFAULT_DEAD_LETTER_TOPIC,
+    DEFAULT_EVENT_TOPIC_PREFIX, DEFAULT_MEMBERSHIP_TOPIC, DEFAULT_QUARANTINE_TOPIC,
+    DEFAULT_RECONCILE_REPORT_TOPIC, DEFAULT_STATE_LOG_TOPIC, DEFAULT_STATUS_TOPIC,
+    DEFAULT_WADM_EVENTS_TOPIC, DEFAULT_WADM_EVENT_CONSUMER_TOPIC,
 };
 
+mod admin;
 mod connections;
+mod export_schemas;
+#[cfg(feature = "gitops")]
+mod gitops;
+mod health;
+#[cfg(feature = "http-gateway")]
+mod http_gateway;
 mod logging;
+mod manifest_source;
+mod metrics;
+mod migrate;
 mod nats;
 mod observer;
+mod registry;
+mod reload;
+mod secrets;
+mod telemetry;
+mod uninstall;
+mod webhooks;
 
-use connections::ControlClientConstructor;
+use connections::{ControlClientPool, LatticeConnection};
+use nats::{KvBucketSettings, StreamSettings};
 
 const WADM_EVENT_STREAM_NAME: &str = "wadm_events";
 const WADM_EVENT_CONSUMER_STREAM_NAME: &str = "wadm_event_consumer";
 const COMMAND_STREAM_NAME: &str = "wadm_commands";
 const STATUS_STREAM_NAME: &str = "wadm_status";
 const NOTIFY_STREAM_NAME: &str = "wadm_notify";
+const DEAD_LETTER_STREAM_NAME: &str = "wadm_dlq";
+const QUARANTINE_STREAM_NAME: &str = "wadm_quarantine";
+const STATE_LOG_STREAM_NAME: &str = "wadm_state_log";
+const AUDIT_STREAM_NAME: &str = "wadm_audit";
+const RECONCILE_REPORT_STREAM_NAME: &str = "wadm_reconcile_reports";
 const WASMBUS_EVENT_STREAM_NAME: &str = "wasmbus_events";
 
 #[derive(Parser, Debug)]
@@ -67,15 +123,370 @@ struct Args {
     #[arg(short = 'e', long = "tracing-endpoint", env = "WADM_TRACING_ENDPOINT")]
     tracing_endpoint: Option<String>,
 
+    /// The exporters to send metrics to, as a comma separated list. `otlp` pushes metrics to the
+    /// same collector as the tracing endpoint and `prometheus` serves them for scraping. Metrics
+    /// aren't exported unless at least one exporter is given
+    #[arg(
+        long = "metrics-exporter",
+        env = "WADM_METRICS_EXPORTER",
+        value_delimiter = ','
+    )]
+    metrics_exporters: Vec<metrics::MetricsExporter>,
+
+    /// Address to serve the `/livez` and `/readyz` health endpoints on. Readiness checks the NATS
+    /// connection, JetStream account limits and the health of the wadm streams. The command and
+    /// event schemas are also served on `/schemas/commands` and `/schemas/events`. Health endpoints
+    /// aren't served unless this is set
+    #[arg(long = "health-address", env = "WADM_HEALTH_ADDRESS")]
+    health_address: Option<std::net::SocketAddr>,
+
+    /// The percentage of a JetStream limit (account memory, storage, streams and consumers, or the
+    /// size of a stream that rejects new messages when full) that can be used before wadm reports
+    /// that it isn't ready
+    #[arg(
+        long = "readiness-threshold",
+        env = "WADM_READINESS_THRESHOLD",
+        default_value = "90",
+        value_parser = clap::value_parser!(u8).range(1..=100)
+    )]
+    readiness_threshold: u8,
+
+    /// The address to serve Prometheus metrics on, at the `/metrics` path
+    #[arg(
+        long = "prometheus-address",
+        env = "WADM_PROMETHEUS_ADDRESS",
+        default_value = "0.0.0.0:9464"
+    )]
+    prometheus_address: std::net::SocketAddr,
+
+    /// How often, in seconds, to push metrics over OTLP
+    #[arg(
+        long = "metrics-interval",
+        env = "WADM_METRICS_INTERVAL",
+        default_value = "60"
+    )]
+    metrics_interval: u64,
+
+    /// Opts in to sending anonymous usage telemetry (the wadm version and coarse buckets of how
+    /// many lattices and manifests it manages) to the telemetry endpoint. Off by default
+    #[arg(
+        long = "telemetry",
+        env = "WADM_TELEMETRY",
+        requires = "telemetry_endpoint"
+    )]
+    telemetry: bool,
+
+    /// The endpoint usage telemetry is POSTed to. Nothing is sent unless --telemetry is also set
+    #[arg(long = "telemetry-endpoint", env = "WADM_TELEMETRY_ENDPOINT")]
+    telemetry_endpoint: Option<String>,
+
+    /// How often, in seconds, to send usage telemetry
+    #[arg(
+        long = "telemetry-interval",
+        env = "WADM_TELEMETRY_INTERVAL",
+        default_value = "86400",
+        value_parser = clap::value_parser!(u64).range(60..)
+    )]
+    telemetry_interval: u64,
+
     /// The NATS JetStream domain to connect to
     #[arg(short = 'd', env = "WADM_JETSTREAM_DOMAIN")]
     domain: Option<String>,
 
-    /// (Advanced) Tweak the maximum number of jobs to run for handling events and commands. Be
-    /// careful how you use this as it can affect performance
+    /// (Advanced) Tweak the maximum number of jobs to run for handling events and commands. Events
+    /// and commands each get a pool of this size. Be careful how you use this as it can affect
+    /// performance
     #[arg(short = 'j', long = "max-jobs", env = "WADM_MAX_JOBS")]
     max_jobs: Option<usize>,
 
+    /// (Advanced) The maximum number of events to handle at once, overriding `--max-jobs` for
+    /// events
+    #[arg(long = "max-event-jobs", env = "WADM_MAX_EVENT_JOBS")]
+    max_event_jobs: Option<usize>,
+
+    /// (Advanced) The maximum number of commands to handle at once, overriding `--max-jobs` for
+    /// commands. Commands have their own pool so they keep running during a storm of events
+    #[arg(long = "max-command-jobs", env = "WADM_MAX_COMMAND_JOBS")]
+    max_command_jobs: Option<usize>,
+
+    /// (Advanced) The number of consecutive control interface failures for a host before wadm
+    /// stops sending it commands and places work on other hosts
+    #[arg(
+        long = "ctl-failure-threshold",
+        env = "WADM_CTL_FAILURE_THRESHOLD",
+        default_value = "5"
+    )]
+    ctl_failure_threshold: u32,
+
+    /// (Advanced) The amount of time in seconds to stop sending commands to an unresponsive host
+    /// before trying it again
+    #[arg(
+        long = "ctl-circuit-cooldown",
+        env = "WADM_CTL_CIRCUIT_COOLDOWN",
+        default_value = "30"
+    )]
+    ctl_circuit_cooldown: u64,
+
+    /// (Advanced) The topic prefix to use for control interface operations, replacing the default
+    /// `wasmbus.ctl`
+    #[arg(long = "ctl-topic-prefix", env = "WADM_CTL_TOPIC_PREFIX")]
+    ctl_topic_prefix: Option<String>,
+
+    /// (Advanced) The prefix of the subjects hosts publish lattice events on, for deployments that
+    /// use a custom `wasmbus` prefix. Events are read from `<prefix>.evt.<lattice>.>`
+    #[arg(
+        long = "event-topic-prefix",
+        env = "WADM_EVENT_TOPIC_PREFIX",
+        default_value = DEFAULT_EVENT_TOPIC_PREFIX
+    )]
+    event_topic_prefix: String,
+
+    /// (Advanced) The prefix of the subjects wadm publishes commands for each lattice on. Changing
+    /// this recreates the command stream, dropping any commands that haven't been handled
+    #[arg(
+        long = "command-topic-prefix",
+        env = "WADM_COMMAND_TOPIC_PREFIX",
+        default_value = DEFAULT_COMMAND_TOPIC_PREFIX
+    )]
+    command_topic_prefix: String,
+
+    /// (Advanced) The amount of time in seconds to wait for a reply to a control interface
+    /// request. Raise this when hosts pull large images over slow links, as requests that time out
+    /// fail the reconcile that sent them. The control interface default is used if not given
+    #[arg(long = "ctl-timeout", env = "WADM_CTL_TIMEOUT")]
+    ctl_timeout: Option<u64>,
+
+    /// (Advanced) The amount of time in seconds to collect offers from hosts for an auction. The
+    /// control interface default is used if not given
+    #[arg(long = "ctl-auction-timeout", env = "WADM_CTL_AUCTION_TIMEOUT")]
+    ctl_auction_timeout: Option<u64>,
+
+    /// How spread scalers find hosts for new instances. `labels` matches spread requirements
+    /// against the host labels in the lattice state. `auction` also runs a component or provider
+    /// auction and only places instances on the hosts that answer, which is useful when the labels
+    /// in the lattice state might be stale. Auctions wait for the auction timeout, so placement is
+    /// slower
+    #[arg(long = "placement", env = "WADM_PLACEMENT", default_value = "labels")]
+    placement: PlacementStrategy,
+
+    /// A JSON file mapping lattice IDs to the control interface settings of that lattice
+    /// (`topic_prefix`, `timeout_seconds`, `auction_timeout_seconds` and `creds_file`). A lattice
+    /// with a `creds_file` uses its own NATS connection for control interface calls, for lattices
+    /// in an account the main credentials can't reach
+    #[arg(
+        long = "lattice-connections-file",
+        env = "WADM_LATTICE_CONNECTIONS_FILE"
+    )]
+    lattice_connections_file: Option<PathBuf>,
+
+    /// The number of times a command is attempted before it is sent to the dead letter stream.
+    /// This only applies to command consumers created after this is set
+    #[arg(
+        long = "command-max-attempts",
+        env = "WADM_COMMAND_MAX_ATTEMPTS",
+        default_value = "3",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    command_max_attempts: u64,
+
+    /// The number of times an event is delivered before it is quarantined when handling it keeps
+    /// failing. Messages that can't be decoded are quarantined right away. This only applies to
+    /// event consumers created after this is set
+    #[arg(
+        long = "quarantine-max-deliveries",
+        env = "WADM_QUARANTINE_MAX_DELIVERIES",
+        default_value = "3",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    quarantine_max_deliveries: u64,
+
+    /// The amount of time in milliseconds to wait before retrying a failed command. The delay
+    /// doubles with each attempt
+    #[arg(
+        long = "command-retry-delay",
+        env = "WADM_COMMAND_RETRY_DELAY",
+        default_value = "1000"
+    )]
+    command_retry_delay: u64,
+
+    /// The maximum amount of time in milliseconds to wait before retrying a failed command
+    #[arg(
+        long = "command-retry-max-delay",
+        env = "WADM_COMMAND_RETRY_MAX_DELAY",
+        default_value = "30000"
+    )]
+    command_retry_max_delay: u64,
+
+    /// The amount of time in seconds a published command stays valid for. Commands that haven't
+    /// been executed by then (e.g. because wadm or NATS was down) are discarded, since the next
+    /// reconcile will publish fresh ones
+    #[arg(
+        long = "command-validity",
+        env = "WADM_COMMAND_VALIDITY",
+        default_value = "300"
+    )]
+    command_validity: u64,
+
+    /// The encoding commands are published with, either `json` or `protobuf`. Protobuf commands
+    /// are smaller and cheaper to encode, but can only be read by versions of wadm that support
+    /// them. Commands are always decoded with the encoding they were published with, so replicas can
+    /// be moved to a new encoding one at a time once every replica supports it
+    #[arg(
+        long = "command-encoding",
+        env = "WADM_COMMAND_ENCODING",
+        default_value = "json"
+    )]
+    command_encoding: Encoding,
+
+    /// The most control interface calls per second the command workers of all lattices combined
+    /// make. Calls past the limit wait their turn, which keeps a large reconcile from flooding
+    /// hosts with requests. Unlimited by default
+    #[arg(long = "control-rate-limit", env = "WADM_CONTROL_RATE_LIMIT")]
+    control_rate_limit: Option<f64>,
+
+    /// The most control interface calls per second the command worker of a single lattice makes.
+    /// Unlimited by default
+    #[arg(
+        long = "lattice-control-rate-limit",
+        env = "WADM_LATTICE_CONTROL_RATE_LIMIT"
+    )]
+    lattice_control_rate_limit: Option<f64>,
+
+    /// The number of control interface calls that can be made at once before the rate limits
+    /// apply, after a quiet period
+    #[arg(
+        long = "control-rate-burst",
+        env = "WADM_CONTROL_RATE_BURST",
+        default_value = "10"
+    )]
+    control_rate_burst: u32,
+
+    /// Enables leader election between wadm processes. Only the process holding a lattice's lease
+    /// reconciles that lattice, while others stay on standby and take over if the lease expires
+    #[arg(long = "leader-election", env = "WADM_LEADER_ELECTION")]
+    leader_election: bool,
+
+    /// Shards lattices across all running wadm processes using consistent hashing, instead of
+    /// electing a leader per lattice. Each process needs a unique host ID
+    #[arg(
+        long = "sharding",
+        env = "WADM_SHARDING",
+        conflicts_with = "leader_election"
+    )]
+    sharding: bool,
+
+    /// The amount of time in seconds a wadm process can go without announcing itself before the
+    /// others consider it gone and take over its lattices
+    #[arg(
+        long = "shard-member-ttl",
+        env = "WADM_SHARD_MEMBER_TTL",
+        default_value = "30",
+        value_parser = clap::value_parser!(u64).range(3..)
+    )]
+    shard_member_ttl: u64,
+
+    /// The amount of time in seconds a lattice lease lasts without being renewed. This is roughly
+    /// how long it takes for a standby process to take over if the leader goes away
+    #[arg(
+        long = "leader-lease-ttl",
+        env = "WADM_LEADER_LEASE_TTL",
+        default_value = "30",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    leader_lease_ttl: u64,
+
+    /// Name of the KV bucket used to store lattice leases for leader election
+    #[arg(
+        long = "leader-lease-bucket",
+        env = "WADM_LEADER_LEASE_BUCKET",
+        default_value = "wadm_leases"
+    )]
+    leader_lease_bucket: String,
+
+    /// The amount of time in seconds a lattice can go without any events (other than heartbeats)
+    /// or API requests before it is hibernated. Hibernated lattices have their consumers and
+    /// scalers stopped until they see activity again. Hibernation is disabled if this is not set
+    #[arg(long = "hibernate-after", env = "WADM_HIBERNATE_AFTER")]
+    hibernate_after: Option<u64>,
+
+    /// The amount of time in seconds a newly deployed version has to reach a deployed status
+    /// before the previously deployed version is automatically redeployed. Rollbacks are disabled
+    /// if this is not set
+    #[arg(long = "rollback-timeout", env = "WADM_ROLLBACK_TIMEOUT")]
+    rollback_timeout: Option<u64>,
+
+    /// The default number of seconds to wait between stages when a shared application is
+    /// undeployed along with the applications that depend on it, giving dependent applications
+    /// time to drain before the shared application goes away
+    #[arg(
+        long = "undeploy-stage-delay",
+        env = "WADM_UNDEPLOY_STAGE_DELAY",
+        default_value = "5"
+    )]
+    undeploy_stage_delay: u64,
+
+    /// Name of a KV bucket to write a report of any work left behind to on shutdown. The report is
+    /// stored under the ID of this wadm process and is always logged, whether or not this is set
+    #[arg(long = "shutdown-report-bucket", env = "WADM_SHUTDOWN_REPORT_BUCKET")]
+    shutdown_report_bucket: Option<String>,
+
+    /// Directory to write a report to whenever a worker panics while handling a message. Panics
+    /// are always logged, whether or not this is set
+    #[arg(
+        long = "crash-report-dir",
+        env = "WADM_CRASH_REPORT_DIR",
+        conflicts_with = "crash_report_bucket"
+    )]
+    crash_report_dir: Option<PathBuf>,
+
+    /// Name of a KV bucket to store a report in whenever a worker panics while handling a message.
+    /// Reports are stored under a unique ID for each panic
+    #[arg(long = "crash-report-bucket", env = "WADM_CRASH_REPORT_BUCKET")]
+    crash_report_bucket: Option<String>,
+
+    /// Directory of manifests to keep deployed. Manifests are put and deployed when their file is
+    /// added or changed and deleted when their file is removed. Meant for deployments where the
+    /// manifests are baked into an image and nothing calls the API
+    #[arg(long = "manifest-dir", env = "WADM_MANIFEST_DIR")]
+    manifest_dir: Option<PathBuf>,
+
+    /// The lattice to deploy manifests from the manifest directory to
+    #[arg(
+        long = "manifest-dir-lattice",
+        env = "WADM_MANIFEST_DIR_LATTICE",
+        default_value = "default",
+        requires = "manifest_dir"
+    )]
+    manifest_dir_lattice: String,
+
+    /// How often, in seconds, to check the manifest directory for changes
+    #[arg(
+        long = "manifest-dir-interval",
+        env = "WADM_MANIFEST_DIR_INTERVAL",
+        default_value = "10",
+        requires = "manifest_dir"
+    )]
+    manifest_dir_interval: u64,
+
+    /// The maximum amount of time in seconds to wait for in-flight work to finish when shutting
+    /// down. Work that is still running after this is abandoned and its messages are redelivered
+    #[arg(
+        long = "shutdown-timeout",
+        env = "WADM_SHUTDOWN_TIMEOUT",
+        default_value = "30"
+    )]
+    shutdown_timeout: u64,
+
+    /// How often in seconds to check the lag of every consumer. Consumers that stopped or stalled
+    /// with messages waiting are recreated
+    #[arg(
+        long = "consumer-check-interval",
+        env = "WADM_CONSUMER_CHECK_INTERVAL",
+        default_value = "30",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    consumer_check_interval: u64,
+
     /// The URL of the nats server you want to connect to
     #[arg(
         short = 's',
@@ -132,8 +543,39 @@ struct Args {
     )]
     cleanup_interval: u64,
 
+    /// Remove hosts from the store once they have missed this many heartbeats in a row, instead of
+    /// after the cleanup interval. Hosts are checked every heartbeat interval when this is set
+    #[arg(long = "max-missed-heartbeats", env = "WADM_MAX_MISSED_HEARTBEATS")]
+    max_missed_heartbeats: Option<u32>,
+
+    /// The interval in seconds that hosts send heartbeats at. This should match the heartbeat
+    /// interval the hosts in the lattice are configured with
+    #[arg(
+        long = "host-heartbeat-interval",
+        env = "WADM_HOST_HEARTBEAT_INTERVAL",
+        default_value = "30",
+        requires = "max_missed_heartbeats"
+    )]
+    host_heartbeat_interval: u64,
+
+    /// Keep a log of every change to the observed state of each lattice and rebuild the state of a
+    /// lattice from it when it is missing on startup, instead of waiting for every host to send a
+    /// heartbeat
+    #[arg(long = "state-log", env = "WADM_STATE_LOG")]
+    state_log: bool,
+
+    /// The interval in seconds at which the state log of each lattice is compacted into a snapshot
+    #[arg(
+        long = "state-log-compaction-interval",
+        env = "WADM_STATE_LOG_COMPACTION_INTERVAL",
+        default_value = "300",
+        requires = "state_log"
+    )]
+    state_log_compaction_interval: u64,
+
     /// The API topic prefix to use. This is an advanced setting that should only be used if you
-    /// know what you are doing
+    /// know what you are doing. The API is served on versioned subjects under the prefix
+    /// (`<prefix>.v1.<lattice>.>`) as well as the unversioned subjects older clients use
     #[arg(
         long = "api-prefix",
         env = "WADM_API_PREFIX",
@@ -148,20 +590,357 @@ struct Args {
     #[arg(long = "stream-prefix", env = "WADM_STREAM_PREFIX")]
     stream_prefix: Option<String>,
 
-    /// Name of the bucket used for storage of manifests
+    /// Name of the bucket used for storage of manifests. Manifests are kept separate from the
+    /// lattice state so they can be replicated and retained differently
     #[arg(
         long = "manifest-bucket-name",
+        alias = "manifest-bucket",
         env = "WADM_MANIFEST_BUCKET_NAME",
         default_value = "wadm_manifests"
     )]
     manifest_bucket: String,
 
+    /// Name of a bucket to copy manifests from on startup, for when the name of the manifest bucket
+    /// is changed. Manifests that already exist in the manifest bucket are left alone
+    #[arg(
+        long = "manifest-bucket-migrate-from",
+        env = "WADM_MANIFEST_BUCKET_MIGRATE_FROM"
+    )]
+    manifest_bucket_migrate_from: Option<String>,
+
+    /// Name of the bucket the JSON Schemas of wadm's commands and events are published to on startup
+    #[arg(
+        long = "schema-bucket-name",
+        env = "WADM_SCHEMA_BUCKET_NAME",
+        default_value = "wadm_schemas"
+    )]
+    schema_bucket: String,
+
+    /// Name of the bucket the last response to each command is stored in. Commands that fail on
+    /// their last attempt are reported in the status of their application
+    #[arg(
+        long = "command-ack-bucket-name",
+        env = "WADM_COMMAND_ACK_BUCKET_NAME",
+        default_value = "wadm_command_acks"
+    )]
+    command_ack_bucket: String,
+
+    /// The amount of time in seconds to keep the response to a command for
+    #[arg(
+        long = "command-ack-ttl",
+        env = "WADM_COMMAND_ACK_TTL",
+        default_value = "3600"
+    )]
+    command_ack_ttl: u64,
+
+    /// The number of replicas to use for the KV buckets wadm creates. Existing buckets are updated
+    /// to match on startup
+    #[arg(long = "kv-replicas", env = "WADM_KV_REPLICAS", default_value = "1")]
+    kv_replicas: usize,
+
+    /// The number of historical values to keep for each key in the state bucket
+    #[arg(
+        long = "state-bucket-history",
+        env = "WADM_STATE_BUCKET_HISTORY",
+        default_value = "1"
+    )]
+    state_bucket_history: i64,
+
+    /// The number of replicas to use for the manifest bucket. Defaults to the number of replicas of
+    /// every other bucket
+    #[arg(
+        long = "manifest-bucket-replicas",
+        env = "WADM_MANIFEST_BUCKET_REPLICAS"
+    )]
+    manifest_bucket_replicas: Option<usize>,
+
+    /// The number of historical values to keep for each key in the manifest bucket
+    #[arg(
+        long = "manifest-bucket-history",
+        env = "WADM_MANIFEST_BUCKET_HISTORY",
+        default_value = "1"
+    )]
+    manifest_bucket_history: i64,
+
+    /// The amount of time in seconds to keep values in the state bucket. 0 keeps values forever
+    #[arg(
+        long = "state-bucket-ttl",
+        env = "WADM_STATE_BUCKET_TTL",
+        default_value = "0"
+    )]
+    state_bucket_ttl: u64,
+
+    /// The amount of time in seconds to keep values in the manifest bucket. 0 keeps values forever
+    #[arg(
+        long = "manifest-bucket-ttl",
+        env = "WADM_MANIFEST_BUCKET_TTL",
+        default_value = "0"
+    )]
+    manifest_bucket_ttl: u64,
+
     /// Run wadm in multitenant mode. This is for advanced multitenant use cases with segmented NATS
     /// account traffic and not simple cases where all lattices use credentials from the same
-    /// account. See the deployment guide for more information
-    #[arg(long = "multitenant", env = "WADM_MULTITENANT", hide = true)]
+    /// account. Events are read from account imported subjects (`<account>.wasmbus.evt.*.>`) and
+    /// each lattice is scoped to its account, so one wadm can serve many NATS accounts. See the
+    /// deployment guide for more information
+    #[arg(long = "multitenant", env = "WADM_MULTITENANT")]
     multitenant: bool,
 
+    /// Restrict the lattices each NATS account can use the API for, in the form
+    /// `ACCOUNT=LATTICE[,LATTICE...]`. Can be given multiple times. A lattice of `*` allows all
+    /// lattices. When given, requests from any other account are denied. The account of a request
+    /// is the multitenant account prefix or the account reported by the NATS server
+    #[arg(long = "account-lattices", value_parser = parse_account_lattices)]
+    account_lattices: Vec<(String, Vec<String>)>,
+
+    /// The most applications that can be stored in each lattice. Putting a new application into a
+    /// lattice that is full is rejected
+    #[arg(long = "max-models-per-lattice", env = "WADM_MAX_MODELS_PER_LATTICE")]
+    max_models_per_lattice: Option<usize>,
+
+    /// The most instances a version of an application can ask for, summed over all of its
+    /// components. Putting or deploying a version that asks for more is rejected
+    #[arg(long = "max-replicas-per-app", env = "WADM_MAX_REPLICAS_PER_APP")]
+    max_replicas_per_app: Option<usize>,
+
+    /// The most versions kept for each application. When a new version is put, the oldest
+    /// versions that aren't deployed are removed
+    #[arg(
+        long = "max-versions-per-app",
+        env = "WADM_MAX_VERSIONS_PER_APP",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    max_versions_per_app: Option<u64>,
+
+    /// Event types to drop before they are processed, as a comma separated list. This can be used
+    /// to cut down on high volume events in large lattices. Types can be given without the
+    /// `com.wasmcloud.lattice.` prefix (e.g. `health_check_status`)
+    #[arg(
+        long = "event-exclude",
+        env = "WADM_EVENT_EXCLUDE",
+        value_delimiter = ','
+    )]
+    event_exclude: Vec<String>,
+
+    /// Event types to sample, as a comma separated list of `TYPE=N` pairs. Only every Nth event of
+    /// each given type is processed (e.g. `host_heartbeat=3`)
+    #[arg(
+        long = "event-sample",
+        env = "WADM_EVENT_SAMPLE",
+        value_delimiter = ',',
+        value_parser = parse_event_sample
+    )]
+    event_sample: Vec<(String, u32)>,
+
+    /// Event types to drop for a specific lattice, in the form `LATTICE=TYPE[,TYPE...]`. Can be
+    /// given multiple times. Lattices given here don't use the global exclude and sample settings
+    #[arg(long = "lattice-event-exclude", value_parser = parse_lattice_event_exclude)]
+    lattice_event_exclude: Vec<(String, Vec<String>)>,
+
+    /// Where event consumers start reading events when they are created: `all` to replay every
+    /// event still in the stream, `new` to skip any backlog or an RFC 3339 time to start from. An
+    /// existing consumer with a different start position is recreated, so this can be used to skip
+    /// a large backlog after a long outage. Defaults to `all`
+    #[arg(long = "event-start", env = "WADM_EVENT_START")]
+    event_start: Option<EventStartPosition>,
+
+    /// Where the event consumer of a specific lattice starts reading events, in the form
+    /// `LATTICE=POSITION`. Can be given multiple times. Overrides `--event-start` for that lattice
+    #[arg(long = "lattice-event-start", value_parser = parse_lattice_event_start)]
+    lattice_event_start: Vec<(String, EventStartPosition)>,
+
+    /// Webhooks that applications can route their lifecycle notifications to, as a comma separated
+    /// list of `NAME=URL` pairs. Applications refer to these by name in a notification policy
+    #[arg(
+        long = "notification-webhook",
+        env = "WADM_NOTIFICATION_WEBHOOKS",
+        value_delimiter = ',',
+        value_parser = parse_notification_webhook
+    )]
+    notification_webhooks: Vec<(String, String)>,
+
+    /// A NATS subject to publish a JSON notification to whenever an application is deployed,
+    /// undeployed, rolled back or scaled, or fails to reconcile
+    #[arg(
+        long = "lifecycle-notification-subject",
+        env = "WADM_LIFECYCLE_NOTIFICATION_SUBJECT"
+    )]
+    lifecycle_notification_subject: Option<String>,
+
+    /// URLs to POST the same JSON notifications to, as a comma separated list. Notifications have a
+    /// `text` field with a summary, so chat webhooks like Slack's can be used as is
+    #[arg(
+        long = "lifecycle-notification-webhook",
+        env = "WADM_LIFECYCLE_NOTIFICATION_WEBHOOKS",
+        value_delimiter = ',',
+        value_parser = parse_lifecycle_webhook,
+        hide_env_values = true
+    )]
+    lifecycle_notification_webhooks: Vec<String>,
+
+    /// The issuer keys that components and providers must be signed by, as a comma separated list.
+    /// Images signed by any other issuer are stopped as soon as wadm sees their claims and aren't
+    /// started again, and the status of their application is set to failed. Unsigned images can't
+    /// be checked and are allowed. If not given, every issuer is allowed
+    #[arg(
+        long = "allowed-issuers",
+        env = "WADM_ALLOWED_ISSUERS",
+        value_delimiter = ','
+    )]
+    allowed_issuers: Vec<String>,
+
+    /// The issuer keys allowed in a specific lattice, in the form `LATTICE=KEY[,KEY...]`. Can be
+    /// given multiple times. Lattices given here don't use the global allowed issuers, and a
+    /// lattice given without any keys allows every issuer
+    #[arg(long = "lattice-allowed-issuers", value_parser = parse_lattice_allowed_issuers)]
+    lattice_allowed_issuers: Vec<(String, Vec<String>)>,
+
+    /// A NATS subject to send a policy request to before publishing any command that starts a
+    /// component or provider or puts a link. The request carries the command and the deployed
+    /// manifest it is for, and the reply decides whether the command is published. Commands are
+    /// denied if no decision comes back in time
+    #[arg(long = "policy-subject", env = "WADM_POLICY_SUBJECT")]
+    policy_subject: Option<String>,
+
+    /// The amount of time in seconds to wait for a decision from the policy subject
+    #[arg(
+        long = "policy-timeout",
+        env = "WADM_POLICY_TIMEOUT",
+        default_value = "2",
+        requires = "policy_subject"
+    )]
+    policy_timeout: u64,
+
+    /// A JSON file of settings that can be reloaded without restarting wadm (`max_jobs`,
+    /// `max_event_jobs`, `max_command_jobs`, `cleanup_interval_seconds`, `log_level`, `event_exclude`, `event_sample`,
+    /// `lattice_event_exclude`, `event_start` and `lattice_event_start`). Start positions only
+    /// apply to event consumers created after a reload. Settings in the file override the ones given on the command line.
+    /// The file is read again on SIGHUP or when a request is sent to the admin reload subject
+    #[arg(long = "config-file", env = "WADM_CONFIG_FILE")]
+    config_file: Option<PathBuf>,
+
+    /// A YAML or JSON file mapping friendly capability names (e.g. `httpserver`) to a contract
+    /// `namespace`, `package`, default `interfaces` and default provider `image`. Links in
+    /// manifests can then set `capability` instead of the full contract, and capability
+    /// components can use the name as their image
+    #[arg(long = "capability-aliases", env = "WADM_CAPABILITY_ALIASES")]
+    capability_aliases: Option<PathBuf>,
+
+    /// Pin the images of a manifest to the digests their tags point to when it is deployed, so
+    /// every host runs the same artifact even if a tag is pushed again. The digests are recorded
+    /// in the stored manifest
+    #[arg(long = "pin-image-digests", env = "WADM_PIN_IMAGE_DIGESTS")]
+    pin_image_digests: bool,
+
+    /// Verify that components have claims for the contracts they are linked to before a manifest
+    /// is deployed, rejecting deploys with links the components can't use. Only components the
+    /// lattice already has claims for are checked
+    #[arg(long = "verify-claims", env = "WADM_VERIFY_CLAIMS")]
+    verify_claims: bool,
+
+    /// Deploy manifests that fail claims verification anyway, returning a warning instead of an
+    /// error
+    #[arg(
+        long = "permissive",
+        env = "WADM_PERMISSIVE",
+        requires = "verify_claims"
+    )]
+    permissive: bool,
+
+    /// A Docker style auth config (like `~/.docker/config.json`) with the credentials to use when
+    /// resolving image digests or pulling manifest artifacts
+    #[arg(long = "registry-auth-file", env = "WADM_REGISTRY_AUTH_FILE")]
+    registry_auth_file: Option<PathBuf>,
+
+    /// Registries to access over plain HTTP when resolving image digests or pulling manifest
+    /// artifacts, as a comma separated list (e.g. `localhost:5000`)
+    #[arg(
+        long = "insecure-registries",
+        env = "WADM_INSECURE_REGISTRIES",
+        value_delimiter = ','
+    )]
+    insecure_registries: Vec<String>,
+
+    /// Credentials for private registries, as a comma separated list of
+    /// `REGISTRY=USERNAME:PASSWORD`. Before a component or provider is started from one of these
+    /// registries, wadm puts the credentials into the lattice so hosts don't need to be configured
+    /// with them. These are also used when resolving image digests
+    #[arg(
+        long = "registry-credential",
+        env = "WADM_REGISTRY_CREDENTIALS",
+        value_delimiter = ',',
+        value_parser = registry::parse_registry_credential,
+        hide_env_values = true
+    )]
+    registry_credentials: Vec<(String, wasmcloud_control_interface::RegistryCredential)>,
+
+    /// A JSON file mapping registries to their credentials, as a `username` and `password` or a
+    /// `token`. Credentials given with `--registry-credential` take precedence
+    #[arg(
+        long = "registry-credentials-file",
+        env = "WADM_REGISTRY_CREDENTIALS_FILE"
+    )]
+    registry_credentials_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    secrets: secrets::SecretArgs,
+
+    #[cfg(feature = "gitops")]
+    #[command(flatten)]
+    gitops: gitops::GitOpsArgs,
+
+    #[cfg(feature = "http-gateway")]
+    #[command(flatten)]
+    http_gateway: http_gateway::HttpGatewayArgs,
+
+    /// The number of replicas to use for the streams wadm creates. Existing streams are updated to
+    /// match on startup
+    #[arg(
+        long = "stream-replicas",
+        env = "WADM_STREAM_REPLICAS",
+        default_value = "1"
+    )]
+    stream_replicas: usize,
+
+    /// Maximum bytes to keep for each stream wadm creates, unless set for a specific stream. -1
+    /// means unlimited. Existing streams are updated to match on startup
+    #[arg(
+        long = "stream-max-bytes",
+        env = "WADM_STREAM_MAX_BYTES",
+        default_value_t = -1,
+        allow_negative_numbers = true
+    )]
+    stream_max_bytes: i64,
+
+    /// Maximum number of messages to keep for each stream wadm creates. -1 means unlimited.
+    /// Existing streams are updated to match on startup
+    #[arg(
+        long = "stream-max-msgs",
+        env = "WADM_STREAM_MAX_MSGS",
+        default_value_t = -1,
+        allow_negative_numbers = true
+    )]
+    stream_max_msgs: i64,
+
+    /// Existing streams that already capture the wasmbus events. When set, the wasmbus event stream
+    /// sources the events from these streams instead of capturing the subjects itself, which would
+    /// overlap with them
+    #[arg(
+        long = "event-stream-source",
+        env = "WADM_EVENT_STREAM_SOURCE",
+        value_delimiter = ','
+    )]
+    event_stream_sources: Vec<String>,
+
+    /// Create the wasmbus event stream as a mirror of the single stream given with
+    /// `--event-stream-source`, rather than sourcing from it
+    #[arg(
+        long = "event-stream-mirror",
+        env = "WADM_EVENT_STREAM_MIRROR",
+        requires = "event_stream_sources"
+    )]
+    event_stream_mirror: bool,
+
     //
     // Max bytes configuration for streams. Primarily configurable to enable deployment on NATS infra
     // with limited resources.
@@ -190,6 +969,46 @@ struct Args {
         hide = true
     )]
     max_command_stream_bytes: i64,
+    /// Maximum bytes to keep for the dead letter stream
+    #[arg(
+        long = "dead-letter-stream-max-bytes",
+        env = "WADM_DEAD_LETTER_STREAM_MAX_BYTES",
+        default_value_t = -1,
+        hide = true
+    )]
+    max_dead_letter_stream_bytes: i64,
+    /// Maximum bytes to keep for the quarantine stream
+    #[arg(
+        long = "quarantine-stream-max-bytes",
+        env = "WADM_QUARANTINE_STREAM_MAX_BYTES",
+        default_value_t = -1,
+        hide = true
+    )]
+    max_quarantine_stream_bytes: i64,
+    /// Maximum bytes to keep for the state log stream
+    #[arg(
+        long = "state-log-stream-max-bytes",
+        env = "WADM_STATE_LOG_STREAM_MAX_BYTES",
+        default_value_t = -1,
+        hide = true
+    )]
+    max_state_log_stream_bytes: i64,
+    /// Maximum bytes to keep for the audit stream
+    #[arg(
+        long = "audit-stream-max-bytes",
+        env = "WADM_AUDIT_STREAM_MAX_BYTES",
+        default_value_t = -1,
+        hide = true
+    )]
+    max_audit_stream_bytes: i64,
+    /// Maximum bytes to keep for the reconcile report stream
+    #[arg(
+        long = "reconcile-report-stream-max-bytes",
+        env = "WADM_RECONCILE_REPORT_STREAM_MAX_BYTES",
+        default_value_t = -1,
+        hide = true
+    )]
+    max_reconcile_report_stream_bytes: i64,
     /// Maximum bytes to keep for the event stream
     #[arg(
         long = "event-stream-max-bytes",
@@ -214,6 +1033,15 @@ struct Args {
         hide = true
     )]
     max_status_stream_bytes: i64,
+    /// Number of statuses to keep for each application. Older statuses are used to look up what an
+    /// application looked like at a point in time. This only applies when the status stream is
+    /// first created
+    #[arg(
+        long = "status-stream-history",
+        env = "WADM_STATUS_STREAM_HISTORY",
+        default_value_t = 10
+    )]
+    status_stream_history: i64,
     /// Maximum bytes to keep for the notify stream
     #[arg(
         long = "notify-stream-max-bytes",
@@ -230,17 +1058,72 @@ struct Args {
         hide = true
     )]
     max_wasmbus_event_stream_bytes: i64,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Rebuilds the state of a lattice from the resources its hosts are running, then exits. Use
+    /// this when upgrading from a wadm version that didn't store state or after losing the state
+    /// bucket
+    MigrateState(migrate::MigrateStateArgs),
+    /// Removes the consumers, streams and KV buckets wadm created for a lattice, or for every
+    /// lattice with `--all`, then exits. Use this when decommissioning wadm to leave the NATS
+    /// cluster clean
+    Uninstall(uninstall::UninstallArgs),
+    /// Writes the JSON Schemas of the manifest and every API request and response, then exits
+    #[command(hide = true)]
+    ExportSchemas(export_schemas::ExportSchemasArgs),
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let started = std::time::Instant::now();
     let args = Args::parse();
 
-    logging::configure_tracing(
+    // NOTE: This doesn't need NATS or any of the other setup, so it runs before all of it
+    if let Some(Command::ExportSchemas(export_args)) = args.command.as_ref() {
+        return export_schemas::export_schemas(export_args).await;
+    }
+
+    let log_filter = logging::configure_tracing(
         args.structured_logging,
         args.tracing_enabled,
-        args.tracing_endpoint,
+        args.tracing_endpoint.clone(),
     );
+    // NOTE: This has to happen before anything creates instruments, otherwise they won't be
+    // exported
+    let meter_provider = metrics::configure_metrics(
+        &args.metrics_exporters,
+        args.tracing_endpoint.as_deref(),
+        args.prometheus_address,
+        Duration::from_secs(args.metrics_interval),
+    )
+    .await?;
+
+    let reloader = reload::FileReloader {
+        base: RuntimeConfig {
+            max_jobs: args.max_jobs,
+            max_event_jobs: args.max_event_jobs,
+            max_command_jobs: args.max_command_jobs,
+            cleanup_interval_seconds: Some(args.cleanup_interval),
+            log_level: None,
+            event_exclude: args.event_exclude.clone(),
+            event_sample: args.event_sample.iter().cloned().collect(),
+            lattice_event_exclude: args.lattice_event_exclude.iter().cloned().collect(),
+            event_start: args.event_start,
+            lattice_event_start: args.lattice_event_start.iter().cloned().collect(),
+        },
+        path: args.config_file.clone(),
+        shared: SharedConfig::default(),
+    };
+    let initial_config = reloader.load().await?;
+    reloader.shared.update(initial_config.clone());
+    if initial_config.log_level.is_some() {
+        logging::set_log_filter(&log_filter, initial_config.log_level.as_deref());
+    }
 
     // Build storage adapter for lattice state (on by default)
     let (client, context) = nats::get_client_and_context(
@@ -253,23 +1136,8 @@ async fn main() -> anyhow::Result<()> {
     )
     .await?;
 
-    // TODO: We will probably need to set up all the flags (like lattice prefix and topic prefix) down the line
-    let connection_pool = ControlClientConstructor::new(client.clone(), None);
-
     let trimmer: &[_] = &['.', '>', '*'];
-
-    let store =
-        nats::ensure_kv_bucket(&context, args.state_bucket, 1, args.max_state_bucket_bytes).await?;
-
-    let state_storage = NatsKvStore::new(store);
-
-    let manifest_storage = nats::ensure_kv_bucket(
-        &context,
-        args.manifest_bucket,
-        1,
-        args.max_manifest_bucket_bytes,
-    )
-    .await?;
+    let command_topic_prefix = args.command_topic_prefix.trim_matches(trimmer);
 
     let internal_stream_name = |stream_name: &str| -> String {
         match args.stream_prefix.clone() {
@@ -284,6 +1152,175 @@ async fn main() -> anyhow::Result<()> {
         }
     };
 
+    if let Some(Command::Uninstall(uninstall_args)) = args.command.as_ref() {
+        let resources = uninstall::WadmResources {
+            event_stream: internal_stream_name(WADM_EVENT_STREAM_NAME),
+            command_stream: internal_stream_name(COMMAND_STREAM_NAME),
+            dead_letter_stream: internal_stream_name(DEAD_LETTER_STREAM_NAME),
+            quarantine_stream: internal_stream_name(QUARANTINE_STREAM_NAME),
+            state_log_stream: internal_stream_name(STATE_LOG_STREAM_NAME),
+            audit_stream: internal_stream_name(AUDIT_STREAM_NAME),
+            status_stream: internal_stream_name(STATUS_STREAM_NAME),
+            reconcile_report_stream: internal_stream_name(RECONCILE_REPORT_STREAM_NAME),
+            notify_stream: NOTIFY_STREAM_NAME.to_owned(),
+            event_consumer_stream: WADM_EVENT_CONSUMER_STREAM_NAME.to_owned(),
+            wasmbus_event_stream: WASMBUS_EVENT_STREAM_NAME.to_owned(),
+            command_topic_prefix: command_topic_prefix.to_owned(),
+            state_bucket: args.state_bucket.clone(),
+            manifest_bucket: args.manifest_bucket.clone(),
+            schema_bucket: args.schema_bucket.clone(),
+            command_ack_bucket: args.command_ack_bucket.clone(),
+            lease_bucket: args.leader_lease_bucket.clone(),
+            optional_buckets: args
+                .shutdown_report_bucket
+                .iter()
+                .chain(args.crash_report_bucket.iter())
+                .cloned()
+                .collect(),
+        };
+        return uninstall::uninstall(
+            &client,
+            &context,
+            &args.api_prefix,
+            &resources,
+            uninstall_args,
+        )
+        .await;
+    }
+
+    let crash_reporter: Option<Arc<dyn CrashReporter + Send + Sync>> = match (
+        args.crash_report_dir.clone(),
+        args.crash_report_bucket.clone(),
+    ) {
+        (Some(dir), _) => Some(Arc::new(FileCrashReporter::new(dir))),
+        (None, Some(bucket)) => {
+            let store = nats::ensure_kv_bucket(
+                &context,
+                bucket,
+                &KvBucketSettings {
+                    replicas: args.kv_replicas,
+                    ..Default::default()
+                },
+            )
+            .await?;
+            Some(Arc::new(KvCrashReporter::new(store)))
+        }
+        (None, None) => None,
+    };
+    crash::install(crash_reporter);
+
+    // NOTE: Every control interface client comes from this pool, so the workers, scalers and API
+    // handlers of a lattice all share the same client
+    let mut connection_pool = ControlClientPool::new(client.clone(), args.ctl_topic_prefix.clone());
+    if let Some(timeout) = args.ctl_timeout {
+        connection_pool = connection_pool.with_timeout(Duration::from_secs(timeout));
+    }
+    if let Some(timeout) = args.ctl_auction_timeout {
+        connection_pool = connection_pool.with_auction_timeout(Duration::from_secs(timeout));
+    }
+    if let Some(path) = args.lattice_connections_file.as_deref() {
+        for (lattice_id, connection) in LatticeConnection::from_file(path).await? {
+            let lattice_client = match connection.creds_file.clone() {
+                Some(creds) => Some(
+                    nats::get_client_and_context(
+                        args.nats_server.clone(),
+                        None,
+                        None,
+                        None,
+                        Some(creds),
+                        args.nats_tls_ca_file.clone(),
+                    )
+                    .await
+                    .with_context(|| format!("Unable to connect to NATS for lattice {lattice_id}"))?
+                    .0,
+                ),
+                None => None,
+            };
+            connection_pool = connection_pool.with_lattice(lattice_id, connection, lattice_client);
+        }
+    }
+
+    let store = nats::ensure_kv_bucket(
+        &context,
+        args.state_bucket,
+        &KvBucketSettings {
+            replicas: args.kv_replicas,
+            history: args.state_bucket_history,
+            max_age: Duration::from_secs(args.state_bucket_ttl),
+            max_bytes: args.max_state_bucket_bytes,
+        },
+    )
+    .await?;
+
+    let state_storage = NatsKvStore::new(store);
+
+    if let Some(Command::MigrateState(migrate_args)) = args.command.as_ref() {
+        let ctl_client = connection_pool.get_connection(
+            &migrate_args.lattice,
+            migrate_args.multitenant_prefix.as_deref(),
+        );
+        return migrate::migrate_state(&state_storage, &ctl_client, migrate_args).await;
+    }
+
+    let manifest_storage = nats::ensure_kv_bucket(
+        &context,
+        args.manifest_bucket,
+        &KvBucketSettings {
+            replicas: args.manifest_bucket_replicas.unwrap_or(args.kv_replicas),
+            history: args.manifest_bucket_history,
+            max_age: Duration::from_secs(args.manifest_bucket_ttl),
+            max_bytes: args.max_manifest_bucket_bytes,
+        },
+    )
+    .await?;
+    if let Some(from) = args
+        .manifest_bucket_migrate_from
+        .as_deref()
+        .filter(|from| *from != manifest_storage.name)
+    {
+        let copied = nats::copy_kv_bucket(&context, from, &manifest_storage).await?;
+        info!(%from, %copied, "Copied manifests from previous manifest bucket");
+    }
+
+    let schema_storage = nats::ensure_kv_bucket(
+        &context,
+        args.schema_bucket,
+        &KvBucketSettings {
+            replicas: args.kv_replicas,
+            ..Default::default()
+        },
+    )
+    .await?;
+    if let Err(e) = schemas::publish_schemas(&schema_storage).await {
+        warn!(error = ?e, "Unable to publish command and event schemas");
+    }
+
+    let command_ack_ttl = Duration::from_secs(args.command_ack_ttl);
+    let command_acks = CommandAckStore::new(
+        nats::ensure_kv_bucket(
+            &context,
+            args.command_ack_bucket,
+            &KvBucketSettings {
+                replicas: args.kv_replicas,
+                max_age: command_ack_ttl,
+                ..Default::default()
+            },
+        )
+        .await?,
+    )
+    .with_ttl(command_ack_ttl);
+
+    // The per stream max bytes take precedence over the max bytes for all streams
+    let stream_settings = |max_bytes: i64| StreamSettings {
+        replicas: args.stream_replicas,
+        max_bytes: if max_bytes == -1 {
+            args.stream_max_bytes
+        } else {
+            max_bytes
+        },
+        max_messages: args.stream_max_msgs,
+    };
+
     debug!("Ensuring wadm event stream");
 
     let event_stream = nats::ensure_limits_stream(
@@ -294,7 +1331,7 @@ async fn main() -> anyhow::Result<()> {
             "A stream that stores all events coming in on the wadm.evt subject in a cluster"
                 .to_string(),
         ),
-        args.max_event_stream_bytes,
+        &stream_settings(args.max_event_stream_bytes),
     )
     .await?;
 
@@ -303,9 +1340,73 @@ async fn main() -> anyhow::Result<()> {
     let command_stream = nats::ensure_stream(
         &context,
         internal_stream_name(COMMAND_STREAM_NAME),
-        vec![DEFAULT_COMMANDS_TOPIC.to_owned()],
+        vec![command_subjects(command_topic_prefix, args.multitenant)],
         Some("A stream that stores all commands for wadm".to_string()),
-        args.max_command_stream_bytes,
+        &stream_settings(args.max_command_stream_bytes),
+    )
+    .await?;
+
+    debug!("Ensuring dead letter stream");
+
+    let dead_letter_stream = nats::ensure_dead_letter_stream(
+        &context,
+        internal_stream_name(DEAD_LETTER_STREAM_NAME),
+        vec![DEFAULT_DEAD_LETTER_TOPIC.to_owned()],
+        &stream_settings(args.max_dead_letter_stream_bytes),
+    )
+    .await?;
+
+    debug!("Ensuring quarantine stream");
+
+    let quarantine_stream = nats::ensure_quarantine_stream(
+        &context,
+        internal_stream_name(QUARANTINE_STREAM_NAME),
+        vec![DEFAULT_QUARANTINE_TOPIC.to_owned()],
+        &stream_settings(args.max_quarantine_stream_bytes),
+    )
+    .await?;
+
+    let state_log_stream = if args.state_log {
+        debug!("Ensuring state log stream");
+        Some(
+            nats::ensure_state_log_stream(
+                &context,
+                internal_stream_name(STATE_LOG_STREAM_NAME),
+                vec![DEFAULT_STATE_LOG_TOPIC.to_owned()],
+                &stream_settings(args.max_state_log_stream_bytes),
+            )
+            .await?,
+        )
+    } else {
+        None
+    };
+
+    // NOTE: Everything that changes lattice state goes through this store, so every change is
+    // logged when the state log is enabled
+    let state_storage = match state_log_stream.clone() {
+        Some(stream) => {
+            let state_storage = LoggedStore::new(state_storage).with_log(StreamStateLog::new(
+                context.clone(),
+                stream,
+                DEFAULT_STATE_LOG_TOPIC.trim_matches(trimmer),
+            ));
+            tokio::spawn(
+                state_storage
+                    .clone()
+                    .compact_every(Duration::from_secs(args.state_log_compaction_interval)),
+            );
+            state_storage
+        }
+        None => LoggedStore::new(state_storage),
+    };
+
+    debug!("Ensuring audit stream");
+
+    let audit_stream = nats::ensure_audit_stream(
+        &context,
+        internal_stream_name(AUDIT_STREAM_NAME),
+        vec![DEFAULT_AUDIT_TOPIC.to_owned()],
+        &stream_settings(args.max_audit_stream_bytes),
     )
     .await?;
 
@@ -313,7 +1414,16 @@ async fn main() -> anyhow::Result<()> {
         &context,
         internal_stream_name(STATUS_STREAM_NAME),
         vec![DEFAULT_STATUS_TOPIC.to_owned()],
-        args.max_status_stream_bytes,
+        &stream_settings(args.max_status_stream_bytes),
+        args.status_stream_history,
+    )
+    .await?;
+
+    let reconcile_report_stream = nats::ensure_reconcile_report_stream(
+        &context,
+        internal_stream_name(RECONCILE_REPORT_STREAM_NAME),
+        vec![DEFAULT_RECONCILE_REPORT_TOPIC.to_owned()],
+        &stream_settings(args.max_reconcile_report_stream_bytes),
     )
     .await?;
 
@@ -329,22 +1439,35 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
-    let wasmbus_event_subjects = match args.multitenant {
-        true => vec![DEFAULT_MULTITENANT_EVENTS_TOPIC.to_owned()],
-        false => vec![DEFAULT_EVENTS_TOPIC.to_owned()],
-    };
+    let wasmbus_event_subjects = vec![event_subjects(&args.event_topic_prefix, args.multitenant)];
 
-    let wasmbus_event_stream = nats::ensure_limits_stream(
-        &context,
-        WASMBUS_EVENT_STREAM_NAME.to_string(),
-        wasmbus_event_subjects.clone(),
-        Some(
-            "A stream that stores all events coming in on the wasmbus.evt subject in a cluster"
-                .to_string(),
-        ),
-        args.max_wasmbus_event_stream_bytes,
-    )
-    .await?;
+    let wasmbus_event_stream = if args.event_stream_sources.is_empty() {
+        nats::ensure_limits_stream(
+            &context,
+            WASMBUS_EVENT_STREAM_NAME.to_string(),
+            wasmbus_event_subjects.clone(),
+            Some(
+                "A stream that stores all events coming in on the wasmbus.evt subject in a cluster"
+                    .to_string(),
+            ),
+            &stream_settings(args.max_wasmbus_event_stream_bytes),
+        )
+        .await?
+    } else {
+        nats::ensure_sourced_stream(
+            &context,
+            WASMBUS_EVENT_STREAM_NAME.to_string(),
+            wasmbus_event_subjects[0].clone(),
+            &args.event_stream_sources,
+            args.event_stream_mirror,
+            Some(
+                "A stream that stores all wasmbus events sourced from the streams that capture them"
+                    .to_string(),
+            ),
+            &stream_settings(args.max_wasmbus_event_stream_bytes),
+        )
+        .await?
+    };
 
     debug!("Ensuring notify stream");
 
@@ -352,7 +1475,7 @@ async fn main() -> anyhow::Result<()> {
         &context,
         NOTIFY_STREAM_NAME.to_owned(),
         vec![format!("{WADM_NOTIFY_PREFIX}.*")],
-        args.max_notify_stream_bytes,
+        &stream_settings(args.max_notify_stream_bytes),
     )
     .await?;
 
@@ -367,44 +1490,176 @@ async fn main() -> anyhow::Result<()> {
             "A stream that sources from wadm_events and wasmbus_events for wadm event consumer's use"
                 .to_string(),
         ),
-        args.max_event_consumer_stream_bytes,
+        &stream_settings(args.max_event_consumer_stream_bytes),
     )
     .await?;
 
+    let health = match args.health_address {
+        Some(address) => health::serve_health(
+            address,
+            health::ReadinessChecker {
+                client: client.clone(),
+                context: context.clone(),
+                streams: [
+                    &event_stream,
+                    &command_stream,
+                    &dead_letter_stream,
+                    &quarantine_stream,
+                    &audit_stream,
+                    &status_stream,
+                    &reconcile_report_stream,
+                    &wasmbus_event_stream,
+                    &notify_stream,
+                    &event_consumer_stream,
+                ]
+                .into_iter()
+                .chain(state_log_stream.as_ref())
+                .map(|stream| stream.cached_info().config.name.clone())
+                .collect(),
+                threshold: args.readiness_threshold,
+            },
+        )
+        .boxed(),
+        None => futures::future::pending().boxed(),
+    };
+
     debug!("Creating event consumer manager");
 
-    let permit_pool = Arc::new(Semaphore::new(
-        args.max_jobs.unwrap_or(Semaphore::MAX_PERMITS),
+    // NOTE: Events and commands have separate pools so a storm of events can't starve commands
+    let event_permits = Arc::new(Semaphore::new(
+        initial_config
+            .event_jobs()
+            .unwrap_or(Semaphore::MAX_PERMITS),
+    ));
+    let command_permits = Arc::new(Semaphore::new(
+        initial_config
+            .command_jobs()
+            .unwrap_or(Semaphore::MAX_PERMITS),
     ));
+    // NOTE: The circuit breaker is shared by all commands and scalers so that a host that stops
+    // responding to commands is also avoided when placing work
+    let circuit_breaker = HostCircuitBreaker::new(
+        args.ctl_failure_threshold,
+        Duration::from_secs(args.ctl_circuit_cooldown),
+    );
+    let secret_resolver = secrets::secret_resolver(&context, &args.secrets).await?;
+    let mut lifecycle = LifecycleNotifier::new();
+    if let Some(subject) = args.lifecycle_notification_subject.as_deref() {
+        lifecycle = lifecycle.with_sink(SubjectSink::new(client.clone(), subject));
+    }
+    for url in args.lifecycle_notification_webhooks.iter() {
+        lifecycle = lifecycle.with_sink(webhooks::HttpNotificationSink::new(url));
+    }
+    let issuers = args.lattice_allowed_issuers.iter().fold(
+        IssuerPolicy::new(args.allowed_issuers.iter().cloned()),
+        |policy, (lattice_id, allowed)| policy.with_lattice(lattice_id.clone(), allowed.clone()),
+    );
+    let quarantine = Quarantine::new(
+        context.clone(),
+        DEFAULT_QUARANTINE_TOPIC.trim_matches(trimmer),
+    )
+    .with_max_deliveries(args.quarantine_max_deliveries);
+    // NOTE: A burst of events for the same host only sends one inventory request to it
+    let inventory_cache = InventoryCache::new();
     let event_worker_creator = EventWorkerCreator {
         state_store: state_storage.clone(),
         manifest_store: manifest_storage.clone(),
         pool: connection_pool.clone(),
-        command_topic_prefix: DEFAULT_COMMANDS_TOPIC.trim_matches(trimmer).to_owned(),
+        inventory_cache: inventory_cache.clone(),
+        command_topic_prefix: command_topic_prefix.to_owned(),
+        command_validity: Duration::from_secs(args.command_validity),
+        command_encoding: args.command_encoding,
         publisher: context.clone(),
         notify_stream,
         status_stream: status_stream.clone(),
+        circuit_breaker: circuit_breaker.clone(),
+        secret_resolver,
+        placement: args.placement,
+        scaler_views: admin::ScalerViews::default(),
+        lifecycle: lifecycle.clone(),
+        issuers,
+        policy: args.policy_subject.as_deref().map(|subject| {
+            NatsPolicyEngine::new(client.clone(), subject)
+                .with_timeout(Duration::from_secs(args.policy_timeout))
+        }),
+        quarantine: quarantine.clone(),
+    };
+    let scaler_views = event_worker_creator.scaler_views.clone();
+    // NOTE: With leader election or sharding, consumers are only started once this process becomes
+    // responsible for a lattice, so existing consumers aren't picked up on startup
+    let coordinated = args.leader_election || args.sharding;
+    let event_options = EventConsumerOptions {
+        config: reloader.shared.clone(),
+        quarantine: Some(quarantine.clone()),
+    };
+    let events_manager: ConsumerManager<EventConsumer> = if coordinated {
+        ConsumerManager::new_empty(event_permits.clone(), event_consumer_stream, event_options)
+    } else {
+        ConsumerManager::new_with_options(
+            event_permits.clone(),
+            event_consumer_stream,
+            event_worker_creator.clone(),
+            args.multitenant,
+            event_options,
+        )
+        .await
     };
-    let events_manager: ConsumerManager<EventConsumer> = ConsumerManager::new(
-        permit_pool.clone(),
-        event_consumer_stream,
-        event_worker_creator.clone(),
-        args.multitenant,
-    )
-    .await;
 
     debug!("Creating command consumer manager");
 
+    let registry_credentials = registry::load_registry_credentials(
+        args.registry_credentials_file.as_deref(),
+        &args.registry_credentials,
+    )
+    .await?;
+    let retry_policy = CommandRetryPolicy {
+        max_attempts: args.command_max_attempts,
+        base_delay: Duration::from_millis(args.command_retry_delay),
+        max_delay: Duration::from_millis(args.command_retry_max_delay),
+    };
+    let rate_limit = |per_second| RateLimit {
+        per_second,
+        burst: args.control_rate_burst,
+    };
+    let mut rate_limiter = ControlRateLimiter::new();
+    if let Some(limit) = args.control_rate_limit {
+        rate_limiter = rate_limiter.with_global_limit(rate_limit(limit));
+    }
+    if let Some(limit) = args.lattice_control_rate_limit {
+        rate_limiter = rate_limiter.with_lattice_limit(rate_limit(limit));
+    }
     let command_worker_creator = CommandWorkerCreator {
-        pool: connection_pool,
+        pool: connection_pool.clone(),
+        circuit_breaker,
+        retry_policy,
+        registry_credentials: registry_credentials.clone(),
+        dead_letter: DeadLetterQueue::new(
+            context.clone(),
+            DEFAULT_DEAD_LETTER_TOPIC.trim_matches(trimmer),
+        ),
+        acks: command_acks.clone(),
+        rate_limiter,
+    };
+    let command_options = CommandConsumerOptions {
+        // NOTE: The consumer needs to deliver the command at least as many times as we attempt
+        // it, otherwise it would be dropped before it could be dead lettered
+        max_deliver: args
+            .command_max_attempts
+            .max(DEFAULT_COMMAND_MAX_DELIVER as u64) as i64,
+        quarantine: Some(quarantine),
+    };
+    let commands_manager: ConsumerManager<CommandConsumer> = if coordinated {
+        ConsumerManager::new_empty(command_permits.clone(), command_stream, command_options)
+    } else {
+        ConsumerManager::new_with_options(
+            command_permits.clone(),
+            command_stream,
+            command_worker_creator.clone(),
+            args.multitenant,
+            command_options,
+        )
+        .await
     };
-    let commands_manager: ConsumerManager<CommandConsumer> = ConsumerManager::new(
-        permit_pool.clone(),
-        command_stream,
-        command_worker_creator.clone(),
-        args.multitenant,
-    )
-    .await;
 
     // TODO(thomastaylor312): We might want to figure out how not to run this globally. Doing a
     // synthetic event sent to the stream could be nice, but all the wadm processes would still fire
@@ -413,55 +1668,473 @@ async fn main() -> anyhow::Result<()> {
     // scenario right now is that multiple fire simultaneously and a few of them just delete nothing
     let reaper = Reaper::new(
         state_storage.clone(),
-        Duration::from_secs(args.cleanup_interval / 2),
+        initial_config
+            .cleanup_interval()
+            .unwrap_or(Duration::from_secs(args.cleanup_interval))
+            / 2,
         [],
     );
+    let reaper = match args.max_missed_heartbeats {
+        Some(missed) => reaper
+            .with_heartbeat_eviction(Duration::from_secs(args.host_heartbeat_interval), missed),
+        None => reaper,
+    };
+    // Reaped hosts are dropped from the inventory cache so it doesn't keep hosts that are gone
+    let reaper = reaper.with_inventory_cache(inventory_cache.clone());
 
     let wadm_event_prefix = DEFAULT_WADM_EVENTS_TOPIC.trim_matches(trimmer);
 
     debug!("Creating lattice observer");
 
+    let shutdown_managers = (events_manager.clone(), commands_manager.clone());
+    let check_interval = Duration::from_secs(args.consumer_check_interval);
+    tokio::spawn({
+        let manager = events_manager.clone();
+        let creator = event_worker_creator.clone();
+        async move { manager.monitor(creator, check_interval).await }
+    });
+    tokio::spawn({
+        let manager = commands_manager.clone();
+        let creator = command_worker_creator.clone();
+        async move { manager.monitor(creator, check_interval).await }
+    });
+    match (args.telemetry, args.telemetry_endpoint.clone()) {
+        (true, Some(endpoint)) => {
+            info!(%endpoint, "Anonymous usage telemetry is enabled");
+            tokio::spawn(telemetry::report_usage(
+                endpoint,
+                Duration::from_secs(args.telemetry_interval),
+                events_manager.clone(),
+                manifest_storage.clone(),
+            ));
+        }
+        (false, Some(_)) => {
+            warn!("A telemetry endpoint was set without --telemetry, so no telemetry will be sent")
+        }
+        _ => {}
+    }
+    let introspector = admin::ProcessIntrospector {
+        events: events_manager.clone(),
+        commands: commands_manager.clone(),
+        scalers: scaler_views,
+        event_permits: event_permits.clone(),
+        command_permits: command_permits.clone(),
+        config: reloader.shared.clone(),
+        api_prefix: args.api_prefix.clone(),
+        multitenant: args.multitenant,
+        started,
+    };
+    let process_id = args
+        .host_id
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let leases = if args.leader_election {
+        let store = nats::ensure_kv_bucket(
+            &context,
+            args.leader_lease_bucket.clone(),
+            &KvBucketSettings {
+                replicas: args.kv_replicas,
+                max_age: Duration::from_secs(args.leader_lease_ttl),
+                ..Default::default()
+            },
+        )
+        .await?;
+        info!(owner_id = %process_id, "Leader election enabled");
+        Some(LatticeLeases::new(store, process_id.clone()))
+    } else {
+        None
+    };
+    let membership = args.sharding.then(|| {
+        info!(member_id = %process_id, "Sharding enabled");
+        ShardMembership::new(
+            client.clone(),
+            DEFAULT_MEMBERSHIP_TOPIC,
+            process_id.clone(),
+            Duration::from_secs(args.shard_member_ttl),
+        )
+    });
+    let (lattice_activity, hibernation) = match args.hibernate_after {
+        Some(secs) => {
+            let (activity, wake) = LatticeActivity::new();
+            (
+                Some(activity.clone()),
+                Some(observer::Hibernation {
+                    activity,
+                    wake,
+                    idle_for: Duration::from_secs(secs),
+                }),
+            )
+        }
+        None => (None, None),
+    };
     let observer = observer::Observer {
-        parser: LatticeIdParser::new("wasmbus", args.multitenant),
+        parser: LatticeIdParser::new(&args.event_topic_prefix, args.multitenant),
+        command_topic_prefix: command_topic_prefix.to_owned(),
         command_manager: commands_manager,
         event_manager: events_manager,
         reaper,
+        config: reloader.shared.subscribe(),
         client: client.clone(),
         command_worker_creator,
         event_worker_creator,
+        hibernation,
+        leadership: leases.clone().map(|leases| observer::Leadership {
+            leases,
+            lease_ttl: Duration::from_secs(args.leader_lease_ttl),
+        }),
+        sharding: membership.clone().map(|membership| {
+            observer::Sharding::new(membership, Duration::from_secs(args.shard_member_ttl / 3))
+        }),
     };
 
     debug!("Subscribing to API topic");
 
+    let flush_client = client.clone();
+    // Applications can route their own notifications to subjects outside of the wadm streams, so
+    // those are sent with core NATS
+    let notifier = ManifestNotifier::new(wadm_event_prefix, context.clone())
+        .with_route_publisher(client.clone())
+        .with_webhooks(webhooks::HttpWebhooks::new(
+            args.notification_webhooks.clone(),
+        ))
+        .with_lifecycle_notifier(lifecycle);
+    let manifest_sync = match args.manifest_dir.clone() {
+        Some(dir) => {
+            info!(dir = %dir.display(), lattice = %args.manifest_dir_lattice, "Syncing manifests from directory");
+            manifest_source::sync_manifests(
+                manifest_source::DirectorySource::new(dir),
+                wadm_client::Client::from_nats_client(
+                    &args.manifest_dir_lattice,
+                    Some(&args.api_prefix),
+                    client.clone(),
+                ),
+                Duration::from_secs(args.manifest_dir_interval),
+            )
+            .boxed()
+        }
+        None => futures::future::pending().boxed(),
+    };
+    #[cfg(feature = "gitops")]
+    let manifest_sync = {
+        let registry = registry::RegistryDigestResolver::new(
+            args.registry_auth_file.as_deref(),
+            args.insecure_registries.clone(),
+        )
+        .await?
+        .with_registry_credentials(&registry_credentials);
+        match args.gitops.fetcher(registry) {
+            Some(fetcher) => {
+                info!("Syncing manifests from GitOps source");
+                gitops::sync_gitops(
+                    fetcher,
+                    &args.gitops,
+                    client.clone(),
+                    args.api_prefix.clone(),
+                )
+                .boxed()
+            }
+            None => manifest_sync,
+        }
+    };
+    #[cfg(feature = "http-gateway")]
+    let http_api = match args.http_gateway.http_addr {
+        Some(address) => http_gateway::serve_http_gateway(
+            address,
+            client.clone(),
+            args.api_prefix.clone(),
+            args.http_gateway.authenticator()?,
+        )
+        .boxed(),
+        None => futures::future::pending().boxed(),
+    };
+    #[cfg(not(feature = "http-gateway"))]
+    let http_api = futures::future::pending::<anyhow::Result<()>>();
     let server = Server::new(
         manifest_storage,
         client,
         Some(&args.api_prefix),
         args.multitenant,
         status_stream,
-        ManifestNotifier::new(wadm_event_prefix, context),
+        notifier,
     )
-    .await?;
+    .await?
+    .with_command_planner(ScalerPlanner::new(
+        state_storage.clone(),
+        connection_pool.clone(),
+    ))
+    .with_topology_source(StateTopology::new(state_storage, connection_pool.clone()))
+    .with_host_cordoner(LabelCordoner::new(connection_pool.clone()))
+    .with_dead_letter_source(StreamDeadLetters::new(
+        context.clone(),
+        dead_letter_stream,
+        DEFAULT_DEAD_LETTER_TOPIC.trim_matches(trimmer),
+        command_topic_prefix,
+    ))
+    .with_audit_log(StreamAuditLog::new(
+        context.clone(),
+        audit_stream,
+        DEFAULT_AUDIT_TOPIC.trim_matches(trimmer),
+    ))
+    .with_reconcile_reports(reconcile_report_stream)
+    .with_command_acks(command_acks);
+    let server = server
+        .with_undeploy_stage_delay(Duration::from_secs(args.undeploy_stage_delay))
+        .with_quotas(ManifestQuotas {
+            max_models: args.max_models_per_lattice,
+            max_replicas: args.max_replicas_per_app,
+            max_versions: args.max_versions_per_app.map(|max| max as usize),
+        });
+    let server = if args.account_lattices.is_empty() {
+        server
+    } else {
+        server.with_authorizer(args.account_lattices.iter().fold(
+            AccountAuthorizer::new(),
+            |authz, (account, lattices)| {
+                authz.with_account(account.clone(), lattices.iter().collect())
+            },
+        ))
+    };
+    let server = match args.rollback_timeout {
+        Some(timeout) => server.with_rollback_timeout(Duration::from_secs(timeout)),
+        None => server,
+    };
+    let server = match lattice_activity {
+        Some(activity) => server.with_lattice_activity(activity),
+        None => server,
+    };
+    let server = match membership.clone() {
+        Some(membership) => server.with_shard_membership(membership),
+        None => server,
+    };
+    let server = match args.capability_aliases.as_ref() {
+        Some(path) => server.with_capability_aliases(AliasRegistry::from_file(path).await?),
+        None => server,
+    };
+    let server = if args.verify_claims {
+        server.with_claims_verifier(LatticeClaimsVerifier::new(connection_pool), args.permissive)
+    } else {
+        server
+    };
+    let server = if args.pin_image_digests {
+        server.with_digest_resolver(
+            registry::RegistryDigestResolver::new(
+                args.registry_auth_file.as_deref(),
+                args.insecure_registries.clone(),
+            )
+            .await?
+            .with_registry_credentials(&registry_credentials),
+        )
+    } else {
+        server
+    };
+    let server = server
+        .with_config_reloader(reloader.clone())
+        .with_introspector(introspector);
+    tokio::spawn(reload::apply_changes(
+        reloader.shared.clone(),
+        event_permits.clone(),
+        command_permits.clone(),
+        log_filter,
+    ));
     tokio::select! {
         res = server.serve() => {
             res?
         }
+        res = reload::reload_on_hangup(reloader) => {
+            res?
+        }
+        res = manifest_sync => {
+            res?
+        }
+        res = health => {
+            res?
+        }
+        res = http_api => {
+            res?
+        }
         res = observer.observe(wasmbus_event_subjects) => {
             res?
         }
-        _ = tokio::signal::ctrl_c() => {}
+        _ = tokio::signal::ctrl_c() => {
+            // Dropping the server and observer stops new requests and lattices from coming in, so
+            // all that is left is letting the consumers finish what they already started
+            let timeout = Duration::from_secs(args.shutdown_timeout);
+            info!(?timeout, "Shutting down, waiting for in-flight work to finish");
+            let (events_manager, commands_manager) = shutdown_managers;
+            let (mut report, commands_report) = tokio::join!(
+                events_manager.drain(timeout),
+                commands_manager.drain(timeout)
+            );
+            report.merge(commands_report);
+            // Make sure anything the drained work published actually made it out
+            if let Err(e) = flush_client.flush().await {
+                warn!(error = %e, "Unable to flush pending publishes during shutdown");
+            }
+            // Release leases after stopping work so standby processes can take over right away
+            if let Some(leases) = leases {
+                leases.release_all().await;
+            }
+            if let Some(membership) = membership {
+                membership.leave().await;
+            }
+            // Push the final measurements before exiting
+            if let Some(provider) = meter_provider {
+                if let Err(e) = provider.shutdown() {
+                    warn!(error = %e, "Unable to flush metrics during shutdown");
+                }
+            }
+            let wadm_id = args.host_id.as_deref().unwrap_or("default");
+            report_shutdown(
+                &context,
+                args.shutdown_report_bucket,
+                args.kv_replicas,
+                wadm_id,
+                &report,
+            )
+            .await;
+        }
     }
     Ok(())
 }
 
+fn parse_event_sample(s: &str) -> Result<(String, u32), String> {
+    let (event_type, every) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid event sample {s}, expected TYPE=N"))?;
+    let every = every
+        .parse()
+        .map_err(|e| format!("Invalid sample rate for event type {event_type}: {e}"))?;
+    Ok((event_type.to_owned(), every))
+}
+
+fn parse_notification_webhook(s: &str) -> Result<(String, String), String> {
+    let (name, url) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid notification webhook {s}, expected NAME=URL"))?;
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(format!(
+            "Invalid URL for notification webhook {name}, expected an http or https URL"
+        ));
+    }
+    Ok((name.to_owned(), url.to_owned()))
+}
+
+fn parse_lifecycle_webhook(s: &str) -> Result<String, String> {
+    // The URL isn't included in the error since webhook URLs often have a secret in them
+    if !s.starts_with("http://") && !s.starts_with("https://") {
+        return Err(
+            "Invalid URL for lifecycle notification webhook, expected an http or https URL"
+                .to_string(),
+        );
+    }
+    Ok(s.to_owned())
+}
+
+fn parse_lattice_event_exclude(s: &str) -> Result<(String, Vec<String>), String> {
+    let (lattice_id, event_types) = s.split_once('=').ok_or_else(|| {
+        format!("Invalid lattice event exclude {s}, expected LATTICE=TYPE[,TYPE...]")
+    })?;
+    Ok((
+        lattice_id.to_owned(),
+        event_types
+            .split(',')
+            .filter(|ty| !ty.is_empty())
+            .map(ToOwned::to_owned)
+            .collect(),
+    ))
+}
+
+fn parse_lattice_event_start(s: &str) -> Result<(String, EventStartPosition), String> {
+    let (lattice_id, position) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid lattice event start {s}, expected LATTICE=POSITION"))?;
+    Ok((lattice_id.to_owned(), position.parse()?))
+}
+
+fn parse_lattice_allowed_issuers(s: &str) -> Result<(String, Vec<String>), String> {
+    let (lattice_id, issuers) = s.split_once('=').ok_or_else(|| {
+        format!("Invalid lattice allowed issuers {s}, expected LATTICE=KEY[,KEY...]")
+    })?;
+    Ok((
+        lattice_id.to_owned(),
+        issuers
+            .split(',')
+            .filter(|issuer| !issuer.is_empty())
+            .map(ToOwned::to_owned)
+            .collect(),
+    ))
+}
+
+fn parse_account_lattices(s: &str) -> Result<(String, Vec<String>), String> {
+    let (account, lattices) = s.split_once('=').ok_or_else(|| {
+        format!("Invalid account lattices {s}, expected ACCOUNT=LATTICE[,LATTICE...]")
+    })?;
+    Ok((
+        account.to_owned(),
+        lattices
+            .split(',')
+            .filter(|lattice| !lattice.is_empty())
+            .map(ToOwned::to_owned)
+            .collect(),
+    ))
+}
+
+/// Logs the given shutdown report and writes it to the report bucket, if one was given
+async fn report_shutdown(
+    context: &Context,
+    bucket: Option<String>,
+    kv_replicas: usize,
+    wadm_id: &str,
+    report: &ShutdownReport,
+) {
+    let data = serde_json::to_vec(report).unwrap_or_default();
+    if report.is_clean() {
+        info!(
+            nacked_messages = report.nacked_messages,
+            "Shut down without leaving any work behind"
+        );
+    } else {
+        warn!(
+            report = %String::from_utf8_lossy(&data),
+            lattices = ?report.lattices_needing_reconcile,
+            "Shut down with work left behind, these lattices may need to be reconciled"
+        );
+    }
+
+    let Some(bucket) = bucket else {
+        return;
+    };
+    let settings = KvBucketSettings {
+        replicas: kv_replicas,
+        ..Default::default()
+    };
+    let res = match nats::ensure_kv_bucket(context, bucket, &settings).await {
+        Ok(store) => store
+            .put(wadm_id, data.into())
+            .await
+            .map(|_| ())
+            .map_err(|e| anyhow::anyhow!("{e:?}")),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = res {
+        warn!(error = ?e, "Unable to store shutdown report");
+    }
+}
+
 #[derive(Clone)]
 struct CommandWorkerCreator {
-    pool: ControlClientConstructor,
+    pool: ControlClientPool,
+    circuit_breaker: HostCircuitBreaker,
+    retry_policy: CommandRetryPolicy,
+    registry_credentials: HashMap<String, wasmcloud_control_interface::RegistryCredential>,
+    dead_letter: DeadLetterQueue,
+    acks: CommandAckStore,
+    rate_limiter: ControlRateLimiter,
 }
 
 #[async_trait::async_trait]
 impl WorkerCreator for CommandWorkerCreator {
-    type Output = CommandWorker;
+    type Output = MiddlewareWorker<CommandWorker>;
 
     async fn create(
         &self,
@@ -470,7 +2143,18 @@ impl WorkerCreator for CommandWorkerCreator {
     ) -> anyhow::Result<Self::Output> {
         let client = self.pool.get_connection(lattice_id, multitenant_prefix);
 
-        Ok(CommandWorker::new(client))
+        let worker = CommandWorker::new(client)
+            .with_circuit_breaker(self.circuit_breaker.clone())
+            .with_retry_policy(self.retry_policy)
+            .with_registry_credentials(self.registry_credentials.clone())
+            .with_dead_letter_queue(self.dead_letter.clone())
+            .with_ack_store(self.acks.clone())
+            .with_rate_limiter(self.rate_limiter.clone());
+        let worker = match multitenant_prefix {
+            Some(prefix) => worker.with_multitenant_prefix(prefix),
+            None => worker,
+        };
+        Ok(MiddlewareWorker::new(worker).with_middleware(WorkMetrics::new("commands")))
     }
 }
 
@@ -478,11 +2162,22 @@ impl WorkerCreator for CommandWorkerCreator {
 struct EventWorkerCreator<StateStore> {
     state_store: StateStore,
     manifest_store: async_nats::jetstream::kv::Store,
-    pool: ControlClientConstructor,
+    pool: ControlClientPool,
+    inventory_cache: InventoryCache,
     command_topic_prefix: String,
+    command_validity: Duration,
+    command_encoding: Encoding,
     publisher: Context,
     notify_stream: Stream,
     status_stream: Stream,
+    circuit_breaker: HostCircuitBreaker,
+    secret_resolver: Option<SecretResolver>,
+    placement: PlacementStrategy,
+    scaler_views: admin::ScalerViews,
+    lifecycle: LifecycleNotifier,
+    issuers: IssuerPolicy,
+    policy: Option<NatsPolicyEngine>,
+    quarantine: Quarantine,
 }
 
 #[async_trait::async_trait]
@@ -490,23 +2185,41 @@ impl<StateStore> WorkerCreator for EventWorkerCreator<StateStore>
 where
     StateStore: wadm::storage::Store + Send + Sync + Clone + 'static,
 {
-    type Output = EventWorker<StateStore, wasmcloud_control_interface::Client, Context>;
+    type Output = MiddlewareWorker<
+        EventWorker<StateStore, CoalescingInventory<wasmcloud_control_interface::Client>, Context>,
+    >;
 
     async fn create(
         &self,
         lattice_id: &str,
         multitenant_prefix: Option<&str>,
     ) -> anyhow::Result<Self::Output> {
-        let client = self.pool.get_connection(lattice_id, multitenant_prefix);
+        // The event worker and the scalers share the cache, so they don't both ask a host for its
+        // inventory during the same burst of events
+        let client = self
+            .inventory_cache
+            .wrap(self.pool.get_connection(lattice_id, multitenant_prefix));
         let command_publisher = CommandPublisher::new(
             self.publisher.clone(),
-            &format!("{}.{lattice_id}", self.command_topic_prefix),
-        );
+            &lattice_subject(&self.command_topic_prefix, lattice_id, multitenant_prefix),
+        )
+        .with_validity(self.command_validity)
+        .with_encoding(self.command_encoding)
+        .with_lifecycle_notifier(self.lifecycle.clone(), lattice_id);
+        let command_publisher = match self.policy.clone() {
+            Some(engine) => command_publisher.with_policy_gate(
+                PolicyGate::new(engine)
+                    .with_manifest_store(self.manifest_store.clone(), multitenant_prefix),
+                lattice_id,
+            ),
+            None => command_publisher,
+        };
         let status_publisher = StatusPublisher::new(
             self.publisher.clone(),
             Some(self.status_stream.clone()),
             &format!("wadm.status.{lattice_id}"),
-        );
+        )
+        .with_lifecycle_notifier(self.lifecycle.clone(), lattice_id);
         let manager = ScalerManager::new(
             self.publisher.clone(),
             self.notify_stream.clone(),
@@ -517,14 +2230,31 @@ where
             command_publisher.clone(),
             status_publisher.clone(),
             client.clone(),
+            self.circuit_breaker.clone(),
+            self.secret_resolver.clone(),
+            self.placement,
         )
         .await?;
-        Ok(EventWorker::new(
+        self.scaler_views.insert(multitenant_prefix, manager.view());
+        let worker = EventWorker::new(
             self.state_store.clone(),
             client,
             command_publisher,
             status_publisher,
             manager,
-        ))
+        )
+        .with_reconcile_reporter(ReconcileReporter::new(
+            self.publisher.clone(),
+            &format!("wadm.reconcile.{lattice_id}"),
+        ));
+        let worker = match self.issuers.for_lattice(lattice_id) {
+            Some(issuers) => worker.with_issuer_allow_list(issuers),
+            None => worker,
+        };
+        // NOTE: Commands that keep failing are already dead lettered by the command worker, so
+        // only events that keep failing are quarantined
+        Ok(MiddlewareWorker::new(worker)
+            .with_middleware(WorkMetrics::new("events"))
+            .with_middleware(self.quarantine.clone()))
     }
 }
@@ -0,0 +1,320 @@
+//! Sources of manifests other than the API. A source is the single source of truth for the
+//! manifests it has: wadm puts and deploys them when they are added or changed and deletes them
+//! when they go away. This is meant for deployments where nothing calls the API, like edge devices
+//! where the manifests are baked into the image
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use tracing::{debug, info, warn};
+use wadm_client::{error::ClientError, loader::parse_yaml_or_json, Client};
+use wadm_types::{Manifest, VERSION_ANNOTATION_KEY};
+
+/// An annotation added to every manifest loaded from a source, set to the name of the source. This
+/// is how manifests from a source are told apart from ones put through the API
+pub(crate) const MANIFEST_SOURCE_ANNOTATION: &str = "wasmcloud.dev/manifest-source";
+
+/// The manifests a source currently has
+#[derive(Debug, Default)]
+pub(crate) struct LoadedManifests {
+    /// The manifests, by name
+    pub(crate) manifests: BTreeMap<String, Manifest>,
+    /// Whether every manifest could be loaded. If not, manifests that went missing aren't deleted,
+    /// as they might just be the ones that failed to load
+    pub(crate) complete: bool,
+}
+
+/// Anything that can provide a set of manifests to keep deployed
+#[async_trait]
+pub(crate) trait ManifestSource {
+    /// A short name for the source, added to every manifest it loads
+    fn name(&self) -> &str;
+
+    /// Loads all of the manifests the source currently has
+    async fn load(&self) -> anyhow::Result<LoadedManifests>;
+}
+
+/// Loads manifests from the YAML and JSON files in a directory. Subdirectories are ignored
+pub(crate) struct DirectorySource {
+    dir: PathBuf,
+}
+
+impl DirectorySource {
+    pub(crate) fn new(dir: impl Into<PathBuf>) -> DirectorySource {
+        DirectorySource { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl ManifestSource for DirectorySource {
+    fn name(&self) -> &str {
+        "directory"
+    }
+
+    async fn load(&self) -> anyhow::Result<LoadedManifests> {
+        let mut entries = tokio::fs::read_dir(&self.dir)
+            .await
+            .with_context(|| format!("Unable to read manifest directory {}", self.dir.display()))?;
+        let mut loaded = LoadedManifests {
+            complete: true,
+            ..Default::default()
+        };
+        // Collect the paths first so files are always loaded in the same order
+        let mut paths = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let is_manifest = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| matches!(ext, "yaml" | "yml" | "json"));
+            if is_manifest && entry.file_type().await.is_ok_and(|ty| ty.is_file()) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        for path in paths {
+            let manifest = match tokio::fs::read(&path)
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|data| parse_yaml_or_json(data).map_err(anyhow::Error::from))
+            {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    warn!(error = ?e, path = %path.display(), "Unable to load manifest, skipping");
+                    loaded.complete = false;
+                    continue;
+                }
+            };
+            let name = manifest.metadata.name.trim().to_owned();
+            if loaded.manifests.contains_key(&name) {
+                warn!(%name, path = %path.display(), "Another file already has a manifest with this name, skipping");
+                loaded.complete = false;
+                continue;
+            }
+            loaded.manifests.insert(name, manifest);
+        }
+        Ok(loaded)
+    }
+}
+
+/// Keeps the manifests from the given source deployed through the API, checking the source for
+/// changes on the given interval. This only returns if the source can't be loaded on the first
+/// attempt
+pub(crate) async fn sync_manifests(
+    source: impl ManifestSource,
+    client: Client,
+    interval: Duration,
+) -> anyhow::Result<()> {
+    let mut syncer = Syncer::new(source.name(), client);
+    // Fail right away on startup if the source is misconfigured, since nothing else will deploy
+    // anything
+    let mut loaded = source.load().await?;
+    syncer.remove_orphans(&loaded).await;
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    ticker.tick().await;
+    loop {
+        syncer.sync(loaded).await;
+        loaded = loop {
+            ticker.tick().await;
+            match source.load().await {
+                Ok(loaded) => break loaded,
+                Err(e) => warn!(error = ?e, "Unable to load manifests, will try again"),
+            }
+        };
+    }
+}
+
+/// Keeps the manifests a source has for a single lattice deployed
+pub(crate) struct Syncer {
+    source_name: String,
+    client: Client,
+    /// The manifests that were last put and deployed, by name
+    applied: HashMap<String, Manifest>,
+}
+
+impl Syncer {
+    /// Creates a syncer for the source with the given name, using a client for the lattice to keep
+    /// the manifests deployed in
+    pub(crate) fn new(source_name: impl Into<String>, client: Client) -> Syncer {
+        Syncer {
+            source_name: source_name.into(),
+            client,
+            applied: HashMap::new(),
+        }
+    }
+
+    /// Puts and deploys the manifests that were added or changed since the last sync and deletes
+    /// the ones that are gone
+    pub(crate) async fn sync(&mut self, loaded: LoadedManifests) {
+        let loaded_names: HashSet<String> = loaded.manifests.keys().cloned().collect();
+        for (name, mut manifest) in loaded.manifests {
+            manifest.metadata.annotations.insert(
+                MANIFEST_SOURCE_ANNOTATION.to_owned(),
+                self.source_name.clone(),
+            );
+            if self.applied.get(&name) == Some(&manifest) {
+                continue;
+            }
+            match self.apply(&name, &manifest).await {
+                Ok(()) => {
+                    self.applied.insert(name, manifest);
+                }
+                Err(e) => warn!(error = %e, %name, "Unable to deploy manifest, will try again"),
+            }
+        }
+
+        if !loaded.complete {
+            return;
+        }
+        let removed: Vec<String> = self
+            .applied
+            .keys()
+            .filter(|name| !loaded_names.contains(*name))
+            .cloned()
+            .collect();
+        for name in removed {
+            match self.client.delete_manifest(&name, None).await {
+                Ok(_) => {
+                    info!(%name, "Manifest was removed from source, deleted it");
+                    self.applied.remove(&name);
+                }
+                Err(e) => warn!(error = %e, %name, "Unable to delete manifest, will try again"),
+            }
+        }
+    }
+
+    /// Puts the manifest if it differs from the latest stored version, and deploys it
+    async fn apply(&self, name: &str, manifest: &Manifest) -> Result<(), ClientError> {
+        let stored = match self.client.get_manifest(name, None).await {
+            Ok(stored) => Some(stored),
+            Err(ClientError::NotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+        if !stored.is_some_and(|stored| same_manifest(&stored, manifest)) {
+            let (_, version) = self.client.put_manifest(manifest).await?;
+            info!(%name, %version, "Put manifest from source");
+        }
+        let (_, version) = self.client.deploy_manifest(name, None).await?;
+        debug!(%name, ?version, "Deployed manifest from source");
+        Ok(())
+    }
+
+    /// Deletes manifests that came from this source but are no longer in it. These are left
+    /// behind when a manifest is removed from the source while wadm isn't running
+    pub(crate) async fn remove_orphans(&self, loaded: &LoadedManifests) {
+        if !loaded.complete {
+            return;
+        }
+        let summaries = match self.client.list_manifests().await {
+            Ok(summaries) => summaries,
+            Err(e) => {
+                warn!(error = %e, "Unable to list manifests to find ones removed from source");
+                return;
+            }
+        };
+        for summary in summaries
+            .into_iter()
+            .filter(|summary| !loaded.manifests.contains_key(&summary.name))
+        {
+            let from_source = self
+                .client
+                .get_manifest(&summary.name, None)
+                .await
+                .is_ok_and(|manifest| {
+                    manifest
+                        .metadata
+                        .annotations
+                        .get(MANIFEST_SOURCE_ANNOTATION)
+                        == Some(&self.source_name)
+                });
+            if !from_source {
+                continue;
+            }
+            match self.client.delete_manifest(&summary.name, None).await {
+                Ok(_) => {
+                    info!(name = %summary.name, "Manifest was removed from source, deleted it")
+                }
+                Err(e) => warn!(error = %e, name = %summary.name, "Unable to delete manifest"),
+            }
+        }
+    }
+}
+
+/// Returns whether the stored manifest is the same as the one from the source. Manifests without a
+/// version get one generated when they are stored, so it is ignored in that case
+fn same_manifest(stored: &Manifest, manifest: &Manifest) -> bool {
+    let mut stored = stored.clone();
+    if !manifest
+        .metadata
+        .annotations
+        .contains_key(VERSION_ANNOTATION_KEY)
+    {
+        stored.metadata.annotations.remove(VERSION_ANNOTATION_KEY);
+    }
+    &stored == manifest
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn can_load_manifests_from_directory() {
+        let dir = std::env::temp_dir().join(format!("wadm-manifests-{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(dir.join("nested")).await.unwrap();
+        let manifest = tokio::fs::read("tests/fixtures/manifests/simple.wadm.yaml")
+            .await
+            .unwrap();
+        tokio::fs::write(dir.join("simple.yaml"), &manifest)
+            .await
+            .unwrap();
+        tokio::fs::write(dir.join("notes.txt"), "not a manifest")
+            .await
+            .unwrap();
+        tokio::fs::write(dir.join("nested").join("other.yaml"), &manifest)
+            .await
+            .unwrap();
+
+        let source = DirectorySource::new(&dir);
+        let loaded = source.load().await.unwrap();
+        assert!(loaded.complete);
+        assert_eq!(
+            loaded.manifests.len(),
+            1,
+            "Only files in the directory should be loaded"
+        );
+
+        tokio::fs::write(dir.join("broken.json"), "{")
+            .await
+            .unwrap();
+        let loaded = source.load().await.unwrap();
+        assert!(
+            !loaded.complete,
+            "Broken files should mark the load as incomplete"
+        );
+        assert_eq!(loaded.manifests.len(), 1);
+
+        let mut from_source = loaded.manifests.values().next().unwrap().clone();
+        from_source
+            .metadata
+            .annotations
+            .remove(VERSION_ANNOTATION_KEY);
+        let mut stored = from_source.clone();
+        stored
+            .metadata
+            .annotations
+            .insert(VERSION_ANNOTATION_KEY.to_string(), "01J0000000".to_string());
+        assert!(
+            same_manifest(&stored, &from_source),
+            "Generated versions should be ignored"
+        );
+        stored.metadata.name = "changed".to_string();
+        assert!(!same_manifest(&stored, &from_source));
+
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+}
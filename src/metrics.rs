@@ -0,0 +1,598 @@
+//! Metrics export. wadm records all of its metrics through the OpenTelemetry global meter, so the
+//! same measurements can be pushed over OTLP (to the same collector traces are sent to), scraped by
+//! Prometheus, or both, depending on which exporters are enabled
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use http_body_util::Full;
+use hyper::{body::Bytes, server::conn::http1, service::service_fn, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use opentelemetry_metrics::{
+    global,
+    metrics::{MetricsError, Result as MetricsResult},
+    KeyValue,
+};
+use opentelemetry_sdk::{
+    metrics::{
+        data::{self, ResourceMetrics, Temporality},
+        exporter::PushMetricsExporter,
+        reader::{
+            AggregationSelector, DefaultAggregationSelector, MetricReader, TemporalitySelector,
+        },
+        Aggregation, InstrumentKind, ManualReader, PeriodicReader, Pipeline, SdkMeterProvider,
+    },
+    runtime, AttributeSet, Resource,
+};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tracing::{debug, warn};
+
+const TRACING_PATH: &str = "/v1/traces";
+const METRICS_PATH: &str = "/v1/metrics";
+
+/// The ways metrics can be exported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MetricsExporter {
+    /// Push metrics over OTLP (HTTP) to the tracing endpoint
+    Otlp,
+    /// Serve metrics in the Prometheus text format for scraping
+    Prometheus,
+}
+
+/// Configures the global meter provider with the given exporters. This has to be called before
+/// anything records metrics, as instruments created beforehand are never exported. Returns `None`
+/// if no exporters are enabled, otherwise returns the provider so it can be flushed on shutdown
+pub async fn configure_metrics(
+    exporters: &[MetricsExporter],
+    otlp_endpoint: Option<&str>,
+    prometheus_address: SocketAddr,
+    interval: Duration,
+) -> anyhow::Result<Option<SdkMeterProvider>> {
+    if exporters.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = SdkMeterProvider::builder()
+        .with_resource(Resource::new([KeyValue::new("service.name", "wadm")]));
+    if exporters.contains(&MetricsExporter::Otlp) {
+        let endpoint = metrics_endpoint(otlp_endpoint);
+        debug!(%endpoint, "Exporting metrics over OTLP");
+        let exporter = OtlpExporter {
+            client: reqwest::Client::new(),
+            endpoint,
+        };
+        builder = builder.with_reader(
+            PeriodicReader::builder(exporter, runtime::Tokio)
+                .with_interval(interval)
+                .build(),
+        );
+    }
+    if exporters.contains(&MetricsExporter::Prometheus) {
+        let listener = TcpListener::bind(prometheus_address)
+            .await
+            .with_context(|| {
+                format!("Unable to serve Prometheus metrics on {prometheus_address}")
+            })?;
+        debug!(address = %prometheus_address, "Serving Prometheus metrics");
+        let reader = SharedReader(Arc::new(ManualReader::builder().build()));
+        tokio::spawn(serve_prometheus(listener, reader.clone()));
+        builder = builder.with_reader(reader);
+    }
+    let provider = builder.build();
+    global::set_meter_provider(provider.clone());
+    Ok(Some(provider))
+}
+
+/// Returns the OTLP metrics endpoint for the given tracing endpoint, so both go to the same
+/// collector
+fn metrics_endpoint(tracing_endpoint: Option<&str>) -> String {
+    let base = tracing_endpoint.unwrap_or("http://localhost:4318");
+    let base = base.strip_suffix(TRACING_PATH).unwrap_or(base);
+    format!("{}{METRICS_PATH}", base.trim_end_matches('/'))
+}
+
+/// A single value of a data point
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Number {
+    Int(i64),
+    Double(f64),
+}
+
+impl From<u64> for Number {
+    fn from(value: u64) -> Self {
+        Number::Int(value.min(i64::MAX as u64) as i64)
+    }
+}
+
+impl From<i64> for Number {
+    fn from(value: i64) -> Self {
+        Number::Int(value)
+    }
+}
+
+impl From<f64> for Number {
+    fn from(value: f64) -> Self {
+        Number::Double(value)
+    }
+}
+
+impl Number {
+    fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(value) => value as f64,
+            Number::Double(value) => value,
+        }
+    }
+}
+
+struct Point {
+    attributes: Vec<(String, String)>,
+    start_time: Option<SystemTime>,
+    time: Option<SystemTime>,
+    value: Number,
+}
+
+struct HistogramPoint {
+    attributes: Vec<(String, String)>,
+    start_time: SystemTime,
+    time: SystemTime,
+    count: u64,
+    sum: Number,
+    bounds: Vec<f64>,
+    bucket_counts: Vec<u64>,
+}
+
+/// The data of a single metric, independent of the number type it was recorded with. Both
+/// exporters encode metrics from this so they always report the same measurements
+enum MetricData {
+    Sum {
+        monotonic: bool,
+        cumulative: bool,
+        points: Vec<Point>,
+    },
+    Gauge(Vec<Point>),
+    Histogram {
+        cumulative: bool,
+        points: Vec<HistogramPoint>,
+    },
+}
+
+impl MetricData {
+    /// Converts the aggregated data of a metric. Returns `None` for aggregations wadm doesn't use
+    fn from_metric(metric: &data::Metric) -> Option<MetricData> {
+        let data = metric.data.as_any();
+        sum::<u64>(data)
+            .or_else(|| sum::<i64>(data))
+            .or_else(|| sum::<f64>(data))
+            .or_else(|| gauge::<u64>(data))
+            .or_else(|| gauge::<i64>(data))
+            .or_else(|| gauge::<f64>(data))
+            .or_else(|| histogram::<u64>(data))
+            .or_else(|| histogram::<i64>(data))
+            .or_else(|| histogram::<f64>(data))
+    }
+}
+
+fn sum<T: Copy + Into<Number> + 'static>(data: &dyn std::any::Any) -> Option<MetricData> {
+    data.downcast_ref::<data::Sum<T>>()
+        .map(|sum| MetricData::Sum {
+            monotonic: sum.is_monotonic,
+            cumulative: matches!(sum.temporality, Temporality::Cumulative),
+            points: sum.data_points.iter().map(point).collect(),
+        })
+}
+
+fn gauge<T: Copy + Into<Number> + 'static>(data: &dyn std::any::Any) -> Option<MetricData> {
+    data.downcast_ref::<data::Gauge<T>>()
+        .map(|gauge| MetricData::Gauge(gauge.data_points.iter().map(point).collect()))
+}
+
+fn histogram<T: Copy + Into<Number> + 'static>(data: &dyn std::any::Any) -> Option<MetricData> {
+    data.downcast_ref::<data::Histogram<T>>()
+        .map(|histogram| MetricData::Histogram {
+            cumulative: matches!(histogram.temporality, Temporality::Cumulative),
+            points: histogram
+                .data_points
+                .iter()
+                .map(|point| HistogramPoint {
+                    attributes: attributes(&point.attributes),
+                    start_time: point.start_time,
+                    time: point.time,
+                    count: point.count,
+                    sum: point.sum.into(),
+                    bounds: point.bounds.clone(),
+                    bucket_counts: point.bucket_counts.clone(),
+                })
+                .collect(),
+        })
+}
+
+fn point<T: Copy + Into<Number>>(point: &data::DataPoint<T>) -> Point {
+    Point {
+        attributes: attributes(&point.attributes),
+        start_time: point.start_time,
+        time: point.time,
+        value: point.value.into(),
+    }
+}
+
+fn attributes(attributes: &AttributeSet) -> Vec<(String, String)> {
+    attributes
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.as_str().into_owned()))
+        .collect()
+}
+
+/// Pushes metrics to an OTLP collector using the JSON encoding of the OTLP HTTP protocol
+struct OtlpExporter {
+    client: reqwest::Client,
+    endpoint: String,
+}
+
+impl TemporalitySelector for OtlpExporter {
+    fn temporality(&self, _kind: InstrumentKind) -> Temporality {
+        Temporality::Cumulative
+    }
+}
+
+impl AggregationSelector for OtlpExporter {
+    fn aggregation(&self, kind: InstrumentKind) -> Aggregation {
+        DefaultAggregationSelector::new().aggregation(kind)
+    }
+}
+
+#[async_trait]
+impl PushMetricsExporter for OtlpExporter {
+    async fn export(&self, metrics: &mut ResourceMetrics) -> MetricsResult<()> {
+        self.client
+            .post(&self.endpoint)
+            .json(&encode_otlp(metrics))
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(|e| MetricsError::Other(format!("Unable to export metrics: {e}")))?;
+        Ok(())
+    }
+
+    async fn force_flush(&self) -> MetricsResult<()> {
+        Ok(())
+    }
+
+    fn shutdown(&self) -> MetricsResult<()> {
+        Ok(())
+    }
+}
+
+fn encode_otlp(metrics: &ResourceMetrics) -> Value {
+    json!({
+        "resourceMetrics": [{
+            "resource": {
+                "attributes": otlp_attributes(
+                    metrics
+                        .resource
+                        .iter()
+                        .map(|(key, value)| (key.to_string(), value.as_str().into_owned())),
+                ),
+            },
+            "scopeMetrics": metrics.scope_metrics.iter().map(|scope| json!({
+                "scope": {
+                    "name": scope.scope.name,
+                    "version": scope.scope.version,
+                },
+                "metrics": scope.metrics.iter().filter_map(encode_otlp_metric).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+        }],
+    })
+}
+
+fn encode_otlp_metric(metric: &data::Metric) -> Option<Value> {
+    let (kind, data) = match MetricData::from_metric(metric)? {
+        MetricData::Sum {
+            monotonic,
+            cumulative,
+            points,
+        } => (
+            "sum",
+            json!({
+                "dataPoints": points.iter().map(otlp_point).collect::<Vec<_>>(),
+                "aggregationTemporality": otlp_temporality(cumulative),
+                "isMonotonic": monotonic,
+            }),
+        ),
+        MetricData::Gauge(points) => (
+            "gauge",
+            json!({ "dataPoints": points.iter().map(otlp_point).collect::<Vec<_>>() }),
+        ),
+        MetricData::Histogram { cumulative, points } => (
+            "histogram",
+            json!({
+                "dataPoints": points.iter().map(|point| json!({
+                    "attributes": otlp_attributes(point.attributes.iter().cloned()),
+                    "startTimeUnixNano": unix_nanos(point.start_time),
+                    "timeUnixNano": unix_nanos(point.time),
+                    "count": point.count.to_string(),
+                    "sum": point.sum.as_f64(),
+                    "explicitBounds": point.bounds,
+                    "bucketCounts": point.bucket_counts.iter().map(u64::to_string).collect::<Vec<_>>(),
+                })).collect::<Vec<_>>(),
+                "aggregationTemporality": otlp_temporality(cumulative),
+            }),
+        ),
+    };
+    Some(json!({
+        "name": metric.name,
+        "description": metric.description,
+        "unit": metric.unit.as_str(),
+        kind: data,
+    }))
+}
+
+fn otlp_point(point: &Point) -> Value {
+    let mut value = json!({
+        "attributes": otlp_attributes(point.attributes.iter().cloned()),
+    });
+    if let Some(start_time) = point.start_time {
+        value["startTimeUnixNano"] = json!(unix_nanos(start_time));
+    }
+    if let Some(time) = point.time {
+        value["timeUnixNano"] = json!(unix_nanos(time));
+    }
+    match point.value {
+        // NOTE: 64 bit integers are encoded as strings in the JSON encoding of protobuf
+        Number::Int(int) => value["asInt"] = json!(int.to_string()),
+        Number::Double(double) => value["asDouble"] = json!(double),
+    }
+    value
+}
+
+fn otlp_attributes(attributes: impl Iterator<Item = (String, String)>) -> Vec<Value> {
+    attributes
+        .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+        .collect()
+}
+
+fn otlp_temporality(cumulative: bool) -> u8 {
+    // These are the values of AggregationTemporality in the OTLP protobuf definitions
+    if cumulative {
+        2
+    } else {
+        1
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+/// A reader that can be shared between the meter provider and the Prometheus endpoint, which
+/// collects from it whenever it is scraped
+#[derive(Debug, Clone)]
+struct SharedReader(Arc<ManualReader>);
+
+impl TemporalitySelector for SharedReader {
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        self.0.temporality(kind)
+    }
+}
+
+impl AggregationSelector for SharedReader {
+    fn aggregation(&self, kind: InstrumentKind) -> Aggregation {
+        self.0.aggregation(kind)
+    }
+}
+
+impl MetricReader for SharedReader {
+    fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+        self.0.register_pipeline(pipeline)
+    }
+
+    fn collect(&self, rm: &mut ResourceMetrics) -> MetricsResult<()> {
+        self.0.collect(rm)
+    }
+
+    fn force_flush(&self) -> MetricsResult<()> {
+        self.0.force_flush()
+    }
+
+    fn shutdown(&self) -> MetricsResult<()> {
+        self.0.shutdown()
+    }
+}
+
+async fn serve_prometheus(listener: TcpListener, reader: SharedReader) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!(error = %e, "Unable to accept Prometheus scrape connection");
+                continue;
+            }
+        };
+        let reader = reader.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| {
+                let reader = reader.clone();
+                async move {
+                    let resp = if req.uri().path() != "/metrics" {
+                        Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body(Full::new(Bytes::new()))
+                    } else {
+                        let mut metrics = ResourceMetrics {
+                            resource: Resource::empty(),
+                            scope_metrics: Vec::new(),
+                        };
+                        match reader.collect(&mut metrics) {
+                            Ok(()) => Response::builder()
+                                .header("Content-Type", "text/plain; version=0.0.4")
+                                .body(Full::new(Bytes::from(encode_prometheus(&metrics)))),
+                            Err(e) => Response::builder()
+                                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                                .body(Full::new(Bytes::from(e.to_string()))),
+                        }
+                    };
+                    resp
+                }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!(error = %e, "Error serving Prometheus scrape");
+            }
+        });
+    }
+}
+
+fn encode_prometheus(metrics: &ResourceMetrics) -> String {
+    let mut out = String::new();
+    for metric in metrics
+        .scope_metrics
+        .iter()
+        .flat_map(|scope| scope.metrics.iter())
+    {
+        let Some(data) = MetricData::from_metric(metric) else {
+            continue;
+        };
+        let name = prometheus_name(&metric.name);
+        let (name, kind) = match &data {
+            MetricData::Sum {
+                monotonic: true, ..
+            } => (format!("{name}_total"), "counter"),
+            MetricData::Sum { .. } | MetricData::Gauge(_) => (name, "gauge"),
+            MetricData::Histogram { .. } => (name, "histogram"),
+        };
+        if !metric.description.is_empty() {
+            out.push_str(&format!(
+                "# HELP {name} {}\n",
+                metric
+                    .description
+                    .replace('\\', "\\\\")
+                    .replace('\n', "\\n")
+            ));
+        }
+        out.push_str(&format!("# TYPE {name} {kind}\n"));
+        match data {
+            MetricData::Sum { points, .. } | MetricData::Gauge(points) => {
+                for point in points {
+                    out.push_str(&format!(
+                        "{name}{} {}\n",
+                        prometheus_labels(&point.attributes, None),
+                        point.value.as_f64()
+                    ));
+                }
+            }
+            MetricData::Histogram { points, .. } => {
+                for point in points {
+                    let mut count = 0;
+                    for (bound, bucket) in point.bounds.iter().zip(point.bucket_counts.iter()) {
+                        count += bucket;
+                        out.push_str(&format!(
+                            "{name}_bucket{} {count}\n",
+                            prometheus_labels(&point.attributes, Some(&bound.to_string()))
+                        ));
+                    }
+                    out.push_str(&format!(
+                        "{name}_bucket{} {}\n",
+                        prometheus_labels(&point.attributes, Some("+Inf")),
+                        point.count
+                    ));
+                    let labels = prometheus_labels(&point.attributes, None);
+                    out.push_str(&format!("{name}_sum{labels} {}\n", point.sum.as_f64()));
+                    out.push_str(&format!("{name}_count{labels} {}\n", point.count));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Converts a metric or label name to one that is valid in Prometheus
+fn prometheus_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn prometheus_labels(attributes: &[(String, String)], le: Option<&str>) -> String {
+    let labels: Vec<String> = attributes
+        .iter()
+        .map(|(key, value)| (prometheus_name(key), value.as_str()))
+        .chain(le.map(|le| ("le".to_string(), le)))
+        .map(|(key, value)| {
+            format!(
+                "{key}=\"{}\"",
+                value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n")
+            )
+        })
+        .collect();
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use opentelemetry_metrics::metrics::MeterProvider;
+
+    use super::*;
+
+    #[test]
+    fn metrics_endpoint_shares_tracing_collector() {
+        assert_eq!(metrics_endpoint(None), "http://localhost:4318/v1/metrics");
+        assert_eq!(
+            metrics_endpoint(Some("http://collector:4318/v1/traces")),
+            "http://collector:4318/v1/metrics"
+        );
+        assert_eq!(
+            metrics_endpoint(Some("http://collector:4318/")),
+            "http://collector:4318/v1/metrics"
+        );
+    }
+
+    #[test]
+    fn exporters_encode_the_same_measurements() {
+        let reader = SharedReader(Arc::new(ManualReader::builder().build()));
+        let provider = SdkMeterProvider::builder()
+            .with_reader(reader.clone())
+            .build();
+        let meter = provider.meter("wadm");
+        let counter = meter
+            .u64_counter("wadm.commands.expired")
+            .with_description("Expired commands")
+            .init();
+        counter.add(2, &[KeyValue::new("lattice_id", "default")]);
+        counter.add(1, &[KeyValue::new("lattice_id", "default")]);
+
+        let mut metrics = ResourceMetrics {
+            resource: Resource::empty(),
+            scope_metrics: Vec::new(),
+        };
+        reader.collect(&mut metrics).unwrap();
+
+        let text = encode_prometheus(&metrics);
+        assert!(text.contains("# TYPE wadm_commands_expired_total counter\n"));
+        assert!(text.contains("wadm_commands_expired_total{lattice_id=\"default\"} 3\n"));
+
+        let otlp = encode_otlp(&metrics);
+        let metric = &otlp["resourceMetrics"][0]["scopeMetrics"][0]["metrics"][0];
+        assert_eq!(metric["name"], "wadm.commands.expired");
+        assert_eq!(metric["sum"]["isMonotonic"], true);
+        assert_eq!(metric["sum"]["dataPoints"][0]["asInt"], "3");
+        assert_eq!(
+            metric["sum"]["dataPoints"][0]["attributes"][0]["key"],
+            "lattice_id"
+        );
+    }
+}
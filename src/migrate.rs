@@ -0,0 +1,59 @@
+//! The `migrate-state` command, which rebuilds the state of a lattice from what its hosts are
+//! running
+
+use wadm::storage::{
+    migrate::{scan_lattice, seed_state},
+    nats_kv::NatsKvStore,
+};
+
+/// Options for the `migrate-state` command
+#[derive(clap::Args, Debug)]
+pub(crate) struct MigrateStateArgs {
+    /// The lattice to rebuild the state of
+    #[arg(long = "lattice", default_value = "default")]
+    pub(crate) lattice: String,
+
+    /// The multitenant prefix (account ID) of the lattice, if wadm runs in multitenant mode
+    #[arg(long = "multitenant-prefix")]
+    pub(crate) multitenant_prefix: Option<String>,
+
+    /// Only report what was found without writing anything to the state bucket
+    #[arg(long = "dry-run")]
+    pub(crate) dry_run: bool,
+}
+
+/// Scans the lattice with the given control client, prints a summary of the apps found and, unless
+/// this is a dry run, replaces the state of the lattice in the store
+pub(crate) async fn migrate_state(
+    store: &NatsKvStore,
+    client: &wasmcloud_control_interface::Client,
+    args: &MigrateStateArgs,
+) -> anyhow::Result<()> {
+    let snapshot = scan_lattice(client).await?;
+    println!(
+        "Found {} hosts, {} components and {} providers in lattice {}",
+        snapshot.hosts.len(),
+        snapshot.components.len(),
+        snapshot.providers.len(),
+        args.lattice
+    );
+    for (app, resources) in snapshot.apps() {
+        println!(
+            "  {app}: {} component instances, {} providers",
+            resources.component_instances, resources.providers
+        );
+    }
+    if !snapshot.unreachable_hosts.is_empty() {
+        println!(
+            "Unable to get the inventory of hosts {}. Existing state won't be removed",
+            snapshot.unreachable_hosts.join(", ")
+        );
+    }
+    if args.dry_run {
+        println!("Dry run, nothing was written");
+        return Ok(());
+    }
+    seed_state(store, &args.lattice, snapshot).await?;
+    println!("Wrote state for lattice {}", args.lattice);
+    Ok(())
+}
@@ -4,14 +4,15 @@ use anyhow::{anyhow, Result};
 use async_nats::{
     jetstream::{
         self,
-        kv::{Config as KvConfig, Store},
+        kv::{Config as KvConfig, CreateErrorKind, Store},
         stream::{Config as StreamConfig, Source, Stream, SubjectTransform},
         Context,
     },
     Client, ConnectOptions,
 };
+use futures::TryStreamExt;
 
-use tracing::{debug, warn};
+use tracing::{debug, error, warn};
 use wadm::DEFAULT_EXPIRY_TIME;
 
 /// Creates a NATS client from the given options
@@ -120,29 +121,29 @@ pub async fn ensure_stream(
     name: String,
     subjects: Vec<String>,
     description: Option<String>,
-    max_bytes: i64,
+    settings: &StreamSettings,
 ) -> Result<Stream> {
     debug!("Ensuring stream {name} exists");
     let stream_config = StreamConfig {
         name: name.clone(),
         description,
-        num_replicas: 1,
+        num_replicas: settings.replicas,
         retention: async_nats::jetstream::stream::RetentionPolicy::WorkQueue,
         subjects,
         max_age: DEFAULT_EXPIRY_TIME,
         storage: async_nats::jetstream::stream::StorageType::File,
         allow_rollup: false,
-        max_bytes,
+        max_bytes: settings.max_bytes,
+        max_messages: settings.max_messages,
         ..Default::default()
     };
 
     if let Ok(stream) = context.get_stream(&name).await {
-        // For now, we only check if the subjects are the same in order to make sure that
-        // newer versions of wadm adjust subjects appropriately. In the case that developers
-        // want to alter the storage or replicas of a stream, for example,
-        // we don't want to override that configuration.
+        // Streams with different subjects are recreated so newer versions of wadm adjust subjects
+        // appropriately. Otherwise only the configurable settings are updated in place, so any
+        // other changes developers made to the stream (like the storage) are kept
         if stream.cached_info().config.subjects == stream_config.subjects {
-            return Ok(stream);
+            return Ok(reconcile_stream(context, stream, settings).await);
         } else {
             warn!("Found stream {name} with different configuration, deleting and recreating");
             context.delete_stream(name).await?;
@@ -160,29 +161,29 @@ pub async fn ensure_limits_stream(
     name: String,
     subjects: Vec<String>,
     description: Option<String>,
-    max_bytes: i64,
+    settings: &StreamSettings,
 ) -> Result<Stream> {
     debug!("Ensuring stream {name} exists");
     let stream_config = StreamConfig {
         name: name.clone(),
         description,
-        num_replicas: 1,
+        num_replicas: settings.replicas,
         retention: async_nats::jetstream::stream::RetentionPolicy::Limits,
         subjects,
         max_age: DEFAULT_EXPIRY_TIME,
         storage: async_nats::jetstream::stream::StorageType::File,
         allow_rollup: false,
-        max_bytes,
+        max_bytes: settings.max_bytes,
+        max_messages: settings.max_messages,
         ..Default::default()
     };
 
     if let Ok(stream) = context.get_stream(&name).await {
-        // For now, we only check if the subjects are the same in order to make sure that
-        // newer versions of wadm adjust subjects appropriately. In the case that developers
-        // want to alter the storage or replicas of a stream, for example,
-        // we don't want to override that configuration.
+        // Streams with different subjects are recreated so newer versions of wadm adjust subjects
+        // appropriately. Otherwise only the configurable settings are updated in place, so any
+        // other changes developers made to the stream (like the storage) are kept
         if stream.cached_info().config.subjects == stream_config.subjects {
-            return Ok(stream);
+            return Ok(reconcile_stream(context, stream, settings).await);
         } else {
             warn!("Found stream {name} with different configuration, deleting and recreating");
             context.delete_stream(name).await?;
@@ -195,13 +196,105 @@ pub async fn ensure_limits_stream(
         .map_err(|e| anyhow::anyhow!("{e:?}"))
 }
 
+/// A helper that ensures that the given stream exists and gets the messages on the given subject
+/// from existing streams, rather than capturing the subject itself. This is for clusters where the
+/// subject is already captured by another stream, as two streams can't capture the same subjects.
+/// With `mirror` set, the stream is a mirror of the single upstream stream. Returns the handle to
+/// the stream
+pub async fn ensure_sourced_stream(
+    context: &Context,
+    name: String,
+    subject: String,
+    upstreams: &[String],
+    mirror: bool,
+    description: Option<String>,
+    settings: &StreamSettings,
+) -> Result<Stream> {
+    debug!("Ensuring stream {name} exists");
+    if mirror && upstreams.len() != 1 {
+        anyhow::bail!("Stream {name} can only mirror a single stream, got {upstreams:?}");
+    }
+    let mut sources = upstreams.iter().map(|upstream| Source {
+        name: upstream.to_owned(),
+        filter_subject: Some(subject.clone()),
+        ..Default::default()
+    });
+    let stream_config = StreamConfig {
+        name: name.clone(),
+        description,
+        num_replicas: settings.replicas,
+        retention: async_nats::jetstream::stream::RetentionPolicy::Limits,
+        subjects: vec![],
+        mirror: if mirror { sources.next() } else { None },
+        sources: if mirror {
+            None
+        } else {
+            Some(sources.collect())
+        },
+        max_age: DEFAULT_EXPIRY_TIME,
+        storage: async_nats::jetstream::stream::StorageType::File,
+        allow_rollup: false,
+        max_bytes: settings.max_bytes,
+        max_messages: settings.max_messages,
+        ..Default::default()
+    };
+
+    if let Ok(stream) = context.get_stream(&name).await {
+        // Streams that captured the subjects themselves or that source from other streams are
+        // recreated, as neither can be changed on an existing stream
+        let config = &stream.cached_info().config;
+        if config.subjects.is_empty()
+            && source_keys(config.mirror.iter()) == source_keys(stream_config.mirror.iter())
+            && source_keys(config.sources.iter().flatten())
+                == source_keys(stream_config.sources.iter().flatten())
+        {
+            return Ok(reconcile_stream(context, stream, settings).await);
+        } else {
+            warn!("Found stream {name} with different sources, deleting and recreating");
+            context.delete_stream(name).await?;
+        }
+    }
+
+    context
+        .get_or_create_stream(stream_config)
+        .await
+        .map_err(|e| anyhow::anyhow!("{e:?}"))
+}
+
+/// Returns the stream names and filters of the given sources, for comparing the sources of a
+/// stream while ignoring the defaults filled in by the server
+fn source_keys<'a>(sources: impl Iterator<Item = &'a Source>) -> Vec<(&'a str, Option<&'a str>)> {
+    let mut keys: Vec<_> = sources
+        .map(|source| (source.name.as_str(), source.filter_subject.as_deref()))
+        .collect();
+    keys.sort();
+    keys
+}
+
+/// Returns the subjects of the messages in a stream. Streams that mirror or source from other
+/// streams don't have subjects of their own, so the subjects they filter on are used instead
+fn carried_subjects(config: &StreamConfig) -> Vec<String> {
+    if !config.subjects.is_empty() {
+        return config.subjects.clone();
+    }
+    let mut subjects: Vec<String> = config
+        .mirror
+        .iter()
+        .chain(config.sources.iter().flatten())
+        .filter_map(|source| source.filter_subject.clone())
+        .collect();
+    subjects.sort();
+    subjects.dedup();
+    subjects
+}
+
 pub async fn ensure_event_consumer_stream(
     context: &Context,
     name: String,
     subject: String,
     streams: Vec<&Stream>,
     description: Option<String>,
-    max_bytes: i64,
+    settings: &StreamSettings,
 ) -> Result<Stream> {
     debug!("Ensuring stream {name} exists");
     // This maps the upstream (wasmbus.evt.*.> & wadm.evt.*.>) Streams into
@@ -212,9 +305,7 @@ pub async fn ensure_event_consumer_stream(
         .iter()
         .map(|stream| stream.cached_info().config.clone())
         .map(|stream_config| Source {
-            name: stream_config.name,
-            subject_transforms: stream_config
-                .subjects
+            subject_transforms: carried_subjects(&stream_config)
                 .iter()
                 .map(|stream_subject| SubjectTransform {
                     source: stream_subject.to_owned(),
@@ -225,11 +316,15 @@ pub async fn ensure_event_consumer_stream(
                         //
                         // multi-tenant:  <account-id>.<subject>.evt.<lattice-id>.<event-type>
                         // single-tenant: <subject>.evt.<lattice-id>.<event-type>
-                        true => subject.replacen('*', "{{wildcard(2)}}", 1),
+                        //
+                        // The account id is kept in front of the lattice id so lattices with the
+                        // same id in different accounts have their own consumers
+                        true => subject.replacen('*', "{{wildcard(1)}}.{{wildcard(2)}}", 1),
                         false => subject.replacen('*', "{{wildcard(1)}}", 1),
                     },
                 })
                 .collect(),
+            name: stream_config.name,
             ..Default::default()
         })
         .collect();
@@ -237,20 +332,21 @@ pub async fn ensure_event_consumer_stream(
     let stream_config = StreamConfig {
         name: name.clone(),
         description,
-        num_replicas: 1,
+        num_replicas: settings.replicas,
         retention: async_nats::jetstream::stream::RetentionPolicy::WorkQueue,
         subjects: vec![],
         max_age: DEFAULT_EXPIRY_TIME,
         sources: Some(sources),
         storage: async_nats::jetstream::stream::StorageType::File,
         allow_rollup: false,
-        max_bytes,
+        max_bytes: settings.max_bytes,
+        max_messages: settings.max_messages,
         ..Default::default()
     };
 
     if let Ok(stream) = context.get_stream(&name).await {
         if stream.cached_info().config.retention == stream_config.retention {
-            return Ok(stream);
+            return Ok(reconcile_stream(context, stream, settings).await);
         } else {
             warn!("Found stream {name} with different configuration, deleting and recreating");
             context.delete_stream(name).await?;
@@ -267,27 +363,182 @@ pub async fn ensure_status_stream(
     context: &Context,
     name: String,
     subjects: Vec<String>,
-    max_bytes: i64,
+    settings: &StreamSettings,
+    max_messages_per_subject: i64,
 ) -> Result<Stream> {
     debug!("Ensuring stream {name} exists");
-    context
+    let stream = context
         .get_or_create_stream(StreamConfig {
             name,
             description: Some(
                 "A stream that stores all status updates for wadm applications".into(),
             ),
-            num_replicas: 1,
+            num_replicas: settings.replicas,
             allow_direct: true,
             retention: async_nats::jetstream::stream::RetentionPolicy::Limits,
-            max_messages_per_subject: 10,
+            max_messages_per_subject,
             subjects,
             max_age: std::time::Duration::from_nanos(0),
             storage: async_nats::jetstream::stream::StorageType::File,
-            max_bytes,
+            max_bytes: settings.max_bytes,
+            max_messages: settings.max_messages,
             ..Default::default()
         })
         .await
-        .map_err(|e| anyhow::anyhow!("{e:?}"))
+        .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+    Ok(reconcile_stream(context, stream, settings).await)
+}
+
+/// A helper that ensures that the reconcile report stream exists. Only the last report for each
+/// model is kept, since it is only used to show what wadm last did
+pub async fn ensure_reconcile_report_stream(
+    context: &Context,
+    name: String,
+    subjects: Vec<String>,
+    settings: &StreamSettings,
+) -> Result<Stream> {
+    debug!("Ensuring stream {name} exists");
+    let stream = context
+        .get_or_create_stream(StreamConfig {
+            name,
+            description: Some(
+                "A stream that stores the last reconcile report of each wadm application".into(),
+            ),
+            num_replicas: settings.replicas,
+            allow_direct: true,
+            retention: async_nats::jetstream::stream::RetentionPolicy::Limits,
+            max_messages_per_subject: 1,
+            subjects,
+            max_age: std::time::Duration::from_nanos(0),
+            storage: async_nats::jetstream::stream::StorageType::File,
+            max_bytes: settings.max_bytes,
+            max_messages: settings.max_messages,
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+    Ok(reconcile_stream(context, stream, settings).await)
+}
+
+/// A helper that ensures that the dead letter stream exists. Dead letters are kept until they are
+/// removed or the stream reaches its max bytes, so they can be inspected and replayed
+pub async fn ensure_dead_letter_stream(
+    context: &Context,
+    name: String,
+    subjects: Vec<String>,
+    settings: &StreamSettings,
+) -> Result<Stream> {
+    debug!("Ensuring stream {name} exists");
+    let stream = context
+        .get_or_create_stream(StreamConfig {
+            name,
+            description: Some(
+                "A stream that stores commands that wadm failed to execute after retrying".into(),
+            ),
+            num_replicas: settings.replicas,
+            allow_direct: true,
+            retention: async_nats::jetstream::stream::RetentionPolicy::Limits,
+            subjects,
+            max_age: std::time::Duration::from_nanos(0),
+            storage: async_nats::jetstream::stream::StorageType::File,
+            max_bytes: settings.max_bytes,
+            max_messages: settings.max_messages,
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+    Ok(reconcile_stream(context, stream, settings).await)
+}
+
+/// A helper that ensures that the quarantine stream exists. Quarantined messages are kept until
+/// they are removed or the stream reaches its max bytes, so they can be inspected
+pub async fn ensure_quarantine_stream(
+    context: &Context,
+    name: String,
+    subjects: Vec<String>,
+    settings: &StreamSettings,
+) -> Result<Stream> {
+    debug!("Ensuring stream {name} exists");
+    let stream = context
+        .get_or_create_stream(StreamConfig {
+            name,
+            description: Some(
+                "A stream that stores messages that wadm couldn't decode or kept failing to handle"
+                    .into(),
+            ),
+            num_replicas: settings.replicas,
+            allow_direct: true,
+            retention: async_nats::jetstream::stream::RetentionPolicy::Limits,
+            subjects,
+            max_age: std::time::Duration::from_nanos(0),
+            storage: async_nats::jetstream::stream::StorageType::File,
+            max_bytes: settings.max_bytes,
+            max_messages: settings.max_messages,
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+    Ok(reconcile_stream(context, stream, settings).await)
+}
+
+/// A helper that ensures that the state log stream exists. Changes are kept until the log is
+/// compacted into a snapshot, and only the last snapshot of each lattice is kept
+pub async fn ensure_state_log_stream(
+    context: &Context,
+    name: String,
+    subjects: Vec<String>,
+    settings: &StreamSettings,
+) -> Result<Stream> {
+    debug!("Ensuring stream {name} exists");
+    let stream = context
+        .get_or_create_stream(StreamConfig {
+            name,
+            description: Some(
+                "A stream that logs changes to the observed state of each lattice".into(),
+            ),
+            num_replicas: settings.replicas,
+            allow_direct: true,
+            retention: async_nats::jetstream::stream::RetentionPolicy::Limits,
+            subjects,
+            max_age: std::time::Duration::from_nanos(0),
+            storage: async_nats::jetstream::stream::StorageType::File,
+            max_bytes: settings.max_bytes,
+            max_messages: settings.max_messages,
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+    Ok(reconcile_stream(context, stream, settings).await)
+}
+
+/// A helper that ensures that the audit stream exists. Audit records are kept until the stream
+/// reaches its max bytes, so the history of a lattice outlives the models in it
+pub async fn ensure_audit_stream(
+    context: &Context,
+    name: String,
+    subjects: Vec<String>,
+    settings: &StreamSettings,
+) -> Result<Stream> {
+    debug!("Ensuring stream {name} exists");
+    let stream = context
+        .get_or_create_stream(StreamConfig {
+            name,
+            description: Some(
+                "A stream that records every mutating call made to the wadm API".into(),
+            ),
+            num_replicas: settings.replicas,
+            allow_direct: true,
+            retention: async_nats::jetstream::stream::RetentionPolicy::Limits,
+            subjects,
+            max_age: std::time::Duration::from_nanos(0),
+            storage: async_nats::jetstream::stream::StorageType::File,
+            max_bytes: settings.max_bytes,
+            max_messages: settings.max_messages,
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+    Ok(reconcile_stream(context, stream, settings).await)
 }
 
 /// A helper that ensures that the notify stream exists
@@ -295,44 +546,183 @@ pub async fn ensure_notify_stream(
     context: &Context,
     name: String,
     subjects: Vec<String>,
-    max_bytes: i64,
+    settings: &StreamSettings,
 ) -> Result<Stream> {
     debug!("Ensuring stream {name} exists");
-    context
+    let stream = context
         .get_or_create_stream(StreamConfig {
             name,
             description: Some("A stream for capturing all notification events for wadm".into()),
-            num_replicas: 1,
+            num_replicas: settings.replicas,
             retention: async_nats::jetstream::stream::RetentionPolicy::Interest,
             subjects,
             max_age: DEFAULT_EXPIRY_TIME,
             storage: async_nats::jetstream::stream::StorageType::File,
-            max_bytes,
+            max_bytes: settings.max_bytes,
+            max_messages: settings.max_messages,
             ..Default::default()
         })
         .await
-        .map_err(|e| anyhow::anyhow!("{e:?}"))
+        .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+    Ok(reconcile_stream(context, stream, settings).await)
 }
 
-/// A helper that ensures that the given KV bucket exists, using defaults to create if it does
-/// not. Returns the handle to the stream
+/// Settings for the streams wadm creates
+#[derive(Clone, Debug)]
+pub struct StreamSettings {
+    /// The number of replicas for the stream
+    pub replicas: usize,
+    /// The maximum size of the stream in bytes. -1 means unlimited
+    pub max_bytes: i64,
+    /// The maximum number of messages in the stream. -1 means unlimited
+    pub max_messages: i64,
+}
+
+impl Default for StreamSettings {
+    fn default() -> Self {
+        StreamSettings {
+            replicas: 1,
+            max_bytes: -1,
+            max_messages: -1,
+        }
+    }
+}
+
+impl StreamSettings {
+    /// Updates the given stream config to match these settings, returning the names of any
+    /// settings that had drifted
+    fn repair(&self, config: &mut StreamConfig) -> Vec<&'static str> {
+        let mut drifted = Vec::new();
+        if config.num_replicas != self.replicas {
+            config.num_replicas = self.replicas;
+            drifted.push("replicas");
+        }
+        if config.max_bytes != self.max_bytes {
+            config.max_bytes = self.max_bytes;
+            drifted.push("max_bytes");
+        }
+        if config.max_messages != self.max_messages {
+            config.max_messages = self.max_messages;
+            drifted.push("max_messages");
+        }
+        drifted
+    }
+}
+
+/// Updates an existing stream in place if its settings have drifted. Some changes can't be made
+/// to a running stream (like more replicas than there are servers in the cluster), so a failed
+/// update is logged and the stream is used with the settings it has
+async fn reconcile_stream(context: &Context, stream: Stream, settings: &StreamSettings) -> Stream {
+    let mut config = stream.cached_info().config.clone();
+    let drifted = settings.repair(&mut config);
+    if drifted.is_empty() {
+        return stream;
+    }
+    let name = config.name.clone();
+    warn!(stream = %name, ?drifted, "Found stream with different settings, updating");
+    if let Err(e) = context.update_stream(config).await {
+        error!(
+            stream = %name,
+            ?drifted,
+            error = ?e,
+            "Unable to update stream settings, the stream will keep running with its current settings"
+        );
+        return stream;
+    }
+    // Fetch the stream again so its cached info reflects the new settings
+    match context.get_stream(&name).await {
+        Ok(updated) => updated,
+        Err(e) => {
+            warn!(stream = %name, error = ?e, "Unable to fetch updated stream info");
+            stream
+        }
+    }
+}
+
+/// Settings for the KV buckets wadm creates
+#[derive(Clone, Debug)]
+pub struct KvBucketSettings {
+    /// The number of replicas for the bucket
+    pub replicas: usize,
+    /// The number of historical values to keep per key
+    pub history: i64,
+    /// How long to keep values in the bucket. A zero duration keeps values forever
+    pub max_age: std::time::Duration,
+    /// The maximum size of the bucket in bytes. -1 means unlimited
+    pub max_bytes: i64,
+}
+
+impl Default for KvBucketSettings {
+    fn default() -> Self {
+        KvBucketSettings {
+            replicas: 1,
+            history: 1,
+            max_age: std::time::Duration::from_nanos(0),
+            max_bytes: -1,
+        }
+    }
+}
+
+impl KvBucketSettings {
+    /// Updates the given KV stream config to match these settings, returning the names of any
+    /// settings that had drifted
+    fn repair(&self, config: &mut StreamConfig) -> Vec<&'static str> {
+        let mut drifted = Vec::new();
+        if config.num_replicas != self.replicas {
+            config.num_replicas = self.replicas;
+            drifted.push("replicas");
+        }
+        // KV buckets store their history as the max messages per subject
+        if config.max_messages_per_subject != self.history {
+            config.max_messages_per_subject = self.history;
+            drifted.push("history");
+        }
+        if config.max_age != self.max_age {
+            config.max_age = self.max_age;
+            drifted.push("max_age");
+        }
+        if config.max_bytes != self.max_bytes {
+            config.max_bytes = self.max_bytes;
+            drifted.push("max_bytes");
+        }
+        drifted
+    }
+}
+
+/// A helper that ensures that the given KV bucket exists with the given settings, creating it if it
+/// does not. If the bucket already exists but its settings have drifted, they are updated in place
+/// so no data is lost. Returns the handle to the bucket
 pub async fn ensure_kv_bucket(
     context: &Context,
     name: String,
-    history_to_keep: i64,
-    max_bytes: i64,
+    settings: &KvBucketSettings,
 ) -> Result<Store> {
     debug!("Ensuring kv bucket {name} exists");
     if let Ok(kv) = context.get_key_value(&name).await {
-        Ok(kv)
+        let mut config = kv.stream.cached_info().config.clone();
+        let drifted = settings.repair(&mut config);
+        if drifted.is_empty() {
+            return Ok(kv);
+        }
+        warn!(bucket = %name, ?drifted, "Found kv bucket with different settings, updating");
+        context
+            .update_stream(config)
+            .await
+            .map_err(|e| anyhow::anyhow!("Unable to update kv bucket {name}: {e:?}"))?;
+        // Fetch the bucket again so its cached info reflects the new settings
+        context
+            .get_key_value(&name)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))
     } else {
         context
             .create_key_value(KvConfig {
                 bucket: name,
-                history: history_to_keep,
-                num_replicas: 1,
+                history: settings.history,
+                num_replicas: settings.replicas,
                 storage: jetstream::stream::StorageType::File,
-                max_bytes,
+                max_age: settings.max_age,
+                max_bytes: settings.max_bytes,
                 ..Default::default()
             })
             .await
@@ -340,9 +730,46 @@ pub async fn ensure_kv_bucket(
     }
 }
 
+/// Copies every key in the bucket with the given name that doesn't exist in the target bucket yet,
+/// returning how many were copied. This is used to move data over when the name of a bucket is
+/// changed, so it is safe to run on every startup. Nothing is copied if the bucket doesn't exist
+pub async fn copy_kv_bucket(context: &Context, from: &str, to: &Store) -> Result<usize> {
+    let Ok(source) = context.get_key_value(from).await else {
+        warn!(bucket = %from, "Bucket to copy from doesn't exist, skipping");
+        return Ok(0);
+    };
+    let keys: Vec<String> = source
+        .keys()
+        .await
+        .map_err(|e| anyhow!("Unable to list keys in bucket {from}: {e:?}"))?
+        .try_collect()
+        .await
+        .map_err(|e| anyhow!("Unable to list keys in bucket {from}: {e:?}"))?;
+    let mut copied = 0;
+    for key in keys {
+        let Some(value) = source
+            .get(&key)
+            .await
+            .map_err(|e| anyhow!("Unable to read {key} from bucket {from}: {e:?}"))?
+        else {
+            continue;
+        };
+        // NOTE: Creating instead of putting leaves anything already in the target alone
+        match to.create(&key, value).await {
+            Ok(_) => copied += 1,
+            Err(e) if e.kind() == CreateErrorKind::AlreadyExists => (),
+            Err(e) => return Err(anyhow!("Unable to copy {key} from bucket {from}: {e:?}")),
+        }
+    }
+    Ok(copied)
+}
+
 #[cfg(test)]
 mod test {
-    use super::resolve_jwt;
+    use super::{
+        carried_subjects, resolve_jwt, source_keys, KvBucketSettings, Source, StreamConfig,
+        StreamSettings,
+    };
     use anyhow::Result;
 
     #[tokio::test]
@@ -366,4 +793,95 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn can_repair_drifted_kv_settings() {
+        let settings = KvBucketSettings {
+            replicas: 3,
+            history: 5,
+            max_age: std::time::Duration::from_secs(60),
+            max_bytes: -1,
+        };
+        let mut config = StreamConfig {
+            name: "KV_wadm_state".to_string(),
+            num_replicas: 1,
+            max_messages_per_subject: 5,
+            max_age: std::time::Duration::from_secs(60),
+            max_bytes: -1,
+            ..Default::default()
+        };
+
+        assert_eq!(settings.repair(&mut config), vec!["replicas"]);
+        assert_eq!(config.num_replicas, 3);
+        assert!(
+            settings.repair(&mut config).is_empty(),
+            "Repaired config should no longer have drifted"
+        );
+
+        config.max_messages_per_subject = 1;
+        config.max_bytes = 1024;
+        assert_eq!(settings.repair(&mut config), vec!["history", "max_bytes"]);
+        assert_eq!(config.max_messages_per_subject, 5);
+        assert_eq!(config.max_bytes, -1);
+    }
+
+    #[test]
+    fn can_repair_drifted_stream_settings() {
+        let settings = StreamSettings {
+            replicas: 3,
+            max_bytes: 1024,
+            max_messages: -1,
+        };
+        let mut config = StreamConfig {
+            name: "wadm_commands".to_string(),
+            num_replicas: 1,
+            max_bytes: 1024,
+            max_messages: 100,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            settings.repair(&mut config),
+            vec!["replicas", "max_messages"]
+        );
+        assert_eq!(config.num_replicas, 3);
+        assert_eq!(config.max_messages, -1);
+        assert!(
+            settings.repair(&mut config).is_empty(),
+            "Repaired config should no longer have drifted"
+        );
+    }
+
+    #[test]
+    fn can_find_subjects_of_sourced_streams() {
+        let source = |name: &str, filter: &str| Source {
+            name: name.to_string(),
+            filter_subject: Some(filter.to_string()),
+            ..Default::default()
+        };
+        let mirror = StreamConfig {
+            name: "wasmbus_events".to_string(),
+            mirror: Some(source("events", "wasmbus.evt.*.>")),
+            ..Default::default()
+        };
+        assert_eq!(carried_subjects(&mirror), vec!["wasmbus.evt.*.>"]);
+
+        let sourced = StreamConfig {
+            name: "wasmbus_events".to_string(),
+            sources: Some(vec![
+                source("west", "wasmbus.evt.*.>"),
+                source("east", "wasmbus.evt.*.>"),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(carried_subjects(&sourced), vec!["wasmbus.evt.*.>"]);
+        assert_eq!(
+            source_keys(sourced.sources.iter().flatten()),
+            vec![
+                ("east", Some("wasmbus.evt.*.>")),
+                ("west", Some("wasmbus.evt.*.>"))
+            ],
+            "Sources should be compared regardless of their order"
+        );
+    }
 }
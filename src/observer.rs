@@ -1,30 +1,79 @@
 //! Types for observing a nats cluster for new lattices
 
-use async_nats::Subscriber;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use async_nats::{Message, Subscriber};
 use futures::{stream::SelectAll, StreamExt, TryFutureExt};
-use tracing::{debug, error, instrument, trace, warn};
+use tokio::sync::{mpsc::UnboundedReceiver, watch};
+use tracing::{debug, error, info, instrument, trace, warn};
 
 use wadm::{
+    config::RuntimeConfig,
     consumers::{
         manager::{ConsumerManager, WorkerCreator},
         CommandConsumer, EventConsumer,
     },
     events::{EventType, HostHeartbeat, HostStarted, ManifestPublished},
-    nats_utils::LatticeIdParser,
-    storage::{nats_kv::NatsKvStore, reaper::Reaper, Store},
-    DEFAULT_COMMANDS_TOPIC, DEFAULT_WADM_EVENT_CONSUMER_TOPIC,
+    hibernation::{LatticeActivity, LatticeRef},
+    leadership::LatticeLeases,
+    nats_utils::{lattice_subject, LatticeIdParser},
+    sharding::ShardMembership,
+    storage::{nats_kv::NatsKvStore, reaper::Reaper, state_log::LoggedStore, Store},
+    DEFAULT_WADM_EVENT_CONSUMER_TOPIC,
 };
 
 use super::{CommandWorkerCreator, EventWorkerCreator};
 
 pub(crate) struct Observer<StateStore> {
     pub(crate) parser: LatticeIdParser,
+    /// The prefix of the topics commands for each lattice are published on
+    pub(crate) command_topic_prefix: String,
     pub(crate) command_manager: ConsumerManager<CommandConsumer>,
     pub(crate) event_manager: ConsumerManager<EventConsumer>,
     pub(crate) client: async_nats::Client,
-    pub(crate) reaper: Reaper<NatsKvStore>,
+    pub(crate) reaper: Reaper<LoggedStore<NatsKvStore>>,
     pub(crate) event_worker_creator: EventWorkerCreator<StateStore>,
     pub(crate) command_worker_creator: CommandWorkerCreator,
+    pub(crate) hibernation: Option<Hibernation>,
+    pub(crate) leadership: Option<Leadership>,
+    pub(crate) sharding: Option<Sharding>,
+    pub(crate) config: watch::Receiver<RuntimeConfig>,
+}
+
+/// Settings for leader election between wadm processes
+pub(crate) struct Leadership {
+    pub(crate) leases: LatticeLeases,
+    pub(crate) lease_ttl: Duration,
+}
+
+/// Settings for sharding lattices between wadm replicas
+pub(crate) struct Sharding {
+    membership: ShardMembership,
+    announce_every: Duration,
+    /// Ownership isn't decided until this point, giving this replica time to hear from the other
+    /// members so it doesn't briefly claim every lattice when it starts
+    settle_until: Instant,
+    /// The lattices this replica is currently running
+    running: HashSet<String>,
+}
+
+impl Sharding {
+    pub(crate) fn new(membership: ShardMembership, announce_every: Duration) -> Sharding {
+        Sharding {
+            membership,
+            announce_every,
+            settle_until: Instant::now() + announce_every,
+            running: HashSet::new(),
+        }
+    }
+}
+
+/// Settings for hibernating lattices that have been idle for a while
+pub(crate) struct Hibernation {
+    pub(crate) activity: LatticeActivity,
+    pub(crate) wake: UnboundedReceiver<LatticeRef>,
+    pub(crate) idle_for: Duration,
 }
 
 impl<StateStore> Observer<StateStore>
@@ -34,81 +83,320 @@ where
     /// Watches the given topic (with wildcards) for wasmbus events. If it finds a lattice that it
     /// isn't managing, it will start managing it immediately
     ///
+    /// If hibernation is enabled, this also tears down lattices that have been idle for too long
+    /// and wakes them back up when they see activity
+    ///
     /// If this errors, it should be considered fatal
     #[instrument(level = "info", skip(self))]
     pub(crate) async fn observe(mut self, subscribe_topics: Vec<String>) -> anyhow::Result<()> {
         let mut sub = get_subscriber(&self.client, subscribe_topics.clone()).await?;
+        let (activity, mut wake, idle_for) = match self.hibernation.take() {
+            Some(hibernation) => (
+                Some(hibernation.activity),
+                Some(hibernation.wake),
+                hibernation.idle_for,
+            ),
+            None => (None, None, Duration::MAX),
+        };
+        // Check a few times per idle period so lattices are hibernated close to when they go idle
+        let mut idle_check = tokio::time::interval(
+            (idle_for / 4).clamp(Duration::from_secs(1), Duration::from_secs(60)),
+        );
+        // Renew leases well before they expire so a slow renewal doesn't cause a failover
+        let lease_ttl = self
+            .leadership
+            .as_ref()
+            .map(|l| l.lease_ttl)
+            .unwrap_or(Duration::MAX);
+        let mut lease_renewal = tokio::time::interval(
+            (lease_ttl / 3).clamp(Duration::from_secs(1), Duration::from_secs(60)),
+        );
+        let mut members = match self.sharding.as_ref() {
+            Some(sharding) => Some(sharding.membership.subscribe().await?),
+            None => None,
+        };
+        let mut announce = tokio::time::interval(
+            self.sharding
+                .as_ref()
+                .map(|s| s.announce_every)
+                .unwrap_or(Duration::MAX)
+                .max(Duration::from_secs(1)),
+        );
+        let mut config = self.config.clone();
         loop {
-            match sub.next().await {
-                Some(msg) => {
-                    if !is_event_we_care_about(&msg.payload) {
-                        continue;
+            tokio::select! {
+                msg = sub.next() => match msg {
+                    Some(msg) => self.handle_event(msg, activity.as_ref()).await,
+                    None => {
+                        warn!("Observer subscriber hang up. Attempting to restart");
+                        sub = get_subscriber(&self.client, subscribe_topics.clone()).await?;
                     }
-
-                    let Some(lattice_info) = self.parser.parse(&msg.subject) else {
-                        trace!(subject = %msg.subject, "Found non-matching lattice subject");
+                },
+                Some(lattice) = async { wake.as_mut()?.recv().await }, if wake.is_some() => {
+                    info!(lattice_id = %lattice.lattice_id, "Waking hibernated lattice");
+                    if !self.lead(&lattice.lattice_id).await {
                         continue;
-                    };
-                    let lattice_id = lattice_info.lattice_id();
-                    let multitenant_prefix = lattice_info.multitenant_prefix();
-                    let event_subject = lattice_info.event_subject();
-
-                    // Create the reaper for this lattice. This operation returns early if it is
-                    // already running
-                    self.reaper.observe(lattice_id);
-
-                    let command_topic = DEFAULT_COMMANDS_TOPIC.replace('*', lattice_id);
-                    let events_topic = DEFAULT_WADM_EVENT_CONSUMER_TOPIC.replace('*', lattice_id);
-                    let needs_command = !self.command_manager.has_consumer(&command_topic).await;
-                    let needs_event = !self.event_manager.has_consumer(&events_topic).await;
-                    if needs_command {
-                        debug!(%lattice_id, subject = %event_subject, mapped_subject = %command_topic, "Found unmonitored lattice, adding command consumer");
-                        let worker = match self
-                            .command_worker_creator
-                            .create(lattice_id, multitenant_prefix)
-                            .await
-                        {
-                            Ok(w) => w,
-                            Err(e) => {
-                                error!(error = %e, %lattice_id, "Couldn't construct worker for command consumer. Will retry on next heartbeat");
-                                continue;
-                            }
-                        };
-                        self.command_manager
-                            .add_for_lattice(&command_topic, lattice_id, multitenant_prefix, worker)
-                            .await
-                            .unwrap_or_else(|e| {
-                                error!(error = %e, %lattice_id, "Couldn't add command consumer. Will retry on next heartbeat");
-                            })
                     }
-                    if needs_event {
-                        debug!(%lattice_id, subject = %event_subject, mapped_subject = %events_topic,  "Found unmonitored lattice, adding event consumer");
-                        let worker = match self
-                            .event_worker_creator
-                            .create(lattice_id, multitenant_prefix)
-                            .await
-                        {
-                            Ok(w) => w,
-                            Err(e) => {
-                                error!(error = %e, %lattice_id, "Couldn't construct worker for event consumer. Will retry on next heartbeat");
-                                continue;
-                            }
-                        };
-                        self.event_manager
-                            .add_for_lattice(&events_topic, lattice_id, multitenant_prefix, worker)
-                            .await
-                            .unwrap_or_else(|e| {
-                                error!(error = %e, %lattice_id, "Couldn't add event consumer. Will retry on next heartbeat");
-                            })
+                    self.reaper.observe(&lattice.lattice_id);
+                    self.ensure_consumers(&lattice.lattice_id, lattice.multitenant_prefix.as_deref())
+                        .await;
+                }
+                _ = idle_check.tick(), if activity.is_some() => {
+                    for lattice in activity.iter().flat_map(|a| a.hibernate_idle(idle_for)) {
+                        self.hibernate(&lattice).await;
+                    }
+                }
+                _ = lease_renewal.tick(), if self.leadership.is_some() => {
+                    self.renew_leases().await;
+                }
+                msg = async { members.as_mut()?.next().await }, if members.is_some() => match msg {
+                    Some(msg) => self.record_member(&msg.payload).await,
+                    None => {
+                        warn!("Shard membership subscriber hang up. Attempting to restart");
+                        if let Some(sharding) = self.sharding.as_ref() {
+                            members = Some(sharding.membership.subscribe().await?);
+                        }
+                    }
+                },
+                _ = announce.tick(), if self.sharding.is_some() => {
+                    self.announce().await;
+                }
+                Ok(()) = config.changed() => {
+                    // NOTE: The reaper checks twice per cleanup interval, see where it is created
+                    if let Some(interval) = config.borrow_and_update().cleanup_interval() {
+                        self.reaper.set_interval(interval / 2);
                     }
                 }
-                None => {
-                    warn!("Observer subscriber hang up. Attempting to restart");
-                    sub = get_subscriber(&self.client, subscribe_topics.clone()).await?;
+            }
+        }
+    }
+
+    async fn handle_event(&mut self, msg: Message, activity: Option<&LatticeActivity>) {
+        // Without hibernation, only the events that can add a lattice matter, so skip everything
+        // else before parsing the subject
+        if activity.is_none() && !is_event_we_care_about(&msg.payload) {
+            return;
+        }
+
+        let Some(lattice_info) = self.parser.parse(&msg.subject) else {
+            trace!(subject = %msg.subject, "Found non-matching lattice subject");
+            return;
+        };
+        let lattice_id = lattice_info.lattice_id();
+        let multitenant_prefix = lattice_info.multitenant_prefix();
+
+        if let Some(activity) = activity {
+            // Heartbeats are sent constantly, so they don't count as activity and don't wake up a
+            // hibernated lattice. Anything else does
+            if is_heartbeat(&msg.payload) {
+                activity.observe(lattice_id, multitenant_prefix);
+                if activity.is_hibernated(lattice_id) {
+                    trace!(%lattice_id, "Ignoring heartbeat for hibernated lattice");
+                    return;
+                }
+            } else {
+                activity.touch(lattice_id, multitenant_prefix);
+            }
+            if !is_event_we_care_about(&msg.payload) {
+                return;
+            }
+        }
+
+        if !self.lead(lattice_id).await {
+            trace!(%lattice_id, "Lattice is handled by another process, staying on standby");
+            return;
+        }
+
+        // Create the reaper for this lattice. This operation returns early if it is
+        // already running
+        self.reaper.observe(lattice_id);
+
+        self.ensure_consumers(lattice_id, multitenant_prefix).await;
+    }
+
+    /// Returns whether this process should reconcile the given lattice, trying to become its
+    /// leader if it isn't already. With sharding, this is whether the lattice belongs to this
+    /// replica's shard. Always returns true if neither is enabled
+    async fn lead(&mut self, lattice_id: &str) -> bool {
+        if let Some(sharding) = self.sharding.as_mut() {
+            if Instant::now() < sharding.settle_until || !sharding.membership.owns(lattice_id) {
+                return false;
+            }
+            sharding.running.insert(lattice_id.to_owned());
+            return true;
+        }
+        let Some(leadership) = self.leadership.as_ref() else {
+            return true;
+        };
+        // Held leases are renewed on an interval, so there is no need to renew on every event
+        if leadership.leases.is_leader(lattice_id) {
+            return true;
+        }
+        leadership
+            .leases
+            .try_acquire(lattice_id)
+            .await
+            .unwrap_or_else(|e| {
+                warn!(error = ?e, %lattice_id, "Unable to acquire lease for lattice. Will retry on next heartbeat");
+                false
+            })
+    }
+
+    /// Renews all held leases, stopping the consumers for any lattice whose lease was lost
+    async fn renew_leases(&self) {
+        let Some(leadership) = self.leadership.as_ref() else {
+            return;
+        };
+        for lattice_id in leadership.leases.held_lattices() {
+            match leadership.leases.try_acquire(&lattice_id).await {
+                Ok(true) => (),
+                Ok(false) => {
+                    info!(%lattice_id, "No longer the leader for lattice, stopping consumers");
+                    self.stop_consumers(&lattice_id).await;
                 }
+                // NOTE: If we can't reach NATS, we keep going and try again on the next renewal.
+                // If the lease expires in the meantime, the next renewal will see it was lost
+                Err(e) => warn!(error = ?e, %lattice_id, "Unable to renew lease for lattice"),
             }
         }
     }
+
+    /// Records an announcement from another replica, handing off any lattices that now belong to
+    /// someone else
+    async fn record_member(&mut self, payload: &[u8]) {
+        let Some(sharding) = self.sharding.as_ref() else {
+            return;
+        };
+        if sharding.membership.record(payload) {
+            // Announce right away so a new member hears from us before it starts claiming lattices
+            if let Err(e) = sharding.membership.announce().await {
+                warn!(error = ?e, "Unable to announce shard membership");
+            }
+            self.rebalance().await;
+        }
+    }
+
+    /// Announces this replica to the others and forgets about members that have gone away
+    async fn announce(&mut self) {
+        let Some(sharding) = self.sharding.as_ref() else {
+            return;
+        };
+        if let Err(e) = sharding.membership.announce().await {
+            warn!(error = ?e, "Unable to announce shard membership. Will retry on next interval");
+        }
+        if sharding.membership.expire_stale() {
+            self.rebalance().await;
+        }
+    }
+
+    /// Stops the consumers for every running lattice that no longer belongs to this replica.
+    /// Lattices that now belong to this replica are picked up on their next heartbeat
+    async fn rebalance(&mut self) {
+        let Some(sharding) = self.sharding.as_mut() else {
+            return;
+        };
+        let moved: Vec<String> = sharding
+            .running
+            .iter()
+            .filter(|lattice_id| !sharding.membership.owns(lattice_id))
+            .cloned()
+            .collect();
+        for lattice_id in moved.iter() {
+            sharding.running.remove(lattice_id);
+        }
+        for lattice_id in moved {
+            info!(%lattice_id, "Lattice moved to another shard, stopping consumers");
+            self.stop_consumers(&lattice_id).await;
+            self.reaper.remove(&lattice_id);
+        }
+    }
+
+    /// Starts the command and event consumers for the given lattice if they aren't running
+    async fn ensure_consumers(&self, lattice_id: &str, multitenant_prefix: Option<&str>) {
+        let command_topic =
+            lattice_subject(&self.command_topic_prefix, lattice_id, multitenant_prefix);
+        let events_topic = format!(
+            "{}.>",
+            lattice_subject(
+                DEFAULT_WADM_EVENT_CONSUMER_TOPIC.trim_end_matches("*.>"),
+                lattice_id,
+                multitenant_prefix,
+            )
+        );
+        let needs_command = !self.command_manager.has_consumer(&command_topic).await;
+        let needs_event = !self.event_manager.has_consumer(&events_topic).await;
+        if needs_command {
+            debug!(%lattice_id, mapped_subject = %command_topic, "Found unmonitored lattice, adding command consumer");
+            let worker = match self
+                .command_worker_creator
+                .create(lattice_id, multitenant_prefix)
+                .await
+            {
+                Ok(w) => w,
+                Err(e) => {
+                    error!(error = %e, %lattice_id, "Couldn't construct worker for command consumer. Will retry on next heartbeat");
+                    return;
+                }
+            };
+            self.command_manager
+                .add_for_lattice(&command_topic, lattice_id, multitenant_prefix, worker)
+                .await
+                .unwrap_or_else(|e| {
+                    error!(error = %e, %lattice_id, "Couldn't add command consumer. Will retry on next heartbeat");
+                })
+        }
+        if needs_event {
+            debug!(%lattice_id, mapped_subject = %events_topic,  "Found unmonitored lattice, adding event consumer");
+            let worker = match self
+                .event_worker_creator
+                .create(lattice_id, multitenant_prefix)
+                .await
+            {
+                Ok(w) => w,
+                Err(e) => {
+                    error!(error = %e, %lattice_id, "Couldn't construct worker for event consumer. Will retry on next heartbeat");
+                    return;
+                }
+            };
+            self.event_manager
+                .add_for_lattice(&events_topic, lattice_id, multitenant_prefix, worker)
+                .await
+                .unwrap_or_else(|e| {
+                    error!(error = %e, %lattice_id, "Couldn't add event consumer. Will retry on next heartbeat");
+                })
+        }
+    }
+
+    /// Stops the consumers and reaper for an idle lattice. Dropping the event worker also drops
+    /// the lattice's scalers and any state they had cached. The durable consumers are left in
+    /// place so nothing is lost while the lattice is hibernated
+    async fn hibernate(&mut self, lattice: &LatticeRef) {
+        let lattice_id = lattice.lattice_id.as_str();
+        info!(%lattice_id, "Hibernating idle lattice");
+        self.stop_consumers(lattice_id).await;
+        self.reaper.remove(lattice_id);
+        // Give up leadership so whichever process sees activity first can wake the lattice
+        if let Some(leadership) = self.leadership.as_ref() {
+            leadership.leases.release(lattice_id).await;
+        }
+        if let Some(sharding) = self.sharding.as_mut() {
+            sharding.running.remove(lattice_id);
+        }
+    }
+
+    /// Stops the command and event consumers for the given lattice. Leases and shards are held per
+    /// lattice ID, so in multitenant mode this stops the lattice in every account
+    async fn stop_consumers(&self, lattice_id: &str) {
+        self.command_manager
+            .remove_all_for_lattice(lattice_id)
+            .await;
+        self.event_manager.remove_all_for_lattice(lattice_id).await;
+    }
+}
+
+// Same trick as below to check for heartbeats without parsing the event
+fn is_heartbeat(data: &[u8]) -> bool {
+    std::str::from_utf8(data).is_ok_and(|s| s.contains(HostHeartbeat::TYPE))
 }
 
 // This is a stupid hacky function to check that this is a host started, host heartbeat, or
@@ -0,0 +1,424 @@
+//! Credentials for private registries, and resolution of image tags to digests and pulling of
+//! artifacts against OCI registries, using the credentials in a Docker style auth config
+//! (`~/.docker/config.json`)
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use reqwest::{header, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use wadm::oci::{DigestResolver, ImageReference, DEFAULT_REGISTRY};
+use wasmcloud_control_interface::RegistryCredential;
+
+/// The manifest types wadm accepts, so registries return the digest of the same manifest a host
+/// would pull
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json, application/vnd.docker.distribution.manifest.v2+json, application/vnd.docker.distribution.manifest.list.v2+json";
+const DIGEST_HEADER: &str = "Docker-Content-Digest";
+/// The only manifest type accepted for artifacts, since the files are the layers of the manifest
+#[cfg(feature = "gitops")]
+const ARTIFACT_MANIFEST_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+/// The annotation tools like `oras` set to the name of the file in a layer
+#[cfg(feature = "gitops")]
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+
+/// The credentials for a single registry in an auth config
+#[derive(Debug, Clone, Default, Deserialize)]
+struct RegistryAuth {
+    /// The base64 encoded `username:password`
+    #[serde(default)]
+    auth: Option<String>,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    /// A token that is exchanged for an access token by registries that use token auth
+    #[serde(default)]
+    identitytoken: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AuthConfig {
+    #[serde(default)]
+    auths: HashMap<String, RegistryAuth>,
+}
+
+#[cfg(feature = "gitops")]
+#[derive(Debug, Deserialize)]
+struct ArtifactManifest {
+    #[serde(default)]
+    layers: Vec<ArtifactLayer>,
+}
+
+#[cfg(feature = "gitops")]
+#[derive(Debug, Deserialize)]
+struct ArtifactLayer {
+    digest: String,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    #[serde(default)]
+    token: Option<String>,
+    #[serde(default)]
+    access_token: Option<String>,
+}
+
+/// A [`DigestResolver`] that looks up digests with HEAD requests to the registry API
+pub(crate) struct RegistryDigestResolver {
+    client: reqwest::Client,
+    auths: HashMap<String, RegistryAuth>,
+    insecure: HashSet<String>,
+}
+
+impl RegistryDigestResolver {
+    /// Creates a resolver that authenticates with the credentials in the given auth config, if
+    /// any. Registries in `insecure` are accessed over plain HTTP
+    pub(crate) async fn new(
+        auth_file: Option<&Path>,
+        insecure: impl IntoIterator<Item = String>,
+    ) -> anyhow::Result<RegistryDigestResolver> {
+        let config: AuthConfig = match auth_file {
+            Some(path) => {
+                let data = tokio::fs::read(path).await.with_context(|| {
+                    format!("Unable to read registry auth file {}", path.display())
+                })?;
+                serde_json::from_slice(&data).with_context(|| {
+                    format!("Unable to parse registry auth file {}", path.display())
+                })?
+            }
+            None => AuthConfig::default(),
+        };
+        Ok(RegistryDigestResolver {
+            client: reqwest::Client::new(),
+            auths: config
+                .auths
+                .into_iter()
+                .map(|(registry, auth)| (normalize_registry(&registry), auth))
+                .collect(),
+            insecure: insecure.into_iter().collect(),
+        })
+    }
+
+    /// Adds the given registry credentials for any registry the auth config doesn't have
+    /// credentials for
+    pub(crate) fn with_registry_credentials(
+        mut self,
+        credentials: &HashMap<String, RegistryCredential>,
+    ) -> RegistryDigestResolver {
+        for (registry, credential) in credentials {
+            self.auths
+                .entry(normalize_registry(registry))
+                .or_insert_with(|| RegistryAuth {
+                    username: credential.username().map(ToOwned::to_owned),
+                    password: credential
+                        .password()
+                        .or(credential.token())
+                        .map(ToOwned::to_owned),
+                    ..Default::default()
+                });
+        }
+        self
+    }
+
+    fn with_credentials(&self, request: RequestBuilder, registry: &str) -> RequestBuilder {
+        match self.auths.get(registry) {
+            Some(RegistryAuth {
+                auth: Some(encoded),
+                ..
+            }) => request.header(header::AUTHORIZATION, format!("Basic {encoded}")),
+            Some(RegistryAuth {
+                username: Some(username),
+                password,
+                ..
+            }) => request.basic_auth(username, password.as_ref()),
+            _ => request,
+        }
+    }
+
+    /// Fetches an access token for the challenge a registry returned
+    async fn fetch_token(
+        &self,
+        registry: &str,
+        challenge: &HashMap<String, String>,
+    ) -> anyhow::Result<String> {
+        let realm = challenge
+            .get("realm")
+            .context("Registry didn't say where to get a token")?;
+        let query: Vec<(&str, &str)> = ["service", "scope"]
+            .into_iter()
+            .filter_map(|key| challenge.get(key).map(|value| (key, value.as_str())))
+            .collect();
+        let request = match self.auths.get(registry) {
+            Some(RegistryAuth {
+                identitytoken: Some(token),
+                ..
+            }) => self
+                .client
+                .post(realm)
+                .form(&[("grant_type", "refresh_token"), ("refresh_token", token)]),
+            _ => self.with_credentials(self.client.get(realm), registry),
+        };
+        let resp: TokenResponse = request
+            .query(&query)
+            .send()
+            .await
+            .and_then(Response::error_for_status)
+            .context("Unable to get a token from the registry")?
+            .json()
+            .await
+            .context("Unable to parse the token from the registry")?;
+        resp.token
+            .or(resp.access_token)
+            .context("Registry didn't return a token")
+    }
+}
+
+#[async_trait]
+impl DigestResolver for RegistryDigestResolver {
+    async fn resolve_digest(&self, image: &ImageReference) -> anyhow::Result<String> {
+        let url = self.api_url(image, &format!("manifests/{}", image.manifest_reference()));
+        let resp = self
+            .send(&image.registry, || {
+                self.client
+                    .head(&url)
+                    .header(header::ACCEPT, MANIFEST_TYPES)
+            })
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Unable to fetch the manifest from {url}"))?;
+        resp.headers()
+            .get(DIGEST_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned)
+            .with_context(|| format!("Registry didn't return a digest for {url}"))
+    }
+}
+
+impl RegistryDigestResolver {
+    /// Returns the URL of the given path under the API of the repository of the image
+    fn api_url(&self, image: &ImageReference, path: &str) -> String {
+        let scheme = if self.insecure.contains(&image.registry) {
+            "http"
+        } else {
+            "https"
+        };
+        // Docker Hub serves its API from a different host than its name
+        let host = if image.registry == DEFAULT_REGISTRY {
+            "registry-1.docker.io"
+        } else {
+            image.registry.as_str()
+        };
+        format!("{scheme}://{host}/v2/{}/{path}", image.repository)
+    }
+
+    /// Sends the request with the credentials for the registry, retrying with an access token if
+    /// the registry asks for one
+    async fn send(
+        &self,
+        registry: &str,
+        request: impl Fn() -> RequestBuilder,
+    ) -> anyhow::Result<Response> {
+        let resp = self.with_credentials(request(), registry).send().await?;
+        if resp.status() != StatusCode::UNAUTHORIZED {
+            return Ok(resp);
+        }
+        let challenge = resp
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_bearer_challenge);
+        match challenge {
+            Some(challenge) => {
+                let token = self.fetch_token(registry, &challenge).await?;
+                Ok(request().bearer_auth(token).send().await?)
+            }
+            None => Ok(resp),
+        }
+    }
+
+    /// Pulls the files in an OCI artifact, like one pushed with `oras push`. Each layer of the
+    /// artifact is a file, named by its `org.opencontainers.image.title` annotation. Returns the
+    /// digest of the artifact and its files
+    #[cfg(feature = "gitops")]
+    pub(crate) async fn pull_artifact(
+        &self,
+        image: &ImageReference,
+    ) -> anyhow::Result<(String, Vec<(String, Vec<u8>)>)> {
+        let url = self.api_url(image, &format!("manifests/{}", image.manifest_reference()));
+        let resp = self
+            .send(&image.registry, || {
+                self.client
+                    .get(&url)
+                    .header(header::ACCEPT, ARTIFACT_MANIFEST_TYPE)
+            })
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Unable to fetch the manifest from {url}"))?;
+        let digest = resp
+            .headers()
+            .get(DIGEST_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned)
+            .with_context(|| format!("Registry didn't return a digest for {url}"))?;
+        let manifest: ArtifactManifest = resp
+            .json()
+            .await
+            .with_context(|| format!("Unable to parse the manifest from {url}"))?;
+
+        let mut files = Vec::with_capacity(manifest.layers.len());
+        for layer in manifest.layers {
+            let Some(title) = layer.annotations.get(TITLE_ANNOTATION).cloned() else {
+                continue;
+            };
+            let url = self.api_url(image, &format!("blobs/{}", layer.digest));
+            let data = self
+                .send(&image.registry, || self.client.get(&url))
+                .await?
+                .error_for_status()
+                .with_context(|| format!("Unable to fetch {title} from {url}"))?
+                .bytes()
+                .await
+                .with_context(|| format!("Unable to fetch {title} from {url}"))?;
+            files.push((title, data.to_vec()));
+        }
+        Ok((digest, files))
+    }
+}
+
+/// Loads the credentials for private registries from a JSON file mapping each registry to its
+/// `username` and `password` or `token`, then adds the ones given on the command line, which take
+/// precedence
+pub(crate) async fn load_registry_credentials(
+    file: Option<&Path>,
+    flags: &[(String, RegistryCredential)],
+) -> anyhow::Result<HashMap<String, RegistryCredential>> {
+    let mut credentials: HashMap<String, RegistryCredential> = match file {
+        Some(path) => {
+            let data = tokio::fs::read(path).await.with_context(|| {
+                format!(
+                    "Unable to read registry credentials file {}",
+                    path.display()
+                )
+            })?;
+            serde_json::from_slice(&data).with_context(|| {
+                format!(
+                    "Unable to parse registry credentials file {}",
+                    path.display()
+                )
+            })?
+        }
+        None => HashMap::new(),
+    };
+    credentials.extend(flags.iter().cloned());
+    Ok(credentials
+        .into_iter()
+        .map(|(registry, credential)| (normalize_registry(&registry), credential))
+        .collect())
+}
+
+/// Parses a registry credential given as `REGISTRY=USERNAME:PASSWORD`
+pub(crate) fn parse_registry_credential(s: &str) -> Result<(String, RegistryCredential), String> {
+    // The credential isn't included in the error so the password isn't printed
+    let invalid = || "Invalid registry credential, expected REGISTRY=USERNAME:PASSWORD".to_string();
+    let (registry, login) = s.split_once('=').ok_or_else(invalid)?;
+    let (username, password) = login.split_once(':').ok_or_else(invalid)?;
+    if registry.is_empty() || username.is_empty() {
+        return Err(invalid());
+    }
+    Ok((
+        registry.to_owned(),
+        RegistryCredential::from_username_password(username, password, "oci"),
+    ))
+}
+
+/// Normalizes the registry keys used in auth configs, like `https://index.docker.io/v1/`, to the
+/// registry names used in image references
+fn normalize_registry(registry: &str) -> String {
+    let host = registry
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(registry);
+    let host = host.split('/').next().unwrap_or(host);
+    match host {
+        "index.docker.io" | "registry-1.docker.io" => DEFAULT_REGISTRY.to_owned(),
+        _ => host.to_owned(),
+    }
+}
+
+/// Parses the parameters of a `WWW-Authenticate: Bearer realm="...",service="...",scope="..."`
+/// challenge. Returns `None` for other kinds of challenges
+fn parse_bearer_challenge(value: &str) -> Option<HashMap<String, String>> {
+    let (scheme, params) = value.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+    let mut parsed = HashMap::new();
+    let mut rest = params.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_lowercase();
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"')?,
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        parsed.insert(key, value.to_owned());
+        rest = after.trim_start_matches(',').trim();
+    }
+    Some(parsed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_registry_auth_challenges() {
+        let challenge = parse_bearer_challenge(
+            r#"Bearer realm="https://ghcr.io/token",service="ghcr.io",scope="repository:wasmcloud/http-server:pull""#,
+        )
+        .expect("Should parse a bearer challenge");
+        assert_eq!(challenge["realm"], "https://ghcr.io/token");
+        assert_eq!(challenge["service"], "ghcr.io");
+        assert_eq!(challenge["scope"], "repository:wasmcloud/http-server:pull");
+        assert!(parse_bearer_challenge(r#"Basic realm="registry""#).is_none());
+
+        assert_eq!(
+            normalize_registry("https://index.docker.io/v1/"),
+            DEFAULT_REGISTRY
+        );
+        assert_eq!(normalize_registry("localhost:5000"), "localhost:5000");
+    }
+
+    #[tokio::test]
+    async fn loads_registry_credentials() {
+        let (registry, credential) =
+            parse_registry_credential("ghcr.io=wasmcloud:pass:word").unwrap();
+        assert_eq!(registry, "ghcr.io");
+        assert_eq!(credential.username(), Some("wasmcloud"));
+        assert_eq!(credential.password(), Some("pass:word"));
+        assert!(parse_registry_credential("ghcr.io").is_err());
+        assert!(parse_registry_credential("ghcr.io=token").is_err());
+
+        let file =
+            std::env::temp_dir().join(format!("wadm-registries-{}.json", std::process::id()));
+        tokio::fs::write(
+            &file,
+            r#"{"ghcr.io": {"token": "from-file"}, "https://index.docker.io/v1/": {"username": "me", "password": "secret"}}"#,
+        )
+        .await
+        .unwrap();
+        let credentials = load_registry_credentials(Some(&file), &[(registry, credential)])
+            .await
+            .unwrap();
+        tokio::fs::remove_file(&file).await.unwrap();
+        assert_eq!(
+            credentials["ghcr.io"].username(),
+            Some("wasmcloud"),
+            "Credentials given as flags should take precedence over the file"
+        );
+        assert_eq!(credentials[DEFAULT_REGISTRY].password(), Some("secret"));
+    }
+}
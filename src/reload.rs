@@ -0,0 +1,102 @@
+//! Reloading of runtime settings without restarting the process
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use tokio::sync::Semaphore;
+use tracing::{error, info};
+
+use wadm::config::{resize_permits, ConfigReloader, RuntimeConfig, SharedConfig};
+
+use crate::logging::{self, LogFilterHandle};
+
+/// Reloads settings from an optional JSON file. Settings given in the file replace the ones wadm
+/// was started with, and settings removed from the file go back to them
+#[derive(Clone)]
+pub(crate) struct FileReloader {
+    pub(crate) base: RuntimeConfig,
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) shared: SharedConfig,
+}
+
+impl FileReloader {
+    /// Returns the settings from the command line with the ones from the file applied on top
+    pub(crate) async fn load(&self) -> anyhow::Result<RuntimeConfig> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(self.base.clone());
+        };
+        let data = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("Unable to read config file {}", path.display()))?;
+        let overrides: RuntimeConfig = serde_json::from_slice(&data)
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+        Ok(self.base.clone().overlay(overrides))
+    }
+}
+
+#[async_trait]
+impl ConfigReloader for FileReloader {
+    async fn reload(&self) -> anyhow::Result<Vec<&'static str>> {
+        let config = self.load().await?;
+        Ok(self.shared.update(config))
+    }
+}
+
+/// Applies changes to the settings that aren't watched directly by the things using them. This
+/// runs until the shared config is dropped
+pub(crate) async fn apply_changes(
+    shared: SharedConfig,
+    event_permits: Arc<Semaphore>,
+    command_permits: Arc<Semaphore>,
+    log_filter: LogFilterHandle,
+) {
+    let mut config = shared.subscribe();
+    let mut current = config.borrow_and_update().clone();
+    while config.changed().await.is_ok() {
+        let updated = config.borrow_and_update().clone();
+        // NOTE: Shrinking a pool waits for running jobs to finish, so it happens in the background
+        if updated.event_jobs() != current.event_jobs() {
+            info!(from = ?current.event_jobs(), to = ?updated.event_jobs(), "Changing max event jobs");
+            tokio::spawn(resize_permits(
+                event_permits.clone(),
+                current.event_jobs(),
+                updated.event_jobs(),
+            ));
+        }
+        if updated.command_jobs() != current.command_jobs() {
+            info!(from = ?current.command_jobs(), to = ?updated.command_jobs(), "Changing max command jobs");
+            tokio::spawn(resize_permits(
+                command_permits.clone(),
+                current.command_jobs(),
+                updated.command_jobs(),
+            ));
+        }
+        if updated.log_level != current.log_level {
+            logging::set_log_filter(&log_filter, updated.log_level.as_deref());
+        }
+        current = updated;
+    }
+}
+
+/// Reloads the config whenever the process receives a SIGHUP
+#[cfg(unix)]
+pub(crate) async fn reload_on_hangup(reloader: FileReloader) -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup()).context("Unable to listen for SIGHUP")?;
+    while hangups.recv().await.is_some() {
+        info!("Received SIGHUP, reloading config");
+        if let Err(e) = reloader.reload().await {
+            error!(error = ?e, "Unable to reload config, keeping the current one");
+        }
+    }
+    Ok(())
+}
+
+/// SIGHUP doesn't exist on this platform, so the config can only be reloaded through the API
+#[cfg(not(unix))]
+pub(crate) async fn reload_on_hangup(_reloader: FileReloader) -> anyhow::Result<()> {
+    std::future::pending().await
+}
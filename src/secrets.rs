@@ -0,0 +1,145 @@
+//! Setup of the backends that secret references in manifests are resolved from
+
+use std::path::PathBuf;
+
+use anyhow::Context as _;
+use async_nats::jetstream::Context;
+use wadm::secrets::{EnvBackend, FileBackend, KvBackend, SecretResolver};
+
+/// Options for the secret backends wadm resolves secret references from. Each backend is only
+/// available to manifests when it is configured
+#[derive(clap::Args, Debug)]
+pub(crate) struct SecretArgs {
+    /// A NATS KV bucket to resolve secret references with the `kv` backend from. The bucket must
+    /// already exist
+    #[arg(long = "secrets-bucket", env = "WADM_SECRETS_BUCKET")]
+    pub(crate) secrets_bucket: Option<String>,
+
+    /// Enable the `env` secret backend, which resolves the key `db-password` from the environment
+    /// variable `{PREFIX}DB_PASSWORD`. Only variables with the prefix can be read
+    #[arg(
+        long = "secrets-env-prefix",
+        env = "WADM_SECRETS_ENV_PREFIX",
+        num_args = 0..=1,
+        default_missing_value = wadm::secrets::DEFAULT_ENV_PREFIX
+    )]
+    pub(crate) secrets_env_prefix: Option<String>,
+
+    /// A directory to resolve secret references with the `file` backend from, where each key is
+    /// the path of a file in the directory
+    #[arg(long = "secrets-dir", env = "WADM_SECRETS_DIR")]
+    pub(crate) secrets_dir: Option<PathBuf>,
+
+    /// The address of a Vault server to resolve secret references with the `vault` backend from.
+    /// Keys are paths of secrets in a KV version 2 secrets engine
+    #[cfg(feature = "vault")]
+    #[arg(long = "vault-addr", env = "VAULT_ADDR", requires = "vault_token")]
+    pub(crate) vault_addr: Option<String>,
+
+    /// The token to authenticate to Vault with
+    #[cfg(feature = "vault")]
+    #[arg(long = "vault-token", env = "VAULT_TOKEN", hide_env_values = true)]
+    pub(crate) vault_token: Option<String>,
+
+    /// The mount path of the KV version 2 secrets engine in Vault
+    #[cfg(feature = "vault")]
+    #[arg(long = "vault-mount", env = "VAULT_MOUNT", default_value = "secret")]
+    pub(crate) vault_mount: String,
+}
+
+/// Creates a resolver with the configured backends, or `None` if no backends are configured
+pub(crate) async fn secret_resolver(
+    context: &Context,
+    args: &SecretArgs,
+) -> anyhow::Result<Option<SecretResolver>> {
+    let mut resolver = SecretResolver::new();
+    if let Some(bucket) = args.secrets_bucket.as_deref() {
+        let store = context
+            .get_key_value(bucket)
+            .await
+            .with_context(|| format!("Unable to open secrets bucket {bucket}"))?;
+        resolver = resolver.with_backend("kv", KvBackend::new(store));
+    }
+    if let Some(prefix) = args.secrets_env_prefix.as_deref() {
+        resolver = resolver.with_backend("env", EnvBackend::new(prefix));
+    }
+    if let Some(dir) = args.secrets_dir.as_ref() {
+        resolver = resolver.with_backend("file", FileBackend::new(dir));
+    }
+    #[cfg(feature = "vault")]
+    if let (Some(addr), Some(token)) = (args.vault_addr.as_deref(), args.vault_token.as_deref()) {
+        resolver = resolver.with_backend(
+            "vault",
+            vault::VaultBackend::new(addr, token, &args.vault_mount),
+        );
+    }
+    Ok((!resolver.is_empty()).then_some(resolver))
+}
+
+#[cfg(feature = "vault")]
+mod vault {
+    use anyhow::Context as _;
+    use async_trait::async_trait;
+    use reqwest::StatusCode;
+    use serde::Deserialize;
+    use wadm::secrets::SecretBackend;
+
+    #[derive(Deserialize)]
+    struct ReadResponse {
+        data: ReadData,
+    }
+
+    #[derive(Deserialize)]
+    struct ReadData {
+        data: serde_json::Map<String, serde_json::Value>,
+    }
+
+    /// A backend that reads secrets from a KV version 2 secrets engine in Vault. Secrets are
+    /// returned as a JSON object of their fields, so references should select a `field`
+    pub(crate) struct VaultBackend {
+        client: reqwest::Client,
+        addr: String,
+        token: String,
+        mount: String,
+    }
+
+    impl VaultBackend {
+        pub(crate) fn new(addr: &str, token: &str, mount: &str) -> VaultBackend {
+            VaultBackend {
+                client: reqwest::Client::new(),
+                addr: addr.trim_end_matches('/').to_owned(),
+                token: token.to_owned(),
+                mount: mount.trim_matches('/').to_owned(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl SecretBackend for VaultBackend {
+        async fn get_secret(&self, key: &str) -> anyhow::Result<Option<String>> {
+            let url = format!(
+                "{}/v1/{}/data/{}",
+                self.addr,
+                self.mount,
+                key.trim_start_matches('/')
+            );
+            let resp = self
+                .client
+                .get(&url)
+                .header("X-Vault-Token", &self.token)
+                .send()
+                .await
+                .context("Unable to reach Vault")?;
+            if resp.status() == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            let resp: ReadResponse = resp
+                .error_for_status()
+                .with_context(|| format!("Unable to read {key} from Vault"))?
+                .json()
+                .await
+                .with_context(|| format!("Unable to parse {key} from Vault"))?;
+            Ok(Some(serde_json::Value::Object(resp.data.data).to_string()))
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Anonymous usage telemetry. This is strictly opt in and only ever sends the version of wadm and
+//! coarse buckets of how many lattices and manifests it manages, so nothing about the lattices,
+//! hosts or applications themselves leaves the process
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use async_nats::jetstream::kv::Store;
+use futures::TryStreamExt;
+use serde::Serialize;
+use tracing::{debug, warn};
+use wadm::consumers::{manager::ConsumerManager, EventConsumer};
+
+/// The usage report POSTed to the telemetry endpoint
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub(crate) struct UsageReport {
+    /// The version of wadm
+    pub version: &'static str,
+    /// The bucket of the number of lattices this process manages
+    pub lattices: &'static str,
+    /// The bucket of the number of manifests stored
+    pub manifests: &'static str,
+}
+
+impl UsageReport {
+    fn new(lattices: usize, manifests: usize) -> UsageReport {
+        UsageReport {
+            version: env!("CARGO_PKG_VERSION"),
+            lattices: bucket(lattices),
+            manifests: bucket(manifests),
+        }
+    }
+}
+
+/// Returns the coarse bucket the given count falls in, so reports can't be used to tell
+/// installations apart
+fn bucket(count: usize) -> &'static str {
+    match count {
+        0 => "0",
+        1 => "1",
+        2..=5 => "2-5",
+        6..=20 => "6-20",
+        21..=100 => "21-100",
+        _ => "100+",
+    }
+}
+
+/// Sends a usage report to the given endpoint on every interval, starting one interval after wadm
+/// starts. Failing to send a report is only logged, as telemetry should never affect wadm itself
+pub(crate) async fn report_usage(
+    endpoint: String,
+    interval: Duration,
+    events: ConsumerManager<EventConsumer>,
+    manifests: Store,
+) {
+    let client = reqwest::Client::new();
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        ticker.tick().await;
+        let lattices = events
+            .statuses()
+            .await
+            .into_iter()
+            .map(|status| status.lattice_id)
+            .collect::<HashSet<_>>()
+            .len();
+        let manifest_count = match count_manifests(&manifests).await {
+            Ok(count) => count,
+            Err(e) => {
+                debug!(error = %e, "Unable to count manifests, skipping usage report");
+                continue;
+            }
+        };
+        let report = UsageReport::new(lattices, manifest_count);
+        debug!(?report, %endpoint, "Sending usage report");
+        if let Err(e) = client
+            .post(&endpoint)
+            .json(&report)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+        {
+            warn!(error = %e, %endpoint, "Unable to send usage report");
+        }
+    }
+}
+
+async fn count_manifests(store: &Store) -> anyhow::Result<usize> {
+    store
+        .keys()
+        .await
+        .context("Unable to list manifests")?
+        .try_fold(0, |count, _| async move { Ok(count + 1) })
+        .await
+        .context("Unable to list manifests")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reports_only_coarse_counts() {
+        let report = UsageReport::new(3, 150);
+        assert_eq!(report.lattices, "2-5");
+        assert_eq!(report.manifests, "100+");
+        assert_eq!(bucket(0), "0");
+        assert_eq!(bucket(20), "6-20");
+        assert_eq!(bucket(21), "21-100");
+
+        let serialized = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            serialized.as_object().unwrap().len(),
+            3,
+            "Reports should only contain the version and buckets"
+        );
+    }
+}
@@ -0,0 +1,532 @@
+//! The `uninstall` command, which removes the consumers, streams and buckets wadm created, either
+//! for a single lattice or for every lattice when decommissioning wadm
+
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use async_nats::jetstream::Context;
+use wadm::consumers::{COMMANDS_CONSUMER_PREFIX, EVENTS_CONSUMER_PREFIX};
+use wadm::nats_utils::lattice_subject;
+use wadm::scaler::manager::WADM_NOTIFY_PREFIX;
+use wadm::workers::command_ack_key;
+use wadm_types::api::StatusType;
+
+/// How often to check whether undeployed apps have been removed
+const UNDEPLOY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Options for the `uninstall` command
+#[derive(clap::Args, Debug)]
+#[command(group(clap::ArgGroup::new("target").required(true).args(["lattice", "all"])))]
+pub(crate) struct UninstallArgs {
+    /// The lattice to remove wadm's consumers, state and manifests for. Streams and buckets that
+    /// are shared with other lattices are kept, but the lattice's messages in them are purged
+    #[arg(long = "lattice")]
+    pub(crate) lattice: Option<String>,
+
+    /// Remove every stream and bucket wadm created, along with all of their consumers
+    #[arg(long = "all")]
+    pub(crate) all: bool,
+
+    /// The multitenant prefix (account ID) of the lattice, if wadm runs in multitenant mode
+    #[arg(long = "multitenant-prefix", requires = "lattice")]
+    pub(crate) multitenant_prefix: Option<String>,
+
+    /// Undeploy all apps through the wadm API before removing anything, so the components and
+    /// providers they run are stopped. This requires wadm to still be running
+    #[arg(long = "undeploy")]
+    pub(crate) undeploy: bool,
+
+    /// The amount of time in seconds to wait for undeployed apps to be removed before continuing
+    #[arg(long = "undeploy-timeout", default_value = "60", requires = "undeploy")]
+    pub(crate) undeploy_timeout: u64,
+
+    /// Only print what would be removed without removing anything
+    #[arg(long = "dry-run")]
+    pub(crate) dry_run: bool,
+
+    /// Don't ask for confirmation before removing anything
+    #[arg(short = 'y', long = "yes")]
+    pub(crate) yes: bool,
+}
+
+/// The names of the streams and buckets wadm creates, after any configured prefixes are applied
+#[derive(Debug, Clone)]
+pub(crate) struct WadmResources {
+    pub(crate) event_stream: String,
+    pub(crate) command_stream: String,
+    pub(crate) dead_letter_stream: String,
+    pub(crate) quarantine_stream: String,
+    pub(crate) state_log_stream: String,
+    pub(crate) audit_stream: String,
+    pub(crate) status_stream: String,
+    pub(crate) reconcile_report_stream: String,
+    pub(crate) notify_stream: String,
+    pub(crate) event_consumer_stream: String,
+    pub(crate) wasmbus_event_stream: String,
+    /// The prefix commands for each lattice are published under
+    pub(crate) command_topic_prefix: String,
+    pub(crate) state_bucket: String,
+    pub(crate) manifest_bucket: String,
+    pub(crate) schema_bucket: String,
+    pub(crate) command_ack_bucket: String,
+    pub(crate) lease_bucket: String,
+    /// Buckets that are only created when configured, like the shutdown and crash report buckets
+    pub(crate) optional_buckets: Vec<String>,
+}
+
+/// Everything that will be removed by an uninstall
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct UninstallPlan {
+    /// Streams to delete, which also deletes all of their consumers
+    pub(crate) streams: Vec<String>,
+    /// Consumers to delete, as (stream, consumer)
+    pub(crate) consumers: Vec<(String, String)>,
+    /// Messages to purge, as (stream, subject filter)
+    pub(crate) purges: Vec<(String, String)>,
+    /// Buckets to delete
+    pub(crate) buckets: Vec<String>,
+    /// Keys to purge, as (bucket, key)
+    pub(crate) keys: Vec<(String, String)>,
+}
+
+impl UninstallPlan {
+    /// Returns a plan that removes everything wadm created
+    pub(crate) fn all(resources: &WadmResources) -> UninstallPlan {
+        UninstallPlan {
+            streams: vec![
+                resources.event_consumer_stream.clone(),
+                resources.event_stream.clone(),
+                resources.wasmbus_event_stream.clone(),
+                resources.command_stream.clone(),
+                resources.dead_letter_stream.clone(),
+                resources.quarantine_stream.clone(),
+                resources.state_log_stream.clone(),
+                resources.audit_stream.clone(),
+                resources.status_stream.clone(),
+                resources.reconcile_report_stream.clone(),
+                resources.notify_stream.clone(),
+                // Left behind by older versions of wadm
+                "wadm_mirror".to_string(),
+                "wadm_multitenant_mirror".to_string(),
+            ],
+            buckets: [
+                &resources.state_bucket,
+                &resources.manifest_bucket,
+                &resources.schema_bucket,
+                &resources.command_ack_bucket,
+                &resources.lease_bucket,
+            ]
+            .into_iter()
+            .chain(resources.optional_buckets.iter())
+            .cloned()
+            .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns a plan that removes the data of a single lattice, given the names of the models
+    /// stored for it
+    pub(crate) fn lattice(
+        resources: &WadmResources,
+        lattice_id: &str,
+        multitenant_prefix: Option<&str>,
+        models: &BTreeSet<String>,
+    ) -> UninstallPlan {
+        let consumer_name = |prefix: &str| match multitenant_prefix {
+            Some(account) => format!("{prefix}-{lattice_id}_{account}"),
+            None => format!("{prefix}-{lattice_id}"),
+        };
+        let scoped = |prefix: &str| lattice_subject(prefix, lattice_id, multitenant_prefix);
+        let set_key = model_set_key(lattice_id, multitenant_prefix);
+        UninstallPlan {
+            streams: Vec::new(),
+            consumers: vec![
+                (
+                    resources.event_consumer_stream.clone(),
+                    consumer_name(EVENTS_CONSUMER_PREFIX),
+                ),
+                (
+                    resources.command_stream.clone(),
+                    consumer_name(COMMANDS_CONSUMER_PREFIX),
+                ),
+            ],
+            purges: vec![
+                (
+                    resources.event_consumer_stream.clone(),
+                    format!("{}.>", scoped("wadm_event_consumer.evt")),
+                ),
+                (
+                    resources.event_stream.clone(),
+                    format!("{}.>", scoped("wadm.evt")),
+                ),
+                (
+                    resources.command_stream.clone(),
+                    scoped(&resources.command_topic_prefix),
+                ),
+                (
+                    resources.dead_letter_stream.clone(),
+                    format!("wadm.dlq.{lattice_id}"),
+                ),
+                (
+                    resources.quarantine_stream.clone(),
+                    format!("wadm.quarantine.{lattice_id}"),
+                ),
+                (
+                    resources.state_log_stream.clone(),
+                    format!("wadm.state_log.{lattice_id}.*"),
+                ),
+                (
+                    resources.status_stream.clone(),
+                    format!("wadm.status.{lattice_id}.*"),
+                ),
+                (
+                    resources.reconcile_report_stream.clone(),
+                    format!("wadm.reconcile.{lattice_id}.*"),
+                ),
+                (
+                    resources.notify_stream.clone(),
+                    format!("{WADM_NOTIFY_PREFIX}.{lattice_id}"),
+                ),
+            ],
+            buckets: Vec::new(),
+            keys: ["host", "component", "provider"]
+                .into_iter()
+                .map(|kind| {
+                    (
+                        resources.state_bucket.clone(),
+                        format!("{kind}_{lattice_id}"),
+                    )
+                })
+                .chain(models.iter().map(|model| {
+                    (
+                        resources.manifest_bucket.clone(),
+                        format!("{set_key}-{model}"),
+                    )
+                }))
+                .chain(models.iter().map(|model| {
+                    (
+                        resources.command_ack_bucket.clone(),
+                        command_ack_key(lattice_id, model),
+                    )
+                }))
+                .chain([
+                    (resources.manifest_bucket.clone(), set_key.clone()),
+                    (resources.lease_bucket.clone(), lattice_id.to_string()),
+                ])
+                .collect(),
+        }
+    }
+
+    /// Returns a line describing each thing that will be removed
+    pub(crate) fn describe(&self) -> Vec<String> {
+        self.consumers
+            .iter()
+            .map(|(stream, consumer)| format!("consumer {consumer} on stream {stream}"))
+            .chain(
+                self.purges
+                    .iter()
+                    .map(|(stream, subject)| format!("messages on {subject} in stream {stream}")),
+            )
+            .chain(self.streams.iter().map(|stream| format!("stream {stream}")))
+            .chain(
+                self.keys
+                    .iter()
+                    .map(|(bucket, key)| format!("key {key} in bucket {bucket}")),
+            )
+            .chain(self.buckets.iter().map(|bucket| format!("bucket {bucket}")))
+            .collect()
+    }
+}
+
+/// Removes what wadm created for the lattice given in the args, or for every lattice, after
+/// optionally undeploying apps and asking for confirmation. Anything that doesn't exist is skipped
+pub(crate) async fn uninstall(
+    client: &async_nats::Client,
+    context: &Context,
+    api_prefix: &str,
+    resources: &WadmResources,
+    args: &UninstallArgs,
+) -> anyhow::Result<()> {
+    let lattices = match args.lattice.as_ref() {
+        Some(lattice) => vec![lattice.clone()],
+        None if args.undeploy => {
+            wadm_client::Client::from_nats_client("default", Some(api_prefix), client.clone())
+                .list_managed_lattices()
+                .await
+                .context("Unable to list the lattices wadm is managing")?
+                .into_iter()
+                .map(|lattice| lattice.id)
+                .collect()
+        }
+        None => Vec::new(),
+    };
+
+    if args.undeploy {
+        for lattice in lattices.iter() {
+            let wadm =
+                wadm_client::Client::from_nats_client(lattice, Some(api_prefix), client.clone());
+            undeploy_all(&wadm, lattice, args).await?;
+        }
+    }
+
+    let plan = match args.lattice.as_deref() {
+        Some(lattice) => {
+            let models = stored_models(
+                context,
+                resources,
+                lattice,
+                args.multitenant_prefix.as_deref(),
+            )
+            .await?;
+            UninstallPlan::lattice(
+                resources,
+                lattice,
+                args.multitenant_prefix.as_deref(),
+                &models,
+            )
+        }
+        None => UninstallPlan::all(resources),
+    };
+
+    println!("The following will be removed if they exist:");
+    for line in plan.describe() {
+        println!("  {line}");
+    }
+    if args.dry_run {
+        println!("Dry run, nothing was removed");
+        return Ok(());
+    }
+    if !args.yes && !confirm()? {
+        println!("Aborted, nothing was removed");
+        return Ok(());
+    }
+
+    execute(context, &plan).await?;
+    println!("Uninstall complete");
+    Ok(())
+}
+
+/// Undeploys every deployed app in the lattice and waits for them to be removed
+async fn undeploy_all(
+    wadm: &wadm_client::Client,
+    lattice: &str,
+    args: &UninstallArgs,
+) -> anyhow::Result<()> {
+    let deployed: Vec<String> = wadm
+        .list_manifests()
+        .await
+        .with_context(|| format!("Unable to list the apps in lattice {lattice}"))?
+        .into_iter()
+        .filter(|summary| summary.deployed_version.is_some())
+        .map(|summary| summary.name)
+        .collect();
+    if deployed.is_empty() {
+        return Ok(());
+    }
+    for name in deployed.iter() {
+        if args.dry_run {
+            println!("Would undeploy {name} in lattice {lattice}");
+            continue;
+        }
+        wadm.undeploy_manifest(name)
+            .await
+            .with_context(|| format!("Unable to undeploy {name} in lattice {lattice}"))?;
+        println!("Undeployed {name} in lattice {lattice}");
+    }
+    if args.dry_run {
+        return Ok(());
+    }
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(args.undeploy_timeout);
+    loop {
+        let remaining: Vec<String> = wadm
+            .list_manifests()
+            .await?
+            .into_iter()
+            .filter(|summary| {
+                deployed.contains(&summary.name)
+                    && summary.detailed_status.info.status_type != StatusType::Undeployed
+            })
+            .map(|summary| summary.name)
+            .collect();
+        if remaining.is_empty() {
+            return Ok(());
+        }
+        if tokio::time::Instant::now() >= deadline {
+            println!(
+                "Timed out waiting for {} in lattice {lattice} to be removed, continuing",
+                remaining.join(", ")
+            );
+            return Ok(());
+        }
+        tokio::time::sleep(UNDEPLOY_POLL_INTERVAL).await;
+    }
+}
+
+/// Returns the names of the models stored for the lattice, if the manifest bucket exists
+async fn stored_models(
+    context: &Context,
+    resources: &WadmResources,
+    lattice_id: &str,
+    multitenant_prefix: Option<&str>,
+) -> anyhow::Result<BTreeSet<String>> {
+    let Ok(store) = context.get_key_value(&resources.manifest_bucket).await else {
+        return Ok(BTreeSet::new());
+    };
+    let key = match multitenant_prefix {
+        Some(account) => format!("{account}-{lattice_id}"),
+        None => lattice_id.to_string(),
+    };
+    match store
+        .get(&key)
+        .await
+        .map_err(|e| anyhow::anyhow!("{e:?}"))?
+    {
+        Some(data) => serde_json::from_slice(&data)
+            .with_context(|| format!("Unable to parse the models stored for lattice {lattice_id}")),
+        None => Ok(BTreeSet::new()),
+    }
+}
+
+/// Returns the key of the set of models stored for a lattice, matching how the server stores them
+fn model_set_key(lattice_id: &str, multitenant_prefix: Option<&str>) -> String {
+    match multitenant_prefix {
+        Some(account) => format!("{account}-{lattice_id}"),
+        None => lattice_id.to_string(),
+    }
+}
+
+/// Asks for confirmation on stdin, returning whether the answer was yes
+fn confirm() -> anyhow::Result<bool> {
+    print!("Continue? [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Removes everything in the plan, skipping anything that doesn't exist
+async fn execute(context: &Context, plan: &UninstallPlan) -> anyhow::Result<()> {
+    for (stream_name, consumer) in plan.consumers.iter() {
+        let Ok(stream) = context.get_stream(stream_name).await else {
+            continue;
+        };
+        if stream.delete_consumer(consumer).await.is_ok() {
+            println!("Deleted consumer {consumer} on stream {stream_name}");
+        }
+    }
+    for (stream_name, subject) in plan.purges.iter() {
+        let Ok(stream) = context.get_stream(stream_name).await else {
+            continue;
+        };
+        let purged =
+            stream.purge().filter(subject).await.map_err(|e| {
+                anyhow::anyhow!("Unable to purge {subject} in {stream_name}: {e:?}")
+            })?;
+        println!(
+            "Purged {} messages on {subject} in stream {stream_name}",
+            purged.purged
+        );
+    }
+    for stream in plan.streams.iter() {
+        if context.get_stream(stream).await.is_ok() {
+            context
+                .delete_stream(stream)
+                .await
+                .map_err(|e| anyhow::anyhow!("Unable to delete stream {stream}: {e:?}"))?;
+            println!("Deleted stream {stream}");
+        }
+    }
+    for (bucket, key) in plan.keys.iter() {
+        let Ok(store) = context.get_key_value(bucket).await else {
+            continue;
+        };
+        if matches!(store.get(key).await, Ok(Some(_))) {
+            store
+                .purge(key)
+                .await
+                .map_err(|e| anyhow::anyhow!("Unable to purge {key} in {bucket}: {e:?}"))?;
+            println!("Purged key {key} in bucket {bucket}");
+        }
+    }
+    for bucket in plan.buckets.iter() {
+        if context.get_key_value(bucket).await.is_ok() {
+            context
+                .delete_key_value(bucket)
+                .await
+                .map_err(|e| anyhow::anyhow!("Unable to delete bucket {bucket}: {e:?}"))?;
+            println!("Deleted bucket {bucket}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn resources() -> WadmResources {
+        WadmResources {
+            event_stream: "tenant.wadm_events".to_string(),
+            command_stream: "tenant.wadm_commands".to_string(),
+            dead_letter_stream: "tenant.wadm_dlq".to_string(),
+            quarantine_stream: "tenant.wadm_quarantine".to_string(),
+            state_log_stream: "tenant.wadm_state_log".to_string(),
+            audit_stream: "tenant.wadm_audit".to_string(),
+            status_stream: "tenant.wadm_status".to_string(),
+            reconcile_report_stream: "tenant.wadm_reconcile_reports".to_string(),
+            notify_stream: "wadm_notify".to_string(),
+            event_consumer_stream: "wadm_event_consumer".to_string(),
+            wasmbus_event_stream: "wasmbus_events".to_string(),
+            command_topic_prefix: "wadm.cmd".to_string(),
+            state_bucket: "wadm_state".to_string(),
+            manifest_bucket: "wadm_manifests".to_string(),
+            schema_bucket: "wadm_schemas".to_string(),
+            command_ack_bucket: "wadm_command_acks".to_string(),
+            lease_bucket: "wadm_leases".to_string(),
+            optional_buckets: vec!["wadm_crashes".to_string()],
+        }
+    }
+
+    #[test]
+    fn plans_lattice_and_full_uninstall() {
+        let resources = resources();
+        let models = BTreeSet::from(["echo".to_string()]);
+        let plan = UninstallPlan::lattice(&resources, "default", Some("ACCOUNT"), &models);
+        assert!(plan.streams.is_empty() && plan.buckets.is_empty());
+        assert!(plan.consumers.contains(&(
+            "tenant.wadm_commands".to_string(),
+            "wadm_commands-default_ACCOUNT".to_string()
+        )));
+        assert!(plan.purges.contains(&(
+            "tenant.wadm_status".to_string(),
+            "wadm.status.default.*".to_string()
+        )));
+        assert!(plan.purges.contains(&(
+            "tenant.wadm_commands".to_string(),
+            "wadm.cmd.ACCOUNT.default".to_string()
+        )));
+        for key in ["ACCOUNT-default-echo", "ACCOUNT-default"] {
+            assert!(
+                plan.keys
+                    .contains(&("wadm_manifests".to_string(), key.to_string())),
+                "Manifest key {key} should be removed"
+            );
+        }
+        assert!(plan
+            .keys
+            .contains(&("wadm_state".to_string(), "host_default".to_string())));
+        assert!(plan
+            .keys
+            .contains(&("wadm_command_acks".to_string(), "default.echo".to_string())));
+
+        let plan = UninstallPlan::all(&resources);
+        assert!(plan.consumers.is_empty() && plan.keys.is_empty());
+        assert!(plan.streams.contains(&"tenant.wadm_events".to_string()));
+        assert!(plan.streams.contains(&"tenant.wadm_audit".to_string()));
+        assert!(plan.buckets.contains(&"wadm_crashes".to_string()));
+        assert_eq!(plan.describe().len(), 19);
+    }
+}
@@ -0,0 +1,91 @@
+//! Delivery of application lifecycle notifications to webhooks
+
+use std::collections::HashMap;
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use cloudevents::Event as CloudEvent;
+use wadm::{
+    notifications::{LifecycleNotification, NotificationSink},
+    server::NotificationWebhooks,
+};
+
+/// Webhooks that notifications are POSTed to as structured CloudEvents, by name
+pub(crate) struct HttpWebhooks {
+    client: reqwest::Client,
+    urls: HashMap<String, String>,
+}
+
+impl HttpWebhooks {
+    pub(crate) fn new(urls: impl IntoIterator<Item = (String, String)>) -> HttpWebhooks {
+        HttpWebhooks {
+            client: reqwest::Client::new(),
+            urls: urls.into_iter().collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationWebhooks for HttpWebhooks {
+    fn contains(&self, name: &str) -> bool {
+        self.urls.contains_key(name)
+    }
+
+    async fn send(&self, name: &str, event: &CloudEvent) -> anyhow::Result<()> {
+        let url = self
+            .urls
+            .get(name)
+            .with_context(|| format!("Unknown webhook {name}"))?;
+        self.client
+            .post(url)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                "application/cloudevents+json",
+            )
+            .body(serde_json::to_vec(event)?)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .with_context(|| format!("Unable to send notification to webhook {name}"))?;
+        Ok(())
+    }
+}
+
+/// A sink that POSTs lifecycle notifications as JSON to a URL
+pub(crate) struct HttpNotificationSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl HttpNotificationSink {
+    pub(crate) fn new(url: &str) -> HttpNotificationSink {
+        HttpNotificationSink {
+            client: reqwest::Client::new(),
+            url: url.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationSink for HttpNotificationSink {
+    async fn send(&self, notification: &LifecycleNotification) -> anyhow::Result<()> {
+        // Errors from reqwest include the URL, which often has a secret in it for chat webhooks
+        let status = self
+            .client
+            .post(&self.url)
+            .json(notification)
+            .send()
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "Unable to reach lifecycle notification webhook: {}",
+                    e.without_url()
+                )
+            })?
+            .status();
+        if !status.is_success() {
+            anyhow::bail!("Lifecycle notification webhook returned {status}");
+        }
+        Ok(())
+    }
+}
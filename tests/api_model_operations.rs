@@ -575,6 +575,71 @@ async fn test_invalid_topics() {
     );
 }
 
+#[tokio::test]
+async fn test_read_only_api() {
+    let env = setup_env()
+        .await
+        .expect("should have set up the test environment");
+    let nats_client = env
+        .nats_client()
+        .await
+        .expect("should have created a nats client");
+    let test_server = setup_server("read_only", nats_client).await;
+
+    let raw = tokio::fs::read("./oam/sqldbpostgres.yaml")
+        .await
+        .expect("Unable to load file");
+    let resp: PutModelResponse = test_server
+        .get_response("default.model.put", raw.clone(), None)
+        .await;
+    assert_put_response(resp, PutResult::Created, "v0.0.1", 1);
+
+    let resp: GetModelResponse = test_server
+        .get_response(
+            "default.ro.model.get.rust-sqldb-postgres-query",
+            Vec::new(),
+            None,
+        )
+        .await;
+    assert!(
+        matches!(resp.result, GetResult::Success),
+        "Should be able to get a manifest from the read-only API"
+    );
+
+    for subject in [
+        "default.ro.model.put",
+        "default.ro.model.deploy.rust-sqldb-postgres-query",
+        "default.ro.model.del.rust-sqldb-postgres-query",
+        "default.ro.dlq.replay",
+    ] {
+        let resp: HashMap<String, String> =
+            test_server.get_response(subject, raw.clone(), None).await;
+        assert_eq!(
+            resp.get("result").expect("Response should have valid data"),
+            "error"
+        );
+        assert!(
+            resp.get("message")
+                .expect("Response should have valid data")
+                .contains("read-only"),
+            "{subject} should be rejected on the read-only API"
+        );
+    }
+
+    let resp: VersionResponse = test_server
+        .get_response(
+            "default.model.versions.rust-sqldb-postgres-query",
+            Vec::new(),
+            None,
+        )
+        .await;
+    assert_eq!(
+        resp.versions.len(),
+        1,
+        "Nothing should have been changed by the rejected requests"
+    );
+}
+
 #[tokio::test]
 async fn test_manifest_parsing() {
     let env = setup_env()
@@ -726,12 +791,32 @@ async fn test_deploy() {
         }
     }
 
+    // A dry run should be rejected since this server has no planner configured
+    let resp: DeployModelResponse = test_server
+        .get_response(
+            "default.model.deploy.rust-sqldb-postgres-query",
+            serde_json::to_vec(&DeployModelRequest {
+                version: Some("v0.0.1".to_string()),
+                dry_run: true,
+                overrides: None,
+            })
+            .unwrap(),
+            None,
+        )
+        .await;
+    assert!(
+        matches!(resp.result, DeployResult::Error),
+        "Dry run without a planner should have errored"
+    );
+
     // Now deploy with a specific version
     let resp: DeployModelResponse = test_server
         .get_response(
             "default.model.deploy.rust-sqldb-postgres-query",
             serde_json::to_vec(&DeployModelRequest {
                 version: Some("v0.0.1".to_string()),
+                dry_run: false,
+                overrides: None,
             })
             .unwrap(),
             None,
@@ -763,6 +848,8 @@ async fn test_deploy() {
             "default.model.deploy.rust-sqldb-postgres-query",
             serde_json::to_vec(&DeployModelRequest {
                 version: Some("latest".to_string()),
+                dry_run: false,
+                overrides: None,
             })
             .unwrap(),
             None,
@@ -17,10 +17,7 @@ use tokio::{
 };
 use wadm::{APP_SPEC_ANNOTATION, MANAGED_BY_ANNOTATION, MANAGED_BY_IDENTIFIER};
 use wadm_client::ClientConnectOptions;
-use wadm_types::{
-    api::{Status, StatusInfo, StatusType},
-    Manifest,
-};
+use wadm_types::{api::StatusType, Manifest};
 use wasmcloud_control_interface::HostInventory;
 
 const LOG_DIR: &str = "tests/e2e_log";
@@ -399,13 +396,17 @@ pub enum ExpectedCount {
 }
 
 pub async fn check_status(
-    stream: &Stream,
-    lattice_id: &str,
+    client: &wadm_client::Client,
     manifest_name: &str,
     expected_status: StatusType,
 ) -> anyhow::Result<()> {
     for i in 0..30 {
-        let status = get_manifest_status_info(stream, lattice_id, manifest_name).await;
+        // Model status doesn't exist or is invalid, assuming undeployed
+        let status = client
+            .get_manifest_status(manifest_name)
+            .await
+            .ok()
+            .map(|status| status.info);
         match status.as_ref() {
             Some(status) if status.status_type == expected_status => break,
             _ if i < 29 => tokio::time::sleep(std::time::Duration::from_secs(1)).await,
@@ -466,21 +467,3 @@ pub fn check_providers(
     }
     Ok(())
 }
-
-pub async fn get_manifest_status_info(
-    stream: &Stream,
-    lattice_id: &str,
-    name: &str,
-) -> Option<StatusInfo> {
-    // NOTE(brooksmtownsend): We're getting the last raw message instead of direct get here
-    // to ensure we fetch the latest message from the cluster leader.
-    match stream
-        .get_last_raw_message_by_subject(&format!("wadm.status.{lattice_id}.{name}",))
-        .await
-        .map(|raw| serde_json::from_slice::<Status>(&raw.payload))
-    {
-        Ok(Ok(status)) => Some(status.info),
-        // Model status doesn't exist or is invalid, assuming undeployed
-        _ => None,
-    }
-}
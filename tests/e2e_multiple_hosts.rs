@@ -165,14 +165,9 @@ async fn test_no_requirements(client_info: &ClientInfo) {
             )
         }
 
-        check_status(
-            &stream,
-            DEFAULT_LATTICE_ID,
-            "hello-simple",
-            StatusType::Deployed,
-        )
-        .await
-        .unwrap();
+        check_status(client, "hello-simple", StatusType::Deployed)
+            .await
+            .unwrap();
 
         Ok(())
     })
@@ -185,14 +180,9 @@ async fn test_no_requirements(client_info: &ClientInfo) {
         .expect("Shouldn't have errored when undeploying manifest");
 
     // Once manifest is undeployed, status should be undeployed
-    check_status(
-        &stream,
-        DEFAULT_LATTICE_ID,
-        "hello-simple",
-        StatusType::Undeployed,
-    )
-    .await
-    .unwrap();
+    check_status(client, "hello-simple", StatusType::Undeployed)
+        .await
+        .unwrap();
 
     // assert that no components or providers with annotations exist
     assert_status(None, Some(3), || async {
@@ -215,14 +205,9 @@ async fn test_no_requirements(client_info: &ClientInfo) {
             )
         }
 
-        check_status(
-            &stream,
-            DEFAULT_LATTICE_ID,
-            "hello-simple",
-            StatusType::Undeployed,
-        )
-        .await
-        .unwrap();
+        check_status(client, "hello-simple", StatusType::Undeployed)
+            .await
+            .unwrap();
 
         Ok(())
     })
@@ -116,7 +116,6 @@ async fn run_shared_component_tests() {
 }
 
 async fn test_shared_providers(client_info: &ClientInfo) {
-    let stream = client_info.get_status_stream().await;
     let client = client_info.wadm_client(SHARED_PROVIDERS_LATTICE);
     let (name, _version) = client
         .put_manifest(client_info.load_raw_manifest("shared_http.yaml").await)
@@ -146,14 +145,9 @@ async fn test_shared_providers(client_info: &ClientInfo) {
 
         ensure!(links.is_empty(), "Shouldn't have any links");
 
-        check_status(
-            &stream,
-            SHARED_PROVIDERS_LATTICE,
-            "shared-http",
-            StatusType::Deployed,
-        )
-        .await
-        .unwrap();
+        check_status(client, "shared-http", StatusType::Deployed)
+            .await
+            .unwrap();
 
         Ok(())
     })
@@ -236,22 +230,12 @@ async fn test_shared_providers(client_info: &ClientInfo) {
             )
         }
 
-        check_status(
-            &stream,
-            SHARED_PROVIDERS_LATTICE,
-            "shared-http",
-            StatusType::Deployed,
-        )
-        .await
-        .unwrap();
-        check_status(
-            &stream,
-            SHARED_PROVIDERS_LATTICE,
-            "shared-http-dev",
-            StatusType::Deployed,
-        )
-        .await
-        .unwrap();
+        check_status(client, "shared-http", StatusType::Deployed)
+            .await
+            .unwrap();
+        check_status(client, "shared-http-dev", StatusType::Deployed)
+            .await
+            .unwrap();
 
         // TODO(#451): Additional validation tests coming in a follow-up PR
         // // You can't undeploy an application that is depended on
@@ -269,7 +253,6 @@ async fn test_shared_providers(client_info: &ClientInfo) {
 }
 
 async fn test_shared_components(client_info: &ClientInfo) {
-    let stream = client_info.get_status_stream().await;
     let client = client_info.wadm_client(SHARED_COMPONENTS_LATTICE);
     let (name, _version) = client
         .put_manifest(client_info.load_raw_manifest("shared_component.yaml").await)
@@ -310,14 +293,9 @@ async fn test_shared_components(client_info: &ClientInfo) {
 
         ensure!(links.is_empty(), "Shouldn't have any links");
 
-        check_status(
-            &stream,
-            SHARED_COMPONENTS_LATTICE,
-            "shared-component",
-            StatusType::Deployed,
-        )
-        .await
-        .unwrap();
+        check_status(client, "shared-component", StatusType::Deployed)
+            .await
+            .unwrap();
 
         Ok(())
     })
@@ -403,22 +381,12 @@ async fn test_shared_components(client_info: &ClientInfo) {
             )
         }
 
-        check_status(
-            &stream,
-            SHARED_COMPONENTS_LATTICE,
-            "shared-component",
-            StatusType::Deployed,
-        )
-        .await
-        .unwrap();
-        check_status(
-            &stream,
-            SHARED_COMPONENTS_LATTICE,
-            "shared-component-dev",
-            StatusType::Deployed,
-        )
-        .await
-        .unwrap();
+        check_status(client, "shared-component", StatusType::Deployed)
+            .await
+            .unwrap();
+        check_status(client, "shared-component-dev", StatusType::Deployed)
+            .await
+            .unwrap();
 
         Ok(())
     })
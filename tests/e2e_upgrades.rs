@@ -3,9 +3,8 @@ use std::time::Duration;
 use std::{collections::HashMap, path::PathBuf};
 
 use anyhow::Context;
-use async_nats::jetstream::consumer::pull::Config;
 use futures::{FutureExt, StreamExt, TryStreamExt};
-use wadm_types::api::{Status, StatusType};
+use wadm_types::api::StatusType;
 
 mod e2e;
 mod helpers;
@@ -97,7 +96,7 @@ async fn test_upgrade(client_info: &ClientInfo) {
         .expect("Shouldn't have errored when deploying manifest");
 
     // Once manifest is deployed, first status should be compensating
-    check_status(&stream, "default", "updateapp", StatusType::Reconciling)
+    check_status(client, "updateapp", StatusType::Reconciling)
         .await
         .unwrap();
 
@@ -205,7 +204,7 @@ async fn test_upgrade(client_info: &ClientInfo) {
             )
         };
 
-        check_status(&stream, "default", "updateapp", StatusType::Deployed)
+        check_status(client, "updateapp", StatusType::Deployed)
             .await
             .expect("application should be deployed after all components and links are deployed");
 
@@ -213,17 +212,13 @@ async fn test_upgrade(client_info: &ClientInfo) {
     })
     .await;
 
-    let consumer = stream
-        .get_or_create_consumer(
-            "test_upgrade_ephemeral",
-            Config {
-                name: Some("test_upgrade_ephemeral".to_string()),
-                deliver_policy: async_nats::jetstream::consumer::DeliverPolicy::New,
-                ..Default::default()
-            },
-        )
-        .await
-        .expect("Should be able to get consumer");
+    // Subscribe before deploying so no status updates are missed
+    let mut statuses = Box::pin(
+        client
+            .watch_status("updateapp")
+            .await
+            .expect("Should be able to watch the status of the manifest"),
+    );
 
     let (name, version) = client
         .put_manifest(client_info.load_raw_manifest("upgradedapp.yaml").await)
@@ -235,20 +230,13 @@ async fn test_upgrade(client_info: &ClientInfo) {
         .await
         .expect("Shouldn't have errored when deploying updated manifest");
 
-    let mut messages = consumer
-        .messages()
-        .await
-        .expect("Unable to get status stream");
-
     tokio::time::timeout(Duration::from_secs(5), async {
         loop {
-            let val = messages
+            let stat = statuses
                 .try_next()
                 .await
-                .expect("Got error when consuming status stream")
+                .expect("Got error when watching status")
                 .expect("Status stream ended early");
-            let stat: Status = serde_json::from_slice(&val.payload)
-                .expect("Should be able to decode status body from JSON");
             if matches!(stat.info.status_type, StatusType::Reconciling) {
                 break;
             }
@@ -258,7 +246,7 @@ async fn test_upgrade(client_info: &ClientInfo) {
     .expect("Timed out waiting for reconciling status after deploying");
 
     // Once manifest is updated, status should be reconciling
-    check_status(&stream, "default", "updateapp", StatusType::Reconciling)
+    check_status(client, "updateapp", StatusType::Reconciling)
         .await
         .unwrap();
 
@@ -343,7 +331,7 @@ async fn test_upgrade(client_info: &ClientInfo) {
             )
         }
 
-        check_status(&stream, "default", "updateapp", StatusType::Deployed)
+        check_status(client, "updateapp", StatusType::Deployed)
             .await
             .unwrap();
 
@@ -365,13 +353,11 @@ async fn test_upgrade(client_info: &ClientInfo) {
     // Once manifest is updated, status should be reconciling
     tokio::time::timeout(Duration::from_secs(5), async {
         loop {
-            let val = messages
+            let stat = statuses
                 .try_next()
                 .await
-                .expect("Got error when consuming status stream")
+                .expect("Got error when watching status")
                 .expect("Status stream ended early");
-            let stat: Status = serde_json::from_slice(&val.payload)
-                .expect("Should be able to decode status body from JSON");
             if matches!(stat.info.status_type, StatusType::Reconciling) {
                 break;
             }
@@ -484,7 +470,7 @@ async fn test_upgrade(client_info: &ClientInfo) {
             )
         }
 
-        check_status(&stream, "default", "updateapp", StatusType::Deployed)
+        check_status(client, "updateapp", StatusType::Deployed)
             .await
             .unwrap();
 
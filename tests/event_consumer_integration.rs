@@ -1,9 +1,14 @@
+use std::sync::Arc;
+
 use anyhow::Result;
 use futures::{Stream, TryStreamExt};
-use tokio::time::{timeout, Duration};
+use tokio::{
+    sync::Semaphore,
+    time::{timeout, Duration},
+};
 
 use wadm::{
-    consumers::{EventConsumer, ScopedMessage},
+    consumers::{EventConsumer, EventStartPosition, ScopedMessage},
     events::*,
 };
 
@@ -64,9 +69,15 @@ async fn get_event_consumer(client: async_nats::Client) -> EventConsumer {
             .await
             .expect("Should be able to create test stream")
     };
-    EventConsumer::new(stream, WASMBUS_EVENT_TOPIC, "default", None)
-        .await
-        .expect("Unable to setup stream")
+    EventConsumer::new(
+        stream,
+        WASMBUS_EVENT_TOPIC,
+        "default",
+        None,
+        EventStartPosition::All,
+    )
+    .await
+    .expect("Unable to setup stream")
 }
 
 #[derive(serde::Deserialize)]
@@ -350,6 +361,75 @@ async fn test_nack_and_rereceive() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_buffered_events_not_redelivered() -> Result<()> {
+    let env = setup_env()
+        .await
+        .expect("should have set up the test environment");
+    let nats_client = env
+        .nats_client()
+        .await
+        .expect("should have created a nats client for the test setup");
+
+    // Leave enough permits that all the events are pulled in one batch
+    let mut stream = get_event_consumer(nats_client.clone())
+        .await
+        .with_permits(Arc::new(Semaphore::new(10)));
+
+    let host_ids: Vec<String> = (0..5).map(|i| format!("host-{i}")).collect();
+    for id in host_ids.iter() {
+        let evt = cloudevents::Event::try_from(Event::HostStopped(HostStopped {
+            labels: Default::default(),
+            id: id.to_owned(),
+        }))?;
+        nats_client
+            .publish(
+                "wasmbus.evt.default.host_stopped",
+                serde_json::to_vec(&evt)?.into(),
+            )
+            .await?;
+    }
+    nats_client.flush().await?;
+
+    // Work on the first event for longer than the ack wait, like a slow worker would, while the
+    // rest of the batch waits
+    let mut first = wait_for_host_stopped(&mut stream, DEFAULT_TIMEOUT_DURATION).await;
+    tokio::time::sleep(Duration::from_secs(5)).await;
+    first.ack().await.expect("Should be able to ack event");
+
+    let mut seen = Vec::new();
+    while let Ok(Some(Ok(mut evt))) = timeout(Duration::from_secs(3), stream.try_next())
+        .await
+        .map(|res| res.transpose())
+    {
+        if let Event::HostStopped(stopped) = evt.as_ref() {
+            seen.push(stopped.id.clone());
+        }
+        evt.ack().await.expect("Should be able to ack event");
+    }
+    seen.sort();
+    assert_eq!(
+        seen,
+        host_ids[1..],
+        "Buffered events should each be received once, without being redelivered"
+    );
+
+    Ok(())
+}
+
+async fn wait_for_host_stopped(
+    mut stream: impl Stream<Item = Result<ScopedMessage<Event>, async_nats::Error>> + Unpin,
+    duration: Duration,
+) -> ScopedMessage<Event> {
+    loop {
+        let mut evt = wait_for_event(&mut stream, duration).await;
+        if matches!(*evt, Event::HostStopped(_)) {
+            return evt;
+        }
+        evt.ack().await.expect("Should be able to ack message");
+    }
+}
+
 async fn wait_for_event(
     mut stream: impl Stream<Item = Result<ScopedMessage<Event>, async_nats::Error>> + Unpin,
     duration: Duration,
@@ -0,0 +1,69 @@
+use wadm::leadership::LatticeLeases;
+
+mod helpers;
+
+use helpers::{create_test_store_with_client, setup_env};
+
+#[tokio::test]
+async fn test_lattice_leases() {
+    let env = setup_env()
+        .await
+        .expect("should have set up the test environment");
+    let nats_client = env
+        .nats_client()
+        .await
+        .expect("should have created a nats client");
+    let store = create_test_store_with_client("lattice_leases_test", nats_client).await;
+
+    let leader = LatticeLeases::new(store.clone(), "leader");
+    let standby = LatticeLeases::new(store.clone(), "standby");
+    let lattice_id = "leases";
+
+    assert!(
+        leader
+            .try_acquire(lattice_id)
+            .await
+            .expect("Should be able to acquire lease"),
+        "First process should become the leader"
+    );
+    assert!(leader.is_leader(lattice_id));
+    assert!(
+        !standby
+            .try_acquire(lattice_id)
+            .await
+            .expect("Should be able to try to acquire lease"),
+        "Second process should stay on standby"
+    );
+    assert!(!standby.is_leader(lattice_id));
+
+    // Renewing should keep the lease
+    assert!(leader
+        .try_acquire(lattice_id)
+        .await
+        .expect("Should be able to renew lease"));
+
+    // A restarted process with the same ID should pick its lease back up
+    let restarted = LatticeLeases::new(store.clone(), "leader");
+    assert!(restarted
+        .try_acquire(lattice_id)
+        .await
+        .expect("Should be able to adopt lease"));
+    assert!(
+        !leader
+            .try_acquire(lattice_id)
+            .await
+            .expect("Should be able to try to renew lease"),
+        "The old process should see its lease was taken over"
+    );
+    assert!(!leader.is_leader(lattice_id));
+
+    restarted.release_all().await;
+    assert!(restarted.held_lattices().is_empty());
+    assert!(
+        standby
+            .try_acquire(lattice_id)
+            .await
+            .expect("Should be able to acquire lease"),
+        "Standby should take over once the lease is released"
+    );
+}
@@ -1,6 +1,13 @@
 use anyhow::{Context as _, Result};
 
-use wadm_types::validation::{validate_manifest_file, ValidationFailureLevel, ValidationOutput};
+use wadm_types::{
+    aliases::{AliasRegistry, CapabilityAlias},
+    validation::{
+        lint_manifest, validate_manifest, validate_manifest_file, ValidationFailureLevel,
+        ValidationOutput,
+    },
+    NotificationRoute, TraitProperty,
+};
 
 /// Ensure that valid YAML manifests are valid
 #[tokio::test]
@@ -118,3 +125,244 @@ async fn validate_policy() -> Result<()> {
     assert!(failures.valid(), "manifest is valid");
     Ok(())
 }
+
+#[tokio::test]
+async fn validate_pinned_hosts() -> Result<()> {
+    let (_manifest, failures) =
+        validate_manifest_file("./tests/fixtures/manifests/pinned-hosts.wadm.yaml")
+            .await
+            .context("failed to validate manifest")?;
+    assert!(!failures.valid(), "manifest should be invalid");
+    assert_eq!(
+        failures.errors().len(),
+        1,
+        "only the bad host ID is an error"
+    );
+    assert!(failures.errors()[0].msg.contains("not-a-host"));
+    assert_eq!(
+        failures.warnings().len(),
+        1,
+        "ignored spreads are a warning"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn validate_anti_affinity() -> Result<()> {
+    let (_manifest, failures) =
+        validate_manifest_file("./tests/fixtures/manifests/anti-affinity.wadm.yaml")
+            .await
+            .context("failed to validate manifest")?;
+    assert_eq!(
+        failures.errors().len(),
+        1,
+        "only the anti-affinity with an unknown component is an error"
+    );
+    assert!(failures.errors()[0].msg.contains("'not-a-component'"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn validate_rollout() -> Result<()> {
+    let (manifest, failures) =
+        validate_manifest_file("./tests/fixtures/manifests/rollout.wadm.yaml")
+            .await
+            .context("failed to validate manifest")?;
+    assert!(failures.is_empty(), "no failures");
+    assert!(
+        manifest
+            .components()
+            .flat_map(|c| c.traits.iter().flatten())
+            .filter(|t| t.is_rollout())
+            .all(|t| matches!(t.properties, TraitProperty::Rollout(_))),
+        "rollout traits should not be parsed as custom traits"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn validate_host_constraint() -> Result<()> {
+    let (manifest, failures) =
+        validate_manifest_file("./tests/fixtures/manifests/host-constraint.wadm.yaml")
+            .await
+            .context("failed to validate manifest")?;
+    assert!(
+        manifest
+            .components()
+            .flat_map(|c| c.traits.iter().flatten())
+            .filter(|t| t.is_host_constraint())
+            .all(|t| matches!(t.properties, TraitProperty::HostConstraint(_))),
+        "host constraint traits should not be parsed as custom traits"
+    );
+    assert_eq!(
+        failures.errors().len(),
+        1,
+        "only the invalid minimum version is an error"
+    );
+    assert!(failures.errors()[0].msg.contains("'1.2'"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn validate_autoscaler() -> Result<()> {
+    let (manifest, failures) =
+        validate_manifest_file("./tests/fixtures/manifests/autoscaler.wadm.yaml")
+            .await
+            .context("failed to validate manifest")?;
+    assert!(
+        manifest
+            .components()
+            .flat_map(|c| c.traits.iter().flatten())
+            .filter(|t| t.is_autoscaler())
+            .all(|t| matches!(t.properties, TraitProperty::Autoscaler(_))),
+        "autoscaler traits should not be parsed as custom traits"
+    );
+    assert_eq!(
+        failures.errors().len(),
+        2,
+        "inverted bounds and wildcard subjects are errors"
+    );
+    assert!(failures.errors()[0].msg.contains("'other-component'"));
+    assert!(failures.errors()[1]
+        .msg
+        .contains("'metrics.*.requests_per_second'"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn validate_schedule() -> Result<()> {
+    let (manifest, failures) =
+        validate_manifest_file("./tests/fixtures/manifests/schedule.wadm.yaml")
+            .await
+            .context("failed to validate manifest")?;
+    assert!(
+        manifest
+            .components()
+            .flat_map(|c| c.traits.iter().flatten())
+            .filter(|t| t.is_schedule())
+            .all(|t| matches!(t.properties, TraitProperty::Schedule(_))),
+        "schedule traits should not be parsed as custom traits"
+    );
+    assert_eq!(
+        failures.errors().len(),
+        2,
+        "unknown timezones and invalid cron expressions are errors"
+    );
+    assert!(failures.errors()[0].msg.contains("'Europe/Berlin'"));
+    assert!(failures.errors()[1].msg.contains("25 is out of range"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn validate_config_payloads() -> Result<()> {
+    let (_manifest, failures) =
+        validate_manifest_file("./tests/fixtures/manifests/config-payload.wadm.yaml")
+            .await
+            .context("failed to validate manifest")?;
+    assert_eq!(
+        failures.errors().len(),
+        2,
+        "non-object JSON and invalid base64 payloads are errors"
+    );
+    assert!(failures.errors()[0].msg.contains("'messaging'"));
+    assert!(failures.errors()[1].msg.contains("'blobstore'"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn validate_secret_refs() -> Result<()> {
+    let (manifest, failures) =
+        validate_manifest_file("./tests/fixtures/manifests/secret-refs.wadm.yaml")
+            .await
+            .context("failed to validate manifest")?;
+    assert_eq!(
+        serde_yaml::to_string(&manifest)?
+            .matches("secret_refs")
+            .count(),
+        3,
+        "secret references should be kept when the manifest is stored"
+    );
+    assert_eq!(
+        failures.errors().len(),
+        2,
+        "secret references without a backend or that shadow a property are errors"
+    );
+    assert!(failures.errors()[0].msg.contains("'password'"));
+    assert!(failures.errors()[1].msg.contains("must have a backend"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn lint_best_practices() -> Result<()> {
+    let (manifest, failures) = validate_manifest_file("./tests/fixtures/manifests/lint.wadm.yaml")
+        .await
+        .context("failed to validate manifest")?;
+    assert!(failures.valid(), "lint warnings shouldn't fail validation");
+
+    let warnings = lint_manifest(&manifest);
+    assert_eq!(warnings.warnings().len(), 4, "{warnings:?}");
+    assert!(warnings.errors().is_empty());
+    assert!(warnings[0].msg.contains("has no tag"));
+    assert!(warnings[1].msg.contains("spread 'anywhere'"));
+    assert!(warnings[2].msg.contains("latest tag"));
+    assert!(warnings[3].msg.contains("'keyvalue' isn't linked"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn validate_notification_routes() -> Result<()> {
+    let (manifest, failures) =
+        validate_manifest_file("./tests/fixtures/manifests/notification-routes.wadm.yaml")
+            .await
+            .context("failed to validate manifest")?;
+    assert_eq!(
+        manifest.notification_routes(),
+        vec![
+            NotificationRoute::Subject("teams.payments.notifications".to_string()),
+            NotificationRoute::Webhook("payments-alerts".to_string()),
+            NotificationRoute::Subject("teams.*.notifications".to_string()),
+        ],
+        "policies without a route should be skipped"
+    );
+    assert_eq!(
+        failures.errors().len(),
+        2,
+        "wildcard subjects and missing routes are errors"
+    );
+    assert!(failures.errors()[0].msg.contains("'everything'"));
+    assert!(failures.errors()[1].msg.contains("'nowhere'"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn validate_capability_aliases() -> Result<()> {
+    let (mut manifest, failures) =
+        validate_manifest_file("./tests/fixtures/manifests/capability-alias.wadm.yaml")
+            .await
+            .context("failed to validate manifest")?;
+    assert_eq!(
+        failures.errors().len(),
+        1,
+        "links to aliases that weren't expanded are errors"
+    );
+    assert!(failures.errors()[0].msg.contains("[keyvalue]"));
+
+    let mut registry = AliasRegistry::default();
+    registry.insert(
+        "keyvalue",
+        CapabilityAlias {
+            namespace: "wasi".to_string(),
+            package: "keyvalue".to_string(),
+            interfaces: vec!["atomics".to_string(), "store".to_string()],
+            image: Some("ghcr.io/wasmcloud/keyvalue-redis:0.24.0".to_string()),
+        },
+    )?;
+    registry
+        .expand(&mut manifest)
+        .map_err(|errors| anyhow::anyhow!(errors.join("\n")))?;
+    assert!(
+        validate_manifest(&manifest).await?.valid(),
+        "expanded manifest should be valid"
+    );
+    Ok(())
+}